@@ -0,0 +1,60 @@
+use brioche_core::blob::SaveBlobOptions;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+mod brioche_bench;
+
+/// The size of the file saved as a blob. Run with the `blocking-blob-writer`
+/// feature to compare the blocking writer against the default async writer.
+const BLOB_SIZE: usize = 256 * 1024 * 1024;
+
+fn run_blob_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build Tokio runtime");
+    let _runtime_guard = runtime.enter();
+
+    let (brioche, context, input_path) = runtime.block_on(async {
+        let (brioche, context) = brioche_bench::brioche_test().await;
+
+        // Use content that doesn't compress or repeat, like a real artifact
+        let mut content = vec![0u8; BLOB_SIZE];
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for chunk in content.chunks_mut(8) {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+        }
+
+        let input_path = context.path("input.bin");
+        tokio::fs::write(&input_path, &content).await.unwrap();
+
+        (brioche, context, input_path)
+    });
+
+    let mut group = c.benchmark_group("blob");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(BLOB_SIZE as u64));
+    group.bench_function("save large blob from file", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let permit = brioche_core::blob::get_save_blob_permit(&brioche)
+                .await
+                .unwrap();
+            brioche_core::blob::save_blob_from_file(
+                &brioche,
+                permit,
+                &input_path,
+                SaveBlobOptions::default(),
+            )
+            .await
+            .unwrap();
+        })
+    });
+    group.finish();
+
+    drop(context);
+}
+
+criterion_group!(benches, run_blob_benchmark);
+criterion_main!(benches);
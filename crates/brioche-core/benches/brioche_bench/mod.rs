@@ -34,7 +34,9 @@ pub async fn brioche_test() -> (Brioche, TestContext) {
 }
 
 pub async fn blob(brioche: &Brioche, content: impl AsRef<[u8]> + std::marker::Unpin) -> BlobHash {
-    let permit = brioche_core::blob::get_save_blob_permit().await.unwrap();
+    let permit = brioche_core::blob::get_save_blob_permit(brioche)
+        .await
+        .unwrap();
     brioche_core::blob::save_blob(
         brioche,
         permit,
@@ -0,0 +1,17 @@
+CREATE TABLE bake_history (
+    id INTEGER PRIMARY KEY NOT NULL,
+    recipe_hash TEXT NOT NULL,
+    artifact_hash TEXT NOT NULL REFERENCES recipes (recipe_hash),
+    project_hash TEXT,
+    export TEXT,
+    duration_ms INTEGER NOT NULL,
+    num_cache_hits INTEGER NOT NULL,
+    num_baked INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
+
+CREATE INDEX bake_history_recipe_hash
+ON bake_history (recipe_hash);
+
+CREATE INDEX bake_history_artifact_hash
+ON bake_history (artifact_hash);
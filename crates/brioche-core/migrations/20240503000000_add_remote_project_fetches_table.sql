@@ -0,0 +1,10 @@
+CREATE TABLE remote_project_fetches (
+    id INTEGER PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    rev TEXT,
+    commit_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
+
+CREATE INDEX remote_project_fetches_url
+ON remote_project_fetches (url);
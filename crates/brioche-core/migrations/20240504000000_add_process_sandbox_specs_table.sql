@@ -0,0 +1,6 @@
+CREATE TABLE process_sandbox_specs (
+    recipe_hash TEXT PRIMARY KEY NOT NULL,
+    bake_dir TEXT NOT NULL,
+    sandbox_config_json TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
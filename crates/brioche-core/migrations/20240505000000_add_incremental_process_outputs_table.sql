@@ -0,0 +1,7 @@
+CREATE TABLE incremental_process_outputs (
+    incremental_key TEXT PRIMARY KEY NOT NULL,
+    base_hash TEXT NOT NULL,
+    recipe_hash TEXT NOT NULL,
+    output_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
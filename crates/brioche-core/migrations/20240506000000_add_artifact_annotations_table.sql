@@ -0,0 +1,5 @@
+CREATE TABLE artifact_annotations (
+    artifact_hash TEXT PRIMARY KEY NOT NULL,
+    annotations_json TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
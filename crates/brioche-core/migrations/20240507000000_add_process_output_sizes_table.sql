@@ -0,0 +1,5 @@
+CREATE TABLE process_output_sizes (
+    structure_key TEXT PRIMARY KEY NOT NULL,
+    output_size INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
@@ -0,0 +1,9 @@
+CREATE TABLE evaluation_cache (
+    project_hash TEXT NOT NULL,
+    export TEXT NOT NULL,
+    parameters_hash TEXT NOT NULL,
+    recipe_hash TEXT NOT NULL,
+    meta_json TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_hash, export, parameters_hash)
+) STRICT;
@@ -0,0 +1,7 @@
+CREATE TABLE pins (
+    kind TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    note TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (kind, hash)
+) STRICT;
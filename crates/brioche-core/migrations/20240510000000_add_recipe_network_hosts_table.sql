@@ -0,0 +1,8 @@
+CREATE TABLE recipe_network_hosts (
+    recipe_hash TEXT NOT NULL,
+    host TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    allowed INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (recipe_hash, host, port, allowed)
+) STRICT;
@@ -0,0 +1,5 @@
+CREATE TABLE failed_process_bakes (
+    input_hash TEXT PRIMARY KEY NOT NULL,
+    message TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
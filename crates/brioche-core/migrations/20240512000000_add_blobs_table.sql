@@ -0,0 +1,10 @@
+CREATE TABLE blobs (
+    blob_hash TEXT PRIMARY KEY NOT NULL,
+    size INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    source_url TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) STRICT;
+
+CREATE INDEX blobs_created_at
+ON blobs (created_at);
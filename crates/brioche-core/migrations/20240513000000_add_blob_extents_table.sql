@@ -0,0 +1,4 @@
+CREATE TABLE blob_extents (
+    blob_hash TEXT PRIMARY KEY NOT NULL,
+    data_extents TEXT NOT NULL
+) STRICT;
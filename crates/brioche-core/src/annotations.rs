@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    recipe::{Artifact, RecipeHash},
+    Brioche,
+};
+
+/// Structured metadata attached to a build output with an annotate
+/// recipe. Annotations are stored alongside the baked artifact, and are
+/// used by commands like `brioche info`, `brioche run`, and
+/// `brioche install`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotations {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The path of the main executable within the artifact, relative to
+    /// the root of the artifact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_executable: Option<String>,
+    /// How to run the artifact as a long-running service, such as a
+    /// development server. Used by `brioche run`, which supervises the
+    /// service instead of running the main executable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceAnnotation>,
+    /// Any other metadata, as arbitrary key-value pairs.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAnnotation {
+    /// The path of the executable that runs the service, relative to the
+    /// root of the artifact.
+    pub command: String,
+    /// Arguments passed to the command, before any arguments passed to
+    /// `brioche run`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Extra environment variables set for the command.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub restart: ServiceRestartPolicy,
+}
+
+/// When a service is started again after its process exits on its own.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ServiceRestartPolicy {
+    /// The service is never restarted, and `brioche run` exits with the
+    /// service's exit code.
+    Never,
+    /// The service is restarted only if it exits unsuccessfully.
+    #[default]
+    OnFailure,
+    /// The service is always restarted when it exits.
+    Always,
+}
+
+/// Check that annotations make sense for the artifact they're attached
+/// to, then store them so they can be retrieved by the artifact's hash.
+/// Annotating the same artifact again replaces its previous annotations.
+pub async fn save_annotations(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    annotations: &Annotations,
+) -> anyhow::Result<()> {
+    if let Some(main_executable) = &annotations.main_executable {
+        ensure_directory_entry(brioche, artifact, "main executable", main_executable).await?;
+    }
+    if let Some(service) = &annotations.service {
+        ensure_directory_entry(brioche, artifact, "service command", &service.command).await?;
+    }
+
+    let artifact_hash = artifact.hash();
+    let annotations_json = serde_json::to_string(annotations)?;
+
+    let artifact_hash_value = artifact_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO artifact_annotations (artifact_hash, annotations_json)
+            VALUES (?, ?)
+            ON CONFLICT (artifact_hash) DO UPDATE SET
+                annotations_json = excluded.annotations_json,
+                created_at = CURRENT_TIMESTAMP
+        "#,
+        artifact_hash_value,
+        annotations_json,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn ensure_directory_entry(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    kind: &str,
+    path: &str,
+) -> anyhow::Result<()> {
+    let Artifact::Directory(directory) = artifact else {
+        anyhow::bail!("{kind} {path:?} can only be set for a directory");
+    };
+    let entry = directory
+        .get(brioche, path.as_bytes())
+        .await
+        .with_context(|| format!("failed to get {kind} {path:?}"))?;
+    anyhow::ensure!(entry.is_some(), "{kind} {path:?} not found in directory");
+
+    Ok(())
+}
+
+/// Get the annotations attached to an artifact, if any.
+pub async fn get_annotations(
+    brioche: &Brioche,
+    artifact_hash: RecipeHash,
+) -> anyhow::Result<Option<Annotations>> {
+    let artifact_hash_value = artifact_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let annotations_json = sqlx::query_scalar!(
+        r#"
+            SELECT annotations_json
+            FROM artifact_annotations
+            WHERE artifact_hash = ?
+        "#,
+        artifact_hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    let Some(annotations_json) = annotations_json else {
+        return Ok(None);
+    };
+    let annotations = serde_json::from_str(&annotations_json)
+        .with_context(|| format!("invalid annotations for artifact {artifact_hash}"))?;
+    Ok(Some(annotations))
+}
@@ -10,18 +10,33 @@ use tracing::Instrument as _;
 
 use crate::{
     project::ProjectHash,
-    recipe::{ArtifactDiscriminants, ProxyRecipe},
+    recipe::{pretty::describe_artifact, ArtifactDiscriminants, ProxyRecipe},
 };
 
 use super::{
-    recipe::{Artifact, CreateDirectory, Directory, File, Meta, Recipe, RecipeHash, WithMeta},
+    recipe::{
+        Artifact, CachePolicy, CreateDirectory, Directory, File, Meta, Recipe, RecipeDiscriminants,
+        RecipeHash, WithMeta,
+    },
     Brioche,
 };
 
 mod download;
+mod environment;
+mod failures;
+pub(crate) mod git;
+mod incremental;
+pub mod mocks;
+mod normalize;
+mod patch;
 mod process;
+pub mod process_inputs;
+mod symlinks;
 mod unarchive;
 
+pub use normalize::normalize_recipe;
+pub use process::{replay_process, ProcessReplay};
+
 #[derive(Debug, Default)]
 pub struct CachedRecipes {
     pub recipes_by_hash: HashMap<RecipeHash, Recipe>,
@@ -30,7 +45,26 @@ pub struct CachedRecipes {
 #[derive(Debug, Default)]
 pub struct ActiveBakes {
     bake_watchers:
-        HashMap<RecipeHash, tokio::sync::watch::Receiver<Option<Result<Artifact, String>>>>,
+        HashMap<RecipeHash, tokio::sync::watch::Receiver<Option<Result<Artifact, BakeFailed>>>>,
+}
+
+/// Running totals of how bakes were completed, used to summarize a build.
+/// Bakes that were already in progress for another run aren't counted.
+#[derive(Debug, Default)]
+pub struct BakeStats {
+    /// Bakes whose result was found in the local database or the registry.
+    pub cache_hits: std::sync::atomic::AtomicU64,
+    /// Bakes that were actually run.
+    pub baked: std::sync::atomic::AtomicU64,
+}
+
+impl BakeStats {
+    pub fn snapshot(&self) -> (u64, u64) {
+        (
+            self.cache_hits.load(std::sync::atomic::Ordering::Relaxed),
+            self.baked.load(std::sync::atomic::Ordering::Relaxed),
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -51,9 +85,16 @@ pub async fn bake(
     recipe: WithMeta<Recipe>,
     scope: &BakeScope,
 ) -> anyhow::Result<WithMeta<Artifact>> {
+    let recipe = normalize_recipe(recipe);
     let recipe_hash = recipe.hash();
     let result = bake_inner(brioche, recipe).await?;
 
+    // Which projects and recipes a bake came from is only recorded for
+    // `brioche clean`, so it's skipped for a read-only store
+    if brioche.read_only {
+        return Ok(result);
+    }
+
     match scope {
         BakeScope::Project {
             project_hash,
@@ -111,6 +152,63 @@ pub async fn bake(
     Ok(result)
 }
 
+/// Bake a recipe, but only make sure the part of the output at `subpath`
+/// is available locally. If the recipe hasn't been baked locally but the
+/// registry has a bake for it, only the directories along the path and the
+/// contents of the subpath are fetched, rather than the whole output.
+/// Returns `None` if nothing exists at the subpath.
+pub async fn bake_subpath(
+    brioche: &Brioche,
+    recipe: WithMeta<Recipe>,
+    scope: &BakeScope,
+    subpath: &[u8],
+) -> anyhow::Result<Option<Artifact>> {
+    let recipe_hash = recipe.hash();
+
+    let is_baked_locally = {
+        let recipe_hash_value = recipe_hash.to_string();
+        let mut db_conn = brioche.db_conn.lock().await;
+        let mut db_transaction = db_conn.begin().await?;
+        let row = sqlx::query!(
+            r#"SELECT 1 AS "found!" FROM bakes WHERE input_hash = ? LIMIT 1"#,
+            recipe_hash_value,
+        )
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+        db_transaction.commit().await?;
+        row.is_some()
+    };
+
+    if !is_baked_locally
+        && recipe.is_expensive_to_bake()
+        && recipe.cache_policy().uses_remote_cache()
+    {
+        let response = get_verified_registry_bake(brioche, recipe_hash).await;
+        if let Some(response) = response {
+            // The bake isn't recorded locally, since most of the output
+            // won't be available
+            tracing::debug!(%recipe_hash, "fetching subpath of bake from registry");
+            return crate::registry::fetch_artifact_subpath(
+                brioche,
+                &response.output_artifact,
+                subpath,
+            )
+            .await;
+        }
+    }
+
+    let artifact = bake(brioche, recipe, scope).await?;
+    let artifact = match artifact.value {
+        Artifact::Directory(directory) => {
+            let entry = directory.get(brioche, subpath).await?;
+            entry.map(|entry| entry.value)
+        }
+        artifact if subpath.iter().all(|&byte| byte == b'/' || byte == b'.') => Some(artifact),
+        _ => None,
+    };
+    Ok(artifact)
+}
+
 #[async_recursion::async_recursion]
 #[tracing::instrument(skip(brioche, recipe), fields(recipe_hash = %recipe.hash(), recipe_kind = ?recipe.kind(), bake_method))]
 async fn bake_inner(
@@ -119,6 +217,7 @@ async fn bake_inner(
 ) -> anyhow::Result<WithMeta<Artifact>> {
     let meta = recipe.meta.clone();
     let recipe_hash = recipe.hash();
+    let recipe_kind = recipe.kind();
 
     // If we're currently resolving the recipe in another task, wait for it to
     // complete and return early
@@ -146,7 +245,7 @@ async fn bake_inner(
                     }
                     Err(error) => {
                         tracing::debug!(%recipe_hash, %error, "received error while waiting for in-progress bake to finish, bake already failed");
-                        anyhow::bail!("{error}");
+                        return Err(error.clone().into());
                     }
                 };
             }
@@ -175,29 +274,73 @@ async fn bake_inner(
         return Ok(WithMeta::new(artifact, meta));
     }
 
+    // With mocks, skip the database and registry entirely, since anything
+    // baked from a mocked result shouldn't be cached
+    if let Some(mocks) = &brioche.bake_mocks {
+        tracing::Span::current().record("bake_method", "mock");
+
+        let result = bake_mocked(brioche, mocks, recipe.value, &meta).await;
+
+        // Remove the active bake watcher
+        {
+            let mut active_bakes = brioche.active_bakes.write().await;
+            active_bakes.bake_watchers.remove(&recipe_hash);
+        }
+
+        return match result {
+            Ok(artifact) => {
+                let _ = bake_tx.send(Some(Ok(artifact.clone())));
+                Ok(WithMeta::new(artifact, meta))
+            }
+            Err(error) => {
+                let error = BakeFailed::new(&error, recipe_kind, meta);
+                let _ = bake_tx.send(Some(Err(error.clone())));
+                Err(error.into())
+            }
+        };
+    }
+
+    let cache_policy = recipe.cache_policy();
+
+    // Recipes that prefer remote results check the mirror stores and the
+    // registry before the database
+    let mut remote_bake = if cache_policy == CachePolicy::PreferRemote {
+        find_remote_bake(brioche, &recipe.value).await
+    } else {
+        None
+    };
+
     // Check the database to see if we've cached this recipe before
-    let mut db_conn = brioche.db_conn.lock().await;
-    let mut db_transaction = db_conn.begin().await?;
-    let input_hash = recipe_hash.to_string();
-    let result = sqlx::query!(
-        r#"
-            SELECT output_artifacts.recipe_json AS artifact_json
-            FROM bakes
-            INNER JOIN recipes AS output_artifacts
-                ON bakes.output_hash = output_artifacts.recipe_hash
-            WHERE bakes.input_hash = ?
-            LIMIT 1
-        "#,
-        input_hash,
-    )
-    .fetch_optional(&mut *db_transaction)
-    .await?;
-    db_transaction.commit().await?;
-    drop(db_conn);
+    let result = if cache_policy == CachePolicy::NoCache || remote_bake.is_some() {
+        None
+    } else {
+        let mut db_conn = brioche.db_conn.lock().await;
+        let mut db_transaction = db_conn.begin().await?;
+        let input_hash = recipe_hash.to_string();
+        let result = sqlx::query!(
+            r#"
+                SELECT output_artifacts.recipe_json AS artifact_json
+                FROM bakes
+                INNER JOIN recipes AS output_artifacts
+                    ON bakes.output_hash = output_artifacts.recipe_hash
+                WHERE bakes.input_hash = ?
+                LIMIT 1
+            "#,
+            input_hash,
+        )
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+        db_transaction.commit().await?;
+        result
+    };
 
     if let Some(row) = result {
         let artifact: Artifact = serde_json::from_str(&row.artifact_json)?;
         tracing::Span::current().record("bake_method", "database_hit");
+        brioche
+            .bake_stats
+            .cache_hits
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         tracing::trace!(%recipe_hash, artifact_hash = %artifact.hash(), "got bake result from database");
 
         // Remove the active bake watcher
@@ -211,35 +354,91 @@ async fn bake_inner(
         return Ok(WithMeta::new(artifact, meta));
     }
 
+    // Anything past this point saves a new bake result
+    if let Err(error) = brioche.ensure_writable(&format!("bake recipe {recipe_hash}")) {
+        let mut active_bakes = brioche.active_bakes.write().await;
+        active_bakes.bake_watchers.remove(&recipe_hash);
+        drop(active_bakes);
+
+        let _ = bake_tx.send(Some(Err(BakeFailed::new(
+            &error,
+            recipe_kind,
+            meta.clone(),
+        ))));
+        return Err(error);
+    }
+
     let input_json = serde_json::to_string(&recipe.value)?;
 
-    // Try to get the baked recipe from the registry (if it might be
-    // expensive to bake)
-    let registry_response = if recipe.is_expensive_to_bake() {
-        brioche.registry_client.get_bake(recipe_hash).await.ok()
-    } else {
-        None
-    };
+    // Try to get the baked recipe from a mirror store, then from the
+    // registry (unless it was already checked)
+    if cache_policy == CachePolicy::Default {
+        remote_bake = find_remote_bake(brioche, &recipe.value).await;
+    }
+
+    // Fail fast if the process already failed recently, rather than
+    // running it again just to hit the same error
+    let caches_failures = failures::caches_failures(brioche, &recipe.value, cache_policy);
+    if caches_failures && remote_bake.is_none() {
+        if let Some(message) = failures::find_failure(brioche, recipe_hash).await? {
+            tracing::Span::current().record("bake_method", "cached_failure");
+
+            // Remove the active bake watcher
+            {
+                let mut active_bakes = brioche.active_bakes.write().await;
+                active_bakes.bake_watchers.remove(&recipe_hash);
+            }
 
-    let result_artifact = match registry_response {
-        Some(response) => {
+            let error = BakeFailed {
+                message,
+                code: Some(crate::error_codes::ErrorCode::PROCESS_FAILED),
+                meta,
+                required_by: vec![],
+            };
+            let _ = bake_tx.send(Some(Err(error.clone())));
+            return Err(error.into());
+        }
+    }
+
+    let result_artifact = match remote_bake {
+        Some(RemoteBake::Mirror(artifact)) => {
+            // The mirror store has the baked recipe, so fetch its blobs
+            // (from the mirror, when it has them)
+            tracing::Span::current().record("bake_method", "mirror_hit");
+            brioche
+                .bake_stats
+                .cache_hits
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            crate::registry::fetch_artifact_blobs(brioche, &artifact).await?;
+            Ok(artifact)
+        }
+        Some(RemoteBake::Registry(response)) => {
             // The registry has the baked recipe, so fetch the references
             // and return the output artifact
+            brioche
+                .bake_stats
+                .cache_hits
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             crate::registry::fetch_bake_references(brioche.clone(), response.clone()).await?;
             Ok(response.output_artifact)
         }
         None => {
             // Bake the recipe for real if we didn't get it from the registry
+            brioche
+                .bake_stats
+                .baked
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             let bake_fut = {
                 let brioche = brioche.clone();
                 let meta = meta.clone();
                 async move {
                     // Clone the recipe (but only if we are going to sync it)
-                    let input_recipe = if recipe.is_expensive_to_bake() {
-                        Some(recipe.value.clone())
-                    } else {
-                        None
-                    };
+                    let input_recipe =
+                        if recipe.is_expensive_to_bake() && cache_policy.allows_upload() {
+                            Some(recipe.value.clone())
+                        } else {
+                            None
+                        };
 
                     // Bake the recipe
                     let baked = run_bake(&brioche, recipe.value, &meta).await?;
@@ -261,10 +460,9 @@ async fn bake_inner(
                 }
                 .instrument(tracing::debug_span!("run_bake_task").or_current())
             };
-            tokio::spawn(bake_fut).await?.map_err(|error| BakeFailed {
-                message: format!("{error:#}"),
-                meta: meta.clone(),
-            })
+            tokio::spawn(bake_fut)
+                .await?
+                .map_err(|error| BakeFailed::new(&error, recipe_kind, meta.clone()))
         }
     };
 
@@ -306,6 +504,18 @@ async fn bake_inner(
         tracing::trace!(%recipe_hash, result_hash = %output_hash, "saved bake result to database");
     }
 
+    if caches_failures {
+        match &result_artifact {
+            Ok(_) => {
+                failures::clear_failure(brioche, recipe_hash).await?;
+            }
+            Err(error) if error.code == Some(crate::error_codes::ErrorCode::PROCESS_FAILED) => {
+                failures::record_failure(brioche, recipe_hash, &error.message).await?;
+            }
+            Err(_) => {}
+        }
+    }
+
     // Remove the active bake watcher
     {
         let mut active_bakes = brioche.active_bakes.write().await;
@@ -320,13 +530,70 @@ async fn bake_inner(
         }
         Err(error) => {
             // Ignore error because channel may have closed
-            let _ = bake_tx.send(Some(Err(format!("{error:#}"))));
+            let _ = bake_tx.send(Some(Err(error.clone())));
             Err(error.into())
         }
     }
 }
 
+enum RemoteBake {
+    Mirror(Artifact),
+    Registry(crate::registry::GetBakeResponse),
+}
+
+/// Find a bake result from a mirror store, then from the registry. Only
+/// recipes that might be expensive to bake are looked up.
+async fn find_remote_bake(brioche: &Brioche, recipe: &Recipe) -> Option<RemoteBake> {
+    if !recipe.is_expensive_to_bake() {
+        return None;
+    }
+
+    let recipe_hash = recipe.hash();
+    if let Some(artifact) = crate::mirror::find_bake(brioche, recipe_hash).await {
+        return Some(RemoteBake::Mirror(artifact));
+    }
+
+    get_verified_registry_bake(brioche, recipe_hash)
+        .await
+        .map(RemoteBake::Registry)
+}
+
 #[tracing::instrument(skip_all, err)]
+/// Get a bake from the registry, but only if its attestation checks out.
+/// Otherwise, the recipe should be baked locally.
+async fn get_verified_registry_bake(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> Option<crate::registry::GetBakeResponse> {
+    let response = brioche.registry_client.get_bake(recipe_hash).await.ok()?;
+    let verified = brioche.attestations.verify_bake(
+        recipe_hash,
+        response.output_hash,
+        &response.output_artifact,
+        response.attestation.as_ref(),
+    );
+    match verified {
+        Ok(()) => Some(response),
+        Err(error) => {
+            tracing::warn!(%recipe_hash, "ignoring bake from registry: {error:#}");
+            None
+        }
+    }
+}
+
+async fn bake_mocked(
+    brioche: &Brioche,
+    mocks: &mocks::BakeMocks,
+    recipe: Recipe,
+    meta: &Arc<Meta>,
+) -> anyhow::Result<Artifact> {
+    match recipe {
+        Recipe::Download(download) => mocks.bake_download(&download),
+        Recipe::CompleteProcess(process) => mocks.bake_process(&process),
+        recipe => run_bake(brioche, recipe, meta).await,
+    }
+}
+
 async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow::Result<Artifact> {
     let scope = BakeScope::Child {
         parent_hash: recipe.hash(),
@@ -354,6 +621,10 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
             let downloaded = download::bake_download(brioche, download).await?;
             Ok(Artifact::File(downloaded))
         }
+        Recipe::GitCheckout(checkout) => {
+            let checkout = git::bake_git_checkout(brioche, meta, checkout).await?;
+            Ok(Artifact::Directory(checkout))
+        }
         Recipe::Unarchive(unarchive) => {
             let unarchived = unarchive::bake_unarchive(brioche, &scope, meta, unarchive).await?;
             Ok(Artifact::Directory(unarchived))
@@ -362,7 +633,8 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
             // We call `bake` recursively here so that two different
             // lazy processes that bake to the same complete process will
             // only run once (since `bake` is memoized).
-            let process = process::bake_lazy_process_to_process(brioche, &scope, process).await?;
+            let process =
+                process::bake_lazy_process_to_process(brioche, &scope, meta, process).await?;
             let result = bake(
                 brioche,
                 WithMeta::new(Recipe::CompleteProcess(process), meta.clone()),
@@ -381,7 +653,7 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
             resources,
         } => {
             let blob_hash = {
-                let permit = super::blob::get_save_blob_permit().await?;
+                let permit = super::blob::get_save_blob_permit(brioche).await?;
                 super::blob::save_blob(
                     brioche,
                     permit,
@@ -435,14 +707,53 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
 
             let mut merged = Directory::default();
             for dir in directories {
-                let Artifact::Directory(dir) = dir.value else {
-                    anyhow::bail!("tried merging non-directory artifact");
+                let dir = match dir.value {
+                    Artifact::Directory(dir) => dir,
+                    other => anyhow::bail!(
+                        "tried merging non-directory artifact: {}",
+                        describe_artifact(&other)
+                    ),
                 };
                 merged.merge(&dir, brioche).await?;
             }
 
             Ok(Artifact::Directory(merged))
         }
+        Recipe::Environment(environment) => {
+            let directories = futures::future::try_join_all(
+                environment
+                    .packages
+                    .iter()
+                    .map(|package| bake(brioche, package.recipe.clone(), &scope)),
+            )
+            .await?;
+
+            let packages = environment
+                .packages
+                .into_iter()
+                .zip(directories)
+                .map(|(package, directory)| {
+                    let directory = match directory.value {
+                        Artifact::Directory(directory) => directory,
+                        other => anyhow::bail!(
+                            "tried adding non-directory artifact to environment as package {:?}: {}",
+                            package.name,
+                            describe_artifact(&other)
+                        ),
+                    };
+                    Ok(environment::EnvironmentPackageOutput {
+                        name: package.name,
+                        priority: package.priority,
+                        directory,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let directory =
+                environment::bake_environment(brioche, &packages, environment.ignore_collisions)
+                    .await?;
+            Ok(Artifact::Directory(directory))
+        }
         Recipe::Proxy(proxy) => {
             let inner = proxy.inner(brioche).await?;
             let artifact = bake(brioche, WithMeta::new(inner, meta.clone()), &scope).await?;
@@ -452,8 +763,12 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
             let mut result = bake(brioche, *directory, &scope).await?;
 
             for _ in 0..depth {
-                let Artifact::Directory(dir) = result.value else {
-                    anyhow::bail!("tried peeling non-directory artifact");
+                let dir = match result.value {
+                    Artifact::Directory(dir) => dir,
+                    other => anyhow::bail!(
+                        "tried peeling non-directory artifact: {}",
+                        describe_artifact(&other)
+                    ),
                 };
                 let entries = dir.entries(brioche).await?;
                 let mut entries = entries.into_values();
@@ -472,8 +787,12 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
         }
         Recipe::Get { directory, path } => {
             let artifact = bake(brioche, *directory, &scope).await?;
-            let Artifact::Directory(directory) = artifact.value else {
-                anyhow::bail!("tried getting item from non-directory");
+            let directory = match artifact.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried getting item from non-directory: {}",
+                    describe_artifact(&other)
+                ),
             };
 
             let Some(result) = directory.get(brioche, &path).await? else {
@@ -497,18 +816,113 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
                 }
             })?;
 
-            let Artifact::Directory(mut directory) = directory.value else {
-                anyhow::bail!("tried removing item from non-directory artifact");
+            let mut directory = match directory.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried removing item from non-directory artifact: {}",
+                    describe_artifact(&other)
+                ),
             };
 
             directory.insert(brioche, &path, artifact).await?;
 
             Ok(Artifact::Directory(directory))
         }
+        Recipe::Remove { directory, paths } => {
+            let directory = bake(brioche, *directory, &scope).await?;
+            let mut directory = match directory.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried removing items from non-directory artifact: {}",
+                    describe_artifact(&other)
+                ),
+            };
+
+            for path in paths {
+                directory.insert(brioche, &path, None).await?;
+            }
+
+            Ok(Artifact::Directory(directory))
+        }
+        Recipe::Rename {
+            directory,
+            from,
+            to,
+        } => {
+            let directory = bake(brioche, *directory, &scope).await?;
+            let mut directory = match directory.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried renaming item in non-directory artifact: {}",
+                    describe_artifact(&other)
+                ),
+            };
+
+            let entry = directory
+                .insert(brioche, &from, None)
+                .await?
+                .with_context(|| format!("tried renaming non-existent path {from:?}"))?;
+            anyhow::ensure!(
+                directory.get(brioche, &to).await?.is_none(),
+                "tried renaming {from:?} to {to:?}, but {to:?} already exists"
+            );
+            directory.insert(brioche, &to, Some(entry)).await?;
+
+            Ok(Artifact::Directory(directory))
+        }
+        Recipe::ResolveSymlinks { directory } => {
+            let directory = bake(brioche, *directory, &scope).await?;
+            let directory = match directory.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried resolving symlinks in non-directory artifact: {}",
+                    describe_artifact(&other)
+                ),
+            };
+
+            let directory = symlinks::bake_resolve_symlinks(brioche, directory).await?;
+            Ok(Artifact::Directory(directory))
+        }
+        Recipe::Patch {
+            directory,
+            patches,
+            strip,
+        } => {
+            let (directory, patches) = tokio::try_join!(
+                bake(brioche, *directory, &scope),
+                futures::future::try_join_all(
+                    patches
+                        .into_iter()
+                        .map(|patch| bake(brioche, patch, &scope))
+                ),
+            )?;
+
+            let directory = match directory.value {
+                Artifact::Directory(directory) => directory,
+                other => anyhow::bail!(
+                    "tried patching non-directory artifact: {}",
+                    describe_artifact(&other)
+                ),
+            };
+            let patches = patches
+                .into_iter()
+                .map(|patch| match patch.value {
+                    Artifact::File(file) => Ok(file),
+                    _ => anyhow::bail!("expected patch to be a file"),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let directory = patch::bake_patch(brioche, meta, directory, patches, strip).await?;
+            Ok(Artifact::Directory(directory))
+        }
         Recipe::SetPermissions { file, executable } => {
             let result = bake(brioche, *file, &scope).await?;
-            let Artifact::File(mut file) = result.value else {
-                anyhow::bail!("tried setting permissions on non-file");
+            let mut file = match result.value {
+                Artifact::File(file) => file,
+                other => anyhow::bail!(
+                    "tried setting permissions on non-file: {}",
+                    describe_artifact(&other)
+                ),
             };
 
             if let Some(executable) = executable {
@@ -521,6 +935,14 @@ async fn run_bake(brioche: &Brioche, recipe: Recipe, meta: &Arc<Meta>) -> anyhow
             let result = bake(brioche, *recipe, &scope).await?;
             Ok(result.value)
         }
+        Recipe::Annotate {
+            recipe,
+            annotations,
+        } => {
+            let result = bake(brioche, *recipe, &scope).await?;
+            crate::annotations::save_annotations(brioche, &result.value, &annotations).await?;
+            Ok(result.value)
+        }
     }
 }
 
@@ -537,12 +959,52 @@ pub async fn create_proxy(brioche: &Brioche, recipe: Recipe) -> anyhow::Result<R
     }))
 }
 
-#[derive(Debug, thiserror::Error)]
-struct BakeFailed {
+#[derive(Debug, Clone, thiserror::Error)]
+pub(crate) struct BakeFailed {
     message: String,
+    /// The code of the original error, which is otherwise lost when
+    /// converting it to a message.
+    pub(crate) code: Option<crate::error_codes::ErrorCode>,
+    meta: Arc<Meta>,
+    /// The recipes that depended on the failed recipe, from the one that
+    /// baked it directly up to the root recipe.
+    required_by: Vec<RequiredBy>,
+}
+
+#[derive(Debug, Clone)]
+struct RequiredBy {
+    kind: RecipeDiscriminants,
     meta: Arc<Meta>,
 }
 
+impl BakeFailed {
+    /// Wrap an error from baking a recipe. If the error came from one of
+    /// the recipe's dependencies failing, the original failure is kept and
+    /// the recipe is added to the recipes that required it.
+    fn new(error: &anyhow::Error, kind: RecipeDiscriminants, meta: Arc<Meta>) -> Self {
+        let mut messages = vec![];
+        for cause in error.chain() {
+            if let Some(failed) = cause.downcast_ref::<BakeFailed>() {
+                let mut failed = failed.clone();
+                messages.push(std::mem::take(&mut failed.message));
+                failed.message = messages.join(": ");
+                failed.code = crate::error_codes::error_code(error);
+                failed.required_by.push(RequiredBy { kind, meta });
+                return failed;
+            }
+
+            messages.push(cause.to_string());
+        }
+
+        Self {
+            message: format!("{error:#}"),
+            code: crate::error_codes::error_code(error),
+            meta,
+            required_by: vec![],
+        }
+    }
+}
+
 impl std::fmt::Display for BakeFailed {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let (message_sources, mut message_lines) = self
@@ -582,6 +1044,29 @@ impl std::fmt::Display for BakeFailed {
             write!(f, "\n    at {source}")?;
         }
 
+        // Show where each dependent recipe came from, skipping recipes
+        // without a source and recipes from the same place as the last one
+        // (such as a recipe wrapping another)
+        let mut last_source = self
+            .meta
+            .source
+            .iter()
+            .flatten()
+            .next()
+            .map(|source| source.to_string());
+        for required_by in &self.required_by {
+            let Some(source) = required_by.meta.source.iter().flatten().next() else {
+                continue;
+            };
+            let source = source.to_string();
+            if last_source.as_ref() == Some(&source) {
+                continue;
+            }
+
+            write!(f, "\nrequired by {:?} recipe at {source}", required_by.kind)?;
+            last_source = Some(source);
+        }
+
         Ok(())
     }
 }
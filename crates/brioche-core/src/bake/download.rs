@@ -10,24 +10,25 @@ use crate::{
 #[tracing::instrument(skip(brioche, download), fields(url = %download.url))]
 pub async fn bake_download(brioche: &Brioche, download: DownloadRecipe) -> anyhow::Result<File> {
     // Acquire a permit to save the blob
-    let save_blob_permit = crate::blob::get_save_blob_permit().await?;
+    let save_blob_permit = crate::blob::get_save_blob_permit(brioche).await?;
 
     // Acquire a permit to download
     tracing::debug!("acquiring download semaphore permit");
     let _permit = brioche.download_semaphore.acquire().await?;
     tracing::debug!("acquired download semaphore permit");
 
+    let recipe_hash = crate::recipe::Recipe::Download(download.clone()).hash();
+    crate::network::check_url(brioche, recipe_hash, &download.url).await?;
+
     tracing::debug!(url = %download.url, "starting download");
 
     let job_id = brioche.reporter.add_job(crate::reporter::NewJob::Download {
         url: download.url.clone(),
     });
 
-    let response = brioche
-        .download_client
-        .get(download.url.clone())
-        .send()
-        .await?;
+    // Each redirect is checked against the allowlist as it's followed
+    let request = brioche.download_client.get(download.url.clone());
+    let response = crate::network::send_recording_redirects(brioche, recipe_hash, request).await?;
     let response = response.error_for_status()?;
 
     let content_length = response.content_length().or_else(|| {
@@ -40,6 +41,15 @@ pub async fn bake_download(brioche: &Brioche, download: DownloadRecipe) -> anyho
         }
     });
 
+    if let Some(content_length) = content_length {
+        crate::disk_space::ensure_available_space(
+            &brioche.home,
+            content_length,
+            &format!("download of {}", download.url),
+        )
+        .await?;
+    }
+
     let mut download_stream = response
         .bytes_stream()
         .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
@@ -49,17 +59,12 @@ pub async fn bake_download(brioche: &Brioche, download: DownloadRecipe) -> anyho
 
     let save_blob_options = crate::blob::SaveBlobOptions::new()
         .expected_hash(Some(download.hash))
+        .source_url(download.url.clone())
         .on_progress(|bytes_read| {
-            if let Some(content_length) = content_length {
-                let progress_percent = (bytes_read as f64 / content_length as f64) * 100.0;
-                let progress_percent = progress_percent.round().min(99.0) as u8;
-                brioche.reporter.update_job(
-                    job_id,
-                    crate::reporter::UpdateJob::Download {
-                        progress_percent: Some(progress_percent),
-                    },
-                );
-            }
+            brioche.reporter.update_job(
+                job_id,
+                crate::reporter::UpdateJob::download_progress(bytes_read as u64, content_length),
+            );
 
             Ok(())
         });
@@ -70,15 +75,27 @@ pub async fn bake_download(brioche: &Brioche, download: DownloadRecipe) -> anyho
         download_stream,
         save_blob_options,
     )
-    .await
-    .context("failed to save blob")?;
-
-    brioche.reporter.update_job(
-        job_id,
-        crate::reporter::UpdateJob::Download {
-            progress_percent: Some(100),
-        },
-    );
+    .await;
+    let blob_hash = match blob_hash {
+        Ok(blob_hash) => blob_hash,
+        Err(error) => {
+            if let Some(hash_updates) = &brioche.hash_updates {
+                if let Some(mismatch) = error.downcast_ref::<crate::blob::HashMismatchError>() {
+                    hash_updates.record(crate::update_hashes::HashUpdate {
+                        url: download.url.clone(),
+                        expected_hash: mismatch.expected_hash.clone(),
+                        actual_hash: mismatch.actual_hash.clone(),
+                    });
+                }
+            }
+
+            return Err(error).context("failed to save blob");
+        }
+    };
+
+    brioche
+        .reporter
+        .update_job(job_id, crate::reporter::UpdateJob::download_finished());
 
     Ok(File {
         content_blob: blob_hash,
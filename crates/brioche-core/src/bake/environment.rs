@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use bstr::BString;
+
+use crate::{
+    error_codes::{CodedError, ErrorCode},
+    recipe::{Artifact, Directory, WithMeta},
+    Brioche,
+};
+
+/// A package of an environment, after baking it.
+pub struct EnvironmentPackageOutput {
+    pub name: String,
+    pub priority: i32,
+    pub directory: Directory,
+}
+
+/// A path where packages with the same priority provide different
+/// artifacts.
+struct Collision {
+    path: BString,
+    packages: Vec<usize>,
+}
+
+/// Lay the directories of each package over each other. Directories that
+/// exist in multiple packages are merged recursively. For any other
+/// path, the package with the highest priority wins, and packages with
+/// the same priority providing different artifacts collide.
+#[tracing::instrument(skip_all, fields(num_packages = packages.len()))]
+pub async fn bake_environment(
+    brioche: &Brioche,
+    packages: &[EnvironmentPackageOutput],
+    ignore_collisions: bool,
+) -> anyhow::Result<Directory> {
+    let directories = packages
+        .iter()
+        .enumerate()
+        .map(|(index, package)| (index, package.directory.clone()))
+        .collect();
+    let mut collisions = vec![];
+    let directory = merge_directories(
+        brioche,
+        packages,
+        BString::default(),
+        directories,
+        &mut collisions,
+    )
+    .await?;
+
+    if collisions.is_empty() {
+        return Ok(directory);
+    }
+
+    let mut message = format!("{} paths collide in environment:", collisions.len());
+    for collision in &collisions {
+        let package_names = collision
+            .packages
+            .iter()
+            .map(|&index| &*packages[index].name)
+            .collect::<Vec<_>>();
+        message.push_str(&format!(
+            "\n  {:?} is provided by {}",
+            collision.path,
+            package_names.join(", ")
+        ));
+    }
+
+    if ignore_collisions {
+        tracing::debug!("{message}");
+        return Ok(directory);
+    }
+
+    Err(CodedError::new(ErrorCode::ENVIRONMENT_COLLISION, message).into())
+}
+
+#[async_recursion::async_recursion]
+async fn merge_directories(
+    brioche: &Brioche,
+    packages: &[EnvironmentPackageOutput],
+    path: BString,
+    directories: Vec<(usize, Directory)>,
+    collisions: &mut Vec<Collision>,
+) -> anyhow::Result<Directory> {
+    let mut candidates = BTreeMap::<BString, Vec<(usize, WithMeta<Artifact>)>>::new();
+    for (index, directory) in directories {
+        for (name, artifact) in directory.entries(brioche).await? {
+            candidates.entry(name).or_default().push((index, artifact));
+        }
+    }
+
+    let mut entries = BTreeMap::new();
+    for (name, candidates) in candidates {
+        let mut entry_path = path.clone();
+        if !entry_path.is_empty() {
+            entry_path.push(b'/');
+        }
+        entry_path.extend_from_slice(&name);
+
+        let top_priority = candidates
+            .iter()
+            .map(|(index, _)| packages[*index].priority)
+            .max()
+            .expect("expected at least one candidate");
+        let top_candidates = candidates
+            .iter()
+            .filter(|(index, _)| packages[*index].priority == top_priority)
+            .collect::<Vec<_>>();
+
+        let all_directories = top_candidates
+            .iter()
+            .all(|(_, artifact)| matches!(artifact.value, Artifact::Directory(_)));
+        if all_directories && candidates.len() > 1 {
+            // Merge every directory at this path, including ones from
+            // lower priority packages. Anything else is shadowed
+            let directories = candidates
+                .into_iter()
+                .filter_map(|(index, artifact)| match artifact.value {
+                    Artifact::Directory(directory) => Some((index, directory)),
+                    _ => None,
+                })
+                .collect();
+            let merged =
+                merge_directories(brioche, packages, entry_path, directories, collisions).await?;
+            entries.insert(name, WithMeta::without_meta(Artifact::Directory(merged)));
+            continue;
+        }
+
+        // The earliest package with the top priority wins
+        let (winner_index, winner) = top_candidates[0];
+        let winner_hash = winner.hash();
+        let colliding = top_candidates
+            .iter()
+            .filter(|(_, artifact)| artifact.hash() != winner_hash)
+            .map(|(index, _)| *index);
+        let mut colliding_packages = vec![*winner_index];
+        for index in colliding {
+            if !colliding_packages.contains(&index) {
+                colliding_packages.push(index);
+            }
+        }
+        if colliding_packages.len() > 1 {
+            collisions.push(Collision {
+                path: entry_path,
+                packages: colliding_packages,
+            });
+        }
+
+        entries.insert(name, winner.clone());
+    }
+
+    let directory = Directory::create(brioche, &entries).await?;
+    Ok(directory)
+}
@@ -0,0 +1,117 @@
+use sqlx::Acquire as _;
+
+use crate::{
+    recipe::{CachePolicy, Recipe, RecipeHash},
+    Brioche,
+};
+
+/// Returns true if a failure from baking this recipe should be cached.
+/// Only processes that exit with an error are cached, since other failures
+/// (like running out of disk space) may not happen again.
+pub fn caches_failures(brioche: &Brioche, recipe: &Recipe, cache_policy: CachePolicy) -> bool {
+    brioche.failed_process_cache_ttl.is_some()
+        && !brioche.read_only
+        && cache_policy != CachePolicy::NoCache
+        && matches!(recipe, Recipe::CompleteProcess(_))
+}
+
+/// Get the error message from a previous failed bake of a process, if it
+/// failed within the TTL. Always returns `None` when retrying failures.
+pub async fn find_failure(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> anyhow::Result<Option<String>> {
+    let Some(ttl) = brioche.failed_process_cache_ttl else {
+        return Ok(None);
+    };
+    if brioche.retry_failed {
+        return Ok(None);
+    }
+
+    let input_hash = recipe_hash.to_string();
+    let ttl_modifier = ttl_modifier(ttl);
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let failure = sqlx::query!(
+        r#"
+            SELECT message, created_at
+            FROM failed_process_bakes
+            WHERE input_hash = ? AND created_at > datetime('now', ?)
+        "#,
+        input_hash,
+        ttl_modifier,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    let message = failure.map(|failure| {
+        let message = failure.message;
+        let created_at = failure.created_at;
+        format!("{message}\nthis process already failed at {created_at} UTC, retry it with --retry-failed")
+    });
+    Ok(message)
+}
+
+/// Record that a process failed, replacing any earlier failure. Failures
+/// older than the TTL are removed at the same time.
+pub async fn record_failure(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+    message: &str,
+) -> anyhow::Result<()> {
+    let Some(ttl) = brioche.failed_process_cache_ttl else {
+        return Ok(());
+    };
+
+    let input_hash = recipe_hash.to_string();
+    let ttl_modifier = ttl_modifier(ttl);
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        "DELETE FROM failed_process_bakes WHERE created_at <= datetime('now', ?)",
+        ttl_modifier,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO failed_process_bakes (input_hash, message)
+            VALUES (?, ?)
+            ON CONFLICT (input_hash) DO UPDATE SET
+                message = excluded.message,
+                created_at = CURRENT_TIMESTAMP
+        "#,
+        input_hash,
+        message,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Forget a process's failure after it bakes successfully.
+pub async fn clear_failure(brioche: &Brioche, recipe_hash: RecipeHash) -> anyhow::Result<()> {
+    let input_hash = recipe_hash.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        "DELETE FROM failed_process_bakes WHERE input_hash = ?",
+        input_hash,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+/// A modifier for SQLite's `datetime` function to go back by the TTL.
+fn ttl_modifier(ttl: std::time::Duration) -> String {
+    format!("-{} seconds", ttl.as_secs())
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::Context as _;
+use bstr::ByteSlice as _;
+
+use crate::{
+    recipe::{Artifact, Directory, GitCheckoutRecipe, Meta},
+    Brioche,
+};
+
+#[tracing::instrument(skip(brioche, meta, checkout), fields(url = %checkout.url, rev = %checkout.rev))]
+pub async fn bake_git_checkout(
+    brioche: &Brioche,
+    meta: &Arc<Meta>,
+    checkout: GitCheckoutRecipe,
+) -> anyhow::Result<Directory> {
+    let commit_id = gix::ObjectId::from_hex(checkout.rev.as_bytes())
+        .with_context(|| format!("git rev {:?} is not a full commit hash", checkout.rev))?;
+
+    // Only fetch over HTTP(S), like downloads. Other transports could read
+    // local repos or use the user's SSH keys, and wouldn't go through the
+    // network allowlist
+    anyhow::ensure!(
+        matches!(checkout.url.scheme(), "http" | "https") && checkout.url.host_str().is_some(),
+        "unsupported git checkout URL {}, only http and https URLs are allowed",
+        checkout.url
+    );
+
+    let recipe_hash = crate::recipe::Recipe::GitCheckout(checkout.clone()).hash();
+    crate::network::check_url(brioche, recipe_hash, &checkout.url).await?;
+
+    tracing::debug!("acquiring download semaphore permit");
+    let _permit = brioche.download_semaphore.acquire().await?;
+    tracing::debug!("acquired download semaphore permit");
+
+    let job_id = brioche.reporter.add_job(crate::reporter::NewJob::Download {
+        url: checkout.url.clone(),
+    });
+
+    let result = check_out_commit(brioche, meta, &checkout.url, commit_id).await;
+
+    brioche
+        .reporter
+        .update_job(job_id, crate::reporter::UpdateJob::download_finished());
+
+    result
+}
+
+async fn check_out_commit(
+    brioche: &Brioche,
+    meta: &Arc<Meta>,
+    url: &url::Url,
+    commit_id: gix::ObjectId,
+) -> anyhow::Result<Directory> {
+    let repo_path = cache_repo_path(brioche, url);
+    let checkout_path = brioche
+        .home
+        .join("git-checkouts")
+        .join(ulid::Ulid::new().to_string());
+
+    {
+        // Only fetch into a cached repo from one task at a time
+        let repo_lock = repo_lock(&repo_path);
+        let _repo_guard = repo_lock.lock().await;
+
+        let url = url.clone();
+        let repo_path = repo_path.clone();
+        let checkout_path = checkout_path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let repo = fetch_commit(&repo_path, &url, commit_id)?;
+            write_commit(&repo, commit_id, &checkout_path)
+        })
+        .await?;
+        if let Err(error) = result {
+            crate::fs_utils::try_remove(&checkout_path).await?;
+            return Err(error)
+                .with_context(|| format!("failed to check out {commit_id} from {url}"));
+        }
+    }
+
+    let artifact = crate::input::create_input(
+        brioche,
+        crate::input::InputOptions {
+            input_path: &checkout_path,
+            remove_input: true,
+            resource_dir: None,
+            input_resource_dirs: &[],
+            meta,
+        },
+    )
+    .await
+    .context("failed to save git checkout")?;
+
+    let Artifact::Directory(directory) = artifact.value else {
+        anyhow::bail!("expected git checkout to be a directory");
+    };
+    Ok(directory)
+}
+
+/// Every repository gets a shared bare repo in the cache, keyed by URL.
+pub(crate) fn cache_repo_path(brioche: &Brioche, url: &url::Url) -> PathBuf {
+    let url_hash = blake3::hash(url.as_str().as_bytes());
+    brioche
+        .home
+        .join("git-cache")
+        .join(url_hash.to_hex().as_str())
+}
+
+pub(crate) fn repo_lock(repo_path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    static REPO_LOCKS: OnceLock<std::sync::Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+        OnceLock::new();
+    let repo_locks = REPO_LOCKS.get_or_init(Default::default);
+    let mut repo_locks = repo_locks.lock().expect("git repo locks poisoned");
+    repo_locks.entry(repo_path.to_owned()).or_default().clone()
+}
+
+/// Open (or create) the cached bare repo, then make sure it contains the
+/// commit. The commit is fetched shallowly, since we only ever need its
+/// tree.
+pub(crate) fn fetch_commit(
+    repo_path: &Path,
+    url: &url::Url,
+    commit_id: gix::ObjectId,
+) -> anyhow::Result<gix::Repository> {
+    let repo = open_cache_repo(repo_path)?;
+
+    if repo.find_object(commit_id).is_ok() {
+        tracing::debug!(%commit_id, "commit already in git cache");
+        return Ok(repo);
+    }
+
+    let refspec = commit_id.to_string();
+    let remote = repo
+        .remote_at(url.as_str())?
+        .with_refspecs([refspec.as_str()], gix::remote::Direction::Fetch)?;
+    let depth = NonZeroU32::new(1).expect("invalid depth");
+    remote
+        .connect(gix::remote::Direction::Fetch)?
+        .prepare_fetch(gix::progress::Discard, Default::default())?
+        .with_shallow(gix::remote::fetch::Shallow::DepthAtRemote(depth))
+        .receive(
+            gix::progress::Discard,
+            &std::sync::atomic::AtomicBool::new(false),
+        )?;
+
+    Ok(repo)
+}
+
+/// Fetch a ref (a branch or tag name, or `HEAD` if not set) into the cached
+/// bare repo, returning the commit it currently points to.
+pub(crate) fn fetch_ref(
+    repo_path: &Path,
+    url: &url::Url,
+    rev: Option<&str>,
+) -> anyhow::Result<(gix::Repository, gix::ObjectId)> {
+    const FETCH_REF: &str = "refs/brioche/fetch";
+
+    let repo = open_cache_repo(repo_path)?;
+
+    let refspec = format!("+{}:{FETCH_REF}", rev.unwrap_or("HEAD"));
+    let remote = repo
+        .remote_at(url.as_str())?
+        .with_refspecs([refspec.as_str()], gix::remote::Direction::Fetch)?;
+    let depth = NonZeroU32::new(1).expect("invalid depth");
+    remote
+        .connect(gix::remote::Direction::Fetch)?
+        .prepare_fetch(gix::progress::Discard, Default::default())?
+        .with_shallow(gix::remote::fetch::Shallow::DepthAtRemote(depth))
+        .receive(
+            gix::progress::Discard,
+            &std::sync::atomic::AtomicBool::new(false),
+        )?;
+
+    let commit_id = repo
+        .find_reference(FETCH_REF)
+        .with_context(|| format!("failed to fetch {refspec} from {url}"))?
+        .peel_to_id_in_place()?
+        .detach();
+    Ok((repo, commit_id))
+}
+
+fn open_cache_repo(repo_path: &Path) -> anyhow::Result<gix::Repository> {
+    let repo = if repo_path.exists() {
+        gix::open(repo_path)?
+    } else {
+        std::fs::create_dir_all(repo_path)?;
+        gix::init_bare(repo_path)?
+    };
+    Ok(repo)
+}
+
+/// Write the tree of a commit to `path`, without a `.git` directory.
+pub(crate) fn write_commit(
+    repo: &gix::Repository,
+    commit_id: gix::ObjectId,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let tree = repo
+        .find_object(commit_id)
+        .with_context(|| format!("commit {commit_id} not found"))?
+        .try_into_commit()
+        .with_context(|| format!("{commit_id} is not a commit"))?
+        .tree()?;
+    write_tree(repo, tree, path)
+}
+
+fn write_tree(repo: &gix::Repository, tree: gix::Tree<'_>, path: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path)?;
+
+    let mut names = HashSet::new();
+    for entry in tree.iter() {
+        let entry = entry?;
+        let name = entry.filename();
+        check_tree_entry_name(name)?;
+
+        // A repeated name could let a later entry write through a symlink
+        // written by an earlier one
+        anyhow::ensure!(
+            names.insert(name.to_owned()),
+            "git tree contains duplicate entry {name:?}"
+        );
+
+        let entry_path = path.join(name.to_path()?);
+        let mode = entry.mode();
+
+        if mode.is_tree() {
+            let subtree = repo.find_object(entry.oid())?.try_into_tree()?;
+            write_tree(repo, subtree, &entry_path)?;
+        } else if mode.is_link() {
+            let blob = repo.find_object(entry.oid())?.try_into_blob()?;
+            crate::fs_utils::symlink_blocking(blob.data.to_path()?, &entry_path)?;
+        } else if mode.is_commit() {
+            // Submodules aren't checked out, so they're left as empty
+            // directories like with a plain `git clone`
+            std::fs::create_dir_all(&entry_path)?;
+        } else {
+            let blob = repo.find_object(entry.oid())?.try_into_blob()?;
+            std::fs::write(&entry_path, &blob.data)?;
+            if mode.is_executable() {
+                let mut permissions = std::fs::metadata(&entry_path)?.permissions();
+                crate::fs_utils::set_executable(&mut permissions);
+                std::fs::set_permissions(&entry_path, permissions)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a tree entry from the repository can only write to a single
+/// path within the checkout. Names follow the same rules as directory
+/// entries, and `.git` is rejected so a checkout can't carry its own repo.
+fn check_tree_entry_name(name: &bstr::BStr) -> anyhow::Result<()> {
+    crate::recipe::check_entry_name(name)?;
+    anyhow::ensure!(
+        !name.eq_ignore_ascii_case(b".git"),
+        "git tree contains invalid entry {name:?}"
+    );
+
+    let path = name.to_path()?;
+    let mut components = path.components();
+    anyhow::ensure!(
+        matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        ),
+        "git tree contains invalid entry {name:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    recipe::{Artifact, CompleteProcessRecipe, Recipe, RecipeHash},
+    Brioche,
+};
+
+/// The last recorded bake of an incremental process.
+struct IncrementalBake {
+    /// The hash of the process without its previous output, i.e. the
+    /// hash of just the inputs the process declared itself.
+    base_hash: RecipeHash,
+    /// The hash of the process that was baked (including its previous
+    /// output).
+    recipe_hash: RecipeHash,
+    output_hash: RecipeHash,
+}
+
+/// Set the previous output of an incremental process, based on the last
+/// recorded bake with the same incremental key. If the last bake was for
+/// a process with the same inputs, the exact same process is returned
+/// again, so that it will be a cache hit rather than running the process
+/// again on top of its own output.
+pub async fn with_previous_output(
+    brioche: &Brioche,
+    process: CompleteProcessRecipe,
+) -> anyhow::Result<CompleteProcessRecipe> {
+    let Some(incremental_key) = &process.incremental_key else {
+        return Ok(process);
+    };
+
+    let Some(previous) = get_last_bake(brioche, incremental_key).await? else {
+        tracing::debug!(%incremental_key, "no previous output for incremental process");
+        return Ok(process);
+    };
+
+    let base_hash = base_hash(&process);
+    if previous.base_hash == base_hash {
+        let recipe = crate::recipe::get_recipe(brioche, previous.recipe_hash).await?;
+        let Recipe::CompleteProcess(recipe) = recipe else {
+            anyhow::bail!(
+                "expected recipe {} for incremental key {incremental_key:?} to be a complete process",
+                previous.recipe_hash
+            );
+        };
+        return Ok(recipe);
+    }
+
+    let previous_output = crate::recipe::get_recipe(brioche, previous.output_hash).await?;
+    let previous_output: Artifact = previous_output.try_into().map_err(|_| {
+        anyhow::anyhow!(
+            "previous output for incremental key {incremental_key:?} is not an artifact"
+        )
+    })?;
+
+    tracing::debug!(%incremental_key, previous_output_hash = %previous.output_hash, "using previous output for incremental process");
+
+    Ok(CompleteProcessRecipe {
+        previous_output: Some(Box::new(previous_output)),
+        ..process
+    })
+}
+
+/// Record the output of an incremental process, so that the next bake
+/// with the same incremental key will start from it.
+pub async fn record_output(
+    brioche: &Brioche,
+    process: &CompleteProcessRecipe,
+    output: &Artifact,
+) -> anyhow::Result<()> {
+    let incremental_key = process
+        .incremental_key
+        .as_ref()
+        .context("expected process to be incremental")?;
+    let base_hash = base_hash(process);
+    let recipe = Recipe::CompleteProcess(process.clone());
+    let recipe_hash = recipe.hash();
+    let output_hash = output.hash();
+
+    crate::recipe::save_recipes(brioche, [recipe, Recipe::from(output.clone())]).await?;
+
+    let base_hash_value = base_hash.to_string();
+    let recipe_hash_value = recipe_hash.to_string();
+    let output_hash_value = output_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO incremental_process_outputs (
+                incremental_key,
+                base_hash,
+                recipe_hash,
+                output_hash
+            ) VALUES (?, ?, ?, ?)
+            ON CONFLICT (incremental_key) DO UPDATE SET
+                base_hash = excluded.base_hash,
+                recipe_hash = excluded.recipe_hash,
+                output_hash = excluded.output_hash,
+                created_at = CURRENT_TIMESTAMP
+        "#,
+        incremental_key,
+        base_hash_value,
+        recipe_hash_value,
+        output_hash_value,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    tracing::debug!(%incremental_key, %recipe_hash, %output_hash, "recorded output for incremental process");
+
+    Ok(())
+}
+
+async fn get_last_bake(
+    brioche: &Brioche,
+    incremental_key: &str,
+) -> anyhow::Result<Option<IncrementalBake>> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let record = sqlx::query!(
+        r#"
+            SELECT base_hash, recipe_hash, output_hash
+            FROM incremental_process_outputs
+            WHERE incremental_key = ?
+        "#,
+        incremental_key,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    Ok(Some(IncrementalBake {
+        base_hash: record.base_hash.parse()?,
+        recipe_hash: record.recipe_hash.parse()?,
+        output_hash: record.output_hash.parse()?,
+    }))
+}
+
+fn base_hash(process: &CompleteProcessRecipe) -> RecipeHash {
+    let base = CompleteProcessRecipe {
+        previous_output: None,
+        ..process.clone()
+    };
+    Recipe::CompleteProcess(base).hash()
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::recipe::{Artifact, CompleteProcessRecipe, DownloadRecipe};
+
+type ProcessMock = Box<dyn Fn(&CompleteProcessRecipe) -> Option<Artifact> + Send + Sync>;
+
+/// Substitutes the results of download and process recipes, so recipes can
+/// be evaluated and tested without network access or a sandbox. When mocks
+/// are set, downloads and processes without a matching mock fail instead
+/// of running.
+///
+/// Results baked with mocks aren't saved to the database or synced, since
+/// they don't reflect what the recipes would actually produce.
+#[derive(Default)]
+pub struct BakeMocks {
+    downloads: HashMap<url::Url, Artifact>,
+    processes: Vec<ProcessMock>,
+}
+
+impl BakeMocks {
+    /// Return `artifact` when downloading from `url`. The download's hash
+    /// isn't checked against the artifact.
+    pub fn download(mut self, url: url::Url, artifact: Artifact) -> Self {
+        self.downloads.insert(url, artifact);
+        self
+    }
+
+    /// Return the artifact from `mock` for each process it matches. Mocks
+    /// are tried in the order they were added.
+    pub fn process(
+        mut self,
+        mock: impl Fn(&CompleteProcessRecipe) -> Option<Artifact> + Send + Sync + 'static,
+    ) -> Self {
+        self.processes.push(Box::new(mock));
+        self
+    }
+
+    pub fn bake_download(&self, download: &DownloadRecipe) -> anyhow::Result<Artifact> {
+        match self.downloads.get(&download.url) {
+            Some(artifact) => Ok(artifact.clone()),
+            None => anyhow::bail!("no mock for download from {}", download.url),
+        }
+    }
+
+    pub fn bake_process(&self, process: &CompleteProcessRecipe) -> anyhow::Result<Artifact> {
+        let artifact = self.processes.iter().find_map(|mock| mock(process));
+        match artifact {
+            Some(artifact) => Ok(artifact),
+            None => anyhow::bail!(
+                "no mock for process {}",
+                crate::recipe::Recipe::CompleteProcess(process.clone()).hash()
+            ),
+        }
+    }
+}
+
+impl std::fmt::Debug for BakeMocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BakeMocks")
+            .field("downloads", &self.downloads)
+            .field("processes", &self.processes.len())
+            .finish()
+    }
+}
@@ -0,0 +1,239 @@
+use bstr::{BString, ByteSlice as _};
+
+use crate::recipe::{
+    ArtifactDiscriminants, CreateDirectory, Directory, EnvironmentPackage, EnvironmentRecipe,
+    Recipe, WithMeta,
+};
+
+/// Rewrite chains of pure recipes into a canonical form, so that recipes
+/// that are built through structurally different but equivalent
+/// expressions end up with the same hash (and so share the same bake
+/// results). Normalization never needs to touch the database, and every
+/// rewrite bakes to the same artifact (or fails the same way) as the
+/// original recipe.
+///
+/// The following rewrites are applied:
+///
+/// - Nested merges are flattened into a single merge, and empty
+///   directories are dropped from merges. A merge of a single directory
+///   becomes the directory itself.
+/// - Entries of a directory that are themselves directories get inlined
+///   as nested paths (e.g. `{ a: { b: x } }` becomes `{ "a/b": x }`), as
+///   long as no other entry overlaps with the inlined path.
+/// - An empty directory recipe becomes an empty directory artifact.
+/// - Casts to the type a recipe already has are removed.
+/// - Setting permissions on a file recipe with known permissions is
+///   folded into the file recipe.
+/// - Peeling a directory zero times is removed.
+pub fn normalize_recipe(recipe: WithMeta<Recipe>) -> WithMeta<Recipe> {
+    let WithMeta { value, meta } = recipe;
+    let value = match value {
+        Recipe::Merge { directories } => normalize_merge(directories),
+        Recipe::CreateDirectory(directory) => normalize_create_directory(directory),
+        Recipe::Cast { recipe, to } => {
+            let recipe = normalize_recipe(*recipe);
+            if static_artifact_type(&recipe) == Some(to) {
+                recipe.value
+            } else {
+                Recipe::Cast {
+                    recipe: Box::new(recipe),
+                    to,
+                }
+            }
+        }
+        Recipe::SetPermissions { file, executable } => {
+            let file = normalize_recipe(*file);
+            normalize_set_permissions(file, executable)
+        }
+        Recipe::Peel { directory, depth } => {
+            let directory = normalize_recipe(*directory);
+            if depth == 0 {
+                directory.value
+            } else {
+                Recipe::Peel {
+                    directory: Box::new(directory),
+                    depth,
+                }
+            }
+        }
+        Recipe::Environment(environment) => {
+            let packages = environment
+                .packages
+                .into_iter()
+                .map(|package| EnvironmentPackage {
+                    recipe: normalize_recipe(package.recipe),
+                    ..package
+                })
+                .collect();
+            Recipe::Environment(EnvironmentRecipe {
+                packages,
+                ignore_collisions: environment.ignore_collisions,
+            })
+        }
+        Recipe::Annotate {
+            recipe,
+            annotations,
+        } => Recipe::Annotate {
+            recipe: Box::new(normalize_recipe(*recipe)),
+            annotations,
+        },
+        value => value,
+    };
+
+    WithMeta::new(value, meta)
+}
+
+fn normalize_merge(directories: Vec<WithMeta<Recipe>>) -> Recipe {
+    let mut flattened = vec![];
+    for directory in directories {
+        let directory = normalize_recipe(directory);
+        match directory.value {
+            Recipe::Merge { directories } => {
+                // Already normalized, so the inner merge can't contain
+                // any further nested merges
+                flattened.extend(directories);
+            }
+            Recipe::Directory(directory) if directory.is_empty() => {
+                // Merging an empty directory does nothing
+            }
+            value => {
+                flattened.push(WithMeta::new(value, directory.meta));
+            }
+        }
+    }
+
+    let is_single_directory = matches!(
+        &flattened[..],
+        [directory] if static_artifact_type(directory) == Some(ArtifactDiscriminants::Directory)
+    );
+    if flattened.is_empty() {
+        Recipe::Directory(Directory::default())
+    } else if is_single_directory {
+        let directory = flattened.pop().expect("expected one directory");
+        directory.value
+    } else {
+        Recipe::Merge {
+            directories: flattened,
+        }
+    }
+}
+
+fn normalize_create_directory(directory: CreateDirectory) -> Recipe {
+    let entries = directory
+        .entries
+        .into_iter()
+        .map(|(path, entry)| (path, normalize_recipe(entry)))
+        .collect::<Vec<_>>();
+
+    let mut normalized = CreateDirectory::default();
+    for (path, entry) in &entries {
+        let overlaps = entries
+            .iter()
+            .any(|(other, _)| other != path && paths_overlap(path, other));
+
+        match &entry.value {
+            Recipe::CreateDirectory(inner) if !overlaps => {
+                for (inner_path, inner_entry) in &inner.entries {
+                    let mut nested_path = path.clone();
+                    nested_path.push(b'/');
+                    nested_path.extend_from_slice(inner_path);
+                    normalized.entries.insert(nested_path, inner_entry.clone());
+                }
+            }
+            _ => {
+                normalized.entries.insert(path.clone(), entry.clone());
+            }
+        }
+    }
+
+    if normalized.is_empty() {
+        Recipe::Directory(Directory::default())
+    } else {
+        Recipe::CreateDirectory(normalized)
+    }
+}
+
+fn normalize_set_permissions(file: WithMeta<Recipe>, executable: Option<bool>) -> Recipe {
+    let WithMeta { value, meta } = file;
+    match (value, executable) {
+        (
+            Recipe::File {
+                content_blob,
+                executable: current,
+                resources,
+            },
+            executable,
+        ) => Recipe::File {
+            content_blob,
+            executable: executable.unwrap_or(current),
+            resources,
+        },
+        (
+            Recipe::CreateFile {
+                content,
+                executable: current,
+                resources,
+            },
+            executable,
+        ) => Recipe::CreateFile {
+            content,
+            executable: executable.unwrap_or(current),
+            resources,
+        },
+        (
+            Recipe::SetPermissions {
+                file,
+                executable: current,
+            },
+            executable,
+        ) => Recipe::SetPermissions {
+            file,
+            executable: executable.or(current),
+        },
+        (value, executable) => Recipe::SetPermissions {
+            file: Box::new(WithMeta::new(value, meta)),
+            executable,
+        },
+    }
+}
+
+/// Returns true if one path is a prefix of the other (when split into
+/// components), meaning inlining one would interact with the other.
+fn paths_overlap(a: &BString, b: &BString) -> bool {
+    let is_prefix = |prefix: &BString, path: &BString| {
+        path.strip_prefix(prefix.as_bytes())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"/"))
+    };
+    is_prefix(a, b) || is_prefix(b, a)
+}
+
+/// Get the type of artifact a recipe will bake to, if it can be determined
+/// without baking it.
+fn static_artifact_type(recipe: &Recipe) -> Option<ArtifactDiscriminants> {
+    match recipe {
+        Recipe::File { .. } | Recipe::CreateFile { .. } | Recipe::SetPermissions { .. } => {
+            Some(ArtifactDiscriminants::File)
+        }
+        Recipe::Directory(_)
+        | Recipe::CreateDirectory(_)
+        | Recipe::Merge { .. }
+        | Recipe::Environment(_)
+        | Recipe::Insert { .. }
+        | Recipe::Remove { .. }
+        | Recipe::Rename { .. }
+        | Recipe::ResolveSymlinks { .. }
+        | Recipe::Patch { .. }
+        | Recipe::Unarchive(_)
+        | Recipe::GitCheckout(_) => Some(ArtifactDiscriminants::Directory),
+        Recipe::Symlink { .. } => Some(ArtifactDiscriminants::Symlink),
+        Recipe::Download(_) => Some(ArtifactDiscriminants::File),
+        Recipe::Cast { to, .. } => Some(*to),
+        Recipe::Annotate { recipe, .. } => static_artifact_type(recipe),
+        Recipe::Process(_)
+        | Recipe::CompleteProcess(_)
+        | Recipe::Peel { .. }
+        | Recipe::Get { .. }
+        | Recipe::Proxy(_)
+        | Recipe::Sync { .. } => None,
+    }
+}
@@ -0,0 +1,441 @@
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bstr::{BStr, BString, ByteSlice as _};
+
+use crate::{
+    path::BriochePath,
+    recipe::{Artifact, Directory, File, Meta, WithMeta},
+    Brioche,
+};
+
+/// The most context lines that get ignored when checking if a hunk would
+/// have applied with fuzz.
+const MAX_DETECTED_FUZZ: usize = 2;
+
+/// Apply unified diffs to a directory. Unlike `patch`, hunks are never
+/// applied with fuzz: each hunk's context must match exactly (though it
+/// can be offset from the line numbers in the hunk header). If a hunk
+/// would only apply by ignoring some of its context, the error says so,
+/// since it usually means the patch needs to be refreshed.
+#[tracing::instrument(skip_all)]
+pub async fn bake_patch(
+    brioche: &Brioche,
+    meta: &Arc<Meta>,
+    mut directory: Directory,
+    patches: Vec<File>,
+    strip: u32,
+) -> anyhow::Result<Directory> {
+    for patch in patches {
+        let patch_contents = read_blob(brioche, &patch).await?;
+        let file_patches = parse_patch(&patch_contents)?;
+
+        for file_patch in file_patches {
+            let old_path = file_patch
+                .old_path
+                .as_ref()
+                .map(|path| strip_path(path, strip))
+                .transpose()?;
+            let new_path = file_patch
+                .new_path
+                .as_ref()
+                .map(|path| strip_path(path, strip))
+                .transpose()?;
+            let path = new_path
+                .as_ref()
+                .or(old_path.as_ref())
+                .context("patch has no file paths")?
+                .clone();
+
+            let existing = match &old_path {
+                Some(old_path) => {
+                    let existing = directory
+                        .get(brioche, old_path)
+                        .await?
+                        .with_context(|| format!("file to patch not found: {old_path}"))?;
+                    let Artifact::File(existing) = existing.value else {
+                        anyhow::bail!("tried to patch non-file: {old_path}");
+                    };
+                    Some(existing)
+                }
+                None => None,
+            };
+
+            let old_contents = match &existing {
+                Some(existing) => read_blob(brioche, existing).await?,
+                None => vec![],
+            };
+            let new_contents = apply_hunks(&path, &old_contents, &file_patch.hunks)?;
+
+            // Remove the old file first, in case the patch renames it
+            if let Some(old_path) = &old_path {
+                directory.insert(brioche, old_path, None).await?;
+            }
+
+            let Some(new_path) = new_path else {
+                anyhow::ensure!(
+                    new_contents.is_empty(),
+                    "patch deletes {path}, but the file has leftover contents"
+                );
+                continue;
+            };
+
+            let content_blob = {
+                let permit = crate::blob::get_save_blob_permit(brioche).await?;
+                crate::blob::save_blob(
+                    brioche,
+                    permit,
+                    &new_contents,
+                    crate::blob::SaveBlobOptions::default(),
+                )
+                .await?
+            };
+            let (executable, resources) = match existing {
+                Some(existing) => (existing.executable, existing.resources),
+                None => (false, Directory::default()),
+            };
+            let new_file = Artifact::File(File {
+                content_blob,
+                executable,
+                resources,
+            });
+
+            directory
+                .insert(
+                    brioche,
+                    &new_path,
+                    Some(WithMeta::new(new_file, meta.clone())),
+                )
+                .await?;
+        }
+    }
+
+    Ok(directory)
+}
+
+async fn read_blob(brioche: &Brioche, file: &File) -> anyhow::Result<Vec<u8>> {
+    let blob_path = crate::blob::blob_path(brioche, file.content_blob).await?;
+    let contents = tokio::fs::read(&blob_path)
+        .await
+        .with_context(|| format!("failed to read blob {}", file.content_blob))?;
+    Ok(contents)
+}
+
+fn strip_path(path: &BStr, strip: u32) -> anyhow::Result<BString> {
+    let parsed_path =
+        BriochePath::new(path).with_context(|| format!("invalid patch path {path}"))?;
+    let mut components = parsed_path.components();
+    for _ in 0..strip {
+        components
+            .next()
+            .with_context(|| format!("can't strip {strip} components from patch path {path}"))?;
+    }
+
+    let stripped = bstr::join("/", components);
+    anyhow::ensure!(
+        !stripped.is_empty(),
+        "patch path {path} is empty after stripping"
+    );
+    Ok(stripped.into())
+}
+
+#[derive(Debug)]
+struct FilePatch {
+    old_path: Option<BString>,
+    new_path: Option<BString>,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Default)]
+struct Hunk {
+    old_start: usize,
+    lines: Vec<HunkLine>,
+}
+
+#[derive(Debug)]
+enum HunkLine {
+    Context(BString),
+    Remove(BString),
+    Add(BString),
+}
+
+impl HunkLine {
+    fn content_mut(&mut self) -> &mut BString {
+        match self {
+            Self::Context(line) | Self::Remove(line) | Self::Add(line) => line,
+        }
+    }
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&BStr> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(line) | HunkLine::Remove(line) => Some(line.as_bstr()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<&BStr> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(line) | HunkLine::Add(line) => Some(line.as_bstr()),
+                HunkLine::Remove(_) => None,
+            })
+            .collect()
+    }
+
+    /// The number of context lines at the start and end of the hunk.
+    fn context_lines(&self) -> (usize, usize) {
+        let is_context = |line: &&HunkLine| matches!(line, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+        (leading, trailing)
+    }
+}
+
+fn parse_patch(contents: &[u8]) -> anyhow::Result<Vec<FilePatch>> {
+    let mut lines = contents.lines_with_terminator().enumerate().peekable();
+    let mut file_patches = vec![];
+
+    while let Some((_, line)) = lines.next() {
+        if let Some(old_path) = line.strip_prefix(b"--- ") {
+            let Some((line_index, new_line)) = lines.next() else {
+                anyhow::bail!("unexpected end of patch after '---' line");
+            };
+            let new_path = new_line
+                .strip_prefix(b"+++ ")
+                .with_context(|| format!("line {}: expected '+++' line", line_index + 1))?;
+
+            file_patches.push(FilePatch {
+                old_path: parse_patch_path(old_path),
+                new_path: parse_patch_path(new_path),
+                hunks: vec![],
+            });
+        } else if line.starts_with(b"@@ ") {
+            let file_patch = file_patches
+                .last_mut()
+                .context("found hunk before file header in patch")?;
+            let (old_start, mut old_remaining, mut new_remaining) = parse_hunk_header(line)?;
+
+            let mut hunk = Hunk {
+                old_start,
+                lines: vec![],
+            };
+            while old_remaining > 0 || new_remaining > 0 {
+                let Some((line_index, line)) = lines.next() else {
+                    anyhow::bail!("unexpected end of patch in hunk");
+                };
+                let (marker, content) = match line.split_first() {
+                    Some((marker, content)) => (*marker, content),
+                    None => anyhow::bail!("line {}: unexpected empty line", line_index + 1),
+                };
+                match marker {
+                    // Some tools strip trailing whitespace, turning empty
+                    // context lines into blank lines
+                    b' ' | b'\n' | b'\r' => {
+                        let content = if marker == b' ' { content } else { line };
+                        hunk.lines.push(HunkLine::Context(content.into()));
+                        old_remaining = old_remaining.saturating_sub(1);
+                        new_remaining = new_remaining.saturating_sub(1);
+                    }
+                    b'-' => {
+                        hunk.lines.push(HunkLine::Remove(content.into()));
+                        old_remaining = old_remaining.saturating_sub(1);
+                    }
+                    b'+' => {
+                        hunk.lines.push(HunkLine::Add(content.into()));
+                        new_remaining = new_remaining.saturating_sub(1);
+                    }
+                    b'\\' => {
+                        strip_last_newline(&mut hunk)?;
+                    }
+                    _ => {
+                        anyhow::bail!("line {}: invalid line in hunk", line_index + 1);
+                    }
+                }
+            }
+
+            // A "no newline" marker can follow the last line of the hunk
+            if let Some((_, line)) = lines.peek() {
+                if line.starts_with(b"\\") {
+                    lines.next();
+                    strip_last_newline(&mut hunk)?;
+                }
+            }
+
+            file_patch.hunks.push(hunk);
+        }
+    }
+
+    anyhow::ensure!(!file_patches.is_empty(), "no file changes found in patch");
+
+    Ok(file_patches)
+}
+
+fn strip_last_newline(hunk: &mut Hunk) -> anyhow::Result<()> {
+    let last_line = hunk
+        .lines
+        .last_mut()
+        .context("unexpected '\\' line at start of hunk")?
+        .content_mut();
+    if last_line.ends_with(b"\r\n") {
+        last_line.truncate(last_line.len() - 2);
+    } else if last_line.ends_with(b"\n") {
+        last_line.truncate(last_line.len() - 1);
+    }
+    Ok(())
+}
+
+fn parse_patch_path(line: &[u8]) -> Option<BString> {
+    // Paths can be followed by a tab and a timestamp
+    let path = line.split_str("\t").next().unwrap_or(line).trim_end();
+    if path == b"/dev/null" {
+        None
+    } else {
+        Some(path.into())
+    }
+}
+
+/// Parse a hunk header like `@@ -1,3 +1,4 @@`, returning the starting line
+/// number in the old file plus the number of old and new lines.
+fn parse_hunk_header(line: &[u8]) -> anyhow::Result<(usize, usize, usize)> {
+    let header = line.to_str().context("invalid hunk header")?;
+    let mut parts = header.split_whitespace().skip(1);
+    let (Some(old_range), Some(new_range)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("invalid hunk header: {header:?}");
+    };
+    let old_range = old_range
+        .strip_prefix('-')
+        .with_context(|| format!("invalid hunk header: {header:?}"))?;
+    let new_range = new_range
+        .strip_prefix('+')
+        .with_context(|| format!("invalid hunk header: {header:?}"))?;
+
+    let parse_range = |range: &str| -> anyhow::Result<(usize, usize)> {
+        let (start, count) = match range.split_once(',') {
+            Some((start, count)) => (start.parse()?, count.parse()?),
+            None => (range.parse()?, 1),
+        };
+        Ok((start, count))
+    };
+    let (old_start, old_count) =
+        parse_range(old_range).with_context(|| format!("invalid hunk header: {header:?}"))?;
+    let (_, new_count) =
+        parse_range(new_range).with_context(|| format!("invalid hunk header: {header:?}"))?;
+
+    Ok((old_start, old_count, new_count))
+}
+
+fn apply_hunks(path: &BStr, contents: &[u8], hunks: &[Hunk]) -> anyhow::Result<Vec<u8>> {
+    let lines = contents.lines_with_terminator().collect::<Vec<_>>();
+
+    let mut result: Vec<u8> = vec![];
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+    for (hunk_index, hunk) in hunks.iter().enumerate() {
+        let hunk_number = hunk_index + 1;
+        let old_lines = hunk.old_lines();
+        let new_lines = hunk.new_lines();
+
+        // Line numbers are 1-based, except that a hunk that only adds
+        // lines refers to the line *after* which lines get added
+        let base = if old_lines.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = base.saturating_add_signed(offset).max(cursor);
+
+        let Some(position) = find_lines(&lines, &old_lines, expected, cursor) else {
+            let fuzz = detect_fuzz(&lines, hunk, expected, cursor);
+            match fuzz {
+                Some((fuzz, position)) => {
+                    anyhow::bail!(
+                        "hunk #{hunk_number} for {path} only applies with fuzz {fuzz} (at line {}), the patch may need to be refreshed",
+                        position + 1,
+                    );
+                }
+                None => {
+                    anyhow::bail!("hunk #{hunk_number} for {path} does not apply");
+                }
+            }
+        };
+
+        for line in &lines[cursor..position] {
+            result.extend_from_slice(line);
+        }
+        for line in new_lines {
+            result.extend_from_slice(line);
+        }
+
+        cursor = position + old_lines.len();
+        offset = position as isize - base as isize;
+    }
+
+    for line in &lines[cursor..] {
+        result.extend_from_slice(line);
+    }
+
+    Ok(result)
+}
+
+/// Find where `needle` appears in `lines`, searching outward from the
+/// expected position. Returns the closest match at or after `min`.
+fn find_lines(lines: &[&[u8]], needle: &[&BStr], expected: usize, min: usize) -> Option<usize> {
+    let matches_at = |position: usize| {
+        position >= min
+            && position + needle.len() <= lines.len()
+            && needle
+                .iter()
+                .zip(&lines[position..])
+                .all(|(needle_line, line)| **needle_line == **line)
+    };
+
+    let max_distance = lines.len().max(expected) + 1;
+    for distance in 0..=max_distance {
+        if let Some(position) = expected.checked_add(distance) {
+            if matches_at(position) {
+                return Some(position);
+            }
+        }
+        if let Some(position) = expected.checked_sub(distance) {
+            if distance > 0 && matches_at(position) {
+                return Some(position);
+            }
+        }
+    }
+
+    None
+}
+
+/// Check if a hunk would match by ignoring some of its leading and
+/// trailing context lines, like `patch` does with its fuzz factor.
+fn detect_fuzz(
+    lines: &[&[u8]],
+    hunk: &Hunk,
+    expected: usize,
+    min: usize,
+) -> Option<(usize, usize)> {
+    let (leading_context, trailing_context) = hunk.context_lines();
+    let old_lines = hunk.old_lines();
+
+    for fuzz in 1..=MAX_DETECTED_FUZZ {
+        let skip_leading = fuzz.min(leading_context);
+        let skip_trailing = fuzz.min(trailing_context);
+        if skip_leading + skip_trailing >= old_lines.len() {
+            break;
+        }
+
+        let needle = &old_lines[skip_leading..old_lines.len() - skip_trailing];
+        let position = find_lines(lines, needle, expected + skip_leading, min);
+        if let Some(position) = position {
+            return Some((fuzz, position - skip_leading.min(position)));
+        }
+    }
+
+    None
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -7,13 +7,18 @@ use std::{
 use anyhow::Context as _;
 use bstr::ByteVec as _;
 use futures::{StreamExt as _, TryStreamExt as _};
+use sqlx::Acquire as _;
 use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 
 use crate::{
+    error_codes::{CodedError, ErrorCode},
+    platform::emulator::EmulatorMode,
+    project::policy::Capability,
     recipe::{
         ArchiveFormat, Artifact, CompleteProcessRecipe, CompleteProcessTemplate,
         CompleteProcessTemplateComponent, CompressionFormat, DirectoryError, DownloadRecipe, Meta,
-        ProcessRecipe, ProcessTemplate, ProcessTemplateComponent, Recipe, Unarchive, WithMeta,
+        ProcessRecipe, ProcessTemplate, ProcessTemplateComponent, Recipe, RecipeHash, Unarchive,
+        WithMeta,
     },
     sandbox::{
         HostPathMode, SandboxExecutionConfig, SandboxPath, SandboxPathOptions, SandboxTemplate,
@@ -22,27 +27,59 @@ use crate::{
     Brioche,
 };
 
-const GUEST_UID_HINT: u32 = 1099;
-const GUEST_GID_HINT: u32 = 1099;
-
-#[tracing::instrument(skip(brioche, process))]
+#[tracing::instrument(skip(brioche, meta, process))]
 pub async fn bake_lazy_process_to_process(
     brioche: &Brioche,
     scope: &super::BakeScope,
+    meta: &Meta,
     process: ProcessRecipe,
 ) -> anyhow::Result<CompleteProcessRecipe> {
-    let unsafe_required = process.networking;
+    // Networking and secrets are only unsafe if the output isn't fixed
+    let networking_requires_unsafe = process.networking && process.output_hash.is_none();
+    let secrets_require_unsafe = !process.secrets.is_empty() && process.output_hash.is_none();
+    let unsafe_required = networking_requires_unsafe || secrets_require_unsafe;
+
+    if networking_requires_unsafe && !process.is_unsafe {
+        return Err(CodedError::new(
+            ErrorCode::NETWORKING_REQUIRES_UNSAFE,
+            "to enable networking, `unsafe` must be set to true (or an output hash must be set)",
+        )
+        .into());
+    } else if secrets_require_unsafe && !process.is_unsafe {
+        return Err(CodedError::new(
+            ErrorCode::SECRETS_REQUIRE_UNSAFE,
+            "to use secrets, `unsafe` must be set to true (or an output hash must be set)",
+        )
+        .into());
+    } else if !unsafe_required && process.is_unsafe {
+        return Err(CodedError::new(
+            ErrorCode::UNNECESSARY_UNSAFE,
+            "process is marked as unsafe but does not use any unsafe features",
+        )
+        .into());
+    }
 
-    if unsafe_required {
-        anyhow::ensure!(
-            process.is_unsafe,
-            "to enable networking, `unsafe` must be set to true"
-        );
-    } else {
-        anyhow::ensure!(
-            !process.is_unsafe,
-            "process is marked as unsafe but does not use any unsafe features"
-        );
+    let capabilities = [
+        process.is_unsafe.then_some(Capability::Unsafe),
+        process.networking.then_some(Capability::Networking),
+    ];
+    let capabilities: Vec<_> = capabilities.into_iter().flatten().collect();
+    crate::project::policy::check_process_capabilities(brioche, meta, &capabilities).await?;
+
+    anyhow::ensure!(
+        !(process.capture_stdout && process.output_scaffold.is_some()),
+        "a process that captures stdout cannot have an output scaffold"
+    );
+    anyhow::ensure!(
+        !(process.capture_stdout && process.incremental_key.is_some()),
+        "a process that captures stdout cannot be incremental"
+    );
+    anyhow::ensure!(
+        !(process.capture_stdout && process.tty),
+        "a process that captures stdout cannot use a TTY"
+    );
+    for secret in &process.secrets {
+        crate::secrets::validate_secret_name(secret)?;
     }
 
     let command =
@@ -85,7 +122,26 @@ pub async fn bake_lazy_process_to_process(
         None => None,
     };
 
-    Ok(CompleteProcessRecipe {
+    let emulator = if process.platform == crate::platform::current_platform() {
+        None
+    } else {
+        let emulator = brioche
+            .emulators
+            .find(process.platform)
+            .await?
+            .with_context(|| {
+                CodedError::new(
+                    ErrorCode::EMULATOR_NOT_FOUND,
+                    format!(
+                        "cannot run process for platform {}: no emulator found (register a QEMU emulator with binfmt_misc, or set one under `emulators` in the config file)",
+                        process.platform
+                    ),
+                )
+            })?;
+        Some(emulator.identity.clone())
+    };
+
+    let process = CompleteProcessRecipe {
         command,
         args,
         env,
@@ -94,7 +150,18 @@ pub async fn bake_lazy_process_to_process(
         platform: process.platform,
         is_unsafe: process.is_unsafe,
         networking: process.networking,
-    })
+        output_hash: process.output_hash,
+        capture_stdout: process.capture_stdout,
+        tty: process.tty,
+        cache_policy: process.cache_policy,
+        secrets: process.secrets,
+        emulator,
+        incremental_key: process.incremental_key,
+        previous_output: None,
+    };
+    let process = super::incremental::with_previous_output(brioche, process).await?;
+
+    Ok(process)
 }
 
 #[tracing::instrument(skip_all)]
@@ -284,13 +351,95 @@ pub async fn bake_process(
     meta: &Arc<Meta>,
     process: CompleteProcessRecipe,
 ) -> anyhow::Result<Artifact> {
+    run_process(brioche, meta, process, None).await
+}
+
+/// The sandbox config used for a past process execution, along with the
+/// temporary directory it ran in.
+struct RecordedSandboxSpec {
+    bake_dir: PathBuf,
+    sandbox_config: SandboxExecutionConfig,
+}
+
+pub struct ProcessReplay {
+    pub output: Artifact,
+    /// The output from when the process was originally baked, if it
+    /// succeeded.
+    pub cached_output: Option<Artifact>,
+}
+
+/// Run a process again using the exact sandbox config that was recorded
+/// when it was last run. The result isn't saved as a bake, so it can be
+/// compared against the original output to find nondeterminism.
+pub async fn replay_process(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> anyhow::Result<ProcessReplay> {
+    let spec = get_sandbox_spec(brioche, recipe_hash)
+        .await?
+        .with_context(|| format!("no recorded process execution found for {recipe_hash}"))?;
+    let recipe = crate::recipe::get_recipe(brioche, recipe_hash).await?;
+    let Recipe::CompleteProcess(process) = recipe else {
+        anyhow::bail!("recipe {recipe_hash} is not a complete process recipe");
+    };
+
+    let cached_output = get_cached_output(brioche, recipe_hash).await?;
+    let output = run_process(brioche, &Arc::new(Meta::default()), process, Some(spec)).await?;
+
+    Ok(ProcessReplay {
+        output,
+        cached_output,
+    })
+}
+
+async fn run_process(
+    brioche: &Brioche,
+    meta: &Arc<Meta>,
+    process: CompleteProcessRecipe,
+    replay_spec: Option<RecordedSandboxSpec>,
+) -> anyhow::Result<Artifact> {
+    // Fetch the blobs of every input at once, rather than one input at a
+    // time as each one gets set up
+    prefetch_process_input_blobs(brioche, &process).await?;
+
     tracing::debug!("acquiring process semaphore permit");
     let _permit = brioche.process_semaphore.acquire().await;
     tracing::debug!("acquired process semaphore permit");
 
-    let hash = Recipe::CompleteProcess(process.clone()).hash();
+    let process_recipe = process.clone();
+    let recipe = Recipe::CompleteProcess(process.clone());
+    let hash = recipe.hash();
+
+    let emulator = match &process.emulator {
+        Some(identity) => {
+            let emulator = brioche.emulators.find(process.platform).await?;
+            let emulator = emulator.filter(|emulator| emulator.identity == *identity);
+            let emulator = emulator.with_context(|| {
+                format!(
+                    "cannot run process for platform {}: emulator {} with hash {} not found",
+                    process.platform, identity.name, identity.content_hash
+                )
+            })?;
+            Some(emulator)
+        }
+        None => None,
+    };
+
+    let temp_dir = &brioche.process_scratch_dir;
+
+    // The output is written to the temp dir, then saved as blobs before
+    // the temp dir gets cleaned up, so it briefly takes up twice its size
+    let estimated_output_size =
+        crate::disk_space::estimated_process_output_size(brioche, &process_recipe).await?;
+    if let Some(estimated_output_size) = estimated_output_size {
+        crate::disk_space::ensure_available_space(
+            temp_dir,
+            estimated_output_size.saturating_mul(2),
+            &format!("process {hash} (based on the output size of a previous build)"),
+        )
+        .await?;
+    }
 
-    let temp_dir = brioche.home.join("process-temp");
     let bake_dir = temp_dir.join(ulid::Ulid::new().to_string());
     let bake_dir = BakeDir::create(bake_dir).await?;
     let root_dir = bake_dir.path().join("root");
@@ -375,12 +524,18 @@ pub async fn bake_process(
                 resource_dir: Some(&host_resource_dir),
                 mtime: Some(crate::fs_utils::brioche_epoch()),
                 link_locals: false,
+                link_strategy: crate::output::LinkStrategy::Copy,
+                permissions: crate::output::OutputPermissions::default(),
             },
         )
         .await
     };
     let create_output_scaffold_fut = async {
-        if let Some(output_scaffold) = &process.output_scaffold {
+        let output_scaffold = process
+            .previous_output
+            .as_ref()
+            .or(process.output_scaffold.as_ref());
+        if let Some(output_scaffold) = output_scaffold {
             crate::output::create_output(
                 brioche,
                 output_scaffold,
@@ -390,6 +545,8 @@ pub async fn bake_process(
                     resource_dir: Some(&host_resource_dir),
                     mtime: Some(crate::fs_utils::brioche_epoch()),
                     link_locals: false,
+                    link_strategy: crate::output::LinkStrategy::Copy,
+                    permissions: crate::output::OutputPermissions::default(),
                 },
             )
             .await
@@ -441,12 +598,31 @@ pub async fn bake_process(
     };
 
     let command = build_process_template(brioche, process.command, dirs).await?;
-    let args = futures::stream::iter(process.args)
+    let mut args = futures::stream::iter(process.args)
         .then(|arg| build_process_template(brioche, arg, dirs))
         .try_collect::<Vec<_>>()
         .await?;
 
-    let env = futures::stream::iter(process.env)
+    // Emulators that aren't handled by binfmt_misc wrap the command, so
+    // the original command becomes the emulator's first argument
+    let command = match emulator {
+        Some(emulator) if emulator.mode == EmulatorMode::Wrapper => {
+            let guest_emulator_path = format!("/brioche-emulator/{}", emulator.identity.name);
+            args.insert(0, command);
+            SandboxTemplate {
+                components: vec![SandboxTemplateComponent::Path(SandboxPath {
+                    host_path: emulator.path.clone(),
+                    options: SandboxPathOptions {
+                        mode: HostPathMode::Read,
+                        guest_path_hint: guest_emulator_path.into(),
+                    },
+                })],
+            }
+        }
+        _ => command,
+    };
+
+    let mut env = futures::stream::iter(process.env)
         .then(|(key, artifact)| async move {
             let template = build_process_template(brioche, artifact, dirs).await?;
             anyhow::Ok((key, template))
@@ -454,6 +630,29 @@ pub async fn bake_process(
         .try_collect::<HashMap<_, _>>()
         .await?;
 
+    // Secrets are mounted read-only from outside of the sandbox root, so
+    // they can't end up in the output or the work dir
+    let host_secrets_dir = bake_dir.path().join("secrets");
+    let mut include_secrets_dir = None;
+    if !process.secrets.is_empty() {
+        let guest_secrets_dir = "/run/brioche-secrets";
+        env.insert(
+            "BRIOCHE_SECRETS_DIR".into(),
+            SandboxTemplate {
+                components: vec![SandboxTemplateComponent::Literal {
+                    value: guest_secrets_dir.into(),
+                }],
+            },
+        );
+        include_secrets_dir = Some((
+            host_secrets_dir.clone(),
+            SandboxPathOptions {
+                mode: HostPathMode::Read,
+                guest_path_hint: guest_secrets_dir.into(),
+            },
+        ));
+    }
+
     let sandbox_config = SandboxExecutionConfig {
         sandbox_root: root_dir,
         include_host_paths: HashMap::from_iter([
@@ -485,7 +684,10 @@ pub async fn bake_process(
                     guest_path_hint: guest_temp_dir.into(),
                 },
             ),
-        ]),
+        ])
+        .into_iter()
+        .chain(include_secrets_dir)
+        .collect(),
         command,
         args,
         env,
@@ -496,17 +698,134 @@ pub async fn bake_process(
                 guest_path_hint: guest_work_dir.into(),
             },
         },
+        stdout_path: process.capture_stdout.then(|| output_path.clone()),
+        stderr_path: None,
+        tty: process.tty,
         networking: process.networking,
-        uid_hint: GUEST_UID_HINT,
-        gid_hint: GUEST_GID_HINT,
+        uid_hint: brioche.sandbox_user.uid,
+        gid_hint: brioche.sandbox_user.gid,
+        subordinate_ids: brioche.sandbox_user.subordinate_ids,
+        trace_path: None,
+        network_proxy: None,
     };
 
-    let result = if brioche.self_exec_processes {
-        run_sandboxed_self_exec(brioche, sandbox_config, stdout_file, stderr_file).await
+    // When replaying, the directories are set up the same way as before,
+    // but the recorded config is used verbatim (apart from moving it to the
+    // new temporary directory)
+    let is_replay = replay_spec.is_some();
+    let mut sandbox_config = match replay_spec {
+        Some(spec) => spec
+            .sandbox_config
+            .rebase_host_paths(&spec.bake_dir, bake_dir.path()),
+        None => {
+            record_sandbox_spec(brioche, recipe, bake_dir.path(), &sandbox_config).await?;
+            sandbox_config
+        }
+    };
+
+    // The trace path is set after recording the sandbox spec, so replays
+    // only trace when requested
+    let trace_path = bake_dir.path().join("trace.json");
+    let traced_guest_paths = if brioche.trace_process_inputs {
+        anyhow::ensure!(
+            brioche.self_exec_processes,
+            "tracing process inputs requires running processes with self-exec"
+        );
+        sandbox_config.trace_path = Some(trace_path.clone());
+        Some(crate::sandbox::input_trace::SandboxGuestPaths::from_config(
+            &sandbox_config,
+        ))
     } else {
+        None
+    };
+
+    // With a network allowlist, networked processes get their own network
+    // namespace, and can only reach the outside through a proxy that
+    // checks each host (which in turn uses the configured proxies).
+    // Otherwise, networked processes are pointed at the configured proxies
+    // directly. Like tracing, this is set after recording the sandbox
+    // spec, so replays use the current network settings
+    let (network_proxy, proxy_config) = match &brioche.network_allowlist {
+        Some(allowlist) if sandbox_config.networking => {
+            let network_proxy =
+                crate::network::NetworkProxy::start(allowlist.clone(), brioche.proxy.clone())?;
+            sandbox_config.network_proxy = Some(crate::sandbox::SandboxNetworkProxy {
+                host_socket_name: network_proxy.socket_name().to_string(),
+                guest_port: crate::network::SANDBOX_PROXY_PORT,
+            });
+
+            let proxy_url: url::Url =
+                format!("http://127.0.0.1:{}", crate::network::SANDBOX_PROXY_PORT).parse()?;
+            let proxy_config = crate::network::ProxyConfig {
+                http: Some(proxy_url.clone()),
+                https: Some(proxy_url),
+                no_proxy: None,
+            };
+            (Some(network_proxy), proxy_config)
+        }
+        _ if sandbox_config.networking => (None, brioche.proxy.clone()),
+        _ => (None, crate::network::ProxyConfig::default()),
+    };
+    for (key, value) in proxy_config.env_vars() {
+        sandbox_config.env.insert(
+            key.into(),
+            SandboxTemplate {
+                components: vec![SandboxTemplateComponent::Literal {
+                    value: value.into(),
+                }],
+            },
+        );
+    }
+
+    // Secrets are written right before the process runs, and removed as
+    // soon as it exits (even when keeping temporary files). The guard also
+    // removes them if the bake is cancelled while the process is running
+    let (secret_values, secrets_guard) =
+        write_secrets(brioche, &process_recipe.secrets, &host_secrets_dir).await?;
+    let redactor = || crate::secrets::Redactor::new(secret_values.iter().cloned());
+
+    let result = if brioche.self_exec_processes {
+        run_sandboxed_self_exec(
+            brioche,
+            sandbox_config,
+            stdout_file,
+            stderr_file,
+            redactor(),
+            redactor(),
+        )
+        .await
+    } else if secret_values.is_empty() {
         run_sandboxed_inline(sandbox_config).await
+    } else {
+        run_sandboxed_inline_redacted(
+            sandbox_config,
+            bake_dir.path(),
+            stdout_file,
+            stderr_file,
+            redactor(),
+            redactor(),
+        )
+        .await
     };
 
+    // Stop the proxy before checking if the secrets were removed, so it
+    // doesn't outlive the process either way
+    let secrets_removed = secrets_guard.remove().await;
+
+    if let Some(network_proxy) = network_proxy {
+        let contacted_hosts = network_proxy.stop();
+        for contacted_host in contacted_hosts.iter().filter(|host| !host.allowed) {
+            tracing::warn!(
+                %hash,
+                host = contacted_host.host,
+                "process tried to contact a host outside of the network allowlist"
+            );
+        }
+        crate::network::record_contacted_hosts(brioche, hash, &contacted_hosts).await?;
+    }
+
+    secrets_removed?;
+
     match result {
         Ok(()) => {}
         Err(error) => {
@@ -514,15 +833,44 @@ pub async fn bake_process(
                 .await
                 .context("failed to write process status")?;
             return Err(error).with_context(|| {
-                format!(
-                    "process failed, view full output from these paths:\n- {}\n- {}",
-                    stdout_path.display(),
-                    stderr_path.display()
+                CodedError::new(
+                    ErrorCode::PROCESS_FAILED,
+                    format!(
+                        "process failed, view full output from these paths:\n- {}\n- {}",
+                        stdout_path.display(),
+                        stderr_path.display()
+                    ),
                 )
             });
         }
     }
 
+    if let Some(traced_guest_paths) = &traced_guest_paths {
+        let reported = report_input_trace(
+            brioche,
+            hash,
+            traced_guest_paths,
+            &trace_path,
+            bake_dir.path(),
+        )
+        .await;
+        if let Err(error) = reported {
+            tracing::warn!(%hash, "failed to report process input trace: {error:#}");
+        }
+    }
+
+    if !is_replay {
+        let recorded = async {
+            let output_size = crate::disk_space::disk_usage(&output_path).await?;
+            crate::disk_space::record_process_output_size(brioche, &process_recipe, output_size)
+                .await
+        }
+        .await;
+        if let Err(error) = recorded {
+            tracing::warn!(%hash, "failed to record process output size: {error:#}");
+        }
+    }
+
     let result = crate::input::create_input(
         brioche,
         crate::input::InputOptions {
@@ -540,12 +888,301 @@ pub async fn bake_process(
         bake_dir.remove().await?;
     }
 
+    if let Some(expected_hash) = process.output_hash {
+        let actual_hash = result.value.hash();
+        if actual_hash != expected_hash {
+            return Err(CodedError::new(
+                ErrorCode::OUTPUT_HASH_MISMATCH,
+                format!("process output hash did not match: expected {expected_hash}, got {actual_hash}"),
+            )
+            .into());
+        }
+    }
+
+    if !is_replay && process_recipe.incremental_key.is_some() {
+        super::incremental::record_output(brioche, &process_recipe, &result.value).await?;
+    }
+
+    if !is_replay {
+        let identical_output =
+            super::process_inputs::find_identical_output(brioche, &process_recipe, &result.value)
+                .await;
+        match identical_output {
+            Ok(Some(identical_output)) => {
+                warn_identical_output(brioche, hash, &identical_output);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                tracing::warn!(%hash, "failed to check for identical process outputs: {error:#}");
+            }
+        }
+    }
+
     Ok(result.value)
 }
 
+fn warn_identical_output(
+    brioche: &Brioche,
+    hash: RecipeHash,
+    identical_output: &super::process_inputs::IdenticalOutput,
+) {
+    let mut message = format!(
+        "warning: process {hash} produced the same output as process {}, which only differed by these inputs:",
+        identical_output.previous_cache_key,
+    );
+    for changed_input in &identical_output.changed_inputs {
+        let describe = |value: &super::process_inputs::ProcessInputValue| match value {
+            super::process_inputs::ProcessInputValue::Artifact { kind, hash } => {
+                format!("{} {hash}", format!("{kind:?}").to_lowercase())
+            }
+            other => format!("{other:?}"),
+        };
+        message.push_str(&format!(
+            "\n  {}: {} -> {}",
+            changed_input.location,
+            describe(&changed_input.previous),
+            describe(&changed_input.current),
+        ));
+    }
+    message.push_str("\nProcesses are cached by their inputs, so this rebuild could have been skipped with content-addressed caching (early cutoff) of the changed inputs");
+
+    brioche
+        .reporter
+        .emit(superconsole::Lines::from_multiline_string(
+            &message,
+            superconsole::style::ContentStyle {
+                foreground_color: Some(superconsole::style::Color::Yellow),
+                ..superconsole::style::ContentStyle::default()
+            },
+        ));
+}
+
+async fn report_input_trace(
+    brioche: &Brioche,
+    hash: RecipeHash,
+    guest_paths: &crate::sandbox::input_trace::SandboxGuestPaths,
+    trace_path: &Path,
+    bake_dir: &Path,
+) -> anyhow::Result<()> {
+    let trace = tokio::fs::read(trace_path)
+        .await
+        .with_context(|| format!("failed to read trace {}", trace_path.display()))?;
+    let accesses: Vec<crate::sandbox::input_trace::FileAccess> = serde_json::from_slice(&trace)?;
+    let report = guest_paths.report(&accesses);
+
+    let report_path = bake_dir.join("input-trace.json");
+    tokio::fs::write(&report_path, serde_json::to_string_pretty(&report)?).await?;
+
+    if report.is_empty() {
+        tracing::info!(%hash, "process used all of its inputs");
+        return Ok(());
+    }
+
+    let mut message = format!("process {hash} input trace:");
+    if !report.unused_inputs.is_empty() {
+        message.push_str("\n  inputs that were never accessed:");
+        for input in &report.unused_inputs {
+            message.push_str(&format!("\n    {input}"));
+        }
+    }
+    if !report.undeclared_accesses.is_empty() {
+        message.push_str("\n  paths that were missing from the sandbox:");
+        for path in &report.undeclared_accesses {
+            message.push_str(&format!("\n    {path}"));
+        }
+    }
+    if brioche.keep_temps {
+        message.push_str(&format!("\nFull report: {}", report_path.display()));
+    }
+
+    brioche
+        .reporter
+        .emit(superconsole::Lines::from_multiline_string(
+            &message,
+            superconsole::style::ContentStyle {
+                foreground_color: Some(superconsole::style::Color::Yellow),
+                ..superconsole::style::ContentStyle::default()
+            },
+        ));
+
+    Ok(())
+}
+
+async fn record_sandbox_spec(
+    brioche: &Brioche,
+    recipe: Recipe,
+    bake_dir: &Path,
+    sandbox_config: &SandboxExecutionConfig,
+) -> anyhow::Result<()> {
+    let recipe_hash = recipe.hash();
+    let bake_dir = bake_dir
+        .to_str()
+        .with_context(|| format!("invalid bake dir path: {}", bake_dir.display()))?;
+    let sandbox_config_json = serde_json::to_string(sandbox_config)?;
+
+    // Save the recipe too, so the process can be replayed even if it fails
+    crate::recipe::save_recipes(brioche, [recipe]).await?;
+
+    let recipe_hash_value = recipe_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO process_sandbox_specs (
+                recipe_hash,
+                bake_dir,
+                sandbox_config_json
+            ) VALUES (?, ?, ?)
+            ON CONFLICT (recipe_hash) DO UPDATE SET
+                bake_dir = excluded.bake_dir,
+                sandbox_config_json = excluded.sandbox_config_json,
+                created_at = CURRENT_TIMESTAMP
+        "#,
+        recipe_hash_value,
+        bake_dir,
+        sandbox_config_json,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn get_sandbox_spec(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> anyhow::Result<Option<RecordedSandboxSpec>> {
+    let recipe_hash_value = recipe_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let record = sqlx::query!(
+        r#"
+            SELECT bake_dir, sandbox_config_json
+            FROM process_sandbox_specs
+            WHERE recipe_hash = ?
+        "#,
+        recipe_hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    let sandbox_config = serde_json::from_str(&record.sandbox_config_json)
+        .context("failed to parse recorded sandbox config")?;
+    Ok(Some(RecordedSandboxSpec {
+        bake_dir: PathBuf::from(record.bake_dir),
+        sandbox_config,
+    }))
+}
+
+async fn get_cached_output(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> anyhow::Result<Option<Artifact>> {
+    let recipe_hash_value = recipe_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let record = sqlx::query!(
+        r#"
+            SELECT output_artifacts.recipe_json AS artifact_json
+            FROM bakes
+            INNER JOIN recipes AS output_artifacts
+                ON bakes.output_hash = output_artifacts.recipe_hash
+            WHERE bakes.input_hash = ?
+            LIMIT 1
+        "#,
+        recipe_hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    let output = record
+        .map(|record| serde_json::from_str(&record.artifact_json))
+        .transpose()?;
+    Ok(output)
+}
+
+/// Write each secret to a file in the secrets directory, returning their
+/// values so they can be redacted from the process's output, along with a
+/// guard that removes the secrets directory.
+async fn write_secrets(
+    brioche: &Brioche,
+    secrets: &BTreeSet<String>,
+    secrets_dir: &Path,
+) -> anyhow::Result<(Vec<Vec<u8>>, SecretsDirGuard)> {
+    if secrets.is_empty() {
+        return Ok((vec![], SecretsDirGuard { secrets_dir: None }));
+    }
+
+    // Create the guard first, so anything written is removed on error
+    let guard = SecretsDirGuard {
+        secrets_dir: Some(secrets_dir.to_owned()),
+    };
+    tokio::fs::create_dir(secrets_dir).await?;
+
+    let mut values = vec![];
+    for name in secrets {
+        let value = brioche.secrets.read(name).await?;
+        let path = secrets_dir.join(name);
+        tokio::fs::write(&path, &value)
+            .await
+            .with_context(|| format!("failed to write secret {name:?}"))?;
+        let mut permissions = tokio::fs::metadata(&path).await?.permissions();
+        crate::fs_utils::set_owner_only(&mut permissions);
+        tokio::fs::set_permissions(&path, permissions).await?;
+
+        values.push(value);
+    }
+
+    Ok((values, guard))
+}
+
+/// Removes a process's secrets directory. Call [`SecretsDirGuard::remove`]
+/// to remove it and check for errors, otherwise it's removed when the
+/// guard is dropped.
+struct SecretsDirGuard {
+    secrets_dir: Option<PathBuf>,
+}
+
+impl SecretsDirGuard {
+    async fn remove(mut self) -> anyhow::Result<()> {
+        let Some(secrets_dir) = self.secrets_dir.take() else {
+            return Ok(());
+        };
+
+        match tokio::fs::remove_dir_all(&secrets_dir).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error).context("failed to remove process secrets"),
+        }
+    }
+}
+
+impl Drop for SecretsDirGuard {
+    fn drop(&mut self) {
+        let Some(secrets_dir) = self.secrets_dir.take() else {
+            return;
+        };
+
+        match std::fs::remove_dir_all(&secrets_dir) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                tracing::warn!(secrets_dir = %secrets_dir.display(), "failed to remove process secrets: {error}");
+            }
+        }
+    }
+}
+
 async fn run_sandboxed_inline(sandbox_config: SandboxExecutionConfig) -> anyhow::Result<()> {
-    let status =
-        tokio::task::spawn_blocking(|| crate::sandbox::run_sandbox(sandbox_config)).await??;
+    let status = tokio::task::spawn_blocking(|| crate::sandbox::run_sandbox(sandbox_config))
+        .await?
+        .with_context(|| CodedError::new(ErrorCode::SANDBOX_FAILED, "failed to run sandbox"))?;
 
     anyhow::ensure!(
         status.success(),
@@ -555,11 +1192,67 @@ async fn run_sandboxed_inline(sandbox_config: SandboxExecutionConfig) -> anyhow:
     Ok(())
 }
 
+/// Run a process inline with its output written to files, then redact the
+/// files into the process's logs. Otherwise, inline processes write
+/// straight to Brioche's own stdout and stderr, where secrets can't be
+/// redacted.
+async fn run_sandboxed_inline_redacted(
+    mut sandbox_config: SandboxExecutionConfig,
+    bake_dir: &Path,
+    write_stdout: tokio::fs::File,
+    write_stderr: tokio::fs::File,
+    stdout_redactor: crate::secrets::Redactor,
+    stderr_redactor: crate::secrets::Redactor,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !sandbox_config.tty,
+        "processes with secrets can only use a TTY when run with self-exec, so their output can be redacted"
+    );
+
+    // A captured stdout is the process's output, so it's left as-is
+    let raw_stdout_path = bake_dir.join("stdout.raw");
+    let raw_stderr_path = bake_dir.join("stderr.raw");
+    if sandbox_config.stdout_path.is_none() {
+        sandbox_config.stdout_path = Some(raw_stdout_path.clone());
+    }
+    sandbox_config.stderr_path = Some(raw_stderr_path.clone());
+
+    let result = run_sandboxed_inline(sandbox_config).await;
+
+    redact_output_file(&raw_stdout_path, write_stdout, stdout_redactor).await?;
+    redact_output_file(&raw_stderr_path, write_stderr, stderr_redactor).await?;
+
+    result
+}
+
+/// Copy a file of raw process output to `write` with secrets redacted,
+/// then remove the raw file.
+async fn redact_output_file(
+    raw_path: &Path,
+    mut write: tokio::fs::File,
+    mut redactor: crate::secrets::Redactor,
+) -> anyhow::Result<()> {
+    let raw = match tokio::fs::read(raw_path).await {
+        Ok(raw) => raw,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error).context("failed to read process output"),
+    };
+
+    write.write_all(&redactor.redact(&raw)).await?;
+    write.write_all(&redactor.finish()).await?;
+    write.flush().await?;
+    tokio::fs::remove_file(raw_path).await?;
+
+    Ok(())
+}
+
 async fn run_sandboxed_self_exec(
     brioche: &Brioche,
     sandbox_config: SandboxExecutionConfig,
     write_stdout: impl tokio::io::AsyncWrite + Send + Sync + 'static,
     write_stderr: impl tokio::io::AsyncWrite + Send + Sync + 'static,
+    mut stdout_redactor: crate::secrets::Redactor,
+    mut stderr_redactor: crate::secrets::Redactor,
 ) -> anyhow::Result<()> {
     tracing::debug!(?sandbox_config, "running sandboxed process");
 
@@ -590,21 +1283,38 @@ async fn run_sandboxed_self_exec(
             let mut write_stdout = std::pin::pin!(write_stdout);
             let mut write_stderr = std::pin::pin!(write_stderr);
             loop {
-                let packet = tokio::select! {
+                // Secrets are redacted before the output is logged or
+                // reported
+                let (packet, eof) = tokio::select! {
                     bytes_read = stdout.read(&mut stdout_buffer) => {
                         let buffer = &stdout_buffer[..bytes_read?];
-                        write_stdout.write_all(buffer).await?;
-                        crate::reporter::ProcessPacket::Stdout(buffer.to_vec())
+                        let redacted = if buffer.is_empty() {
+                            stdout_redactor.finish()
+                        } else {
+                            stdout_redactor.redact(buffer)
+                        };
+                        write_stdout.write_all(&redacted).await?;
+                        (crate::reporter::ProcessPacket::Stdout(redacted), buffer.is_empty())
                     }
                     bytes_read = stderr.read(&mut stderr_buffer) => {
                         let buffer = &stderr_buffer[..bytes_read?];
-                        write_stderr.write_all(buffer).await?;
-                        crate::reporter::ProcessPacket::Stdout(buffer.to_vec())
+                        let redacted = if buffer.is_empty() {
+                            stderr_redactor.finish()
+                        } else {
+                            stderr_redactor.redact(buffer)
+                        };
+                        write_stderr.write_all(&redacted).await?;
+                        (crate::reporter::ProcessPacket::Stdout(redacted), buffer.is_empty())
                     }
                 };
 
                 if packet.bytes().is_empty() {
-                    break;
+                    if eof {
+                        break;
+                    }
+
+                    // Everything read so far was held back by the redactor
+                    continue;
                 }
 
                 brioche.reporter.update_job(
@@ -637,13 +1347,50 @@ async fn run_sandboxed_self_exec(
     );
 
     let result = output?;
-    if !result.status.success() {
+    if result.status.code() == Some(crate::sandbox::SANDBOX_ERROR_EXIT_CODE.into()) {
+        return Err(CodedError::new(
+            ErrorCode::SANDBOX_FAILED,
+            "failed to set up the process sandbox (the stderr log has details)",
+        )
+        .into());
+    } else if !result.status.success() {
         anyhow::bail!("process exited with status code {}", result.status);
     }
 
     Ok(())
 }
 
+async fn prefetch_process_input_blobs(
+    brioche: &Brioche,
+    process: &CompleteProcessRecipe,
+) -> anyhow::Result<()> {
+    let templates = [&process.command]
+        .into_iter()
+        .chain(&process.args)
+        .chain(process.env.values());
+    let inputs = templates
+        .flat_map(|template| &template.components)
+        .filter_map(|component| match component {
+            CompleteProcessTemplateComponent::Input { artifact } => Some(artifact.value.clone()),
+            _ => None,
+        });
+    let output_scaffold = process
+        .previous_output
+        .as_deref()
+        .or(process.output_scaffold.as_deref())
+        .cloned();
+    let artifacts = inputs
+        .chain([Artifact::Directory(process.work_dir.clone())])
+        .chain(output_scaffold);
+
+    let mut blobs = HashSet::new();
+    crate::references::descendent_artifact_blobs(brioche, artifacts, &mut blobs).await?;
+    let blobs = blobs.into_iter().collect::<Vec<_>>();
+    crate::blob::prefetch_blobs(brioche, &blobs).await?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy)]
 struct ProcessTemplateDirs<'a> {
     output_path: &'a Path,
@@ -868,24 +1615,17 @@ async fn append_dependency_envs(
 
                     // Get the path of the symlink relative to the
                     // root of the dependency artifact
-                    let dependency_subpath = bstr::join(
-                        "/",
-                        [
-                            "brioche-env.d".as_bytes(),
-                            "env".as_bytes(),
-                            &**env_var,
-                            &*env_value_target,
-                        ]
-                        .into_iter(),
-                    );
                     let dependency_subpath =
-                        crate::fs_utils::logical_path_bytes(&dependency_subpath)?;
+                        crate::path::BriochePath::from_component("brioche-env.d")?
+                            .join_component("env")?
+                            .join_component(env_var)?
+                            .resolve(&env_value_target)?;
 
                     // Append the env var
                     env_var_appends.push((
                         env_var.clone(),
                         dependency_artifact.clone(),
-                        bstr::BString::new(dependency_subpath),
+                        dependency_subpath.into_bstring(),
                     ));
                 }
             }
@@ -947,6 +1687,8 @@ async fn set_up_rootfs(
         resource_dir: None,
         mtime: None,
         link_locals: true,
+        link_strategy: crate::output::LinkStrategy::Copy,
+        permissions: crate::output::OutputPermissions::default(),
     };
 
     let dash = Recipe::Unarchive(Unarchive {
@@ -989,7 +1731,7 @@ async fn set_up_rootfs(
         .await
         .context("failed to create usr")?;
 
-    tokio::fs::symlink("/bin/env", usr_bin_dir.join("env"))
+    crate::fs_utils::symlink(Path::new("/bin/env"), &usr_bin_dir.join("env"))
         .await
         .context("failed to symlink env")?;
 
@@ -998,10 +1740,12 @@ async fn set_up_rootfs(
         .await
         .context("failed to create etc")?;
 
-    let etc_passwd_contents = format!(
-        "{guest_username}:!x:{GUEST_UID_HINT}:{GUEST_GID_HINT}::{guest_home_dir}:/bin/sh\n",
-    );
+    let crate::sandbox::SandboxUser { uid, gid, .. } = brioche.sandbox_user;
+    let etc_passwd_contents =
+        format!("{guest_username}:!x:{uid}:{gid}::{guest_home_dir}:/bin/sh\n");
     tokio::fs::write(etc_dir.join("passwd"), &etc_passwd_contents).await?;
+    let etc_group_contents = format!("{guest_username}:x:{gid}:\n");
+    tokio::fs::write(etc_dir.join("group"), &etc_group_contents).await?;
 
     tracing::trace!("built rootfs");
 
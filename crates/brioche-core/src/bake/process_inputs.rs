@@ -0,0 +1,326 @@
+use bstr::BString;
+use joinery::JoinableIterator as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    recipe::{
+        Artifact, ArtifactDiscriminants, CachePolicy, CompleteProcessRecipe,
+        CompleteProcessTemplate, CompleteProcessTemplateComponent, Recipe, RecipeHash, WithMeta,
+    },
+    Brioche,
+};
+
+use super::BakeScope;
+
+const MAX_IDENTICAL_OUTPUT_CANDIDATES: i64 = 10;
+
+/// Every value that goes into a process's cache key. A process's output is
+/// cached by the hash of its complete recipe, so a change to any of these
+/// inputs causes the process to run again.
+#[derive(Debug, Clone)]
+pub struct ProcessInputsReport {
+    /// The hash of the complete process recipe, used as its cache key.
+    pub cache_key: RecipeHash,
+    pub inputs: Vec<ProcessInput>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessInput {
+    /// Where the value is used in the process, such as `args[1]` or
+    /// `env.PATH[0]`.
+    pub location: String,
+    pub value: ProcessInputValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessInputValue {
+    Literal(BString),
+    Artifact {
+        kind: ArtifactDiscriminants,
+        hash: RecipeHash,
+    },
+    Setting(String),
+}
+
+/// Report the inputs of a process recipe. Lazy process recipes are
+/// resolved the same way as when baking, so each input is baked first
+/// (using cached results when available). The process itself isn't run.
+pub async fn process_inputs(
+    brioche: &Brioche,
+    recipe: WithMeta<Recipe>,
+    scope: &BakeScope,
+) -> anyhow::Result<ProcessInputsReport> {
+    let WithMeta { value, meta } = recipe;
+    let process = match value {
+        Recipe::Process(process) => {
+            super::process::bake_lazy_process_to_process(brioche, scope, &meta, process).await?
+        }
+        Recipe::CompleteProcess(process) => process,
+        other => {
+            anyhow::bail!("expected a process recipe, got {:?}", other.kind());
+        }
+    };
+
+    Ok(complete_process_inputs(process))
+}
+
+/// Report the inputs of a complete process recipe.
+pub fn complete_process_inputs(process: CompleteProcessRecipe) -> ProcessInputsReport {
+    let cache_key = Recipe::CompleteProcess(process.clone()).hash();
+
+    let CompleteProcessRecipe {
+        command,
+        args,
+        env,
+        work_dir,
+        output_scaffold,
+        platform,
+        is_unsafe,
+        networking,
+        output_hash,
+        capture_stdout,
+        tty,
+        cache_policy,
+        secrets,
+        emulator,
+        incremental_key,
+        previous_output,
+    } = process;
+
+    let mut inputs = vec![];
+    add_template_inputs(&mut inputs, "command".to_string(), &command);
+    for (n, arg) in args.iter().enumerate() {
+        add_template_inputs(&mut inputs, format!("args[{n}]"), arg);
+    }
+    for (key, value) in &env {
+        add_template_inputs(&mut inputs, format!("env.{key}"), value);
+    }
+    inputs.push(ProcessInput {
+        location: "work_dir".to_string(),
+        value: artifact_value(&Artifact::Directory(work_dir)),
+    });
+    if let Some(output_scaffold) = &output_scaffold {
+        inputs.push(ProcessInput {
+            location: "output_scaffold".to_string(),
+            value: artifact_value(output_scaffold),
+        });
+    }
+    if let Some(previous_output) = &previous_output {
+        inputs.push(ProcessInput {
+            location: "previous_output".to_string(),
+            value: artifact_value(previous_output),
+        });
+    }
+
+    let settings = [
+        ("platform", Some(platform.to_string())),
+        ("unsafe", is_unsafe.then(|| "true".to_string())),
+        ("networking", networking.then(|| "true".to_string())),
+        ("output_hash", output_hash.map(|hash| hash.to_string())),
+        ("capture_stdout", capture_stdout.then(|| "true".to_string())),
+        ("tty", tty.then(|| "true".to_string())),
+        (
+            "cache_policy",
+            (cache_policy != CachePolicy::Default).then(|| cache_policy.to_string()),
+        ),
+        (
+            "secrets",
+            (!secrets.is_empty()).then(|| secrets.iter().join_with(", ").to_string()),
+        ),
+        (
+            "emulator",
+            emulator.map(|emulator| format!("{} {}", emulator.name, emulator.content_hash)),
+        ),
+        ("incremental_key", incremental_key),
+    ];
+    for (location, value) in settings {
+        if let Some(value) = value {
+            inputs.push(ProcessInput {
+                location: location.to_string(),
+                value: ProcessInputValue::Setting(value),
+            });
+        }
+    }
+
+    ProcessInputsReport { cache_key, inputs }
+}
+
+impl ProcessInputsReport {
+    /// A key that's shared by every process with the same structure, i.e.
+    /// processes that only differ by their input artifacts.
+    pub fn structure_key(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for input in &self.inputs {
+            hasher.update(input.location.as_bytes());
+            hasher.update(&[0]);
+            match &input.value {
+                ProcessInputValue::Literal(value) => {
+                    hasher.update(b"literal:");
+                    hasher.update(value);
+                }
+                ProcessInputValue::Artifact { .. } => {
+                    hasher.update(b"artifact");
+                }
+                ProcessInputValue::Setting(value) => {
+                    hasher.update(b"setting:");
+                    hasher.update(value.as_bytes());
+                }
+            }
+            hasher.update(&[0]);
+        }
+
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+/// A previously-run process that produced the same output as another
+/// process, even though some of its inputs were different.
+#[derive(Debug, Clone)]
+pub struct IdenticalOutput {
+    pub previous_cache_key: RecipeHash,
+    pub changed_inputs: Vec<ChangedInput>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangedInput {
+    pub location: String,
+    pub previous: ProcessInputValue,
+    pub current: ProcessInputValue,
+}
+
+/// Look for a previous run of an equivalent process that produced the same
+/// output. Processes are only considered equivalent if they have the same
+/// structure and only differ by their input artifacts, which usually means
+/// a dependency was rebuilt without its output actually changing.
+pub async fn find_identical_output(
+    brioche: &Brioche,
+    process: &CompleteProcessRecipe,
+    output: &Artifact,
+) -> anyhow::Result<Option<IdenticalOutput>> {
+    let report = complete_process_inputs(process.clone());
+    let output_hash = output.hash();
+
+    let output_hash_value = output_hash.to_string();
+    let cache_key_value = report.cache_key.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let previous_recipes = sqlx::query!(
+        r#"
+            SELECT recipes.recipe_json
+            FROM bakes
+            INNER JOIN recipes
+                ON recipes.recipe_hash = bakes.input_hash
+            WHERE
+                bakes.output_hash = ?
+                AND bakes.input_hash != ?
+                AND json_extract(recipes.recipe_json, '$.type') = 'complete_process'
+            ORDER BY bakes.id DESC
+            LIMIT ?
+        "#,
+        output_hash_value,
+        cache_key_value,
+        MAX_IDENTICAL_OUTPUT_CANDIDATES,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    for previous_recipe in previous_recipes {
+        let previous_recipe: Recipe = serde_json::from_str(&previous_recipe.recipe_json)?;
+        let Recipe::CompleteProcess(previous_process) = previous_recipe else {
+            continue;
+        };
+        let previous_report = complete_process_inputs(previous_process);
+
+        if let Some(changed_inputs) = changed_artifact_inputs(&previous_report, &report) {
+            return Ok(Some(IdenticalOutput {
+                previous_cache_key: previous_report.cache_key,
+                changed_inputs,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the changed inputs between two reports, but only if every
+/// changed input is an artifact.
+fn changed_artifact_inputs(
+    previous: &ProcessInputsReport,
+    current: &ProcessInputsReport,
+) -> Option<Vec<ChangedInput>> {
+    if previous.inputs.len() != current.inputs.len() {
+        return None;
+    }
+
+    let mut changed_inputs = vec![];
+    for (previous_input, current_input) in previous.inputs.iter().zip(&current.inputs) {
+        if previous_input.location != current_input.location {
+            return None;
+        }
+
+        match (&previous_input.value, &current_input.value) {
+            (previous_value, current_value) if previous_value == current_value => {}
+            (
+                previous_value @ ProcessInputValue::Artifact { .. },
+                current_value @ ProcessInputValue::Artifact { .. },
+            ) => {
+                changed_inputs.push(ChangedInput {
+                    location: current_input.location.clone(),
+                    previous: previous_value.clone(),
+                    current: current_value.clone(),
+                });
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    if changed_inputs.is_empty() {
+        None
+    } else {
+        Some(changed_inputs)
+    }
+}
+
+fn add_template_inputs(
+    inputs: &mut Vec<ProcessInput>,
+    location: String,
+    template: &CompleteProcessTemplate,
+) {
+    let num_components = template.components.len();
+    for (n, component) in template.components.iter().enumerate() {
+        // Placeholders like the output path are the same for every
+        // process, so they never cause a rebuild
+        let value = match component {
+            CompleteProcessTemplateComponent::Literal { value } => {
+                ProcessInputValue::Literal(value.clone())
+            }
+            CompleteProcessTemplateComponent::Input { artifact } => artifact_value(artifact),
+            CompleteProcessTemplateComponent::OutputPath
+            | CompleteProcessTemplateComponent::ResourceDir
+            | CompleteProcessTemplateComponent::InputResourceDirs
+            | CompleteProcessTemplateComponent::HomeDir
+            | CompleteProcessTemplateComponent::WorkDir
+            | CompleteProcessTemplateComponent::TempDir => {
+                continue;
+            }
+        };
+
+        let location = if num_components > 1 {
+            format!("{location}[{n}]")
+        } else {
+            location.clone()
+        };
+        inputs.push(ProcessInput { location, value });
+    }
+}
+
+fn artifact_value(artifact: &Artifact) -> ProcessInputValue {
+    ProcessInputValue::Artifact {
+        kind: artifact.into(),
+        hash: artifact.hash(),
+    }
+}
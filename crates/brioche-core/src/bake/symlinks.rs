@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use anyhow::Context as _;
+use bstr::{BString, ByteSlice as _};
+
+use crate::{
+    recipe::{Artifact, Directory, WithMeta},
+    Brioche,
+};
+
+/// The maximum number of symlinks followed while resolving a single path,
+/// matching Linux's limit.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// Replace every symlink within a directory with the artifact it points to.
+/// Symlinks must be relative and must resolve to a path within the
+/// directory, otherwise an error is returned (as are dangling symlinks and
+/// symlink cycles). The resources of files are left untouched.
+#[tracing::instrument(skip_all)]
+pub async fn bake_resolve_symlinks(
+    brioche: &Brioche,
+    directory: Directory,
+) -> anyhow::Result<Directory> {
+    let mut visiting = vec![];
+    let resolved = resolve_artifact(
+        brioche,
+        &directory,
+        vec![],
+        Artifact::Directory(directory.clone()),
+        &mut visiting,
+    )
+    .await?;
+
+    let Artifact::Directory(resolved) = resolved else {
+        anyhow::bail!("expected resolved artifact to be a directory");
+    };
+    Ok(resolved)
+}
+
+#[async_recursion::async_recursion]
+async fn resolve_artifact(
+    brioche: &Brioche,
+    root: &Directory,
+    path: Vec<BString>,
+    artifact: Artifact,
+    visiting: &mut Vec<Vec<BString>>,
+) -> anyhow::Result<Artifact> {
+    match artifact {
+        Artifact::File(file) => Ok(Artifact::File(file)),
+        Artifact::Symlink { .. } => {
+            let (resolved_path, target) = resolve_path(brioche, root, &path).await?;
+            resolve_artifact(brioche, root, resolved_path, target, visiting).await
+        }
+        Artifact::Directory(directory) => {
+            // A directory that's already being resolved means a symlink
+            // pointed back to one of its own parents
+            anyhow::ensure!(
+                !visiting.contains(&path),
+                "symlink cycle found at {:?}",
+                display_path(&path),
+            );
+            visiting.push(path.clone());
+
+            let mut resolved_directory = Directory::default();
+            for (name, entry) in directory.entries(brioche).await? {
+                let mut entry_path = path.clone();
+                entry_path.push(name.clone());
+
+                let resolved =
+                    resolve_artifact(brioche, root, entry_path, entry.value, visiting).await?;
+                resolved_directory
+                    .insert(brioche, &name, Some(WithMeta::new(resolved, entry.meta)))
+                    .await?;
+            }
+
+            visiting.pop();
+
+            Ok(Artifact::Directory(resolved_directory))
+        }
+    }
+}
+
+/// Resolve a path within `root`, following every symlink along the way
+/// (including the last component). Returns the canonical path and the
+/// artifact it points to, which is never a symlink.
+async fn resolve_path(
+    brioche: &Brioche,
+    root: &Directory,
+    path: &[BString],
+) -> anyhow::Result<(Vec<BString>, Artifact)> {
+    let mut directories = vec![root.clone()];
+    let mut resolved_path: Vec<BString> = vec![];
+    let mut resolved_file = None;
+    let mut remaining: VecDeque<BString> = path.iter().cloned().collect();
+    let mut num_follows = 0;
+
+    while let Some(component) = remaining.pop_front() {
+        anyhow::ensure!(
+            resolved_file.is_none(),
+            "failed to resolve {:?}: {:?} is not a directory",
+            display_path(path),
+            display_path(&resolved_path),
+        );
+
+        match component.as_bytes() {
+            b"" | b"." => {
+                continue;
+            }
+            b".." => {
+                anyhow::ensure!(
+                    resolved_path.pop().is_some(),
+                    "failed to resolve {:?}: symlink points outside the directory",
+                    display_path(path),
+                );
+                directories.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        let directory = directories.last().expect("no directory to resolve from");
+        let entry = directory.get(brioche, &component).await?.with_context(|| {
+            format!(
+                "failed to resolve {:?}: {:?} not found",
+                display_path(path),
+                display_path(&[&resolved_path[..], &[component.clone()][..]].concat()),
+            )
+        })?;
+
+        match entry.value {
+            Artifact::Symlink { target } => {
+                num_follows += 1;
+                anyhow::ensure!(
+                    num_follows <= MAX_SYMLINK_FOLLOWS,
+                    "failed to resolve {:?}: too many levels of symlinks",
+                    display_path(path),
+                );
+                anyhow::ensure!(
+                    !target.starts_with(b"/"),
+                    "failed to resolve {:?}: symlink has absolute target {target:?}",
+                    display_path(path),
+                );
+
+                // The target is relative to the directory containing the
+                // symlink, which is where we already are
+                let target_components = target.split_str("/").collect::<Vec<_>>();
+                for target_component in target_components.into_iter().rev() {
+                    remaining.push_front(target_component.into());
+                }
+            }
+            Artifact::Directory(directory) => {
+                resolved_path.push(component);
+                directories.push(directory);
+            }
+            artifact @ Artifact::File(_) => {
+                resolved_path.push(component);
+                resolved_file = Some(artifact);
+            }
+        }
+    }
+
+    let artifact = match resolved_file {
+        Some(file) => file,
+        None => Artifact::Directory(directories.pop().expect("no resolved directory")),
+    };
+    Ok((resolved_path, artifact))
+}
+
+fn display_path(path: &[BString]) -> BString {
+    bstr::join("/", path).into()
+}
@@ -30,10 +30,7 @@ pub async fn bake_unarchive(
 
     let job_id = brioche.reporter.add_job(crate::reporter::NewJob::Unarchive);
 
-    let archive_path = {
-        let permit = crate::blob::get_save_blob_permit().await?;
-        crate::blob::blob_path(brioche, permit, blob_hash).await?
-    };
+    let archive_path = crate::blob::blob_path(brioche, blob_hash).await?;
     let archive_file = tokio::fs::File::open(&archive_path).await?;
     let uncompressed_archive_size = archive_file.metadata().await?.len();
     let archive_file = tokio::io::BufReader::new(archive_file);
@@ -59,7 +56,7 @@ pub async fn bake_unarchive(
 
             let entry_artifact = match archive_entry.header().entry_type() {
                 tokio_tar::EntryType::Regular => {
-                    let permit = crate::blob::get_save_blob_permit().await?;
+                    let permit = crate::blob::get_save_blob_permit(brioche).await?;
                     let entry_blob_hash = crate::blob::save_blob_from_reader(
                         brioche,
                         permit,
@@ -118,11 +115,11 @@ pub async fn bake_unarchive(
                 }
             };
 
-            let entry_path = crate::fs_utils::logical_path_bytes(&entry_path);
+            let entry_path = crate::path::BriochePath::new(&*entry_path);
             let Ok(entry_path) = entry_path else {
                 continue;
             };
-            if entry_path.is_empty() {
+            if entry_path.is_root() {
                 continue;
             }
             let Some(entry_artifact) = entry_artifact else {
@@ -130,7 +127,7 @@ pub async fn bake_unarchive(
             };
 
             directory_entries.insert(
-                entry_path.into(),
+                entry_path.into_bstring(),
                 WithMeta::new(entry_artifact, meta.clone()),
             );
         }
@@ -1,25 +1,37 @@
 use std::{
-    os::unix::prelude::PermissionsExt as _,
+    collections::{HashMap, HashSet},
+    io::Read as _,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context as _;
+use futures::{StreamExt as _, TryStreamExt as _};
 use sqlx::Acquire as _;
-use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
 
-use super::{Brioche, Hash};
+use super::{Brioche, Hash, HashAlgorithm};
+
+pub mod bundle;
 
 pub struct SaveBlobPermit<'a> {
     _permit: tokio::sync::SemaphorePermit<'a>,
 }
 
+/// The default limit for how many blobs are saved at once.
 pub const MAX_CONCURRENT_BLOB_SAVES: usize = 10;
 
-static SAVE_BLOB_SEMAPHORE: tokio::sync::Semaphore =
-    tokio::sync::Semaphore::const_new(MAX_CONCURRENT_BLOB_SAVES);
+/// Registry blobs at least this large (compressed) are shown as download
+/// jobs in the reporter. Smaller blobs are downloaded quickly, and there can
+/// be many of them, so they are left out.
+const REPORT_BLOB_DOWNLOAD_MIN_BYTES: u64 = 1024 * 1024;
+
+/// The most blobs fetched at once by [`prefetch_blobs`]. Each fetch also
+/// needs a save permit, so fewer may be saved at once.
+const PREFETCH_BLOBS_CONCURRENCY: usize = 25;
 
-pub async fn get_save_blob_permit<'a>() -> anyhow::Result<SaveBlobPermit<'a>> {
-    let permit = SAVE_BLOB_SEMAPHORE
+pub async fn get_save_blob_permit(brioche: &Brioche) -> anyhow::Result<SaveBlobPermit<'_>> {
+    let permit = brioche
+        .save_blob_semaphore
         .acquire()
         .await
         .context("failed to acquire permit to save blob")?;
@@ -33,84 +45,39 @@ pub async fn save_blob<'a>(
     bytes: &[u8],
     options: SaveBlobOptions<'a>,
 ) -> anyhow::Result<BlobHash> {
-    let mut hasher = blake3::Hasher::new();
-    let mut validation_hashing = options
-        .expected_hash
-        .as_ref()
-        .map(|validate_hash| (validate_hash, super::Hasher::for_hash(validate_hash)));
-
+    let source_url = options.source_url.clone();
+    let mut hasher = BlobHasher::new(options.expected_hash.as_ref());
     hasher.update(bytes);
-
-    if let Some((_, validate_hasher)) = &mut validation_hashing {
-        validate_hasher.update(bytes);
-    }
-
-    let hash = hasher.finalize();
-    let blob_hash = BlobHash(hash);
-    let blob_path = local_blob_path(brioche, blob_hash);
-
-    if let Some((expected_hash, validate_hasher)) = validation_hashing {
-        let actual_hash = validate_hasher.finish()?;
-
-        if *expected_hash != actual_hash {
-            anyhow::bail!("expected hash {} but got {}", expected_hash, actual_hash);
-        }
-
-        let expected_hash_string = expected_hash.to_string();
-        let blob_hash_string = blob_hash.to_string();
-
-        let mut db_conn = brioche.db_conn.lock().await;
-        let mut db_transaction = db_conn.begin().await?;
-        sqlx::query!(
-            r"
-                INSERT INTO blob_aliases (hash, blob_hash) VALUES (?, ?)
-                ON CONFLICT (hash) DO UPDATE SET blob_hash = ?
-            ",
-            expected_hash_string,
-            blob_hash_string,
-            blob_hash_string,
-        )
-        .execute(&mut *db_transaction)
+    let blob_hash = hasher
+        .finish(brioche, options.source_url, |quarantine_path| {
+            tokio::fs::write(quarantine_path, bytes)
+        })
         .await?;
-        db_transaction.commit().await?;
-        drop(db_conn);
-    }
 
-    if let Some(parent) = blob_path.parent() {
-        tokio::fs::create_dir_all(&parent)
-            .await
-            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    if brioche.read_only {
+        let blob_path = primary_blob_path(brioche, blob_hash);
+        return existing_read_only_blob(brioche, blob_hash, &blob_path).await;
     }
 
-    if tokio::fs::try_exists(&blob_path).await? {
+    if blob_already_saved(brioche, blob_hash).await? {
         return Ok(blob_hash);
     }
 
-    let temp_dir = brioche.home.join("blobs-temp");
-    tokio::fs::create_dir_all(&temp_dir).await.unwrap();
-    let temp_path = temp_dir.join(ulid::Ulid::new().to_string());
-
-    let mut temp_file = tokio::fs::File::create(&temp_path)
-        .await
-        .context("failed to open temp file")?;
+    let (temp_path, mut temp_file) = create_temp_blob_file(brioche).await?;
     temp_file
         .write_all(bytes)
         .await
         .context("failed to write blob to temp file")?;
-    temp_file
-        .set_permissions(blob_permissions())
-        .await
-        .context("failed to set blob permissions")?;
-    let temp_file = temp_file.into_std().await;
-    tokio::task::spawn_blocking(move || {
-        temp_file.set_modified(crate::fs_utils::brioche_epoch())?;
-        anyhow::Ok(())
-    })
-    .await??;
 
-    tokio::fs::rename(&temp_path, &blob_path)
-        .await
-        .context("failed to rename blob from temp file")?;
+    persist_new_blob(
+        brioche,
+        blob_hash,
+        &temp_path,
+        temp_file,
+        None,
+        source_url.as_ref(),
+    )
+    .await?;
 
     Ok(blob_hash)
 }
@@ -119,107 +86,42 @@ pub async fn save_blob<'a>(
 pub async fn save_blob_from_reader<'a, R>(
     brioche: &Brioche,
     _permit: SaveBlobPermit<'_>,
-    mut input: R,
+    input: R,
     mut options: SaveBlobOptions<'a>,
 ) -> anyhow::Result<BlobHash>
 where
     R: tokio::io::AsyncRead + Unpin,
 {
     anyhow::ensure!(!options.remove_input, "cannot remove input from reader");
+    brioche.ensure_writable("save blob")?;
 
-    let mut hasher = blake3::Hasher::new();
-    let mut validation_hashing = options
-        .expected_hash
-        .as_ref()
-        .map(|validate_hash| (validate_hash, super::Hasher::for_hash(validate_hash)));
-
-    let temp_dir = brioche.home.join("blobs-temp");
-    tokio::fs::create_dir_all(&temp_dir).await.unwrap();
-    let temp_path = temp_dir.join(ulid::Ulid::new().to_string());
-    let mut temp_file = tokio::fs::File::create(&temp_path)
-        .await
-        .context("failed to open temp file")?;
-
+    let (temp_path, mut temp_file) = create_temp_blob_file(brioche).await?;
     tracing::trace!(temp_path = %temp_path.display(), "saving blob");
 
-    let mut buffer = vec![0u8; 1024 * 1024];
-    let mut total_bytes_read = 0;
-    loop {
-        let length = input.read(&mut buffer).await.context("failed to read")?;
-        if length == 0 {
-            break;
-        }
-
-        total_bytes_read += length;
-        let buffer = &buffer[..length];
-
-        temp_file
-            .write_all(buffer)
-            .await
-            .context("failed to write all")?;
-
-        hasher.update(buffer);
-
-        if let Some((_, validate_hasher)) = &mut validation_hashing {
-            validate_hasher.update(buffer);
-        }
-
-        if let Some(on_progress) = &mut options.on_progress {
-            on_progress(total_bytes_read)?;
-        }
-    }
-
-    let hash = hasher.finalize();
-    let blob_hash = BlobHash(hash);
-    let blob_path = local_blob_path(brioche, blob_hash);
-
-    if let Some((expected_hash, validate_hasher)) = validation_hashing {
-        let actual_hash = validate_hasher.finish()?;
-
-        if *expected_hash != actual_hash {
-            anyhow::bail!("expected hash {} but got {}", expected_hash, actual_hash);
-        }
-
-        let expected_hash_string = expected_hash.to_string();
-        let blob_hash_string = blob_hash.to_string();
-
-        let mut db_conn = brioche.db_conn.lock().await;
-        let mut db_transaction = db_conn.begin().await?;
-        sqlx::query!(
-            r"
-                INSERT INTO blob_aliases (hash, blob_hash) VALUES (?, ?)
-                ON CONFLICT (hash) DO UPDATE SET blob_hash = ?
-            ",
-            expected_hash_string,
-            blob_hash_string,
-            blob_hash_string,
-        )
-        .execute(&mut *db_transaction)
+    let source_url = options.source_url.clone();
+    let mut hasher = BlobHasher::new(options.expected_hash.as_ref());
+    hash_stream(
+        input,
+        &mut hasher,
+        Some(&mut temp_file),
+        &mut options.on_progress,
+    )
+    .await?;
+    let blob_hash = hasher
+        .finish(brioche, options.source_url, |quarantine_path| {
+            tokio::fs::rename(&temp_path, quarantine_path)
+        })
         .await?;
-        db_transaction.commit().await?;
-        drop(db_conn);
-    }
-
-    if let Some(parent) = blob_path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
-    }
-
-    tracing::debug!(overwrite = blob_path.exists(), %blob_hash, "saved blob");
-
-    temp_file
-        .set_permissions(blob_permissions())
-        .await
-        .context("failed to set blob permissions")?;
-    let temp_file = temp_file.into_std().await;
-    tokio::task::spawn_blocking(move || {
-        temp_file.set_modified(crate::fs_utils::brioche_epoch())?;
-        anyhow::Ok(())
-    })
-    .await??;
 
-    tokio::fs::rename(&temp_path, &blob_path)
-        .await
-        .context("failed to rename blob from temp file")?;
+    persist_new_blob(
+        brioche,
+        blob_hash,
+        &temp_path,
+        temp_file,
+        None,
+        source_url.as_ref(),
+    )
+    .await?;
 
     Ok(blob_hash)
 }
@@ -229,118 +131,100 @@ pub async fn save_blob_from_file<'a>(
     brioche: &Brioche,
     _permit: SaveBlobPermit<'_>,
     input_path: &Path,
-    options: SaveBlobOptions<'a>,
+    mut options: SaveBlobOptions<'a>,
 ) -> anyhow::Result<BlobHash> {
-    let mut hasher = blake3::Hasher::new();
-    let mut validation_hashing = options
-        .expected_hash
-        .as_ref()
-        .map(|validate_hash| (validate_hash, super::Hasher::for_hash(validate_hash)));
-
-    {
-        let mut buffer = vec![0u8; 1024 * 1024];
-        let mut input_file = tokio::fs::File::open(&input_path)
-            .await
-            .with_context(|| format!("failed to open input file {}", input_path.display()))?;
-        loop {
-            let length = input_file
-                .read(&mut buffer)
-                .await
-                .context("failed to read")?;
-            if length == 0 {
-                break;
-            }
-
-            let buffer = &buffer[..length];
-
-            hasher.update(buffer);
+    let input_file = tokio::fs::File::open(&input_path)
+        .await
+        .with_context(|| format!("failed to open input file {}", input_path.display()))?;
+    let input_metadata = input_file.metadata().await.with_context(|| {
+        format!(
+            "failed to get metadata for input file {}",
+            input_path.display()
+        )
+    })?;
+    let mut permissions = input_metadata.permissions();
+    crate::fs_utils::set_readonly(&mut permissions);
+
+    // Since an exclusive file (i.e. one with no hardlinks) can be moved
+    // into place, it only needs to be read to hash it. We need to check
+    // for exclusivity, because we would otherwise ruin the permission of
+    // other hard links to the same file
+    let move_input = options.remove_input && crate::fs_utils::is_file_exclusive(&input_metadata);
+
+    // Sparse files (like disk images) are saved without filling in their
+    // holes, and their data regions are recorded so outputs can be sparse
+    let data_extents = if brioche.read_only {
+        None
+    } else {
+        crate::fs_utils::sparse_data_extents(input_path).await?
+    };
 
-            if let Some((_, validate_hasher)) = &mut validation_hashing {
-                validate_hasher.update(buffer);
-            }
+    let source_url = options.source_url.clone();
+    let mut hasher = BlobHasher::new(options.expected_hash.as_ref());
+    if brioche.read_only || move_input || data_extents.is_some() {
+        hash_stream(
+            input_file,
+            &mut hasher,
+            None::<&mut tokio::fs::File>,
+            &mut options.on_progress,
+        )
+        .await?;
+        let blob_hash = hasher
+            .finish(brioche, options.source_url, |quarantine_path| {
+                tokio::fs::copy(input_path, quarantine_path)
+            })
+            .await?;
+
+        let blob_path = primary_blob_path(brioche, blob_hash);
+        if brioche.read_only {
+            return existing_read_only_blob(brioche, blob_hash, &blob_path).await;
         }
-    }
 
-    let hash = hasher.finalize();
-    let blob_hash = BlobHash(hash);
-    let blob_path = local_blob_path(brioche, blob_hash);
-
-    if let Some((expected_hash, validate_hasher)) = validation_hashing {
-        let actual_hash = validate_hasher.finish()?;
-
-        if *expected_hash != actual_hash {
-            anyhow::bail!("expected hash {} but got {}", expected_hash, actual_hash);
+        if let Some(data_extents) = &data_extents {
+            record_blob_data_extents(brioche, blob_hash, data_extents).await?;
         }
 
-        let expected_hash_string = expected_hash.to_string();
-        let blob_hash_string = blob_hash.to_string();
-
-        let mut db_conn = brioche.db_conn.lock().await;
-        let mut db_transaction = db_conn.begin().await?;
-        sqlx::query!(
-            r"
-                INSERT INTO blob_aliases (hash, blob_hash) VALUES (?, ?)
-                ON CONFLICT (hash) DO UPDATE SET blob_hash = ?
-            ",
-            expected_hash_string,
-            blob_hash_string,
-            blob_hash_string,
-        )
-        .execute(&mut *db_transaction)
-        .await?;
-        db_transaction.commit().await?;
-        drop(db_conn);
-    }
-
-    if let Some(parent) = blob_path.parent() {
-        tokio::fs::create_dir_all(&parent)
+        if !move_input {
+            // Copy the data of the sparse file into a temp file, keeping
+            // the holes
+            let (temp_path, temp_file) = create_temp_blob_file(brioche).await?;
+            crate::fs_utils::sparse_copy(
+                input_path,
+                &temp_path,
+                data_extents.as_deref().unwrap_or_default(),
+            )
             .await
-            .with_context(|| format!("failed to create directory {}", parent.display()))?;
-    }
+            .context("failed to copy sparse file to temp file")?;
+
+            persist_new_blob(
+                brioche,
+                blob_hash,
+                &temp_path,
+                temp_file,
+                Some(permissions),
+                source_url.as_ref(),
+            )
+            .await?;
+            tracing::debug!(input_path = %input_path.display(), %blob_hash, "saved blob by copying sparse file");
+
+            if options.remove_input {
+                remove_input_file(input_path).await?;
+            }
 
-    let existing_blob_file = match tokio::fs::File::open(&blob_path).await {
-        Ok(file) => Some(file),
-        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
-        Err(error) => {
-            return Err(error)
-                .with_context(|| format!("failed to open blob file at {}", blob_path.display()));
+            return Ok(blob_hash);
         }
-    };
 
-    let input_metadata = tokio::fs::metadata(&input_path).await.with_context(|| {
-        format!(
-            "failed to get metadata for input file {}",
-            input_path.display()
-        )
-    })?;
+        if blob_already_saved(brioche, blob_hash).await? {
+            remove_input_file(input_path).await?;
+            return Ok(blob_hash);
+        }
 
-    let permissions = blob_permissions();
-    if let Some(existing_blob_file) = existing_blob_file {
-        // The blob file already exists, so don't try to create it again. But
-        // we may still need to remove the input file
-        if options.remove_input {
-            tokio::fs::remove_file(input_path)
+        if let Some(parent) = blob_path.parent() {
+            tokio::fs::create_dir_all(&parent)
                 .await
-                .with_context(|| format!("failed to remove input file {}", input_path.display()))?;
+                .with_context(|| format!("failed to create directory {}", parent.display()))?;
         }
 
-        // Make sure the blob's permissions and modified time are set properly
-        existing_blob_file
-            .set_permissions(permissions)
-            .await
-            .context("failed to set blob permissions")?;
-        let existing_blob_file = existing_blob_file.into_std().await;
-        tokio::task::spawn_blocking(move || {
-            existing_blob_file.set_modified(crate::fs_utils::brioche_epoch())?;
-            anyhow::Ok(())
-        })
-        .await??;
-    } else if options.remove_input && is_file_exclusive(&input_metadata) {
-        // Since this file is exclusive (i.e. has no hardlinks), we can
-        // change its permissions and move it into place. We need to check
-        // for exclusivity, because we would otherwise ruin the permission
-        // of other hard links to the same file.
-
         tokio::fs::set_permissions(input_path, permissions)
             .await
             .context("failed to set blob permissions")?;
@@ -356,120 +240,986 @@ pub async fn save_blob_from_file<'a>(
                     blob_path.display()
                 )
             })?;
+        sync_saved_blob(brioche, &blob_path).await?;
         tracing::debug!(input_path = %input_path.display(), %blob_hash, ?move_type, "saved blob by moving file");
-    } else {
-        crate::fs_utils::atomic_copy(input_path, &blob_path)
+
+        let (source, source_url) = BlobSource::for_saved_blob(source_url.as_ref());
+        record_blob(brioche, blob_hash, input_metadata.len(), source, source_url).await?;
+
+        crate::blob_store::queue_upload(brioche, blob_hash, &blob_path).await;
+
+        return Ok(blob_hash);
+    }
+
+    // Reflink the input into a temp file if the filesystem supports it,
+    // then hash the temp file. Otherwise, copy the input into a temp file
+    // while hashing it, so it's only read once
+    let temp_path = temp_blob_path(brioche).await?;
+    let reflinked = crate::fs_utils::try_reflink(input_path, &temp_path)
+        .await
+        .context("failed to reflink input file to temp file")?;
+    let mut temp_file;
+    if reflinked {
+        temp_file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&temp_path)
             .await
-            .with_context(|| {
-                format!(
-                    "failed to copy file from {} to {} to save blob",
-                    input_path.display(),
-                    blob_path.display()
-                )
-            })?;
-        tokio::fs::set_permissions(&blob_path, permissions)
+            .context("failed to open temp file")?;
+        hash_stream(
+            &mut temp_file,
+            &mut hasher,
+            None::<&mut tokio::fs::File>,
+            &mut options.on_progress,
+        )
+        .await?;
+    } else if cfg!(feature = "blocking-blob-writer") {
+        temp_file = tokio::fs::File::create(&temp_path)
             .await
-            .context("failed to set blob permissions")?;
-        crate::fs_utils::set_mtime_to_brioche_epoch(input_path)
+            .context("failed to open temp file")?;
+        temp_file = hash_stream_to_blocking_writer(
+            input_file,
+            &mut hasher,
+            temp_file,
+            input_metadata.len(),
+            &mut options.on_progress,
+        )
+        .await?;
+    } else {
+        temp_file = tokio::fs::File::create(&temp_path)
             .await
-            .context("failed to set blob modified time")?;
-        tracing::debug!(input_path = %input_path.display(), %blob_hash, "saved blob by copying file");
+            .context("failed to open temp file")?;
+        hash_stream(
+            input_file,
+            &mut hasher,
+            Some(&mut temp_file),
+            &mut options.on_progress,
+        )
+        .await?;
+    }
+    let blob_hash = hasher
+        .finish(brioche, options.source_url, |quarantine_path| {
+            tokio::fs::rename(&temp_path, quarantine_path)
+        })
+        .await?;
 
-        if options.remove_input {
-            tokio::fs::remove_file(input_path)
-                .await
-                .with_context(|| format!("failed to remove input file {}", input_path.display()))?;
-        }
+    persist_new_blob(
+        brioche,
+        blob_hash,
+        &temp_path,
+        temp_file,
+        Some(permissions),
+        source_url.as_ref(),
+    )
+    .await?;
+    tracing::debug!(input_path = %input_path.display(), %blob_hash, reflinked, "saved blob by copying file");
+
+    if options.remove_input {
+        remove_input_file(input_path).await?;
     }
 
     Ok(blob_hash)
 }
 
-#[derive(Default)]
-pub struct SaveBlobOptions<'a> {
-    expected_hash: Option<Hash>,
-    on_progress: Option<Box<dyn FnMut(usize) -> anyhow::Result<()> + Send + 'a>>,
-    remove_input: bool,
+/// Hashes the data of a blob while it's being saved, along with the
+/// expected hash if the data needs to be validated.
+struct BlobHasher<'a> {
+    hasher: blake3::Hasher,
+    validation_hashing: Option<(&'a Hash, super::Hasher)>,
+    total_bytes: u64,
 }
 
-impl<'a> SaveBlobOptions<'a> {
-    pub fn new() -> Self {
-        Self::default()
+impl<'a> BlobHasher<'a> {
+    fn new(expected_hash: Option<&'a Hash>) -> Self {
+        Self {
+            hasher: blake3::Hasher::new(),
+            validation_hashing: expected_hash
+                .map(|expected_hash| (expected_hash, super::Hasher::for_hash(expected_hash))),
+            total_bytes: 0,
+        }
     }
 
-    pub fn expected_hash(mut self, expected_hash: Option<Hash>) -> Self {
-        self.expected_hash = expected_hash;
-        self
+    fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        if let Some((_, validate_hasher)) = &mut self.validation_hashing {
+            validate_hasher.update(bytes);
+        }
+        self.total_bytes += bytes.len() as u64;
     }
 
-    pub fn on_progress(
-        mut self,
-        on_progress: impl FnMut(usize) -> anyhow::Result<()> + Send + 'a,
-    ) -> Self {
-        self.on_progress = Some(Box::new(on_progress));
-        self
-    }
+    /// Get the hash of the blob. If there's an expected hash, the data is
+    /// validated against it and an alias for the blob is recorded. When
+    /// the data doesn't match, `quarantine` is called to save the data to
+    /// the quarantine path, and a [`HashMismatchError`] is returned.
+    async fn finish<F, Fut, T>(
+        self,
+        brioche: &Brioche,
+        source_url: Option<url::Url>,
+        quarantine: F,
+    ) -> anyhow::Result<BlobHash>
+    where
+        F: FnOnce(PathBuf) -> Fut,
+        Fut: std::future::Future<Output = std::io::Result<T>>,
+    {
+        let blob_hash = BlobHash(self.hasher.finalize());
 
-    pub fn remove_input(mut self, remove_input: bool) -> Self {
-        self.remove_input = remove_input;
-        self
+        let Some((expected_hash, validate_hasher)) = self.validation_hashing else {
+            return Ok(blob_hash);
+        };
+
+        let actual_hash = validate_hasher.finish()?;
+        if *expected_hash != actual_hash {
+            let quarantine_path = quarantine_path(brioche, blob_hash);
+            let quarantined =
+                quarantine_data(&quarantine_path, quarantine(quarantine_path.clone())).await;
+            return Err(HashMismatchError {
+                expected_hash: expected_hash.clone(),
+                actual_hash,
+                size: self.total_bytes,
+                source_url,
+                quarantine_path: quarantined.then_some(quarantine_path),
+            }
+            .into());
+        }
+
+        if !brioche.read_only {
+            save_blob_alias(brioche, expected_hash, blob_hash).await?;
+        }
+
+        Ok(blob_hash)
     }
 }
 
-pub async fn find_blob(brioche: &Brioche, hash: &Hash) -> anyhow::Result<Option<BlobHash>> {
-    let hash_string = hash.to_string();
+async fn save_blob_alias(
+    brioche: &Brioche,
+    expected_hash: &Hash,
+    blob_hash: BlobHash,
+) -> anyhow::Result<()> {
+    let expected_hash_string = expected_hash.to_string();
+    let blob_hash_string = blob_hash.to_string();
+
     let mut db_conn = brioche.db_conn.lock().await;
     let mut db_transaction = db_conn.begin().await?;
-    let result = sqlx::query!(
-        r#"
-            SELECT blob_hash FROM blob_aliases WHERE hash = ? LIMIT 1
-        "#,
-        hash_string,
+    sqlx::query!(
+        r"
+                INSERT INTO blob_aliases (hash, blob_hash) VALUES (?, ?)
+                ON CONFLICT (hash) DO UPDATE SET blob_hash = ?
+            ",
+        expected_hash_string,
+        blob_hash_string,
+        blob_hash_string,
     )
-    .fetch_optional(&mut *db_transaction)
+    .execute(&mut *db_transaction)
     .await?;
     db_transaction.commit().await?;
     drop(db_conn);
 
-    match result {
-        Some(row) => {
-            let blob_hash = row.blob_hash.parse()?;
+    Ok(())
+}
+
+/// Read all of `input` and hash it, writing it to `output` if set.
+async fn hash_stream<R, W>(
+    mut input: R,
+    hasher: &mut BlobHasher<'_>,
+    mut output: Option<&mut W>,
+    on_progress: &mut Option<Box<dyn FnMut(usize) -> anyhow::Result<()> + Send + '_>>,
+) -> anyhow::Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let length = input.read(&mut buffer).await.context("failed to read")?;
+        if length == 0 {
+            break;
+        }
+
+        let buffer = &buffer[..length];
+        if let Some(output) = &mut output {
+            output
+                .write_all(buffer)
+                .await
+                .context("failed to write all")?;
+        }
+
+        hasher.update(buffer);
+
+        if let Some(on_progress) = on_progress {
+            on_progress(hasher.total_bytes as usize)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Like [`hash_stream`], but writes to `output` from a dedicated blocking
+/// thread with larger buffers, rather than through Tokio's async file
+/// wrapper (which hands each write off to a blocking thread separately).
+/// Space for `size_hint` bytes is preallocated up front where the
+/// filesystem supports it. This is much faster for very large blobs, and
+/// is enabled with the `blocking-blob-writer` feature.
+async fn hash_stream_to_blocking_writer<R>(
+    mut input: R,
+    hasher: &mut BlobHasher<'_>,
+    output: tokio::fs::File,
+    size_hint: u64,
+    on_progress: &mut Option<Box<dyn FnMut(usize) -> anyhow::Result<()> + Send + '_>>,
+) -> anyhow::Result<tokio::fs::File>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    const BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+    // Keep a few buffers in flight, so reading and hashing the next buffer
+    // overlaps with writing the previous one
+    let (buffer_tx, mut buffer_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(2);
+    let output = output.into_std().await;
+    let writer = tokio::task::spawn_blocking(move || {
+        use std::io::Write as _;
+
+        let mut output = output;
+        crate::fs_utils::preallocate(&output, size_hint)?;
+        while let Some(buffer) = buffer_rx.blocking_recv() {
+            output.write_all(&buffer)?;
+        }
+        output.flush()?;
+        anyhow::Ok(output)
+    });
+
+    loop {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut length = 0;
+        while length < buffer.len() {
+            let read_length = input
+                .read(&mut buffer[length..])
+                .await
+                .context("failed to read")?;
+            if read_length == 0 {
+                break;
+            }
+            length += read_length;
+        }
+        if length == 0 {
+            break;
+        }
+
+        buffer.truncate(length);
+        hasher.update(&buffer);
+
+        if buffer_tx.send(buffer).await.is_err() {
+            // The writer stopped early, so return its error
+            break;
+        }
+
+        if let Some(on_progress) = on_progress {
+            on_progress(hasher.total_bytes as usize)?;
+        }
+    }
+
+    drop(buffer_tx);
+    let output = writer.await?.context("failed to write all")?;
+    Ok(tokio::fs::File::from_std(output))
+}
+
+async fn create_temp_blob_file(brioche: &Brioche) -> anyhow::Result<(PathBuf, tokio::fs::File)> {
+    let temp_path = temp_blob_path(brioche).await?;
+    let temp_file = tokio::fs::File::create(&temp_path)
+        .await
+        .context("failed to open temp file")?;
+    Ok((temp_path, temp_file))
+}
+
+/// Get a new path for a temp blob file, creating its parent directory.
+async fn temp_blob_path(brioche: &Brioche) -> anyhow::Result<PathBuf> {
+    let temp_dir = brioche.home.join("blobs-temp");
+    tokio::fs::create_dir_all(&temp_dir)
+        .await
+        .with_context(|| format!("failed to create directory {}", temp_dir.display()))?;
+    Ok(temp_dir.join(ulid::Ulid::new().to_string()))
+}
+
+/// Check if a blob is already saved, either in the primary store or in a
+/// secondary blob directory. If it's in the primary store, its permissions
+/// and modified time are set properly in case it was saved by an older
+/// version.
+async fn blob_already_saved(brioche: &Brioche, blob_hash: BlobHash) -> anyhow::Result<bool> {
+    let blob_path = primary_blob_path(brioche, blob_hash);
+    match tokio::fs::File::open(&blob_path).await {
+        Ok(existing_blob_file) => {
+            set_blob_file_permissions(&existing_blob_file)
+                .await
+                .context("failed to set blob permissions")?;
+            let existing_blob_file = existing_blob_file.into_std().await;
+            tokio::task::spawn_blocking(move || {
+                existing_blob_file.set_modified(crate::fs_utils::brioche_epoch())?;
+                anyhow::Ok(())
+            })
+            .await??;
+            return Ok(true);
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to open blob file at {}", blob_path.display()));
+        }
+    }
+
+    let in_secondary = crate::blob_store::find_local_blob(brioche, blob_hash)
+        .await
+        .is_some();
+    Ok(in_secondary)
+}
+
+/// Move a fully-written temp file into the store as a new blob, unless
+/// the blob was already saved. The blob is made read-only, using
+/// `permissions` if set, and recorded in the `blobs` table.
+async fn persist_new_blob(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    temp_path: &Path,
+    temp_file: tokio::fs::File,
+    permissions: Option<std::fs::Permissions>,
+    source_url: Option<&url::Url>,
+) -> anyhow::Result<()> {
+    if blob_already_saved(brioche, blob_hash).await? {
+        drop(temp_file);
+        tokio::fs::remove_file(temp_path)
+            .await
+            .context("failed to remove blob temp file")?;
+        return Ok(());
+    }
+
+    let blob_path = primary_blob_path(brioche, blob_hash);
+    if let Some(parent) = blob_path.parent() {
+        tokio::fs::create_dir_all(&parent)
+            .await
+            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    }
+
+    let size = temp_file
+        .metadata()
+        .await
+        .context("failed to get blob temp file metadata")?
+        .len();
+
+    match permissions {
+        Some(permissions) => temp_file
+            .set_permissions(permissions)
+            .await
+            .context("failed to set blob permissions")?,
+        None => set_blob_file_permissions(&temp_file)
+            .await
+            .context("failed to set blob permissions")?,
+    }
+    let temp_file = temp_file.into_std().await;
+    tokio::task::spawn_blocking(move || {
+        temp_file.set_modified(crate::fs_utils::brioche_epoch())?;
+        anyhow::Ok(())
+    })
+    .await??;
+
+    persist_temp_blob(brioche, temp_path, &blob_path).await?;
+    tracing::debug!(%blob_hash, "saved blob");
+
+    let (source, source_url) = BlobSource::for_saved_blob(source_url);
+    record_blob(brioche, blob_hash, size, source, source_url).await?;
+
+    crate::blob_store::queue_upload(brioche, blob_hash, &blob_path).await;
+
+    Ok(())
+}
+
+async fn remove_input_file(input_path: &Path) -> anyhow::Result<()> {
+    tokio::fs::remove_file(input_path)
+        .await
+        .with_context(|| format!("failed to remove input file {}", input_path.display()))
+}
+
+/// Where a blob in the store came from, as recorded in the `blobs` table.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BlobSource {
+    /// Saved locally, such as from a project input or a process output.
+    Local,
+    /// Downloaded from a URL.
+    Download,
+    /// Fetched from the registry.
+    Registry,
+    /// Copied from a mirror store.
+    Mirror,
+    /// Fetched from a remote blob store.
+    BlobStore,
+}
+
+impl BlobSource {
+    /// Blobs saved with a source URL were downloaded, and anything else
+    /// was saved locally.
+    fn for_saved_blob(source_url: Option<&url::Url>) -> (Self, Option<&url::Url>) {
+        match source_url {
+            Some(source_url) => (Self::Download, Some(source_url)),
+            None => (Self::Local, None),
+        }
+    }
+}
+
+/// Record the size and source of a blob that was just saved to the
+/// primary store. A blob that was already recorded keeps its original
+/// metadata.
+async fn record_blob(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    size: u64,
+    source: BlobSource,
+    source_url: Option<&url::Url>,
+) -> anyhow::Result<()> {
+    let blob_hash_value = blob_hash.to_string();
+    let size = i64::try_from(size).unwrap_or(i64::MAX);
+    let source_value = source.to_string();
+    let source_url_value = source_url.map(|url| url.to_string());
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO blobs (blob_hash, size, source, source_url)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (blob_hash) DO NOTHING
+        "#,
+        blob_hash_value,
+        size,
+        source_value,
+        source_url_value,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    Ok(())
+}
+
+/// How carefully blobs and database writes are flushed to disk before
+/// they're considered saved, trading crash safety for speed.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Durability {
+    /// Nothing is synced. A crash or power loss may leave recently saved
+    /// blobs truncated or empty, and lose recent database writes.
+    None,
+    /// Blob contents are synced before they're moved into place, so a
+    /// saved blob is never truncated. A crash may still lose recently
+    /// saved blobs entirely, which are then fetched or baked again.
+    #[default]
+    Metadata,
+    /// Like `metadata`, but the blobs directory is synced after each blob
+    /// is moved into place, and each database transaction is synced
+    /// before it's committed.
+    Full,
+}
+
+impl Durability {
+    pub fn syncs_files(self) -> bool {
+        matches!(self, Self::Metadata | Self::Full)
+    }
+
+    pub fn syncs_directories(self) -> bool {
+        matches!(self, Self::Full)
+    }
+
+    pub fn sqlite_synchronous(self) -> sqlx::sqlite::SqliteSynchronous {
+        match self {
+            Self::None => sqlx::sqlite::SqliteSynchronous::Off,
+            Self::Metadata => sqlx::sqlite::SqliteSynchronous::Normal,
+            Self::Full => sqlx::sqlite::SqliteSynchronous::Full,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SaveBlobOptions<'a> {
+    expected_hash: Option<Hash>,
+    on_progress: Option<Box<dyn FnMut(usize) -> anyhow::Result<()> + Send + 'a>>,
+    remove_input: bool,
+    source_url: Option<url::Url>,
+}
+
+impl<'a> SaveBlobOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn expected_hash(mut self, expected_hash: Option<Hash>) -> Self {
+        self.expected_hash = expected_hash;
+        self
+    }
+
+    pub fn on_progress(
+        mut self,
+        on_progress: impl FnMut(usize) -> anyhow::Result<()> + Send + 'a,
+    ) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    pub fn remove_input(mut self, remove_input: bool) -> Self {
+        self.remove_input = remove_input;
+        self
+    }
+
+    /// Where the data came from, used for error messages and recorded
+    /// with the blob.
+    pub fn source_url(mut self, source_url: url::Url) -> Self {
+        self.source_url = Some(source_url);
+        self
+    }
+}
+
+/// Returned when the data for a blob doesn't match the expected hash. The
+/// data is kept in a quarantine directory, so it can be inspected before
+/// updating the expected hash.
+#[derive(Debug, thiserror::Error)]
+pub struct HashMismatchError {
+    pub expected_hash: Hash,
+    pub actual_hash: Hash,
+    pub size: u64,
+    pub source_url: Option<url::Url>,
+    pub quarantine_path: Option<PathBuf>,
+}
+
+impl std::fmt::Display for HashMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            expected_hash,
+            actual_hash,
+            size,
+            source_url,
+            quarantine_path,
+        } = self;
+
+        match source_url {
+            Some(source_url) => writeln!(f, "hash mismatch for {source_url}")?,
+            None => writeln!(f, "hash mismatch")?,
+        }
+        writeln!(f, "  expected: {expected_hash}")?;
+        writeln!(f, "       got: {actual_hash}")?;
+        writeln!(f, "      size: {size} bytes")?;
+        if let Some(quarantine_path) = quarantine_path {
+            writeln!(f, "the data was kept at {}", quarantine_path.display())?;
+        }
+        write!(
+            f,
+            "if the data is correct, update the expected hash to {actual_hash}"
+        )
+    }
+}
+
+/// Returned when a blob's contents don't match its hash while it's being
+/// copied to an output.
+#[derive(Debug, thiserror::Error)]
+#[error("blob {blob_hash} at {} is corrupted (its contents hash to {actual_hash}), failed to write {}", .blob_path.display(), .output_path.display())]
+pub struct CorruptBlobError {
+    pub blob_hash: BlobHash,
+    pub actual_hash: BlobHash,
+    pub blob_path: PathBuf,
+    pub output_path: PathBuf,
+}
+
+/// Copy a blob to an output path, hashing it as it's written. If the blob
+/// doesn't match its hash, the output is removed and a [`CorruptBlobError`]
+/// is returned. When `data_extents` is set, only those regions are copied,
+/// and everything between them is left as a hole (see
+/// [`blob_data_extents`]).
+pub async fn copy_blob_verified(
+    blob_hash: BlobHash,
+    blob_path: &Path,
+    output_path: &Path,
+    data_extents: Option<&[std::ops::Range<u64>]>,
+) -> anyhow::Result<()> {
+    let mut blob_file = tokio::fs::File::open(blob_path)
+        .await
+        .with_context(|| format!("failed to open blob {}", blob_path.display()))?;
+    let size = blob_file.metadata().await?.len();
+    let mut output_file = tokio::fs::File::create(output_path)
+        .await
+        .with_context(|| format!("failed to create output {}", output_path.display()))?;
+
+    let whole_blob = [0..size];
+    let data_extents = data_extents.unwrap_or(&whole_blob);
+
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut offset = 0;
+    for extent in data_extents {
+        // Holes read as zeros
+        hash_zeros(&mut hasher, extent.start.saturating_sub(offset));
+
+        blob_file
+            .seek(std::io::SeekFrom::Start(extent.start))
+            .await?;
+        output_file
+            .seek(std::io::SeekFrom::Start(extent.start))
+            .await?;
+
+        offset = extent.start;
+        while offset < extent.end {
+            let max_length = usize::try_from(extent.end - offset)
+                .unwrap_or(usize::MAX)
+                .min(buffer.len());
+            let length = blob_file.read(&mut buffer[..max_length]).await?;
+            if length == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..length]);
+            output_file.write_all(&buffer[..length]).await?;
+            offset += length as u64;
+        }
+    }
+    hash_zeros(&mut hasher, size.saturating_sub(offset));
+
+    output_file.set_len(size).await?;
+    output_file.flush().await?;
+    drop(output_file);
+
+    let actual_hash = BlobHash(hasher.finalize());
+    if actual_hash != blob_hash {
+        crate::fs_utils::try_remove(output_path).await?;
+        return Err(CorruptBlobError {
+            blob_hash,
+            actual_hash,
+            blob_path: blob_path.to_owned(),
+            output_path: output_path.to_owned(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn hash_zeros(hasher: &mut blake3::Hasher, length: u64) {
+    const ZEROS: [u8; 4096] = [0; 4096];
+
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk_length = remaining.min(ZEROS.len() as u64) as usize;
+        hasher.update(&ZEROS[..chunk_length]);
+        remaining -= chunk_length as u64;
+    }
+}
+
+/// A read-only store can't save new blobs, but saving a blob that's
+/// already in the store succeeds without changing anything.
+async fn existing_read_only_blob(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    blob_path: &Path,
+) -> anyhow::Result<BlobHash> {
+    let in_secondary = crate::blob_store::find_local_blob(brioche, blob_hash)
+        .await
+        .is_some();
+    if !in_secondary && !tokio::fs::try_exists(blob_path).await? {
+        brioche.ensure_writable(&format!("save blob {blob_hash}"))?;
+    }
+
+    Ok(blob_hash)
+}
+
+fn quarantine_path(brioche: &Brioche, blob_hash: BlobHash) -> PathBuf {
+    brioche
+        .home
+        .join("blobs-quarantine")
+        .join(blob_hash.to_string())
+}
+
+/// Move data that failed validation into the quarantine directory. Returns
+/// false if it couldn't be saved, since this shouldn't replace the hash
+/// mismatch error.
+async fn quarantine_data<T>(
+    quarantine_path: &Path,
+    save: impl std::future::Future<Output = std::io::Result<T>>,
+) -> bool {
+    if let Some(parent) = quarantine_path.parent() {
+        if let Err(error) = tokio::fs::create_dir_all(parent).await {
+            tracing::warn!(path = %parent.display(), "failed to create quarantine directory: {error}");
+            return false;
+        }
+    }
+
+    match save.await {
+        Ok(_) => true,
+        Err(error) => {
+            tracing::warn!(path = %quarantine_path.display(), "failed to quarantine data: {error}");
+            false
+        }
+    }
+}
+
+pub async fn find_blob(brioche: &Brioche, hash: &Hash) -> anyhow::Result<Option<BlobHash>> {
+    let hash_string = hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let result = sqlx::query!(
+        r#"
+            SELECT blob_hash FROM blob_aliases WHERE hash = ? LIMIT 1
+        "#,
+        hash_string,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    match result {
+        Some(row) => {
+            let blob_hash = row.blob_hash.parse()?;
             Ok(Some(blob_hash))
         }
-        None => Ok(None),
+        None => Ok(None),
+    }
+}
+
+/// Get the path to a blob, fetching it from the registry if it isn't saved
+/// locally. A save permit is only acquired when the blob needs to be
+/// fetched, so lookups for blobs that already exist don't contend with
+/// blob saves.
+pub async fn blob_path(brioche: &Brioche, blob_hash: BlobHash) -> anyhow::Result<PathBuf> {
+    if let Some(local_path) = try_local_blob_path(brioche, blob_hash).await? {
+        return Ok(local_path);
+    }
+
+    let permit = get_save_blob_permit(brioche).await?;
+    fetch_blob(brioche, permit, blob_hash).await
+}
+
+/// Record the regions of a blob that contain data, if it was saved from a
+/// sparse file.
+async fn record_blob_data_extents(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    data_extents: &[std::ops::Range<u64>],
+) -> anyhow::Result<()> {
+    let blob_hash_value = blob_hash.to_string();
+    let data_extents = serde_json::to_string(data_extents)?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO blob_extents (blob_hash, data_extents)
+            VALUES (?, ?)
+            ON CONFLICT (blob_hash) DO NOTHING
+        "#,
+        blob_hash_value,
+        data_extents,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Get the regions of a blob that contain data, if it was saved from a
+/// sparse file. The rest of the blob is all zeros, so it can be left as
+/// holes when the blob is copied.
+pub async fn blob_data_extents(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+) -> anyhow::Result<Option<Vec<std::ops::Range<u64>>>> {
+    let blob_hash_value = blob_hash.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let data_extents = sqlx::query_scalar!(
+        "SELECT data_extents FROM blob_extents WHERE blob_hash = ?",
+        blob_hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let data_extents = data_extents
+        .map(|data_extents| serde_json::from_str(&data_extents))
+        .transpose()
+        .context("invalid blob data extents")?;
+    Ok(data_extents)
+}
+
+/// Fetch many blobs in parallel, skipping any that are already saved
+/// locally. The fetches are reported as a single job, so a cached bake
+/// result's blobs can all be fetched up front rather than one at a time
+/// while its output is created.
+#[tracing::instrument(skip_all, fields(num_blobs = blob_hashes.len()))]
+pub async fn prefetch_blobs(brioche: &Brioche, blob_hashes: &[BlobHash]) -> anyhow::Result<()> {
+    let blob_hashes = blob_hashes.iter().copied().collect::<HashSet<_>>();
+
+    // Check which blobs are missing in parallel
+    let missing_blobs = futures::stream::iter(blob_hashes)
+        .map(|blob_hash| async move {
+            let local_path = try_local_blob_path(brioche, blob_hash).await?;
+            anyhow::Ok((blob_hash, local_path))
+        })
+        .buffer_unordered(PREFETCH_BLOBS_CONCURRENCY)
+        .try_filter_map(|(blob_hash, local_path)| async move {
+            Ok(local_path.is_none().then_some(blob_hash))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Short-circuit if we have nothing to fetch
+    if missing_blobs.is_empty() {
+        return Ok(());
+    }
+
+    // Blobs available from a blob store don't need to be in the registry
+    let mut registry_blobs = vec![];
+    for &blob_hash in &missing_blobs {
+        if !crate::blob_store::has_blob(brioche, blob_hash).await {
+            registry_blobs.push(blob_hash);
+        }
+    }
+
+    // When fetching more than one blob, check that the registry has all of
+    // them up front with a single batch request. This way, we fail fast
+    // instead of after fetching most of the blobs
+    if registry_blobs.len() > 1 {
+        match brioche.registry_client.known_blobs(&registry_blobs).await {
+            Ok(known_blobs) => {
+                let unknown_blobs = registry_blobs
+                    .iter()
+                    .filter(|blob_hash| !known_blobs.contains(blob_hash))
+                    .collect::<Vec<_>>();
+                if let Some(unknown_blob) = unknown_blobs.first() {
+                    anyhow::bail!(
+                        "{} blob(s) are missing locally and not available from the registry (including {unknown_blob})",
+                        unknown_blobs.len(),
+                    );
+                }
+            }
+            Err(error) => {
+                tracing::debug!("failed to check known blobs, fetching anyway: {error:#}");
+            }
+        }
+    }
+
+    let job_id = brioche
+        .reporter
+        .add_job(crate::reporter::NewJob::RegistryFetch {
+            total_blobs: missing_blobs.len(),
+            total_recipes: 0,
+        });
+
+    // These blobs aren't needed until the whole batch is done, so yield to
+    // any blob a bake is waiting on right now
+    let fetch_blobs = futures::stream::iter(missing_blobs)
+        .map(Ok)
+        .try_for_each_concurrent(PREFETCH_BLOBS_CONCURRENCY, |blob_hash| async move {
+            blob_path(brioche, blob_hash).await?;
+
+            brioche.reporter.update_job(
+                job_id,
+                crate::reporter::UpdateJob::RegistryFetchAdd {
+                    blobs_fetched: 1,
+                    recipes_fetched: 0,
+                },
+            );
+
+            anyhow::Ok(())
+        });
+    crate::registry::transfer::with_priority(
+        crate::registry::transfer::TransferPriority::Prefetch,
+        fetch_blobs,
+    )
+    .await?;
+
+    brioche
+        .reporter
+        .update_job(job_id, crate::reporter::UpdateJob::RegistryFetchFinish);
+
+    Ok(())
+}
+
+/// Get the path to a blob if it's already saved locally (in the primary
+/// blob store or a secondary one), without fetching it or acquiring a save
+/// permit.
+pub async fn try_local_blob_path(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+) -> anyhow::Result<Option<PathBuf>> {
+    let primary_path = primary_blob_path(brioche, blob_hash);
+
+    if tokio::fs::try_exists(&primary_path).await? {
+        Ok(Some(primary_path))
+    } else {
+        Ok(crate::blob_store::find_local_blob(brioche, blob_hash).await)
     }
 }
 
-pub async fn blob_path(
+/// Fetch a blob from a blob store or the registry and save it locally,
+/// returning its path. Does nothing if the blob has already been saved
+/// (e.g. by another task while waiting for the permit).
+pub async fn fetch_blob(
     brioche: &Brioche,
     _permit: SaveBlobPermit<'_>,
     blob_hash: BlobHash,
 ) -> anyhow::Result<PathBuf> {
-    let local_path = local_blob_path(brioche, blob_hash);
-
-    if tokio::fs::try_exists(&local_path).await? {
+    if let Some(local_path) = try_local_blob_path(brioche, blob_hash).await? {
         return Ok(local_path);
     };
 
+    brioche.ensure_writable(&format!("fetch blob {blob_hash}"))?;
+
+    let local_path = primary_blob_path(brioche, blob_hash);
+
     if let Some(local_path_dir) = local_path.parent() {
         tokio::fs::create_dir_all(&local_path_dir).await?;
     }
 
-    let blob = brioche.registry_client.get_blob(blob_hash).await?;
-
     let temp_dir = brioche.home.join("blobs-temp");
     tokio::fs::create_dir_all(&temp_dir).await?;
     let temp_path = temp_dir.join(ulid::Ulid::new().to_string());
 
-    let mut temp_file = tokio::fs::File::create(&temp_path)
+    let (size, source) = match crate::blob_store::fetch_blob(brioche, blob_hash).await {
+        Some((blob, source)) => (
+            write_temp_blob(&temp_dir, &temp_path, blob_hash, &blob).await?,
+            source,
+        ),
+        None => (
+            fetch_registry_blob(brioche, blob_hash, &temp_path).await?,
+            BlobSource::Registry,
+        ),
+    };
+
+    let temp_file = tokio::fs::File::open(&temp_path)
         .await
         .context("failed to open temp file")?;
-    temp_file
-        .write_all(&blob)
-        .await
-        .context("failed to write blob to temp file")?;
-    temp_file
-        .set_permissions(blob_permissions())
+    set_blob_file_permissions(&temp_file)
         .await
         .context("failed to set blob permissions")?;
     let temp_file = temp_file.into_std().await;
@@ -479,27 +1229,677 @@ pub async fn blob_path(
     })
     .await??;
 
-    tokio::fs::rename(&temp_path, &local_path)
-        .await
-        .context("failed to rename blob from temp file")?;
+    persist_temp_blob(brioche, &temp_path, &local_path).await?;
+    record_blob(brioche, blob_hash, size, source, None).await?;
+    if source != BlobSource::BlobStore {
+        crate::blob_store::queue_upload(brioche, blob_hash, &local_path).await;
+    }
 
     Ok(local_path)
 }
 
-pub fn local_blob_path(brioche: &Brioche, blob_hash: BlobHash) -> PathBuf {
+/// Write a blob that was read into memory to a temp file, returning its size.
+async fn write_temp_blob(
+    temp_dir: &Path,
+    temp_path: &Path,
+    blob_hash: BlobHash,
+    blob: &[u8],
+) -> anyhow::Result<u64> {
+    crate::disk_space::ensure_available_space(
+        temp_dir,
+        blob.len() as u64,
+        &format!("blob {blob_hash}"),
+    )
+    .await?;
+
+    let mut temp_file = tokio::fs::File::create(temp_path)
+        .await
+        .context("failed to open temp file")?;
+    temp_file
+        .write_all(blob)
+        .await
+        .context("failed to write blob to temp file")?;
+    temp_file.flush().await?;
+
+    Ok(blob.len() as u64)
+}
+
+/// Download a blob from the registry to a temp file, returning its size.
+/// Shows a download job in the reporter once the blob turns out to be
+/// large.
+async fn fetch_registry_blob(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    temp_path: &Path,
+) -> anyhow::Result<u64> {
+    let mut job_id = None;
+    let result = brioche
+        .registry_client
+        .download_blob(blob_hash, temp_path, |downloaded_bytes, total_bytes| {
+            let size = total_bytes.unwrap_or(downloaded_bytes);
+            if job_id.is_none() && size >= REPORT_BLOB_DOWNLOAD_MIN_BYTES {
+                if let Ok(url) = brioche.registry_client.blob_url(blob_hash) {
+                    let new_job_id = brioche
+                        .reporter
+                        .add_job(crate::reporter::NewJob::Download { url });
+                    job_id = Some(new_job_id);
+                }
+            }
+
+            if let Some(job_id) = job_id {
+                brioche.reporter.update_job(
+                    job_id,
+                    crate::reporter::UpdateJob::download_progress(downloaded_bytes, total_bytes),
+                );
+            }
+        })
+        .await;
+
+    if let Some(job_id) = job_id {
+        brioche
+            .reporter
+            .update_job(job_id, crate::reporter::UpdateJob::download_finished());
+    }
+
+    if result.is_err() {
+        crate::fs_utils::try_remove(temp_path).await?;
+    }
+
+    result
+}
+
+/// Move a fully-written blob from `blobs-temp` into the blobs directory.
+/// If the directories are on different filesystems (detected at startup,
+/// or when the rename fails), the blob is copied next to its final path,
+/// synced, then renamed, so a partially-copied blob is never visible.
+async fn persist_temp_blob(
+    brioche: &Brioche,
+    temp_path: &Path,
+    blob_path: &Path,
+) -> anyhow::Result<()> {
+    if brioche.durability.syncs_files() {
+        crate::fs_utils::sync_file(temp_path)
+            .await
+            .context("failed to sync blob temp file")?;
+    }
+
+    if !brioche.blobs_temp_cross_device {
+        match tokio::fs::rename(temp_path, blob_path).await {
+            Ok(()) => {
+                sync_blobs_dir(brioche, blob_path).await?;
+                return Ok(());
+            }
+            Err(error) if crate::fs_utils::is_cross_device_error(&error) => {
+                tracing::debug!(temp_path = %temp_path.display(), "blobs-temp is on a different filesystem, falling back to copying");
+            }
+            Err(error) => {
+                return Err(error).context("failed to rename blob from temp file");
+            }
+        }
+    }
+
+    crate::fs_utils::durable_copy(temp_path, blob_path)
+        .await
+        .context("failed to copy blob from temp file")?;
+    crate::fs_utils::try_remove(temp_path)
+        .await
+        .context("failed to remove blob temp file")?;
+
+    Ok(())
+}
+
+/// Sync the blobs directory after a blob was renamed into place, if the
+/// durability level requires it.
+async fn sync_blobs_dir(brioche: &Brioche, blob_path: &Path) -> anyhow::Result<()> {
+    if !brioche.durability.syncs_directories() {
+        return Ok(());
+    }
+
+    if let Some(blobs_dir) = blob_path.parent() {
+        crate::fs_utils::sync_dir(blobs_dir)
+            .await
+            .context("failed to sync blobs directory")?;
+    }
+
+    Ok(())
+}
+
+/// Sync a blob that was moved or copied into place directly (rather than
+/// written to `blobs-temp` first), if the durability level requires it.
+async fn sync_saved_blob(brioche: &Brioche, blob_path: &Path) -> anyhow::Result<()> {
+    if brioche.durability.syncs_files() {
+        crate::fs_utils::sync_file(blob_path)
+            .await
+            .context("failed to sync blob")?;
+    }
+
+    sync_blobs_dir(brioche, blob_path).await
+}
+
+/// Check if `blobs-temp` is on a different filesystem from `blobs` (e.g.
+/// because one is a symlink to another disk), in which case blobs can't
+/// be renamed into place.
+pub(crate) async fn is_blobs_temp_cross_device(brioche_home: &Path) -> anyhow::Result<bool> {
+    let blobs_dir = brioche_home.join("blobs");
+    let temp_dir = brioche_home.join("blobs-temp");
+    tokio::fs::create_dir_all(&blobs_dir).await?;
+    tokio::fs::create_dir_all(&temp_dir).await?;
+
+    let blobs_metadata = tokio::fs::metadata(&blobs_dir).await?;
+    let temp_metadata = tokio::fs::metadata(&temp_dir).await?;
+    Ok(!crate::fs_utils::is_same_filesystem(
+        &blobs_metadata,
+        &temp_metadata,
+    ))
+}
+
+/// What to do with corrupt blobs found when verifying the blobs directory.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CorruptBlobAction {
+    /// Only report problems, without changing anything.
+    Report,
+    /// Move corrupt blobs into the quarantine directory so they can be
+    /// inspected, and remove blob aliases that point to them.
+    #[default]
+    Quarantine,
+    /// Delete corrupt blobs, and remove blob aliases that point to them.
+    Remove,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyBlobsReport {
+    /// The number of files in the blobs directory that were checked.
+    pub num_blobs: u64,
+
+    pub corrupt_blobs: Vec<CorruptBlob>,
+
+    /// Blob aliases that point to a blob that's missing or corrupt.
+    pub broken_aliases: Vec<BrokenBlobAlias>,
+
+    pub action: CorruptBlobAction,
+}
+
+impl VerifyBlobsReport {
+    pub fn has_problems(&self) -> bool {
+        !self.corrupt_blobs.is_empty() || !self.broken_aliases.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorruptBlob {
+    pub path: PathBuf,
+
+    /// The hash from the blob's file name, or `None` if the file name isn't
+    /// a valid blob hash.
+    pub expected_hash: Option<BlobHash>,
+
+    /// The hash of the blob's contents, or `None` if it couldn't be read.
+    pub actual_hash: Option<BlobHash>,
+
+    /// Where the blob was moved, if it was quarantined.
+    pub quarantine_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenBlobAlias {
+    pub hash: String,
+    pub blob_hash: String,
+}
+
+/// Re-hash every file in the blobs directory, and check that each blob
+/// alias points to a blob that exists. Corrupt blobs and broken aliases
+/// are then handled based on `action`. Blobs are always renamed into
+/// place after being written, so this is safe to run alongside builds.
+pub async fn verify_blobs(
+    brioche: &Brioche,
+    action: CorruptBlobAction,
+) -> anyhow::Result<VerifyBlobsReport> {
+    if action != CorruptBlobAction::Report {
+        brioche.ensure_writable("repair blobs")?;
+    }
+
     let blobs_dir = brioche.home.join("blobs");
-    let blob_path = blobs_dir.join(hex::encode(blob_hash.0.as_bytes()));
-    blob_path
+    let (num_blobs, corrupt_blobs) =
+        tokio::task::spawn_blocking(move || find_corrupt_blobs(&blobs_dir)).await??;
+
+    let mut report = VerifyBlobsReport {
+        num_blobs,
+        corrupt_blobs: vec![],
+        broken_aliases: vec![],
+        action,
+    };
+
+    let mut corrupt_hashes = HashSet::new();
+    for mut corrupt_blob in corrupt_blobs {
+        corrupt_hashes.extend(corrupt_blob.expected_hash);
+
+        match action {
+            CorruptBlobAction::Report => {}
+            CorruptBlobAction::Quarantine => {
+                let file_name = corrupt_blob.path.file_name().context("invalid blob path")?;
+                let quarantine_dir = brioche.home.join("blobs-quarantine");
+                let quarantine_path = quarantine_dir.join(file_name);
+                tokio::fs::create_dir_all(&quarantine_dir)
+                    .await
+                    .context("failed to create quarantine directory")?;
+                tokio::fs::rename(&corrupt_blob.path, &quarantine_path)
+                    .await
+                    .with_context(|| {
+                        format!("failed to quarantine blob {}", corrupt_blob.path.display())
+                    })?;
+                corrupt_blob.quarantine_path = Some(quarantine_path);
+            }
+            CorruptBlobAction::Remove => {
+                crate::fs_utils::try_remove(&corrupt_blob.path)
+                    .await
+                    .with_context(|| {
+                        format!("failed to remove blob {}", corrupt_blob.path.display())
+                    })?;
+            }
+        }
+
+        tracing::warn!(path = %corrupt_blob.path.display(), ?action, "found corrupt blob");
+        report.corrupt_blobs.push(corrupt_blob);
+    }
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let aliases = sqlx::query!(
+        r#"
+            SELECT hash, blob_hash FROM blob_aliases ORDER BY hash
+        "#,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    for alias in aliases {
+        let hash = alias.hash;
+        let blob_hash = alias.blob_hash;
+        let is_valid = match blob_hash.parse::<BlobHash>() {
+            Ok(blob_hash) => {
+                !corrupt_hashes.contains(&blob_hash)
+                    && try_local_blob_path(brioche, blob_hash).await?.is_some()
+            }
+            Err(_) => false,
+        };
+        if !is_valid {
+            report
+                .broken_aliases
+                .push(BrokenBlobAlias { hash, blob_hash });
+        }
+    }
+
+    if action != CorruptBlobAction::Report && !report.broken_aliases.is_empty() {
+        // Removing the alias means the data gets downloaded again the next
+        // time it's needed, instead of failing to find the blob
+        let broken_hashes = report
+            .broken_aliases
+            .iter()
+            .map(|alias| &alias.hash)
+            .collect::<Vec<_>>();
+        let broken_hashes = serde_json::to_string(&broken_hashes)?;
+
+        let mut db_conn = brioche.db_conn.lock().await;
+        let mut db_transaction = db_conn.begin().await?;
+        sqlx::query!(
+            r#"
+                DELETE FROM blob_aliases
+                WHERE hash IN (SELECT value FROM json_each(?))
+            "#,
+            broken_hashes,
+        )
+        .execute(&mut *db_transaction)
+        .await?;
+        db_transaction.commit().await?;
+        drop(db_conn);
+    }
+
+    Ok(report)
+}
+
+/// Hash each file in the blobs directory, returning the number of files
+/// and the ones that don't match their file name.
+fn find_corrupt_blobs(blobs_dir: &Path) -> anyhow::Result<(u64, Vec<CorruptBlob>)> {
+    let entries = match std::fs::read_dir(blobs_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((0, vec![]));
+        }
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!("failed to read blobs directory {}", blobs_dir.display())
+            });
+        }
+    };
+
+    let mut num_blobs = 0;
+    let mut corrupt_blobs = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let expected_hash = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<BlobHash>().ok());
+        let actual_hash = match hash_blob_file(&path) {
+            Ok(hash) => Some(hash),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                // Removed while verifying, e.g. by `brioche clean`
+                continue;
+            }
+            Err(error) => {
+                tracing::warn!(path = %path.display(), "failed to read blob: {error}");
+                None
+            }
+        };
+
+        num_blobs += 1;
+        if expected_hash.is_none() || expected_hash != actual_hash {
+            corrupt_blobs.push(CorruptBlob {
+                path,
+                expected_hash,
+                actual_hash,
+                quarantine_path: None,
+            });
+        }
+    }
+
+    corrupt_blobs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((num_blobs, corrupt_blobs))
+}
+
+fn hash_blob_file(path: &Path) -> std::io::Result<BlobHash> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(BlobHash(hasher.finalize()))
+}
+
+/// Options for [`backfill_blob_aliases`].
+#[derive(Debug, Clone)]
+pub struct BackfillAliasesOptions {
+    /// The algorithms to add an alias for to each blob.
+    pub algorithms: Vec<HashAlgorithm>,
+    /// The most blob data to read per second, so backfilling can run
+    /// alongside builds without hogging the disk.
+    pub max_bytes_per_second: Option<u64>,
+}
+
+impl Default for BackfillAliasesOptions {
+    fn default() -> Self {
+        Self {
+            algorithms: vec![HashAlgorithm::Sha256],
+            max_bytes_per_second: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillAliasesReport {
+    pub num_blobs: u64,
+    pub num_blobs_hashed: u64,
+    pub num_aliases_added: u64,
+    pub bytes_hashed: u64,
+}
+
+/// Hash each blob with each of the given algorithms, and record the
+/// hashes as blob aliases. Blobs that already have an alias for an
+/// algorithm aren't hashed with it again. Afterwards, recipes that expect
+/// one of these hashes (like downloads) find the blob locally instead of
+/// downloading it again.
+///
+/// Aliases are saved after each blob, so backfilling can be interrupted
+/// and resumed later without losing progress.
+pub async fn backfill_blob_aliases(
+    brioche: &Brioche,
+    options: &BackfillAliasesOptions,
+) -> anyhow::Result<BackfillAliasesReport> {
+    brioche.ensure_writable("add blob aliases")?;
+
+    let blobs_dir = brioche.home.join("blobs");
+    let blob_hashes = tokio::task::spawn_blocking(move || list_blobs(&blobs_dir)).await??;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let aliases = sqlx::query!(
+        r#"
+            SELECT hash, blob_hash FROM blob_aliases
+        "#,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let mut aliased_algorithms = HashMap::<BlobHash, HashSet<HashAlgorithm>>::new();
+    for alias in aliases {
+        let (Ok(hash), Ok(blob_hash)) = (
+            alias.hash.parse::<Hash>(),
+            alias.blob_hash.parse::<BlobHash>(),
+        ) else {
+            continue;
+        };
+        aliased_algorithms
+            .entry(blob_hash)
+            .or_default()
+            .insert(hash.algorithm());
+    }
+
+    let mut report = BackfillAliasesReport {
+        num_blobs: blob_hashes.len() as u64,
+        ..Default::default()
+    };
+    let mut rate_limiter = RateLimiter::new(options.max_bytes_per_second);
+    for blob_hash in blob_hashes {
+        let aliased = aliased_algorithms.get(&blob_hash);
+        let algorithms = options
+            .algorithms
+            .iter()
+            .copied()
+            .filter(|algorithm| !aliased.is_some_and(|aliased| aliased.contains(algorithm)))
+            .collect::<HashSet<_>>();
+        if algorithms.is_empty() {
+            continue;
+        }
+
+        let blob_path = local_blob_path(brioche, blob_hash).await?;
+        let result = tokio::task::spawn_blocking(move || {
+            let result = hash_blob_file_with(&blob_path, &algorithms, &mut rate_limiter);
+            (result, rate_limiter)
+        })
+        .await?;
+        let (bytes_read, hashes) = match result {
+            (Ok(hashed), limiter) => {
+                rate_limiter = limiter;
+                hashed
+            }
+            (Err(error), limiter) if error.kind() == std::io::ErrorKind::NotFound => {
+                // Removed while backfilling, e.g. by `brioche clean`
+                rate_limiter = limiter;
+                continue;
+            }
+            (Err(error), _) => {
+                return Err(error).with_context(|| format!("failed to hash blob {blob_hash}"));
+            }
+        };
+
+        let aliases = hashes
+            .iter()
+            .map(|hash| hash.to_string())
+            .collect::<Vec<_>>();
+        let aliases = serde_json::to_string(&aliases)?;
+        let blob_hash_value = blob_hash.to_string();
+
+        let mut db_conn = brioche.db_conn.lock().await;
+        let mut db_transaction = db_conn.begin().await?;
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO blob_aliases (hash, blob_hash)
+                SELECT value, ? FROM json_each(?)
+                WHERE true
+                ON CONFLICT (hash) DO NOTHING
+            "#,
+            blob_hash_value,
+            aliases,
+        )
+        .execute(&mut *db_transaction)
+        .await?;
+        db_transaction.commit().await?;
+        drop(db_conn);
+
+        report.num_blobs_hashed += 1;
+        report.bytes_hashed += bytes_read;
+        report.num_aliases_added += result.rows_affected();
+    }
+
+    Ok(report)
+}
+
+/// List the hashes of the blobs in the blobs directory. Files that aren't
+/// named after a blob hash are skipped.
+fn list_blobs(blobs_dir: &Path) -> anyhow::Result<Vec<BlobHash>> {
+    let entries = match std::fs::read_dir(blobs_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(vec![]);
+        }
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!("failed to read blobs directory {}", blobs_dir.display())
+            });
+        }
+    };
+
+    let mut blob_hashes = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let blob_hash = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<BlobHash>().ok());
+        blob_hashes.extend(blob_hash);
+    }
+
+    blob_hashes.sort_by_key(|blob_hash| *blob_hash.0.as_bytes());
+    Ok(blob_hashes)
+}
+
+/// Hash a file with several algorithms at once, returning the number of
+/// bytes read along with the hashes.
+fn hash_blob_file_with(
+    path: &Path,
+    algorithms: &HashSet<HashAlgorithm>,
+    rate_limiter: &mut RateLimiter,
+) -> std::io::Result<(u64, Vec<Hash>)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hashers = algorithms
+        .iter()
+        .map(|algorithm| super::Hasher::for_algorithm(*algorithm))
+        .collect::<Vec<_>>();
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut bytes_read = 0;
+    loop {
+        let length = file.read(&mut buffer)?;
+        if length == 0 {
+            break;
+        }
+
+        for hasher in &mut hashers {
+            hasher.update(&buffer[..length]);
+        }
+        bytes_read += length as u64;
+        rate_limiter.consume(length as u64);
+    }
+
+    let hashes = hashers
+        .into_iter()
+        .map(|hasher| hasher.finish())
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(std::io::Error::other)?;
+    Ok((bytes_read, hashes))
+}
+
+/// Sleeps as needed to keep the average rate of bytes consumed under a
+/// limit.
+struct RateLimiter {
+    max_bytes_per_second: Option<u64>,
+    start: std::time::Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    fn new(max_bytes_per_second: Option<u64>) -> Self {
+        Self {
+            max_bytes_per_second,
+            start: std::time::Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        let Some(max_bytes_per_second) = self.max_bytes_per_second else {
+            return;
+        };
+
+        self.bytes += bytes;
+        let target_elapsed =
+            std::time::Duration::from_secs_f64(self.bytes as f64 / max_bytes_per_second as f64);
+        if let Some(remaining) = target_elapsed.checked_sub(self.start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
 }
 
-fn blob_permissions() -> std::fs::Permissions {
-    std::fs::Permissions::from_mode(0o444)
+/// Get the path to a blob within the local blob stores. The primary store
+/// is checked first, then each secondary store in order. If the blob isn't
+/// in any of them, returns the path it would be saved to in the primary
+/// store.
+pub async fn local_blob_path(brioche: &Brioche, blob_hash: BlobHash) -> anyhow::Result<PathBuf> {
+    let local_path = try_local_blob_path(brioche, blob_hash).await?;
+    Ok(local_path.unwrap_or_else(|| primary_blob_path(brioche, blob_hash)))
 }
 
-fn is_file_exclusive(metadata: &std::fs::Metadata) -> bool {
-    use std::os::unix::fs::MetadataExt as _;
+/// Get the path where a blob is saved in the primary blob store, which is
+/// the only store new blobs are written to.
+pub fn primary_blob_path(brioche: &Brioche, blob_hash: BlobHash) -> PathBuf {
+    let blobs_dir = brioche.home.join("blobs");
+    let blob_path = blobs_dir.join(hex::encode(blob_hash.0.as_bytes()));
+    blob_path
+}
 
-    metadata.nlink() == 1
+async fn set_blob_file_permissions(file: &tokio::fs::File) -> anyhow::Result<()> {
+    let mut permissions = file.metadata().await?.permissions();
+    crate::fs_utils::set_readonly(&mut permissions);
+    file.set_permissions(permissions).await?;
+    Ok(())
 }
 
 #[derive(
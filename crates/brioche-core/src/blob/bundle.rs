@@ -0,0 +1,246 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::Context as _;
+use futures::TryStreamExt as _;
+use sqlx::Acquire as _;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+use crate::{Brioche, Hash};
+
+use super::{BlobHash, SaveBlobOptions};
+
+/// The path of the manifest within a bundle. The manifest is always the
+/// first entry, so it can be checked before reading any blobs.
+const MANIFEST_PATH: &str = "brioche-bundle.json";
+
+/// The directory within a bundle containing the blobs, each named by
+/// its hash.
+const BLOBS_DIR: &str = "blobs";
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleManifest {
+    version: u32,
+    blobs: Vec<BlobHash>,
+    aliases: Vec<BundleAlias>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleAlias {
+    hash: Hash,
+    blob_hash: BlobHash,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportBundleReport {
+    pub num_blobs: usize,
+    pub num_aliases: usize,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportBundleReport {
+    pub num_blobs: usize,
+    pub num_new_blobs: usize,
+    pub num_aliases: usize,
+    pub total_size: u64,
+}
+
+/// Write blobs to a zstd-compressed tarball, along with any aliases
+/// recorded for them. The bundle can be imported with [`import_bundle`]
+/// to transfer blobs to a machine that can't reach the registry. Blobs
+/// that aren't in the local store are fetched first.
+pub async fn export_bundle(
+    brioche: &Brioche,
+    blob_hashes: &[BlobHash],
+    output_path: &Path,
+) -> anyhow::Result<ExportBundleReport> {
+    let mut seen = HashSet::new();
+    let blob_hashes = blob_hashes
+        .iter()
+        .copied()
+        .filter(|blob_hash| seen.insert(*blob_hash))
+        .collect::<Vec<_>>();
+
+    super::prefetch_blobs(brioche, &blob_hashes).await?;
+
+    let aliases = blob_aliases(brioche, &blob_hashes).await?;
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        blobs: blob_hashes.clone(),
+        aliases,
+    };
+
+    let output_file = tokio::fs::File::create(output_path)
+        .await
+        .with_context(|| format!("failed to create {}", output_path.display()))?;
+    let encoder = async_compression::tokio::write::ZstdEncoder::new(output_file);
+    let mut builder = tokio_tar::Builder::new(encoder);
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tokio_tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, MANIFEST_PATH, &manifest_json[..])
+        .await?;
+
+    let mut report = ExportBundleReport {
+        num_blobs: blob_hashes.len(),
+        num_aliases: manifest.aliases.len(),
+        total_size: 0,
+    };
+
+    for blob_hash in blob_hashes {
+        let blob_path = super::blob_path(brioche, blob_hash).await?;
+        let blob_file = tokio::fs::File::open(&blob_path)
+            .await
+            .with_context(|| format!("failed to open blob {}", blob_path.display()))?;
+        let blob_size = blob_file.metadata().await?.len();
+
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(blob_size);
+        header.set_mode(0o444);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{BLOBS_DIR}/{blob_hash}"), blob_file)
+            .await?;
+
+        report.total_size += blob_size;
+    }
+
+    let mut encoder = builder.into_inner().await?;
+    encoder.shutdown().await?;
+
+    Ok(report)
+}
+
+/// Save the blobs and aliases from a bundle written by [`export_bundle`].
+/// Each blob is checked against the hash it was exported with, and
+/// aliases are only recorded once all the blobs have been saved.
+pub async fn import_bundle(
+    brioche: &Brioche,
+    bundle_path: &Path,
+) -> anyhow::Result<ImportBundleReport> {
+    brioche.ensure_writable("import blob bundle")?;
+
+    let bundle_file = tokio::fs::File::open(bundle_path)
+        .await
+        .with_context(|| format!("failed to open {}", bundle_path.display()))?;
+    let bundle_file = tokio::io::BufReader::new(bundle_file);
+    let decoder = async_compression::tokio::bufread::ZstdDecoder::new(bundle_file);
+    let mut archive = tokio_tar::Archive::new(decoder);
+    let mut entries = archive.entries()?;
+
+    let mut manifest_entry = entries.try_next().await?.context("blob bundle is empty")?;
+    anyhow::ensure!(
+        &*manifest_entry.path_bytes() == MANIFEST_PATH.as_bytes(),
+        "blob bundle does not start with {MANIFEST_PATH}"
+    );
+    let mut manifest_json = vec![];
+    manifest_entry.read_to_end(&mut manifest_json).await?;
+    let manifest: BundleManifest =
+        serde_json::from_slice(&manifest_json).context("invalid blob bundle manifest")?;
+    anyhow::ensure!(
+        manifest.version == BUNDLE_VERSION,
+        "unsupported blob bundle version {}",
+        manifest.version
+    );
+
+    let mut expected_blobs = manifest.blobs.iter().copied().collect::<HashSet<_>>();
+    let mut report = ImportBundleReport::default();
+
+    while let Some(entry) = entries.try_next().await? {
+        let entry_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let expected_hash = entry_path
+            .strip_prefix(BLOBS_DIR)
+            .and_then(|path| path.strip_prefix('/'))
+            .and_then(|name| name.parse::<BlobHash>().ok())
+            .with_context(|| format!("unexpected entry {entry_path:?} in blob bundle"))?;
+        anyhow::ensure!(
+            expected_blobs.remove(&expected_hash),
+            "blob {expected_hash} in bundle is not listed in the manifest"
+        );
+
+        let already_saved = super::try_local_blob_path(brioche, expected_hash)
+            .await?
+            .is_some();
+        let entry_size = entry.header().size()?;
+
+        let permit = super::get_save_blob_permit(brioche).await?;
+        let blob_hash =
+            super::save_blob_from_reader(brioche, permit, entry, SaveBlobOptions::new())
+                .await
+                .with_context(|| format!("failed to save blob {expected_hash} from bundle"))?;
+        anyhow::ensure!(
+            blob_hash == expected_hash,
+            "blob {expected_hash} in bundle has hash {blob_hash}"
+        );
+
+        report.num_blobs += 1;
+        if !already_saved {
+            report.num_new_blobs += 1;
+        }
+        report.total_size += entry_size;
+    }
+
+    if let Some(missing_blob) = expected_blobs.into_iter().next() {
+        anyhow::bail!("blob {missing_blob} listed in the manifest is missing from the bundle");
+    }
+
+    for alias in &manifest.aliases {
+        anyhow::ensure!(
+            manifest.blobs.contains(&alias.blob_hash),
+            "alias {} in bundle points to blob {} that isn't in the bundle",
+            alias.hash,
+            alias.blob_hash
+        );
+        super::save_blob_alias(brioche, &alias.hash, alias.blob_hash).await?;
+        report.num_aliases += 1;
+    }
+
+    Ok(report)
+}
+
+/// Get all aliases recorded for the given blobs.
+async fn blob_aliases(
+    brioche: &Brioche,
+    blob_hashes: &[BlobHash],
+) -> anyhow::Result<Vec<BundleAlias>> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let mut aliases = vec![];
+    for blob_hash in blob_hashes {
+        let blob_hash_value = blob_hash.to_string();
+        let hashes = sqlx::query_scalar!(
+            r#"
+                SELECT hash FROM blob_aliases WHERE blob_hash = ? ORDER BY hash
+            "#,
+            blob_hash_value,
+        )
+        .fetch_all(&mut *db_transaction)
+        .await?;
+
+        for hash in hashes {
+            let hash = hash
+                .parse::<Hash>()
+                .with_context(|| format!("invalid alias {hash:?} for blob {blob_hash}"))?;
+            aliases.push(BundleAlias {
+                hash,
+                blob_hash: *blob_hash,
+            });
+        }
+    }
+
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    Ok(aliases)
+}
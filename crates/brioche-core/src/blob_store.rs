@@ -0,0 +1,354 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+
+use crate::{
+    blob::{BlobHash, BlobSource},
+    Brioche,
+};
+
+pub mod s3;
+
+/// A place blobs can be read from and written to, other than the Brioche
+/// home's own blob store. Local stores (secondary blob directories) are
+/// consulted right after the primary store and have their blobs used in
+/// place. Other stores, like mirror stores and remote stores, are
+/// consulted next, before the registry, and have their blobs copied into
+/// the primary store. Writable stores get a copy of each new blob saved
+/// locally.
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// A description of the store for logs and error messages.
+    fn name(&self) -> String;
+
+    /// Whether new blobs should be written to this store.
+    fn is_writable(&self) -> bool;
+
+    /// Where blobs copied from this store are recorded as coming from.
+    fn source(&self) -> BlobSource {
+        BlobSource::BlobStore
+    }
+
+    /// Get the path to a blob that can be used in place, like a blob in
+    /// the primary store, or `None` if the store doesn't have it. Only
+    /// local stores return a path, so blobs from other stores get fetched
+    /// and validated instead.
+    async fn local_blob_path(&self, _blob_hash: BlobHash) -> anyhow::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    /// Get the contents of a blob, or `None` if the store doesn't have it.
+    /// The contents aren't validated, since callers need to validate blobs
+    /// from shared stores anyway.
+    async fn get_blob(&self, blob_hash: BlobHash) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Check if the store has a blob.
+    async fn has_blob(&self, blob_hash: BlobHash) -> anyhow::Result<bool>;
+
+    /// Write a blob from a file that's already been saved to the local
+    /// store.
+    async fn put_blob(&self, blob_hash: BlobHash, path: &Path) -> anyhow::Result<()>;
+}
+
+/// Configuration for a remote blob store, from the `blob_stores` key in
+/// the config file.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlobStoreConfig {
+    Filesystem {
+        path: PathBuf,
+        #[serde(default)]
+        write: bool,
+    },
+    S3(s3::S3BlobStoreConfig),
+}
+
+impl BlobStoreConfig {
+    pub fn build(
+        &self,
+        http_client_options: &crate::network::HttpClientOptions,
+    ) -> anyhow::Result<Box<dyn BlobStore>> {
+        match self {
+            Self::Filesystem { path, write } => {
+                Ok(Box::new(FilesystemBlobStore::new(path.clone(), *write)))
+            }
+            Self::S3(config) => {
+                let store = s3::S3BlobStore::new(config.clone(), http_client_options)?;
+                Ok(Box::new(store))
+            }
+        }
+    }
+}
+
+/// A directory of blobs laid out like the Brioche home's `blobs`
+/// directory. This is used for secondary blob directories, for the blobs
+/// of mirror stores, and for directories configured as blob stores (such
+/// as a cache directory restored between CI runs).
+pub struct FilesystemBlobStore {
+    blobs_dir: PathBuf,
+    kind: FilesystemBlobStoreKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilesystemBlobStoreKind {
+    /// Blobs are used in place, and new blobs are never written.
+    Local,
+    /// Blobs are copied out of another Brioche home, and new blobs are
+    /// never written.
+    Mirror,
+    /// Blobs are copied out of the store, and new blobs are written if
+    /// the store is writable.
+    Remote { writable: bool },
+}
+
+impl FilesystemBlobStore {
+    pub fn new(blobs_dir: PathBuf, writable: bool) -> Self {
+        Self {
+            blobs_dir,
+            kind: FilesystemBlobStoreKind::Remote { writable },
+        }
+    }
+
+    /// A secondary blob directory, such as a shared cache on a network
+    /// mount. Its blobs are used in place.
+    pub fn local(blobs_dir: PathBuf) -> Self {
+        Self {
+            blobs_dir,
+            kind: FilesystemBlobStoreKind::Local,
+        }
+    }
+
+    /// The blobs of a mirror store, from the Brioche home directory at
+    /// `mirror_path`.
+    pub fn mirror(mirror_path: &Path) -> Self {
+        Self {
+            blobs_dir: mirror_path.join("blobs"),
+            kind: FilesystemBlobStoreKind::Mirror,
+        }
+    }
+
+    fn blob_path(&self, blob_hash: BlobHash) -> PathBuf {
+        self.blobs_dir
+            .join(hex::encode(blob_hash.to_blake3().as_bytes()))
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for FilesystemBlobStore {
+    fn name(&self) -> String {
+        self.blobs_dir.display().to_string()
+    }
+
+    fn is_writable(&self) -> bool {
+        self.kind == FilesystemBlobStoreKind::Remote { writable: true }
+    }
+
+    fn source(&self) -> BlobSource {
+        match self.kind {
+            FilesystemBlobStoreKind::Mirror => BlobSource::Mirror,
+            FilesystemBlobStoreKind::Local | FilesystemBlobStoreKind::Remote { .. } => {
+                BlobSource::BlobStore
+            }
+        }
+    }
+
+    async fn local_blob_path(&self, blob_hash: BlobHash) -> anyhow::Result<Option<PathBuf>> {
+        if self.kind != FilesystemBlobStoreKind::Local {
+            return Ok(None);
+        }
+
+        let blob_path = self.blob_path(blob_hash);
+        let exists = tokio::fs::try_exists(&blob_path).await?;
+        Ok(exists.then_some(blob_path))
+    }
+
+    async fn get_blob(&self, blob_hash: BlobHash) -> anyhow::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.blob_path(blob_hash)).await {
+            Ok(blob) => Ok(Some(blob)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn has_blob(&self, blob_hash: BlobHash) -> anyhow::Result<bool> {
+        let exists = tokio::fs::try_exists(self.blob_path(blob_hash)).await?;
+        Ok(exists)
+    }
+
+    async fn put_blob(&self, blob_hash: BlobHash, path: &Path) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.blobs_dir)
+            .await
+            .with_context(|| format!("failed to create directory {}", self.blobs_dir.display()))?;
+
+        let blob_path = self.blob_path(blob_hash);
+        crate::fs_utils::atomic_copy(path, &blob_path).await?;
+        Ok(())
+    }
+}
+
+/// Find a blob in the local blob stores, checking each one in order.
+/// Stores that can't be read (e.g. an unmounted network share) are
+/// skipped.
+pub async fn find_local_blob(brioche: &Brioche, blob_hash: BlobHash) -> Option<PathBuf> {
+    for store in brioche.blob_stores.iter() {
+        match store.local_blob_path(blob_hash).await {
+            Ok(Some(blob_path)) => return Some(blob_path),
+            Ok(None) => {}
+            Err(error) => {
+                tracing::debug!(
+                    store = store.name(),
+                    "failed to check local blob store: {error:#}"
+                );
+            }
+        }
+    }
+
+    None
+}
+
+/// Check if any blob store can provide a blob, so it doesn't need to be
+/// fetched from the registry.
+pub async fn has_blob(brioche: &Brioche, blob_hash: BlobHash) -> bool {
+    for store in brioche.blob_stores.iter() {
+        match store.has_blob(blob_hash).await {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(error) => {
+                tracing::debug!(store = store.name(), %blob_hash, "failed to check blob store: {error:#}");
+            }
+        }
+    }
+
+    false
+}
+
+/// Get a blob from the first blob store that has it, along with where it
+/// came from. Blobs that don't match their hash are skipped, and errors
+/// from a store are logged rather than returned, so the blob can still be
+/// fetched from the registry.
+pub async fn fetch_blob(brioche: &Brioche, blob_hash: BlobHash) -> Option<(Vec<u8>, BlobSource)> {
+    for store in brioche.blob_stores.iter() {
+        let blob = match store.get_blob(blob_hash).await {
+            Ok(Some(blob)) => blob,
+            Ok(None) => continue,
+            Err(error) => {
+                tracing::warn!(store = store.name(), %blob_hash, "failed to get blob from blob store: {error:#}");
+                continue;
+            }
+        };
+
+        match blob_hash.validate_matches(&blob) {
+            Ok(()) => return Some((blob, store.source())),
+            Err(error) => {
+                tracing::warn!(
+                    store = store.name(),
+                    "ignoring blob from blob store: {error:#}"
+                );
+            }
+        }
+    }
+
+    None
+}
+
+pub enum BlobUploadMessage {
+    Upload {
+        blob_hash: BlobHash,
+        path: PathBuf,
+    },
+    Flush {
+        completed: tokio::sync::oneshot::Sender<()>,
+    },
+}
+
+/// Queue a newly-saved blob to be written to each writable blob store. The
+/// upload runs in the background, so saving a blob doesn't wait on a slow
+/// remote store.
+pub async fn queue_upload(brioche: &Brioche, blob_hash: BlobHash, path: &Path) {
+    if !brioche.blob_stores.iter().any(|store| store.is_writable()) {
+        return;
+    }
+
+    let message = BlobUploadMessage::Upload {
+        blob_hash,
+        path: path.to_owned(),
+    };
+    if brioche.blob_upload_tx.send(message).await.is_err() {
+        tracing::warn!(%blob_hash, "failed to queue blob upload");
+    }
+}
+
+/// Wait for every queued blob upload to finish.
+pub async fn flush_uploads(brioche: &Brioche) -> anyhow::Result<()> {
+    let (completed_tx, completed_rx) = tokio::sync::oneshot::channel();
+    brioche
+        .blob_upload_tx
+        .send(BlobUploadMessage::Flush {
+            completed: completed_tx,
+        })
+        .await?;
+    completed_rx.await?;
+    Ok(())
+}
+
+const UPLOAD_CONCURRENCY: usize = 10;
+
+/// Start a task that uploads queued blobs to the writable blob stores,
+/// running a few uploads at a time.
+pub(crate) fn start_upload_task(
+    blob_stores: Arc<Vec<Box<dyn BlobStore>>>,
+) -> tokio::sync::mpsc::Sender<BlobUploadMessage> {
+    let (upload_tx, mut upload_rx) = tokio::sync::mpsc::channel(1000);
+
+    tokio::spawn(async move {
+        let mut uploads = tokio::task::JoinSet::new();
+
+        while let Some(message) = upload_rx.recv().await {
+            match message {
+                BlobUploadMessage::Upload { blob_hash, path } => {
+                    if uploads.len() >= UPLOAD_CONCURRENCY {
+                        uploads.join_next().await;
+                    }
+
+                    let blob_stores = blob_stores.clone();
+                    uploads.spawn(async move {
+                        upload_blob(&blob_stores, blob_hash, &path).await;
+                    });
+                }
+                BlobUploadMessage::Flush { completed } => {
+                    while uploads.join_next().await.is_some() {}
+                    let _ = completed.send(()).inspect_err(|_| {
+                        tracing::warn!("failed to send blob upload flush completion");
+                    });
+                }
+            }
+        }
+    });
+
+    upload_tx
+}
+
+/// Write a blob to each writable blob store that doesn't already have it.
+/// Failures are logged, since the blob is still saved locally.
+async fn upload_blob(blob_stores: &[Box<dyn BlobStore>], blob_hash: BlobHash, path: &Path) {
+    for store in blob_stores {
+        if !store.is_writable() {
+            continue;
+        }
+
+        let result = async {
+            if !store.has_blob(blob_hash).await? {
+                store.put_blob(blob_hash, path).await?;
+                tracing::debug!(store = store.name(), %blob_hash, "uploaded blob to blob store");
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(error) = result {
+            tracing::warn!(store = store.name(), %blob_hash, "failed to upload blob to blob store: {error:#}");
+        }
+    }
+}
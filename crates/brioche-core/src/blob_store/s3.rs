@@ -0,0 +1,327 @@
+use std::{path::Path, time::SystemTime};
+
+use anyhow::Context as _;
+use sha2::Digest as _;
+
+use crate::blob::BlobHash;
+
+use super::BlobStore;
+
+/// The payload hash sent for uploads, so blobs can be streamed from disk
+/// without hashing them first.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Configuration for an S3-compatible bucket used as a blob store.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct S3BlobStoreConfig {
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// The endpoint for S3-compatible services (e.g. MinIO or R2).
+    /// Defaults to the AWS endpoint for the region. Requests always use
+    /// path-style URLs.
+    pub endpoint: Option<url::Url>,
+    /// A prefix for the key of each blob within the bucket.
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub write: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl S3Credentials {
+    /// Read credentials from the standard `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY`, and `AWS_SESSION_TOKEN` environment
+    /// variables. Returns `None` if they aren't set, in which case requests
+    /// are sent anonymously.
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token,
+        })
+    }
+}
+
+/// A blob store backed by an S3-compatible bucket. Requests are signed with
+/// AWS Signature Version 4.
+pub struct S3BlobStore {
+    config: S3BlobStoreConfig,
+    endpoint: url::Url,
+    credentials: Option<S3Credentials>,
+    client: reqwest::Client,
+}
+
+impl S3BlobStore {
+    pub fn new(
+        config: S3BlobStoreConfig,
+        http_client_options: &crate::network::HttpClientOptions,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_credentials(config, S3Credentials::from_env(), http_client_options)
+    }
+
+    pub fn new_with_credentials(
+        config: S3BlobStoreConfig,
+        credentials: Option<S3Credentials>,
+        http_client_options: &crate::network::HttpClientOptions,
+    ) -> anyhow::Result<Self> {
+        let endpoint = match &config.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://s3.{}.amazonaws.com", config.region)
+                .parse()
+                .with_context(|| format!("invalid S3 region {:?}", config.region))?,
+        };
+        let client = http_client_options
+            .configure(reqwest::Client::builder().user_agent(crate::USER_AGENT))
+            .build()?;
+
+        Ok(Self {
+            config,
+            endpoint,
+            credentials,
+            client,
+        })
+    }
+
+    fn blob_url(&self, blob_hash: BlobHash) -> url::Url {
+        let blob_name = hex::encode(blob_hash.to_blake3().as_bytes());
+        let key_segments = self
+            .config
+            .prefix
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .chain([&*blob_name]);
+
+        // Encode the path ourselves, since the canonical request needs
+        // every character other than unreserved ones to be encoded
+        let mut path = self.endpoint.path().trim_end_matches('/').to_string();
+        for segment in std::iter::once(&*self.config.bucket).chain(key_segments) {
+            path.push('/');
+            path.push_str(&urlencoding::encode(segment));
+        }
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query(None);
+        url
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: url::Url,
+        payload_hash: &str,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let mut request = self.client.request(method.clone(), url.clone());
+        let Some(credentials) = &self.credentials else {
+            return Ok(request);
+        };
+
+        let headers = sign_request(
+            credentials,
+            &self.config.region,
+            method.as_str(),
+            &url,
+            payload_hash,
+            SystemTime::now(),
+        )?;
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for S3BlobStore {
+    fn name(&self) -> String {
+        format!("s3://{}/{}", self.config.bucket, self.config.prefix)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.config.write
+    }
+
+    async fn get_blob(&self, blob_hash: BlobHash) -> anyhow::Result<Option<Vec<u8>>> {
+        let url = self.blob_url(blob_hash);
+        let empty_payload_hash = hex::encode(sha2::Sha256::digest(b""));
+        let response = self
+            .request(reqwest::Method::GET, url, &empty_payload_hash)?
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+        let blob = response.bytes().await?;
+        Ok(Some(blob.to_vec()))
+    }
+
+    async fn has_blob(&self, blob_hash: BlobHash) -> anyhow::Result<bool> {
+        let url = self.blob_url(blob_hash);
+        let empty_payload_hash = hex::encode(sha2::Sha256::digest(b""));
+        let response = self
+            .request(reqwest::Method::HEAD, url, &empty_payload_hash)?
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn put_blob(&self, blob_hash: BlobHash, path: &Path) -> anyhow::Result<()> {
+        let url = self.blob_url(blob_hash);
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("failed to open blob {}", path.display()))?;
+        let length = file.metadata().await?.len();
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file));
+
+        self.request(reqwest::Method::PUT, url, UNSIGNED_PAYLOAD)?
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Compute the headers to sign a request with AWS Signature Version 4,
+/// including the `Authorization` header. Only the host and `x-amz-*`
+/// headers are signed.
+fn sign_request(
+    credentials: &S3Credentials,
+    region: &str,
+    method: &str,
+    url: &url::Url,
+    payload_hash: &str,
+    time: SystemTime,
+) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let timestamp = format_amz_timestamp(time)?;
+    let date = &timestamp[..8];
+
+    let host = url.host_str().context("S3 URL has no host")?;
+    let host = match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    };
+
+    let mut signed_headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", payload_hash.to_string()),
+        ("x-amz-date", timestamp.clone()),
+    ];
+    if let Some(session_token) = &credentials.session_token {
+        signed_headers.push(("x-amz-security-token", session_token.clone()));
+    }
+
+    let mut query_pairs = url
+        .query_pairs()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                urlencoding::encode(&name),
+                urlencoding::encode(&value)
+            )
+        })
+        .collect::<Vec<_>>();
+    query_pairs.sort();
+
+    let canonical_headers = signed_headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect::<String>();
+    let signed_header_names = signed_headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{}\n{}\n{canonical_headers}\n{signed_header_names}\n{payload_hash}",
+        url.path(),
+        query_pairs.join("&"),
+    );
+
+    let scope = format!("{date}/{region}/s3/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex::encode(sha2::Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let signing_key = [date, region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", credentials.secret_access_key).into_bytes(),
+        |key, part| hmac_sha256(&key, part.as_bytes()),
+    );
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_header_names}, Signature={signature}",
+        credentials.access_key_id,
+    );
+
+    // Host is set by the HTTP client from the URL
+    let mut headers = signed_headers
+        .into_iter()
+        .filter(|(name, _)| *name != "host")
+        .collect::<Vec<_>>();
+    headers.push(("authorization", authorization));
+    Ok(headers)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    ring::hmac::sign(&key, data).as_ref().to_vec()
+}
+
+/// Format a time as `YYYYMMDDTHHMMSSZ` in UTC, as used by the `x-amz-date`
+/// header.
+fn format_amz_timestamp(time: SystemTime) -> anyhow::Result<String> {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .context("time is before the Unix epoch")?
+        .as_secs();
+    let days = (seconds / 86400) as i64;
+    let seconds_of_day = seconds % 86400;
+
+    // Convert days since the epoch to a civil date, see:
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Ok(format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        seconds_of_day / 3600,
+        (seconds_of_day / 60) % 60,
+        seconds_of_day % 60,
+    ))
+}
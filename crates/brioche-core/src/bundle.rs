@@ -0,0 +1,505 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+use tokio::io::AsyncWriteExt as _;
+
+use crate::{
+    blob::BlobHash,
+    project::{ProjectHash, Projects},
+    recipe::{Recipe, RecipeHash},
+    Brioche,
+};
+
+/// The directory within a source bundle that holds everything needed to
+/// build the project without network access.
+pub const VENDOR_DIR: &str = ".brioche-vendor";
+
+const MANIFEST_FILENAME: &str = "bundle.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    pub project_hash: ProjectHash,
+    pub export: String,
+
+    /// Registry projects the project depends on (directly or indirectly),
+    /// stored under `projects/<hash>` in the vendor directory.
+    pub vendored_projects: Vec<ProjectHash>,
+
+    /// Bake results for recipes that need network access, like downloads
+    /// and git checkouts. The recipes themselves are stored under
+    /// `recipes/<hash>.json`, and their blobs under `blobs/<hash>`.
+    pub vendored_bakes: Vec<VendoredBake>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendoredBake {
+    pub input_hash: RecipeHash,
+    pub output_hash: RecipeHash,
+}
+
+/// Write a `.tar.gz` archive containing a project, its lockfile, and
+/// everything it needs from the network: the registry projects it depends
+/// on and the outputs of its downloads and git checkouts. Extracting the
+/// archive and building it will give the same result without network
+/// access.
+///
+/// The export should already have been baked, since the bakes recorded for
+/// it are used to find what needs to be vendored. All files in the archive
+/// are placed under the directory `prefix`.
+pub async fn write_source_bundle(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+    export: &str,
+    prefix: &str,
+    output_path: &Path,
+) -> anyhow::Result<BundleManifest> {
+    projects.validate_no_dirty_lockfiles()?;
+
+    let project_root = projects.project_root(project_hash)?;
+    let mut entries = BTreeMap::new();
+    add_dir_entries(&mut entries, &project_root, "").await?;
+
+    // Find every registry project that gets loaded while building the
+    // project. Path dependencies are fine as long as they are within the
+    // project itself, since they get included in the archive anyway
+    let mut vendored_projects = vec![];
+    let mut visited = HashSet::new();
+    let mut unvisited = VecDeque::from_iter(projects.project(project_hash)?.dependency_hashes());
+    while let Some(dep_hash) = unvisited.pop_front() {
+        if !visited.insert(dep_hash) {
+            continue;
+        }
+
+        unvisited.extend(projects.project(dep_hash)?.dependency_hashes());
+
+        let dep_root = projects.project_root(dep_hash)?;
+        let registry_path = brioche.home.join("projects").join(dep_hash.to_string());
+        if dep_root.starts_with(&project_root) {
+            continue;
+        } else if tokio::fs::try_exists(&registry_path).await? {
+            let vendor_path = format!("{VENDOR_DIR}/projects/{dep_hash}");
+            add_dir_entries(&mut entries, &registry_path, &vendor_path).await?;
+            vendored_projects.push(dep_hash);
+        } else {
+            anyhow::bail!(
+                "dependency at {} is outside of the project, so it can't be bundled",
+                dep_root.display()
+            );
+        }
+    }
+    vendored_projects.sort_by_key(|hash| hash.to_string());
+
+    let network_bakes = network_bakes(brioche, project_hash, export).await?;
+    let mut references = crate::references::RecipeReferences::default();
+    crate::references::recipe_references(
+        brioche,
+        &mut references,
+        network_bakes
+            .iter()
+            .flat_map(|bake| [bake.input_hash, bake.output_hash]),
+    )
+    .await?;
+
+    for dir in ["", "/recipes", "/blobs"] {
+        entries.insert(format!("{VENDOR_DIR}{dir}"), BundleEntry::Directory);
+    }
+    for (recipe_hash, recipe) in &references.recipes {
+        let recipe_json = serde_json::to_vec(recipe)?;
+        entries.insert(
+            format!("{VENDOR_DIR}/recipes/{recipe_hash}.json"),
+            BundleEntry::Contents(recipe_json),
+        );
+    }
+    for blob_hash in &references.blobs {
+        let blob_path = crate::blob::blob_path(brioche, *blob_hash).await?;
+        entries.insert(
+            format!("{VENDOR_DIR}/blobs/{blob_hash}"),
+            BundleEntry::File {
+                path: blob_path,
+                executable: false,
+            },
+        );
+    }
+
+    let mut vendored_bakes = network_bakes;
+    vendored_bakes.sort_by_key(|bake| bake.input_hash.to_string());
+    let manifest = BundleManifest {
+        project_hash,
+        export: export.to_string(),
+        vendored_projects,
+        vendored_bakes,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    entries.insert(
+        format!("{VENDOR_DIR}/{MANIFEST_FILENAME}"),
+        BundleEntry::Contents(manifest_json),
+    );
+
+    let temp_path = output_path.with_extension(format!("tmp-{}", ulid::Ulid::new()));
+    let result = write_tar_gz(prefix, entries, &temp_path).await;
+    if let Err(error) = result {
+        let _ = crate::fs_utils::try_remove(&temp_path).await;
+        return Err(error).context("failed to write source bundle");
+    }
+
+    tokio::fs::rename(&temp_path, output_path)
+        .await
+        .with_context(|| format!("failed to write {}", output_path.display()))?;
+
+    Ok(manifest)
+}
+
+/// If the project at `project_path` was extracted from a source bundle,
+/// make its vendored projects and bake results available locally, so
+/// loading and building it won't need to reach the registry or the
+/// network. Returns `false` if the project isn't a source bundle.
+pub async fn import_source_bundle(brioche: &Brioche, project_path: &Path) -> anyhow::Result<bool> {
+    let vendor_dir = project_path.join(VENDOR_DIR);
+    let manifest_path = vendor_dir.join(MANIFEST_FILENAME);
+    let manifest = match tokio::fs::read(&manifest_path).await {
+        Ok(manifest) => manifest,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(false);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read {}", manifest_path.display()));
+        }
+    };
+    let manifest: BundleManifest = serde_json::from_slice(&manifest)
+        .with_context(|| format!("failed to parse {}", manifest_path.display()))?;
+
+    // Put the vendored projects where they would end up if they were
+    // fetched from the registry
+    for project_hash in &manifest.vendored_projects {
+        let local_path = brioche.home.join("projects").join(project_hash.to_string());
+        if tokio::fs::try_exists(&local_path).await? {
+            continue;
+        }
+
+        let temp_id = ulid::Ulid::new();
+        let temp_project_path = brioche.home.join("projects-temp").join(temp_id.to_string());
+        let vendored_path = vendor_dir.join("projects").join(project_hash.to_string());
+        copy_dir(&vendored_path, &temp_project_path)
+            .await
+            .with_context(|| format!("failed to copy vendored project {project_hash}"))?;
+
+        if let Some(local_dir) = local_path.parent() {
+            tokio::fs::create_dir_all(local_dir)
+                .await
+                .context("failed to create project directory")?;
+        }
+        tokio::fs::rename(&temp_project_path, &local_path)
+            .await
+            .context("failed to move vendored project")?;
+    }
+
+    let mut blobs_dir = tokio::fs::read_dir(vendor_dir.join("blobs")).await;
+    if let Ok(blobs_dir) = &mut blobs_dir {
+        while let Some(entry) = blobs_dir.next_entry().await? {
+            let blob_hash: BlobHash = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+                .with_context(|| format!("invalid vendored blob {}", entry.path().display()))?;
+            if crate::blob::try_local_blob_path(brioche, blob_hash)
+                .await?
+                .is_some()
+            {
+                continue;
+            }
+
+            let permit = crate::blob::get_save_blob_permit(brioche).await?;
+            let saved_hash = crate::blob::save_blob_from_file(
+                brioche,
+                permit,
+                &entry.path(),
+                crate::blob::SaveBlobOptions::new(),
+            )
+            .await?;
+            anyhow::ensure!(
+                saved_hash == blob_hash,
+                "vendored blob {blob_hash} has hash {saved_hash}"
+            );
+        }
+    }
+
+    let mut recipes = vec![];
+    let mut recipes_dir = tokio::fs::read_dir(vendor_dir.join("recipes")).await;
+    if let Ok(recipes_dir) = &mut recipes_dir {
+        while let Some(entry) = recipes_dir.next_entry().await? {
+            let recipe_json = tokio::fs::read(entry.path()).await?;
+            let recipe: Recipe = serde_json::from_slice(&recipe_json)
+                .with_context(|| format!("invalid vendored recipe {}", entry.path().display()))?;
+            recipes.push(recipe);
+        }
+    }
+    crate::recipe::save_recipes(brioche, &recipes).await?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    for bake in &manifest.vendored_bakes {
+        let input_hash = bake.input_hash.to_string();
+        let output_hash = bake.output_hash.to_string();
+        sqlx::query!(
+            r#"
+                INSERT INTO bakes (input_hash, output_hash)
+                VALUES (?, ?)
+                ON CONFLICT (input_hash, output_hash) DO NOTHING
+            "#,
+            input_hash,
+            output_hash,
+        )
+        .execute(&mut *db_transaction)
+        .await?;
+    }
+    db_transaction.commit().await?;
+
+    tracing::debug!(
+        path = %project_path.display(),
+        num_projects = manifest.vendored_projects.len(),
+        num_bakes = manifest.vendored_bakes.len(),
+        "imported source bundle"
+    );
+
+    Ok(true)
+}
+
+/// Find the bakes of recipes that need network access while baking an
+/// export of a project, either directly or as a child of another bake.
+async fn network_bakes(
+    brioche: &Brioche,
+    project_hash: ProjectHash,
+    export: &str,
+) -> anyhow::Result<Vec<VendoredBake>> {
+    let project_hash_value = project_hash.to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let records = sqlx::query!(
+        r#"
+            WITH RECURSIVE project_descendent_bakes (recipe_hash) AS (
+                SELECT project_bakes.recipe_hash
+                FROM project_bakes
+                WHERE project_hash = ? AND export = ?
+                UNION
+                SELECT child_bakes.recipe_hash
+                FROM child_bakes
+                INNER JOIN project_descendent_bakes ON
+                    project_descendent_bakes.recipe_hash = child_bakes.parent_hash
+            )
+            SELECT bakes.input_hash, bakes.output_hash
+            FROM project_descendent_bakes
+            INNER JOIN bakes ON
+                bakes.input_hash = project_descendent_bakes.recipe_hash
+            INNER JOIN recipes AS input_recipes ON
+                input_recipes.recipe_hash = bakes.input_hash
+            WHERE input_recipes.recipe_json->>'type' IN ('download', 'git_checkout')
+        "#,
+        project_hash_value,
+        export,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            Ok(VendoredBake {
+                input_hash: record
+                    .input_hash
+                    .parse()
+                    .context("invalid recipe hash from database")?,
+                output_hash: record
+                    .output_hash
+                    .parse()
+                    .context("invalid recipe hash from database")?,
+            })
+        })
+        .collect()
+}
+
+enum BundleEntry {
+    Directory,
+    File { path: PathBuf, executable: bool },
+    Contents(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+/// Add the contents of the directory `dir` to the archive entries, under
+/// the path `archive_path`. Version control directories and the vendor
+/// directory of a previous bundle are skipped.
+async fn add_dir_entries(
+    entries: &mut BTreeMap<String, BundleEntry>,
+    dir: &Path,
+    archive_path: &str,
+) -> anyhow::Result<()> {
+    let dir = dir.to_owned();
+    let dir_entries = tokio::task::spawn_blocking(move || {
+        let walker = walkdir::WalkDir::new(&dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = entry.file_name();
+                entry.depth() != 1 || (name != ".git" && name != VENDOR_DIR)
+            });
+
+        let mut dir_entries = vec![];
+        for entry in walker {
+            let entry = entry?;
+            let relative_path = entry.path().strip_prefix(&dir)?;
+            let relative_path = relative_path
+                .to_str()
+                .with_context(|| format!("path is not valid UTF-8: {relative_path:?}"))?
+                .to_string();
+
+            let file_type = entry.file_type();
+            let bundle_entry = if file_type.is_dir() {
+                BundleEntry::Directory
+            } else if file_type.is_symlink() {
+                BundleEntry::Symlink(std::fs::read_link(entry.path())?)
+            } else if file_type.is_file() {
+                let permissions = entry.metadata()?.permissions();
+                BundleEntry::File {
+                    path: entry.path().to_owned(),
+                    executable: crate::fs_utils::is_executable(&permissions),
+                }
+            } else {
+                anyhow::bail!("unsupported file type at {}", entry.path().display());
+            };
+            dir_entries.push((relative_path, bundle_entry));
+        }
+
+        anyhow::Ok(dir_entries)
+    })
+    .await??;
+
+    if !archive_path.is_empty() {
+        let mut parent = String::new();
+        for component in archive_path.split('/') {
+            if !parent.is_empty() {
+                parent.push('/');
+            }
+            parent.push_str(component);
+            entries.insert(parent.clone(), BundleEntry::Directory);
+        }
+    }
+
+    for (relative_path, entry) in dir_entries {
+        let path = if archive_path.is_empty() {
+            relative_path
+        } else {
+            format!("{archive_path}/{relative_path}")
+        };
+        entries.insert(path, entry);
+    }
+
+    Ok(())
+}
+
+async fn write_tar_gz(
+    prefix: &str,
+    entries: BTreeMap<String, BundleEntry>,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    let output = tokio::fs::File::create(output_path).await?;
+    let encoder = async_compression::tokio::write::GzipEncoder::new(output);
+    let mut builder = tokio_tar::Builder::new(encoder);
+    let mtime = crate::fs_utils::brioche_epoch()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("invalid Brioche epoch")
+        .as_secs();
+
+    let root = std::iter::once((String::new(), BundleEntry::Directory));
+    for (path, entry) in root.chain(entries) {
+        let path = if path.is_empty() {
+            prefix.to_string()
+        } else {
+            format!("{prefix}/{path}")
+        };
+
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(mtime);
+
+        match entry {
+            BundleEntry::Directory => {
+                header.set_entry_type(tokio_tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder
+                    .append_data(&mut header, format!("{path}/"), tokio::io::empty())
+                    .await?;
+            }
+            BundleEntry::File {
+                path: file_path,
+                executable,
+            } => {
+                let file = tokio::fs::File::open(&file_path)
+                    .await
+                    .with_context(|| format!("failed to open {}", file_path.display()))?;
+                header.set_entry_type(tokio_tar::EntryType::Regular);
+                header.set_mode(if executable { 0o755 } else { 0o644 });
+                header.set_size(file.metadata().await?.len());
+                builder.append_data(&mut header, &path, file).await?;
+            }
+            BundleEntry::Contents(contents) => {
+                header.set_entry_type(tokio_tar::EntryType::Regular);
+                header.set_mode(0o644);
+                header.set_size(contents.len() as u64);
+                builder
+                    .append_data(&mut header, &path, &contents[..])
+                    .await?;
+            }
+            BundleEntry::Symlink(target) => {
+                header.set_entry_type(tokio_tar::EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_size(0);
+                header.set_link_name(&target)?;
+                builder
+                    .append_data(&mut header, &path, tokio::io::empty())
+                    .await?;
+            }
+        }
+    }
+
+    let mut encoder = builder.into_inner().await?;
+    encoder.shutdown().await?;
+    Ok(())
+}
+
+async fn copy_dir(source: &Path, dest: &Path) -> anyhow::Result<()> {
+    let source = source.to_owned();
+    let dest = dest.to_owned();
+    tokio::task::spawn_blocking(move || {
+        for entry in walkdir::WalkDir::new(&source) {
+            let entry = entry?;
+            let dest_path = dest.join(entry.path().strip_prefix(&source)?);
+
+            let file_type = entry.file_type();
+            if file_type.is_dir() {
+                std::fs::create_dir_all(&dest_path)?;
+            } else if file_type.is_symlink() {
+                let target = std::fs::read_link(entry.path())?;
+                crate::fs_utils::symlink_blocking(&target, &dest_path)?;
+            } else {
+                std::fs::copy(entry.path(), &dest_path)?;
+            }
+        }
+
+        anyhow::Ok(())
+    })
+    .await??;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use anyhow::Context as _;
+
+use crate::{Brioche, Hash, HashAlgorithm};
+
+const PGP_SIGNED_MESSAGE_HEADER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const PGP_SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumEntry {
+    pub file_name: String,
+    pub hash: Hash,
+}
+
+/// A checksum file with its entries, along with how its contents were
+/// signed (if at all).
+#[derive(Debug, Clone)]
+pub struct ChecksumFile {
+    pub entries: Vec<ChecksumEntry>,
+    pub signature: ChecksumSignature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumSignature {
+    Unsigned,
+
+    /// The file is a clearsigned PGP message. Only the signed contents are
+    /// parsed, the signature itself isn't verified.
+    UnverifiedPgp,
+
+    /// The file's contents were verified against a signify signature.
+    Verified,
+}
+
+/// Parse a checksum file, like `SHA256SUMS` or `SHA512SUMS`. Both the
+/// GNU coreutils format (`<hex>  <file>`) and the BSD format
+/// (`SHA256 (<file>) = <hex>`) are supported. For the GNU format, the
+/// algorithm is inferred from the length of each hash unless `algorithm`
+/// is set. Empty lines and `#` comments are skipped.
+pub fn parse_checksums(
+    contents: &str,
+    algorithm: Option<HashAlgorithm>,
+) -> anyhow::Result<Vec<ChecksumEntry>> {
+    let mut entries = vec![];
+    for (line_index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let entry = parse_bsd_line(line)
+            .or_else(|| parse_gnu_line(line, algorithm))
+            .with_context(|| format!("invalid checksum on line {}: {line:?}", line_index + 1))??;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+fn parse_gnu_line(
+    line: &str,
+    algorithm: Option<HashAlgorithm>,
+) -> Option<anyhow::Result<ChecksumEntry>> {
+    let (hex_value, file_name) = line.split_once(char::is_whitespace)?;
+
+    // Binary mode entries are marked with a `*` before the file name
+    let file_name = file_name.trim_start();
+    let file_name = file_name.strip_prefix('*').unwrap_or(file_name);
+
+    let value = hex::decode(hex_value).ok()?;
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => {
+            let algorithm = [
+                HashAlgorithm::Md5,
+                HashAlgorithm::Sha1,
+                HashAlgorithm::Sha256,
+                HashAlgorithm::Sha512,
+            ]
+            .into_iter()
+            .find(|algorithm| algorithm.output_len() == value.len());
+            match algorithm {
+                Some(algorithm) => algorithm,
+                None => {
+                    return Some(Err(anyhow::anyhow!(
+                        "could not determine hash algorithm for {}-byte hash",
+                        value.len()
+                    )));
+                }
+            }
+        }
+    };
+
+    Some(
+        Hash::from_value(algorithm, value).map(|hash| ChecksumEntry {
+            file_name: file_name.to_string(),
+            hash,
+        }),
+    )
+}
+
+fn parse_bsd_line(line: &str) -> Option<anyhow::Result<ChecksumEntry>> {
+    let (algorithm, rest) = line.split_once(" (")?;
+    let (file_name, hex_value) = rest.rsplit_once(") = ")?;
+
+    let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => HashAlgorithm::Md5,
+        "sha1" => HashAlgorithm::Sha1,
+        "sha256" => HashAlgorithm::Sha256,
+        "sha512" => HashAlgorithm::Sha512,
+        _ => {
+            return Some(Err(anyhow::anyhow!(
+                "unsupported hash algorithm {algorithm:?}"
+            )));
+        }
+    };
+    let value = match hex::decode(hex_value.trim()) {
+        Ok(value) => value,
+        Err(error) => return Some(Err(error.into())),
+    };
+
+    Some(
+        Hash::from_value(algorithm, value).map(|hash| ChecksumEntry {
+            file_name: file_name.to_string(),
+            hash,
+        }),
+    )
+}
+
+/// Read a checksum file, verifying it against a signify-style Ed25519
+/// signature if one is given (as used by OpenBSD's `signify` and
+/// `minisign -l`). `signature` is the contents of the signature file and
+/// `public_key` is the base64 public key (optionally with its untrusted
+/// comment line). Without a signature, a clearsigned PGP message is
+/// accepted, but its signature isn't verified.
+pub fn read_checksum_file(
+    contents: &str,
+    algorithm: Option<HashAlgorithm>,
+    signature: Option<(&str, &str)>,
+) -> anyhow::Result<ChecksumFile> {
+    if let Some((signature, public_key)) = signature {
+        verify_signify(contents.as_bytes(), signature, public_key)
+            .context("failed to verify checksum file signature")?;
+        let entries = parse_checksums(contents, algorithm)?;
+        return Ok(ChecksumFile {
+            entries,
+            signature: ChecksumSignature::Verified,
+        });
+    }
+
+    if let Some(signed) = contents
+        .trim_start()
+        .strip_prefix(PGP_SIGNED_MESSAGE_HEADER)
+    {
+        // Skip the armor headers (like `Hash: SHA256`), which end with an
+        // empty line, then take everything up to the signature
+        let (_, message) = signed
+            .split_once("\n\n")
+            .context("invalid PGP signed message")?;
+        let (message, _) = message
+            .split_once(PGP_SIGNATURE_HEADER)
+            .context("PGP signed message has no signature")?;
+
+        // Undo dash-escaping
+        let message = message
+            .lines()
+            .map(|line| line.strip_prefix("- ").unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let entries = parse_checksums(&message, algorithm)?;
+        return Ok(ChecksumFile {
+            entries,
+            signature: ChecksumSignature::UnverifiedPgp,
+        });
+    }
+
+    let entries = parse_checksums(contents, algorithm)?;
+    Ok(ChecksumFile {
+        entries,
+        signature: ChecksumSignature::Unsigned,
+    })
+}
+
+/// Verify a signify signature over `message`. Signatures and public keys
+/// are base64-encoded, starting with the algorithm (`Ed`) and an 8-byte
+/// key number, which must match between the two.
+fn verify_signify(message: &[u8], signature: &str, public_key: &str) -> anyhow::Result<()> {
+    let signature = decode_signify_line(signature).context("invalid signature")?;
+    let public_key = decode_signify_line(public_key).context("invalid public key")?;
+
+    anyhow::ensure!(
+        signature.len() == 2 + 8 + 64,
+        "invalid signature length (prehashed minisign signatures are not supported, sign with `minisign -l`)"
+    );
+    anyhow::ensure!(public_key.len() == 2 + 8 + 32, "invalid public key length");
+    anyhow::ensure!(
+        &signature[..2] == b"Ed" && &public_key[..2] == b"Ed",
+        "unsupported signature algorithm"
+    );
+    anyhow::ensure!(
+        signature[2..10] == public_key[2..10],
+        "signature was made with a different key"
+    );
+
+    let public_key =
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key[10..]);
+    public_key
+        .verify(message, &signature[10..])
+        .map_err(|_| anyhow::anyhow!("signature does not match"))?;
+
+    Ok(())
+}
+
+/// Decode the first line of a signify file that isn't a comment.
+fn decode_signify_line(contents: &str) -> anyhow::Result<Vec<u8>> {
+    let line = contents
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .context("no key or signature found")?;
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, line)?;
+    Ok(bytes)
+}
+
+/// Read a file from a URL or a local path.
+pub async fn read_url_or_path(brioche: &Brioche, source: &str) -> anyhow::Result<String> {
+    match url::Url::parse(source) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            let response = brioche
+                .download_client
+                .get(url)
+                .send()
+                .await
+                .with_context(|| format!("failed to fetch {source}"))?;
+            let response = response
+                .error_for_status()
+                .with_context(|| format!("failed to fetch {source}"))?;
+            let contents = response.text().await?;
+            Ok(contents)
+        }
+        _ => {
+            let contents = tokio::fs::read_to_string(source)
+                .await
+                .with_context(|| format!("failed to read {source}"))?;
+            Ok(contents)
+        }
+    }
+}
+
+/// The source code for pinning a download to a hash, in the form used by
+/// project scripts.
+pub fn download_source(url: &str, hash: &Hash) -> String {
+    let hash = match hash {
+        Hash::Sha256 { value } => format!("std.sha256Hash({:?})", hex::encode(value)),
+        hash => format!(
+            "{{ type: {:?}, value: {:?} }}",
+            hash.algorithm().to_string(),
+            hex::encode(hash.value())
+        ),
+    };
+    format!("std.download({{\n  url: {url:?},\n  hash: {hash},\n}})")
+}
@@ -0,0 +1,172 @@
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{project::ProjectHash, recipe::RecipeHash, Brioche};
+
+#[derive(Debug, Default, Clone)]
+pub struct CleanResults {
+    /// The number of project bakes removed (one per baked export).
+    pub num_project_bakes: u64,
+    /// The number of cached bake results removed.
+    pub num_bakes: u64,
+    /// The number of materialized outputs removed from the `locals`
+    /// directory.
+    pub num_locals: u64,
+}
+
+/// Remove the cached evaluations, cached bakes, and materialized outputs
+/// associated with a project. Bakes that are also reachable from another project are kept.
+/// Blobs and recipes are never removed, since they may be shared with other
+/// projects. Materialized outputs of pinned artifacts are kept too.
+pub async fn clean_project(
+    brioche: &Brioche,
+    project_hash: ProjectHash,
+) -> anyhow::Result<CleanResults> {
+    brioche.ensure_writable("clean project")?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let project_hash_value = project_hash.to_string();
+
+    // Find every recipe baked by the project (directly or as a child bake)
+    // that wasn't also baked by another project
+    let clean_recipes = sqlx::query!(
+        r#"
+            WITH RECURSIVE
+                project_recipes (recipe_hash) AS (
+                    SELECT project_bakes.recipe_hash
+                    FROM project_bakes
+                    WHERE project_hash = ?
+                    UNION
+                    SELECT child_bakes.recipe_hash
+                    FROM child_bakes
+                    INNER JOIN project_recipes ON
+                        project_recipes.recipe_hash = child_bakes.parent_hash
+                ),
+                other_recipes (recipe_hash) AS (
+                    SELECT project_bakes.recipe_hash
+                    FROM project_bakes
+                    WHERE project_hash != ?
+                    UNION
+                    SELECT child_bakes.recipe_hash
+                    FROM child_bakes
+                    INNER JOIN other_recipes ON
+                        other_recipes.recipe_hash = child_bakes.parent_hash
+                )
+            SELECT recipe_hash AS "recipe_hash!"
+            FROM project_recipes
+            WHERE recipe_hash NOT IN (SELECT recipe_hash FROM other_recipes)
+        "#,
+        project_hash_value,
+        project_hash_value,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    let clean_recipes = clean_recipes
+        .into_iter()
+        .map(|record| record.recipe_hash)
+        .collect::<Vec<_>>();
+    let clean_recipes = serde_json::to_string(&clean_recipes)?;
+
+    // Find the outputs of those bakes that aren't also the output of a bake
+    // we're keeping
+    let output_hashes = sqlx::query!(
+        r#"
+            SELECT DISTINCT output_hash
+            FROM bakes
+            WHERE
+                input_hash IN (SELECT value FROM json_each(?))
+                AND output_hash NOT IN (
+                    SELECT output_hash
+                    FROM bakes
+                    WHERE input_hash NOT IN (SELECT value FROM json_each(?))
+                )
+                AND output_hash NOT IN (
+                    SELECT hash FROM pins WHERE kind = 'artifact'
+                )
+        "#,
+        clean_recipes,
+        clean_recipes,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    let output_hashes = output_hashes
+        .into_iter()
+        .map(|record| {
+            record
+                .output_hash
+                .parse::<RecipeHash>()
+                .context("invalid recipe hash from database")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let num_bakes = sqlx::query!(
+        r#"
+            DELETE FROM bakes
+            WHERE input_hash IN (SELECT value FROM json_each(?))
+        "#,
+        clean_recipes,
+    )
+    .execute(&mut *db_transaction)
+    .await?
+    .rows_affected();
+
+    sqlx::query!(
+        r#"
+            DELETE FROM child_bakes
+            WHERE parent_hash IN (SELECT value FROM json_each(?))
+        "#,
+        clean_recipes,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+
+    let num_project_bakes = sqlx::query!(
+        r#"
+            DELETE FROM project_bakes
+            WHERE project_hash = ?
+        "#,
+        project_hash_value,
+    )
+    .execute(&mut *db_transaction)
+    .await?
+    .rows_affected();
+
+    sqlx::query!(
+        r#"
+            DELETE FROM evaluation_cache
+            WHERE project_hash = ?
+        "#,
+        project_hash_value,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    // Remove the materialized outputs. Files within a local output are
+    // hardlinks, so removing them won't affect the blobs they link to
+    let local_dir = brioche.home.join("locals");
+    let mut num_locals = 0;
+    for output_hash in output_hashes {
+        let local_path = local_dir.join(output_hash.to_string());
+        let local_resource_dir = local_dir.join(format!("{output_hash}-resources.d"));
+
+        for path in [&local_path, &local_resource_dir] {
+            crate::fs_utils::set_directory_rwx_recursive(path).await?;
+        }
+
+        if crate::fs_utils::try_remove(&local_path).await? {
+            num_locals += 1;
+        }
+        crate::fs_utils::try_remove(&local_resource_dir).await?;
+    }
+
+    Ok(CleanResults {
+        num_project_bakes,
+        num_bakes,
+        num_locals,
+    })
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+
+use crate::{
+    error_codes::ErrorCode, output::OutputPermissions, project::Projects, recipe::RecipeHash,
+    Brioche,
+};
+
+pub mod editor;
+
+/// Get the path of the daemon's socket. The socket goes in the runtime
+/// directory when there is one, so it's cleaned up on logout, or in the
+/// default data directory otherwise.
+pub fn socket_path() -> anyhow::Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("dev", "brioche", "brioche")
+        .context("failed to get Brioche directories (is $HOME set?)")?;
+    let dir = dirs.runtime_dir().unwrap_or_else(|| dirs.data_local_dir());
+    Ok(dir.join("daemon.sock"))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DaemonOptions {
+    /// Prefetch updates for the registry dependencies of projects built by
+    /// the daemon once it has been idle for this long. Disabled if unset.
+    pub prefetch_updates_after: Option<std::time::Duration>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DaemonRequest {
+    Build(DaemonBuildRequest),
+}
+
+/// A request to build a local project. Paths must be absolute, since the
+/// daemon doesn't share the client's working directory.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonBuildRequest {
+    pub project_path: PathBuf,
+    pub export: String,
+    pub output: Option<PathBuf>,
+    pub replace: bool,
+    pub merge: bool,
+    pub output_permissions: OutputPermissions,
+    #[serde(default)]
+    pub link_strategy: crate::output::LinkStrategy,
+    /// The client's environment, which variables declared in the project's
+    /// `brioche.toml` are read from. Uses the daemon's own environment if
+    /// unset.
+    #[serde(default)]
+    pub env: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DaemonResponse {
+    BuildFinished {
+        artifact_hash: RecipeHash,
+        num_jobs: usize,
+        elapsed: std::time::Duration,
+    },
+    Failed {
+        message: String,
+        code: Option<ErrorCode>,
+    },
+}
+
+/// Run the daemon, handling requests from the socket until the process is
+/// stopped. Each connection is handled concurrently, but builds are run
+/// one at a time. Each build reloads the project from disk, so edits
+/// between builds are picked up. What stays warm between builds is
+/// everything that doesn't depend on the project's source: the database
+/// connection, cached recipes and bakes, and the script runtime.
+pub async fn serve(
+    brioche: &Brioche,
+    socket_path: &Path,
+    options: &DaemonOptions,
+) -> anyhow::Result<()> {
+    let listener = bind(socket_path).await?;
+    tracing::info!(socket_path = %socket_path.display(), "daemon listening");
+
+    // Evaluating scripts isn't `Send`, so connections are handled as local
+    // tasks on this thread
+    let local_set = tokio::task::LocalSet::new();
+    let state = Arc::new(DaemonState::new());
+    let accept_connections = async {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            if let Err(error) = check_peer(&stream) {
+                tracing::warn!("rejected daemon connection: {error:#}");
+                continue;
+            }
+
+            let brioche = brioche.clone();
+            let state = state.clone();
+            tokio::task::spawn_local(async move {
+                let result = handle_connection(&brioche, stream, &state).await;
+                if let Err(error) = result {
+                    tracing::warn!("failed to handle daemon connection: {error:#}");
+                }
+            });
+        }
+    };
+
+    local_set
+        .run_until(async {
+            tokio::select! {
+                result = accept_connections => result,
+                never = prefetch_updates_while_idle(brioche, options, &state) => match never {},
+            }
+        })
+        .await
+}
+
+/// State shared between the daemon's connections.
+struct DaemonState {
+    /// Held while running a build. Builds share the reporter's job counts
+    /// and can write to the same output paths, so they run one at a time.
+    build_lock: tokio::sync::Mutex<()>,
+
+    /// The number of builds that are running or waiting for the build
+    /// lock. The daemon is idle when this is zero.
+    active_builds: tokio::sync::watch::Sender<usize>,
+
+    built_project_paths: std::sync::Mutex<BTreeSet<PathBuf>>,
+}
+
+impl DaemonState {
+    fn new() -> Self {
+        let (active_builds, _) = tokio::sync::watch::channel(0);
+        Self {
+            build_lock: tokio::sync::Mutex::new(()),
+            active_builds,
+            built_project_paths: std::sync::Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    async fn build(
+        &self,
+        brioche: &Brioche,
+        request: &DaemonBuildRequest,
+    ) -> anyhow::Result<DaemonResponse> {
+        self.active_builds.send_modify(|active| *active += 1);
+        let _active_build = ActiveBuild {
+            active_builds: &self.active_builds,
+        };
+
+        let _build_lock = self.build_lock.lock().await;
+        let response = build(brioche, request).await?;
+
+        let mut built_project_paths = self
+            .built_project_paths
+            .lock()
+            .expect("built project paths poisoned");
+        built_project_paths.insert(request.project_path.clone());
+
+        Ok(response)
+    }
+}
+
+async fn bind(socket_path: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    if tokio::fs::try_exists(socket_path).await? {
+        // Only replace the socket if nothing is listening on it anymore
+        anyhow::ensure!(
+            tokio::net::UnixStream::connect(socket_path).await.is_err(),
+            "a daemon is already running at {}",
+            socket_path.display()
+        );
+        tokio::fs::remove_file(socket_path).await?;
+    }
+
+    if let Some(parent) = socket_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(socket_path)
+        .with_context(|| format!("failed to bind socket {}", socket_path.display()))?;
+
+    // Builds run with the daemon's permissions, so only the same user
+    // should be able to connect. Connections are also checked in
+    // `check_peer`, since the socket is briefly accessible before this
+    tokio::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+        .await
+        .with_context(|| {
+            format!(
+                "failed to set permissions of socket {}",
+                socket_path.display()
+            )
+        })?;
+
+    Ok(listener)
+}
+
+/// Only accept connections from processes running as the same user as the
+/// daemon.
+fn check_peer(stream: &tokio::net::UnixStream) -> anyhow::Result<()> {
+    let peer_uid = stream
+        .peer_cred()
+        .context("failed to get peer credentials")?
+        .uid();
+    let daemon_uid = nix::unistd::getuid().as_raw();
+    anyhow::ensure!(
+        peer_uid == daemon_uid,
+        "connection from uid {peer_uid} doesn't match daemon uid {daemon_uid}"
+    );
+    Ok(())
+}
+
+struct ActiveBuild<'a> {
+    active_builds: &'a tokio::sync::watch::Sender<usize>,
+}
+
+impl Drop for ActiveBuild<'_> {
+    fn drop(&mut self) {
+        self.active_builds.send_modify(|active| *active -= 1);
+    }
+}
+
+async fn handle_connection(
+    brioche: &Brioche,
+    stream: tokio::net::UnixStream,
+    state: &DaemonState,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let message = serde_json::from_str::<serde_json::Value>(&line);
+        let response = match message {
+            Ok(message) if editor::is_rpc_message(&message) => {
+                editor::handle_rpc_message(brioche, message).await
+            }
+            Ok(message) => {
+                let request: DaemonRequest = serde_json::from_value(message)?;
+
+                let response = match request {
+                    DaemonRequest::Build(request) => {
+                        // The request isn't logged in full, since the
+                        // client's environment can hold secrets
+                        tracing::debug!(project_path = %request.project_path.display(), export = request.export, "received daemon build request");
+                        state.build(brioche, &request).await
+                    }
+                };
+                let response = response.unwrap_or_else(|error| DaemonResponse::Failed {
+                    message: format!("{error:?}"),
+                    code: crate::error_codes::error_code(&error),
+                });
+                serde_json::to_value(&response)?
+            }
+            Err(error) => editor::parse_error_response(&error),
+        };
+
+        let mut response = serde_json::to_string(&response)?;
+        response.push('\n');
+        writer.write_all(response.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+async fn build(brioche: &Brioche, request: &DaemonBuildRequest) -> anyhow::Result<DaemonResponse> {
+    anyhow::ensure!(
+        request.project_path.is_absolute(),
+        "project path must be absolute"
+    );
+
+    let start = std::time::Instant::now();
+    let start_num_jobs = brioche.reporter.num_jobs();
+
+    let (mut brioche, projects) = fresh_session(brioche);
+    if let Some(env) = &request.env {
+        brioche.project_env = Some(Arc::new(env.clone()));
+    }
+
+    let project_hash = projects.load(&brioche, &request.project_path, true).await?;
+
+    let num_lockfiles_updated = projects.commit_dirty_lockfiles().await?;
+    if num_lockfiles_updated > 0 {
+        tracing::info!(num_lockfiles_updated, "updated lockfiles");
+    }
+
+    let recipe =
+        crate::script::evaluate::evaluate(&brioche, &projects, project_hash, &request.export)
+            .await?;
+    let artifact = crate::history::bake_and_record(
+        &brioche,
+        recipe,
+        &crate::bake::BakeScope::Project {
+            project_hash,
+            export: request.export.clone(),
+        },
+    )
+    .await?;
+
+    let license_policy = crate::licenses::project_license_policy(&projects, project_hash).await?;
+    if let Some(license_policy) = &license_policy {
+        let report = crate::licenses::scan_licenses(
+            &brioche,
+            &projects,
+            project_hash,
+            &artifact.value,
+            Some(license_policy),
+        )
+        .await?;
+        for entry in report.warnings() {
+            tracing::warn!("license policy warning: {entry}");
+        }
+        report.ensure_allowed()?;
+    }
+
+    if let Some(output) = &request.output {
+        anyhow::ensure!(output.is_absolute(), "output path must be absolute");
+
+        let output_options = crate::output::OutputOptions {
+            output_path: output,
+            merge: false,
+            resource_dir: None,
+            mtime: Some(std::time::SystemTime::now()),
+            link_locals: false,
+            link_strategy: request.link_strategy,
+            permissions: request.output_permissions,
+        };
+        if request.merge && !request.replace {
+            crate::output::create_output(
+                &brioche,
+                &artifact.value,
+                crate::output::OutputOptions {
+                    merge: true,
+                    ..output_options
+                },
+            )
+            .await?;
+        } else {
+            crate::output::create_output_atomic(
+                &brioche,
+                &artifact.value,
+                output_options,
+                request.replace,
+            )
+            .await?;
+        }
+    }
+
+    Ok(DaemonResponse::BuildFinished {
+        artifact_hash: artifact.value.hash(),
+        num_jobs: brioche.reporter.num_jobs() - start_num_jobs,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Wait until the daemon has been idle for the configured time, then
+/// prefetch updates for every project built so far, repeating for as long
+/// as the daemon stays idle. Prefetching stops as soon as a build starts,
+/// so it never holds up a build.
+async fn prefetch_updates_while_idle(
+    brioche: &Brioche,
+    options: &DaemonOptions,
+    state: &DaemonState,
+) -> std::convert::Infallible {
+    let Some(idle_time) = options.prefetch_updates_after else {
+        return std::future::pending().await;
+    };
+
+    let mut active_builds = state.active_builds.subscribe();
+    loop {
+        // Start the idle timer over whenever a build starts
+        let _ = active_builds.wait_for(|active| *active == 0).await;
+        let build_started = tokio::select! {
+            _ = tokio::time::sleep(idle_time) => false,
+            _ = active_builds.wait_for(|active| *active > 0) => true,
+        };
+        if build_started {
+            continue;
+        }
+
+        let project_paths = state
+            .built_project_paths
+            .lock()
+            .expect("built project paths poisoned")
+            .clone();
+        tokio::select! {
+            _ = prefetch_updates(brioche, &project_paths) => {}
+            _ = active_builds.wait_for(|active| *active > 0) => {}
+        }
+    }
+}
+
+async fn prefetch_updates(brioche: &Brioche, project_paths: &BTreeSet<PathBuf>) {
+    for project_path in project_paths {
+        let (brioche, projects) = fresh_session(brioche);
+        let result = crate::registry::transfer::with_priority(
+            crate::registry::transfer::TransferPriority::Idle,
+            async {
+                let project_hash = projects.load(&brioche, project_path, false).await?;
+                crate::outdated::prefetch_updates(&brioche, &projects, project_hash).await
+            },
+        )
+        .await;
+        match result {
+            Ok(num_fetched) => {
+                tracing::info!(project_path = %project_path.display(), num_fetched, "prefetched updates");
+            }
+            Err(error) => {
+                tracing::warn!(project_path = %project_path.display(), "failed to prefetch updates: {error:#}");
+            }
+        }
+    }
+}
+
+/// Use a fresh VFS and set of projects for each request, since the
+/// immutable VFS never notices when a file changes on disk.
+fn fresh_session(brioche: &Brioche) -> (Brioche, Projects) {
+    let mut brioche = brioche.clone();
+    brioche.vfs = crate::vfs::Vfs::immutable();
+    (brioche, Projects::default())
+}
+
+/// A connection to a running daemon.
+pub struct DaemonClient {
+    lines: tokio::io::Lines<tokio::io::BufReader<tokio::net::unix::OwnedReadHalf>>,
+    writer: tokio::net::unix::OwnedWriteHalf,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon, or return `None` if no daemon is running.
+    pub async fn connect(socket_path: &Path) -> Option<Self> {
+        let stream = tokio::net::UnixStream::connect(socket_path).await.ok()?;
+        let (reader, writer) = stream.into_split();
+        Some(Self {
+            lines: tokio::io::BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    pub async fn request(&mut self, request: &DaemonRequest) -> anyhow::Result<DaemonResponse> {
+        let mut request = serde_json::to_string(request)?;
+        request.push('\n');
+        self.writer.write_all(request.as_bytes()).await?;
+
+        let response = self
+            .lines
+            .next_line()
+            .await?
+            .context("daemon closed the connection")?;
+        let response = serde_json::from_str(&response)?;
+        Ok(response)
+    }
+}
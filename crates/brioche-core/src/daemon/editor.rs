@@ -0,0 +1,333 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::Acquire as _;
+
+use crate::{
+    error_codes::ErrorCode,
+    recipe::{Artifact, Recipe, RecipeHash},
+    Brioche,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, serde::Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: serde_json::Value,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<RpcErrorData>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcErrorData {
+    error_code: ErrorCode,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn from_error(error: &anyhow::Error) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: format!("{error:#}"),
+            data: crate::error_codes::error_code(error)
+                .map(|error_code| RpcErrorData { error_code }),
+        }
+    }
+}
+
+/// Returns true if a message from the socket is a JSON-RPC message rather
+/// than a daemon request.
+pub(super) fn is_rpc_message(message: &serde_json::Value) -> bool {
+    message.get("jsonrpc").is_some()
+}
+
+/// Handle a JSON-RPC 2.0 message from an editor plugin, returning the
+/// response to send back. Editors use this to show what each export
+/// resolves to, and whether it's already baked, as inline decorations.
+///
+/// Supported methods:
+/// - `brioche/resolveExport` with `{ projectPath, export }`: evaluate an
+///   export, returning its recipe hash, bake status, and a preview of the
+///   recipe.
+/// - `brioche/exportStatus` with `{ projectPath, exports? }`: the recipe
+///   hash and bake status of each export, defaulting to the named exports
+///   of the project's root module.
+pub(super) async fn handle_rpc_message(
+    brioche: &Brioche,
+    message: serde_json::Value,
+) -> serde_json::Value {
+    let id = message
+        .get("id")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+    let result = match serde_json::from_value::<RpcRequest>(message) {
+        Ok(request) if request.jsonrpc == "2.0" => {
+            handle_rpc_request(brioche, &request.method, request.params).await
+        }
+        Ok(_) => Err(RpcError::new(
+            INVALID_REQUEST,
+            "unsupported JSON-RPC version",
+        )),
+        Err(error) => Err(RpcError::new(INVALID_REQUEST, error.to_string())),
+    };
+
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = RpcResponse {
+        jsonrpc: "2.0",
+        id,
+        result,
+        error,
+    };
+    serde_json::to_value(response).expect("failed to serialize JSON-RPC response")
+}
+
+/// Build the response for a line that isn't valid JSON.
+pub(super) fn parse_error_response(error: &serde_json::Error) -> serde_json::Value {
+    let response = RpcResponse {
+        jsonrpc: "2.0",
+        id: serde_json::Value::Null,
+        result: None,
+        error: Some(RpcError::new(PARSE_ERROR, error.to_string())),
+    };
+    serde_json::to_value(response).expect("failed to serialize JSON-RPC response")
+}
+
+async fn handle_rpc_request(
+    brioche: &Brioche,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, RpcError> {
+    match method {
+        "brioche/resolveExport" => {
+            let params: ResolveExportParams = parse_params(params)?;
+            ensure_absolute(&params.project_path)?;
+            let result = resolve_export(brioche, &params.project_path, &params.export)
+                .await
+                .map_err(|error| RpcError::from_error(&error))?;
+            Ok(serde_json::to_value(result).expect("failed to serialize result"))
+        }
+        "brioche/exportStatus" => {
+            let params: ExportStatusParams = parse_params(params)?;
+            ensure_absolute(&params.project_path)?;
+            let result = export_status(brioche, &params.project_path, params.exports)
+                .await
+                .map_err(|error| RpcError::from_error(&error))?;
+            Ok(serde_json::to_value(result).expect("failed to serialize result"))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {method:?}"),
+        )),
+    }
+}
+
+fn parse_params<T>(params: serde_json::Value) -> Result<T, RpcError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_json::from_value(params).map_err(|error| RpcError::new(INVALID_PARAMS, error.to_string()))
+}
+
+fn ensure_absolute(path: &Path) -> Result<(), RpcError> {
+    if path.is_absolute() {
+        Ok(())
+    } else {
+        Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("path must be absolute: {}", path.display()),
+        ))
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolveExportParams {
+    project_path: PathBuf,
+    export: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportStatusParams {
+    project_path: PathBuf,
+
+    /// The exports to check. Defaults to the named exports of the
+    /// project's root module.
+    #[serde(default)]
+    exports: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BakeStatus {
+    /// The export's recipe has been baked, so building it is a cache hit.
+    Baked,
+
+    /// The export's recipe hasn't been baked locally yet.
+    NotBaked,
+}
+
+/// What an export resolves to, for previewing in an editor.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResolution {
+    pub export: String,
+    pub recipe_hash: RecipeHash,
+    pub status: BakeStatus,
+    pub artifact_hash: Option<RecipeHash>,
+
+    /// The recipe, rendered as with `brioche build --dry-run`.
+    pub preview: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportStatus {
+    pub export: String,
+    pub recipe_hash: Option<RecipeHash>,
+    pub status: Option<BakeStatus>,
+    pub artifact_hash: Option<RecipeHash>,
+
+    /// Set if the export couldn't be evaluated.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportStatusResult {
+    pub exports: Vec<ExportStatus>,
+}
+
+async fn resolve_export(
+    brioche: &Brioche,
+    project_path: &Path,
+    export: &str,
+) -> anyhow::Result<ExportResolution> {
+    let (brioche, projects) = super::fresh_session(brioche);
+    let project_hash = projects.load(&brioche, project_path, true).await?;
+
+    let recipe =
+        crate::script::evaluate::evaluate(&brioche, &projects, project_hash, export).await?;
+    let (status, artifact_hash) = bake_status(&brioche, &recipe.value).await?;
+    let preview = crate::recipe::pretty::render_recipe(&brioche, &recipe.value).await?;
+
+    Ok(ExportResolution {
+        export: export.to_string(),
+        recipe_hash: recipe.hash(),
+        status,
+        artifact_hash,
+        preview,
+    })
+}
+
+async fn export_status(
+    brioche: &Brioche,
+    project_path: &Path,
+    exports: Option<Vec<String>>,
+) -> anyhow::Result<ExportStatusResult> {
+    let (brioche, projects) = super::fresh_session(brioche);
+    let project_hash = projects.load(&brioche, project_path, true).await?;
+
+    let exports = match exports {
+        Some(exports) => exports,
+        None => {
+            let root_module_path = projects.project_root_module_path(project_hash)?;
+            let root_module = tokio::fs::read_to_string(&root_module_path).await?;
+            crate::project::analyze::module_exports(&root_module)?
+        }
+    };
+
+    let mut statuses = vec![];
+    for export in exports {
+        let result = async {
+            let recipe =
+                crate::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+                    .await?;
+            let (status, artifact_hash) = bake_status(&brioche, &recipe.value).await?;
+            anyhow::Ok((recipe.hash(), status, artifact_hash))
+        }
+        .await;
+
+        let status = match result {
+            Ok((recipe_hash, status, artifact_hash)) => ExportStatus {
+                export,
+                recipe_hash: Some(recipe_hash),
+                status: Some(status),
+                artifact_hash,
+                error: None,
+            },
+            Err(error) => ExportStatus {
+                export,
+                recipe_hash: None,
+                status: None,
+                artifact_hash: None,
+                error: Some(format!("{error:#}")),
+            },
+        };
+        statuses.push(status);
+    }
+
+    Ok(ExportStatusResult { exports: statuses })
+}
+
+async fn bake_status(
+    brioche: &Brioche,
+    recipe: &Recipe,
+) -> anyhow::Result<(BakeStatus, Option<RecipeHash>)> {
+    // Artifacts are already baked
+    if let Ok(artifact) = Artifact::try_from(recipe.clone()) {
+        return Ok((BakeStatus::Baked, Some(artifact.hash())));
+    }
+
+    let input_hash = recipe.hash().to_string();
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let output_hash = sqlx::query_scalar!(
+        "SELECT output_hash FROM bakes WHERE input_hash = ? LIMIT 1",
+        input_hash,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    match output_hash {
+        Some(output_hash) => Ok((BakeStatus::Baked, Some(output_hash.parse()?))),
+        None => Ok((BakeStatus::NotBaked, None)),
+    }
+}
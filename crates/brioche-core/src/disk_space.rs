@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use anyhow::Context as _;
+use human_repr::HumanCount as _;
+use sqlx::Acquire as _;
+
+use crate::{recipe::CompleteProcessRecipe, Brioche};
+
+/// Space to keep free on top of the estimated size of an operation, so
+/// other writes happening at the same time (the database, temp files,
+/// other jobs) don't run out of space either.
+const MIN_HEADROOM: u64 = 256 * 1024 * 1024;
+
+/// Returned when there isn't enough disk space to safely start an
+/// operation.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "not enough disk space for {purpose}: needs about {} (plus {} of headroom), but only {} is available at {}",
+    .required.human_count_bytes(),
+    MIN_HEADROOM.human_count_bytes(),
+    .available.human_count_bytes(),
+    .path.display(),
+)]
+pub struct InsufficientSpaceError {
+    pub purpose: String,
+    pub path: std::path::PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// Get the space available to unprivileged users on the filesystem
+/// containing `path`. If `path` doesn't exist yet, its closest existing
+/// ancestor is used instead.
+pub fn available_space(path: &Path) -> anyhow::Result<u64> {
+    let existing_path = path
+        .ancestors()
+        .find(|path| path.exists())
+        .with_context(|| format!("no existing ancestor of {}", path.display()))?;
+    let stat = nix::sys::statvfs::statvfs(existing_path)
+        .with_context(|| format!("failed to get filesystem stats for {}", path.display()))?;
+
+    let blocks_available = u64::from(stat.blocks_available());
+    let fragment_size = u64::from(stat.fragment_size());
+    Ok(blocks_available.saturating_mul(fragment_size))
+}
+
+/// Fail early if the filesystem containing `path` doesn't have room for
+/// `required` bytes plus some headroom, rather than failing partway
+/// through with `ENOSPC`.
+pub async fn ensure_available_space(
+    path: &Path,
+    required: u64,
+    purpose: &str,
+) -> anyhow::Result<()> {
+    let path = path.to_owned();
+    let available = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || available_space(&path)).await??
+    };
+
+    if available < required.saturating_add(MIN_HEADROOM) {
+        return Err(InsufficientSpaceError {
+            purpose: purpose.to_string(),
+            path,
+            required,
+            available,
+        }
+        .into());
+    }
+
+    tracing::debug!(path = %path.display(), required, available, "disk space check passed");
+    Ok(())
+}
+
+/// Get the total size of the files at `path`, without following symlinks.
+pub async fn disk_usage(path: &Path) -> anyhow::Result<u64> {
+    let path = path.to_owned();
+    let size = tokio::task::spawn_blocking(move || {
+        let mut size = 0u64;
+        for entry in walkdir::WalkDir::new(&path) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                size = size.saturating_add(entry.metadata()?.len());
+            }
+        }
+        anyhow::Ok(size)
+    })
+    .await??;
+    Ok(size)
+}
+
+/// Estimate the size of a process's output from previous runs of an
+/// equivalent process. See [`record_process_output_size`].
+pub async fn estimated_process_output_size(
+    brioche: &Brioche,
+    process: &CompleteProcessRecipe,
+) -> anyhow::Result<Option<u64>> {
+    let structure_key = process_structure_key(process);
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let output_size = sqlx::query_scalar!(
+        r#"
+            SELECT output_size
+            FROM process_output_sizes
+            WHERE structure_key = ?
+        "#,
+        structure_key,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(output_size.map(|size| size.try_into().unwrap_or(0)))
+}
+
+/// Record the size of a process's output, to estimate the space needed
+/// the next time an equivalent process runs. Processes are considered
+/// equivalent if they only differ by their input artifacts, so rebuilding
+/// a package after one of its dependencies changed uses the size of the
+/// last build.
+pub async fn record_process_output_size(
+    brioche: &Brioche,
+    process: &CompleteProcessRecipe,
+    output_size: u64,
+) -> anyhow::Result<()> {
+    let structure_key = process_structure_key(process);
+    let output_size = i64::try_from(output_size).unwrap_or(i64::MAX);
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO process_output_sizes (structure_key, output_size)
+            VALUES (?, ?)
+            ON CONFLICT (structure_key) DO UPDATE SET
+                output_size = excluded.output_size,
+                created_at = CURRENT_TIMESTAMP
+        "#,
+        structure_key,
+        output_size,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+fn process_structure_key(process: &CompleteProcessRecipe) -> String {
+    let report = crate::bake::process_inputs::complete_process_inputs(process.clone());
+    report.structure_key()
+}
@@ -0,0 +1,413 @@
+use std::str::FromStr;
+
+/// A stable code identifying a kind of error, written like `BRI0001`.
+/// Each code has a longer explanation, shown by `brioche explain`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde_with::SerializeDisplay,
+    serde_with::DeserializeFromStr,
+)]
+pub struct ErrorCode(u16);
+
+impl ErrorCode {
+    pub const HASH_MISMATCH: Self = Self(1);
+    pub const INSUFFICIENT_DISK_SPACE: Self = Self(2);
+    pub const UNSUPPORTED_API_VERSION: Self = Self(3);
+    pub const NETWORKING_REQUIRES_UNSAFE: Self = Self(4);
+    pub const UNNECESSARY_UNSAFE: Self = Self(5);
+    pub const PROCESS_FAILED: Self = Self(6);
+    pub const EMULATOR_NOT_FOUND: Self = Self(7);
+    pub const LOCKFILE_OUT_OF_DATE: Self = Self(8);
+    pub const OUTPUT_HASH_MISMATCH: Self = Self(9);
+    pub const SANDBOX_FAILED: Self = Self(10);
+    pub const DEPENDENCY_RESOLUTION_FAILED: Self = Self(11);
+    pub const REGISTRY_PERMISSION_DENIED: Self = Self(12);
+    pub const SECRETS_REQUIRE_UNSAFE: Self = Self(13);
+    pub const SECRET_NOT_CONFIGURED: Self = Self(14);
+    pub const LICENSE_POLICY_VIOLATION: Self = Self(15);
+    pub const READ_ONLY_STORE: Self = Self(16);
+    pub const ENVIRONMENT_COLLISION: Self = Self(17);
+    pub const NETWORK_HOST_NOT_ALLOWED: Self = Self(18);
+    pub const CORRUPT_BLOB: Self = Self(19);
+
+    pub fn explanation(self) -> Option<&'static ErrorExplanation> {
+        EXPLANATIONS
+            .iter()
+            .find(|explanation| explanation.code == self)
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BRI{:04}", self.0)
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = anyhow::Error;
+
+    /// Parse a code like `BRI0001`. The prefix is case-insensitive, and
+    /// can be left out (e.g. `0001` or `1`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = match s.get(..3) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("BRI") => &s[3..],
+            _ => s,
+        };
+        let number = number
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid error code: {s:?}"))?;
+        Ok(Self(number))
+    }
+}
+
+#[derive(Debug)]
+pub struct ErrorExplanation {
+    pub code: ErrorCode,
+    pub title: &'static str,
+    pub description: &'static str,
+
+    /// Short suggestions for fixing the error, most common first.
+    pub fixes: &'static [&'static str],
+}
+
+/// The catalog of error explanations, ordered by code.
+pub const EXPLANATIONS: &[ErrorExplanation] = &[
+    ErrorExplanation {
+        code: ErrorCode::HASH_MISMATCH,
+        title: "Downloaded content doesn't match its expected hash",
+        description: "\
+A download (or other content with an expected hash) didn't match the hash
+given for it. Usually, this means the file at the URL changed since the hash
+was recorded, such as when a release is re-uploaded. Less often, it means
+the content was corrupted or tampered with in transit.
+
+The mismatched content is kept in the quarantine directory (the path is
+shown in the error), so it can be inspected before deciding what to do.",
+        fixes: &[
+            "If the new content is expected, update the hash in the project. \
+             `brioche build --update-hashes` does this for downloads",
+            "Otherwise, don't use the new content until the source of the \
+             change is known",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::INSUFFICIENT_DISK_SPACE,
+        title: "Not enough disk space",
+        description: "\
+There isn't enough free disk space to safely start an operation, such as
+fetching a large blob or running a process. Brioche checks for some extra
+headroom on top of what the operation needs, so other writes happening at
+the same time don't run out of space either.",
+        fixes: &[
+            "Free up space on the filesystem shown in the error",
+            "Run `brioche store-stats` to see how much space can be reclaimed \
+             from the Brioche store, and `brioche clean` to remove cached \
+             build results for a project",
+            "Set `process_scratch_dir` in the config file to run processes \
+             on a different disk",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::UNSUPPORTED_API_VERSION,
+        title: "Project requires an unsupported API version",
+        description: "\
+The project declares a Brioche API version that this version of Brioche
+can't run.",
+        fixes: &[
+            "If the version is newer than what's supported, update Brioche \
+             with `brioche self-update`",
+            "If it's older than what's supported, update the project to a \
+             newer API version",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::NETWORKING_REQUIRES_UNSAFE,
+        title: "Networking requires `unsafe`",
+        description: "\
+A process enabled networking, but wasn't marked as unsafe. Processes with
+network access can produce different results each time they run, which
+breaks the guarantee that a recipe always bakes to the same output.",
+        fixes: &[
+            "Set an output hash for the process, so its output is validated \
+             after it runs",
+            "Mark the process as `unsafe` to acknowledge that its output \
+             isn't reproducible",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::UNNECESSARY_UNSAFE,
+        title: "Process is marked `unsafe` unnecessarily",
+        description: "\
+A process was marked as `unsafe`, but doesn't use any unsafe features (such
+as networking without an output hash).",
+        fixes: &[
+            "Remove `unsafe` from the process, so it isn't mistaken for one \
+             whose output isn't reproducible",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::PROCESS_FAILED,
+        title: "Process failed",
+        description: "\
+A process run by a recipe exited with an error. The error lists the paths
+of the process's full stdout and stderr logs, which usually explain what
+went wrong.",
+        fixes: &[
+            "Read the stderr log, then fix the recipe or its inputs",
+            "Run `brioche replay` to run the failed process again in the \
+             same sandbox",
+            "Run `brioche inputs` to list everything the process depends on",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::EMULATOR_NOT_FOUND,
+        title: "No emulator for the process's platform",
+        description: "\
+A process targets a different platform from the current machine, but no
+emulator is available to run it.",
+        fixes: &[
+            "Register a QEMU user-mode emulator with `binfmt_misc` (many \
+             distributions package these as `qemu-user-static`)",
+            "Set an emulator for the platform under `emulators` in the \
+             config file",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::LOCKFILE_OUT_OF_DATE,
+        title: "Lockfile is out of date",
+        description: "\
+A project fetched from the registry has a lockfile that doesn't match its
+dependencies. Brioche updates lockfiles for local projects automatically,
+but registry projects are used exactly as they were published.",
+        fixes: &["If you maintain the project, run `brioche check` on it to \
+             update its lockfile, then publish it again with \
+             `brioche publish`"],
+    },
+    ErrorExplanation {
+        code: ErrorCode::OUTPUT_HASH_MISMATCH,
+        title: "Process output doesn't match its output hash",
+        description: "\
+A process with an output hash produced a different output. Output hashes
+are usually set for processes that use the network, so a change often
+means something fetched by the process changed upstream. It can also mean
+the process isn't deterministic, such as when it records timestamps.",
+        fixes: &[
+            "If the new output is expected, update the output hash to the \
+             one shown in the error",
+            "Run `brioche replay` to run the process again and compare its \
+             output, to check whether it's deterministic",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::SANDBOX_FAILED,
+        title: "Failed to set up the process sandbox",
+        description: "\
+Brioche couldn't set up the sandbox for a process, so the process never
+started. Processes run in unprivileged user namespaces, which some systems
+disable or restrict.",
+        fixes: &[
+            "Check that unprivileged user namespaces are enabled (e.g. \
+             `sysctl kernel.unprivileged_userns_clone` should be 1, and \
+             AppArmor shouldn't restrict them)",
+            "If Brioche runs in a container, allow it to create user \
+             namespaces (for Docker, run it with `--privileged` or a \
+             seccomp profile that allows `unshare`)",
+            "If `sandbox_user.subordinate_ids` is set, check that \
+             `newuidmap` and `newgidmap` are installed",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::DEPENDENCY_RESOLUTION_FAILED,
+        title: "Failed to resolve dependencies",
+        description: "\
+One or more of the project's dependencies couldn't be loaded. The error
+lists each failed dependency with its cause. Path dependencies are loaded
+from the local filesystem, and other dependencies are looked up in the
+registry, then pinned in the lockfile.",
+        fixes: &[
+            "Check that path dependencies point to a directory with a \
+             `project.bri` file",
+            "Check the dependency's name for typos, and that it's been \
+             published to the registry",
+            "If the registry can't be reached, check the network connection \
+             or the `registry_url` in the config file",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::REGISTRY_PERMISSION_DENIED,
+        title: "Not allowed to publish to the registry",
+        description: "\
+The registry rejected publishing (or pruning) a project because the client
+isn't allowed to publish it. Scoped projects (like `@scope/name`) need a
+registry token for their scope.",
+        fixes: &[
+            "Set a token for the project's scope under `registry_tokens` in \
+             the config file",
+            "Check that the token has permission to publish to the scope",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::SECRETS_REQUIRE_UNSAFE,
+        title: "Secrets require an output hash or `unsafe`",
+        description: "\
+A process uses secrets, but has neither an output hash nor `unsafe` set.
+Secret values aren't part of a recipe's cache key, so the output of a
+process that uses them has to either be validated against an output hash,
+or be marked as unsafe to acknowledge that it depends on something outside
+the recipe. Results of unsafe processes that use secrets are never uploaded
+to the registry.",
+        fixes: &[
+            "Set an output hash for the process, such as when the secret is \
+             only used to download something",
+            "Mark the process as `unsafe` if its output depends on the secret \
+             (e.g. a signed artifact)",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::SECRET_NOT_CONFIGURED,
+        title: "Secret isn't configured",
+        description: "\
+A process uses a secret that Brioche doesn't know how to read. Secrets are
+configured per machine, so projects only refer to them by name.",
+        fixes: &[
+            "Add the secret under `secrets` in the config file, e.g. \
+             `npm_token = { env = \"NPM_TOKEN\" }` or \
+             `signing_key = { file = \"/path/to/key\" }`",
+            "If the secret is read from an environment variable, check that \
+             the variable is set for Brioche (or the daemon)",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::LICENSE_POLICY_VIOLATION,
+        title: "Build output includes a disallowed license",
+        description: "\
+The project's license policy (`brioche_licenses.toml`) doesn't allow a
+license used by the build. Licenses come from the `license` field of each
+project in the dependency graph, and from `license` annotations on artifacts
+within the output, such as annotated downloads.",
+        fixes: &[
+            "Run `brioche build --license-report <path>` to see where each \
+             license comes from",
+            "Replace the dependency or download that uses the license",
+            "If the license is acceptable, add it to `allow` in the policy \
+             file (or set `on_violation = \"warn\"`)",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::READ_ONLY_STORE,
+        title: "The Brioche store is read-only",
+        description: "\
+The Brioche store was opened read-only (with `read_only = true` in the config
+file), but something needed to save to it, such as baking a recipe that isn't
+cached, fetching a blob from the registry, or loading a project that isn't
+in the store yet. Only builds that are fully cached work with a read-only
+store.",
+        fixes: &[
+            "Build the project with a writable store first, then use that \
+             store as the read-only store",
+            "Check that the project and its lockfile match what was built \
+             into the store",
+            "Open the store normally by removing `read_only` from the config \
+             file",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::ENVIRONMENT_COLLISION,
+        title: "Packages in an environment collide",
+        description: "\
+Two or more packages merged into an environment provide different files,
+symlinks, or directories at the same path, and none of them has a higher
+priority than the others. Brioche doesn't pick one silently, since the
+environment would then depend on the order the packages were listed in. The
+error lists each colliding path along with the packages that provide it.",
+        fixes: &[
+            "Give the package that should win a higher priority",
+            "Remove the conflicting path from one of the packages before \
+             adding it to the environment",
+            "Set `ignoreCollisions` to let the earliest package win",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::NETWORK_HOST_NOT_ALLOWED,
+        title: "Host is not in the network allowlist",
+        description: "\
+A download, git checkout, or networked process tried to contact a host that
+isn't in the `network_allowlist` from the config file. When an allowlist is
+set, each entry allows a domain along with its subdomains, and every other
+host is blocked. Networked processes can only reach the network through a
+proxy (set with the `HTTP_PROXY` and `HTTPS_PROXY` environment variables),
+which rejects requests to other hosts.",
+        fixes: &[
+            "Add the host's domain to `network_allowlist` in the config file",
+            "Change the recipe to fetch from a host that's already allowed",
+        ],
+    },
+    ErrorExplanation {
+        code: ErrorCode::CORRUPT_BLOB,
+        title: "A blob in the store is corrupted",
+        description: "\
+While writing an output, a blob's contents didn't match its hash. Blobs are
+read-only, so this usually means the file in the store was changed by
+another program, or was damaged by a disk or filesystem problem. The output
+path that was being written is removed, rather than being left with the
+corrupted contents.",
+        fixes: &[
+            "Run `brioche fsck` to quarantine corrupted blobs, then build again \
+             to fetch or rebuild them",
+            "If blobs keep getting corrupted, check the disk for problems",
+        ],
+    },
+];
+
+/// An error with a code, for errors that don't have their own type. Can
+/// also be attached as context to another error.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Find the code for an error, from the outermost error or context that
+/// has one.
+pub fn error_code(error: &anyhow::Error) -> Option<ErrorCode> {
+    if let Some(error) = error.downcast_ref::<CodedError>() {
+        return Some(error.code);
+    }
+
+    error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<CodedError>() {
+            Some(error.code)
+        } else if let Some(error) = cause.downcast_ref::<crate::bake::BakeFailed>() {
+            error.code
+        } else if cause.is::<crate::blob::HashMismatchError>() {
+            Some(ErrorCode::HASH_MISMATCH)
+        } else if cause.is::<crate::blob::CorruptBlobError>() {
+            Some(ErrorCode::CORRUPT_BLOB)
+        } else if cause.is::<crate::disk_space::InsufficientSpaceError>() {
+            Some(ErrorCode::INSUFFICIENT_DISK_SPACE)
+        } else if cause.is::<crate::script::api_version::UnsupportedApiVersion>() {
+            Some(ErrorCode::UNSUPPORTED_API_VERSION)
+        } else if cause.is::<crate::registry::RegistryPermissionError>() {
+            Some(ErrorCode::REGISTRY_PERMISSION_DENIED)
+        } else {
+            None
+        }
+    })
+}
@@ -1,8 +1,12 @@
-use std::path::{Component, Path, PathBuf};
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
 
 use anyhow::Context as _;
-use bstr::ByteSlice as _;
 use relative_path::RelativePath;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
 
 pub fn logical_path(path: &Path) -> PathBuf {
     let mut components = vec![];
@@ -21,26 +25,6 @@ pub fn logical_path(path: &Path) -> PathBuf {
     PathBuf::from_iter(components)
 }
 
-pub fn logical_path_bytes(path: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let mut components = vec![];
-    for component in path.split_str("/") {
-        match component {
-            b"" | b"." => {}
-            b".." => {
-                let popped = components.pop();
-                if popped.is_none() {
-                    anyhow::bail!("path {:?} escapes top-level", bstr::BStr::new(path));
-                }
-            }
-            normal => {
-                components.push(normal);
-            }
-        }
-    }
-
-    Ok(bstr::join("/", components))
-}
-
 pub fn is_subpath(path: &RelativePath) -> bool {
     let mut depth: i32 = 0;
     for component in path.components() {
@@ -103,12 +87,28 @@ pub async fn move_file(source: &Path, dest: &Path) -> anyhow::Result<MoveType> {
 
             MoveType::Rename
         }
-        Err(_) => {
+        Err(error) => {
+            // Usually the source and destination are on different
+            // filesystems (e.g. when the process scratch directory is on a
+            // separate disk), so fall back to copying
+            if !is_cross_device_error(&error) {
+                tracing::debug!(source = %source.display(), dest = %dest.display(), %error, "failed to rename file, falling back to copying");
+            }
+
             let metadata = tokio::fs::symlink_metadata(source).await?;
             if metadata.is_dir() {
                 anyhow::bail!("cannot move directory across filesystems");
-            } else if metadata.is_file() || metadata.is_symlink() {
-                atomic_copy(source, dest).await?;
+            } else if metadata.is_symlink() {
+                let target = tokio::fs::read_link(source).await?;
+                let dest_temp = dest.with_extension(format!("tmp-{}", ulid::Ulid::new()));
+                symlink(&target, &dest_temp).await?;
+                tokio::fs::rename(&dest_temp, dest)
+                    .await
+                    .context("failed to rename temp symlink")?;
+                tokio::fs::remove_file(source).await?;
+                MoveType::Copy
+            } else if metadata.is_file() {
+                atomic_copy_with_mtime(source, dest, metadata.modified()?).await?;
                 tokio::fs::remove_file(source).await?;
                 MoveType::Copy
             } else {
@@ -122,7 +122,7 @@ pub async fn move_file(source: &Path, dest: &Path) -> anyhow::Result<MoveType> {
 
 pub async fn atomic_copy(source: &Path, dest: &Path) -> anyhow::Result<()> {
     let dest_temp = dest.with_extension(format!("tmp-{}", ulid::Ulid::new()));
-    tokio::fs::copy(source, &dest_temp)
+    reflink_or_copy(source, &dest_temp)
         .await
         .context("failed to copy file to temp")?;
     tokio::fs::rename(dest_temp, dest)
@@ -131,6 +131,341 @@ pub async fn atomic_copy(source: &Path, dest: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Like [`atomic_copy`], but also keeps the source file's modified time,
+/// which `std::fs::copy` doesn't preserve.
+async fn atomic_copy_with_mtime(
+    source: &Path,
+    dest: &Path,
+    mtime: std::time::SystemTime,
+) -> anyhow::Result<()> {
+    let dest_temp = dest.with_extension(format!("tmp-{}", ulid::Ulid::new()));
+    reflink_or_copy(source, &dest_temp)
+        .await
+        .context("failed to copy file to temp")?;
+    set_mtime(&dest_temp, mtime)
+        .await
+        .context("failed to set modified time of temp file")?;
+    tokio::fs::rename(dest_temp, dest)
+        .await
+        .context("failed to rename temp file")?;
+    Ok(())
+}
+
+/// Copy a file. On filesystems that support reflinks (like btrfs or XFS),
+/// the copy shares the source file's data until either file is changed,
+/// so even very large files are copied almost instantly. Otherwise, the
+/// contents are copied normally, which uses `copy_file_range` where
+/// available. Like `std::fs::copy`, the permissions are copied too. Returns
+/// whether the file was reflinked.
+pub async fn reflink_or_copy(source: &Path, dest: &Path) -> anyhow::Result<bool> {
+    let source = source.to_owned();
+    let dest = dest.to_owned();
+    let reflinked =
+        tokio::task::spawn_blocking(move || reflink_or_copy_blocking(&source, &dest)).await??;
+    Ok(reflinked)
+}
+
+/// Create `dest` as a reflink of `source`, without falling back to a copy.
+/// Returns `false` without creating `dest` if reflinks aren't supported.
+pub async fn try_reflink(source: &Path, dest: &Path) -> anyhow::Result<bool> {
+    let source = source.to_owned();
+    let dest = dest.to_owned();
+    let reflinked =
+        tokio::task::spawn_blocking(move || try_reflink_blocking(&source, &dest)).await??;
+    Ok(reflinked)
+}
+
+/// Blocking version of [`reflink_or_copy`].
+pub fn reflink_or_copy_blocking(source: &Path, dest: &Path) -> std::io::Result<bool> {
+    if try_reflink_blocking(source, dest)? {
+        tracing::trace!(source = %source.display(), dest = %dest.display(), "reflinked file");
+        return Ok(true);
+    }
+
+    std::fs::copy(source, dest)?;
+    Ok(false)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        mod ioctl {
+            nix::ioctl_write_int!(ficlone, 0x94, 9);
+        }
+
+        /// Try to create `dest` as a reflink of `source` with the `FICLONE`
+        /// ioctl. Returns `false` without creating `dest` if the filesystem
+        /// doesn't support reflinks, or if the files are on different
+        /// filesystems.
+        fn try_reflink_blocking(source: &Path, dest: &Path) -> std::io::Result<bool> {
+            use std::os::fd::AsRawFd as _;
+
+            use nix::errno::Errno;
+
+            let source_file = std::fs::File::open(source)?;
+            let dest_file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(dest)?;
+
+            // SAFETY: Both file descriptors are valid and open for the
+            // duration of the call
+            let result = unsafe {
+                ioctl::ficlone(
+                    dest_file.as_raw_fd(),
+                    source_file.as_raw_fd() as nix::sys::ioctl::ioctl_param_type,
+                )
+            };
+            match result {
+                Ok(_) => {
+                    dest_file.set_permissions(source_file.metadata()?.permissions())?;
+                    Ok(true)
+                }
+                Err(
+                    Errno::EOPNOTSUPP
+                    | Errno::EXDEV
+                    | Errno::EINVAL
+                    | Errno::ENOTTY
+                    | Errno::ENOSYS,
+                ) => {
+                    drop(dest_file);
+                    std::fs::remove_file(dest)?;
+                    Ok(false)
+                }
+                Err(error) => {
+                    drop(dest_file);
+                    let _ = std::fs::remove_file(dest);
+                    Err(error.into())
+                }
+            }
+        }
+    } else {
+        fn try_reflink_blocking(_source: &Path, _dest: &Path) -> std::io::Result<bool> {
+            Ok(false)
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        /// Reserve disk space for `length` bytes of a file without changing
+        /// its size, so writing a large file doesn't fragment it. Does
+        /// nothing if the filesystem doesn't support preallocating space.
+        pub fn preallocate(file: &std::fs::File, length: u64) -> anyhow::Result<()> {
+            use std::os::fd::AsRawFd as _;
+
+            use nix::{errno::Errno, fcntl::FallocateFlags};
+
+            let Ok(length) = i64::try_from(length) else {
+                return Ok(());
+            };
+            if length == 0 {
+                return Ok(());
+            }
+
+            let result = nix::fcntl::fallocate(
+                file.as_raw_fd(),
+                FallocateFlags::FALLOC_FL_KEEP_SIZE,
+                0,
+                length,
+            );
+            match result {
+                Ok(()) | Err(Errno::EOPNOTSUPP | Errno::ENOSYS) => Ok(()),
+                Err(error) => Err(error.into()),
+            }
+        }
+    } else {
+        pub fn preallocate(_file: &std::fs::File, _length: u64) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+/// Atomically swap the files or directories at `a` and `b`. Returns `false`
+/// without changing anything if the platform or filesystem can't exchange
+/// paths.
+pub async fn try_exchange(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    let a = a.to_owned();
+    let b = b.to_owned();
+    let exchanged = tokio::task::spawn_blocking(move || try_exchange_blocking(&a, &b)).await??;
+    Ok(exchanged)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", target_env = "gnu"))] {
+        fn try_exchange_blocking(a: &Path, b: &Path) -> std::io::Result<bool> {
+            use nix::{errno::Errno, fcntl::RenameFlags};
+
+            let result = nix::fcntl::renameat2(None, a, None, b, RenameFlags::RENAME_EXCHANGE);
+            match result {
+                Ok(()) => Ok(true),
+                Err(Errno::EINVAL | Errno::ENOSYS | Errno::EOPNOTSUPP | Errno::EXDEV) => Ok(false),
+                Err(error) => Err(error.into()),
+            }
+        }
+    } else {
+        fn try_exchange_blocking(_a: &Path, _b: &Path) -> std::io::Result<bool> {
+            Ok(false)
+        }
+    }
+}
+
+/// Find the regions of a sparse file that contain data. Returns `None` if
+/// the file doesn't have any holes, or if the filesystem can't report them.
+pub async fn sparse_data_extents(path: &Path) -> anyhow::Result<Option<Vec<Range<u64>>>> {
+    let path = path.to_owned();
+    let data_extents = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        data_extents_blocking(&file)
+    })
+    .await??;
+    Ok(data_extents)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        fn data_extents_blocking(file: &std::fs::File) -> anyhow::Result<Option<Vec<Range<u64>>>> {
+            use std::os::{fd::AsRawFd as _, unix::fs::MetadataExt as _};
+
+            use nix::{errno::Errno, unistd::Whence};
+
+            // A file with every block allocated can't have any holes
+            let metadata = file.metadata()?;
+            let size = metadata.len();
+            if metadata.blocks().saturating_mul(512) >= size {
+                return Ok(None);
+            }
+
+            let fd = file.as_raw_fd();
+            let mut data_extents = vec![];
+            let mut offset = 0;
+            while offset < size {
+                let start = match nix::unistd::lseek(fd, offset as i64, Whence::SeekData) {
+                    Ok(start) => start as u64,
+                    // There's no more data past the offset
+                    Err(Errno::ENXIO) => break,
+                    // The filesystem doesn't support finding holes
+                    Err(Errno::EINVAL) => return Ok(None),
+                    Err(error) => return Err(error.into()),
+                };
+                let end = nix::unistd::lseek(fd, start as i64, Whence::SeekHole)? as u64;
+                data_extents.push(start..end);
+                offset = end;
+            }
+
+            Ok(Some(data_extents))
+        }
+    } else {
+        fn data_extents_blocking(_file: &std::fs::File) -> anyhow::Result<Option<Vec<Range<u64>>>> {
+            Ok(None)
+        }
+    }
+}
+
+/// Copy a file, only writing the regions in `data_extents`. Anything
+/// between the regions is left as a hole, so a copy of a sparse file stays
+/// sparse. The destination is created or truncated, and doesn't keep the
+/// source's permissions.
+pub async fn sparse_copy(
+    source: &Path,
+    dest: &Path,
+    data_extents: &[Range<u64>],
+) -> anyhow::Result<()> {
+    let mut source_file = tokio::fs::File::open(source).await?;
+    let size = source_file.metadata().await?.len();
+    let mut dest_file = tokio::fs::File::create(dest).await?;
+
+    for extent in data_extents {
+        source_file.seek(SeekFrom::Start(extent.start)).await?;
+        dest_file.seek(SeekFrom::Start(extent.start)).await?;
+
+        let mut extent_data = (&mut source_file).take(extent.end - extent.start);
+        tokio::io::copy(&mut extent_data, &mut dest_file).await?;
+    }
+
+    dest_file.set_len(size).await?;
+    dest_file.flush().await?;
+
+    Ok(())
+}
+
+/// Create a hard link, or copy the file if the source and destination are
+/// on different filesystems.
+pub async fn hard_link_or_copy(source: &Path, dest: &Path) -> anyhow::Result<()> {
+    match tokio::fs::hard_link(source, dest).await {
+        Ok(()) => Ok(()),
+        Err(error) if is_cross_device_error(&error) => {
+            let metadata = tokio::fs::metadata(source).await?;
+            atomic_copy_with_mtime(source, dest, metadata.modified()?).await?;
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Copy a file, possibly to another filesystem, syncing the copy to disk
+/// before renaming it into place. Unlike [`atomic_copy`], the file's
+/// modified time is kept and the copy is durable once it's visible.
+pub async fn durable_copy(source: &Path, dest: &Path) -> anyhow::Result<()> {
+    let source = source.to_owned();
+    let dest = dest.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let mtime = std::fs::metadata(&source)?.modified()?;
+        let dest_temp = dest.with_extension(format!("tmp-{}", ulid::Ulid::new()));
+
+        let result = (|| {
+            reflink_or_copy_blocking(&source, &dest_temp).context("failed to copy file to temp")?;
+            let file = std::fs::File::open(&dest_temp)?;
+            file.set_modified(mtime)?;
+            file.sync_all().context("failed to sync temp file")?;
+            drop(file);
+
+            std::fs::rename(&dest_temp, &dest).context("failed to rename temp file")?;
+            anyhow::Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&dest_temp);
+        }
+        result?;
+
+        // Sync the directory too, so the rename itself is durable. Not
+        // every platform supports opening directories, so this is best
+        // effort
+        if let Some(parent) = dest.parent() {
+            if let Ok(parent) = std::fs::File::open(parent) {
+                let _ = parent.sync_all();
+            }
+        }
+
+        anyhow::Ok(())
+    })
+    .await??;
+
+    Ok(())
+}
+
+/// Flush a file's contents and metadata to disk.
+pub async fn sync_file(path: &Path) -> anyhow::Result<()> {
+    let file = tokio::fs::File::open(path).await?;
+    file.sync_all().await?;
+    Ok(())
+}
+
+/// Flush a directory's entries to disk, so renames into the directory are
+/// durable. Not every platform supports opening directories, so this does
+/// nothing if the directory can't be opened.
+pub async fn sync_dir(path: &Path) -> anyhow::Result<()> {
+    let Ok(dir) = tokio::fs::File::open(path).await else {
+        return Ok(());
+    };
+    dir.sync_all().await?;
+    Ok(())
+}
+
+pub fn is_cross_device_error(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(nix::libc::EXDEV)
+}
+
 pub async fn try_remove(path: &Path) -> anyhow::Result<bool> {
     let meta = tokio::fs::symlink_metadata(path).await;
     let meta = match meta {
@@ -229,7 +564,166 @@ cfg_if::cfg_if! {
 
             Ok(())
         }
+
+        /// Make the permissions read-only and non-executable, as used
+        /// for blobs.
+        pub fn set_readonly(permissions: &mut std::fs::Permissions) {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            permissions.set_mode(0o444);
+        }
+
+        /// Make the permissions readable and writable by the owner only,
+        /// as used for private keys.
+        pub fn set_owner_only(permissions: &mut std::fs::Permissions) {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            permissions.set_mode(0o600);
+        }
+
+        pub fn set_executable(permissions: &mut std::fs::Permissions) {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let new_mode = permissions.mode() | 0o111;
+            permissions.set_mode(new_mode);
+        }
+
+        /// Returns true if the file has no other hard links.
+        pub fn is_file_exclusive(metadata: &std::fs::Metadata) -> bool {
+            use std::os::unix::fs::MetadataExt as _;
+
+            metadata.nlink() == 1
+        }
+
+        pub fn symlink_blocking(target: &Path, link: &Path) -> std::io::Result<()> {
+            std::os::unix::fs::symlink(target, link)
+        }
+
+        pub fn is_same_filesystem(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+            use std::os::unix::fs::MetadataExt as _;
+
+            a.dev() == b.dev()
+        }
+
+        /// Get an ID that's shared by every hard link to the same file.
+        pub fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+            use std::os::unix::fs::MetadataExt as _;
+
+            Some((metadata.dev(), metadata.ino()))
+        }
+    } else if #[cfg(windows)] {
+        // Windows has no executable bit, so files are never treated as
+        // executable when read as inputs, and the executable flag only
+        // exists within artifacts
+        pub fn is_executable(_permissions: &std::fs::Permissions) -> bool {
+            false
+        }
+
+        pub fn set_rwx(permissions: &mut std::fs::Permissions) {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+        }
+
+        pub async fn set_mtime(path: &Path, mtime: std::time::SystemTime) -> anyhow::Result<()> {
+            use std::os::windows::fs::OpenOptionsExt as _;
+
+            // Needed to open directories
+            const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x02000000;
+
+            let path = path.to_owned();
+            tokio::task::spawn_blocking(move || {
+                let file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+                    .open(path)?;
+                file.set_modified(mtime)?;
+                anyhow::Ok(())
+            }).await??;
+
+            Ok(())
+        }
+
+        pub fn set_readonly(permissions: &mut std::fs::Permissions) {
+            permissions.set_readonly(true);
+        }
+
+        /// Files inherit the ACL of their parent directory on Windows, so
+        /// this relies on the Brioche home directory being private.
+        pub fn set_owner_only(_permissions: &mut std::fs::Permissions) {}
+
+        pub fn set_executable(_permissions: &mut std::fs::Permissions) {}
+
+        /// Returns true if the file has no other hard links. The link count
+        /// isn't available on stable Rust for Windows, so files are always
+        /// assumed to be shared.
+        pub fn is_file_exclusive(_metadata: &std::fs::Metadata) -> bool {
+            false
+        }
+
+        pub fn symlink_blocking(target: &Path, link: &Path) -> std::io::Result<()> {
+            // Windows needs to know if the target is a directory. Relative
+            // targets are resolved from the symlink's parent directory
+            let resolved_target = match link.parent() {
+                Some(parent) => parent.join(target),
+                None => target.to_owned(),
+            };
+            if resolved_target.is_dir() {
+                std::os::windows::fs::symlink_dir(target, link)
+            } else {
+                std::os::windows::fs::symlink_file(target, link)
+            }
+        }
+
+        /// The volume isn't available on stable Rust for Windows, so
+        /// directories are assumed to be on the same filesystem. Renames
+        /// across volumes still fall back to copying when they fail.
+        pub fn is_same_filesystem(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+            true
+        }
+
+        /// The file index isn't available on stable Rust for Windows, so
+        /// hard links to the same file can't be identified.
+        pub fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+            None
+        }
+    }
+}
+
+pub async fn symlink(target: &Path, link: &Path) -> anyhow::Result<()> {
+    let target = target.to_owned();
+    let link = link.to_owned();
+    tokio::task::spawn_blocking(move || symlink_blocking(&target, &link)).await??;
+    Ok(())
+}
+
+/// Returns true if the path is on a Windows drive mounted into WSL (such
+/// as `/mnt/c`). These mounts don't reliably support permissions or hard
+/// links, so they shouldn't be used for the Brioche home directory.
+pub fn is_wsl_windows_mount(path: &Path) -> bool {
+    if !is_wsl() {
+        return false;
     }
+
+    let mut components = path.components();
+    let (Some(Component::RootDir), Some(Component::Normal(mnt)), Some(Component::Normal(drive))) =
+        (components.next(), components.next(), components.next())
+    else {
+        return false;
+    };
+
+    mnt == "mnt"
+        && drive
+            .to_str()
+            .is_some_and(|drive| drive.len() == 1 && drive.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+fn is_wsl() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+
+    std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
 }
 
 #[derive(Debug, Clone, Copy)]
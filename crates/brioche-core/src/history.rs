@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    bake::BakeScope,
+    project::ProjectHash,
+    recipe::{Artifact, Recipe, RecipeHash, WithMeta},
+    Brioche,
+};
+
+/// A record of a top-level bake, such as from `brioche build`.
+#[derive(Debug, Clone)]
+pub struct BakeRun {
+    pub recipe_hash: RecipeHash,
+    pub artifact_hash: RecipeHash,
+    pub project_hash: Option<ProjectHash>,
+    pub export: Option<String>,
+    pub duration: std::time::Duration,
+    /// The number of bakes that were found in the local cache or registry.
+    pub num_cache_hits: u64,
+    /// The number of bakes that were actually run.
+    pub num_baked: u64,
+    /// When the bake finished, in UTC, as formatted by SQLite.
+    pub created_at: Option<String>,
+}
+
+impl BakeRun {
+    /// The fraction of non-trivial bakes that were served from the cache,
+    /// or `None` if there weren't any.
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        let total = self.num_cache_hits + self.num_baked;
+        if total == 0 {
+            None
+        } else {
+            Some(self.num_cache_hits as f64 / total as f64)
+        }
+    }
+}
+
+/// Bake a root recipe, then record the run in the build history.
+pub async fn bake_and_record(
+    brioche: &Brioche,
+    recipe: WithMeta<Recipe>,
+    scope: &BakeScope,
+) -> anyhow::Result<WithMeta<Artifact>> {
+    let recipe_hash = recipe.hash();
+    let start = std::time::Instant::now();
+
+    // Count bakes with fresh stats for just this run, so runs happening at
+    // the same time (e.g. through the daemon) don't count each other's bakes
+    let bake_stats = Arc::new(crate::bake::BakeStats::default());
+    let run_brioche = Brioche {
+        bake_stats: bake_stats.clone(),
+        ..brioche.clone()
+    };
+    let artifact = crate::bake::bake(&run_brioche, recipe, scope).await?;
+
+    let duration = start.elapsed();
+    let (num_cache_hits, num_baked) = bake_stats.snapshot();
+
+    let (project_hash, export) = match scope {
+        BakeScope::Project {
+            project_hash,
+            export,
+        } => (Some(*project_hash), Some(export.clone())),
+        BakeScope::Child { .. } | BakeScope::Anonymous => (None, None),
+    };
+
+    // Save the artifact itself so it can be re-materialized from the
+    // history later, even if it was never saved as a bake result
+    crate::recipe::save_recipes(brioche, [Recipe::from(artifact.value.clone())]).await?;
+
+    // A read-only store can't record history, but the build still works
+    if brioche.read_only {
+        return Ok(artifact);
+    }
+
+    record_bake_run(
+        brioche,
+        &BakeRun {
+            recipe_hash,
+            artifact_hash: artifact.hash(),
+            project_hash,
+            export,
+            duration,
+            num_cache_hits,
+            num_baked,
+            created_at: None,
+        },
+    )
+    .await?;
+
+    Ok(artifact)
+}
+
+pub async fn record_bake_run(brioche: &Brioche, run: &BakeRun) -> anyhow::Result<()> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let recipe_hash_value = run.recipe_hash.to_string();
+    let artifact_hash_value = run.artifact_hash.to_string();
+    let project_hash_value = run.project_hash.map(|hash| hash.to_string());
+    let export_value = run.export.as_deref();
+    let duration_ms = i64::try_from(run.duration.as_millis()).unwrap_or(i64::MAX);
+    let num_cache_hits = i64::try_from(run.num_cache_hits)?;
+    let num_baked = i64::try_from(run.num_baked)?;
+    sqlx::query!(
+        r#"
+            INSERT INTO bake_history (
+                recipe_hash,
+                artifact_hash,
+                project_hash,
+                export,
+                duration_ms,
+                num_cache_hits,
+                num_baked
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        recipe_hash_value,
+        artifact_hash_value,
+        project_hash_value,
+        export_value,
+        duration_ms,
+        num_cache_hits,
+        num_baked,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+/// List the most recent bake runs, newest first.
+pub async fn list_bake_runs(brioche: &Brioche, limit: u32) -> anyhow::Result<Vec<BakeRun>> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let records = sqlx::query!(
+        r#"
+            SELECT
+                recipe_hash,
+                artifact_hash,
+                project_hash,
+                export,
+                duration_ms,
+                num_cache_hits,
+                num_baked,
+                created_at
+            FROM bake_history
+            ORDER BY id DESC
+            LIMIT ?
+        "#,
+        limit,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            let recipe_hash = record
+                .recipe_hash
+                .parse()
+                .context("invalid recipe hash from database")?;
+            let artifact_hash = record
+                .artifact_hash
+                .parse()
+                .context("invalid recipe hash from database")?;
+            let project_hash = record
+                .project_hash
+                .map(|hash| hash.parse::<ProjectHash>())
+                .transpose()
+                .context("invalid project hash from database")?;
+            Ok(BakeRun {
+                recipe_hash,
+                artifact_hash,
+                project_hash,
+                export: record.export,
+                duration: std::time::Duration::from_millis(record.duration_ms.try_into()?),
+                num_cache_hits: record.num_cache_hits.try_into()?,
+                num_baked: record.num_baked.try_into()?,
+                created_at: Some(record.created_at),
+            })
+        })
+        .collect()
+}
+
+/// Find the artifact for a past bake run, by either the hash of the root
+/// recipe or the hash of the resulting artifact.
+pub async fn find_bake_run_artifact(
+    brioche: &Brioche,
+    hash: RecipeHash,
+) -> anyhow::Result<Option<Artifact>> {
+    let hash_value = hash.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let record = sqlx::query!(
+        r#"
+            SELECT artifact_hash
+            FROM bake_history
+            WHERE recipe_hash = ? OR artifact_hash = ?
+            ORDER BY id DESC
+            LIMIT 1
+        "#,
+        hash_value,
+        hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    let artifact_hash: RecipeHash = record
+        .artifact_hash
+        .parse()
+        .context("invalid recipe hash from database")?;
+
+    let recipe = crate::recipe::get_recipe(brioche, artifact_hash).await?;
+    let artifact = Artifact::try_from(recipe)
+        .map_err(|_| anyhow::anyhow!("bake history artifact {artifact_hash} is not complete"))?;
+    Ok(Some(artifact))
+}
@@ -68,7 +68,15 @@ pub async fn create_input_inner(
                 move || {
                     let input_file = std::fs::File::open(&input_path)
                         .with_context(|| format!("failed to open file {}", input_path.display()))?;
-                    let pack = brioche_pack::extract_pack(input_file).ok();
+                    let pack = match brioche_pack::try_extract_pack(input_file) {
+                        Ok(pack) => pack,
+                        Err(error) => {
+                            // Treat files with a malformed pack like any other
+                            // file, so a hostile input can't fail the build
+                            tracing::warn!(input_path = %input_path.display(), %error, "ignoring malformed pack");
+                            None
+                        }
+                    };
                     anyhow::Ok(pack)
                 }
             })
@@ -172,7 +180,7 @@ pub async fn create_input_inner(
         };
 
         let blob_hash = {
-            let permit = super::blob::get_save_blob_permit().await?;
+            let permit = super::blob::get_save_blob_permit(brioche).await?;
             super::blob::save_blob_from_file(
                 brioche,
                 permit,
@@ -210,6 +218,13 @@ pub async fn create_input_inner(
                         options.input_path.display()
                     )
                 })?;
+            crate::path::validate_component(&entry_name).with_context(|| {
+                format!(
+                    "invalid file name {} in directory {}",
+                    entry.file_name().to_string_lossy(),
+                    options.input_path.display()
+                )
+            })?;
             let entry_name = bstr::BString::from(entry_name);
 
             let result_entry = create_input_inner(
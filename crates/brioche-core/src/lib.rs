@@ -1,4 +1,8 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use anyhow::Context as _;
 use registry::RegistryClient;
@@ -10,13 +14,32 @@ use tokio::{
     sync::{Mutex, RwLock},
 };
 
+pub mod annotations;
 pub mod bake;
 pub mod blob;
+pub mod blob_store;
+pub mod bundle;
+pub mod checksums;
+pub mod clean;
+#[cfg(unix)]
+pub mod daemon;
+pub mod disk_space;
 pub mod encoding;
+pub mod error_codes;
 pub mod fs_utils;
+pub mod history;
 pub mod input;
+pub mod licenses;
+pub mod mirror;
+pub mod network;
+pub mod oci;
+pub mod outdated;
 pub mod output;
+pub mod packaging;
+pub mod path;
+pub mod pins;
 pub mod platform;
+pub mod profile;
 pub mod project;
 pub mod publish;
 pub mod recipe;
@@ -25,7 +48,13 @@ pub mod registry;
 pub mod reporter;
 pub mod sandbox;
 pub mod script;
+pub mod secrets;
+pub mod service;
+pub mod snapshot;
+pub mod store_migrate;
+pub mod store_stats;
 pub mod sync;
+pub mod update_hashes;
 pub mod utils;
 pub mod vfs;
 
@@ -45,6 +74,9 @@ pub struct Brioche {
     /// to follow the platform's conventions for storing application data, such
     /// as `~/.local/share/brioche` on Linux.
     pub home: PathBuf,
+    /// Set when `blobs-temp` is on a different filesystem from `blobs`, so
+    /// new blobs are copied into place instead of renamed.
+    pub blobs_temp_cross_device: bool,
     /// Causes Brioche to call itself to execute processes in a sandbox, rather
     /// than using a `tokio::spawn_blocking` thread. This could allow for
     /// running more processes at a time. This option mainly exists because
@@ -54,14 +86,114 @@ pub struct Brioche {
     /// useful for debugging, where build outputs may succeed but need to be
     /// manually investigated.
     pub keep_temps: bool,
+    /// Trace the files each process accesses while it runs, then report
+    /// inputs it never used and files it couldn't find in the sandbox.
+    pub trace_process_inputs: bool,
     /// Synchronize baked recipes to the registry automatically.
     pub sync_tx: Arc<tokio::sync::mpsc::Sender<SyncMessage>>,
     pub cached_recipes: Arc<RwLock<bake::CachedRecipes>>,
     pub active_bakes: Arc<RwLock<bake::ActiveBakes>>,
+    /// Counts the bakes done through this instance. Each recorded run
+    /// swaps in its own counters, see [`history::bake_and_record`].
+    pub bake_stats: Arc<bake::BakeStats>,
     pub process_semaphore: Arc<tokio::sync::Semaphore>,
     pub download_semaphore: Arc<tokio::sync::Semaphore>,
+    /// Limits how many blobs are saved at once, such as when ingesting
+    /// inputs or unpacking archives.
+    pub save_blob_semaphore: Arc<tokio::sync::Semaphore>,
     pub download_client: reqwest_middleware::ClientWithMiddleware,
     pub registry_client: registry::RegistryClient,
+    /// Restricts which files can be read when loading project scripts.
+    pub script_read_access: script::read_access::ReadAccess,
+    /// Restricts which capabilities dependency projects may use. Loaded
+    /// from the per-user `policy.toml` file, and narrowed by a project's
+    /// own policy file.
+    pub capability_policy: Option<Arc<project::policy::CapabilityPolicy>>,
+    /// The capabilities granted to each loaded dependency project, checked
+    /// when baking processes.
+    pub dependency_capabilities: Arc<RwLock<project::policy::DependencyCapabilities>>,
+    /// Emulators used to run processes for platforms other than the
+    /// current one.
+    pub emulators: Arc<platform::emulator::Emulators>,
+    /// When set, downloads with a mismatched hash are recorded so the
+    /// project's source can be updated with the actual hash.
+    pub hash_updates: Option<Arc<update_hashes::HashUpdates>>,
+    /// When set, downloads and processes are resolved using these mocks
+    /// instead of being run. Used for testing recipes.
+    pub bake_mocks: Option<Arc<bake::mocks::BakeMocks>>,
+    /// Signs bakes pushed to the registry, and verifies bakes pulled from
+    /// the registry.
+    pub attestations: Arc<registry::attestation::Attestations>,
+    /// What `brioche install` does when an executable would replace one
+    /// from another package, unless overridden on the command line.
+    pub install_conflict_policy: profile::ConflictPolicy,
+    /// The directory where processes get their temporary work directories.
+    /// This can be on a different filesystem from the Brioche home
+    /// directory, in which case outputs get copied into the blob store
+    /// instead of moved.
+    pub process_scratch_dir: PathBuf,
+    /// Read-only stores consulted for bake results and recipes before
+    /// falling back to the registry. Their blobs are consulted through
+    /// `blob_stores`.
+    pub mirror_stores: Arc<Vec<mirror::MirrorStore>>,
+    /// Blob stores consulted in order after the Brioche home's own `blobs`
+    /// directory: secondary blob directories (used in place), then the
+    /// blobs of mirror stores, then remote stores like an S3 bucket. New
+    /// blobs are only ever saved to the home directory, then copied to
+    /// each writable store.
+    pub blob_stores: Arc<Vec<Box<dyn blob_store::BlobStore>>>,
+    /// Uploads new blobs to the writable blob stores in the background.
+    pub blob_upload_tx: Arc<tokio::sync::mpsc::Sender<blob_store::BlobUploadMessage>>,
+    /// How carefully blobs and database writes are flushed to disk.
+    pub durability: blob::Durability,
+    /// The user and group that processes run as within the sandbox.
+    pub sandbox_user: sandbox::SandboxUser,
+    /// Secrets that can be mounted into processes.
+    pub secrets: Arc<secrets::Secrets>,
+    /// The store is read-only (e.g. mounted from an image), so nothing new
+    /// can be saved. Anything already in the store can still be used.
+    pub read_only: bool,
+    /// When set, downloads, git checkouts, and networked processes can
+    /// only contact hosts in the allowlist.
+    pub network_allowlist: Option<Arc<network::NetworkAllowlist>>,
+    /// Proxies used for downloads and the registry, which networked
+    /// processes are also pointed at.
+    pub proxy: network::ProxyConfig,
+    /// When set, processes that fail are remembered for this long, and
+    /// baking them again fails immediately with the same error.
+    pub failed_process_cache_ttl: Option<std::time::Duration>,
+    /// Run processes again even if they recently failed.
+    pub retry_failed: bool,
+    /// The environment that variables declared in a project's
+    /// `brioche.toml` are read from. When unset, they're read from
+    /// Brioche's own environment. The daemon sets this to the environment
+    /// of the client that requested the build.
+    pub project_env: Option<Arc<HashMap<String, String>>>,
+}
+
+impl Brioche {
+    /// Return an error if the store is read-only. `action` describes what
+    /// needed to write to the store, for the error message.
+    pub fn ensure_writable(&self, action: &str) -> anyhow::Result<()> {
+        if self.read_only {
+            return Err(error_codes::CodedError::new(
+                error_codes::ErrorCode::READ_ONLY_STORE,
+                format!("cannot {action}: the Brioche store is read-only"),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Read a variable declared in a project's `brioche.toml`, from
+    /// [`Brioche::project_env`] if set.
+    pub fn project_env_var(&self, name: &str) -> Option<String> {
+        match &self.project_env {
+            Some(project_env) => project_env.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        }
+    }
 }
 
 pub struct BriocheBuilder {
@@ -71,7 +203,28 @@ pub struct BriocheBuilder {
     home: Option<PathBuf>,
     self_exec_processes: bool,
     keep_temps: bool,
+    trace_process_inputs: bool,
     sync: bool,
+    script_read_access: script::read_access::ReadAccess,
+    capability_policy: Option<project::policy::CapabilityPolicy>,
+    emulators: HashMap<platform::Platform, PathBuf>,
+    update_hashes: bool,
+    bake_mocks: Option<bake::mocks::BakeMocks>,
+    trusted_builder_keys: BTreeSet<String>,
+    process_scratch_dir: Option<PathBuf>,
+    mirror_stores: Vec<PathBuf>,
+    secondary_blob_dirs: Vec<PathBuf>,
+    blob_stores: Vec<Box<dyn blob_store::BlobStore>>,
+    durability: Option<blob::Durability>,
+    sandbox_user: Option<sandbox::SandboxUser>,
+    secrets: HashMap<String, secrets::SecretSource>,
+    read_only: Option<bool>,
+    network_allowlist: Option<Vec<String>>,
+    max_concurrent_processes: Option<usize>,
+    max_concurrent_downloads: Option<usize>,
+    max_concurrent_blob_saves: Option<usize>,
+    failed_process_cache_ttl: Option<std::time::Duration>,
+    retry_failed: bool,
 }
 
 impl BriocheBuilder {
@@ -83,7 +236,28 @@ impl BriocheBuilder {
             home: None,
             self_exec_processes: true,
             keep_temps: false,
+            trace_process_inputs: false,
             sync: false,
+            script_read_access: script::read_access::ReadAccess::default(),
+            capability_policy: None,
+            emulators: HashMap::new(),
+            update_hashes: false,
+            bake_mocks: None,
+            trusted_builder_keys: BTreeSet::new(),
+            process_scratch_dir: None,
+            mirror_stores: vec![],
+            secondary_blob_dirs: vec![],
+            blob_stores: vec![],
+            durability: None,
+            sandbox_user: None,
+            secrets: HashMap::new(),
+            read_only: None,
+            network_allowlist: None,
+            max_concurrent_processes: None,
+            max_concurrent_downloads: None,
+            max_concurrent_blob_saves: None,
+            failed_process_cache_ttl: None,
+            retry_failed: false,
         }
     }
 
@@ -107,6 +281,11 @@ impl BriocheBuilder {
         self
     }
 
+    pub fn trace_process_inputs(mut self, trace_process_inputs: bool) -> Self {
+        self.trace_process_inputs = trace_process_inputs;
+        self
+    }
+
     pub fn vfs(mut self, vfs: vfs::Vfs) -> Self {
         self.vfs = vfs;
         self
@@ -117,6 +296,142 @@ impl BriocheBuilder {
         self
     }
 
+    pub fn script_read_access(
+        mut self,
+        script_read_access: script::read_access::ReadAccess,
+    ) -> Self {
+        self.script_read_access = script_read_access;
+        self
+    }
+
+    pub fn capability_policy(mut self, policy: project::policy::CapabilityPolicy) -> Self {
+        self.capability_policy = Some(policy);
+        self
+    }
+
+    /// Only accept bakes from the registry signed by this builder key
+    /// (hex-encoded). Adds to the keys from the config file.
+    pub fn trusted_builder_key(mut self, public_key: String) -> Self {
+        self.trusted_builder_keys.insert(public_key);
+        self
+    }
+
+    /// Set the emulator binary to use for a foreign platform. Takes
+    /// priority over the emulators from the config file.
+    pub fn emulator(mut self, platform: platform::Platform, path: PathBuf) -> Self {
+        self.emulators.insert(platform, path);
+        self
+    }
+
+    pub fn update_hashes(mut self, update_hashes: bool) -> Self {
+        self.update_hashes = update_hashes;
+        self
+    }
+
+    pub fn bake_mocks(mut self, bake_mocks: bake::mocks::BakeMocks) -> Self {
+        self.bake_mocks = Some(bake_mocks);
+        self
+    }
+
+    /// Set the directory used for process work directories. Takes priority
+    /// over the config file.
+    pub fn process_scratch_dir(mut self, process_scratch_dir: PathBuf) -> Self {
+        self.process_scratch_dir = Some(process_scratch_dir);
+        self
+    }
+
+    /// Add read-only mirror stores, in addition to the ones from the
+    /// config file.
+    pub fn mirror_stores(mut self, mirror_stores: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.mirror_stores.extend(mirror_stores);
+        self
+    }
+
+    /// Add secondary blob directories, in addition to the ones from the
+    /// config file.
+    pub fn secondary_blob_dirs(
+        mut self,
+        secondary_blob_dirs: impl IntoIterator<Item = PathBuf>,
+    ) -> Self {
+        self.secondary_blob_dirs.extend(secondary_blob_dirs);
+        self
+    }
+
+    /// Add a remote blob store, in addition to the ones from the config
+    /// file.
+    pub fn blob_store(mut self, blob_store: impl blob_store::BlobStore + 'static) -> Self {
+        self.blob_stores.push(Box::new(blob_store));
+        self
+    }
+
+    /// Set how carefully blobs and database writes are flushed to disk.
+    /// Takes priority over the config file.
+    pub fn durability(mut self, durability: blob::Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    /// Set the user and group that processes run as within the sandbox.
+    /// Takes priority over the config file.
+    pub fn sandbox_user(mut self, sandbox_user: sandbox::SandboxUser) -> Self {
+        self.sandbox_user = Some(sandbox_user);
+        self
+    }
+
+    /// Set where to read a secret from. Takes priority over the secrets
+    /// from the config file.
+    pub fn secret(mut self, name: String, source: secrets::SecretSource) -> Self {
+        self.secrets.insert(name, source);
+        self
+    }
+
+    /// Open the store read-only. Takes priority over the config file.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// Only allow contacting hosts within these domains. Takes priority
+    /// over the config file.
+    pub fn network_allowlist(mut self, domains: Vec<String>) -> Self {
+        self.network_allowlist = Some(domains);
+        self
+    }
+
+    /// Set the maximum number of processes that run at once. Takes
+    /// priority over the config file.
+    pub fn max_concurrent_processes(mut self, max_concurrent_processes: usize) -> Self {
+        self.max_concurrent_processes = Some(max_concurrent_processes);
+        self
+    }
+
+    /// Set the maximum number of downloads that run at once. Takes
+    /// priority over the config file.
+    pub fn max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
+        self.max_concurrent_downloads = Some(max_concurrent_downloads);
+        self
+    }
+
+    /// Set the maximum number of blobs saved at once. Takes priority over
+    /// the config file.
+    pub fn max_concurrent_blob_saves(mut self, max_concurrent_blob_saves: usize) -> Self {
+        self.max_concurrent_blob_saves = Some(max_concurrent_blob_saves);
+        self
+    }
+
+    /// Remember failed processes for this long, so baking them again
+    /// fails immediately. Takes priority over the config file.
+    pub fn failed_process_cache_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.failed_process_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Run processes again even if they recently failed.
+    pub fn retry_failed(mut self, retry_failed: bool) -> Self {
+        self.retry_failed = retry_failed;
+        self
+    }
+
     pub async fn build(self) -> anyhow::Result<Brioche> {
         let dirs = directories::ProjectDirs::from("dev", "brioche", "brioche")
             .context("failed to get Brioche directories (is $HOME set?)")?;
@@ -158,30 +473,105 @@ impl BriocheBuilder {
             None => BriocheConfig::default(),
         };
 
-        let brioche_home = match self.home {
-            Some(home) => home,
-            None => dirs.data_local_dir().to_owned(),
+        let capability_policy = match self.capability_policy {
+            Some(policy) => Some(policy),
+            None => {
+                let policy_path = dirs.config_dir().join("policy.toml");
+                project::policy::read_policy_file(&policy_path).await?
+            }
         };
 
-        tokio::fs::create_dir_all(&brioche_home).await?;
+        let brioche_home = self
+            .home
+            .or_else(|| config.home.clone())
+            .unwrap_or_else(|| dirs.data_local_dir().to_owned());
+
+        let read_only = self.read_only.or(config.read_only).unwrap_or(false);
+
+        if !read_only {
+            tokio::fs::create_dir_all(&brioche_home).await?;
+        }
+
+        if fs_utils::is_wsl_windows_mount(&brioche_home) {
+            tracing::warn!(
+                brioche_home = %brioche_home.display(),
+                "Brioche home is on a Windows drive mounted in WSL, which doesn't fully support permissions or hard links; consider using a path within the WSL filesystem instead"
+            );
+        }
+
+        // Nothing gets written to a read-only store, so there's no need
+        // to check where blobs would be written
+        let blobs_temp_cross_device =
+            !read_only && blob::is_blobs_temp_cross_device(&brioche_home).await?;
+        if blobs_temp_cross_device {
+            tracing::debug!("blobs-temp is on a different filesystem from blobs, blobs will be copied into place");
+        }
 
         let database_path = brioche_home.join("brioche.db");
 
-        let db_conn_options = sqlx::sqlite::SqliteConnectOptions::new()
-            .filename(&database_path)
-            .create_if_missing(true)
-            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
-            .auto_vacuum(sqlx::sqlite::SqliteAutoVacuum::Full);
-        let mut db_conn = sqlx::sqlite::SqliteConnection::connect_with(&db_conn_options).await?;
+        let durability = self.durability.or(config.durability).unwrap_or_default();
+
+        let max_concurrent_processes = concurrency_limit(
+            "max_concurrent_processes",
+            self.max_concurrent_processes
+                .or(config.max_concurrent_processes)
+                .unwrap_or(MAX_CONCURRENT_PROCESSES),
+        )?;
+        let max_concurrent_downloads = concurrency_limit(
+            "max_concurrent_downloads",
+            self.max_concurrent_downloads
+                .or(config.max_concurrent_downloads)
+                .unwrap_or(MAX_CONCURRENT_DOWNLOADS),
+        )?;
+        let max_concurrent_blob_saves = concurrency_limit(
+            "max_concurrent_blob_saves",
+            self.max_concurrent_blob_saves
+                .or(config.max_concurrent_blob_saves)
+                .unwrap_or(blob::MAX_CONCURRENT_BLOB_SAVES),
+        )?;
+
+        let mut db_conn = if read_only {
+            // Reading a database in WAL mode needs a shared memory file
+            // next to it, which can't be created in a read-only directory.
+            // Opening it as immutable avoids that, but is only safe when
+            // there's no write-ahead log left (i.e. it was closed cleanly)
+            let wal_path = brioche_home.join("brioche.db-wal");
+            let has_wal = tokio::fs::try_exists(&wal_path).await?;
+            let db_conn_options = sqlx::sqlite::SqliteConnectOptions::new()
+                .filename(&database_path)
+                .read_only(true)
+                .immutable(!has_wal);
+            sqlx::sqlite::SqliteConnection::connect_with(&db_conn_options)
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to open read-only database at {}",
+                        database_path.display()
+                    )
+                })?
+        } else {
+            let db_conn_options = sqlx::sqlite::SqliteConnectOptions::new()
+                .filename(&database_path)
+                .create_if_missing(true)
+                .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+                .synchronous(durability.sqlite_synchronous())
+                .auto_vacuum(sqlx::sqlite::SqliteAutoVacuum::Full);
+            sqlx::sqlite::SqliteConnection::connect_with(&db_conn_options).await?
+        };
 
         tracing::debug!(
             database_path = %database_path.display(),
+            read_only,
             "connected to database"
         );
 
-        sqlx::migrate!().run(&mut db_conn).await?;
+        if read_only {
+            ensure_migrations_applied(&mut db_conn).await?;
+        } else {
+            sqlx::migrate!().run(&mut db_conn).await?;
 
-        tracing::debug!("finished running database migrations");
+            tracing::debug!("finished running database migrations");
+        }
 
         let download_retry_policy = reqwest_retry::policies::ExponentialBackoff::builder()
             .retry_bounds(
@@ -191,7 +581,18 @@ impl BriocheBuilder {
             .build_with_max_retries(5);
         let download_retry_middleware =
             reqwest_retry::RetryTransientMiddleware::new_with_policy(download_retry_policy);
-        let download_client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+        let proxy = config.proxy.clone().with_env_fallback()?;
+        let http_client_options =
+            network::HttpClientOptions::new(&proxy, &config.ca_certificates).await?;
+
+        let network_allowlist = self
+            .network_allowlist
+            .or(config.network_allowlist)
+            .map(|domains| Arc::new(network::NetworkAllowlist::new(domains)));
+        let download_client = http_client_options
+            .configure(reqwest::Client::builder().user_agent(USER_AGENT))
+            .redirect(network::redirect_policy(network_allowlist.clone()))
+            .build()?;
         let download_client = reqwest_middleware::ClientBuilder::new(download_client)
             .with(download_retry_middleware)
             .build();
@@ -207,8 +608,85 @@ impl BriocheBuilder {
                     .parse()
                     .expect("failed to parse default registry URL")
             });
-            registry::RegistryClient::new(registry_url, registry_auth)
+            let registry_options = registry::RegistryClientOptions {
+                max_connections: config
+                    .registry_max_connections
+                    .unwrap_or(registry::DEFAULT_MAX_CONNECTIONS),
+                download_bytes_per_second: config.registry_download_bandwidth,
+                upload_bytes_per_second: config.registry_upload_bandwidth,
+                scope_tokens: config
+                    .registry_tokens
+                    .iter()
+                    .map(|(scope, token)| {
+                        let scope = scope.strip_prefix('@').unwrap_or(scope);
+                        (scope.to_string(), token.clone())
+                    })
+                    .collect(),
+                max_requests_per_second: config.registry_max_requests_per_second,
+                http: http_client_options.clone(),
+            };
+            registry::RegistryClient::new_with_options(
+                registry_url,
+                registry_auth,
+                registry_options,
+            )
         });
+        registry_client.set_reporter(self.reporter.clone());
+
+        let mut script_read_access = self.script_read_access;
+        script_read_access
+            .allowed_paths
+            .extend(config.script_allow_read.iter().cloned());
+
+        let mut emulators = config.emulators.clone();
+        emulators.extend(self.emulators);
+
+        let mut secrets = config.secrets.clone();
+        secrets.extend(self.secrets);
+
+        let mut trusted_builder_keys = config.trusted_builder_keys.clone();
+        trusted_builder_keys.extend(self.trusted_builder_keys);
+        let attestations = registry::attestation::Attestations::new(
+            brioche_home.join(registry::attestation::SIGNING_KEY_FILENAME),
+            trusted_builder_keys,
+        );
+
+        let process_scratch_dir = self
+            .process_scratch_dir
+            .or_else(|| config.process_scratch_dir.clone())
+            .unwrap_or_else(|| brioche_home.join("process-temp"));
+
+        let mirror_stores = config
+            .mirror_stores
+            .iter()
+            .cloned()
+            .chain(self.mirror_stores)
+            .filter(|path| *path != brioche_home)
+            .map(mirror::MirrorStore::new)
+            .collect::<Vec<_>>();
+
+        let primary_blobs_dir = brioche_home.join("blobs");
+        let mut blob_stores: Vec<Box<dyn blob_store::BlobStore>> = vec![];
+        for blobs_dir in config
+            .secondary_blob_dirs
+            .iter()
+            .cloned()
+            .chain(self.secondary_blob_dirs)
+            .filter(|path| *path != primary_blobs_dir)
+        {
+            blob_stores.push(Box::new(blob_store::FilesystemBlobStore::local(blobs_dir)));
+        }
+        for mirror_store in &mirror_stores {
+            blob_stores.push(Box::new(blob_store::FilesystemBlobStore::mirror(
+                mirror_store.path(),
+            )));
+        }
+        for store_config in &config.blob_stores {
+            blob_stores.push(store_config.build(&http_client_options)?);
+        }
+        blob_stores.extend(self.blob_stores);
+        let blob_stores = Arc::new(blob_stores);
+        let blob_upload_tx = blob_store::start_upload_task(blob_stores.clone());
 
         let (sync_tx, mut sync_rx) = tokio::sync::mpsc::channel(1000);
 
@@ -253,22 +731,225 @@ impl BriocheBuilder {
             vfs: self.vfs,
             db_conn: Arc::new(Mutex::new(db_conn)),
             home: brioche_home,
+            blobs_temp_cross_device,
             self_exec_processes: self.self_exec_processes,
             keep_temps: self.keep_temps,
+            trace_process_inputs: self.trace_process_inputs,
             sync_tx: Arc::new(sync_tx),
             cached_recipes: Arc::new(RwLock::new(bake::CachedRecipes::default())),
             active_bakes: Arc::new(RwLock::new(bake::ActiveBakes::default())),
-            process_semaphore: Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_PROCESSES)),
-            download_semaphore: Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_DOWNLOADS)),
+            bake_stats: Arc::new(bake::BakeStats::default()),
+            process_semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent_processes)),
+            download_semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent_downloads)),
+            save_blob_semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent_blob_saves)),
             download_client,
             registry_client,
+            script_read_access,
+            capability_policy: capability_policy.map(Arc::new),
+            dependency_capabilities: Arc::new(RwLock::new(
+                project::policy::DependencyCapabilities::default(),
+            )),
+            emulators: Arc::new(platform::emulator::Emulators::new(emulators)),
+            hash_updates: self
+                .update_hashes
+                .then(|| Arc::new(update_hashes::HashUpdates::default())),
+            bake_mocks: self.bake_mocks.map(Arc::new),
+            attestations: Arc::new(attestations),
+            install_conflict_policy: config.install_conflict_policy.unwrap_or_default(),
+            process_scratch_dir,
+            mirror_stores: Arc::new(mirror_stores),
+            blob_stores,
+            blob_upload_tx: Arc::new(blob_upload_tx),
+            durability,
+            sandbox_user: self
+                .sandbox_user
+                .or(config.sandbox_user)
+                .unwrap_or_default(),
+            secrets: Arc::new(secrets::Secrets::new(secrets)),
+            read_only,
+            network_allowlist,
+            proxy,
+            failed_process_cache_ttl: self.failed_process_cache_ttl.or_else(|| {
+                config
+                    .failed_process_cache_ttl
+                    .map(std::time::Duration::from_secs)
+            }),
+            retry_failed: self.retry_failed,
+            project_env: None,
         })
     }
 }
 
+/// Check that a concurrency limit allows at least one task, since nothing
+/// could ever run otherwise.
+fn concurrency_limit(name: &str, limit: usize) -> anyhow::Result<usize> {
+    anyhow::ensure!(limit > 0, "{name} must be at least 1");
+    Ok(limit)
+}
+
+/// Migrations can't be run against a read-only database, so check that
+/// it was already migrated by a compatible version of Brioche.
+async fn ensure_migrations_applied(db_conn: &mut sqlx::SqliteConnection) -> anyhow::Result<()> {
+    let applied_versions =
+        sqlx::query_scalar!("SELECT version FROM _sqlx_migrations WHERE success = TRUE")
+            .fetch_all(&mut *db_conn)
+            .await
+            .context("failed to read migrations from read-only database")?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+    let missing_migration = sqlx::migrate!()
+        .iter()
+        .find(|migration| !applied_versions.contains(&migration.version));
+    if let Some(migration) = missing_migration {
+        return Err(error_codes::CodedError::new(
+            error_codes::ErrorCode::READ_ONLY_STORE,
+            format!(
+                "the read-only store's database needs to be migrated (missing migration {}: {})",
+                migration.version, migration.description
+            ),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
 struct BriocheConfig {
+    /// The directory where all of Brioche's data is stored. Defaults to the
+    /// platform's local data directory. Set by `brioche store migrate`.
+    home: Option<PathBuf>,
+
     registry_url: Option<url::Url>,
+
+    /// The maximum number of concurrent requests to the registry.
+    registry_max_connections: Option<usize>,
+
+    /// Limit the average download speed from the registry, in bytes per
+    /// second.
+    registry_download_bandwidth: Option<u64>,
+
+    /// Limit the average upload speed to the registry, in bytes per second.
+    registry_upload_bandwidth: Option<u64>,
+
+    /// Limit the number of requests sent to the registry per second.
+    registry_max_requests_per_second: Option<f64>,
+
+    /// Tokens for publishing and fetching scoped projects, keyed by scope
+    /// (e.g. `"@org" = "..."`).
+    #[serde(default)]
+    registry_tokens: HashMap<String, String>,
+
+    /// Extra paths that project scripts are allowed to read from, outside
+    /// of the project directories.
+    #[serde(default)]
+    script_allow_read: Vec<PathBuf>,
+
+    /// Emulators for running processes for foreign platforms, keyed by
+    /// platform (e.g. `"aarch64-linux" = "/usr/bin/qemu-aarch64-static"`).
+    /// When unset, QEMU emulators registered with `binfmt_misc` are used.
+    #[serde(default)]
+    emulators: HashMap<platform::Platform, PathBuf>,
+
+    /// Hex-encoded public keys of builders whose bakes are trusted. When
+    /// set, bakes from the registry are only used if they're signed by one
+    /// of these keys.
+    #[serde(default)]
+    trusted_builder_keys: BTreeSet<String>,
+
+    /// What to do when installing an executable that conflicts with one
+    /// from another package (`"error"`, `"skip"`, or `"replace"`).
+    install_conflict_policy: Option<profile::ConflictPolicy>,
+
+    /// A directory for process work directories, such as a path on a
+    /// separate disk from the Brioche home directory. Defaults to
+    /// `process-temp` within the Brioche home directory.
+    process_scratch_dir: Option<PathBuf>,
+
+    /// Other Brioche home directories to use as read-only stores, such as
+    /// a team's shared store on a network mount. Blobs and bake results
+    /// are looked up in these stores (in order) before the registry.
+    #[serde(default)]
+    mirror_stores: Vec<PathBuf>,
+
+    /// Extra directories of blobs to use in place, such as a large network
+    /// mount shared by a team (e.g. `["/mnt/nfs/brioche/blobs"]`). Blobs
+    /// are looked up in the Brioche home's own `blobs` directory first,
+    /// then in each of these directories in order. New blobs are only
+    /// saved to the Brioche home, so these can be mounted read-only.
+    #[serde(default)]
+    secondary_blob_dirs: Vec<PathBuf>,
+
+    /// Remote blob stores, such as an S3 bucket shared by CI machines,
+    /// consulted in order before the registry. Each is a table with a
+    /// `type` of `"s3"` (with `bucket`, `region`, `endpoint`, and `prefix`
+    /// keys) or `"filesystem"` (with a `path` key). Set `write = true` to
+    /// copy new blobs to the store. S3 credentials are read from the
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` environment
+    /// variables.
+    #[serde(default)]
+    blob_stores: Vec<blob_store::BlobStoreConfig>,
+
+    /// How carefully blobs and database writes are flushed to disk before
+    /// they're considered saved (`"none"`, `"metadata"`, or `"full"`).
+    /// Defaults to `"metadata"`, which never leaves truncated blobs after a
+    /// crash.
+    durability: Option<blob::Durability>,
+
+    /// The user and group that processes run as within the sandbox, as a
+    /// table with `uid`, `gid`, and `subordinate_ids` keys. Defaults to
+    /// UID and GID 1099, without subordinate IDs.
+    sandbox_user: Option<sandbox::SandboxUser>,
+
+    /// Secrets that processes can use, keyed by name, with where to read
+    /// each one from (e.g. `npm_token = { env = "NPM_TOKEN" }` or
+    /// `signing_key = { file = "/path/to/key" }`).
+    #[serde(default)]
+    secrets: HashMap<String, secrets::SecretSource>,
+
+    /// Open the store read-only, such as when it's mounted from an image.
+    /// Builds that are fully cached still work, but anything that needs
+    /// to save new results fails.
+    read_only: Option<bool>,
+
+    /// Domains that downloads, git checkouts, and networked processes may
+    /// contact (e.g. `["github.com", "crates.io"]`), including their
+    /// subdomains. Networked processes reach these hosts through an HTTP
+    /// proxy, and can't make any other connections. When unset, any host
+    /// can be contacted.
+    network_allowlist: Option<Vec<String>>,
+
+    /// The maximum number of processes that run at once. Defaults to 20.
+    max_concurrent_processes: Option<usize>,
+
+    /// The maximum number of downloads (including git checkouts) that run
+    /// at once. Defaults to 20.
+    max_concurrent_downloads: Option<usize>,
+
+    /// The maximum number of blobs saved at once, such as when ingesting
+    /// input files, unpacking archives, or fetching from the registry.
+    /// Defaults to 10.
+    max_concurrent_blob_saves: Option<usize>,
+
+    /// Remember processes that fail for this many seconds, so baking the
+    /// same process again fails immediately with the same error instead of
+    /// running it again (e.g. `3600` for an hour). Useful in CI, where a
+    /// broken target might be requested repeatedly. Pass `--retry-failed`
+    /// to run a failed process again anyway. Disabled when unset.
+    failed_process_cache_ttl: Option<u64>,
+
+    /// HTTP proxies for downloads, the registry, and networked processes,
+    /// as a table with `http`, `https`, and `no_proxy` keys. Any that
+    /// aren't set are read from the `HTTP_PROXY`, `HTTPS_PROXY`, and
+    /// `NO_PROXY` environment variables.
+    #[serde(default)]
+    proxy: network::ProxyConfig,
+
+    /// PEM files with extra CA certificates to trust for downloads and the
+    /// registry, such as for a proxy that intercepts TLS connections.
+    #[serde(default)]
+    ca_certificates: Vec<PathBuf>,
 }
 
 pub enum SyncMessage {
@@ -288,6 +969,10 @@ pub enum SyncMessage {
 #[include = "tslib/**/*.d.ts"]
 pub struct RuntimeFiles;
 
+/// An expected hash, used to validate downloads and other external content.
+/// SHA-1 and MD5 are only supported for interop with upstream checksum
+/// files, and using them logs a warning since they aren't collision
+/// resistant.
 #[serde_with::serde_as]
 #[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 #[serde(tag = "type")]
@@ -297,41 +982,190 @@ pub enum Hash {
         #[serde_as(as = "serde_with::hex::Hex")]
         value: Vec<u8>,
     },
+    Sha512 {
+        #[serde_as(as = "serde_with::hex::Hex")]
+        value: Vec<u8>,
+    },
+    Sha1 {
+        #[serde_as(as = "serde_with::hex::Hex")]
+        value: Vec<u8>,
+    },
+    Md5 {
+        #[serde_as(as = "serde_with::hex::Hex")]
+        value: Vec<u8>,
+    },
+}
+
+impl Hash {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Hash::Sha256 { .. } => HashAlgorithm::Sha256,
+            Hash::Sha512 { .. } => HashAlgorithm::Sha512,
+            Hash::Sha1 { .. } => HashAlgorithm::Sha1,
+            Hash::Md5 { .. } => HashAlgorithm::Md5,
+        }
+    }
+
+    pub fn value(&self) -> &[u8] {
+        match self {
+            Hash::Sha256 { value }
+            | Hash::Sha512 { value }
+            | Hash::Sha1 { value }
+            | Hash::Md5 { value } => value,
+        }
+    }
+
+    pub fn from_value(algorithm: HashAlgorithm, value: Vec<u8>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            value.len() == algorithm.output_len(),
+            "expected {algorithm} hash to be {} bytes, but got {} bytes",
+            algorithm.output_len(),
+            value.len(),
+        );
+
+        let hash = match algorithm {
+            HashAlgorithm::Sha256 => Hash::Sha256 { value },
+            HashAlgorithm::Sha512 => Hash::Sha512 { value },
+            HashAlgorithm::Sha1 => Hash::Sha1 { value },
+            HashAlgorithm::Md5 => Hash::Md5 { value },
+        };
+        Ok(hash)
+    }
+
+    /// Parse a [Subresource Integrity](https://www.w3.org/TR/SRI/) string,
+    /// like `sha256-<base64>`. If the string lists multiple hashes, the
+    /// strongest one is used, and unknown algorithms are ignored.
+    pub fn from_sri(sri: &str) -> anyhow::Result<Self> {
+        let mut strongest: Option<Hash> = None;
+        for sri_hash in sri.split_ascii_whitespace() {
+            // Anything after a `?` is an option, which we don't use
+            let sri_hash = sri_hash.split_once('?').map_or(sri_hash, |(hash, _)| hash);
+            let Some((algorithm, value)) = sri_hash.split_once('-') else {
+                anyhow::bail!("invalid SRI hash {sri_hash:?}");
+            };
+            let Ok(algorithm) = algorithm.parse::<HashAlgorithm>() else {
+                continue;
+            };
+
+            let value = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, value)
+                .with_context(|| format!("invalid base64 in SRI hash {sri_hash:?}"))?;
+            let hash = Hash::from_value(algorithm, value)?;
+
+            let is_stronger = match &strongest {
+                Some(strongest) => hash.algorithm() > strongest.algorithm(),
+                None => true,
+            };
+            if is_stronger {
+                strongest = Some(hash);
+            }
+        }
+
+        strongest.with_context(|| format!("no supported hash in SRI string {sri:?}"))
+    }
+
+    pub fn to_sri(&self) -> String {
+        let value =
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, self.value());
+        format!("{}-{value}", self.algorithm())
+    }
 }
 
 impl std::fmt::Display for Hash {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm(), hex::encode(self.value()))
+    }
+}
+
+impl std::str::FromStr for Hash {
+    type Err = anyhow::Error;
+
+    /// Parse either the display form of a hash (`sha256:<hex>`) or an SRI
+    /// string (`sha256-<base64>`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((algorithm, value)) => {
+                let algorithm = algorithm.parse::<HashAlgorithm>()?;
+                let value =
+                    hex::decode(value).with_context(|| format!("invalid hex in hash {s:?}"))?;
+                Hash::from_value(algorithm, value)
+            }
+            None => Hash::from_sri(s),
+        }
+    }
+}
+
+/// The supported hash algorithms, ordered from weakest to strongest.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, strum::EnumString, strum::Display,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// The length of a hash value in bytes.
+    pub fn output_len(&self) -> usize {
         match self {
-            Hash::Sha256 { value } => write!(f, "sha256:{}", hex::encode(value)),
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
         }
     }
+
+    /// Returns true for algorithms that are only supported for interop,
+    /// since they aren't collision resistant.
+    pub fn is_weak(&self) -> bool {
+        matches!(self, Self::Md5 | Self::Sha1)
+    }
 }
 
 pub enum Hasher {
     Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
 }
 
 impl Hasher {
     pub fn for_hash(hash: &Hash) -> Self {
-        match hash {
-            Hash::Sha256 { .. } => Self::Sha256(sha2::Sha256::new()),
+        let algorithm = hash.algorithm();
+        if algorithm.is_weak() {
+            tracing::warn!(%hash, "validating with {algorithm}, which is not collision resistant. Consider using sha256 instead");
+        }
+
+        Self::for_algorithm(algorithm)
+    }
+
+    pub fn for_algorithm(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(sha2::Sha512::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Md5 => Self::Md5(md5::Md5::new()),
         }
     }
 
     pub fn update(&mut self, bytes: &[u8]) {
         match self {
             Self::Sha256(hasher) => hasher.update(bytes),
+            Self::Sha512(hasher) => hasher.update(bytes),
+            Self::Sha1(hasher) => hasher.update(bytes),
+            Self::Md5(hasher) => hasher.update(bytes),
         }
     }
 
     pub fn finish(self) -> anyhow::Result<Hash> {
-        match self {
-            Self::Sha256(hasher) => {
-                let hash = hasher.finalize();
-                Ok(Hash::Sha256 {
-                    value: hash.as_slice().to_vec(),
-                })
-            }
-        }
+        let (algorithm, value) = match self {
+            Self::Sha256(hasher) => (HashAlgorithm::Sha256, hasher.finalize().to_vec()),
+            Self::Sha512(hasher) => (HashAlgorithm::Sha512, hasher.finalize().to_vec()),
+            Self::Sha1(hasher) => (HashAlgorithm::Sha1, hasher.finalize().to_vec()),
+            Self::Md5(hasher) => (HashAlgorithm::Md5, hasher.finalize().to_vec()),
+        };
+        Hash::from_value(algorithm, value)
     }
 }
@@ -0,0 +1,493 @@
+use std::{
+    collections::{BTreeSet, HashSet, VecDeque},
+    path::Path,
+};
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    annotations::Annotations,
+    error_codes::{CodedError, ErrorCode},
+    project::{ProjectHash, Projects},
+    recipe::{Artifact, RecipeHash},
+    references::RecipeReferences,
+    Brioche,
+};
+
+/// The name of the license policy file, placed next to the root project's
+/// `project.bri`.
+pub const LICENSE_POLICY_FILENAME: &str = "brioche_licenses.toml";
+
+/// Which licenses a build's output may include. Licenses are matched
+/// case-insensitively against each license in a declared SPDX expression.
+///
+/// ```toml
+/// # When set, only these licenses are allowed
+/// allow = ["MIT", "Apache-2.0", "BSD-3-Clause"]
+///
+/// # These licenses are never allowed
+/// deny = ["GPL-3.0-only"]
+///
+/// # What to do when a disallowed license is found ("error" or "warn")
+/// on_violation = "error"
+///
+/// # What to do when a project or output doesn't declare a license
+/// on_unknown = "warn"
+/// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LicensePolicy {
+    #[serde(default)]
+    pub allow: Option<BTreeSet<String>>,
+
+    #[serde(default)]
+    pub deny: BTreeSet<String>,
+
+    #[serde(default = "default_on_violation")]
+    pub on_violation: PolicyAction,
+
+    #[serde(default)]
+    pub on_unknown: PolicyAction,
+}
+
+impl Default for LicensePolicy {
+    fn default() -> Self {
+        Self {
+            allow: None,
+            deny: BTreeSet::new(),
+            on_violation: default_on_violation(),
+            on_unknown: PolicyAction::default(),
+        }
+    }
+}
+
+fn default_on_violation() -> PolicyAction {
+    PolicyAction::Error
+}
+
+impl LicensePolicy {
+    /// Check a license expression. An expression is allowed if any of its
+    /// `OR` alternatives only uses allowed licenses.
+    pub fn check(&self, license: Option<&str>) -> LicenseStatus {
+        let Some(license) = license else {
+            return LicenseStatus::Unknown;
+        };
+        let Ok(expression) = LicenseExpression::parse(license) else {
+            return LicenseStatus::Unknown;
+        };
+
+        if expression.is_allowed(&|id| self.allows_id(id)) {
+            LicenseStatus::Allowed
+        } else {
+            LicenseStatus::Disallowed
+        }
+    }
+
+    fn allows_id(&self, id: &str) -> bool {
+        // For an exception like `GPL-2.0-only WITH Classpath-exception-2.0`,
+        // the list can either name the whole term or just the license
+        let base_id = id.split(" WITH ").next().unwrap_or(id);
+        let matches = |listed: &String| {
+            listed.eq_ignore_ascii_case(id) || listed.eq_ignore_ascii_case(base_id)
+        };
+
+        if self.deny.iter().any(matches) {
+            return false;
+        }
+
+        match &self.allow {
+            Some(allow) => allow.iter().any(matches),
+            None => true,
+        }
+    }
+
+    fn action(&self, status: LicenseStatus) -> PolicyAction {
+        match status {
+            LicenseStatus::Allowed => PolicyAction::Allow,
+            LicenseStatus::Disallowed => self.on_violation,
+            LicenseStatus::Unknown => self.on_unknown,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    #[default]
+    Allow,
+    Warn,
+    Error,
+}
+
+pub async fn read_license_policy(path: &Path) -> anyhow::Result<Option<LicensePolicy>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read license policy {}", path.display()));
+        }
+    };
+
+    let policy = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse license policy {}", path.display()))?;
+    Ok(Some(policy))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseStatus {
+    Allowed,
+    Disallowed,
+
+    /// No license was declared, or the license expression couldn't be
+    /// parsed.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LicenseSource {
+    /// A project in the build's dependency graph, declared with `license`
+    /// in its project definition.
+    #[serde(rename_all = "camelCase")]
+    Project {
+        name: Option<String>,
+        project_hash: ProjectHash,
+
+        /// The names of the dependencies leading to this project from the
+        /// root project (empty for the root project itself).
+        dependency_path: Vec<String>,
+    },
+
+    /// An artifact within the output, declared with a `license`
+    /// annotation (usually on a download).
+    #[serde(rename_all = "camelCase")]
+    Artifact {
+        name: Option<String>,
+        version: Option<String>,
+        artifact_hash: RecipeHash,
+    },
+}
+
+impl std::fmt::Display for LicenseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Project {
+                name,
+                dependency_path,
+                ..
+            } => {
+                let name = name.as_deref().unwrap_or("<unnamed>");
+                if dependency_path.is_empty() {
+                    write!(f, "project {name}")
+                } else {
+                    write!(
+                        f,
+                        "project {name} (dependency path: {})",
+                        dependency_path.join(" -> ")
+                    )
+                }
+            }
+            Self::Artifact {
+                name,
+                version,
+                artifact_hash,
+            } => match (name, version) {
+                (Some(name), Some(version)) => write!(f, "artifact {name} {version}"),
+                (Some(name), None) => write!(f, "artifact {name}"),
+                (None, _) => write!(f, "artifact {artifact_hash}"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseEntry {
+    pub source: LicenseSource,
+    pub license: Option<String>,
+    pub status: LicenseStatus,
+    pub action: PolicyAction,
+}
+
+impl std::fmt::Display for LicenseEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let license = self.license.as_deref().unwrap_or("no license declared");
+        write!(f, "{}: {license}", self.source)
+    }
+}
+
+/// A machine-readable report of the licenses found for a build.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseReport {
+    /// The policy the licenses were checked against. Without a policy,
+    /// every license is allowed.
+    pub policy: Option<LicensePolicy>,
+    pub entries: Vec<LicenseEntry>,
+}
+
+impl LicenseReport {
+    pub fn warnings(&self) -> impl Iterator<Item = &LicenseEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.action == PolicyAction::Warn)
+    }
+
+    pub fn violations(&self) -> impl Iterator<Item = &LicenseEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.action == PolicyAction::Error)
+    }
+
+    /// Return an error if any license violates the policy.
+    pub fn ensure_allowed(&self) -> anyhow::Result<()> {
+        let violations: Vec<_> = self.violations().collect();
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = "build output includes licenses not allowed by policy:".to_string();
+        for entry in violations {
+            message.push_str(&format!("\n- {entry}"));
+        }
+        Err(CodedError::new(ErrorCode::LICENSE_POLICY_VIOLATION, message).into())
+    }
+}
+
+/// Read the license policy file of a project, if it has one.
+pub async fn project_license_policy(
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<Option<LicensePolicy>> {
+    let project_root = projects.project_root(project_hash)?;
+    read_license_policy(&project_root.join(LICENSE_POLICY_FILENAME)).await
+}
+
+/// Collect the licenses of every project in the root project's dependency
+/// graph, and of every annotated artifact within the build output, then
+/// check them against the license policy.
+pub async fn scan_licenses(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+    output: &Artifact,
+    policy: Option<&LicensePolicy>,
+) -> anyhow::Result<LicenseReport> {
+    let default_policy = LicensePolicy::default();
+    let check_policy = policy.unwrap_or(&default_policy);
+
+    let mut entries = vec![];
+    let mut push_entry = |source: LicenseSource, license: Option<String>| {
+        let status = check_policy.check(license.as_deref());
+        entries.push(LicenseEntry {
+            source,
+            license,
+            status,
+            action: check_policy.action(status),
+        });
+    };
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([(vec![], project_hash)]);
+    while let Some((dependency_path, project_hash)) = queue.pop_front() {
+        if !visited.insert(project_hash) {
+            continue;
+        }
+
+        let project = projects.project(project_hash)?;
+        push_entry(
+            LicenseSource::Project {
+                name: project.definition.name.clone(),
+                project_hash,
+                dependency_path: dependency_path.clone(),
+            },
+            project.definition.license.clone(),
+        );
+
+        for (name, dependency_hash) in project.dependencies() {
+            let mut path = dependency_path.clone();
+            path.push(name.to_string());
+            queue.push_back((path, dependency_hash));
+        }
+    }
+
+    for (artifact_hash, annotations) in output_annotations(brioche, output).await? {
+        if annotations.license.is_none() {
+            continue;
+        }
+
+        push_entry(
+            LicenseSource::Artifact {
+                name: annotations.name,
+                version: annotations.version,
+                artifact_hash,
+            },
+            annotations.license,
+        );
+    }
+
+    Ok(LicenseReport {
+        policy: policy.cloned(),
+        entries,
+    })
+}
+
+/// Get the annotations of the output artifact and every artifact it
+/// contains.
+async fn output_annotations(
+    brioche: &Brioche,
+    output: &Artifact,
+) -> anyhow::Result<Vec<(RecipeHash, Annotations)>> {
+    let mut references = RecipeReferences::default();
+    crate::references::recipe_references(brioche, &mut references, [output.hash()]).await?;
+
+    let mut artifact_hashes = references
+        .recipes
+        .keys()
+        .map(|hash| hash.to_string())
+        .collect::<Vec<_>>();
+    artifact_hashes.sort();
+    let artifact_hashes = serde_json::to_string(&artifact_hashes)?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT artifact_hash, annotations_json
+            FROM artifact_annotations
+            WHERE artifact_hash IN (SELECT value FROM json_each(?))
+            ORDER BY artifact_hash
+        "#,
+        artifact_hashes,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    records
+        .into_iter()
+        .map(|record| {
+            let artifact_hash: RecipeHash = record.artifact_hash.parse()?;
+            let annotations = serde_json::from_str(&record.annotations_json)
+                .with_context(|| format!("invalid annotations for artifact {artifact_hash}"))?;
+            anyhow::Ok((artifact_hash, annotations))
+        })
+        .collect()
+}
+
+/// A parsed SPDX license expression. License exceptions are kept as part
+/// of the license (e.g. `GPL-2.0-only WITH Classpath-exception-2.0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseExpression {
+    License(String),
+    And(Vec<LicenseExpression>),
+    Or(Vec<LicenseExpression>),
+}
+
+impl LicenseExpression {
+    pub fn parse(expression: &str) -> anyhow::Result<Self> {
+        let expression_spaced = expression.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = expression_spaced.split_whitespace().collect();
+        let mut parser = ExpressionParser {
+            tokens: &tokens,
+            position: 0,
+        };
+
+        let parsed = parser
+            .parse_or()
+            .with_context(|| format!("invalid license expression {expression:?}"))?;
+        anyhow::ensure!(
+            parser.position == tokens.len(),
+            "invalid license expression {expression:?}: unexpected {:?}",
+            tokens[parser.position]
+        );
+        Ok(parsed)
+    }
+
+    pub fn is_allowed(&self, allows_id: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            Self::License(id) => allows_id(id),
+            Self::And(terms) => terms.iter().all(|term| term.is_allowed(allows_id)),
+            Self::Or(terms) => terms.iter().any(|term| term.is_allowed(allows_id)),
+        }
+    }
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> ExpressionParser<'a> {
+    fn peek_operator(&self, operator: &str) -> bool {
+        self.tokens
+            .get(self.position)
+            .is_some_and(|token| token.eq_ignore_ascii_case(operator))
+    }
+
+    fn next(&mut self) -> anyhow::Result<&'a str> {
+        let token = *self
+            .tokens
+            .get(self.position)
+            .context("unexpected end of expression")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<LicenseExpression> {
+        let mut terms = vec![self.parse_and()?];
+        while self.peek_operator("OR") {
+            self.position += 1;
+            terms.push(self.parse_and()?);
+        }
+
+        if terms.len() == 1 {
+            Ok(terms.remove(0))
+        } else {
+            Ok(LicenseExpression::Or(terms))
+        }
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<LicenseExpression> {
+        let mut terms = vec![self.parse_term()?];
+        while self.peek_operator("AND") {
+            self.position += 1;
+            terms.push(self.parse_term()?);
+        }
+
+        if terms.len() == 1 {
+            Ok(terms.remove(0))
+        } else {
+            Ok(LicenseExpression::And(terms))
+        }
+    }
+
+    fn parse_term(&mut self) -> anyhow::Result<LicenseExpression> {
+        let token = self.next()?;
+        if token == "(" {
+            let expression = self.parse_or()?;
+            anyhow::ensure!(self.next()? == ")", "expected `)`");
+            return Ok(expression);
+        }
+
+        let is_operator = ["AND", "OR", "WITH", ")"]
+            .iter()
+            .any(|operator| token.eq_ignore_ascii_case(operator));
+        anyhow::ensure!(!is_operator, "expected a license, found {token:?}");
+
+        let mut license = token.to_string();
+        if self.peek_operator("WITH") {
+            self.position += 1;
+            let exception = self.next()?;
+            license = format!("{license} WITH {exception}");
+        }
+
+        Ok(LicenseExpression::License(license))
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use sqlx::Connection as _;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::{
+    recipe::{Artifact, Recipe, RecipeHash},
+    Brioche,
+};
+
+/// Another Brioche home directory that's used as a read-only source of
+/// blobs and bake results, such as a team's shared store on a network
+/// mount. Mirrors are consulted after the local store, but before the
+/// registry. Nothing is ever written to a mirror. A mirror's blobs are
+/// read through a [`crate::blob_store::FilesystemBlobStore`].
+pub struct MirrorStore {
+    path: PathBuf,
+    db_conn: OnceCell<Option<Mutex<sqlx::SqliteConnection>>>,
+}
+
+impl MirrorStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            db_conn: OnceCell::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Look up a bake result in the mirror's database. The artifact's blobs
+    /// may still need to be fetched.
+    pub async fn get_bake(&self, recipe_hash: RecipeHash) -> anyhow::Result<Option<Artifact>> {
+        let Some(db_conn) = self.db_conn().await else {
+            return Ok(None);
+        };
+
+        let mut db_conn = db_conn.lock().await;
+        let input_hash = recipe_hash.to_string();
+        let artifact_json = sqlx::query_scalar!(
+            r#"
+                SELECT output_artifacts.recipe_json
+                FROM bakes
+                INNER JOIN recipes AS output_artifacts
+                    ON bakes.output_hash = output_artifacts.recipe_hash
+                WHERE bakes.input_hash = ?
+                LIMIT 1
+            "#,
+            input_hash,
+        )
+        .fetch_optional(&mut *db_conn)
+        .await?;
+
+        match artifact_json {
+            Some(artifact_json) => {
+                let artifact = serde_json::from_str(&artifact_json)?;
+                Ok(Some(artifact))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Look up recipes in the mirror's database, skipping any that aren't
+    /// found.
+    pub async fn get_recipes(
+        &self,
+        recipe_hashes: &HashSet<RecipeHash>,
+    ) -> anyhow::Result<HashMap<RecipeHash, Recipe>> {
+        let Some(db_conn) = self.db_conn().await else {
+            return Ok(HashMap::new());
+        };
+
+        let mut db_conn = db_conn.lock().await;
+        let mut recipes = HashMap::new();
+        for &recipe_hash in recipe_hashes {
+            let recipe_hash_value = recipe_hash.to_string();
+            let recipe_json = sqlx::query_scalar!(
+                "SELECT recipe_json FROM recipes WHERE recipe_hash = ? LIMIT 1",
+                recipe_hash_value,
+            )
+            .fetch_optional(&mut *db_conn)
+            .await?;
+            let Some(recipe_json) = recipe_json else {
+                continue;
+            };
+
+            let recipe: Recipe = serde_json::from_str(&recipe_json)?;
+            let actual_hash = recipe.hash();
+            anyhow::ensure!(
+                actual_hash == recipe_hash,
+                "expected recipe hash from mirror store to be {recipe_hash}, but was {actual_hash}"
+            );
+            recipes.insert(recipe_hash, recipe);
+        }
+
+        Ok(recipes)
+    }
+
+    /// Open the mirror's database read-only the first time it's needed.
+    /// If it can't be opened (e.g. because the mirror isn't mounted), the
+    /// mirror's bakes are skipped for the rest of the session.
+    async fn db_conn(&self) -> Option<&Mutex<sqlx::SqliteConnection>> {
+        let db_conn = self
+            .db_conn
+            .get_or_init(|| async {
+                let database_path = self.path.join("brioche.db");
+                let db_conn_options = sqlx::sqlite::SqliteConnectOptions::new()
+                    .filename(&database_path)
+                    .read_only(true);
+                match sqlx::SqliteConnection::connect_with(&db_conn_options).await {
+                    Ok(db_conn) => Some(Mutex::new(db_conn)),
+                    Err(error) => {
+                        tracing::warn!(
+                            database_path = %database_path.display(),
+                            "failed to open mirror store database: {error}"
+                        );
+                        None
+                    }
+                }
+            })
+            .await;
+        db_conn.as_ref()
+    }
+}
+
+impl std::fmt::Debug for MirrorStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MirrorStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Find a bake result from the first mirror store that has one. Errors
+/// from a mirror are logged and otherwise ignored, so an unavailable
+/// mirror falls back to baking normally.
+pub async fn find_bake(brioche: &Brioche, recipe_hash: RecipeHash) -> Option<Artifact> {
+    for mirror in brioche.mirror_stores.iter() {
+        match mirror.get_bake(recipe_hash).await {
+            Ok(Some(artifact)) => {
+                tracing::debug!(%recipe_hash, mirror = %mirror.path().display(), "got bake result from mirror store");
+                return Some(artifact);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                tracing::warn!(%recipe_hash, mirror = %mirror.path().display(), "failed to get bake from mirror store: {error:#}");
+            }
+        }
+    }
+
+    None
+}
+
+/// Find recipes from the mirror stores, trying each mirror in order for
+/// the recipes that haven't been found yet.
+pub async fn find_recipes(
+    brioche: &Brioche,
+    recipe_hashes: &HashSet<RecipeHash>,
+) -> HashMap<RecipeHash, Recipe> {
+    let mut recipes = HashMap::new();
+    let mut remaining = recipe_hashes.clone();
+    for mirror in brioche.mirror_stores.iter() {
+        if remaining.is_empty() {
+            break;
+        }
+
+        match mirror.get_recipes(&remaining).await {
+            Ok(mirror_recipes) => {
+                for (recipe_hash, recipe) in mirror_recipes {
+                    remaining.remove(&recipe_hash);
+                    recipes.insert(recipe_hash, recipe);
+                }
+            }
+            Err(error) => {
+                tracing::warn!(mirror = %mirror.path().display(), "failed to get recipes from mirror store: {error:#}");
+            }
+        }
+    }
+
+    recipes
+}
@@ -0,0 +1,720 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+
+use crate::{
+    error_codes::{CodedError, ErrorCode},
+    recipe::RecipeHash,
+    Brioche,
+};
+
+/// The port the network proxy listens on within the sandbox. Processes
+/// run in their own network namespace when using the proxy, so this can
+/// be the same for every process.
+pub const SANDBOX_PROXY_PORT: u16 = 3128;
+
+/// The longest request head the network proxy accepts.
+const MAX_REQUEST_HEAD_LENGTH: usize = 64 * 1024;
+
+/// Domains that downloads, git checkouts, and networked processes are
+/// allowed to contact. Each entry allows the domain itself along with
+/// all of its subdomains, so `example.com` also allows
+/// `cdn.example.com`. A leading `*.` is accepted and ignored, and `*`
+/// allows every host.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkAllowlist {
+    domains: BTreeSet<String>,
+}
+
+impl NetworkAllowlist {
+    pub fn new(domains: impl IntoIterator<Item = String>) -> Self {
+        let domains = domains
+            .into_iter()
+            .map(|domain| {
+                let domain = normalize_host(&domain);
+                match domain.strip_prefix("*.") {
+                    Some(domain) => domain.to_string(),
+                    None => domain,
+                }
+            })
+            .collect();
+        Self { domains }
+    }
+
+    pub fn allows(&self, host: &str) -> bool {
+        if self.domains.contains("*") {
+            return true;
+        }
+
+        let host = normalize_host(host);
+        let mut candidate = &*host;
+        loop {
+            if self.domains.contains(candidate) {
+                return true;
+            }
+
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// A host that a recipe contacted (or tried to contact) while baking.
+/// Recorded as part of the recipe's provenance.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactedHost {
+    pub host: String,
+    pub port: u16,
+    /// False if the host was blocked by the network allowlist.
+    pub allowed: bool,
+}
+
+pub fn host_not_allowed_error(host: &str) -> anyhow::Error {
+    CodedError::new(
+        ErrorCode::NETWORK_HOST_NOT_ALLOWED,
+        format!("host {host:?} is not in the network allowlist"),
+    )
+    .into()
+}
+
+/// Check that the host of a URL is allowed before contacting it, and
+/// record it as contacted by the recipe. URLs without a host are always
+/// allowed.
+pub async fn check_url(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+    url: &url::Url,
+) -> anyhow::Result<()> {
+    let Some(host) = url.host_str() else {
+        return Ok(());
+    };
+
+    let allowed = brioche
+        .network_allowlist
+        .as_ref()
+        .map_or(true, |allowlist| allowlist.allows(host));
+    let contacted_host = ContactedHost {
+        host: normalize_host(host),
+        port: url.port_or_known_default().unwrap_or_default(),
+        allowed,
+    };
+    record_contacted_hosts(brioche, recipe_hash, &BTreeSet::from([contacted_host])).await?;
+
+    if !allowed {
+        return Err(host_not_allowed_error(host));
+    }
+
+    Ok(())
+}
+
+/// The most redirects to follow for a single request, matching reqwest's
+/// default policy.
+const MAX_REDIRECTS: usize = 10;
+
+tokio::task_local! {
+    /// Collects the hosts redirected to by requests sent with
+    /// [`send_recording_redirects`].
+    static REDIRECT_HOSTS: Arc<Mutex<BTreeSet<ContactedHost>>>;
+}
+
+/// Build the redirect policy for the download client. Each redirect is
+/// checked against the allowlist before it's followed, so a download can't
+/// be bounced to a host outside of it.
+pub fn redirect_policy(allowlist: Option<Arc<NetworkAllowlist>>) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+
+        let Some(host) = attempt.url().host_str() else {
+            return attempt.follow();
+        };
+        let allowed = allowlist
+            .as_ref()
+            .map_or(true, |allowlist| allowlist.allows(host));
+        let contacted_host = ContactedHost {
+            host: normalize_host(host),
+            port: attempt.url().port_or_known_default().unwrap_or_default(),
+            allowed,
+        };
+        let _ = REDIRECT_HOSTS.try_with(|hosts| {
+            let mut hosts = hosts.lock().expect("redirect hosts poisoned");
+            hosts.insert(contacted_host);
+        });
+
+        if allowed {
+            attempt.follow()
+        } else {
+            attempt.error(format!("host {host:?} is not in the network allowlist"))
+        }
+    })
+}
+
+/// Send a request with the download client, recording each host it gets
+/// redirected to as contacted by the recipe. The redirect policy checks
+/// the allowlist itself, so this only needs to turn a blocked redirect
+/// into the proper error.
+pub async fn send_recording_redirects(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+    request: reqwest_middleware::RequestBuilder,
+) -> anyhow::Result<reqwest::Response> {
+    let redirect_hosts = Arc::new(Mutex::new(BTreeSet::new()));
+    let response = REDIRECT_HOSTS
+        .scope(redirect_hosts.clone(), request.send())
+        .await;
+
+    let redirect_hosts =
+        std::mem::take(&mut *redirect_hosts.lock().expect("redirect hosts poisoned"));
+    record_contacted_hosts(brioche, recipe_hash, &redirect_hosts).await?;
+
+    if let Some(blocked_host) = redirect_hosts.iter().find(|host| !host.allowed) {
+        return Err(host_not_allowed_error(&blocked_host.host));
+    }
+
+    Ok(response?)
+}
+
+pub async fn record_contacted_hosts(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+    contacted_hosts: &BTreeSet<ContactedHost>,
+) -> anyhow::Result<()> {
+    if contacted_hosts.is_empty() {
+        return Ok(());
+    }
+
+    let recipe_hash_value = recipe_hash.to_string();
+    let contacted_hosts_json = serde_json::to_string(contacted_hosts)?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO recipe_network_hosts (recipe_hash, host, port, allowed)
+            SELECT
+                ?,
+                value->>'host',
+                value->>'port',
+                value->>'allowed'
+            FROM json_each(?)
+            WHERE true
+            ON CONFLICT (recipe_hash, host, port, allowed) DO NOTHING
+        "#,
+        recipe_hash_value,
+        contacted_hosts_json,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Get the hosts a recipe contacted while it was baked locally.
+pub async fn contacted_hosts(
+    brioche: &Brioche,
+    recipe_hash: RecipeHash,
+) -> anyhow::Result<Vec<ContactedHost>> {
+    let recipe_hash_value = recipe_hash.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT host, port, allowed
+            FROM recipe_network_hosts
+            WHERE recipe_hash = ?
+            ORDER BY host, port, allowed
+        "#,
+        recipe_hash_value,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let contacted_hosts = records
+        .into_iter()
+        .map(|record| {
+            anyhow::Ok(ContactedHost {
+                host: record.host,
+                port: record.port.try_into()?,
+                allowed: record.allowed != 0,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(contacted_hosts)
+}
+
+/// Proxies for outgoing HTTP requests. Each one is read from the config
+/// file, falling back to the standard environment variables (`HTTP_PROXY`,
+/// `HTTPS_PROXY`, and `NO_PROXY`, or their lowercase forms).
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct ProxyConfig {
+    /// The proxy for `http://` URLs.
+    pub http: Option<url::Url>,
+    /// The proxy for `https://` URLs.
+    pub https: Option<url::Url>,
+    /// A comma-separated list of hosts to contact directly instead of
+    /// through a proxy. Each entry matches the host and its subdomains,
+    /// and `*` matches every host.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Fill in any proxies that aren't set from the environment.
+    pub fn with_env_fallback(self) -> anyhow::Result<Self> {
+        let env_var = |names: [&str; 2]| {
+            names
+                .into_iter()
+                .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        };
+        let env_url = |names: [&str; 2]| {
+            env_var(names)
+                .map(|value| {
+                    value
+                        .parse::<url::Url>()
+                        .with_context(|| format!("invalid proxy URL in ${}: {value:?}", names[0]))
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            http: self.http.map_or_else(
+                || env_url(["HTTP_PROXY", "http_proxy"]),
+                |url| Ok(Some(url)),
+            )?,
+            https: self.https.map_or_else(
+                || env_url(["HTTPS_PROXY", "https_proxy"]),
+                |url| Ok(Some(url)),
+            )?,
+            no_proxy: self.no_proxy.or_else(|| env_var(["NO_PROXY", "no_proxy"])),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none()
+    }
+
+    /// Get the proxy to use for a URL with the given scheme and host, or
+    /// `None` to connect directly.
+    pub fn proxy_for(&self, scheme: &str, host: &str) -> Option<&url::Url> {
+        let proxy = match scheme {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        }?;
+
+        let host = normalize_host(host);
+        let bypassed = self
+            .no_proxy
+            .iter()
+            .flat_map(|no_proxy| no_proxy.split(','))
+            .map(|entry| normalize_host(entry.trim()))
+            .filter(|entry| !entry.is_empty())
+            .any(|entry| {
+                let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+                entry == "*" || host == entry || host.ends_with(&format!(".{entry}"))
+            });
+        if bypassed {
+            None
+        } else {
+            Some(proxy)
+        }
+    }
+
+    /// Build the proxies for a `reqwest` client.
+    pub fn reqwest_proxies(&self) -> anyhow::Result<Vec<reqwest::Proxy>> {
+        let no_proxy = self
+            .no_proxy
+            .as_deref()
+            .and_then(reqwest::NoProxy::from_string);
+        let mut proxies = vec![];
+        if let Some(http) = &self.http {
+            let proxy = reqwest::Proxy::http(http.clone())
+                .with_context(|| format!("invalid HTTP proxy {http}"))?;
+            proxies.push(proxy.no_proxy(no_proxy.clone()));
+        }
+        if let Some(https) = &self.https {
+            let proxy = reqwest::Proxy::https(https.clone())
+                .with_context(|| format!("invalid HTTPS proxy {https}"))?;
+            proxies.push(proxy.no_proxy(no_proxy.clone()));
+        }
+        Ok(proxies)
+    }
+
+    /// Environment variables that point processes at these proxies.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut env_vars = vec![];
+        if let Some(http) = &self.http {
+            env_vars.push(("http_proxy", http.to_string()));
+            env_vars.push(("HTTP_PROXY", http.to_string()));
+        }
+        if let Some(https) = &self.https {
+            env_vars.push(("https_proxy", https.to_string()));
+            env_vars.push(("HTTPS_PROXY", https.to_string()));
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            env_vars.push(("no_proxy", no_proxy.clone()));
+            env_vars.push(("NO_PROXY", no_proxy.clone()));
+        }
+        env_vars
+    }
+}
+
+/// Options shared by every HTTP client Brioche uses, for the registry and
+/// for downloads.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClientOptions {
+    /// Send requests through these proxies. When empty, proxies are
+    /// detected from the environment.
+    pub proxies: Vec<reqwest::Proxy>,
+    /// Trust these certificates in addition to the built-in root
+    /// certificates, such as for a proxy that intercepts TLS.
+    pub root_certificates: Vec<reqwest::Certificate>,
+}
+
+impl HttpClientOptions {
+    /// Build the options from the proxy config and a list of PEM files
+    /// with extra CA certificates.
+    pub async fn new(
+        proxy: &ProxyConfig,
+        ca_certificate_paths: &[std::path::PathBuf],
+    ) -> anyhow::Result<Self> {
+        let proxies = proxy.reqwest_proxies()?;
+
+        let mut root_certificates = vec![];
+        for path in ca_certificate_paths {
+            let pem = tokio::fs::read(path).await.with_context(|| {
+                format!("failed to read CA certificates from {}", path.display())
+            })?;
+            let certificate = reqwest::Certificate::from_pem(&pem)
+                .with_context(|| format!("invalid CA certificates in {}", path.display()))?;
+            root_certificates.push(certificate);
+        }
+
+        Ok(Self {
+            proxies,
+            root_certificates,
+        })
+    }
+
+    pub fn configure(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        for proxy in &self.proxies {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    }
+}
+
+/// An HTTP proxy that only connects to hosts in the network allowlist.
+/// Listens on an abstract Unix socket, which the sandbox forwards
+/// connections to from [`SANDBOX_PROXY_PORT`]. Supports `CONNECT` (used
+/// for HTTPS) and plain HTTP requests. Allowed requests are sent through
+/// the upstream proxies, if any are configured.
+pub struct NetworkProxy {
+    socket_name: String,
+    contacted_hosts: Arc<Mutex<BTreeSet<ContactedHost>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl NetworkProxy {
+    pub fn start(allowlist: Arc<NetworkAllowlist>, upstream: ProxyConfig) -> anyhow::Result<Self> {
+        let socket_name = format!("brioche-network-proxy-{}", ulid::Ulid::new());
+        let listener = bind_abstract(&socket_name)
+            .with_context(|| format!("failed to bind network proxy socket {socket_name:?}"))?;
+
+        let upstream = Arc::new(upstream);
+        let contacted_hosts = Arc::new(Mutex::new(BTreeSet::new()));
+        let task = tokio::spawn({
+            let contacted_hosts = contacted_hosts.clone();
+            async move {
+                // Connections are spawned into the set, so they're all
+                // closed when the proxy stops
+                let mut connections = tokio::task::JoinSet::new();
+                loop {
+                    let stream = match listener.accept().await {
+                        Ok((stream, _)) => stream,
+                        Err(error) => {
+                            tracing::warn!("failed to accept network proxy connection: {error}");
+                            continue;
+                        }
+                    };
+
+                    let allowlist = allowlist.clone();
+                    let upstream = upstream.clone();
+                    let contacted_hosts = contacted_hosts.clone();
+                    connections.spawn(async move {
+                        let result = handle_proxy_connection(
+                            stream,
+                            &allowlist,
+                            &upstream,
+                            &contacted_hosts,
+                        )
+                        .await;
+                        if let Err(error) = result {
+                            tracing::debug!("network proxy connection failed: {error:#}");
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(Self {
+            socket_name,
+            contacted_hosts,
+            task,
+        })
+    }
+
+    /// The name of the abstract Unix socket the proxy listens on.
+    pub fn socket_name(&self) -> &str {
+        &self.socket_name
+    }
+
+    /// Stop the proxy, closing any open connections, and return every
+    /// host that was requested through it.
+    pub fn stop(self) -> BTreeSet<ContactedHost> {
+        self.task.abort();
+        let mut contacted_hosts = self
+            .contacted_hosts
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        std::mem::take(&mut *contacted_hosts)
+    }
+}
+
+fn bind_abstract(socket_name: &str) -> anyhow::Result<tokio::net::UnixListener> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            use std::os::linux::net::SocketAddrExt as _;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(socket_name)?;
+            let listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::UnixListener::from_std(listener)?;
+            Ok(listener)
+        } else {
+            let _ = socket_name;
+            anyhow::bail!("the network proxy is only supported on Linux");
+        }
+    }
+}
+
+async fn read_head<R>(reader: &mut R) -> anyhow::Result<String>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    let mut head = String::new();
+    loop {
+        let length = reader.read_line(&mut head).await?;
+        anyhow::ensure!(length > 0, "connection closed before end of HTTP head");
+        anyhow::ensure!(
+            head.len() <= MAX_REQUEST_HEAD_LENGTH,
+            "HTTP head is too long"
+        );
+        if head.ends_with("\r\n\r\n") || head == "\r\n" {
+            return Ok(head);
+        }
+    }
+}
+
+/// Build a `Proxy-Authorization` header from the credentials in a proxy
+/// URL, if it has any.
+fn proxy_authorization_header(proxy: &url::Url) -> Option<String> {
+    if proxy.username().is_empty() {
+        return None;
+    }
+
+    let username = urlencoding::decode(proxy.username()).ok()?;
+    let password = urlencoding::decode(proxy.password().unwrap_or_default()).ok()?;
+    let credentials = base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        format!("{username}:{password}"),
+    );
+    Some(format!("Proxy-Authorization: Basic {credentials}\r\n"))
+}
+
+async fn handle_proxy_connection(
+    stream: tokio::net::UnixStream,
+    allowlist: &NetworkAllowlist,
+    upstream_proxies: &ProxyConfig,
+    contacted_hosts: &Mutex<BTreeSet<ContactedHost>>,
+) -> anyhow::Result<()> {
+    let mut stream = tokio::io::BufReader::new(stream);
+    let head = read_head(&mut stream).await?;
+
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut request_line_parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (
+        request_line_parts.next(),
+        request_line_parts.next(),
+        request_line_parts.next(),
+    ) else {
+        anyhow::bail!("invalid request line: {request_line:?}");
+    };
+
+    let is_connect = method.eq_ignore_ascii_case("CONNECT");
+    let (host, port, url) = if is_connect {
+        let (host, port) = target
+            .rsplit_once(':')
+            .with_context(|| format!("invalid CONNECT target: {target:?}"))?;
+        let port = port
+            .parse()
+            .with_context(|| format!("invalid CONNECT target: {target:?}"))?;
+        (normalize_host(host), port, None)
+    } else {
+        let url: url::Url = target
+            .parse()
+            .with_context(|| format!("invalid proxy request URL: {target:?}"))?;
+        anyhow::ensure!(
+            url.scheme() == "http",
+            "unsupported proxy request URL: {target:?}"
+        );
+        let host = url
+            .host_str()
+            .with_context(|| format!("proxy request URL has no host: {target:?}"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        (normalize_host(host), port, Some(url))
+    };
+
+    let allowed = allowlist.allows(&host);
+    contacted_hosts
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .insert(ContactedHost {
+            host: host.clone(),
+            port,
+            allowed,
+        });
+
+    if !allowed {
+        tracing::debug!(host, port, "network proxy blocked host");
+        let body = format!("host {host:?} is not in the Brioche network allowlist\n");
+        let response = format!(
+            "HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.get_mut().write_all(response.as_bytes()).await?;
+        return Ok(());
+    }
+
+    let scheme = if is_connect { "https" } else { "http" };
+    let upstream_proxy = upstream_proxies.proxy_for(scheme, &host);
+    let connect_result = match upstream_proxy {
+        Some(upstream_proxy) => {
+            let proxy_host = upstream_proxy
+                .host_str()
+                .with_context(|| format!("upstream proxy has no host: {upstream_proxy}"))?;
+            let proxy_port = upstream_proxy.port_or_known_default().unwrap_or(80);
+            tokio::net::TcpStream::connect((proxy_host, proxy_port)).await
+        }
+        None => tokio::net::TcpStream::connect((&*host, port)).await,
+    };
+    let mut upstream = match connect_result {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            stream
+                .get_mut()
+                .write_all(
+                    b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            return Err(error).with_context(|| format!("failed to connect to {host}:{port}"));
+        }
+    };
+
+    match (url, upstream_proxy) {
+        (Some(url), upstream_proxy) => {
+            // Send the request to the host directly (or to the upstream
+            // proxy as-is). The connection is closed after one request,
+            // since a later request on the same connection could be for a
+            // different host
+            let request_target = match upstream_proxy {
+                Some(_) => url.as_str(),
+                None => &url[url::Position::BeforePath..url::Position::AfterQuery],
+            };
+            let mut request = format!("{method} {request_target} {version}\r\n");
+            for line in lines.filter(|line| !line.is_empty()) {
+                let name = line.split(':').next().unwrap_or_default().trim();
+                let skip = ["proxy-connection", "proxy-authorization", "connection"]
+                    .iter()
+                    .any(|skipped| name.eq_ignore_ascii_case(skipped));
+                if !skip {
+                    request.push_str(line);
+                    request.push_str("\r\n");
+                }
+            }
+            if let Some(authorization) = upstream_proxy.and_then(proxy_authorization_header) {
+                request.push_str(&authorization);
+            }
+            request.push_str("Connection: close\r\n\r\n");
+
+            upstream.write_all(request.as_bytes()).await?;
+        }
+        (None, Some(upstream_proxy)) => {
+            // Open a tunnel through the upstream proxy, and pass along its
+            // response if it refuses
+            let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+            if let Some(authorization) = proxy_authorization_header(upstream_proxy) {
+                request.push_str(&authorization);
+            }
+            request.push_str("\r\n");
+            upstream.write_all(request.as_bytes()).await?;
+
+            let mut upstream_reader = tokio::io::BufReader::new(upstream);
+            let response_head = read_head(&mut upstream_reader).await?;
+            let is_established = response_head
+                .split(' ')
+                .nth(1)
+                .is_some_and(|status| status.starts_with('2'));
+            if !is_established {
+                stream.get_mut().write_all(response_head.as_bytes()).await?;
+                anyhow::bail!("upstream proxy refused tunnel to {host}:{port}");
+            }
+            anyhow::ensure!(
+                upstream_reader.buffer().is_empty(),
+                "upstream proxy sent data before the tunnel was established"
+            );
+            upstream = upstream_reader.into_inner();
+
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await?;
+        }
+        (None, None) => {
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await?;
+        }
+    }
+
+    // Send along anything the client sent after the request head
+    upstream.write_all(stream.buffer()).await?;
+    let mut stream = stream.into_inner();
+
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+
+    Ok(())
+}
@@ -0,0 +1,504 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use bstr::{BString, ByteSlice as _};
+use sha2::Digest as _;
+use tokio::io::AsyncWriteExt as _;
+
+use crate::{
+    output::OutputOptions,
+    platform::Platform,
+    recipe::{Artifact, RecipeHash},
+    Brioche,
+};
+
+const RESOURCES_DIR: &str = "brioche-resources.d";
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// How to split an artifact into image layers. Resources (the packages
+/// under `brioche-resources.d`) go into base layers, and everything else
+/// goes into a final layer on top. Since each layer is a tarball with
+/// fixed metadata, the same packages always produce the same layer, so
+/// rebuilding a package only invalidates the layers it's part of.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum LayerStrategy {
+    /// Put everything in a single layer.
+    Single,
+    /// Group resource packages by how deeply they're nested in the
+    /// dependency graph, with the deepest (and usually most stable)
+    /// dependencies in the lowest layers.
+    #[default]
+    Depth,
+    /// Give each resource package its own layer. When there are more
+    /// packages than layers, packages are assigned to layers by hashing
+    /// their names, so adding or removing a package doesn't move others.
+    Package,
+}
+
+/// A planned image layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerGroup {
+    pub name: String,
+    /// The resource packages in this layer. The last layer has no
+    /// packages, and contains everything not in another layer.
+    pub packages: Vec<BString>,
+}
+
+pub struct ExportOciOptions<'a> {
+    /// The directory to write the OCI image layout to.
+    pub output_dir: &'a Path,
+    pub strategy: LayerStrategy,
+    /// The maximum number of layers, including the final layer.
+    pub max_layers: usize,
+    pub platform: Platform,
+    /// The reference name used for the image in `index.json`.
+    pub tag: Option<&'a str>,
+    /// The path to run when starting a container, relative to the root of
+    /// the image.
+    pub entrypoint: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OciExport {
+    pub manifest_digest: String,
+    pub layers: Vec<OciLayer>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OciLayer {
+    pub name: String,
+    pub digest: String,
+    pub size: u64,
+}
+
+/// Split resource packages into layers. `packages` maps each package name
+/// to its depth in the dependency graph, where packages used directly by
+/// the artifact have a depth of 1. The result only depends on the inputs,
+/// and always ends with the final layer for everything else.
+pub fn plan_layers(
+    packages: &BTreeMap<BString, usize>,
+    strategy: LayerStrategy,
+    max_layers: usize,
+) -> Vec<LayerGroup> {
+    let base_layers = max_layers.saturating_sub(1);
+
+    let mut groups = match strategy {
+        _ if base_layers == 0 || packages.is_empty() => vec![],
+        LayerStrategy::Single => vec![],
+        LayerStrategy::Depth => {
+            let mut by_depth = BTreeMap::<usize, Vec<BString>>::new();
+            for (name, depth) in packages {
+                by_depth.entry(*depth).or_default().push(name.clone());
+            }
+
+            // Deepest first, so the lowest layers change the least often
+            let mut groups = by_depth
+                .into_iter()
+                .rev()
+                .map(|(depth, packages)| LayerGroup {
+                    name: format!("depth-{depth}"),
+                    packages,
+                })
+                .collect::<Vec<_>>();
+
+            // Merge the deepest groups together if there are too many
+            if groups.len() > base_layers {
+                let num_merged = groups.len() - base_layers + 1;
+                let merged = groups.drain(..num_merged).collect::<Vec<_>>();
+                let name = format!("{}+", merged.last().expect("no groups to merge").name);
+                let mut packages = merged
+                    .into_iter()
+                    .flat_map(|group| group.packages)
+                    .collect::<Vec<_>>();
+                packages.sort();
+                groups.insert(0, LayerGroup { name, packages });
+            }
+
+            groups
+        }
+        LayerStrategy::Package if packages.len() <= base_layers => {
+            let mut packages = packages.iter().collect::<Vec<_>>();
+            packages.sort_by(|(a_name, a_depth), (b_name, b_depth)| {
+                b_depth.cmp(a_depth).then_with(|| a_name.cmp(b_name))
+            });
+            packages
+                .into_iter()
+                .map(|(name, _)| LayerGroup {
+                    name: format!("package-{name}"),
+                    packages: vec![name.clone()],
+                })
+                .collect()
+        }
+        LayerStrategy::Package => {
+            let mut buckets = BTreeMap::<u64, Vec<BString>>::new();
+            for name in packages.keys() {
+                let hash = blake3::hash(name);
+                let hash_bytes: [u8; 8] = hash.as_bytes()[..8]
+                    .try_into()
+                    .expect("invalid hash length");
+                let bucket = u64::from_le_bytes(hash_bytes) % base_layers as u64;
+                buckets.entry(bucket).or_default().push(name.clone());
+            }
+
+            buckets
+                .into_iter()
+                .map(|(bucket, packages)| LayerGroup {
+                    name: format!("packages-{bucket}"),
+                    packages,
+                })
+                .collect()
+        }
+    };
+
+    groups.push(LayerGroup {
+        name: "root".to_string(),
+        packages: vec![],
+    });
+    groups
+}
+
+/// Find the depth of each resource package used by an artifact. Resources
+/// used by files within resources are one level deeper than the resources
+/// that use them.
+pub async fn resource_package_depths(
+    brioche: &Brioche,
+    artifact: &Artifact,
+) -> anyhow::Result<BTreeMap<BString, usize>> {
+    let mut depths = BTreeMap::<BString, usize>::new();
+    let mut visited = HashMap::<RecipeHash, usize>::new();
+    let mut stack = vec![(artifact.clone(), 0)];
+
+    while let Some((artifact, depth)) = stack.pop() {
+        let hash = artifact.hash();
+        if visited
+            .get(&hash)
+            .is_some_and(|visited_depth| *visited_depth >= depth)
+        {
+            continue;
+        }
+        visited.insert(hash, depth);
+
+        match artifact {
+            Artifact::File(file) => {
+                for (name, entry) in file.resources.entries(brioche).await? {
+                    let package_depth = depths.entry(name).or_default();
+                    *package_depth = (*package_depth).max(depth + 1);
+                    stack.push((entry.value, depth + 1));
+                }
+            }
+            Artifact::Directory(directory) => {
+                for (_, entry) in directory.entries(brioche).await? {
+                    stack.push((entry.value, depth));
+                }
+            }
+            Artifact::Symlink { .. } => {}
+        }
+    }
+
+    Ok(depths)
+}
+
+/// Export a directory artifact as an OCI image layout, split into layers
+/// using [`plan_layers`]. Layer tarballs are written with sorted entries and
+/// fixed ownership and timestamps, so exporting the same artifact again
+/// produces identical layers.
+pub async fn export_oci_image(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    options: ExportOciOptions<'_>,
+) -> anyhow::Result<OciExport> {
+    anyhow::ensure!(
+        matches!(artifact, Artifact::Directory(_)),
+        "only directory artifacts can be exported as OCI images"
+    );
+
+    let temp_dir = brioche
+        .home
+        .join("oci-temp")
+        .join(ulid::Ulid::new().to_string());
+    let root_dir = temp_dir.join("root");
+    tokio::fs::create_dir_all(&temp_dir).await?;
+
+    let result = export_oci_image_from_temp(brioche, artifact, &root_dir, &options).await;
+
+    crate::fs_utils::set_directory_rwx_recursive(&temp_dir).await?;
+    tokio::fs::remove_dir_all(&temp_dir)
+        .await
+        .with_context(|| format!("failed to remove {}", temp_dir.display()))?;
+
+    result
+}
+
+async fn export_oci_image_from_temp(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    root_dir: &Path,
+    options: &ExportOciOptions<'_>,
+) -> anyhow::Result<OciExport> {
+    crate::output::create_output(
+        brioche,
+        artifact,
+        OutputOptions {
+            output_path: root_dir,
+            merge: false,
+            resource_dir: None,
+            mtime: Some(crate::fs_utils::brioche_epoch()),
+            link_locals: false,
+            link_strategy: crate::output::LinkStrategy::Copy,
+            permissions: crate::output::OutputPermissions::default(),
+        },
+    )
+    .await?;
+
+    // Packages that were already in the artifact's own resource directory
+    // aren't referenced by any file, so treat them as direct dependencies
+    let mut packages = resource_package_depths(brioche, artifact).await?;
+    let resources_dir = root_dir.join(RESOURCES_DIR);
+    if crate::fs_utils::is_dir(&resources_dir).await {
+        let mut read_dir = tokio::fs::read_dir(&resources_dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name();
+            let name = <[u8]>::from_os_str(&name).context("invalid resource name")?;
+            packages.entry(BString::from(name)).or_insert(1);
+        }
+    }
+
+    let groups = plan_layers(&packages, options.strategy, options.max_layers);
+
+    let blobs_dir = options.output_dir.join("blobs").join("sha256");
+    tokio::fs::create_dir_all(&blobs_dir)
+        .await
+        .with_context(|| format!("failed to create {}", blobs_dir.display()))?;
+
+    let claimed_roots = groups
+        .iter()
+        .flat_map(|group| &group.packages)
+        .map(|name| package_root(name))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut layers = vec![];
+    for group in &groups {
+        let roots = group
+            .packages
+            .iter()
+            .map(|name| package_root(name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let filter = if roots.is_empty() {
+            LayerFilter::Exclude(claimed_roots.clone())
+        } else {
+            LayerFilter::Include(roots)
+        };
+
+        let temp_path = blobs_dir.join(format!("tmp-{}", ulid::Ulid::new()));
+        write_layer_tar(root_dir, &filter, &temp_path)
+            .await
+            .with_context(|| format!("failed to write layer {}", group.name))?;
+        let (digest, size) = sha256_file(&temp_path).await?;
+        tokio::fs::rename(&temp_path, blobs_dir.join(&digest)).await?;
+
+        layers.push(OciLayer {
+            name: group.name.clone(),
+            digest: format!("sha256:{digest}"),
+            size,
+        });
+    }
+
+    let mut container_config = serde_json::Map::new();
+    if let Some(entrypoint) = options.entrypoint {
+        let entrypoint = format!("/{}", entrypoint.trim_start_matches('/'));
+        container_config.insert("Entrypoint".into(), serde_json::json!([entrypoint]));
+    }
+    let config = serde_json::json!({
+        "architecture": options.platform.oci_arch(),
+        "os": "linux",
+        "config": container_config,
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": layers.iter().map(|layer| &layer.digest).collect::<Vec<_>>(),
+        },
+    });
+    let (config_digest, config_size) = write_json_blob(&blobs_dir, &config).await?;
+
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": {
+            "mediaType": CONFIG_MEDIA_TYPE,
+            "digest": config_digest,
+            "size": config_size,
+        },
+        "layers": layers.iter().map(|layer| serde_json::json!({
+            "mediaType": LAYER_MEDIA_TYPE,
+            "digest": layer.digest,
+            "size": layer.size,
+            "annotations": {
+                "dev.brioche.layer": layer.name,
+            },
+        })).collect::<Vec<_>>(),
+    });
+    let (manifest_digest, manifest_size) = write_json_blob(&blobs_dir, &manifest).await?;
+
+    let mut manifest_descriptor = serde_json::json!({
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "digest": manifest_digest,
+        "size": manifest_size,
+    });
+    if let Some(tag) = options.tag {
+        manifest_descriptor["annotations"] = serde_json::json!({
+            "org.opencontainers.image.ref.name": tag,
+        });
+    }
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "manifests": [manifest_descriptor],
+    });
+    tokio::fs::write(
+        options.output_dir.join("index.json"),
+        serde_json::to_vec(&index)?,
+    )
+    .await?;
+    tokio::fs::write(
+        options.output_dir.join("oci-layout"),
+        r#"{"imageLayoutVersion":"1.0.0"}"#,
+    )
+    .await?;
+
+    Ok(OciExport {
+        manifest_digest,
+        layers,
+    })
+}
+
+fn package_root(name: &[u8]) -> anyhow::Result<PathBuf> {
+    let name = name
+        .to_path()
+        .with_context(|| format!("invalid resource name {:?}", bstr::BStr::new(name)))?;
+    Ok(Path::new(RESOURCES_DIR).join(name))
+}
+
+enum LayerFilter {
+    /// Only include these subtrees (and their parent directories).
+    Include(Vec<PathBuf>),
+    /// Include everything except these subtrees.
+    Exclude(Vec<PathBuf>),
+}
+
+impl LayerFilter {
+    fn includes(&self, path: &Path) -> bool {
+        match self {
+            Self::Include(roots) => roots
+                .iter()
+                .any(|root| path.starts_with(root) || root.starts_with(path)),
+            Self::Exclude(roots) => !roots.iter().any(|root| path.starts_with(root)),
+        }
+    }
+}
+
+async fn write_layer_tar(
+    root_dir: &Path,
+    filter: &LayerFilter,
+    tar_path: &Path,
+) -> anyhow::Result<()> {
+    let entries = {
+        let root_dir = root_dir.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut entries = vec![];
+            let walker = walkdir::WalkDir::new(&root_dir)
+                .min_depth(1)
+                .sort_by_file_name();
+            for entry in walker {
+                let entry = entry?;
+                let relative_path = entry.path().strip_prefix(&root_dir)?.to_owned();
+                entries.push((relative_path, entry.into_path()));
+            }
+            anyhow::Ok(entries)
+        })
+        .await??
+    };
+
+    let mtime = crate::fs_utils::brioche_epoch()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let tar_file = tokio::fs::File::create(tar_path).await?;
+    let mut builder = tokio_tar::Builder::new(tar_file);
+    for (relative_path, path) in entries {
+        if !filter.includes(&relative_path) {
+            continue;
+        }
+
+        let metadata = tokio::fs::symlink_metadata(&path).await?;
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(mtime);
+
+        if metadata.is_dir() {
+            header.set_entry_type(tokio_tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder
+                .append_data(&mut header, &relative_path, tokio::io::empty())
+                .await?;
+        } else if metadata.is_symlink() {
+            let target = tokio::fs::read_link(&path).await?;
+            header.set_entry_type(tokio_tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            header.set_link_name(&target)?;
+            builder
+                .append_data(&mut header, &relative_path, tokio::io::empty())
+                .await?;
+        } else if metadata.is_file() {
+            let mode = if crate::fs_utils::is_executable(&metadata.permissions()) {
+                0o755
+            } else {
+                0o644
+            };
+            header.set_entry_type(tokio_tar::EntryType::Regular);
+            header.set_mode(mode);
+            header.set_size(metadata.len());
+            let file = tokio::fs::File::open(&path).await?;
+            builder
+                .append_data(&mut header, &relative_path, file)
+                .await?;
+        } else {
+            anyhow::bail!("unsupported file type at {}", path.display());
+        }
+    }
+
+    let mut tar_file = builder.into_inner().await?;
+    tar_file.flush().await?;
+
+    Ok(())
+}
+
+async fn sha256_file(path: &Path) -> anyhow::Result<(String, u64)> {
+    let path = path.to_owned();
+    let result = tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = sha2::Sha256::new();
+        let size = std::io::copy(&mut file, &mut hasher)?;
+        let digest = hex::encode(hasher.finalize());
+        anyhow::Ok((digest, size))
+    })
+    .await??;
+    Ok(result)
+}
+
+async fn write_json_blob(
+    blobs_dir: &Path,
+    value: &serde_json::Value,
+) -> anyhow::Result<(String, u64)> {
+    let contents = serde_json::to_vec(value)?;
+    let digest = hex::encode(sha2::Sha256::digest(&contents));
+    tokio::fs::write(blobs_dir.join(&digest), &contents).await?;
+    Ok((format!("sha256:{digest}"), contents.len() as u64))
+}
@@ -0,0 +1,147 @@
+use anyhow::Context as _;
+
+use crate::{
+    project::{DependencyDefinition, Lockfile, ProjectHash, Projects, Version},
+    Brioche,
+};
+
+#[derive(Debug, Clone)]
+pub struct OutdatedDependency {
+    pub name: String,
+    /// The version constraint from the project definition.
+    pub constraint: Version,
+    pub locked: DependencyVersion,
+    /// The newest version in the registry (skipping yanked versions).
+    pub latest: DependencyVersion,
+    /// The newest version allowed by the constraint, if it's different
+    /// from `locked`.
+    pub upgradable: Option<DependencyVersion>,
+}
+
+impl OutdatedDependency {
+    /// Returns true if the constraint prevents upgrading to the latest
+    /// version.
+    pub fn is_blocked(&self) -> bool {
+        self.upgradable
+            .as_ref()
+            .map_or(true, |upgradable| upgradable.hash != self.latest.hash)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DependencyVersion {
+    pub hash: ProjectHash,
+    pub version: Option<String>,
+}
+
+/// Compare a project's locked registry dependencies against the newest
+/// versions in the registry. Only dependencies with a newer version are
+/// returned.
+pub async fn find_outdated_dependencies(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<Vec<OutdatedDependency>> {
+    let project = projects.project(project_hash)?;
+    let project_root = projects.project_root(project_hash)?;
+
+    let lockfile_path = project_root.join("brioche.lock");
+    let lockfile_contents = match tokio::fs::read_to_string(&lockfile_path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            // Nothing is locked, so every dependency was just resolved
+            // to the newest version
+            return Ok(vec![]);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read lockfile {}", lockfile_path.display()));
+        }
+    };
+    let lockfile: Lockfile = serde_json::from_str(&lockfile_contents)
+        .with_context(|| format!("failed to parse lockfile {}", lockfile_path.display()))?;
+
+    let mut outdated = vec![];
+    for (name, locked_hash) in &lockfile.dependencies {
+        // Dependencies not listed explicitly were imported directly,
+        // which is equivalent to `*`
+        let constraint = match project.definition.dependencies.get(name) {
+            Some(DependencyDefinition::Version(version)) => version.clone(),
+            Some(DependencyDefinition::Path { .. }) => continue,
+            None => Version::Any,
+        };
+
+        let latest = crate::project::find_registry_version(brioche, name, &Version::Any).await?;
+        if latest.project_hash == *locked_hash {
+            continue;
+        }
+
+        let allowed_hash = match constraint {
+            Version::Any => latest.project_hash,
+        };
+        let upgradable = if allowed_hash != *locked_hash {
+            Some(registry_dependency_version(brioche, allowed_hash).await?)
+        } else {
+            None
+        };
+
+        let locked_version = projects
+            .project(*locked_hash)
+            .ok()
+            .and_then(|locked| locked.definition.version.clone());
+
+        outdated.push(OutdatedDependency {
+            name: name.clone(),
+            constraint,
+            locked: DependencyVersion {
+                hash: *locked_hash,
+                version: locked_version,
+            },
+            latest: registry_dependency_version(brioche, latest.project_hash).await?,
+            upgradable,
+        });
+    }
+
+    Ok(outdated)
+}
+
+/// Download the newest allowed versions of a project's outdated registry
+/// dependencies into the local store, without changing its lockfile, so
+/// updating to them later doesn't need to wait on the registry. Returns
+/// the number of dependencies fetched.
+pub async fn prefetch_updates(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<usize> {
+    let outdated = find_outdated_dependencies(brioche, projects, project_hash).await?;
+
+    let mut num_fetched = 0;
+    for dependency in outdated {
+        let Some(upgradable) = dependency.upgradable else {
+            continue;
+        };
+
+        crate::project::fetch_project_from_registry(brioche, upgradable.hash)
+            .await
+            .with_context(|| format!("failed to prefetch update for '{}'", dependency.name))?;
+        num_fetched += 1;
+    }
+
+    Ok(num_fetched)
+}
+
+async fn registry_dependency_version(
+    brioche: &Brioche,
+    project_hash: ProjectHash,
+) -> anyhow::Result<DependencyVersion> {
+    let project = brioche
+        .registry_client
+        .get_project(project_hash)
+        .await
+        .with_context(|| format!("failed to get project {project_hash} from registry"))?;
+    Ok(DependencyVersion {
+        hash: project_hash,
+        version: project.definition.version,
+    })
+}
@@ -1,13 +1,10 @@
-use std::{
-    collections::HashSet,
-    path::{Path, PathBuf},
-};
+use std::path::{Path, PathBuf};
 
 use anyhow::Context as _;
 use bstr::ByteSlice;
 
 use super::{
-    recipe::{Artifact, Directory, File},
+    recipe::{Artifact, Directory, File, RecipeHash},
     Brioche,
 };
 
@@ -23,6 +20,122 @@ pub struct OutputOptions<'a> {
     pub merge: bool,
     pub mtime: Option<std::time::SystemTime>,
     pub link_locals: bool,
+    pub link_strategy: LinkStrategy,
+    pub permissions: OutputPermissions,
+}
+
+/// How files are materialized from the blob store when creating an output.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LinkStrategy {
+    /// Copy each file out of the blob store.
+    #[default]
+    Copy,
+    /// Hard link non-executable files to their read-only blobs, which is
+    /// much faster and takes no extra space for large outputs. Linked files
+    /// keep the blob's read-only permissions and modified time. Files are
+    /// copied instead when they can't be linked, such as executables, when
+    /// changing the output's owner, or when the output is on another
+    /// filesystem.
+    Hardlink,
+}
+
+/// The permissions and ownership to give a materialized output. The
+/// default matches files created with a `022` umask: directories and
+/// executables get `0o755`, and other files get `0o644`. Outputs that
+/// hard link into the `locals` directory share their permissions with the
+/// store, so they only support the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputPermissions {
+    /// Permission bits to clear from every file and directory.
+    pub umask: u32,
+
+    /// The mode for directories, before applying the umask [default:
+    /// `0o777`].
+    pub directory_mode: Option<u32>,
+
+    /// Remove write permissions from files.
+    pub readonly: bool,
+
+    /// Change the owner of every file, directory, and symlink. Usually
+    /// requires running as root.
+    pub owner: Option<OutputOwner>,
+}
+
+impl Default for OutputPermissions {
+    fn default() -> Self {
+        Self {
+            umask: 0o022,
+            directory_mode: None,
+            readonly: false,
+            owner: None,
+        }
+    }
+}
+
+impl OutputPermissions {
+    /// Returns true if no umask, read-only flag, or owner was set, so files
+    /// don't need different permissions or ownership from the blob store.
+    pub fn is_default(&self) -> bool {
+        let default = Self::default();
+        self.umask == default.umask && self.readonly == default.readonly && self.owner.is_none()
+    }
+
+    pub fn file_mode(&self, executable: bool, readonly: bool) -> u32 {
+        let mode = if executable { 0o777 } else { 0o666 };
+        let mode = mode & !self.umask;
+        if readonly || self.readonly {
+            mode & !0o222
+        } else {
+            mode
+        }
+    }
+
+    pub fn directory_mode(&self, readonly: bool) -> u32 {
+        let mode = self.directory_mode.unwrap_or(0o777) & !self.umask;
+        if readonly {
+            mode & !0o222
+        } else {
+            mode
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OutputOwner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl std::str::FromStr for OutputOwner {
+    type Err = anyhow::Error;
+
+    /// Parse an owner as `UID:GID`, using numeric IDs.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (uid, gid) = s
+            .split_once(':')
+            .with_context(|| format!("invalid owner {s:?}, expected UID:GID"))?;
+        let uid = uid
+            .parse()
+            .with_context(|| format!("invalid user ID {uid:?}"))?;
+        let gid = gid
+            .parse()
+            .with_context(|| format!("invalid group ID {gid:?}"))?;
+        Ok(Self { uid, gid })
+    }
 }
 
 #[tracing::instrument(skip(brioche, artifact), fields(artifact_hash = %artifact.hash()), err)]
@@ -48,6 +161,219 @@ pub async fn create_output(
     Ok(())
 }
 
+/// Create an output by first writing it to a temporary path next to
+/// `options.output_path`, then moving it into place once it's complete. If
+/// `replace` is set, an existing output is only removed after the new output
+/// has been written, so a failed build never leaves a half-written output
+/// behind. Merging isn't supported, since it modifies the output in place.
+///
+/// Files and symlinks are moved into place with a single rename. An existing
+/// directory is swapped with `renameat2(RENAME_EXCHANGE)` on Linux. Where
+/// that isn't supported, the output path is replaced with a symlink to the
+/// new output instead (see [`replace_with_symlink`]).
+#[tracing::instrument(skip(brioche, artifact), fields(artifact_hash = %artifact.hash()), err)]
+pub async fn create_output_atomic(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    options: OutputOptions<'_>,
+    replace: bool,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(!options.merge, "cannot merge into an atomic output");
+
+    let output_path = options.output_path;
+    let existing_metadata = tokio::fs::symlink_metadata(output_path).await.ok();
+    let output_exists = existing_metadata.is_some();
+    anyhow::ensure!(
+        replace || !output_exists,
+        "output path {} already exists",
+        output_path.display()
+    );
+
+    let output_name = output_path
+        .file_name()
+        .with_context(|| format!("invalid output path {}", output_path.display()))?
+        .to_string_lossy();
+    let output_parent = match output_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let temp_id = ulid::Ulid::new();
+    let temp_path = output_parent.join(format!(".{output_name}.brioche-tmp-{temp_id}"));
+
+    let result = create_output(
+        brioche,
+        artifact,
+        OutputOptions {
+            output_path: &temp_path,
+            ..options
+        },
+    )
+    .await;
+    if let Err(error) = result {
+        remove_temp_output(&temp_path).await;
+        return Err(error);
+    }
+
+    // Files and symlinks can be replaced with a single atomic rename
+    let temp_is_dir = matches!(artifact, Artifact::Directory(_));
+    let output_is_dir = existing_metadata.is_some_and(|metadata| metadata.is_dir());
+    if !output_exists || (!temp_is_dir && !output_is_dir) {
+        if let Err(error) = tokio::fs::rename(&temp_path, output_path).await {
+            remove_temp_output(&temp_path).await;
+            return Err(error).with_context(|| {
+                format!(
+                    "failed to move output into place at {}",
+                    output_path.display()
+                )
+            });
+        }
+
+        return Ok(());
+    }
+
+    // Otherwise, swap the new output into place in one step where the
+    // filesystem supports it. The old output ends up at the temporary path
+    let exchanged = crate::fs_utils::try_exchange(&temp_path, output_path)
+        .await
+        .with_context(|| {
+            format!(
+                "failed to move output into place at {}",
+                output_path.display()
+            )
+        });
+    match exchanged {
+        Ok(true) => {
+            remove_temp_output(&temp_path).await;
+            return Ok(());
+        }
+        Ok(false) => {}
+        Err(error) => {
+            remove_temp_output(&temp_path).await;
+            return Err(error);
+        }
+    }
+
+    // As a fallback, the output path becomes a symlink to the new output,
+    // which can then be replaced with a single rename
+    let result = replace_with_symlink(output_path, &temp_path, &output_name, temp_id).await;
+    if let Err(error) = result {
+        remove_temp_output(&temp_path).await;
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Replace the directory at `output_path` by moving `temp_path` to a
+/// sibling named after `temp_id`, then renaming a symlink to it over
+/// `output_path`. Replacing a previous symlink this way is atomic, and the
+/// directory it pointed to is removed afterwards. Replacing a directory
+/// that isn't a symlink isn't atomic: the old output is moved out of the
+/// way first, so `output_path` briefly doesn't exist.
+async fn replace_with_symlink(
+    output_path: &Path,
+    temp_path: &Path,
+    output_name: &str,
+    temp_id: ulid::Ulid,
+) -> anyhow::Result<()> {
+    let output_parent = temp_path.parent().unwrap_or(Path::new("."));
+    let target_prefix = format!(".{output_name}.brioche-out-");
+    let target_name = format!("{target_prefix}{temp_id}");
+    let target_path = output_parent.join(&target_name);
+    let link_path = output_parent.join(format!(".{output_name}.brioche-link-{temp_id}"));
+
+    // Only remove the previous target if it's one we created
+    let previous_target = tokio::fs::read_link(output_path)
+        .await
+        .ok()
+        .filter(|target| {
+            target.parent() == Some(Path::new(""))
+                && target
+                    .to_str()
+                    .is_some_and(|target| target.starts_with(&target_prefix))
+        })
+        .map(|target| output_parent.join(target));
+
+    tokio::fs::rename(temp_path, &target_path)
+        .await
+        .with_context(|| format!("failed to move output to {}", target_path.display()))?;
+    let link_result = tokio::task::spawn_blocking({
+        let target_name = PathBuf::from(&target_name);
+        let link_path = link_path.clone();
+        move || crate::fs_utils::symlink_blocking(&target_name, &link_path)
+    })
+    .await?;
+    if let Err(error) = link_result {
+        remove_temp_output(&target_path).await;
+        return Err(error)
+            .with_context(|| format!("failed to create symlink {}", link_path.display()));
+    }
+
+    // A symlink can't be renamed over a directory, so an existing directory
+    // has to be moved out of the way first
+    let old_path = output_parent.join(format!(".{output_name}.brioche-old-{temp_id}"));
+    let output_is_symlink = tokio::fs::symlink_metadata(output_path)
+        .await
+        .is_ok_and(|metadata| metadata.is_symlink());
+    if !output_is_symlink {
+        let result = tokio::fs::rename(output_path, &old_path).await;
+        if let Err(error) = result {
+            remove_temp_output(&link_path).await;
+            remove_temp_output(&target_path).await;
+            return Err(error).with_context(|| {
+                format!(
+                    "failed to move existing output {} out of the way",
+                    output_path.display()
+                )
+            });
+        }
+    }
+
+    if let Err(error) = tokio::fs::rename(&link_path, output_path).await {
+        if !output_is_symlink {
+            let restored = tokio::fs::rename(&old_path, output_path).await;
+            if let Err(restore_error) = restored {
+                tracing::error!(
+                    old_path = %old_path.display(),
+                    "failed to restore previous output: {restore_error}"
+                );
+            }
+        }
+        remove_temp_output(&link_path).await;
+        remove_temp_output(&target_path).await;
+        return Err(error).with_context(|| {
+            format!(
+                "failed to move output into place at {}",
+                output_path.display()
+            )
+        });
+    }
+
+    for path in previous_target
+        .iter()
+        .chain((!output_is_symlink).then_some(&old_path))
+    {
+        crate::fs_utils::set_directory_rwx_recursive(path).await?;
+        crate::fs_utils::try_remove(path)
+            .await
+            .with_context(|| format!("failed to remove old output {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+async fn remove_temp_output(temp_path: &Path) {
+    let result = async {
+        crate::fs_utils::set_directory_rwx_recursive(temp_path).await?;
+        crate::fs_utils::try_remove(temp_path).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(error) = result {
+        tracing::warn!(temp_path = %temp_path.display(), "failed to remove temporary output: {error:#}");
+    }
+}
+
 #[async_recursion::async_recursion]
 #[tracing::instrument(skip(brioche, artifact, link_lock), fields(artifact_hash = %artifact.hash()), err)]
 async fn create_output_inner<'a: 'async_recursion>(
@@ -56,6 +382,11 @@ async fn create_output_inner<'a: 'async_recursion>(
     options: OutputOptions<'a>,
     link_lock: Option<&'a tokio::sync::MutexGuard<'a, LocalOutputLock>>,
 ) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !options.link_locals || options.permissions == OutputPermissions::default(),
+        "custom output permissions are not supported when linking to local outputs"
+    );
+
     let link_lock = match (options.link_locals, link_lock) {
         (false, _) => None,
         (true, Some(lock)) => Some(lock),
@@ -73,7 +404,7 @@ async fn create_output_inner<'a: 'async_recursion>(
             resources,
         }) => {
             if resources.is_empty() {
-                let blob_path = super::blob::local_blob_path(brioche, *content_blob);
+                let blob_path = super::blob::local_blob_path(brioche, *content_blob).await?;
 
                 anyhow::ensure!(
                     tokio::fs::try_exists(&blob_path).await?,
@@ -81,9 +412,16 @@ async fn create_output_inner<'a: 'async_recursion>(
                     blob_path.display(),
                 );
 
+                // A linked file shares the blob's mode and modified time,
+                // so files that need their own are copied instead
+                let can_link_blob = options.link_strategy == LinkStrategy::Hardlink
+                    && !*executable
+                    && options.mtime.is_none()
+                    && options.permissions.is_default();
+
                 if options.link_locals && !*executable {
                     crate::fs_utils::try_remove(options.output_path).await?;
-                    tokio::fs::hard_link(&blob_path, options.output_path)
+                    crate::fs_utils::hard_link_or_copy(&blob_path, options.output_path)
                         .await
                         .with_context(|| {
                             format!(
@@ -92,16 +430,27 @@ async fn create_output_inner<'a: 'async_recursion>(
                                 options.output_path.display()
                             )
                         })?;
+                } else if can_link_blob && try_link_blob(&blob_path, options.output_path).await? {
+                    tracing::trace!(output_path = %options.output_path.display(), "linked output file to blob");
                 } else {
-                    tokio::fs::copy(&blob_path, options.output_path)
-                        .await
-                        .with_context(|| {
-                            format!(
-                                "failed to copy blob from {} to {}",
-                                blob_path.display(),
-                                options.output_path.display()
-                            )
-                        })?;
+                    // A file being merged over may be hard linked to a
+                    // blob, so remove it rather than writing through it
+                    if options.merge {
+                        crate::fs_utils::try_remove(options.output_path).await?;
+                    }
+
+                    // The blob is verified as it's copied, so a corrupted
+                    // blob fails here instead of when the output is used.
+                    // Blobs saved from sparse files keep their holes
+                    let data_extents =
+                        super::blob::blob_data_extents(brioche, *content_blob).await?;
+                    super::blob::copy_blob_verified(
+                        *content_blob,
+                        &blob_path,
+                        options.output_path,
+                        data_extents.as_deref(),
+                    )
+                    .await?;
 
                     // Set the file permissions and mtime. We set the file
                     // to be read-only and reset the file's modified time
@@ -114,9 +463,11 @@ async fn create_output_inner<'a: 'async_recursion>(
                             executable: *executable,
                             readonly: options.link_locals,
                         },
+                        &options.permissions,
                     )
                     .await
                     .context("failed to set output file permissions")?;
+                    set_output_owner(options.output_path, options.permissions.owner).await?;
 
                     if let Some(mtime) = options.mtime {
                         crate::fs_utils::set_mtime(options.output_path, mtime)
@@ -142,6 +493,8 @@ async fn create_output_inner<'a: 'async_recursion>(
                         merge: true,
                         mtime: None,
                         link_locals: options.link_locals,
+                        link_strategy: options.link_strategy,
+                        permissions: options.permissions,
                     },
                     link_lock,
                 )
@@ -158,7 +511,7 @@ async fn create_output_inner<'a: 'async_recursion>(
                         create_local_output_inner(brioche, &artifact_without_resources, link_lock)
                             .await?;
                     crate::fs_utils::try_remove(options.output_path).await?;
-                    tokio::fs::hard_link(&local_path.path, options.output_path)
+                    crate::fs_utils::hard_link_or_copy(&local_path.path, options.output_path)
                         .await
                         .with_context(|| {
                             format!(
@@ -177,6 +530,8 @@ async fn create_output_inner<'a: 'async_recursion>(
                             merge: options.merge,
                             mtime: options.mtime,
                             link_locals: options.link_locals,
+                            link_strategy: options.link_strategy,
+                            permissions: options.permissions,
                         },
                         link_lock,
                     )
@@ -194,7 +549,7 @@ async fn create_output_inner<'a: 'async_recursion>(
                     tracing::debug!(target = %target.display(), "removed conflicting file to create symlink");
                 }
             }
-            tokio::fs::symlink(&target, options.output_path)
+            crate::fs_utils::symlink(&target, options.output_path)
                 .await
                 .with_context(|| {
                     format!(
@@ -203,6 +558,7 @@ async fn create_output_inner<'a: 'async_recursion>(
                         target.display(),
                     )
                 })?;
+            set_output_owner(options.output_path, options.permissions.owner).await?;
         }
         Artifact::Directory(directory) => {
             let result = tokio::fs::create_dir(options.output_path).await;
@@ -245,6 +601,8 @@ async fn create_output_inner<'a: 'async_recursion>(
                                     merge: true,
                                     mtime: options.mtime,
                                     link_locals: options.link_locals,
+                                    link_strategy: options.link_strategy,
+                                    permissions: options.permissions,
                                 },
                                 Some(link_lock),
                             )
@@ -257,7 +615,7 @@ async fn create_output_inner<'a: 'async_recursion>(
                         let local_output =
                             create_local_output_inner(brioche, &entry.value, link_lock).await?;
                         crate::fs_utils::try_remove(&entry_path).await?;
-                        tokio::fs::hard_link(&local_output.path, &entry_path)
+                        crate::fs_utils::hard_link_or_copy(&local_output.path, &entry_path)
                             .await
                             .context("failed to create hardlink into Brioche `locals` directory")?;
                     }
@@ -271,6 +629,8 @@ async fn create_output_inner<'a: 'async_recursion>(
                                 merge: true,
                                 mtime: options.mtime,
                                 link_locals: options.link_locals,
+                                link_strategy: options.link_strategy,
+                                permissions: options.permissions,
                             },
                             link_lock,
                         )
@@ -282,14 +642,52 @@ async fn create_output_inner<'a: 'async_recursion>(
             set_directory_permissions(
                 options.output_path,
                 SetDirectoryPermissions { readonly: false },
+                &options.permissions,
             )
             .await?;
+            set_output_owner(options.output_path, options.permissions.owner).await?;
         }
     }
 
     Ok(())
 }
 
+/// Try to hard link an output file to its blob. Returns `false` if the
+/// link couldn't be created (e.g. because the output is on another
+/// filesystem), so the file can be copied instead.
+async fn try_link_blob(blob_path: &Path, output_path: &Path) -> anyhow::Result<bool> {
+    crate::fs_utils::try_remove(output_path).await?;
+    match tokio::fs::hard_link(blob_path, output_path).await {
+        Ok(()) => Ok(true),
+        Err(error) => {
+            tracing::debug!(
+                blob_path = %blob_path.display(),
+                output_path = %output_path.display(),
+                "failed to hard link output file to blob, copying instead: {error}"
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Get the path where an artifact will be saved as a local output, without
+/// creating it. The path only depends on the Brioche home directory and the
+/// artifact's hash, so it's stable across builds: external tools can
+/// reference it before the output is created with [`create_local_output`].
+pub fn local_output_path(brioche: &Brioche, artifact_hash: RecipeHash) -> PathBuf {
+    brioche.home.join("locals").join(artifact_hash.to_string())
+}
+
+/// Get the path where an artifact's resources will be saved alongside its
+/// local output. The directory is only created if the artifact has
+/// resources.
+pub fn local_output_resource_dir(brioche: &Brioche, artifact_hash: RecipeHash) -> PathBuf {
+    brioche
+        .home
+        .join("locals")
+        .join(format!("{artifact_hash}-resources.d"))
+}
+
 pub async fn create_local_output(
     brioche: &Brioche,
     artifact: &Artifact,
@@ -313,14 +711,14 @@ async fn create_local_output_inner(
     artifact: &Artifact,
     lock: &tokio::sync::MutexGuard<'_, LocalOutputLock>,
 ) -> anyhow::Result<LocalOutput> {
-    let local_dir = brioche.home.join("locals");
-    tokio::fs::create_dir_all(&local_dir).await?;
-
     let artifact_hash = artifact.hash();
-    let local_path = local_dir.join(artifact_hash.to_string());
-    let local_resource_dir = local_dir.join(format!("{artifact_hash}-resources.d"));
+    let local_path = local_output_path(brioche, artifact_hash);
+    let local_resource_dir = local_output_resource_dir(brioche, artifact_hash);
+    tokio::fs::create_dir_all(brioche.home.join("locals")).await?;
 
     if !try_exists_and_ensure_local_meta(&local_path).await? {
+        brioche.ensure_writable(&format!("create local output for {artifact_hash}"))?;
+
         let local_temp_dir = brioche.home.join("locals-temp");
         tokio::fs::create_dir_all(&local_temp_dir).await?;
         let temp_id = ulid::Ulid::new();
@@ -336,6 +734,8 @@ async fn create_local_output_inner(
                 merge: false,
                 mtime: None,
                 link_locals: true,
+                link_strategy: LinkStrategy::Copy,
+                permissions: OutputPermissions::default(),
             },
             Some(lock),
         )
@@ -353,6 +753,7 @@ async fn create_local_output_inner(
                         executable: file.executable,
                         readonly: true,
                     },
+                    &OutputPermissions::default(),
                 )
                 .await
                 .context("failed to set permissions for local file")?;
@@ -360,11 +761,13 @@ async fn create_local_output_inner(
                     .await
                     .context("failed to set modified time for local file")?;
             }
-            Artifact::Directory(_) => {
-                set_directory_permissions(&local_path, SetDirectoryPermissions { readonly: true })
-                    .await
-                    .context("failed to set permissions for local output directory")?
-            }
+            Artifact::Directory(_) => set_directory_permissions(
+                &local_path,
+                SetDirectoryPermissions { readonly: true },
+                &OutputPermissions::default(),
+            )
+            .await
+            .context("failed to set permissions for local output directory")?,
             Artifact::Symlink { .. } => {}
         }
 
@@ -376,6 +779,7 @@ async fn create_local_output_inner(
             set_directory_permissions(
                 &local_resource_dir,
                 SetDirectoryPermissions { readonly: true },
+                &OutputPermissions::default(),
             )
             .await
             .context("failed to set permissions for local output resources")?;
@@ -403,12 +807,7 @@ async fn fetch_descendent_artifact_blobs(
     brioche: &Brioche,
     artifact: &Artifact,
 ) -> anyhow::Result<()> {
-    // Find artifact blobs
-    let mut blobs = HashSet::new();
-    crate::references::descendent_artifact_blobs(brioche, [artifact.clone()], &mut blobs).await?;
-
-    // Fetch all referenced blobs
-    crate::registry::fetch_blobs(brioche.clone(), &blobs).await?;
+    crate::registry::fetch_artifact_blobs(brioche, artifact).await?;
 
     Ok(())
 }
@@ -484,31 +883,75 @@ struct SetDirectoryPermissions {
 
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
-        async fn set_file_permissions(path: &Path, permissions: SetFilePermissions) -> anyhow::Result<()> {
+        async fn set_file_permissions(
+            path: &Path,
+            permissions: SetFilePermissions,
+            output_permissions: &OutputPermissions,
+        ) -> anyhow::Result<()> {
             use std::os::unix::fs::PermissionsExt as _;
 
-            let mode = match permissions {
-                SetFilePermissions { executable: true, readonly: false } => 0o755,
-                SetFilePermissions { executable: false, readonly: false } => 0o644,
-                SetFilePermissions { executable: true, readonly: true } => 0o555,
-                SetFilePermissions { executable: false, readonly: true } => 0o444,
-            };
+            let mode = output_permissions.file_mode(permissions.executable, permissions.readonly);
             let permissions = std::fs::Permissions::from_mode(mode);
             tokio::fs::set_permissions(path, permissions).await?;
             Ok(())
         }
 
-        async fn set_directory_permissions(path: &Path, permissions: SetDirectoryPermissions) -> anyhow::Result<()> {
+        async fn set_directory_permissions(
+            path: &Path,
+            permissions: SetDirectoryPermissions,
+            output_permissions: &OutputPermissions,
+        ) -> anyhow::Result<()> {
             use std::os::unix::fs::PermissionsExt as _;
 
-            let mode = match permissions {
-                SetDirectoryPermissions { readonly: false } => 0o755,
-                SetDirectoryPermissions { readonly: true } => 0o555,
-            };
+            let mode = output_permissions.directory_mode(permissions.readonly);
 
             let permissions = std::fs::Permissions::from_mode(mode);
             tokio::fs::set_permissions(path, permissions).await?;
             Ok(())
         }
+
+        async fn set_output_owner(path: &Path, owner: Option<OutputOwner>) -> anyhow::Result<()> {
+            let Some(owner) = owner else {
+                return Ok(());
+            };
+
+            let path = path.to_owned();
+            tokio::task::spawn_blocking(move || {
+                std::os::unix::fs::lchown(&path, Some(owner.uid), Some(owner.gid))
+                    .with_context(|| format!("failed to change owner of {}", path.display()))
+            })
+            .await??;
+            Ok(())
+        }
+    } else if #[cfg(windows)] {
+        // Only the read-only flag can be set on Windows, so executables
+        // are materialized as plain files
+
+        async fn set_file_permissions(
+            path: &Path,
+            permissions: SetFilePermissions,
+            output_permissions: &OutputPermissions,
+        ) -> anyhow::Result<()> {
+            let mut file_permissions = tokio::fs::metadata(path).await?.permissions();
+            file_permissions.set_readonly(permissions.readonly || output_permissions.readonly);
+            tokio::fs::set_permissions(path, file_permissions).await?;
+            Ok(())
+        }
+
+        async fn set_directory_permissions(
+            path: &Path,
+            permissions: SetDirectoryPermissions,
+            _output_permissions: &OutputPermissions,
+        ) -> anyhow::Result<()> {
+            let mut directory_permissions = tokio::fs::metadata(path).await?.permissions();
+            directory_permissions.set_readonly(permissions.readonly);
+            tokio::fs::set_permissions(path, directory_permissions).await?;
+            Ok(())
+        }
+
+        async fn set_output_owner(_path: &Path, owner: Option<OutputOwner>) -> anyhow::Result<()> {
+            anyhow::ensure!(owner.is_none(), "changing the owner of outputs is not supported on Windows");
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,577 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use bstr::{BString, ByteSlice as _};
+use futures::TryStreamExt as _;
+use tokio::io::AsyncWriteExt as _;
+
+use crate::{
+    blob::BlobHash,
+    path::BriochePath,
+    platform::Platform,
+    recipe::{Artifact, Directory},
+    Brioche,
+};
+
+const RESOURCES_DIR: &[u8] = b"brioche-resources.d";
+
+/// The timestamp used for every file within a package, so packaging the
+/// same artifact twice gives the same result.
+const MTIME_DATE: (u16, u8, u8) = (2000, 1, 1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PackageFormat {
+    Zip,
+    Deb,
+    Rpm,
+}
+
+impl PackageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Deb => "deb",
+            Self::Rpm => "rpm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageMetadata {
+    pub name: String,
+    pub version: String,
+    pub license: Option<String>,
+    pub description: Option<String>,
+    pub maintainer: Option<String>,
+}
+
+impl PackageMetadata {
+    /// The default file name for a package, such as `foo_1.0.0_amd64.deb`.
+    pub fn file_name(&self, format: PackageFormat, platform: Platform) -> String {
+        let Self { name, version, .. } = self;
+        match format {
+            PackageFormat::Zip => format!("{name}-{version}-{platform}.zip"),
+            PackageFormat::Deb => format!("{name}_{version}_{}.deb", platform.oci_arch()),
+            PackageFormat::Rpm => format!("{name}-{version}-1.{}.rpm", platform.qemu_arch()),
+        }
+    }
+
+    fn description(&self) -> String {
+        self.description
+            .clone()
+            .unwrap_or_else(|| format!("{} (built with Brioche)", self.name))
+    }
+}
+
+pub struct PackageOptions<'a> {
+    pub format: PackageFormat,
+    pub metadata: &'a PackageMetadata,
+    pub platform: Platform,
+    /// Where the artifact gets installed. Defaults to `/opt/<name>` for
+    /// `.deb` and `.rpm` packages, or a top-level `<name>-<version>`
+    /// directory for `.zip` files.
+    pub prefix: Option<&'a str>,
+}
+
+/// A file within a package, taken from the artifact rather than an output
+/// on disk, so file modes only depend on the artifact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageEntry {
+    Directory,
+    File {
+        content_blob: BlobHash,
+        executable: bool,
+    },
+    Symlink {
+        target: BString,
+    },
+}
+
+impl PackageEntry {
+    pub fn mode(&self) -> u32 {
+        match self {
+            Self::Directory => 0o755,
+            Self::File {
+                executable: true, ..
+            } => 0o755,
+            Self::File {
+                executable: false, ..
+            } => 0o644,
+            Self::Symlink { .. } => 0o777,
+        }
+    }
+}
+
+/// List every entry of a directory artifact, keyed by path. Resources used
+/// by files are included under `brioche-resources.d`, the same way as when
+/// writing the artifact as an output, so packed executables still work
+/// after the package is installed.
+pub async fn package_entries(
+    brioche: &Brioche,
+    directory: &Directory,
+) -> anyhow::Result<BTreeMap<BString, PackageEntry>> {
+    let mut entries = BTreeMap::new();
+    let mut directories = vec![(BriochePath::root(), directory.clone())];
+
+    while let Some((prefix, directory)) = directories.pop() {
+        if !prefix.is_root() {
+            insert_package_entry(
+                &mut entries,
+                prefix.clone().into_bstring(),
+                PackageEntry::Directory,
+            )?;
+        }
+
+        let mut walk = std::pin::pin!(directory.walk(brioche));
+        while let Some((path, artifact)) = walk.try_next().await? {
+            let entry = match artifact.value {
+                Artifact::File(file) => {
+                    if !file.resources.is_empty() {
+                        let resources_dir = BriochePath::from_component(RESOURCES_DIR)?;
+                        directories.push((resources_dir, file.resources));
+                    }
+
+                    PackageEntry::File {
+                        content_blob: file.content_blob,
+                        executable: file.executable,
+                    }
+                }
+                Artifact::Directory(_) => PackageEntry::Directory,
+                Artifact::Symlink { target } => PackageEntry::Symlink { target },
+            };
+
+            let path = prefix.join(&path).into_bstring();
+            insert_package_entry(&mut entries, path, entry)?;
+        }
+    }
+
+    Ok(entries)
+}
+
+fn insert_package_entry(
+    entries: &mut BTreeMap<BString, PackageEntry>,
+    path: BString,
+    entry: PackageEntry,
+) -> anyhow::Result<()> {
+    match entries.get(&path) {
+        Some(existing) if *existing == entry => {}
+        Some(_) => {
+            anyhow::bail!("conflicting entries at {path:?} when merging resources");
+        }
+        None => {
+            entries.insert(path, entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a directory artifact as a `.zip`, `.deb`, or `.rpm` package, so it
+/// can be installed on machines without Brioche.
+pub async fn write_package(
+    brioche: &Brioche,
+    directory: &Directory,
+    options: PackageOptions<'_>,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    validate_metadata(options.metadata)?;
+
+    let artifact = Artifact::Directory(directory.clone());
+    crate::registry::fetch_artifact_blobs(brioche, &artifact).await?;
+    let entries = package_entries(brioche, directory).await?;
+
+    let PackageMetadata { name, version, .. } = options.metadata;
+    let prefix = match (options.prefix, options.format) {
+        (Some(prefix), _) => prefix.to_string(),
+        (None, PackageFormat::Zip) => format!("{name}-{version}"),
+        (None, PackageFormat::Deb | PackageFormat::Rpm) => format!("/opt/{name}"),
+    };
+    // Packages for system package managers shouldn't claim ownership of
+    // shared directories like `/opt`
+    let include_parents = options.format != PackageFormat::Rpm;
+    let entries = archive_entries(brioche, &prefix, include_parents, entries).await?;
+
+    let temp_path = output_path.with_extension(format!("tmp-{}", ulid::Ulid::new()));
+    let result = match options.format {
+        PackageFormat::Zip => write_zip(entries, &temp_path).await,
+        PackageFormat::Deb => {
+            write_deb(entries, options.metadata, options.platform, &temp_path).await
+        }
+        PackageFormat::Rpm => {
+            write_rpm(entries, options.metadata, options.platform, &temp_path).await
+        }
+    };
+    if let Err(error) = result {
+        let _ = crate::fs_utils::try_remove(&temp_path).await;
+        return Err(error).with_context(|| format!("failed to write {} package", options.format));
+    }
+
+    tokio::fs::rename(&temp_path, output_path)
+        .await
+        .with_context(|| format!("failed to write {}", output_path.display()))?;
+
+    Ok(())
+}
+
+fn validate_metadata(metadata: &PackageMetadata) -> anyhow::Result<()> {
+    let PackageMetadata { name, version, .. } = metadata;
+
+    // Debian's rules for package names, which also work for the other
+    // formats
+    let is_valid_name = name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    anyhow::ensure!(
+        is_valid_name,
+        "invalid package name {name:?}: must be lowercase letters, digits, `+`, `-`, or `.`"
+    );
+
+    let is_valid_version = version.starts_with(|c: char| c.is_ascii_digit())
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.~-:".contains(c));
+    anyhow::ensure!(
+        is_valid_version,
+        "invalid package version {version:?}: must start with a digit, and only contain letters, digits, `+`, `.`, `~`, `-`, or `:`"
+    );
+
+    Ok(())
+}
+
+struct ArchiveEntry {
+    path: String,
+    mode: u32,
+    kind: ArchiveEntryKind,
+}
+
+enum ArchiveEntryKind {
+    Directory,
+    File(PathBuf),
+    Contents(Vec<u8>),
+    Symlink(String),
+}
+
+/// Convert package entries to archive entries under `prefix`. The prefix
+/// directory itself is always included, but the directories leading up to
+/// it are only included if `include_parents` is set.
+async fn archive_entries(
+    brioche: &Brioche,
+    prefix: &str,
+    include_parents: bool,
+    entries: BTreeMap<BString, PackageEntry>,
+) -> anyhow::Result<Vec<ArchiveEntry>> {
+    let is_absolute = prefix.starts_with('/');
+    let components = prefix
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!components.is_empty(), "invalid package prefix {prefix:?}");
+
+    let mut archive_entries = vec![];
+    for n in 1..=components.len() {
+        let is_prefix = n == components.len();
+        if is_prefix || include_parents {
+            let path = components[..n].join("/");
+            let path = if is_absolute {
+                format!("/{path}")
+            } else {
+                path
+            };
+            archive_entries.push(ArchiveEntry {
+                path,
+                mode: 0o755,
+                kind: ArchiveEntryKind::Directory,
+            });
+        }
+    }
+
+    let prefix = components.join("/");
+    let prefix = if is_absolute {
+        format!("/{prefix}")
+    } else {
+        prefix
+    };
+    for (path, entry) in entries {
+        let path = path
+            .to_str()
+            .with_context(|| format!("path is not valid UTF-8: {path:?}"))?;
+        let path = format!("{prefix}/{path}");
+
+        let mode = entry.mode();
+        let kind = match entry {
+            PackageEntry::Directory => ArchiveEntryKind::Directory,
+            PackageEntry::File { content_blob, .. } => {
+                ArchiveEntryKind::File(crate::blob::local_blob_path(brioche, content_blob).await?)
+            }
+            PackageEntry::Symlink { target } => {
+                let target = target
+                    .to_str()
+                    .with_context(|| format!("symlink target is not valid UTF-8: {target:?}"))?;
+                ArchiveEntryKind::Symlink(target.to_string())
+            }
+        };
+        archive_entries.push(ArchiveEntry { path, mode, kind });
+    }
+
+    Ok(archive_entries)
+}
+
+fn mtime_secs() -> u64 {
+    crate::fs_utils::brioche_epoch()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("invalid Brioche epoch")
+        .as_secs()
+}
+
+async fn write_zip(entries: Vec<ArchiveEntry>, output_path: &Path) -> anyhow::Result<()> {
+    let output_path = output_path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::create(&output_path)?;
+        let mut zip = zip::ZipWriter::new(file);
+        let (year, month, day) = MTIME_DATE;
+        let mtime = zip::DateTime::from_date_and_time(year, month, day, 0, 0, 0)
+            .map_err(|_| anyhow::anyhow!("invalid zip timestamp"))?;
+
+        for entry in entries {
+            let options = zip::write::FileOptions::default()
+                .last_modified_time(mtime)
+                .unix_permissions(entry.mode);
+            match entry.kind {
+                ArchiveEntryKind::Directory => {
+                    zip.add_directory(entry.path, options)?;
+                }
+                ArchiveEntryKind::File(blob_path) => {
+                    zip.start_file(entry.path, options)?;
+                    let mut blob = std::fs::File::open(&blob_path)
+                        .with_context(|| format!("failed to open {}", blob_path.display()))?;
+                    std::io::copy(&mut blob, &mut zip)?;
+                }
+                ArchiveEntryKind::Contents(contents) => {
+                    zip.start_file(entry.path, options)?;
+                    std::io::Write::write_all(&mut zip, &contents)?;
+                }
+                ArchiveEntryKind::Symlink(target) => {
+                    zip.add_symlink(entry.path, target, options)?;
+                }
+            }
+        }
+
+        zip.finish()?;
+        anyhow::Ok(())
+    })
+    .await??;
+
+    Ok(())
+}
+
+async fn write_deb(
+    entries: Vec<ArchiveEntry>,
+    metadata: &PackageMetadata,
+    platform: Platform,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    let mut control = format!(
+        "Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: {}\n",
+        metadata.name,
+        metadata.version,
+        platform.oci_arch(),
+        metadata.maintainer.as_deref().unwrap_or("Unknown"),
+    );
+    if let Some(license) = &metadata.license {
+        control.push_str(&format!("License: {license}\n"));
+    }
+    control.push_str(&format!("Description: {}\n", metadata.description()));
+
+    let control_tar = tar_gz(vec![
+        ArchiveEntry {
+            path: ".".to_string(),
+            mode: 0o755,
+            kind: ArchiveEntryKind::Directory,
+        },
+        ArchiveEntry {
+            path: "./control".to_string(),
+            mode: 0o644,
+            kind: ArchiveEntryKind::Contents(control.into_bytes()),
+        },
+    ])
+    .await?;
+
+    // Paths in the data archive are relative to the root, like `./opt/foo`
+    let data_entries = std::iter::once(ArchiveEntry {
+        path: ".".to_string(),
+        mode: 0o755,
+        kind: ArchiveEntryKind::Directory,
+    })
+    .chain(entries.into_iter().map(|entry| ArchiveEntry {
+        path: format!("./{}", entry.path.trim_start_matches('/')),
+        ..entry
+    }))
+    .collect();
+    let data_tar = tar_gz(data_entries).await?;
+
+    let mut ar = b"!<arch>\n".to_vec();
+    append_ar_member(&mut ar, "debian-binary", b"2.0\n")?;
+    append_ar_member(&mut ar, "control.tar.gz", &control_tar)?;
+    append_ar_member(&mut ar, "data.tar.gz", &data_tar)?;
+
+    tokio::fs::write(output_path, ar).await?;
+    Ok(())
+}
+
+async fn tar_gz(entries: Vec<ArchiveEntry>) -> anyhow::Result<Vec<u8>> {
+    let encoder = async_compression::tokio::write::GzipEncoder::new(vec![]);
+    let mut builder = tokio_tar::Builder::new(encoder);
+    let mtime = mtime_secs();
+
+    for entry in entries {
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(mtime);
+        header.set_mode(entry.mode);
+
+        match entry.kind {
+            ArchiveEntryKind::Directory => {
+                header.set_entry_type(tokio_tar::EntryType::Directory);
+                header.set_size(0);
+                builder
+                    .append_data(&mut header, format!("{}/", entry.path), tokio::io::empty())
+                    .await?;
+            }
+            ArchiveEntryKind::File(blob_path) => {
+                let blob = tokio::fs::File::open(&blob_path)
+                    .await
+                    .with_context(|| format!("failed to open {}", blob_path.display()))?;
+                header.set_entry_type(tokio_tar::EntryType::Regular);
+                header.set_size(blob.metadata().await?.len());
+                builder.append_data(&mut header, &entry.path, blob).await?;
+            }
+            ArchiveEntryKind::Contents(contents) => {
+                header.set_entry_type(tokio_tar::EntryType::Regular);
+                header.set_size(contents.len() as u64);
+                builder
+                    .append_data(&mut header, &entry.path, &contents[..])
+                    .await?;
+            }
+            ArchiveEntryKind::Symlink(target) => {
+                header.set_entry_type(tokio_tar::EntryType::Symlink);
+                header.set_size(0);
+                header.set_link_name(&target)?;
+                builder
+                    .append_data(&mut header, &entry.path, tokio::io::empty())
+                    .await?;
+            }
+        }
+    }
+
+    let mut encoder = builder.into_inner().await?;
+    encoder.shutdown().await?;
+    Ok(encoder.into_inner())
+}
+
+/// Append a file to an `ar` archive, as used for `.deb` packages.
+fn append_ar_member(ar: &mut Vec<u8>, name: &str, contents: &[u8]) -> anyhow::Result<()> {
+    anyhow::ensure!(name.len() <= 16, "ar member name too long: {name:?}");
+
+    let header = format!(
+        "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+        name,
+        mtime_secs(),
+        0,
+        0,
+        "100644",
+        contents.len(),
+    );
+    assert_eq!(header.len(), 60, "invalid ar header length");
+
+    ar.extend_from_slice(header.as_bytes());
+    ar.extend_from_slice(contents);
+    if contents.len() % 2 != 0 {
+        ar.push(b'\n');
+    }
+
+    Ok(())
+}
+
+async fn write_rpm(
+    entries: Vec<ArchiveEntry>,
+    metadata: &PackageMetadata,
+    platform: Platform,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        entries.iter().all(|entry| entry.path.starts_with('/')),
+        "the prefix for an rpm package must be an absolute path"
+    );
+
+    // The rpm builder only reads file contents from disk, so inline
+    // contents get staged next to the output first. Directories and
+    // symlinks are added from an empty file
+    let staging_path = output_path.with_extension("staging");
+    tokio::fs::create_dir(&staging_path).await?;
+
+    let metadata = metadata.clone();
+    let output_path = output_path.to_owned();
+    let result = tokio::task::spawn_blocking({
+        let staging_path = staging_path.clone();
+        move || {
+            let empty_path = staging_path.join("empty");
+            std::fs::write(&empty_path, b"")?;
+
+            let mut builder = rpm::PackageBuilder::new(
+                &metadata.name,
+                &metadata.version,
+                metadata.license.as_deref().unwrap_or("Unknown"),
+                platform.qemu_arch(),
+                &metadata.description(),
+            )
+            .release("1")
+            .compression(rpm::CompressionType::Gzip)
+            .source_date(crate::fs_utils::brioche_epoch());
+            if let Some(maintainer) = &metadata.maintainer {
+                builder = builder.packager(maintainer);
+            }
+
+            for (n, entry) in entries.into_iter().enumerate() {
+                let options = rpm::FileOptions::new(&entry.path);
+                let mode = entry.mode as u16;
+                builder = match entry.kind {
+                    ArchiveEntryKind::Directory => {
+                        builder.with_file(&empty_path, options.mode(rpm::FileMode::dir(mode)))?
+                    }
+                    ArchiveEntryKind::File(blob_path) => {
+                        builder.with_file(&blob_path, options.mode(rpm::FileMode::regular(mode)))?
+                    }
+                    ArchiveEntryKind::Contents(contents) => {
+                        let contents_path = staging_path.join(n.to_string());
+                        std::fs::write(&contents_path, contents)?;
+                        builder
+                            .with_file(&contents_path, options.mode(rpm::FileMode::regular(mode)))?
+                    }
+                    ArchiveEntryKind::Symlink(target) => builder.with_file(
+                        &empty_path,
+                        options
+                            .symlink(target)
+                            .mode(rpm::FileMode::symbolic_link(mode)),
+                    )?,
+                };
+            }
+
+            let package = builder.build()?;
+            package.write_file(&output_path)?;
+            anyhow::Ok(())
+        }
+    })
+    .await;
+
+    crate::fs_utils::try_remove(&staging_path).await?;
+    result??;
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use bstr::{BStr, BString, ByteSlice as _};
+
+/// A normalized relative path within an artifact, such as the path of an
+/// entry within a directory. Paths are raw bytes, since entry names aren't
+/// required to be valid UTF-8.
+///
+/// A normalized path's components are separated by `/`, and none of them
+/// are empty, `.`, or `..`. The empty path is the root, which refers to
+/// the artifact itself.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BriochePath(BString);
+
+impl BriochePath {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Parse and normalize a path. Both `/` and `\` are treated as
+    /// separators, empty and `.` components are skipped, and `..` removes
+    /// the previous component. Returns an error if the path would escape
+    /// the root.
+    pub fn new(path: impl AsRef<[u8]>) -> Result<Self, BriochePathError> {
+        let path = path.as_ref();
+
+        let mut components = vec![];
+        for component in path.split(|&byte| byte == b'/' || byte == b'\\') {
+            match component {
+                b"" | b"." => {}
+                b".." => {
+                    let popped = components.pop();
+                    if popped.is_none() {
+                        return Err(BriochePathError::Escapes { path: path.into() });
+                    }
+                }
+                component => {
+                    validate_component(component)?;
+                    components.push(component);
+                }
+            }
+        }
+
+        Ok(Self(bstr::join("/", components).into()))
+    }
+
+    /// Create a path from a single entry name. Unlike [`BriochePath::new`],
+    /// the name isn't normalized, so it can't contain separators or be
+    /// empty, `.`, or `..`.
+    pub fn from_component(name: impl AsRef<[u8]>) -> Result<Self, BriochePathError> {
+        let name = name.as_ref();
+        validate_component(name)?;
+        Ok(Self(name.into()))
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_bstr(&self) -> &BStr {
+        self.0.as_bstr()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bstring(self) -> BString {
+        self.0
+    }
+
+    pub fn components(&self) -> impl DoubleEndedIterator<Item = &BStr> {
+        self.0
+            .split_str("/")
+            .filter(|component| !component.is_empty())
+            .map(BStr::new)
+    }
+
+    /// The last component of the path, or `None` for the root.
+    pub fn file_name(&self) -> Option<&BStr> {
+        self.components().next_back()
+    }
+
+    /// The path without its last component, or `None` for the root.
+    pub fn parent(&self) -> Option<BriochePath> {
+        if self.is_root() {
+            return None;
+        }
+
+        match self.0.rsplit_once_str("/") {
+            Some((parent, _)) => Some(Self(parent.into())),
+            None => Some(Self::root()),
+        }
+    }
+
+    pub fn join(&self, other: &BriochePath) -> BriochePath {
+        if self.is_root() {
+            other.clone()
+        } else if other.is_root() {
+            self.clone()
+        } else {
+            Self(bstr::join("/", [self.as_bytes(), other.as_bytes()]).into())
+        }
+    }
+
+    pub fn join_component(&self, name: impl AsRef<[u8]>) -> Result<BriochePath, BriochePathError> {
+        let name = Self::from_component(name)?;
+        Ok(self.join(&name))
+    }
+
+    /// Resolve `path` relative to this one. `path` is normalized the same
+    /// way as with [`BriochePath::new`], so it can use `..` to refer to
+    /// the parents of this path, but not to escape the root.
+    pub fn resolve(&self, path: impl AsRef<[u8]>) -> Result<BriochePath, BriochePathError> {
+        let path = path.as_ref();
+        let joined = bstr::join("/", [self.as_bytes(), path]);
+        Self::new(joined).map_err(|error| match error {
+            BriochePathError::Escapes { .. } => BriochePathError::Escapes { path: path.into() },
+            error => error,
+        })
+    }
+}
+
+impl std::fmt::Display for BriochePath {
+    /// Display the path, replacing invalid UTF-8 with U+FFFD.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<[u8]> for BriochePath {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl From<BriochePath> for BString {
+    fn from(path: BriochePath) -> Self {
+        path.0
+    }
+}
+
+/// Check that `name` is valid as a single path component: not empty, not
+/// `.` or `..`, and without any separators or NUL bytes.
+pub fn validate_component(name: &[u8]) -> Result<(), BriochePathError> {
+    let is_special = name.is_empty() || name == b"." || name == b"..";
+    let has_invalid_byte = name
+        .iter()
+        .any(|&byte| byte == b'/' || byte == b'\\' || byte == 0);
+    if is_special || has_invalid_byte {
+        return Err(BriochePathError::InvalidComponent {
+            component: name.into(),
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum BriochePathError {
+    #[error("path escapes the top-level directory: {path:?}")]
+    Escapes { path: BString },
+    #[error("invalid path component: {component:?}")]
+    InvalidComponent { component: BString },
+}
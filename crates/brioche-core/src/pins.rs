@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    blob::BlobHash,
+    recipe::{Artifact, RecipeHash},
+    Brioche,
+};
+
+/// What a pin protects from being removed from the store.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PinKind {
+    /// A single blob.
+    Blob,
+    /// An artifact, along with every blob it references (including the
+    /// blobs of nested files and resources).
+    Artifact,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pin {
+    pub kind: PinKind,
+    pub hash: String,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+/// Pin a blob, so it's never removed from the store until it's unpinned.
+/// The blob must already be saved locally. Pinning a blob again replaces
+/// its note.
+pub async fn pin_blob(
+    brioche: &Brioche,
+    blob_hash: BlobHash,
+    note: Option<&str>,
+) -> anyhow::Result<()> {
+    brioche.ensure_writable("pin blob")?;
+
+    let local_path = crate::blob::try_local_blob_path(brioche, blob_hash).await?;
+    anyhow::ensure!(local_path.is_some(), "blob {blob_hash} not found");
+
+    insert_pin(brioche, PinKind::Blob, &blob_hash.to_string(), note).await
+}
+
+/// Remove the pin from a blob. Returns false if the blob wasn't pinned.
+pub async fn unpin_blob(brioche: &Brioche, blob_hash: BlobHash) -> anyhow::Result<bool> {
+    brioche.ensure_writable("unpin blob")?;
+    delete_pin(brioche, PinKind::Blob, &blob_hash.to_string()).await
+}
+
+/// Pin an artifact and everything it references, so none of it is removed
+/// from the store until it's unpinned. Any blobs of the artifact that
+/// aren't saved locally are fetched first, so a pinned artifact can be
+/// used offline. Pinning an artifact again replaces its note.
+pub async fn pin_artifact(
+    brioche: &Brioche,
+    artifact_hash: RecipeHash,
+    note: Option<&str>,
+) -> anyhow::Result<()> {
+    brioche.ensure_writable("pin artifact")?;
+
+    let recipe = crate::recipe::get_recipe(brioche, artifact_hash).await?;
+    let artifact: Artifact = recipe
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("cannot pin {artifact_hash}: recipe is not an artifact"))?;
+
+    let mut blobs = HashSet::new();
+    crate::references::descendent_artifact_blobs(brioche, [artifact], &mut blobs).await?;
+    futures::future::try_join_all(
+        blobs
+            .into_iter()
+            .map(|blob_hash| crate::blob::blob_path(brioche, blob_hash)),
+    )
+    .await
+    .with_context(|| format!("failed to fetch blobs for artifact {artifact_hash}"))?;
+
+    insert_pin(brioche, PinKind::Artifact, &artifact_hash.to_string(), note).await
+}
+
+/// Remove the pin from an artifact. Returns false if the artifact wasn't
+/// pinned. Blobs referenced by the artifact stay pinned if they're pinned
+/// on their own or by another artifact.
+pub async fn unpin_artifact(brioche: &Brioche, artifact_hash: RecipeHash) -> anyhow::Result<bool> {
+    brioche.ensure_writable("unpin artifact")?;
+    delete_pin(brioche, PinKind::Artifact, &artifact_hash.to_string()).await
+}
+
+/// Pin either an artifact or a blob by its hash. Artifacts and blobs both
+/// use BLAKE3 hashes, so the hash is pinned as an artifact if there's a
+/// recipe with that hash, or as a blob otherwise.
+pub async fn pin_hash(
+    brioche: &Brioche,
+    hash: &str,
+    note: Option<&str>,
+) -> anyhow::Result<PinKind> {
+    let recipe_hash: RecipeHash = hash.parse()?;
+    if recipe_exists(brioche, recipe_hash).await? {
+        pin_artifact(brioche, recipe_hash, note).await?;
+        Ok(PinKind::Artifact)
+    } else {
+        let blob_hash: BlobHash = hash.parse()?;
+        pin_blob(brioche, blob_hash, note).await?;
+        Ok(PinKind::Blob)
+    }
+}
+
+/// Remove every pin with the given hash, whether it's for an artifact or
+/// a blob. Returns the kinds of pins removed.
+pub async fn unpin_hash(brioche: &Brioche, hash: &str) -> anyhow::Result<Vec<PinKind>> {
+    let mut unpinned = vec![];
+    if unpin_artifact(brioche, hash.parse()?).await? {
+        unpinned.push(PinKind::Artifact);
+    }
+    if unpin_blob(brioche, hash.parse()?).await? {
+        unpinned.push(PinKind::Blob);
+    }
+    Ok(unpinned)
+}
+
+pub async fn list_pins(brioche: &Brioche) -> anyhow::Result<Vec<Pin>> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT kind, hash, note, created_at
+            FROM pins
+            ORDER BY created_at, kind, hash
+        "#,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let pins = records
+        .into_iter()
+        .map(|record| {
+            let kind = record
+                .kind
+                .parse()
+                .with_context(|| format!("invalid pin kind {:?}", record.kind))?;
+            anyhow::Ok(Pin {
+                kind,
+                hash: record.hash,
+                note: record.note,
+                created_at: record.created_at,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(pins)
+}
+
+/// Get every blob that's pinned, either directly or by a pinned artifact.
+/// Anything that removes blobs from the store must keep these.
+pub async fn pinned_blobs(brioche: &Brioche) -> anyhow::Result<HashSet<BlobHash>> {
+    let pins = list_pins(brioche).await?;
+
+    let mut blobs = HashSet::new();
+    let mut artifact_hashes = vec![];
+    for pin in pins {
+        match pin.kind {
+            PinKind::Blob => {
+                blobs.insert(pin.hash.parse()?);
+            }
+            PinKind::Artifact => {
+                artifact_hashes.push(pin.hash.parse()?);
+            }
+        }
+    }
+
+    let recipes = crate::recipe::get_recipes(brioche, artifact_hashes).await?;
+    let artifacts = recipes
+        .into_iter()
+        .map(|(artifact_hash, recipe)| {
+            Artifact::try_from(recipe)
+                .map_err(|_| anyhow::anyhow!("pinned recipe {artifact_hash} is not an artifact"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    crate::references::descendent_artifact_blobs(brioche, artifacts, &mut blobs).await?;
+
+    Ok(blobs)
+}
+
+async fn insert_pin(
+    brioche: &Brioche,
+    kind: PinKind,
+    hash: &str,
+    note: Option<&str>,
+) -> anyhow::Result<()> {
+    let kind_value = kind.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO pins (kind, hash, note)
+            VALUES (?, ?, ?)
+            ON CONFLICT (kind, hash) DO UPDATE SET note = excluded.note
+        "#,
+        kind_value,
+        hash,
+        note,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn delete_pin(brioche: &Brioche, kind: PinKind, hash: &str) -> anyhow::Result<bool> {
+    let kind_value = kind.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let result = sqlx::query!(
+        r#"
+            DELETE FROM pins
+            WHERE kind = ? AND hash = ?
+        "#,
+        kind_value,
+        hash,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+async fn recipe_exists(brioche: &Brioche, recipe_hash: RecipeHash) -> anyhow::Result<bool> {
+    let recipe_hash_value = recipe_hash.to_string();
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let row = sqlx::query!(
+        r#"SELECT 1 AS "found!" FROM recipes WHERE recipe_hash = ?"#,
+        recipe_hash_value,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(row.is_some())
+}
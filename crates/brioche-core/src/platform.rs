@@ -1,3 +1,5 @@
+pub mod emulator;
+
 #[derive(
     Debug,
     Clone,
@@ -13,11 +15,34 @@
 pub enum Platform {
     #[strum(serialize = "x86_64-linux")]
     X86_64Linux,
+    #[strum(serialize = "aarch64-linux")]
+    Aarch64Linux,
+}
+
+impl Platform {
+    /// The architecture name QEMU uses for this platform, as used in
+    /// emulator names like `qemu-aarch64`.
+    pub fn qemu_arch(&self) -> &'static str {
+        match self {
+            Platform::X86_64Linux => "x86_64",
+            Platform::Aarch64Linux => "aarch64",
+        }
+    }
+
+    /// The architecture name used in OCI image configs.
+    pub fn oci_arch(&self) -> &'static str {
+        match self {
+            Platform::X86_64Linux => "amd64",
+            Platform::Aarch64Linux => "arm64",
+        }
+    }
 }
 
 pub fn current_platform() -> Platform {
     if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
         Platform::X86_64Linux
+    } else if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        Platform::Aarch64Linux
     } else {
         unimplemented!("unsupported platform");
     }
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+
+use super::Platform;
+
+const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// Identifies the emulator used to run a process for a foreign platform.
+/// This is included in a process's recipe, so processes run again if the
+/// emulator changes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmulatorIdentity {
+    /// The file name of the emulator binary, such as `qemu-aarch64-static`.
+    pub name: String,
+    /// The BLAKE3 hash of the emulator binary.
+    pub content_hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    pub path: PathBuf,
+    pub identity: EmulatorIdentity,
+    pub mode: EmulatorMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulatorMode {
+    /// The emulator is registered with `binfmt_misc` using the `F` (fix
+    /// binary) flag, so the kernel runs foreign binaries with it
+    /// transparently, even within the sandbox.
+    Binfmt,
+    /// The emulator needs to be called explicitly, with the foreign binary
+    /// as its first argument. Only the top-level command is emulated, so
+    /// the emulator should be statically linked.
+    Wrapper,
+}
+
+/// Finds emulators for foreign platforms. Emulators set in the config file
+/// take priority, otherwise QEMU emulators registered with `binfmt_misc`
+/// are used. Results are cached, since the emulator binary gets hashed.
+#[derive(Debug, Default)]
+pub struct Emulators {
+    configured: HashMap<Platform, PathBuf>,
+    found: tokio::sync::Mutex<HashMap<Platform, Option<Arc<Emulator>>>>,
+}
+
+impl Emulators {
+    pub fn new(configured: HashMap<Platform, PathBuf>) -> Self {
+        Self {
+            configured,
+            found: Default::default(),
+        }
+    }
+
+    pub async fn find(&self, platform: Platform) -> anyhow::Result<Option<Arc<Emulator>>> {
+        let mut found = self.found.lock().await;
+        if let Some(emulator) = found.get(&platform) {
+            return Ok(emulator.clone());
+        }
+
+        let emulator = match self.configured.get(&platform) {
+            Some(path) => Some(emulator_from_path(path, EmulatorMode::Wrapper).await?),
+            None => detect_binfmt_emulator(platform).await?,
+        };
+        let emulator = emulator.map(Arc::new);
+
+        if let Some(emulator) = &emulator {
+            tracing::debug!(
+                %platform,
+                path = %emulator.path.display(),
+                mode = ?emulator.mode,
+                "found emulator"
+            );
+        }
+
+        found.insert(platform, emulator.clone());
+        Ok(emulator)
+    }
+}
+
+async fn detect_binfmt_emulator(platform: Platform) -> anyhow::Result<Option<Emulator>> {
+    let entry_path = Path::new(BINFMT_MISC_DIR).join(format!("qemu-{}", platform.qemu_arch()));
+    let entry = match tokio::fs::read_to_string(&entry_path).await {
+        Ok(entry) => entry,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read binfmt entry {}", entry_path.display()));
+        }
+    };
+
+    let Some(entry) = BinfmtEntry::parse(&entry) else {
+        tracing::warn!(path = %entry_path.display(), "failed to parse binfmt entry");
+        return Ok(None);
+    };
+    if !entry.enabled {
+        return Ok(None);
+    }
+
+    // Without the fix-binary flag, the kernel looks up the interpreter
+    // from within the sandbox, where it won't exist
+    let mode = if entry.flags.contains('F') {
+        EmulatorMode::Binfmt
+    } else {
+        EmulatorMode::Wrapper
+    };
+
+    let emulator = emulator_from_path(&entry.interpreter, mode).await?;
+    Ok(Some(emulator))
+}
+
+async fn emulator_from_path(path: &Path, mode: EmulatorMode) -> anyhow::Result<Emulator> {
+    let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("failed to read emulator {}", path.display()))?;
+    let content_hash = blake3::hash(&contents);
+
+    let name = path
+        .file_name()
+        .with_context(|| format!("invalid emulator path {}", path.display()))?
+        .to_string_lossy()
+        .into_owned();
+
+    Ok(Emulator {
+        path: path.to_owned(),
+        identity: EmulatorIdentity {
+            name,
+            content_hash: content_hash.to_hex().to_string(),
+        },
+        mode,
+    })
+}
+
+struct BinfmtEntry {
+    enabled: bool,
+    interpreter: PathBuf,
+    flags: String,
+}
+
+impl BinfmtEntry {
+    fn parse(entry: &str) -> Option<Self> {
+        let mut enabled = None;
+        let mut interpreter = None;
+        let mut flags = String::new();
+        for line in entry.lines() {
+            match line.split_once(' ') {
+                Some(("interpreter", path)) => {
+                    interpreter = Some(PathBuf::from(path));
+                }
+                Some(("flags:", line_flags)) => {
+                    flags = line_flags.to_string();
+                }
+                _ => match line {
+                    "enabled" => enabled = Some(true),
+                    "disabled" => enabled = Some(false),
+                    _ => {}
+                },
+            }
+        }
+
+        Some(Self {
+            enabled: enabled?,
+            interpreter: interpreter?,
+            flags,
+        })
+    }
+}
@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+use crate::{
+    fs_utils,
+    output::OutputOptions,
+    recipe::{Artifact, Directory},
+    Brioche,
+};
+
+/// What to do when an executable being installed would replace an
+/// executable from another package in the profile's `bin/` directory.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Fail the installation without changing anything.
+    #[default]
+    Error,
+    /// Keep the existing executable, and don't install the new one.
+    Skip,
+    /// Replace the existing executable with the new one.
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InstallOptions<'a> {
+    pub profile_dir: &'a Path,
+    pub conflict_policy: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinEntry {
+    /// A symlink to an executable within the package.
+    Link { target: PathBuf },
+    /// A script that sets up the environment for a packed executable
+    /// before running it.
+    Wrapper { contents: String },
+}
+
+#[derive(Debug, Default)]
+pub struct InstallResult {
+    pub package_dir: PathBuf,
+    /// Names of executables added to (or already up to date in) the
+    /// profile's `bin/` directory.
+    pub installed: Vec<String>,
+    /// Names of executables that conflicted with an existing executable,
+    /// and were kept as-is because of the conflict policy.
+    pub skipped: Vec<String>,
+}
+
+/// Install a directory into a profile. The directory gets written to its
+/// own package directory within the profile (named by the artifact hash),
+/// then each executable in its `bin/` directory is exposed from the
+/// profile's `bin/` directory. Plain executables are symlinked, while
+/// packed executables that rely on a resource directory get a wrapper
+/// script that points them at the package's resources. Generated entries
+/// only depend on the installed package, so installing the same package
+/// twice leaves the profile unchanged.
+pub async fn install_to_profile(
+    brioche: &Brioche,
+    directory: Directory,
+    options: InstallOptions<'_>,
+) -> anyhow::Result<InstallResult> {
+    let artifact = Artifact::Directory(directory);
+    let packages_dir = options.profile_dir.join("packages");
+    let package_dir = packages_dir.join(artifact.hash().to_string());
+
+    if !tokio::fs::try_exists(&package_dir).await? {
+        tokio::fs::create_dir_all(&packages_dir)
+            .await
+            .with_context(|| format!("failed to create {}", packages_dir.display()))?;
+        crate::output::create_output_atomic(
+            brioche,
+            &artifact,
+            OutputOptions {
+                output_path: &package_dir,
+                merge: false,
+                resource_dir: None,
+                mtime: Some(std::time::SystemTime::now()),
+                link_locals: false,
+                link_strategy: crate::output::LinkStrategy::Copy,
+                permissions: crate::output::OutputPermissions::default(),
+            },
+            false,
+        )
+        .await?;
+    }
+
+    let bin_entries = package_bin_entries(&package_dir).await?;
+
+    // Check for every conflict before changing anything, so a failed
+    // install doesn't leave the profile half-updated
+    let profile_bin_dir = options.profile_dir.join("bin");
+    let mut conflicts = vec![];
+    let mut to_write = vec![];
+    let mut result = InstallResult {
+        package_dir: package_dir.clone(),
+        ..Default::default()
+    };
+    for (name, entry) in bin_entries {
+        let path = profile_bin_dir.join(&name);
+        match existing_bin_entry(&path).await? {
+            None => {
+                to_write.push((name, path, entry));
+            }
+            Some(existing) if existing == entry => {
+                result.installed.push(name);
+            }
+            Some(_) => match options.conflict_policy {
+                ConflictPolicy::Error => conflicts.push(path),
+                ConflictPolicy::Skip => result.skipped.push(name),
+                ConflictPolicy::Replace => to_write.push((name, path, entry)),
+            },
+        }
+    }
+
+    if !conflicts.is_empty() {
+        let conflicts = conflicts
+            .iter()
+            .map(|path| format!("  {}", path.display()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        anyhow::bail!("installed executables would conflict with existing files:\n{conflicts}");
+    }
+
+    tokio::fs::create_dir_all(&profile_bin_dir)
+        .await
+        .with_context(|| format!("failed to create {}", profile_bin_dir.display()))?;
+    for (name, path, entry) in to_write {
+        write_bin_entry(&path, &entry)
+            .await
+            .with_context(|| format!("failed to install {}", path.display()))?;
+        result.installed.push(name);
+    }
+
+    result.installed.sort();
+    Ok(result)
+}
+
+/// Get the entries to expose in the profile's `bin/` directory for an
+/// installed package, sorted by name.
+pub async fn package_bin_entries(package_dir: &Path) -> anyhow::Result<Vec<(String, BinEntry)>> {
+    let bin_dir = package_dir.join("bin");
+    let mut read_dir = match tokio::fs::read_dir(&bin_dir).await {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", bin_dir.display()))
+        }
+    };
+
+    let resource_dir = package_dir.join("brioche-resources.d");
+    let has_resource_dir = fs_utils::is_dir(&resource_dir).await;
+
+    let mut entries = vec![];
+    while let Some(dir_entry) = read_dir.next_entry().await? {
+        let Some(name) = dir_entry.file_name().to_str().map(|name| name.to_string()) else {
+            tracing::warn!(path = %dir_entry.path().display(), "skipping executable with non-UTF-8 name");
+            continue;
+        };
+        let path = dir_entry.path();
+
+        // Follow symlinks, so links to executables elsewhere in the
+        // package get exposed too
+        if !fs_utils::is_file(&path).await {
+            continue;
+        }
+
+        let is_packed = has_resource_dir && is_packed_executable(&path).await?;
+        let entry = if is_packed {
+            BinEntry::Wrapper {
+                contents: wrapper_script(&path, &resource_dir)?,
+            }
+        } else {
+            BinEntry::Link { target: path }
+        };
+        entries.push((name, entry));
+    }
+
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries)
+}
+
+async fn is_packed_executable(path: &Path) -> anyhow::Result<bool> {
+    let path = path.to_owned();
+    let pack = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let pack = match brioche_pack::try_extract_pack(file) {
+            Ok(pack) => pack,
+            Err(error) => {
+                tracing::warn!(path = %path.display(), %error, "ignoring malformed pack");
+                None
+            }
+        };
+        anyhow::Ok(pack)
+    })
+    .await??;
+
+    Ok(pack.is_some())
+}
+
+fn wrapper_script(program: &Path, resource_dir: &Path) -> anyhow::Result<String> {
+    let program = shell_quote(program)?;
+    let resource_dir = shell_quote(resource_dir)?;
+    Ok(format!(
+        "#!/bin/sh\n\
+        # Generated by brioche install\n\
+        export BRIOCHE_RESOURCE_DIR={resource_dir}\n\
+        exec {program} \"$@\"\n"
+    ))
+}
+
+fn shell_quote(path: &Path) -> anyhow::Result<String> {
+    let path = path
+        .to_str()
+        .with_context(|| format!("path is not valid UTF-8: {}", path.display()))?;
+    Ok(format!("'{}'", path.replace('\'', r"'\''")))
+}
+
+async fn existing_bin_entry(path: &Path) -> anyhow::Result<Option<BinEntry>> {
+    let metadata = match tokio::fs::symlink_metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    if metadata.is_symlink() {
+        let target = tokio::fs::read_link(path).await?;
+        Ok(Some(BinEntry::Link { target }))
+    } else if metadata.is_file() {
+        // Anything that isn't a script we generated won't be valid UTF-8
+        // or won't match, so it still counts as a conflict
+        let contents = tokio::fs::read(path).await?;
+        let contents = String::from_utf8_lossy(&contents).into_owned();
+        Ok(Some(BinEntry::Wrapper { contents }))
+    } else {
+        anyhow::bail!("{} exists and is not a file", path.display());
+    }
+}
+
+async fn write_bin_entry(path: &Path, entry: &BinEntry) -> anyhow::Result<()> {
+    let temp_path = path.with_extension(format!("brioche-tmp-{}", ulid::Ulid::new()));
+    match entry {
+        BinEntry::Link { target } => {
+            fs_utils::symlink(target, &temp_path).await?;
+        }
+        BinEntry::Wrapper { contents } => {
+            tokio::fs::write(&temp_path, contents).await?;
+            let mut permissions = tokio::fs::metadata(&temp_path).await?.permissions();
+            fs_utils::set_executable(&mut permissions);
+            tokio::fs::set_permissions(&temp_path, permissions).await?;
+        }
+    }
+
+    if let Err(error) = tokio::fs::rename(&temp_path, path).await {
+        let _ = fs_utils::try_remove(&temp_path).await;
+        return Err(error.into());
+    }
+
+    Ok(())
+}
@@ -10,11 +10,17 @@ use futures::{StreamExt as _, TryStreamExt as _};
 use relative_path::{PathExt as _, RelativePath, RelativePathBuf};
 use tokio::io::AsyncWriteExt as _;
 
-use crate::recipe::{Artifact, RecipeHash};
+use crate::{
+    error_codes::{CodedError, ErrorCode},
+    recipe::{Artifact, RecipeHash},
+};
 
 use super::{vfs::FileId, Brioche};
 
 pub mod analyze;
+pub mod env;
+pub mod policy;
+pub mod remote;
 
 #[derive(Clone, Default)]
 pub struct Projects {
@@ -37,7 +43,11 @@ impl Projects {
                 if fully_valid {
                     let errors = &projects.project_load_errors[project_hash];
                     if !errors.is_empty() {
-                        anyhow::bail!("project load errors: {errors:?}");
+                        return Err(CodedError::new(
+                            ErrorCode::DEPENDENCY_RESOLUTION_FAILED,
+                            format!("project load errors: {errors:?}"),
+                        )
+                        .into());
                     }
                 }
 
@@ -45,6 +55,10 @@ impl Projects {
             }
         }
 
+        // Projects extracted from a source bundle come with their registry
+        // dependencies, which need to be in place before loading
+        crate::bundle::import_source_bundle(brioche, path).await?;
+
         let project_hash = load_project(
             self.clone(),
             brioche.clone(),
@@ -61,10 +75,18 @@ impl Projects {
                 .map_err(|_| anyhow::anyhow!("failed to acquire 'projects' lock"))?;
             let errors = &projects.project_load_errors[&project_hash];
             if !errors.is_empty() {
-                anyhow::bail!("project load errors: {errors:?}");
+                return Err(CodedError::new(
+                    ErrorCode::DEPENDENCY_RESOLUTION_FAILED,
+                    format!("project load errors: {errors:?}"),
+                )
+                .into());
             }
         }
 
+        if fully_valid {
+            policy::register_dependency_capabilities(brioche, self, project_hash).await?;
+        }
+
         Ok(project_hash)
     }
 
@@ -209,6 +231,15 @@ impl Projects {
         Ok(path.to_owned())
     }
 
+    /// Returns the hashes of every project that has been loaded.
+    pub fn project_hashes(&self) -> anyhow::Result<Vec<ProjectHash>> {
+        let projects = self
+            .inner
+            .read()
+            .map_err(|_| anyhow::anyhow!("failed to acquire 'projects' lock"))?;
+        Ok(projects.projects.keys().copied().collect())
+    }
+
     pub fn project(&self, project_hash: ProjectHash) -> anyhow::Result<Arc<Project>> {
         let projects = self
             .inner
@@ -502,8 +533,16 @@ async fn load_project(
         let local_set = tokio::task::LocalSet::new();
 
         local_set.spawn_local(async move {
-            let result =
-                load_project_inner(&projects, &brioche, &path, fully_valid, false, depth).await;
+            let result = load_project_inner(
+                &projects,
+                &brioche,
+                &path,
+                fully_valid,
+                false,
+                &HashMap::new(),
+                depth,
+            )
+            .await;
             let _ = tx.send(result).inspect_err(|err| {
                 tracing::warn!("failed to send project load result: {err:?}");
             });
@@ -523,6 +562,7 @@ async fn load_project_inner(
     path: &Path,
     fully_valid: bool,
     lockfile_required: bool,
+    dependency_overrides: &HashMap<String, ProjectHash>,
     depth: usize,
 ) -> anyhow::Result<(ProjectHash, Arc<Project>, Vec<LoadProjectError>)> {
     tracing::debug!(path = %path.display(), "resolving project");
@@ -532,7 +572,8 @@ async fn load_project_inner(
         .with_context(|| format!("failed to canonicalize path {}", path.display()))?;
     let workspace = find_workspace(&path).await?;
 
-    let project_analysis = analyze::analyze_project(&brioche.vfs, &path).await?;
+    let project_analysis =
+        analyze::analyze_project(&brioche.vfs, &brioche.script_read_access, &path).await?;
 
     let lockfile_path = path.join("brioche.lock");
     let lockfile_contents = tokio::fs::read_to_string(&lockfile_path).await;
@@ -568,20 +609,46 @@ async fn load_project_inner(
     let mut new_lockfile = Lockfile::default();
     let mut errors = vec![];
 
-    static DEPENDENCY_NAME_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-    let dependency_name_regex = DEPENDENCY_NAME_REGEX
-        .get_or_init(|| regex::Regex::new("^[a-zA-Z0-9_]+$").expect("failed to compile regex"));
-
     let dep_depth = depth
         .checked_sub(1)
         .context("project dependency depth exceeded")?;
+    let definition = &project_analysis.definition;
+    for name in definition.follows.keys() {
+        anyhow::ensure!(
+            definition.dependencies.contains_key(name),
+            "'{name}' in `follows` is not a dependency of the project"
+        );
+    }
+
+    // Load dependencies that follow other dependencies last, so the
+    // dependencies they follow are already loaded
+    let mut dependency_defs = definition.dependencies.iter().collect::<Vec<_>>();
+    dependency_defs.sort_by_key(|(name, _)| definition.follows.contains_key(*name));
+
     let mut dependencies = HashMap::new();
-    for (name, dependency_def) in &project_analysis.definition.dependencies {
+    for (name, dependency_def) in dependency_defs {
         anyhow::ensure!(
-            dependency_name_regex.is_match(name),
+            crate::registry::name::ProjectName::is_valid(name),
             "invalid dependency name"
         );
 
+        if let Some(dep_hash) = dependency_overrides.get(name) {
+            keep_locked_dependency(lockfile.as_ref(), &mut new_lockfile, name);
+            dependencies.insert(name.to_owned(), *dep_hash);
+            continue;
+        }
+
+        let dep_overrides = match follows_overrides(definition, name, &dependencies) {
+            Ok(dep_overrides) => dep_overrides,
+            Err(error) => {
+                errors.push(LoadProjectError::FailedToLoadDependency {
+                    name: name.to_owned(),
+                    cause: format!("{error:#}"),
+                });
+                continue;
+            }
+        };
+
         let dependency_hash = match dependency_def {
             DependencyDefinition::Path { path: subpath } => {
                 let dep_path = path.join(subpath);
@@ -592,6 +659,7 @@ async fn load_project_inner(
                     &dep_path,
                     fully_valid,
                     lockfile_required,
+                    &dep_overrides,
                     dep_depth,
                     &mut errors,
                 )
@@ -612,6 +680,7 @@ async fn load_project_inner(
                     fully_valid,
                     lockfile_required,
                     lockfile.as_ref(),
+                    &dep_overrides,
                     dep_depth,
                     &mut new_lockfile,
                     &mut errors,
@@ -625,6 +694,16 @@ async fn load_project_inner(
             }
         };
 
+        let dep_project = projects.project(dependency_hash)?;
+        for dep_dep_name in dep_overrides.keys() {
+            if dep_project.dependency_hash(dep_dep_name).is_none() {
+                errors.push(LoadProjectError::FailedToLoadDependency {
+                    name: name.to_owned(),
+                    cause: format!("'{name}' has no dependency '{dep_dep_name}' to follow"),
+                });
+            }
+        }
+
         dependencies.insert(name.to_owned(), dependency_hash);
     }
 
@@ -635,7 +714,7 @@ async fn load_project_inner(
             };
 
             anyhow::ensure!(
-                dependency_name_regex.is_match(dep_name),
+                crate::registry::name::ProjectName::is_valid(dep_name),
                 "invalid imported dependency name: {dep_name}",
             );
 
@@ -644,6 +723,12 @@ async fn load_project_inner(
                     // Dependency already exists
                 }
                 std::collections::hash_map::Entry::Vacant(entry) => {
+                    if let Some(dep_hash) = dependency_overrides.get(dep_name) {
+                        keep_locked_dependency(lockfile.as_ref(), &mut new_lockfile, dep_name);
+                        entry.insert(*dep_hash);
+                        continue;
+                    }
+
                     // Dependency not included explicitly, so load it from
                     // the registry. Equivalent to using a version of `*`
                     let load_result = try_load_registry_dependency_with_errors(
@@ -655,6 +740,7 @@ async fn load_project_inner(
                         fully_valid,
                         lockfile_required,
                         lockfile.as_ref(),
+                        &HashMap::new(),
                         dep_depth,
                         &mut new_lockfile,
                         &mut errors,
@@ -694,11 +780,17 @@ async fn load_project_inner(
         }
     }
 
+    let project_config = env::read_project_config(&path.join(env::PROJECT_CONFIG_FILENAME))
+        .await?
+        .unwrap_or_default();
+
     let project = Project {
         definition: project_analysis.definition,
         dependencies,
         modules,
         statics,
+        env: project_config.env,
+        api_version: project_config.api_version,
     };
     let project = Arc::new(project);
     let project_hash = ProjectHash::from_serializable(&project)?;
@@ -715,7 +807,11 @@ async fn load_project_inner(
 
         if lockfile.as_ref() != Some(&new_lockfile) {
             if lockfile_required {
-                anyhow::bail!("lockfile at {} is out of date", lockfile_path.display());
+                return Err(CodedError::new(
+                    ErrorCode::LOCKFILE_OUT_OF_DATE,
+                    format!("lockfile at {} is out of date", lockfile_path.display()),
+                )
+                .into());
             } else {
                 projects.dirty_lockfiles.insert(lockfile_path, new_lockfile);
             }
@@ -746,6 +842,7 @@ async fn try_load_path_dependency_with_errors(
     dep_path: &Path,
     fully_valid: bool,
     lockfile_required: bool,
+    dependency_overrides: &HashMap<String, ProjectHash>,
     dep_depth: usize,
     errors: &mut Vec<LoadProjectError>,
 ) -> Option<ProjectHash> {
@@ -755,6 +852,7 @@ async fn try_load_path_dependency_with_errors(
         dep_path,
         fully_valid,
         lockfile_required,
+        dependency_overrides,
         dep_depth,
     )
     .await;
@@ -792,6 +890,7 @@ async fn try_load_registry_dependency_with_errors(
     fully_valid: bool,
     lockfile_required: bool,
     lockfile: Option<&Lockfile>,
+    dependency_overrides: &HashMap<String, ProjectHash>,
     dep_depth: usize,
     new_lockfile: &mut Lockfile,
     errors: &mut Vec<LoadProjectError>,
@@ -822,6 +921,7 @@ async fn try_load_registry_dependency_with_errors(
         &resolved_dep.local_path,
         fully_valid,
         resolved_dep.lockfile_required,
+        dependency_overrides,
         dep_depth,
     )
     .await;
@@ -866,6 +966,39 @@ async fn try_load_registry_dependency_with_errors(
     Some(actual_hash)
 }
 
+/// Get the dependencies a dependency should use in place of its own,
+/// based on the project's `follows` definition.
+fn follows_overrides(
+    definition: &ProjectDefinition,
+    name: &str,
+    dependencies: &HashMap<String, ProjectHash>,
+) -> anyhow::Result<HashMap<String, ProjectHash>> {
+    let Some(follows) = definition.follows.get(name) else {
+        return Ok(HashMap::new());
+    };
+
+    follows
+        .iter()
+        .map(|(dep_dep_name, target)| {
+            let target_hash = dependencies.get(target).with_context(|| {
+                format!("'{name}' can't follow '{target}', which is not a dependency of the project or follows another dependency")
+            })?;
+            anyhow::Ok((dep_dep_name.clone(), *target_hash))
+        })
+        .collect()
+}
+
+/// Keep an overridden dependency's entry in the lockfile, so following
+/// a dependency doesn't make a project's lockfile out of date.
+fn keep_locked_dependency(lockfile: Option<&Lockfile>, new_lockfile: &mut Lockfile, name: &str) {
+    let locked_hash = lockfile.and_then(|lockfile| lockfile.dependencies.get(name));
+    if let Some(locked_hash) = locked_hash {
+        new_lockfile
+            .dependencies
+            .insert(name.to_owned(), *locked_hash);
+    }
+}
+
 async fn resolve_dependency_to_local_path(
     brioche: &Brioche,
     workspace: Option<&Workspace>,
@@ -936,17 +1069,71 @@ async fn resolve_project_from_registry(
     dependency_name: &str,
     dependency_version: &Version,
 ) -> anyhow::Result<ProjectHash> {
-    let tag = match dependency_version {
+    let registry_version =
+        find_registry_version(brioche, dependency_name, dependency_version).await?;
+
+    if let Some(deprecation) = registry_version.deprecation {
+        brioche
+            .reporter
+            .emit(superconsole::Lines::from_multiline_string(
+                &format!("warning: '{dependency_name}' is deprecated: {deprecation}"),
+                superconsole::style::ContentStyle {
+                    foreground_color: Some(superconsole::style::Color::Yellow),
+                    ..superconsole::style::ContentStyle::default()
+                },
+            ));
+    }
+
+    Ok(registry_version.project_hash)
+}
+
+pub(crate) struct RegistryVersion {
+    pub project_hash: ProjectHash,
+    pub deprecation: Option<String>,
+}
+
+/// Find the newest version of a project in the registry that matches
+/// `version`, without fetching it.
+pub(crate) async fn find_registry_version(
+    brioche: &Brioche,
+    project_name: &str,
+    version: &Version,
+) -> anyhow::Result<RegistryVersion> {
+    let tag = match version {
         Version::Any => "latest",
     };
     let response = brioche
         .registry_client
-        .get_project_tag(dependency_name, tag)
+        .get_project_tag(project_name, tag)
         .await?;
-    Ok(response.project_hash)
+
+    // Yanked versions are only used if they're already locked, so fall
+    // back to the newest version that hasn't been yanked
+    if response.yanked {
+        let versions = brioche
+            .registry_client
+            .get_project_versions(project_name)
+            .await?;
+        let version = versions
+            .versions
+            .into_iter()
+            .find(|version| !version.yanked)
+            .with_context(|| {
+                format!("all published versions of '{project_name}' have been yanked")
+            })?;
+        Ok(RegistryVersion {
+            project_hash: version.project_hash,
+            deprecation: version.deprecation,
+        })
+    } else {
+        Ok(RegistryVersion {
+            project_hash: response.project_hash,
+            deprecation: response.deprecation,
+        })
+    }
 }
 
-async fn fetch_project_from_registry(
+pub(crate) async fn fetch_project_from_registry(
     brioche: &Brioche,
     project_hash: ProjectHash,
 ) -> anyhow::Result<PathBuf> {
@@ -956,6 +1143,8 @@ async fn fetch_project_from_registry(
         return Ok(local_path);
     }
 
+    brioche.ensure_writable(&format!("fetch project {project_hash}"))?;
+
     let temp_id = ulid::Ulid::new();
     let temp_project_path = brioche.home.join("projects-temp").join(temp_id.to_string());
     tokio::fs::create_dir_all(&temp_project_path).await?;
@@ -998,6 +1187,8 @@ async fn fetch_project_from_registry(
                         &artifact,
                         crate::output::OutputOptions {
                             link_locals: false,
+                            link_strategy: crate::output::LinkStrategy::Copy,
+                            permissions: crate::output::OutputPermissions::default(),
                             merge: true,
                             mtime: None,
                             output_path: &include_path,
@@ -1016,6 +1207,8 @@ async fn fetch_project_from_registry(
                         &artifact,
                         crate::output::OutputOptions {
                             link_locals: false,
+                            link_strategy: crate::output::LinkStrategy::Copy,
+                            permissions: crate::output::OutputPermissions::default(),
                             merge: true,
                             mtime: None,
                             output_path: module_dir,
@@ -1077,6 +1270,21 @@ async fn fetch_project_from_registry(
         .await
         .context("failed to write lockfile")?;
 
+    if !project.env.is_empty() || project.api_version.is_some() {
+        let project_config = env::ProjectConfig {
+            api_version: project.api_version,
+            env: project.env.clone(),
+        };
+        let project_config_contents =
+            toml::to_string(&project_config).context("failed to serialize project config")?;
+        tokio::fs::write(
+            temp_project_path.join(env::PROJECT_CONFIG_FILENAME),
+            project_config_contents,
+        )
+        .await
+        .context("failed to write project config")?;
+    }
+
     if let Some(local_dir) = local_path.parent() {
         tokio::fs::create_dir_all(local_dir)
             .await
@@ -1118,8 +1326,40 @@ async fn resolve_workspace_project_path(
     Ok(None)
 }
 
+/// Resolve the project path from a CLI target like `./packages/foo#test`
+/// (without the `#export` part). The path is resolved relative to
+/// `current_dir`. If there's no directory at that path, it's treated as the
+/// name of a member of the workspace containing `current_dir` instead.
+pub async fn resolve_project_path(current_dir: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let project_path = current_dir.join(path);
+    if tokio::fs::try_exists(&project_path).await? {
+        return Ok(project_path);
+    }
+
+    let workspace = find_workspace_from(current_dir).await?;
+    if let Some(workspace) = workspace {
+        if let Some(member_path) = resolve_workspace_project_path(&workspace, path).await? {
+            return Ok(member_path);
+        }
+    }
+
+    anyhow::bail!(
+        "project not found: {} does not exist and is not a workspace member",
+        project_path.display()
+    );
+}
+
 async fn find_workspace(project_path: &Path) -> anyhow::Result<Option<Workspace>> {
-    for workspace_path in project_path.ancestors().skip(1) {
+    match project_path.parent() {
+        Some(parent) => find_workspace_from(parent).await,
+        None => Ok(None),
+    }
+}
+
+/// Find the workspace containing `path`, checking `path` itself and then
+/// each of its ancestors.
+async fn find_workspace_from(path: &Path) -> anyhow::Result<Option<Workspace>> {
+    for workspace_path in path.ancestors() {
         let workspace_def_path = workspace_path.join("brioche_workspace.toml");
         if tokio::fs::try_exists(&workspace_def_path).await? {
             let workspace_def = tokio::fs::read_to_string(&workspace_def_path)
@@ -1160,27 +1400,13 @@ async fn resolve_static(
                 .context("no parent path for module path")?;
             let input_path = module_dir_path.join(include.path());
 
-            let canonical_project_root =
-                tokio::fs::canonicalize(project_root)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "failed to canonicalize project root {}",
-                            project_root.display()
-                        )
-                    })?;
-            let canonical_input_path =
-                tokio::fs::canonicalize(&input_path)
-                    .await
-                    .with_context(|| {
-                        format!("failed to canonicalize input path {}", input_path.display())
-                    })?;
-            anyhow::ensure!(
-                canonical_input_path.starts_with(&canonical_project_root),
-                "input path {} escapes project root {}",
-                include.path(),
-                project_root.display(),
-            );
+            // Check the real path of the include, so a symlink can't be
+            // used to pull in files from outside the project
+            brioche
+                .script_read_access
+                .ensure_can_read_project_file(project_root, &input_path)
+                .await
+                .with_context(|| format!("failed to include {:?}", include.path()))?;
 
             let artifact = crate::input::create_input(
                 brioche,
@@ -1248,7 +1474,7 @@ async fn resolve_static(
                                 },
                             )?;
                         if glob_set.is_match(&relative_entry_path) {
-                            paths.push((entry.path().to_owned(), relative_entry_path));
+                            paths.push((entry.clone(), relative_entry_path));
                         }
                     }
 
@@ -1258,11 +1484,21 @@ async fn resolve_static(
             .await??;
 
             let artifacts = futures::stream::iter(paths)
-                .then(|(full_path, relative_path)| async move {
+                .then(|(entry, relative_path)| async move {
+                    // Symlinks are kept as symlinks, but anything else
+                    // gets read, so make sure it's really in the project
+                    let full_path = entry.path();
+                    if !entry.path_is_symlink() {
+                        brioche
+                            .script_read_access
+                            .ensure_can_read_project_file(project_root, full_path)
+                            .await?;
+                    }
+
                     let artifact = crate::input::create_input(
                         brioche,
                         crate::input::InputOptions {
-                            input_path: &full_path,
+                            input_path: full_path,
                             meta: &Default::default(),
                             remove_input: false,
                             resource_dir: None,
@@ -1307,6 +1543,10 @@ pub struct Project {
     #[serde_as(as = "HashMap<_, Vec<(_, _)>>")]
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub statics: HashMap<RelativePathBuf, BTreeMap<StaticQuery, Option<RecipeHash>>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, env::EnvVarDeclaration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<u32>,
 }
 
 impl Project {
@@ -1323,6 +1563,13 @@ impl Project {
     pub fn dependency_hash(&self, name: &str) -> Option<ProjectHash> {
         self.dependencies.get(name).copied()
     }
+
+    /// The script API version negotiated for the project, which is the
+    /// declared version or the original version if none was declared.
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+            .unwrap_or(crate::script::api_version::DEFAULT_API_VERSION)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -1342,8 +1589,21 @@ pub enum LoadProjectError {
 pub struct ProjectDefinition {
     pub name: Option<String>,
     pub version: Option<String>,
+
+    /// The project's license, as an SPDX license expression (e.g.
+    /// `"MIT OR Apache-2.0"`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+
     #[serde(default)]
     pub dependencies: HashMap<String, DependencyDefinition>,
+
+    /// Dependencies of dependencies to replace with the project's own
+    /// dependencies, by dependency name and then the name of its
+    /// dependency. For example, `{ foo: { bar: "baz" } }` makes `foo` use
+    /// this project's `baz` dependency in place of its own `bar`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub follows: HashMap<String, HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -8,7 +8,10 @@ use biome_rowan::{AstNode as _, AstNodeList as _, AstSeparatedList as _};
 use relative_path::{PathExt as _, RelativePathBuf};
 
 use crate::{
-    script::specifier::{BriocheImportSpecifier, BriocheModuleSpecifier},
+    script::{
+        read_access::ReadAccess,
+        specifier::{BriocheImportSpecifier, BriocheModuleSpecifier},
+    },
     vfs::{FileId, Vfs},
 };
 
@@ -61,9 +64,16 @@ impl StaticInclude {
     }
 }
 
-pub async fn analyze_project(vfs: &Vfs, project_path: &Path) -> anyhow::Result<ProjectAnalysis> {
+pub async fn analyze_project(
+    vfs: &Vfs,
+    read_access: &ReadAccess,
+    project_path: &Path,
+) -> anyhow::Result<ProjectAnalysis> {
     let root_module_path = project_path.join("project.bri");
     let file = root_module_path.display();
+    read_access
+        .ensure_can_read_project_file(project_path, &root_module_path)
+        .await?;
     let (_, contents) = vfs
         .load(&root_module_path)
         .await
@@ -131,6 +141,7 @@ pub async fn analyze_project(vfs: &Vfs, project_path: &Path) -> anyhow::Result<P
     let mut local_modules = HashMap::new();
     let root_module = analyze_module(
         vfs,
+        read_access,
         &root_module_path,
         project_path,
         Some(&module),
@@ -148,6 +159,7 @@ pub async fn analyze_project(vfs: &Vfs, project_path: &Path) -> anyhow::Result<P
 #[async_recursion::async_recursion(?Send)]
 pub async fn analyze_module(
     vfs: &Vfs,
+    read_access: &ReadAccess,
     module_path: &Path,
     project_path: &Path,
     module: Option<&'async_recursion biome_js_syntax::JsModule>,
@@ -184,6 +196,9 @@ pub async fn analyze_module(
                 crate::fs_utils::is_subpath(&project_subpath),
                 "{display_path}: module escapes project root"
             );
+            read_access
+                .ensure_can_read_project_file(project_path, &module_path)
+                .await?;
 
             let (file_id, contents) = vfs
                 .load(&module_path)
@@ -256,9 +271,15 @@ pub async fn analyze_module(
                     import_module_path.starts_with(project_path),
                     "invalid import path: must be within project root",
                 );
-                let import_module_specifier =
-                    analyze_module(vfs, &import_module_path, project_path, None, local_modules)
-                        .await?;
+                let import_module_specifier = analyze_module(
+                    vfs,
+                    read_access,
+                    &import_module_path,
+                    project_path,
+                    None,
+                    local_modules,
+                )
+                .await?;
                 ImportAnalysis::LocalModule(import_module_specifier)
             }
             BriocheImportSpecifier::External(dependency) => {
@@ -342,6 +363,62 @@ where
         .filter_map(|result| result.transpose())
 }
 
+/// Find the names of a module's top-level exported functions and
+/// variables. Re-exports and default exports aren't included.
+pub fn find_exports(module: &biome_js_syntax::JsModule) -> impl Iterator<Item = String> + '_ {
+    module.items().iter().flat_map(|item| {
+        let biome_js_syntax::AnyJsModuleItem::JsExport(export) = item else {
+            return vec![];
+        };
+        let Ok(biome_js_syntax::AnyJsExportClause::AnyJsDeclarationClause(declaration)) =
+            export.export_clause()
+        else {
+            return vec![];
+        };
+
+        match declaration {
+            biome_js_syntax::AnyJsDeclarationClause::JsFunctionDeclaration(function) => function
+                .id()
+                .ok()
+                .and_then(|id| binding_name(&id))
+                .into_iter()
+                .collect(),
+            biome_js_syntax::AnyJsDeclarationClause::JsVariableDeclarationClause(variables) => {
+                let Ok(declaration) = variables.declaration() else {
+                    return vec![];
+                };
+                declaration
+                    .declarators()
+                    .iter()
+                    .filter_map(|declarator| declarator.ok()?.id().ok())
+                    .filter_map(|id| binding_name(id.as_any_js_binding()?))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    })
+}
+
+/// Parse a module and find the names of its exports, as with
+/// [`find_exports`].
+pub fn module_exports(contents: &str) -> anyhow::Result<Vec<String>> {
+    let parsed = biome_js_parser::parse(
+        contents,
+        biome_js_syntax::JsFileSource::ts().with_module_kind(biome_js_syntax::ModuleKind::Module),
+        biome_js_parser::JsParserOptions::default(),
+    )
+    .cast::<biome_js_syntax::JsModule>()
+    .expect("failed to cast module");
+    let module = parsed.try_tree().context("failed to parse module")?;
+
+    Ok(find_exports(&module).collect())
+}
+
+fn binding_name(binding: &biome_js_syntax::AnyJsBinding) -> Option<String> {
+    let name = binding.as_js_identifier_binding()?.name_token().ok()?;
+    Some(name.text_trimmed().to_string())
+}
+
 pub fn find_statics<'a, D>(
     module: &'a biome_js_syntax::JsModule,
     mut display_location: impl FnMut(usize) -> D + 'a,
@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context as _;
+
+/// The name of the per-project config file, placed next to `project.bri`.
+pub const PROJECT_CONFIG_FILENAME: &str = "brioche.toml";
+
+/// Project settings read from `brioche.toml`. This declares the script API
+/// version the project was written against, and the environment variables
+/// the project can read during evaluation:
+///
+/// ```toml
+/// api_version = 2
+///
+/// [env.MY_FEATURE]
+/// type = "boolean"
+/// default = false
+/// description = "Enable my feature"
+///
+/// [env.API_URL]
+/// type = "string"
+/// ```
+///
+/// A variable without a default is required, and evaluation fails if it
+/// isn't set.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, EnvVarDeclaration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvVarDeclaration {
+    #[serde(rename = "type")]
+    pub ty: EnvVarType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<EnvValue>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EnvVarType {
+    String,
+    Number,
+    Boolean,
+}
+
+impl std::fmt::Display for EnvVarType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String => write!(f, "string"),
+            Self::Number => write!(f, "number"),
+            Self::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Boolean(bool),
+    Number(serde_json::Number),
+    String(String),
+}
+
+impl EnvValue {
+    pub fn ty(&self) -> EnvVarType {
+        match self {
+            Self::Boolean(_) => EnvVarType::Boolean,
+            Self::Number(_) => EnvVarType::Number,
+            Self::String(_) => EnvVarType::String,
+        }
+    }
+}
+
+impl EnvVarType {
+    fn parse(&self, value: &str) -> anyhow::Result<EnvValue> {
+        match self {
+            Self::String => Ok(EnvValue::String(value.to_string())),
+            Self::Number => {
+                let number = if let Ok(number) = value.parse::<i64>() {
+                    serde_json::Number::from(number)
+                } else {
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                        .with_context(|| format!("expected a number, got {value:?}"))?
+                };
+                Ok(EnvValue::Number(number))
+            }
+            Self::Boolean => match value {
+                "true" | "1" => Ok(EnvValue::Boolean(true)),
+                "false" | "0" => Ok(EnvValue::Boolean(false)),
+                _ => anyhow::bail!("expected a boolean, got {value:?}"),
+            },
+        }
+    }
+}
+
+pub async fn read_project_config(path: &Path) -> anyhow::Result<Option<ProjectConfig>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read project config {}", path.display()));
+        }
+    };
+
+    let config: ProjectConfig = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse project config {}", path.display()))?;
+
+    if let Some(api_version) = config.api_version {
+        crate::script::api_version::validate_api_version(api_version)
+            .with_context(|| format!("{}: unsupported API version", path.display()))?;
+    }
+
+    for (name, declaration) in &config.env {
+        anyhow::ensure!(
+            is_valid_env_var_name(name),
+            "{}: invalid environment variable name {name:?}",
+            path.display(),
+        );
+
+        if let Some(default) = &declaration.default {
+            anyhow::ensure!(
+                default.ty() == declaration.ty,
+                "{}: default for environment variable {name} should be a {}, but got a {}",
+                path.display(),
+                declaration.ty,
+                default.ty(),
+            );
+        }
+    }
+
+    Ok(Some(config))
+}
+
+/// Get the values of a project's declared environment variables, using
+/// `lookup` to read each variable. Only declared variables can be read by
+/// scripts, so every value that can affect a build flows into the recipes
+/// built from it, and so into their hashes.
+pub fn resolve_env(
+    declarations: &BTreeMap<String, EnvVarDeclaration>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<BTreeMap<String, EnvValue>> {
+    let mut values = BTreeMap::new();
+    for (name, declaration) in declarations {
+        let value = match lookup(name) {
+            Some(value) => declaration
+                .ty
+                .parse(&value)
+                .with_context(|| format!("invalid value for environment variable {name}"))?,
+            None => declaration
+                .default
+                .clone()
+                .with_context(|| format!("required environment variable {name} is not set"))?,
+        };
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+fn is_valid_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::{recipe::Meta, Brioche};
+
+use super::{ProjectHash, Projects};
+
+/// The name of the per-project policy file, placed next to `project.bri`.
+/// When present in the root project, it's combined with the per-user policy
+/// file, so a project can only narrow what the user allows.
+pub const PROJECT_POLICY_FILENAME: &str = "brioche_policy.toml";
+
+/// Restricts which capabilities dependency projects may use. The root
+/// project is always trusted; the policy only applies to its (transitive)
+/// dependencies.
+///
+/// ```toml
+/// # Capabilities granted to every dependency not listed below
+/// [default]
+/// networking = false
+/// unsafe = false
+///
+/// [projects.curl]
+/// networking = true
+/// unsafe = true
+/// ```
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapabilityPolicy {
+    #[serde(default)]
+    pub default: Capabilities,
+
+    #[serde(default)]
+    pub projects: HashMap<String, Capabilities>,
+}
+
+impl CapabilityPolicy {
+    pub fn allows(&self, project_name: &str, capability: Capability) -> bool {
+        self.capabilities(project_name).allows(capability)
+    }
+
+    pub fn capabilities(&self, project_name: &str) -> Capabilities {
+        self.projects
+            .get(project_name)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Return a policy that only grants a capability to a project if both
+    /// policies grant it.
+    pub fn intersect(&self, other: &Self) -> Self {
+        let project_names = self.projects.keys().chain(other.projects.keys());
+        let projects = project_names
+            .map(|name| {
+                let capabilities = self.capabilities(name).intersect(other.capabilities(name));
+                (name.clone(), capabilities)
+            })
+            .collect();
+
+        Self {
+            default: self.default.intersect(other.default),
+            projects,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Capabilities {
+    #[serde(default, rename = "unsafe")]
+    pub is_unsafe: bool,
+
+    #[serde(default)]
+    pub networking: bool,
+}
+
+impl Capabilities {
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Unsafe => self.is_unsafe,
+            Capability::Networking => self.networking,
+        }
+    }
+
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            is_unsafe: self.is_unsafe && other.is_unsafe,
+            networking: self.networking && other.networking,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Processes marked as `unsafe`, which opt out of parts of the sandbox.
+    Unsafe,
+    /// Processes with networking enabled.
+    Networking,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsafe => write!(f, "unsafe"),
+            Self::Networking => write!(f, "networking"),
+        }
+    }
+}
+
+pub async fn read_policy_file(path: &Path) -> anyhow::Result<Option<CapabilityPolicy>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read policy file {}", path.display()));
+        }
+    };
+
+    let policy = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse policy file {}", path.display()))?;
+    Ok(Some(policy))
+}
+
+/// The capabilities granted to each dependency project that has been
+/// loaded, keyed by the dependency's root directory. Projects that aren't
+/// listed (such as root projects) aren't restricted.
+#[derive(Debug, Default)]
+pub struct DependencyCapabilities {
+    projects: HashMap<PathBuf, (String, Capabilities)>,
+}
+
+impl DependencyCapabilities {
+    fn insert(&mut self, project_root: PathBuf, name: &str, capabilities: Capabilities) {
+        self.projects
+            .entry(project_root)
+            .and_modify(|(_, existing)| *existing = existing.intersect(capabilities))
+            .or_insert_with(|| (name.to_string(), capabilities));
+    }
+
+    fn find(&self, path: &Path) -> Option<&(String, Capabilities)> {
+        path.ancestors()
+            .find_map(|ancestor| self.projects.get(ancestor))
+    }
+}
+
+/// Resolve the active policy for a root project, then record the
+/// capabilities granted to each of its (transitive) dependencies. The
+/// active policy is the per-user policy, narrowed by the root project's own
+/// policy file.
+pub async fn register_dependency_capabilities(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<()> {
+    let project_root = projects.project_root(project_hash)?;
+    let project_policy = read_policy_file(&project_root.join(PROJECT_POLICY_FILENAME)).await?;
+    let policy = match (project_policy, &brioche.capability_policy) {
+        (Some(project_policy), Some(user_policy)) => user_policy.intersect(&project_policy),
+        (Some(policy), None) => policy,
+        (None, Some(policy)) => (**policy).clone(),
+        (None, None) => {
+            return Ok(());
+        }
+    };
+
+    let root_project = projects.project(project_hash)?;
+    let mut visited = HashSet::from([project_hash]);
+    let mut queue = VecDeque::new();
+    for (name, dep_hash) in root_project.dependencies() {
+        queue.push_back((name.to_string(), dep_hash));
+    }
+
+    let mut dependencies = vec![];
+    while let Some((dep_name, dep_hash)) = queue.pop_front() {
+        if !visited.insert(dep_hash) {
+            continue;
+        }
+
+        let dep_root = projects.project_root(dep_hash)?;
+        dependencies.push((dep_root, dep_name));
+
+        let dep_project = projects.project(dep_hash)?;
+        for (name, hash) in dep_project.dependencies() {
+            queue.push_back((name.to_string(), hash));
+        }
+    }
+
+    let mut dependency_capabilities = brioche.dependency_capabilities.write().await;
+    for (dep_root, dep_name) in dependencies {
+        let capabilities = policy.capabilities(&dep_name);
+        dependency_capabilities.insert(dep_root, &dep_name, capabilities);
+    }
+
+    Ok(())
+}
+
+/// Ensure that a process only uses capabilities allowed for every
+/// dependency project on the call stack that created it. Frames are taken
+/// from the recipe's metadata, so processes without a recorded source
+/// (such as recipes loaded from the cache) aren't attributed to any project.
+pub async fn check_process_capabilities(
+    brioche: &Brioche,
+    meta: &Meta,
+    capabilities: &[Capability],
+) -> anyhow::Result<()> {
+    if capabilities.is_empty() {
+        return Ok(());
+    }
+    let Some(frames) = &meta.source else {
+        return Ok(());
+    };
+
+    let dependency_capabilities = brioche.dependency_capabilities.read().await;
+    for frame in frames {
+        let Some(path) = frame
+            .file_name
+            .as_deref()
+            .and_then(|file_name| url::Url::parse(file_name).ok())
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok())
+        else {
+            continue;
+        };
+        let Some((project_name, allowed)) = dependency_capabilities.find(&path) else {
+            continue;
+        };
+
+        for capability in capabilities {
+            anyhow::ensure!(
+                allowed.allows(*capability),
+                "project '{project_name}' uses capability '{capability}' at {frame}, which is not allowed by policy",
+            );
+        }
+    }
+
+    Ok(())
+}
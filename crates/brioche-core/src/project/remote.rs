@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::Brioche;
+
+/// A project fetched from a git repository instead of a local path, given
+/// on the command line like `github:owner/repo` or `git+https://...`.
+///
+/// Supported formats:
+/// - `github:owner/repo` or `github:owner/repo/<rev>`
+/// - `gitlab:owner/repo` or `gitlab:owner/repo/<rev>`
+/// - `git+<url>`, optionally with a `?rev=<rev>` query parameter
+///
+/// `<rev>` can be a branch, a tag, or a full commit hash. The repository's
+/// default branch is used if it's not set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteProjectSource {
+    pub url: url::Url,
+    pub rev: Option<String>,
+}
+
+impl RemoteProjectSource {
+    /// Parse a remote project source, or return `None` if `source` doesn't
+    /// look like one (e.g. because it's a local path).
+    pub fn parse(source: &str) -> anyhow::Result<Option<Self>> {
+        if let Some(repo) = source.strip_prefix("github:") {
+            Self::parse_forge("https://github.com", repo).map(Some)
+        } else if let Some(repo) = source.strip_prefix("gitlab:") {
+            Self::parse_forge("https://gitlab.com", repo).map(Some)
+        } else if let Some(url) = source.strip_prefix("git+") {
+            let mut url: url::Url = url
+                .parse()
+                .with_context(|| format!("invalid git URL: {url}"))?;
+
+            let rev = url
+                .query_pairs()
+                .find(|(key, _)| key == "rev")
+                .map(|(_, rev)| rev.into_owned());
+            let other_query_pairs = url
+                .query_pairs()
+                .filter(|(key, _)| key != "rev")
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect::<Vec<_>>();
+            if other_query_pairs.is_empty() {
+                url.set_query(None);
+            } else {
+                url.query_pairs_mut()
+                    .clear()
+                    .extend_pairs(other_query_pairs);
+            }
+
+            Ok(Some(Self { url, rev }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_forge(base_url: &str, repo: &str) -> anyhow::Result<Self> {
+        let mut components = repo.splitn(3, '/');
+        let (Some(owner), Some(name)) = (components.next(), components.next()) else {
+            anyhow::bail!("invalid repository {repo:?}, expected `owner/repo`");
+        };
+        anyhow::ensure!(
+            !owner.is_empty() && !name.is_empty(),
+            "invalid repository {repo:?}, expected `owner/repo`"
+        );
+        let rev = components
+            .next()
+            .filter(|rev| !rev.is_empty())
+            .map(|rev| rev.to_string());
+
+        let url = format!("{base_url}/{owner}/{name}.git")
+            .parse()
+            .with_context(|| format!("invalid repository {repo:?}"))?;
+        Ok(Self { url, rev })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchedRemoteProject {
+    /// The path of the checked out project.
+    pub path: PathBuf,
+    /// The commit that was checked out.
+    pub commit: String,
+}
+
+/// Fetch a remote project and check it out into the brioche home
+/// directory. Each checkout is kept by commit, so fetching the same commit
+/// again reuses the existing checkout. The resolved commit is recorded in
+/// the database, so the exact revision that was built can be looked up
+/// later.
+pub async fn fetch_remote_project(
+    brioche: &Brioche,
+    source: &RemoteProjectSource,
+) -> anyhow::Result<FetchedRemoteProject> {
+    let repo_path = crate::bake::git::cache_repo_path(brioche, &source.url);
+    let url_hash = blake3::hash(source.url.as_str().as_bytes());
+    let checkouts_dir = brioche
+        .home
+        .join("remote-projects")
+        .join(url_hash.to_hex().as_str());
+    tokio::fs::create_dir_all(&checkouts_dir).await?;
+
+    let commit = {
+        let repo_lock = crate::bake::git::repo_lock(&repo_path);
+        let _repo_guard = repo_lock.lock().await;
+
+        let source = source.clone();
+        let checkouts_dir = checkouts_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let full_commit_id = source
+                .rev
+                .as_deref()
+                .filter(|rev| rev.len() == 40)
+                .and_then(|rev| gix::ObjectId::from_hex(rev.as_bytes()).ok());
+            let (repo, commit_id) = match full_commit_id {
+                Some(commit_id) => {
+                    let repo = crate::bake::git::fetch_commit(&repo_path, &source.url, commit_id)?;
+                    (repo, commit_id)
+                }
+                None => {
+                    crate::bake::git::fetch_ref(&repo_path, &source.url, source.rev.as_deref())?
+                }
+            };
+
+            // Check out to a temporary path first, so a failed checkout
+            // doesn't get reused
+            let commit = commit_id.to_string();
+            let checkout_path = checkouts_dir.join(&commit);
+            if !checkout_path.exists() {
+                let temp_path = checkouts_dir.join(format!(".{commit}.tmp-{}", ulid::Ulid::new()));
+                crate::bake::git::write_commit(&repo, commit_id, &temp_path)?;
+                std::fs::rename(&temp_path, &checkout_path)?;
+            }
+
+            anyhow::Ok(commit)
+        })
+        .await?
+        .with_context(|| format!("failed to fetch project from {}", source.url))?
+    };
+
+    record_remote_project_fetch(brioche, source, &commit).await?;
+
+    Ok(FetchedRemoteProject {
+        path: checkouts_dir.join(&commit),
+        commit,
+    })
+}
+
+async fn record_remote_project_fetch(
+    brioche: &Brioche,
+    source: &RemoteProjectSource,
+    commit: &str,
+) -> anyhow::Result<()> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+
+    let url = source.url.as_str();
+    let rev = source.rev.as_deref();
+    sqlx::query!(
+        r#"
+            INSERT INTO remote_project_fetches (url, rev, commit_hash)
+            VALUES (?, ?, ?)
+        "#,
+        url,
+        rev,
+        commit,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+
+    db_transaction.commit().await?;
+
+    Ok(())
+}
@@ -18,6 +18,9 @@ pub async fn publish_project(
         .project(project_hash)
         .context("project not found")?;
     let project_name = project.definition.name.clone().context("project must have a name to be published (does the root module have `export const project = { ... }`?")?;
+    project_name
+        .parse::<crate::registry::name::ProjectName>()
+        .with_context(|| format!("cannot publish project with invalid name {project_name:?}"))?;
 
     // Get all project references (project dependencies / blobs / recipes)
     let mut project_references = ProjectReferences::default();
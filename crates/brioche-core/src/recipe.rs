@@ -1,6 +1,6 @@
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     sync::{Arc, OnceLock, RwLock},
 };
 
@@ -10,9 +10,22 @@ use futures::{StreamExt as _, TryStreamExt as _};
 use joinery::JoinableIterator as _;
 use sqlx::{Acquire as _, Arguments as _};
 
-use crate::encoding::TickEncoded;
+use crate::{
+    annotations::Annotations,
+    encoding::TickEncoded,
+    path::{BriochePath, BriochePathError},
+};
+
+use super::{
+    blob::BlobHash,
+    platform::{emulator::EmulatorIdentity, Platform},
+    Brioche, Hash,
+};
 
-use super::{blob::BlobHash, platform::Platform, Brioche, Hash};
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+pub mod diff;
+pub mod pretty;
 
 #[serde_with::serde_as]
 #[derive(
@@ -47,6 +60,8 @@ pub enum Recipe {
     #[serde(rename_all = "camelCase")]
     Download(DownloadRecipe),
     #[serde(rename_all = "camelCase")]
+    GitCheckout(GitCheckoutRecipe),
+    #[serde(rename_all = "camelCase")]
     Unarchive(Unarchive),
     Process(ProcessRecipe),
     CompleteProcess(CompleteProcessRecipe),
@@ -68,6 +83,10 @@ pub enum Recipe {
     Merge {
         directories: Vec<WithMeta<Recipe>>,
     },
+    /// Merge the outputs of several packages into one directory, reporting
+    /// paths where packages collide. See [`EnvironmentRecipe`].
+    #[serde(rename_all = "camelCase")]
+    Environment(EnvironmentRecipe),
     #[serde(rename_all = "camelCase")]
     Peel {
         directory: Box<WithMeta<Recipe>>,
@@ -86,17 +105,54 @@ pub enum Recipe {
         path: BString,
         recipe: Option<Box<WithMeta<Recipe>>>,
     },
+    /// Remove paths from a directory. Paths that don't exist are ignored.
+    #[serde(rename_all = "camelCase")]
+    Remove {
+        directory: Box<WithMeta<Recipe>>,
+        #[serde_as(as = "Vec<TickEncoded>")]
+        paths: Vec<BString>,
+    },
+    /// Move an entry within a directory. Fails if there's nothing at `from`
+    /// or if something already exists at `to`.
+    #[serde(rename_all = "camelCase")]
+    Rename {
+        directory: Box<WithMeta<Recipe>>,
+        #[serde_as(as = "TickEncoded")]
+        from: BString,
+        #[serde_as(as = "TickEncoded")]
+        to: BString,
+    },
+    /// Replace symlinks within a directory with the artifacts they point
+    /// to. Fails if any symlink points outside of the directory.
+    #[serde(rename_all = "camelCase")]
+    ResolveSymlinks {
+        directory: Box<WithMeta<Recipe>>,
+    },
     #[serde(rename_all = "camelCase")]
     SetPermissions {
         file: Box<WithMeta<Recipe>>,
         executable: Option<bool>,
     },
     #[serde(rename_all = "camelCase")]
+    Patch {
+        directory: Box<WithMeta<Recipe>>,
+        patches: Vec<WithMeta<Recipe>>,
+        strip: u32,
+    },
+    #[serde(rename_all = "camelCase")]
     Proxy(ProxyRecipe),
     #[serde(rename_all = "camelCase")]
     Sync {
         recipe: Box<WithMeta<Recipe>>,
     },
+    /// Attach structured metadata to the artifact baked from `recipe`. The
+    /// artifact itself is returned unchanged, and the annotations can be
+    /// looked up by its hash afterwards.
+    #[serde(rename_all = "camelCase")]
+    Annotate {
+        recipe: Box<WithMeta<Recipe>>,
+        annotations: Annotations,
+    },
 }
 
 impl Recipe {
@@ -131,9 +187,34 @@ impl Recipe {
         self.into()
     }
 
+    /// The cache policy set on the recipe. Only processes can set a
+    /// policy, so other recipes use the default. Processes that use
+    /// secrets without an output hash are always local-only, since their
+    /// output could depend on (or contain) the secrets.
+    pub fn cache_policy(&self) -> CachePolicy {
+        let (cache_policy, secrets, output_hash) = match self {
+            Recipe::Process(process) => {
+                (process.cache_policy, &process.secrets, process.output_hash)
+            }
+            Recipe::CompleteProcess(process) => {
+                (process.cache_policy, &process.secrets, process.output_hash)
+            }
+            _ => return CachePolicy::Default,
+        };
+
+        if !secrets.is_empty() && output_hash.is_none() && cache_policy.allows_upload() {
+            CachePolicy::LocalOnly
+        } else {
+            cache_policy
+        }
+    }
+
     pub fn is_expensive_to_bake(&self) -> bool {
         match self {
-            Recipe::Download(_) | Recipe::CompleteProcess(_) | Recipe::Sync { .. } => true,
+            Recipe::Download(_)
+            | Recipe::GitCheckout(_)
+            | Recipe::CompleteProcess(_)
+            | Recipe::Sync { .. } => true,
             Recipe::File { .. }
             | Recipe::Directory(_)
             | Recipe::Symlink { .. }
@@ -143,11 +224,17 @@ impl Recipe {
             | Recipe::CreateDirectory(_)
             | Recipe::Cast { .. }
             | Recipe::Merge { .. }
+            | Recipe::Environment(_)
             | Recipe::Peel { .. }
             | Recipe::Get { .. }
             | Recipe::Insert { .. }
+            | Recipe::Remove { .. }
+            | Recipe::Rename { .. }
+            | Recipe::ResolveSymlinks { .. }
             | Recipe::SetPermissions { .. }
-            | Recipe::Proxy(_) => false,
+            | Recipe::Patch { .. }
+            | Recipe::Proxy(_)
+            | Recipe::Annotate { .. } => false,
         }
     }
 }
@@ -224,6 +311,18 @@ pub async fn get_recipes(
         recipes.insert(recipe_hash, recipe);
     }
 
+    drop(cached_recipes);
+
+    // Copy any recipes that are only available from a mirror store
+    if !uncached_recipes.is_empty() && !brioche.mirror_stores.is_empty() {
+        let mirrored_recipes = crate::mirror::find_recipes(brioche, &uncached_recipes).await;
+        save_recipes(brioche, mirrored_recipes.values()).await?;
+        for (recipe_hash, recipe) in mirrored_recipes {
+            uncached_recipes.remove(&recipe_hash);
+            recipes.insert(recipe_hash, recipe);
+        }
+    }
+
     if !uncached_recipes.is_empty() {
         anyhow::bail!("recipes not found: {uncached_recipes:?}");
     }
@@ -267,6 +366,28 @@ where
         return Ok(0);
     }
 
+    if brioche.read_only {
+        // Nothing can be saved, but the recipes may already be in the store
+        let local_recipes = crate::references::local_recipes(
+            brioche,
+            uncached_recipes.iter().map(|recipe| recipe.hash()),
+        )
+        .await?;
+        if let Some(missing) = uncached_recipes
+            .iter()
+            .find(|recipe| !local_recipes.contains(&recipe.hash()))
+        {
+            brioche.ensure_writable(&format!("save recipe {}", missing.hash()))?;
+        }
+
+        let mut cached_recipes = brioche.cached_recipes.write().await;
+        for recipe in uncached_recipes {
+            cached_recipes.recipes_by_hash.insert(recipe.hash(), recipe);
+        }
+
+        return Ok(0);
+    }
+
     let mut db_conn = brioche.db_conn.lock().await;
     let mut db_transaction = db_conn.begin().await?;
 
@@ -376,8 +497,15 @@ where
     where
         D: serde::Deserializer<'de>,
     {
-        let value: T = serde::Deserialize::deserialize(deserializer)?;
-        Ok(WithMeta::without_meta(value))
+        // Recipes from scripts can include a `meta` field with the stack
+        // frames where they were created, which isn't part of the value
+        let mut value: serde_json::Value = serde::Deserialize::deserialize(deserializer)?;
+        let meta = match value.as_object_mut().and_then(|value| value.remove("meta")) {
+            Some(meta) => serde_json::from_value(meta).map_err(serde::de::Error::custom)?,
+            None => Meta::default(),
+        };
+        let value = T::deserialize(value).map_err(serde::de::Error::custom)?;
+        Ok(WithMeta::new(value, Arc::new(meta)))
     }
 }
 
@@ -489,6 +617,43 @@ pub struct DownloadRecipe {
     pub hash: Hash,
 }
 
+/// Check out a commit from a git repository, as a directory without the
+/// `.git` directory. `rev` must be a full commit hash, so that the result
+/// doesn't change if the repository does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitCheckoutRecipe {
+    pub url: url::Url,
+    pub rev: String,
+}
+
+/// Merge the outputs of several packages into a single directory, like
+/// an installed environment with each package's `bin`, `lib`, etc. laid
+/// over each other. Unlike a merge, packages can't silently replace each
+/// other's files: when packages provide different artifacts at the same
+/// path, the package with the highest priority wins, and packages with
+/// the same priority collide. Collisions fail the bake, listing every
+/// colliding path, unless `ignore_collisions` is set (in which case the
+/// earliest package wins).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentRecipe {
+    pub packages: Vec<EnvironmentPackage>,
+    #[serde(default)]
+    pub ignore_collisions: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentPackage {
+    /// The name of the package, used when reporting collisions.
+    pub name: String,
+    pub recipe: WithMeta<Recipe>,
+    /// Packages with a higher priority win collisions.
+    #[serde(default)]
+    pub priority: i32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Unarchive {
@@ -528,6 +693,89 @@ pub struct ProcessRecipe {
 
     #[serde(default, skip_serializing_if = "crate::utils::is_default")]
     pub networking: bool,
+
+    /// The expected hash of the output artifact. A process with a fixed
+    /// output hash can enable networking without being marked as `unsafe`,
+    /// since the output is validated against the hash after it runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<RecipeHash>,
+
+    /// Use the process's stdout as its output (as a non-executable file),
+    /// instead of whatever the process writes to `$BRIOCHE_OUTPUT`.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub capture_stdout: bool,
+
+    /// Attach the process's stdout and stderr to a pseudo-terminal, for
+    /// tools that behave differently (or hang) without a TTY. Terminal
+    /// control sequences are stripped from the logged output.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub tty: bool,
+
+    /// How the process's results are cached and shared.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub cache_policy: CachePolicy,
+
+    /// Names of secrets to mount into the process, from the secrets
+    /// configured for Brioche. Only the names are part of the recipe, so
+    /// secret values never affect the cache key or get saved with the
+    /// recipe. Requires an output hash or `unsafe`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub secrets: BTreeSet<String>,
+
+    /// Run the process incrementally. The output from the last bake of a
+    /// process with the same key is used in place of the output scaffold,
+    /// so the process can update its previous output (e.g. to reuse a
+    /// build cache between builds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incremental_key: Option<String>,
+}
+
+/// Controls how a recipe's bake results are cached and shared, set by
+/// projects for specific recipes.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::Display,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum CachePolicy {
+    /// Reuse results from the local database, mirror stores, and the
+    /// registry, and upload new results when syncing.
+    #[default]
+    Default,
+
+    /// Always bake the recipe again instead of reusing a cached result.
+    /// Results are still recorded locally, but never uploaded.
+    NoCache,
+
+    /// Only use results baked locally, and never upload them to the
+    /// registry (e.g. because the output contains secrets), nor any bakes
+    /// that use its output.
+    LocalOnly,
+
+    /// Check mirror stores and the registry before the local database, so
+    /// a shared result is used even if a different one was baked locally.
+    PreferRemote,
+}
+
+impl CachePolicy {
+    /// Whether results can be fetched from mirror stores or the registry.
+    pub fn uses_remote_cache(self) -> bool {
+        matches!(self, Self::Default | Self::PreferRemote)
+    }
+
+    /// Whether results can be uploaded to the registry.
+    pub fn allows_upload(self) -> bool {
+        matches!(self, Self::Default | Self::PreferRemote)
+    }
 }
 
 #[serde_with::serde_as]
@@ -558,6 +806,48 @@ pub struct CompleteProcessRecipe {
 
     #[serde(default, skip_serializing_if = "crate::utils::is_default")]
     pub networking: bool,
+
+    /// The expected hash of the output artifact. A process with a fixed
+    /// output hash can enable networking without being marked as `unsafe`,
+    /// since the output is validated against the hash after it runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<RecipeHash>,
+
+    /// Use the process's stdout as its output (as a non-executable file),
+    /// instead of whatever the process writes to `$BRIOCHE_OUTPUT`.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub capture_stdout: bool,
+
+    /// Attach the process's stdout and stderr to a pseudo-terminal, for
+    /// tools that behave differently (or hang) without a TTY. Terminal
+    /// control sequences are stripped from the logged output.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub tty: bool,
+
+    /// How the process's results are cached and shared.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub cache_policy: CachePolicy,
+
+    /// Names of secrets to mount into the process, from the secrets
+    /// configured for Brioche. Only the names are part of the recipe, so
+    /// secret values never affect the cache key or get saved with the
+    /// recipe. Requires an output hash or `unsafe`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub secrets: BTreeSet<String>,
+
+    /// The emulator used to run the process, when its platform differs
+    /// from the current platform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emulator: Option<EmulatorIdentity>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incremental_key: Option<String>,
+
+    /// The output from the previous bake of an incremental process, which
+    /// is used in place of the output scaffold. This makes the previous
+    /// output part of the process's cache key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_output: Option<Box<Artifact>>,
 }
 
 #[serde_with::serde_as]
@@ -710,6 +1000,34 @@ impl Directory {
         Ok(entries)
     }
 
+    /// Recursively walk the directory, yielding the path of each entry
+    /// (relative to this directory) along with its artifact. Entries are
+    /// yielded depth-first in sorted order, with each directory before its
+    /// contents. Subdirectories are only loaded once the walk reaches them,
+    /// so stopping early avoids loading the rest of the tree.
+    pub fn walk<'a>(
+        &self,
+        brioche: &'a Brioche,
+    ) -> impl futures::Stream<Item = anyhow::Result<(BriochePath, WithMeta<Artifact>)>> + 'a {
+        let root = Some(self.clone());
+        let stack: Vec<(BriochePath, WithMeta<Artifact>)> = vec![];
+
+        futures::stream::try_unfold((root, stack), move |(root, mut stack)| async move {
+            if let Some(root) = root {
+                push_walk_entries(brioche, &mut stack, &BriochePath::root(), &root).await?;
+            }
+
+            let Some((path, artifact)) = stack.pop() else {
+                return anyhow::Ok(None);
+            };
+            if let Artifact::Directory(directory) = &artifact.value {
+                push_walk_entries(brioche, &mut stack, &path, directory).await?;
+            }
+
+            anyhow::Ok(Some(((path, artifact), (None, stack))))
+        })
+    }
+
     #[async_recursion::async_recursion]
     async fn get_by_components(
         &self,
@@ -849,24 +1167,12 @@ impl Directory {
         brioche: &Brioche,
         path: &[u8],
     ) -> Result<Option<WithMeta<Artifact>>, DirectoryError> {
-        let path = bstr::BStr::new(path);
-        let mut components = vec![];
-        for component in path.split(|&byte| byte == b'/' || byte == b'\\') {
-            if component.is_empty() || component == b"." {
-                // Skip this component
-            } else if component == b".." {
-                // Pop the last component
-                let removed_component = components.pop();
-                if removed_component.is_none() {
-                    return Err(DirectoryError::PathEscapes { path: path.into() });
-                }
-            } else {
-                // Push this component
-                components.push(bstr::BStr::new(component));
-            }
-        }
+        let full_path = BStr::new(path);
+        let path = parse_directory_path(full_path)?;
+        let components = path.components().collect::<Vec<_>>();
 
-        self.get_by_components(brioche, path, &components).await
+        self.get_by_components(brioche, full_path, &components)
+            .await
     }
 
     pub async fn insert(
@@ -875,25 +1181,44 @@ impl Directory {
         path: &[u8],
         artifact: Option<WithMeta<Artifact>>,
     ) -> Result<Option<WithMeta<Artifact>>, DirectoryError> {
-        let path = bstr::BStr::new(path);
-        let mut components = vec![];
-        for component in path.split(|&byte| byte == b'/' || byte == b'\\') {
-            if component.is_empty() || component == b"." {
-                // Skip this component
-            } else if component == b".." {
-                // Pop the last component
-                let removed_component = components.pop();
-                if removed_component.is_none() {
-                    return Err(DirectoryError::PathEscapes { path: path.into() });
+        let full_path = BStr::new(path);
+        let path = parse_directory_path(full_path)?;
+        let components = path.components().collect::<Vec<_>>();
+
+        self.insert_by_components(brioche, full_path, &components, artifact)
+            .await
+    }
+
+    /// Check that each entry name in the directory is a single normal path
+    /// component (not empty, not `.` or `..`, and with no `/` or NUL bytes).
+    /// Entries are always kept in canonical (sorted) order, since they're
+    /// stored in a `BTreeMap`.
+    pub fn check_invariants(&self) -> Result<(), DirectoryInvariantError> {
+        for name in self.entries.keys() {
+            check_entry_name(name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the invariants of this directory, all of its subdirectories,
+    /// and the resources of all files it contains.
+    #[async_recursion::async_recursion]
+    pub async fn check_invariants_recursive(&self, brioche: &Brioche) -> anyhow::Result<()> {
+        self.check_invariants()?;
+
+        for (name, entry) in self.entries(brioche).await? {
+            let result = match &entry.value {
+                Artifact::Directory(directory) => {
+                    directory.check_invariants_recursive(brioche).await
                 }
-            } else {
-                // Push this component
-                components.push(bstr::BStr::new(component));
-            }
+                Artifact::File(file) => file.resources.check_invariants_recursive(brioche).await,
+                Artifact::Symlink { .. } => Ok(()),
+            };
+            result.with_context(|| format!("invalid directory entry {name:?}"))?;
         }
 
-        self.insert_by_components(brioche, path, &components, artifact)
-            .await
+        Ok(())
     }
 
     #[async_recursion::async_recursion]
@@ -939,6 +1264,33 @@ impl Directory {
     }
 }
 
+/// Push the entries of `directory` onto a walk's stack, in reverse order so
+/// they get popped in sorted order.
+async fn push_walk_entries(
+    brioche: &Brioche,
+    stack: &mut Vec<(BriochePath, WithMeta<Artifact>)>,
+    path: &BriochePath,
+    directory: &Directory,
+) -> anyhow::Result<()> {
+    let entries = directory.entries(brioche).await?;
+    for (name, entry) in entries.into_iter().rev() {
+        let entry_path = path.join_component(&name)?;
+        stack.push((entry_path, entry));
+    }
+
+    Ok(())
+}
+
+fn parse_directory_path(path: &BStr) -> Result<BriochePath, DirectoryError> {
+    let parsed = BriochePath::new(path).map_err(|error| match error {
+        BriochePathError::Escapes { .. } => DirectoryError::PathEscapes { path: path.into() },
+        BriochePathError::InvalidComponent { .. } => {
+            DirectoryError::InvalidPath { path: path.into() }
+        }
+    })?;
+    Ok(parsed)
+}
+
 impl TryFrom<Recipe> for Artifact {
     type Error = RecipeIncomplete;
 
@@ -966,6 +1318,7 @@ impl TryFrom<Recipe> for Artifact {
             }
             Recipe::Sync { recipe } => recipe.value.try_into(),
             Recipe::Download { .. }
+            | Recipe::GitCheckout { .. }
             | Recipe::Unarchive { .. }
             | Recipe::Process { .. }
             | Recipe::CompleteProcess { .. }
@@ -973,11 +1326,17 @@ impl TryFrom<Recipe> for Artifact {
             | Recipe::CreateDirectory(..)
             | Recipe::Cast { .. }
             | Recipe::Merge { .. }
+            | Recipe::Environment(_)
             | Recipe::Peel { .. }
             | Recipe::Get { .. }
             | Recipe::Insert { .. }
+            | Recipe::Remove { .. }
+            | Recipe::Rename { .. }
+            | Recipe::ResolveSymlinks { .. }
             | Recipe::SetPermissions { .. }
-            | Recipe::Proxy { .. } => Err(RecipeIncomplete),
+            | Recipe::Patch { .. }
+            | Recipe::Proxy { .. }
+            | Recipe::Annotate { .. } => Err(RecipeIncomplete),
         }
     }
 }
@@ -1041,6 +1400,8 @@ pub enum DirectoryError {
     EmptyPath { path: bstr::BString },
     #[error("path escapes directory structure: {path:?}")]
     PathEscapes { path: bstr::BString },
+    #[error("invalid path: {path:?}")]
+    InvalidPath { path: bstr::BString },
     #[error("path descends into non-directory: {path:?}")]
     PathDescendsIntoNonDirectory { path: bstr::BString },
     #[error("path {path:?} contains an incomplete recipe")]
@@ -1049,6 +1410,31 @@ pub enum DirectoryError {
     Other(#[from] anyhow::Error),
 }
 
+/// Check that a name is a single normal path component, so it can be used
+/// as a directory entry (not empty, not `.` or `..`, and with no `/` or NUL
+/// bytes).
+pub fn check_entry_name(name: &[u8]) -> Result<(), DirectoryInvariantError> {
+    if name.is_empty() {
+        return Err(DirectoryInvariantError::EmptyName);
+    }
+
+    let is_special = name == b"." || name == b"..";
+    let has_invalid_byte = name.iter().any(|&byte| byte == b'/' || byte == 0);
+    if is_special || has_invalid_byte {
+        return Err(DirectoryInvariantError::InvalidName { name: name.into() });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DirectoryInvariantError {
+    #[error("directory contains an entry with an empty name")]
+    EmptyName,
+    #[error("directory contains an entry with an invalid name: {name:?}")]
+    InvalidName { name: bstr::BString },
+}
+
 #[derive(
     Debug,
     Clone,
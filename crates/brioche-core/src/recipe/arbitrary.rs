@@ -0,0 +1,99 @@
+//! `proptest` strategies for generating artifact trees, for property tests
+//! in this crate and downstream ones. Enabled with the `proptest` feature.
+
+use std::collections::BTreeMap;
+
+use bstr::BString;
+use proptest::strategy::Strategy as _;
+
+use crate::{
+    blob::{get_save_blob_permit, save_blob_from_reader, SaveBlobOptions},
+    Brioche,
+};
+
+use super::{Artifact, Directory, File, WithMeta};
+
+/// A description of an artifact tree that can be generated by `proptest`.
+/// Use [`create_test_artifact`] to turn it into a real artifact.
+#[derive(Debug, Clone)]
+pub enum TestArtifact {
+    File { content: Vec<u8>, executable: bool },
+    Symlink { target: Vec<u8> },
+    Directory(BTreeMap<BString, TestArtifact>),
+}
+
+pub fn arb_entry_name() -> impl proptest::strategy::Strategy<Value = BString> {
+    "[a-zA-Z0-9_.-]{1,8}"
+        .prop_filter("entry name must not be . or ..", |name| {
+            name != "." && name != ".."
+        })
+        .prop_map(BString::from)
+}
+
+pub fn arb_entry_path() -> impl proptest::strategy::Strategy<Value = BString> {
+    proptest::collection::vec(arb_entry_name(), 1..4)
+        .prop_map(|components| bstr::join("/", components).into())
+}
+
+pub fn arb_test_artifact() -> impl proptest::strategy::Strategy<Value = TestArtifact> {
+    let leaf = proptest::prop_oneof![
+        (
+            proptest::collection::vec(proptest::num::u8::ANY, 0..16),
+            proptest::bool::ANY
+        )
+            .prop_map(|(content, executable)| TestArtifact::File {
+                content,
+                executable
+            }),
+        "[a-z/]{1,8}".prop_map(|target| TestArtifact::Symlink {
+            target: target.into_bytes()
+        }),
+    ];
+    leaf.prop_recursive(3, 32, 4, |inner| {
+        proptest::collection::btree_map(arb_entry_name(), inner, 0..4)
+            .prop_map(TestArtifact::Directory)
+    })
+}
+
+pub fn arb_test_directory() -> impl proptest::strategy::Strategy<Value = TestArtifact> {
+    proptest::collection::btree_map(arb_entry_name(), arb_test_artifact(), 0..6)
+        .prop_map(TestArtifact::Directory)
+}
+
+/// Create the artifact described by a [`TestArtifact`], saving the
+/// contents of its files as blobs.
+#[async_recursion::async_recursion]
+pub async fn create_test_artifact(
+    brioche: &Brioche,
+    artifact: &TestArtifact,
+) -> anyhow::Result<Artifact> {
+    match artifact {
+        TestArtifact::File {
+            content,
+            executable,
+        } => {
+            let permit = get_save_blob_permit(brioche).await?;
+            let content_blob =
+                save_blob_from_reader(brioche, permit, &content[..], SaveBlobOptions::default())
+                    .await?;
+            Ok(Artifact::File(File {
+                content_blob,
+                executable: *executable,
+                resources: Directory::default(),
+            }))
+        }
+        TestArtifact::Symlink { target } => Ok(Artifact::Symlink {
+            target: target.clone().into(),
+        }),
+        TestArtifact::Directory(entries) => {
+            let mut directory = Directory::default();
+            for (name, entry) in entries {
+                let entry = create_test_artifact(brioche, entry).await?;
+                directory
+                    .insert(brioche, name, Some(WithMeta::without_meta(entry)))
+                    .await?;
+            }
+            Ok(Artifact::Directory(directory))
+        }
+    }
+}
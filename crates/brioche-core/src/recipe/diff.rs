@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+
+use crate::Brioche;
+
+use super::{Recipe, RecipeHash};
+
+/// A single difference between two recipes.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeChange {
+    /// Where the change is within the new recipe, such as
+    /// `command.components[0].recipe.entries.bin.executable`. Recipes
+    /// referenced by hash (like directory entries) are followed, so the
+    /// path continues into the referenced recipe.
+    pub path: String,
+    /// The old value, or `None` if it was added.
+    pub old: Option<String>,
+    /// The new value, or `None` if it was removed.
+    pub new: Option<String>,
+}
+
+/// Produce a minimal structural diff between two recipes, such as the
+/// root recipes of two builds, to explain why a recipe's hash changed.
+/// Only the innermost fields that differ are reported: changing a file
+/// deep within a directory reports just that file's changed field, not
+/// every directory hash above it.
+pub async fn diff_recipes(
+    brioche: &Brioche,
+    old: &Recipe,
+    new: &Recipe,
+) -> anyhow::Result<Vec<RecipeChange>> {
+    let old = serde_json::to_value(old)?;
+    let new = serde_json::to_value(new)?;
+
+    let mut changes = vec![];
+    diff_values(brioche, String::new(), &old, &new, &mut changes).await?;
+    Ok(changes)
+}
+
+/// Diff two recipes by hash. Both recipes must be saved locally.
+pub async fn diff_recipe_hashes(
+    brioche: &Brioche,
+    old: RecipeHash,
+    new: RecipeHash,
+) -> anyhow::Result<Vec<RecipeChange>> {
+    let old_recipe = super::get_recipe(brioche, old).await?;
+    let new_recipe = super::get_recipe(brioche, new).await?;
+    diff_recipes(brioche, &old_recipe, &new_recipe).await
+}
+
+#[async_recursion::async_recursion]
+async fn diff_values(
+    brioche: &Brioche,
+    path: String,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changes: &mut Vec<RecipeChange>,
+) -> anyhow::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+
+    match (old, new) {
+        (serde_json::Value::Object(old_fields), serde_json::Value::Object(new_fields))
+            if old_fields.get("type") == new_fields.get("type") =>
+        {
+            let keys = old_fields
+                .keys()
+                .chain(new_fields.keys())
+                .collect::<BTreeSet<_>>();
+            for key in keys {
+                let field_path = field_path(&path, key);
+                match (old_fields.get(key), new_fields.get(key)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_values(brioche, field_path, old_value, new_value, changes).await?;
+                    }
+                    (old_value, new_value) => {
+                        changes.push(RecipeChange {
+                            path: field_path,
+                            old: old_value.map(describe_value),
+                            new: new_value.map(describe_value),
+                        });
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(old_items), serde_json::Value::Array(new_items)) => {
+            let length = old_items.len().max(new_items.len());
+            for index in 0..length {
+                let item_path = format!("{path}[{index}]");
+                match (old_items.get(index), new_items.get(index)) {
+                    (Some(old_item), Some(new_item)) => {
+                        diff_values(brioche, item_path, old_item, new_item, changes).await?;
+                    }
+                    (old_item, new_item) => {
+                        changes.push(RecipeChange {
+                            path: item_path,
+                            old: old_item.map(describe_value),
+                            new: new_item.map(describe_value),
+                        });
+                    }
+                }
+            }
+        }
+        (serde_json::Value::String(old_string), serde_json::Value::String(new_string)) => {
+            // Follow hashes of saved recipes, so the change is reported
+            // within the referenced recipe instead of just as a new hash
+            let referenced = match (
+                try_get_hashed_recipe(brioche, old_string).await,
+                try_get_hashed_recipe(brioche, new_string).await,
+            ) {
+                (Some(old_recipe), Some(new_recipe)) => Some((old_recipe, new_recipe)),
+                _ => None,
+            };
+
+            match referenced {
+                Some((old_recipe, new_recipe)) => {
+                    let old_recipe = serde_json::to_value(&old_recipe)?;
+                    let new_recipe = serde_json::to_value(&new_recipe)?;
+                    diff_values(brioche, path, &old_recipe, &new_recipe, changes).await?;
+                }
+                None => {
+                    changes.push(RecipeChange {
+                        path,
+                        old: Some(describe_value(old)),
+                        new: Some(describe_value(new)),
+                    });
+                }
+            }
+        }
+        _ => {
+            changes.push(RecipeChange {
+                path,
+                old: Some(describe_value(old)),
+                new: Some(describe_value(new)),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up a recipe if the string is the hash of a saved recipe. Blob
+/// hashes look the same as recipe hashes, so anything that isn't found is
+/// treated as a plain value.
+async fn try_get_hashed_recipe(brioche: &Brioche, value: &str) -> Option<Recipe> {
+    let recipe_hash: RecipeHash = value.parse().ok()?;
+    let mut recipes = super::get_recipes(brioche, [recipe_hash]).await.ok()?;
+    recipes.remove(&recipe_hash)
+}
+
+fn field_path(path: &str, key: &str) -> String {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    match (path.is_empty(), is_identifier) {
+        (true, true) => key.to_string(),
+        (false, true) => format!("{path}.{key}"),
+        (_, false) => format!("{path}[{key:?}]"),
+    }
+}
+
+/// Describe a changed value in a single line. Nested recipes are shown by
+/// their type and hash rather than in full.
+fn describe_value(value: &serde_json::Value) -> String {
+    if let Some(recipe_type) = value.get("type").and_then(|value| value.as_str()) {
+        if let Ok(recipe) = serde_json::from_value::<Recipe>(value.clone()) {
+            return format!(
+                "{recipe_type} recipe {}",
+                super::pretty::short_hash(recipe.hash())
+            );
+        }
+    }
+
+    value.to_string()
+}
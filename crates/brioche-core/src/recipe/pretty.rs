@@ -0,0 +1,632 @@
+use bstr::{BStr, ByteSlice as _};
+use human_repr::HumanCount as _;
+use joinery::JoinableIterator as _;
+
+use crate::{blob::BlobHash, Brioche};
+
+use super::{
+    Artifact, CachePolicy, CompleteProcessRecipe, CompleteProcessTemplate,
+    CompleteProcessTemplateComponent, Directory, File, ProcessRecipe, ProcessTemplate,
+    ProcessTemplateComponent, Recipe, WithMeta,
+};
+
+/// The number of hex characters shown for abbreviated hashes.
+const SHORT_HASH_LENGTH: usize = 12;
+
+/// The maximum number of bytes of inline file content to show.
+const MAX_CONTENT_PREVIEW_LENGTH: usize = 64;
+
+/// Render a recipe as canonical, human-readable text. The output is an
+/// indented tree with one node per line, where directory entries and
+/// environment variables are sorted by name and hashes are abbreviated,
+/// so the same recipe always renders the same way.
+pub async fn render_recipe(brioche: &Brioche, recipe: &Recipe) -> anyhow::Result<String> {
+    let mut printer = Printer::new(brioche);
+    printer.recipe(0, None, recipe).await?;
+    Ok(printer.output)
+}
+
+/// Render an artifact as canonical, human-readable text. Directories are
+/// expanded recursively. See [`render_recipe`].
+pub async fn render_artifact(brioche: &Brioche, artifact: &Artifact) -> anyhow::Result<String> {
+    let mut printer = Printer::new(brioche);
+    printer.artifact(0, None, artifact).await?;
+    Ok(printer.output)
+}
+
+/// Abbreviate a hash for display.
+pub fn short_hash(hash: impl std::fmt::Display) -> String {
+    let mut hash = hash.to_string();
+    hash.truncate(SHORT_HASH_LENGTH);
+    hash
+}
+
+/// Describe an artifact in a single line, without looking up the contents
+/// of directories. Used for error messages.
+pub fn describe_artifact(artifact: &Artifact) -> String {
+    match artifact {
+        Artifact::File(File {
+            content_blob,
+            executable,
+            resources: _,
+        }) => {
+            let executable = if *executable { " (executable)" } else { "" };
+            format!("file {}{executable}", short_hash(content_blob))
+        }
+        Artifact::Symlink { target } => format!("symlink -> {:?}", BStr::new(target)),
+        Artifact::Directory(directory) => describe_directory(directory),
+    }
+}
+
+fn describe_directory(directory: &Directory) -> String {
+    let num_entries = directory.entry_hashes().len();
+    let entries = match num_entries {
+        1 => "1 entry".to_string(),
+        n => format!("{n} entries"),
+    };
+    let hash = Artifact::Directory(directory.clone()).hash();
+    format!("directory {} ({entries})", short_hash(hash))
+}
+
+struct Printer<'a> {
+    brioche: &'a Brioche,
+    output: String,
+}
+
+impl<'a> Printer<'a> {
+    fn new(brioche: &'a Brioche) -> Self {
+        Self {
+            brioche,
+            output: String::new(),
+        }
+    }
+
+    fn line(&mut self, depth: usize, label: Option<&str>, text: impl std::fmt::Display) {
+        for _ in 0..depth {
+            self.output.push_str("  ");
+        }
+        if let Some(label) = label {
+            self.output.push_str(label);
+            self.output.push_str(": ");
+        }
+        self.output.push_str(&text.to_string());
+        self.output.push('\n');
+    }
+
+    async fn file_header(&self, content_blob: BlobHash, executable: bool) -> String {
+        let mut details = vec![];
+
+        // Blobs that aren't available locally are shown without a size,
+        // rather than fetching them just to render them
+        if let Ok(Some(blob_path)) =
+            crate::blob::try_local_blob_path(self.brioche, content_blob).await
+        {
+            if let Ok(metadata) = tokio::fs::metadata(&blob_path).await {
+                details.push(metadata.len().human_count_bytes().to_string());
+            }
+        }
+        if executable {
+            details.push("executable".to_string());
+        }
+
+        if details.is_empty() {
+            format!("file {}", short_hash(content_blob))
+        } else {
+            format!("file {} ({})", short_hash(content_blob), details.join(", "))
+        }
+    }
+
+    #[async_recursion::async_recursion]
+    async fn artifact<'l>(
+        &mut self,
+        depth: usize,
+        label: Option<&'l str>,
+        artifact: &Artifact,
+    ) -> anyhow::Result<()> {
+        match artifact {
+            Artifact::File(File {
+                content_blob,
+                executable,
+                resources,
+            }) => {
+                let header = self.file_header(*content_blob, *executable).await;
+                self.line(depth, label, header);
+                if !resources.is_empty() {
+                    self.directory(depth + 1, Some("resources"), resources)
+                        .await?;
+                }
+            }
+            Artifact::Symlink { target } => {
+                self.line(depth, label, format!("symlink -> {:?}", BStr::new(target)));
+            }
+            Artifact::Directory(directory) => {
+                self.directory(depth, label, directory).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn directory(
+        &mut self,
+        depth: usize,
+        label: Option<&str>,
+        directory: &Directory,
+    ) -> anyhow::Result<()> {
+        self.line(depth, label, describe_directory(directory));
+
+        let entries = directory.entries(self.brioche).await?;
+        for (name, entry) in &entries {
+            let name = name.to_str_lossy();
+            self.artifact(depth + 1, Some(&name), &entry.value).await?;
+        }
+
+        Ok(())
+    }
+
+    #[async_recursion::async_recursion]
+    async fn recipe<'l>(
+        &mut self,
+        depth: usize,
+        label: Option<&'l str>,
+        recipe: &Recipe,
+    ) -> anyhow::Result<()> {
+        match recipe {
+            Recipe::File {
+                content_blob,
+                executable,
+                resources,
+            } => {
+                let header = self.file_header(*content_blob, *executable).await;
+                self.line(depth, label, header);
+                if !is_empty_directory(resources) {
+                    self.recipe(depth + 1, Some("resources"), resources).await?;
+                }
+            }
+            Recipe::Directory(directory) => {
+                self.directory(depth, label, directory).await?;
+            }
+            Recipe::Symlink { target } => {
+                self.line(depth, label, format!("symlink -> {:?}", BStr::new(target)));
+            }
+            Recipe::Download(download) => {
+                self.line(
+                    depth,
+                    label,
+                    format!("download {} ({})", download.url, download.hash),
+                );
+            }
+            Recipe::GitCheckout(checkout) => {
+                self.line(
+                    depth,
+                    label,
+                    format!("git_checkout {} @ {}", checkout.url, checkout.rev),
+                );
+            }
+            Recipe::Unarchive(unarchive) => {
+                self.line(
+                    depth,
+                    label,
+                    format!(
+                        "unarchive {} ({})",
+                        serde_name(&unarchive.archive),
+                        serde_name(&unarchive.compression)
+                    ),
+                );
+                self.recipe(depth + 1, Some("file"), &unarchive.file)
+                    .await?;
+            }
+            Recipe::Process(process) => {
+                self.process(depth, label, recipe, process).await?;
+            }
+            Recipe::CompleteProcess(process) => {
+                self.complete_process(depth, label, recipe, process).await?;
+            }
+            Recipe::CreateFile {
+                content,
+                executable,
+                resources,
+            } => {
+                let mut preview = content.clone();
+                let truncated = preview.len() > MAX_CONTENT_PREVIEW_LENGTH;
+                preview.truncate(MAX_CONTENT_PREVIEW_LENGTH);
+                let mut details = vec![content.len().human_count_bytes().to_string()];
+                if *executable {
+                    details.push("executable".to_string());
+                }
+                let ellipsis = if truncated { "..." } else { "" };
+                self.line(
+                    depth,
+                    label,
+                    format!(
+                        "create_file ({}) {:?}{ellipsis}",
+                        details.join(", "),
+                        BStr::new(&preview)
+                    ),
+                );
+                if !is_empty_directory(resources) {
+                    self.recipe(depth + 1, Some("resources"), resources).await?;
+                }
+            }
+            Recipe::CreateDirectory(directory) => {
+                self.line(depth, label, "create_directory");
+                for (name, entry) in &directory.entries {
+                    let name = name.to_str_lossy();
+                    self.recipe(depth + 1, Some(&name), entry).await?;
+                }
+            }
+            Recipe::Cast { recipe, to } => {
+                self.line(depth, label, format!("cast to {}", serde_name(to)));
+                self.recipe(depth + 1, Some("recipe"), recipe).await?;
+            }
+            Recipe::Merge { directories } => {
+                self.line(depth, label, "merge");
+                for (n, directory) in directories.iter().enumerate() {
+                    self.recipe(depth + 1, Some(&n.to_string()), directory)
+                        .await?;
+                }
+            }
+            Recipe::Environment(environment) => {
+                if environment.ignore_collisions {
+                    self.line(depth, label, "environment (ignoring collisions)");
+                } else {
+                    self.line(depth, label, "environment");
+                }
+                for package in &environment.packages {
+                    let name = format!("{} (priority {})", package.name, package.priority);
+                    self.recipe(depth + 1, Some(&name), &package.recipe).await?;
+                }
+            }
+            Recipe::Peel {
+                directory,
+                depth: peel_depth,
+            } => {
+                self.line(depth, label, format!("peel {peel_depth}"));
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+            }
+            Recipe::Get { directory, path } => {
+                self.line(depth, label, format!("get {:?}", BStr::new(path)));
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+            }
+            Recipe::Insert {
+                directory,
+                path,
+                recipe,
+            } => {
+                self.line(depth, label, format!("insert {:?}", BStr::new(path)));
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+                match recipe {
+                    Some(recipe) => {
+                        self.recipe(depth + 1, Some("recipe"), recipe).await?;
+                    }
+                    None => {
+                        self.line(depth + 1, Some("recipe"), "none");
+                    }
+                }
+            }
+            Recipe::Remove { directory, paths } => {
+                let paths = paths
+                    .iter()
+                    .map(|path| format!("{:?}", BStr::new(path)))
+                    .collect::<Vec<_>>();
+                self.line(depth, label, format!("remove [{}]", paths.join(", ")));
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+            }
+            Recipe::Rename {
+                directory,
+                from,
+                to,
+            } => {
+                self.line(
+                    depth,
+                    label,
+                    format!("rename {:?} -> {:?}", BStr::new(from), BStr::new(to)),
+                );
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+            }
+            Recipe::ResolveSymlinks { directory } => {
+                self.line(depth, label, "resolve_symlinks");
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+            }
+            Recipe::SetPermissions { file, executable } => {
+                let executable = match executable {
+                    Some(true) => "executable",
+                    Some(false) => "not executable",
+                    None => "unchanged",
+                };
+                self.line(depth, label, format!("set_permissions ({executable})"));
+                self.recipe(depth + 1, Some("file"), file).await?;
+            }
+            Recipe::Patch {
+                directory,
+                patches,
+                strip,
+            } => {
+                self.line(depth, label, format!("patch (strip {strip})"));
+                self.recipe(depth + 1, Some("directory"), directory).await?;
+                for (n, patch) in patches.iter().enumerate() {
+                    self.recipe(depth + 1, Some(&format!("patch {n}")), patch)
+                        .await?;
+                }
+            }
+            Recipe::Proxy(proxy) => {
+                self.line(depth, label, format!("proxy {}", short_hash(proxy.recipe)));
+            }
+            Recipe::Sync { recipe } => {
+                self.line(depth, label, "sync");
+                self.recipe(depth + 1, Some("recipe"), recipe).await?;
+            }
+            Recipe::Annotate {
+                recipe,
+                annotations,
+            } => {
+                self.line(depth, label, "annotate");
+                let fields = [
+                    ("name", &annotations.name),
+                    ("version", &annotations.version),
+                    ("license", &annotations.license),
+                    ("main executable", &annotations.main_executable),
+                ];
+                for (name, value) in fields {
+                    if let Some(value) = value {
+                        self.line(depth + 1, Some(name), format!("{value:?}"));
+                    }
+                }
+                for (name, value) in &annotations.extra {
+                    self.line(depth + 1, Some(name), format!("{value:?}"));
+                }
+                self.recipe(depth + 1, Some("recipe"), recipe).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process(
+        &mut self,
+        depth: usize,
+        label: Option<&str>,
+        recipe: &Recipe,
+        process: &ProcessRecipe,
+    ) -> anyhow::Result<()> {
+        self.line(
+            depth,
+            label,
+            format!(
+                "process {} ({})",
+                short_hash(recipe.hash()),
+                process.platform
+            ),
+        );
+
+        let mut inputs = vec![];
+        let command = process_template(&process.command, &mut inputs);
+        self.line(depth + 1, Some("command"), command);
+        for (n, arg) in process.args.iter().enumerate() {
+            let arg = process_template(arg, &mut inputs);
+            self.line(depth + 1, Some(&format!("arg {n}")), arg);
+        }
+        for (name, value) in &process.env {
+            let value = process_template(value, &mut inputs);
+            self.line(
+                depth + 1,
+                Some(&format!("env {}", name.to_str_lossy())),
+                value,
+            );
+        }
+
+        let flags = [
+            ("unsafe", process.is_unsafe),
+            ("networking", process.networking),
+            ("capture stdout", process.capture_stdout),
+            ("tty", process.tty),
+        ];
+        for (name, enabled) in flags {
+            if enabled {
+                self.line(depth + 1, Some(name), "true");
+            }
+        }
+        if let Some(output_hash) = process.output_hash {
+            self.line(depth + 1, Some("output hash"), short_hash(output_hash));
+        }
+        if process.cache_policy != CachePolicy::Default {
+            self.line(depth + 1, Some("cache policy"), process.cache_policy);
+        }
+        if !process.secrets.is_empty() {
+            self.line(
+                depth + 1,
+                Some("secrets"),
+                process.secrets.iter().join_with(", "),
+            );
+        }
+        if let Some(incremental_key) = &process.incremental_key {
+            self.line(
+                depth + 1,
+                Some("incremental key"),
+                format!("{incremental_key:?}"),
+            );
+        }
+
+        for (n, input) in inputs.iter().enumerate() {
+            self.recipe(depth + 1, Some(&format!("input {n}")), input)
+                .await?;
+        }
+        for (n, dependency) in process.dependencies.iter().enumerate() {
+            self.recipe(depth + 1, Some(&format!("dependency {n}")), dependency)
+                .await?;
+        }
+        if !is_empty_directory(&process.work_dir) {
+            self.recipe(depth + 1, Some("work dir"), &process.work_dir)
+                .await?;
+        }
+        if let Some(output_scaffold) = &process.output_scaffold {
+            self.recipe(depth + 1, Some("output scaffold"), output_scaffold)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn complete_process(
+        &mut self,
+        depth: usize,
+        label: Option<&str>,
+        recipe: &Recipe,
+        process: &CompleteProcessRecipe,
+    ) -> anyhow::Result<()> {
+        self.line(
+            depth,
+            label,
+            format!(
+                "complete_process {} ({})",
+                short_hash(recipe.hash()),
+                process.platform
+            ),
+        );
+
+        let mut inputs = vec![];
+        let command = complete_process_template(&process.command, &mut inputs);
+        self.line(depth + 1, Some("command"), command);
+        for (n, arg) in process.args.iter().enumerate() {
+            let arg = complete_process_template(arg, &mut inputs);
+            self.line(depth + 1, Some(&format!("arg {n}")), arg);
+        }
+        for (name, value) in &process.env {
+            let value = complete_process_template(value, &mut inputs);
+            self.line(
+                depth + 1,
+                Some(&format!("env {}", name.to_str_lossy())),
+                value,
+            );
+        }
+
+        let flags = [
+            ("unsafe", process.is_unsafe),
+            ("networking", process.networking),
+            ("capture stdout", process.capture_stdout),
+            ("tty", process.tty),
+        ];
+        for (name, enabled) in flags {
+            if enabled {
+                self.line(depth + 1, Some(name), "true");
+            }
+        }
+        if let Some(output_hash) = process.output_hash {
+            self.line(depth + 1, Some("output hash"), short_hash(output_hash));
+        }
+        if process.cache_policy != CachePolicy::Default {
+            self.line(depth + 1, Some("cache policy"), process.cache_policy);
+        }
+        if !process.secrets.is_empty() {
+            self.line(
+                depth + 1,
+                Some("secrets"),
+                process.secrets.iter().join_with(", "),
+            );
+        }
+        if let Some(emulator) = &process.emulator {
+            self.line(depth + 1, Some("emulator"), &emulator.name);
+        }
+        if let Some(incremental_key) = &process.incremental_key {
+            self.line(
+                depth + 1,
+                Some("incremental key"),
+                format!("{incremental_key:?}"),
+            );
+        }
+
+        for (n, input) in inputs.iter().enumerate() {
+            self.artifact(depth + 1, Some(&format!("input {n}")), input)
+                .await?;
+        }
+        if !process.work_dir.is_empty() {
+            self.directory(depth + 1, Some("work dir"), &process.work_dir)
+                .await?;
+        }
+        if let Some(output_scaffold) = &process.output_scaffold {
+            self.artifact(depth + 1, Some("output scaffold"), output_scaffold)
+                .await?;
+        }
+        if let Some(previous_output) = &process.previous_output {
+            self.artifact(depth + 1, Some("previous output"), previous_output)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Render a template inline, with placeholders like `<input 0>` for
+/// inputs. Inputs are added to `inputs`, so they can be rendered after
+/// the template.
+fn process_template<'a>(
+    template: &'a ProcessTemplate,
+    inputs: &mut Vec<&'a WithMeta<Recipe>>,
+) -> String {
+    let mut rendered = String::new();
+    for component in &template.components {
+        match component {
+            ProcessTemplateComponent::Literal { value } => {
+                rendered.push_str(&value.to_str_lossy());
+            }
+            ProcessTemplateComponent::Input { recipe } => {
+                rendered.push_str(&format!("<input {}>", inputs.len()));
+                inputs.push(recipe);
+            }
+            ProcessTemplateComponent::OutputPath => rendered.push_str("<output path>"),
+            ProcessTemplateComponent::ResourceDir => rendered.push_str("<resource dir>"),
+            ProcessTemplateComponent::InputResourceDirs => {
+                rendered.push_str("<input resource dirs>");
+            }
+            ProcessTemplateComponent::HomeDir => rendered.push_str("<home dir>"),
+            ProcessTemplateComponent::WorkDir => rendered.push_str("<work dir>"),
+            ProcessTemplateComponent::TempDir => rendered.push_str("<temp dir>"),
+        }
+    }
+
+    format!("{rendered:?}")
+}
+
+fn complete_process_template<'a>(
+    template: &'a CompleteProcessTemplate,
+    inputs: &mut Vec<&'a WithMeta<Artifact>>,
+) -> String {
+    let mut rendered = String::new();
+    for component in &template.components {
+        match component {
+            CompleteProcessTemplateComponent::Literal { value } => {
+                rendered.push_str(&value.to_str_lossy());
+            }
+            CompleteProcessTemplateComponent::Input { artifact } => {
+                rendered.push_str(&format!("<input {}>", inputs.len()));
+                inputs.push(artifact);
+            }
+            CompleteProcessTemplateComponent::OutputPath => rendered.push_str("<output path>"),
+            CompleteProcessTemplateComponent::ResourceDir => rendered.push_str("<resource dir>"),
+            CompleteProcessTemplateComponent::InputResourceDirs => {
+                rendered.push_str("<input resource dirs>");
+            }
+            CompleteProcessTemplateComponent::HomeDir => rendered.push_str("<home dir>"),
+            CompleteProcessTemplateComponent::WorkDir => rendered.push_str("<work dir>"),
+            CompleteProcessTemplateComponent::TempDir => rendered.push_str("<temp dir>"),
+        }
+    }
+
+    format!("{rendered:?}")
+}
+
+fn is_empty_directory(recipe: &Recipe) -> bool {
+    match recipe {
+        Recipe::Directory(directory) => directory.is_empty(),
+        Recipe::CreateDirectory(directory) => directory.is_empty(),
+        _ => false,
+    }
+}
+
+/// Get the name of a unit enum variant, as it's serialized.
+fn serde_name(value: &impl serde::Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => "?".to_string(),
+    }
+}
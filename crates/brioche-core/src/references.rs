@@ -79,6 +79,8 @@ pub async fn project_references(
             dependencies,
             modules,
             statics,
+            env: _,
+            api_version: _,
         } = &*project;
 
         references.projects.insert(project_hash, project.clone());
@@ -116,6 +118,7 @@ pub fn referenced_blobs(recipe: &Recipe) -> Vec<BlobHash> {
         Recipe::Directory(_)
         | Recipe::Symlink { .. }
         | Recipe::Download(_)
+        | Recipe::GitCheckout(_)
         | Recipe::Unarchive(_)
         | Recipe::Process(_)
         | Recipe::CompleteProcess(_)
@@ -123,12 +126,18 @@ pub fn referenced_blobs(recipe: &Recipe) -> Vec<BlobHash> {
         | Recipe::CreateDirectory(_)
         | Recipe::Cast { .. }
         | Recipe::Merge { .. }
+        | Recipe::Environment(_)
         | Recipe::Peel { .. }
         | Recipe::Get { .. }
         | Recipe::Insert { .. }
+        | Recipe::Remove { .. }
+        | Recipe::Rename { .. }
+        | Recipe::ResolveSymlinks { .. }
         | Recipe::SetPermissions { .. }
+        | Recipe::Patch { .. }
         | Recipe::Proxy(_)
-        | Recipe::Sync { .. } => vec![],
+        | Recipe::Sync { .. }
+        | Recipe::Annotate { .. } => vec![],
     }
 }
 
@@ -146,6 +155,7 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
             .collect(),
         Recipe::Symlink { .. } => vec![],
         Recipe::Download(_) => vec![],
+        Recipe::GitCheckout(_) => vec![],
         Recipe::Unarchive(unarchive) => referenced_recipes(&unarchive.file),
         Recipe::Process(process) => {
             let ProcessRecipe {
@@ -158,6 +168,12 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
                 platform: _,
                 is_unsafe: _,
                 networking: _,
+                output_hash: _,
+                capture_stdout: _,
+                tty: _,
+                cache_policy: _,
+                secrets: _,
+                incremental_key: _,
             } = process;
 
             let templates = [command].into_iter().chain(args).chain(env.values());
@@ -197,11 +213,20 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
                 platform: _,
                 is_unsafe: _,
                 networking: _,
+                output_hash: _,
+                capture_stdout: _,
+                tty: _,
+                cache_policy: _,
+                secrets: _,
+                emulator: _,
+                incremental_key: _,
+                previous_output,
             } = process;
 
             let work_dir = Recipe::from(work_dir.clone());
-            let output_scaffold = output_scaffold
-                .as_ref()
+            let output_scaffolds = output_scaffold
+                .iter()
+                .chain(previous_output)
                 .map(|artifact| Recipe::from((**artifact).clone()));
 
             let templates = [command].into_iter().chain(args).chain(env.values());
@@ -222,7 +247,7 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
                     | CompleteProcessTemplateComponent::TempDir => vec![],
                 })
                 .chain(referenced_recipes(&work_dir))
-                .chain(output_scaffold.iter().flat_map(referenced_recipes))
+                .chain(output_scaffolds.flat_map(|recipe| referenced_recipes(&recipe)))
                 .collect()
         }
         Recipe::CreateFile {
@@ -240,6 +265,11 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
             .iter()
             .flat_map(|dir| referenced_recipes(dir))
             .collect(),
+        Recipe::Environment(environment) => environment
+            .packages
+            .iter()
+            .flat_map(|package| referenced_recipes(&package.recipe))
+            .collect(),
         Recipe::Peel {
             directory,
             depth: _,
@@ -253,12 +283,34 @@ pub fn referenced_recipes(recipe: &Recipe) -> Vec<RecipeHash> {
             .into_iter()
             .chain(recipe.iter().flat_map(|recipe| referenced_recipes(recipe)))
             .collect(),
+        Recipe::Remove {
+            directory,
+            paths: _,
+        } => referenced_recipes(directory),
+        Recipe::Rename {
+            directory,
+            from: _,
+            to: _,
+        } => referenced_recipes(directory),
+        Recipe::ResolveSymlinks { directory } => referenced_recipes(directory),
         Recipe::SetPermissions {
             file,
             executable: _,
         } => referenced_recipes(file),
+        Recipe::Patch {
+            directory,
+            patches,
+            strip: _,
+        } => referenced_recipes(directory)
+            .into_iter()
+            .chain(patches.iter().flat_map(|patch| referenced_recipes(patch)))
+            .collect(),
         Recipe::Proxy(proxy) => vec![proxy.recipe],
         Recipe::Sync { recipe } => referenced_recipes(recipe),
+        Recipe::Annotate {
+            recipe,
+            annotations: _,
+        } => referenced_recipes(recipe),
     }
 }
 
@@ -6,7 +6,7 @@ use std::{
 
 use anyhow::Context as _;
 use futures::{FutureExt as _, StreamExt as _, TryStreamExt as _};
-use tokio::io::AsyncReadExt as _;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
 
 use crate::{
     blob::BlobHash,
@@ -19,18 +19,83 @@ const GET_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
+pub mod attestation;
+pub mod name;
+pub mod throttle;
+pub mod transfer;
+
+/// The default maximum number of registry requests in flight at once.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 25;
+
+/// How many times to retry a blob download that fails partway through.
+const BLOB_DOWNLOAD_MAX_RETRIES: u32 = 5;
+const BLOB_DOWNLOAD_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+const BLOB_DOWNLOAD_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
 #[derive(Clone)]
 pub enum RegistryClient {
     Enabled {
         client: reqwest_middleware::ClientWithMiddleware,
         url: url::Url,
         auth: RegistryAuthentication,
+        scope_tokens: Arc<HashMap<String, String>>,
+        transfers: Arc<transfer::TransferScheduler>,
+        throttle: Arc<throttle::RequestThrottle>,
     },
     Disabled,
 }
 
+#[derive(Debug, Clone)]
+pub struct RegistryClientOptions {
+    /// The maximum number of requests in flight at once. Connections are
+    /// pooled and reused (and multiplexed over HTTP/2 when the registry
+    /// supports it), so this also bounds the number of open connections.
+    pub max_connections: usize,
+
+    /// Limit the average download speed for blobs, in bytes per second.
+    pub download_bytes_per_second: Option<u64>,
+
+    /// Limit the average upload speed for blobs, in bytes per second.
+    /// Uploads also pause while blobs are being downloaded.
+    pub upload_bytes_per_second: Option<u64>,
+
+    /// Tokens used to authenticate requests for scoped projects, keyed by
+    /// scope name (without the leading `@`). Requests for projects in
+    /// other scopes use the default authentication.
+    pub scope_tokens: HashMap<String, String>,
+
+    /// Space out requests so no more than this many are sent per second.
+    /// Regardless of this option, requests are paused when the registry
+    /// responds with 429 Too Many Requests.
+    pub max_requests_per_second: Option<f64>,
+
+    /// Proxies and extra CA certificates for registry requests.
+    pub http: crate::network::HttpClientOptions,
+}
+
+impl Default for RegistryClientOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            download_bytes_per_second: None,
+            upload_bytes_per_second: None,
+            scope_tokens: HashMap::new(),
+            max_requests_per_second: None,
+            http: crate::network::HttpClientOptions::default(),
+        }
+    }
+}
+
 impl RegistryClient {
     pub fn new(url: url::Url, auth: RegistryAuthentication) -> Self {
+        Self::new_with_options(url, auth, RegistryClientOptions::default())
+    }
+
+    pub fn new_with_options(
+        url: url::Url,
+        auth: RegistryAuthentication,
+        options: RegistryClientOptions,
+    ) -> Self {
         let retry_policy = reqwest_retry::policies::ExponentialBackoff::builder()
             .retry_bounds(
                 std::time::Duration::from_millis(500),
@@ -40,17 +105,42 @@ impl RegistryClient {
         let retry_middleware =
             reqwest_retry::RetryTransientMiddleware::new_with_policy(retry_policy);
 
+        // A single client is shared for all registry requests (clones share
+        // the same connection pool)
         let client = reqwest::Client::builder()
             .user_agent(crate::USER_AGENT)
             .connect_timeout(CONNECT_TIMEOUT)
             .read_timeout(READ_TIMEOUT)
+            .pool_max_idle_per_host(options.max_connections);
+        let client = options
+            .http
+            .configure(client)
             .build()
             .expect("failed to build reqwest client");
+        let throttle = Arc::new(throttle::RequestThrottle::new(
+            options.max_requests_per_second,
+        ));
         let client = reqwest_middleware::ClientBuilder::new(client)
             .with(retry_middleware)
+            .with(RequestMiddleware {
+                semaphore: Arc::new(tokio::sync::Semaphore::new(options.max_connections.max(1))),
+                throttle: throttle.clone(),
+            })
             .build();
 
-        Self::Enabled { client, url, auth }
+        let transfers = Arc::new(transfer::TransferScheduler::new(
+            options.download_bytes_per_second,
+            options.upload_bytes_per_second,
+        ));
+
+        Self::Enabled {
+            client,
+            url,
+            auth,
+            scope_tokens: Arc::new(options.scope_tokens),
+            transfers,
+            throttle,
+        }
     }
 
     pub fn disabled() -> Self {
@@ -62,23 +152,78 @@ impl RegistryClient {
         method: reqwest::Method,
         path: &str,
     ) -> anyhow::Result<reqwest_middleware::RequestBuilder> {
-        let Self::Enabled { client, url, auth } = self else {
+        self.request_with_scope(method, path, None)
+    }
+
+    /// Build a request for a project in the given scope, authenticated
+    /// with the scope's token if one is configured.
+    fn request_with_scope(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        scope: Option<&str>,
+    ) -> anyhow::Result<reqwest_middleware::RequestBuilder> {
+        let Self::Enabled {
+            client,
+            url,
+            auth,
+            scope_tokens,
+            ..
+        } = self
+        else {
             return Err(anyhow::anyhow!("registry client is disabled"));
         };
         let endpoint_url = url.join(path).context("failed to construct registry URL")?;
         let request = client
             .request(method, endpoint_url)
             .query(&[("brioche", env!("CARGO_PKG_VERSION"))]);
-        let request = match auth {
-            RegistryAuthentication::Anonymous => request,
-            RegistryAuthentication::Admin { password } => {
+        let scope_token = scope.and_then(|scope| scope_tokens.get(scope));
+        let request = match (scope_token, auth) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, RegistryAuthentication::Anonymous) => request,
+            (None, RegistryAuthentication::Admin { password }) => {
                 request.basic_auth("admin", Some(password))
             }
         };
         Ok(request)
     }
 
+    fn has_scope_token(&self, scope: &str) -> bool {
+        match self {
+            Self::Enabled { scope_tokens, .. } => scope_tokens.contains_key(scope),
+            Self::Disabled => false,
+        }
+    }
+
+    /// Show in the reporter when the registry is throttling requests.
+    pub fn set_reporter(&self, reporter: crate::reporter::Reporter) {
+        if let Self::Enabled { throttle, .. } = self {
+            throttle.set_reporter(reporter);
+        }
+    }
+
+    fn transfers(&self) -> anyhow::Result<&Arc<transfer::TransferScheduler>> {
+        let Self::Enabled { transfers, .. } = self else {
+            return Err(anyhow::anyhow!("registry client is disabled"));
+        };
+        Ok(transfers)
+    }
+
+    /// The URL a blob is downloaded from.
+    pub fn blob_url(&self, blob_hash: BlobHash) -> anyhow::Result<url::Url> {
+        let Self::Enabled { url, .. } = self else {
+            return Err(anyhow::anyhow!("registry client is disabled"));
+        };
+        let blob_url = url
+            .join(&format!("v0/blobs/{blob_hash}.zst"))
+            .context("failed to construct registry URL")?;
+        Ok(blob_url)
+    }
+
     pub async fn get_blob(&self, blob_hash: BlobHash) -> anyhow::Result<Vec<u8>> {
+        let transfers = self.transfers()?.clone();
+        let _download = transfers.start_download().await;
+
         // No timeout for blobs, since they can take a while to download
         let response = self
             .request(reqwest::Method::GET, &format!("v0/blobs/{blob_hash}.zst"))?
@@ -86,7 +231,19 @@ impl RegistryClient {
             .await?
             .error_for_status()?;
 
-        let response_stream = response.bytes_stream().map_err(std::io::Error::other);
+        let response_stream = response
+            .bytes_stream()
+            .then(move |chunk| {
+                let transfers = transfers.clone();
+                async move {
+                    if let Ok(chunk) = &chunk {
+                        transfers.consume_download(chunk.len() as u64).await;
+                    }
+                    chunk
+                }
+            })
+            .map_err(std::io::Error::other)
+            .boxed();
         let response_reader = tokio_util::io::StreamReader::new(response_stream);
         let mut response_reader =
             async_compression::tokio::bufread::ZstdDecoder::new(response_reader);
@@ -101,9 +258,183 @@ impl RegistryClient {
         Ok(response_body)
     }
 
+    /// Download a blob to `output_path`, returning its size. The compressed
+    /// blob is streamed to a partial file next to `output_path`, and if the
+    /// download fails partway through, it's retried with exponential backoff
+    /// and resumed from where it left off using a range request. The blob
+    /// is validated against its hash before returning, so `output_path`
+    /// can be renamed into place afterwards.
+    ///
+    /// `on_progress` is called with the number of bytes downloaded so far
+    /// and the total size (if known). Both counts are of the compressed
+    /// blob.
+    pub async fn download_blob(
+        &self,
+        blob_hash: BlobHash,
+        output_path: &std::path::Path,
+        mut on_progress: impl FnMut(u64, Option<u64>) + Send,
+    ) -> anyhow::Result<u64> {
+        let compressed_path = output_path.with_extension("zst.partial");
+        let result = self
+            .download_blob_inner(blob_hash, output_path, &compressed_path, &mut on_progress)
+            .await;
+        crate::fs_utils::try_remove(&compressed_path).await?;
+        result
+    }
+
+    async fn download_blob_inner(
+        &self,
+        blob_hash: BlobHash,
+        output_path: &std::path::Path,
+        compressed_path: &std::path::Path,
+        on_progress: &mut (impl FnMut(u64, Option<u64>) + Send),
+    ) -> anyhow::Result<u64> {
+        let transfers = self.transfers()?.clone();
+        let _download = transfers.start_download().await;
+
+        let mut compressed_file = tokio::fs::File::create(compressed_path)
+            .await
+            .context("failed to create partial blob file")?;
+        let mut progress = BlobDownloadProgress::default();
+        let mut backoff = BLOB_DOWNLOAD_INITIAL_BACKOFF;
+        let mut retries = 0;
+        loop {
+            let result = self
+                .download_blob_range(
+                    blob_hash,
+                    &mut compressed_file,
+                    &mut progress,
+                    &transfers,
+                    on_progress,
+                )
+                .await;
+            match result {
+                Ok(()) => break,
+                Err(error)
+                    if retries < BLOB_DOWNLOAD_MAX_RETRIES
+                        && is_transient_download_error(&error) =>
+                {
+                    retries += 1;
+                    tracing::debug!(%blob_hash, downloaded_bytes = progress.downloaded_bytes, retries, "blob download failed, retrying: {error:#}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(BLOB_DOWNLOAD_MAX_BACKOFF);
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| {
+                        format!("failed to download blob {blob_hash} after {retries} retries")
+                    });
+                }
+            }
+        }
+        compressed_file.flush().await?;
+        drop(compressed_file);
+
+        // Decompress the blob while hashing it, so it's never read twice
+        let compressed_file = tokio::fs::File::open(compressed_path).await?;
+        let mut decoder = async_compression::tokio::bufread::ZstdDecoder::new(
+            tokio::io::BufReader::new(compressed_file),
+        );
+        let mut output_file = tokio::fs::File::create(output_path)
+            .await
+            .context("failed to create blob file")?;
+        let mut hasher = blake3::Hasher::new();
+        let mut size = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let length = decoder
+                .read(&mut buffer)
+                .await
+                .context("failed to decompress blob")?;
+            if length == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..length]);
+            output_file.write_all(&buffer[..length]).await?;
+            size += length as u64;
+        }
+        output_file.flush().await?;
+
+        let actual_hash = BlobHash::from_blake3(hasher.finalize());
+        anyhow::ensure!(
+            actual_hash == blob_hash,
+            "blob hash did not match: expected {blob_hash}, got {actual_hash}"
+        );
+
+        Ok(size)
+    }
+
+    /// Download the rest of a compressed blob, appending it to
+    /// `compressed_file`. If some of the blob was already downloaded, only
+    /// the remaining range is requested.
+    async fn download_blob_range(
+        &self,
+        blob_hash: BlobHash,
+        compressed_file: &mut tokio::fs::File,
+        progress: &mut BlobDownloadProgress,
+        transfers: &Arc<transfer::TransferScheduler>,
+        on_progress: &mut (impl FnMut(u64, Option<u64>) + Send),
+    ) -> anyhow::Result<()> {
+        // No timeout for blobs, since they can take a while to download
+        let mut request =
+            self.request(reqwest::Method::GET, &format!("v0/blobs/{blob_hash}.zst"))?;
+        if progress.downloaded_bytes > 0 {
+            request = request.header(
+                reqwest::header::RANGE,
+                format!("bytes={}-", progress.downloaded_bytes),
+            );
+        }
+        let response = request.send().await?.error_for_status()?;
+
+        // Start over if the server sent the whole blob instead of a range
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            progress.downloaded_bytes = 0;
+            progress.total_bytes = None;
+        }
+        if progress.total_bytes.is_none() {
+            // Read the header directly, since the response body is wrapped
+            // to hold a request permit and doesn't know its own length
+            progress.total_bytes = response
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse::<u64>().ok())
+                .map(|length| progress.downloaded_bytes + length);
+        }
+
+        // Drop anything written after the last complete chunk
+        compressed_file.set_len(progress.downloaded_bytes).await?;
+        compressed_file
+            .seek(std::io::SeekFrom::Start(progress.downloaded_bytes))
+            .await?;
+
+        let mut response_stream = response.bytes_stream();
+        while let Some(chunk) = response_stream.try_next().await? {
+            transfers.consume_download(chunk.len() as u64).await;
+            compressed_file.write_all(&chunk).await?;
+            progress.downloaded_bytes += chunk.len() as u64;
+            on_progress(progress.downloaded_bytes, progress.total_bytes);
+        }
+
+        if let Some(total_bytes) = progress.total_bytes {
+            if progress.downloaded_bytes != total_bytes {
+                return Err(BlobDownloadInterruptedError {
+                    downloaded_bytes: progress.downloaded_bytes,
+                    total_bytes,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn send_blob(&self, blob_hash: BlobHash, content: Vec<u8>) -> anyhow::Result<()> {
         let path = format!("v0/blobs/{blob_hash}");
 
+        // Uploads are sent as a single body so they can be retried, so the
+        // upload limit is applied per blob rather than per chunk
+        self.transfers()?.consume_upload(content.len() as u64).await;
+
         self.request(reqwest::Method::PUT, &path)?
             .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
             .body(content)
@@ -119,12 +450,34 @@ impl RegistryClient {
         project_name: &str,
         tag: &str,
     ) -> anyhow::Result<GetProjectTagResponse> {
+        let parsed_name: name::ProjectName = project_name.parse()?;
         let project_name_component = urlencoding::Encoded::new(project_name);
         let tag_component = urlencoding::Encoded::new(tag);
         let response = self
-            .request(
+            .request_with_scope(
                 reqwest::Method::GET,
                 &format!("v0/project-tags/{project_name_component}/{tag_component}"),
+                parsed_name.scope.as_deref(),
+            )?
+            .timeout(GET_TIMEOUT)
+            .send()
+            .await?;
+        let response_body = response.error_for_status()?.json().await?;
+        Ok(response_body)
+    }
+
+    /// List the published versions of a project, newest first.
+    pub async fn get_project_versions(
+        &self,
+        project_name: &str,
+    ) -> anyhow::Result<GetProjectVersionsResponse> {
+        let parsed_name: name::ProjectName = project_name.parse()?;
+        let project_name_component = urlencoding::Encoded::new(project_name);
+        let response = self
+            .request_with_scope(
+                reqwest::Method::GET,
+                &format!("v0/project-versions/{project_name_component}"),
+                parsed_name.scope.as_deref(),
             )?
             .timeout(GET_TIMEOUT)
             .send()
@@ -137,11 +490,85 @@ impl RegistryClient {
         &self,
         project_tags: &CreateProjectTagsRequest,
     ) -> anyhow::Result<CreateProjectTagsResponse> {
+        // Tags are authenticated by scope, so every tag in a request
+        // needs to be in the same scope
+        let mut project_names = project_tags
+            .tags
+            .iter()
+            .map(|tag| tag.project_name.parse::<name::ProjectName>())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        project_names.dedup();
+        let scope = match &project_names[..] {
+            [] => None,
+            [first, rest @ ..] => {
+                anyhow::ensure!(
+                    rest.iter().all(|name| name.scope == first.scope),
+                    "cannot create tags for projects in different scopes at once"
+                );
+                first.scope.as_deref()
+            }
+        };
+
         let response = self
-            .request(reqwest::Method::POST, "v0/project-tags")?
+            .request_with_scope(reqwest::Method::POST, "v0/project-tags", scope)?
             .json(project_tags)
             .send()
             .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            let project_name = project_names
+                .first()
+                .map(|name| name.to_string())
+                .unwrap_or_default();
+            return Err(RegistryPermissionError {
+                project_name,
+                scope: scope.map(|scope| scope.to_string()),
+                has_scope_token: scope.is_some_and(|scope| self.has_scope_token(scope)),
+            }
+            .into());
+        }
+
+        let response_body = response.error_for_status()?.json().await?;
+        Ok(response_body)
+    }
+
+    /// Ask the registry to delete old tags of a project based on a
+    /// retention policy. The registry then removes any recipes, bakes, and
+    /// blobs that are no longer reachable from a remaining tag.
+    pub async fn prune_project(
+        &self,
+        project_name: &str,
+        request: &PruneProjectRequest,
+    ) -> anyhow::Result<PruneProjectResponse> {
+        anyhow::ensure!(
+            request.older_than_secs.is_some() || request.keep_latest.is_some(),
+            "a maximum age or a number of tags to keep is required to prune {project_name}"
+        );
+
+        let parsed_name: name::ProjectName = project_name.parse()?;
+        let project_name_component = urlencoding::Encoded::new(project_name);
+        let response = self
+            .request_with_scope(
+                reqwest::Method::POST,
+                &format!("v0/project-versions/{project_name_component}/prune"),
+                parsed_name.scope.as_deref(),
+            )?
+            .json(request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(crate::error_codes::CodedError::new(
+                crate::error_codes::ErrorCode::REGISTRY_PERMISSION_DENIED,
+                format!(
+                    "not allowed to prune {project_name}: the registry rejected the credentials"
+                ),
+            )
+            .into());
+        }
+
         let response_body = response.error_for_status()?.json().await?;
         Ok(response_body)
     }
@@ -295,6 +722,7 @@ impl RegistryClient {
         &self,
         input_hash: RecipeHash,
         output_hash: RecipeHash,
+        attestation: Option<attestation::BakeAttestation>,
     ) -> anyhow::Result<CreateBakeResponse> {
         let response = self
             .request(
@@ -302,7 +730,10 @@ impl RegistryClient {
                 &format!("v0/recipes/{input_hash}/bake"),
             )?
             .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .json(&CreateBakeRequest { output_hash })
+            .json(&CreateBakeRequest {
+                output_hash,
+                attestation,
+            })
             .send()
             .await?;
 
@@ -337,8 +768,8 @@ pub async fn fetch_bake_references(
             move |&blob_hash| {
                 let brioche = brioche.clone();
                 async move {
-                    let blob_path = super::blob::local_blob_path(&brioche, blob_hash);
-                    !matches!(tokio::fs::try_exists(&blob_path).await, Ok(true))
+                    let local_path = super::blob::try_local_blob_path(&brioche, blob_hash).await;
+                    !matches!(local_path, Ok(Some(_)))
                 }
             }
         })
@@ -370,8 +801,7 @@ pub async fn fetch_bake_references(
         .try_for_each_concurrent(25, |blob| {
             let brioche = brioche.clone();
             async move {
-                let permit = crate::blob::get_save_blob_permit().await?;
-                super::blob::blob_path(&brioche, permit, blob).await?;
+                super::blob::blob_path(&brioche, blob).await?;
 
                 brioche.reporter.update_job(
                     job_id,
@@ -516,87 +946,166 @@ pub async fn fetch_recipes_deep(
     Ok(())
 }
 
-#[tracing::instrument(skip(brioche, blobs))]
-pub async fn fetch_blobs(brioche: Brioche, blobs: &HashSet<BlobHash>) -> anyhow::Result<()> {
-    let unknown_blobs = futures::stream::iter(blobs.clone())
-        .map(|blob_hash| {
-            let brioche = brioche.clone();
-            // Create a future to map to the hash plus a result indicating
-            // whether it exists on disk already. We do this instead of
-            // filtering so we can do the checks in parallel.
-            // See this discussion:
-            // https://github.com/alexpusch/rust-magic-patterns/blob/master/rust-stream-visualized/Readme.md
-            async move {
-                let blob_path = super::blob::local_blob_path(&brioche, blob_hash);
-
-                let try_exists = tokio::fs::try_exists(&blob_path).await;
+/// Fetch only the part of an artifact at `subpath`: the directories along
+/// the path, then the recipes and blobs under the subpath itself. Unlike
+/// fetching a whole bake, sibling entries are never fetched. Returns
+/// `None` if nothing exists at the subpath.
+#[tracing::instrument(skip(brioche, artifact), fields(artifact_hash = %artifact.hash()))]
+pub async fn fetch_artifact_subpath(
+    brioche: &Brioche,
+    artifact: &Artifact,
+    subpath: &[u8],
+) -> anyhow::Result<Option<Artifact>> {
+    let subpath = crate::path::BriochePath::new(subpath)?;
+
+    let mut current = artifact.clone();
+    for component in subpath.components() {
+        let Artifact::Directory(directory) = &current else {
+            return Ok(None);
+        };
+        let Some(entry) = directory.entry_hashes().get(component) else {
+            return Ok(None);
+        };
+        let entry_hash = entry.value;
 
-                (blob_hash, try_exists)
-            }
-        })
-        .buffer_unordered(25)
-        .filter_map(|(blob_hash, try_exists)| async move {
-            // Filter to blobs that don't exist
-            if matches!(try_exists, Ok(true)) {
-                None
-            } else {
-                Some(blob_hash)
-            }
-        })
-        .collect::<Vec<_>>()
-        .map(anyhow::Ok)
-        .await?;
+        let known_recipes = crate::references::local_recipes(brioche, [entry_hash]).await?;
+        if !known_recipes.contains(&entry_hash) {
+            let recipe = brioche.registry_client.get_recipe(entry_hash).await?;
+            crate::recipe::save_recipes(brioche, [recipe]).await?;
+        }
 
-    // Short-circuit if we have nothing to fetch
-    if unknown_blobs.is_empty() {
-        return Ok(());
+        let entry_recipe = crate::recipe::get_recipe(brioche, entry_hash).await?;
+        current = entry_recipe
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("directory entry {entry_hash} is not an artifact"))?;
     }
 
-    let job_id = brioche
-        .reporter
-        .add_job(crate::reporter::NewJob::RegistryFetch {
-            total_blobs: unknown_blobs.len(),
-            total_recipes: 0,
-        });
+    fetch_recipes_deep(brioche, HashSet::from([current.hash()])).await?;
+    fetch_artifact_blobs(brioche, &current).await?;
 
-    futures::stream::iter(unknown_blobs)
-        .map(Ok)
-        .try_for_each_concurrent(25, |blob| {
-            let brioche = brioche.clone();
-            async move {
-                let permit = crate::blob::get_save_blob_permit().await?;
-                super::blob::blob_path(&brioche, permit, blob).await?;
+    Ok(Some(current))
+}
 
-                brioche.reporter.update_job(
-                    job_id,
-                    crate::reporter::UpdateJob::RegistryFetchAdd {
-                        blobs_fetched: 1,
-                        recipes_fetched: 0,
-                    },
-                );
+/// Fetch every blob referenced by an artifact (recursively) that isn't
+/// already saved locally.
+pub async fn fetch_artifact_blobs(brioche: &Brioche, artifact: &Artifact) -> anyhow::Result<()> {
+    let mut blobs = HashSet::new();
+    crate::references::descendent_artifact_blobs(brioche, [artifact.clone()], &mut blobs).await?;
 
-                anyhow::Ok(())
-            }
-        })
-        .await?;
-
-    brioche
-        .reporter
-        .update_job(job_id, crate::reporter::UpdateJob::RegistryFetchFinish);
+    let blobs = blobs.into_iter().collect::<Vec<_>>();
+    crate::blob::prefetch_blobs(brioche, &blobs).await?;
 
     Ok(())
 }
 
+/// Returned when the registry closes a blob download before sending the
+/// whole blob.
+#[derive(Debug, thiserror::Error)]
+#[error("blob download ended after {downloaded_bytes} of {total_bytes} bytes")]
+struct BlobDownloadInterruptedError {
+    downloaded_bytes: u64,
+    total_bytes: u64,
+}
+
+/// Returns true if a failed blob download could succeed if it's retried:
+/// connection errors, timeouts, server errors, and interrupted responses.
+/// Client errors (like a missing blob) are returned right away.
+fn is_transient_download_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if cause.is::<BlobDownloadInterruptedError>() {
+            return true;
+        }
+
+        let reqwest_error = match cause.downcast_ref::<reqwest_middleware::Error>() {
+            Some(reqwest_middleware::Error::Reqwest(error)) => Some(error),
+            Some(reqwest_middleware::Error::Middleware(_)) => None,
+            None => cause.downcast_ref::<reqwest::Error>(),
+        };
+        let Some(reqwest_error) = reqwest_error else {
+            return false;
+        };
+
+        match reqwest_error.status() {
+            Some(status) => status.is_server_error(),
+            None => {
+                reqwest_error.is_connect()
+                    || reqwest_error.is_timeout()
+                    || reqwest_error.is_body()
+                    || reqwest_error.is_decode()
+                    || reqwest_error.is_request()
+            }
+        }
+    })
+}
+
 #[derive(Clone)]
 pub enum RegistryAuthentication {
     Anonymous,
     Admin { password: String },
 }
 
+/// Returned when the registry rejects publishing a project because the
+/// client isn't allowed to publish it.
+#[derive(Debug, thiserror::Error)]
+pub struct RegistryPermissionError {
+    pub project_name: String,
+    pub scope: Option<String>,
+    pub has_scope_token: bool,
+}
+
+impl std::fmt::Display for RegistryPermissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            project_name,
+            scope,
+            has_scope_token,
+        } = self;
+        match (scope, has_scope_token) {
+            (Some(scope), true) => write!(
+                f,
+                "not allowed to publish {project_name}: the registry token for @{scope} does not have permission to publish to this scope"
+            ),
+            (Some(scope), false) => write!(
+                f,
+                "not allowed to publish {project_name}: no registry token is configured for @{scope} (set one under `registry_tokens` in the brioche config)"
+            ),
+            (None, _) => write!(
+                f,
+                "not allowed to publish {project_name}: the registry rejected the credentials"
+            ),
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetProjectTagResponse {
     pub project_hash: ProjectHash,
+    /// Set if the tagged version was yanked. Yanked versions are skipped
+    /// when resolving new dependencies, but are still used when they're
+    /// already in a lockfile.
+    #[serde(default)]
+    pub yanked: bool,
+    /// A message shown when resolving a deprecated version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetProjectVersionsResponse {
+    pub versions: Vec<ProjectVersion>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectVersion {
+    pub version: String,
+    pub project_hash: ProjectHash,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -629,6 +1138,41 @@ pub struct UpdatedTag {
     pub previous_hash: Option<ProjectHash>,
 }
 
+/// A retention policy for a project's tags. A tag is deleted only if it
+/// matches every criterion that's set. The `latest` tag is never deleted.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneProjectRequest {
+    /// Delete tags published more than this many seconds ago.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub older_than_secs: Option<u64>,
+
+    /// Keep this many of the most recently published tags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_latest: Option<usize>,
+
+    /// Only report which tags would be deleted.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneProjectResponse {
+    /// The tags that were deleted (or would be, for a dry run).
+    pub pruned_tags: Vec<PrunedTag>,
+
+    /// The number of tags left after pruning.
+    pub remaining_tags: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunedTag {
+    pub tag: String,
+    pub project_hash: ProjectHash,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetBakeResponse {
@@ -636,12 +1180,16 @@ pub struct GetBakeResponse {
     pub output_artifact: Artifact,
     pub referenced_recipes: HashSet<RecipeHash>,
     pub referenced_blobs: HashSet<BlobHash>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<attestation::BakeAttestation>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateBakeRequest {
     pub output_hash: RecipeHash,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<attestation::BakeAttestation>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -649,3 +1197,125 @@ pub struct CreateBakeRequest {
 pub struct CreateBakeResponse {
     pub canonical_output_hash: RecipeHash,
 }
+
+/// Rebuild a response so that `permit` is only released once the response
+/// body has been read to the end (or dropped). The rebuilt response doesn't
+/// keep the original URL, so this is only used for successful responses.
+fn hold_permit_for_body(
+    response: reqwest::Response,
+    permit: tokio::sync::OwnedSemaphorePermit,
+) -> reqwest::Response {
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+
+    let body = response.bytes_stream().map(move |chunk| {
+        let _permit = &permit;
+        chunk
+    });
+    let response = builder
+        .body(reqwest::Body::wrap_stream(body))
+        .expect("failed to rebuild response");
+    reqwest::Response::from(response)
+}
+
+/// How much of a compressed blob has been downloaded so far, kept across
+/// retries so the download can be resumed.
+#[derive(Debug, Default)]
+struct BlobDownloadProgress {
+    downloaded_bytes: u64,
+    total_bytes: Option<u64>,
+}
+
+/// Limits the number of registry requests in flight, records the latency
+/// of each request (including each retry attempt), and waits and retries
+/// when the registry throttles requests.
+struct RequestMiddleware {
+    semaphore: Arc<tokio::sync::Semaphore>,
+    throttle: Arc<throttle::RequestThrottle>,
+}
+
+/// The maximum number of times a single request is retried after being
+/// throttled, before the throttled response is returned as-is.
+const MAX_THROTTLED_RETRIES: u32 = 10;
+
+#[async_trait::async_trait]
+impl reqwest_middleware::Middleware for RequestMiddleware {
+    async fn handle(
+        &self,
+        req: reqwest::Request,
+        extensions: &mut http::Extensions,
+        next: reqwest_middleware::Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        let method = req.method().clone();
+        let path = req.url().path().to_string();
+
+        let mut req = req;
+        let mut num_throttled = 0;
+        loop {
+            self.throttle.wait_turn().await;
+
+            // Keep a copy of the request in case we need to retry it. Requests
+            // with streaming bodies can't be cloned, so they aren't retried
+            let retry_req = if num_throttled < MAX_THROTTLED_RETRIES {
+                req.try_clone()
+            } else {
+                None
+            };
+
+            let permit = self
+                .semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|error| reqwest_middleware::Error::Middleware(error.into()))?;
+
+            let start = std::time::Instant::now();
+            let result = next.clone().run(req, extensions).await;
+            let elapsed_ms = start.elapsed().as_millis();
+
+            // Successful responses keep the permit until their body is read,
+            // since the body can take much longer than the headers
+            let result = match result {
+                Ok(response) if response.status().is_success() => {
+                    Ok(hold_permit_for_body(response, permit))
+                }
+                result => {
+                    drop(permit);
+                    result
+                }
+            };
+
+            match &result {
+                Ok(response) => {
+                    tracing::debug!(%method, %path, status = %response.status(), elapsed_ms, "registry request finished");
+                }
+                Err(error) => {
+                    tracing::debug!(%method, %path, %error, elapsed_ms, "registry request failed");
+                }
+            }
+
+            let retry_after = result
+                .as_ref()
+                .ok()
+                .and_then(throttle::throttled_retry_after);
+            match (retry_after, retry_req) {
+                (Some(retry_after), Some(retry_req)) => {
+                    tracing::debug!(%method, %path, retry_after_ms = retry_after.as_millis(), "registry request throttled");
+                    self.throttle.pause(retry_after);
+                    num_throttled += 1;
+                    req = retry_req;
+                }
+                (retry_after, _) => {
+                    if result.is_ok() && retry_after.is_none() {
+                        self.throttle.resume();
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
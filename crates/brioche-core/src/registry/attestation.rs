@@ -0,0 +1,207 @@
+use std::{collections::BTreeSet, path::PathBuf};
+
+use anyhow::Context as _;
+use ring::signature::KeyPair as _;
+
+use crate::recipe::{Artifact, RecipeHash};
+
+/// The file name of the builder's signing key, stored in the Brioche home
+/// directory as a PKCS#8 document. The key is created the first time a bake
+/// is pushed to the registry.
+pub const SIGNING_KEY_FILENAME: &str = "signing-key.pk8";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// The statement signed by a builder when pushing a bake: baking the recipe
+/// with `input_hash` produced the artifact with `output_hash`. The input
+/// hash covers the full recipe, including all of its inputs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BakeManifest {
+    pub version: u32,
+    pub input_hash: RecipeHash,
+    pub output_hash: RecipeHash,
+}
+
+impl BakeManifest {
+    fn signed_message(&self) -> anyhow::Result<Vec<u8>> {
+        let mut message = vec![];
+        json_canon::to_writer(&mut message, self)?;
+        Ok(message)
+    }
+}
+
+/// A bake manifest along with the builder's Ed25519 signature over its
+/// canonical JSON form.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BakeAttestation {
+    pub manifest: BakeManifest,
+    /// The builder's public key, hex-encoded.
+    pub public_key: String,
+    /// The signature, hex-encoded.
+    pub signature: String,
+}
+
+impl BakeAttestation {
+    /// Check that the signature is valid for the manifest. This says
+    /// nothing about whether the signer is trusted.
+    pub fn verify_signature(&self) -> anyhow::Result<()> {
+        let public_key = hex::decode(&self.public_key).context("invalid public key")?;
+        let signature = hex::decode(&self.signature).context("invalid signature")?;
+        let message = self.manifest.signed_message()?;
+
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key)
+            .verify(&message, &signature)
+            .map_err(|_| anyhow::anyhow!("attestation signature is invalid"))?;
+
+        Ok(())
+    }
+}
+
+/// Signs bakes pushed to the registry, and verifies attestations of bakes
+/// pulled from the registry.
+#[derive(Debug)]
+pub struct Attestations {
+    signing_key_path: PathBuf,
+    signing_key: tokio::sync::OnceCell<SigningKey>,
+    /// Hex-encoded public keys of trusted builders. When empty, bakes from
+    /// the registry are accepted without an attestation, but any attestation
+    /// that is included must still be valid.
+    trusted_keys: BTreeSet<String>,
+}
+
+impl Attestations {
+    pub fn new(signing_key_path: PathBuf, trusted_keys: BTreeSet<String>) -> Self {
+        Self {
+            signing_key_path,
+            signing_key: tokio::sync::OnceCell::new(),
+            trusted_keys,
+        }
+    }
+
+    pub async fn signing_key(&self) -> anyhow::Result<&SigningKey> {
+        self.signing_key
+            .get_or_try_init(|| SigningKey::load_or_create(&self.signing_key_path))
+            .await
+    }
+
+    pub async fn sign(
+        &self,
+        input_hash: RecipeHash,
+        output_hash: RecipeHash,
+    ) -> anyhow::Result<BakeAttestation> {
+        let signing_key = self.signing_key().await?;
+        signing_key.sign(BakeManifest {
+            version: MANIFEST_VERSION,
+            input_hash,
+            output_hash,
+        })
+    }
+
+    /// Verify the chain from a recipe to a bake output fetched from the
+    /// registry: the output artifact must match the output hash, which must
+    /// match the signed manifest for the recipe, which must be signed by a
+    /// trusted builder (if any are configured).
+    pub fn verify_bake(
+        &self,
+        input_hash: RecipeHash,
+        output_hash: RecipeHash,
+        output_artifact: &Artifact,
+        attestation: Option<&BakeAttestation>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            output_artifact.hash() == output_hash,
+            "bake output artifact does not match output hash {output_hash}"
+        );
+
+        let Some(attestation) = attestation else {
+            anyhow::ensure!(
+                self.trusted_keys.is_empty(),
+                "bake for {input_hash} has no attestation"
+            );
+            return Ok(());
+        };
+
+        attestation.verify_signature()?;
+        anyhow::ensure!(
+            attestation.manifest.input_hash == input_hash
+                && attestation.manifest.output_hash == output_hash,
+            "attestation does not match bake {input_hash} -> {output_hash}"
+        );
+
+        if !self.trusted_keys.is_empty() {
+            anyhow::ensure!(
+                self.trusted_keys.contains(&attestation.public_key),
+                "bake for {input_hash} was signed by untrusted key {}",
+                attestation.public_key,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SigningKey {
+    key_pair: ring::signature::Ed25519KeyPair,
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    async fn load_or_create(path: &std::path::Path) -> anyhow::Result<Self> {
+        let pkcs8 = match tokio::fs::read(path).await {
+            Ok(pkcs8) => pkcs8,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let rng = ring::rand::SystemRandom::new();
+                let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng)
+                    .map_err(|_| anyhow::anyhow!("failed to generate signing key"))?;
+                let pkcs8 = pkcs8.as_ref().to_vec();
+
+                let temp_path = path.with_extension(format!("{}.tmp", ulid::Ulid::new()));
+                tokio::fs::write(&temp_path, &pkcs8)
+                    .await
+                    .with_context(|| format!("failed to write {}", temp_path.display()))?;
+                let mut permissions = tokio::fs::metadata(&temp_path).await?.permissions();
+                crate::fs_utils::set_owner_only(&mut permissions);
+                tokio::fs::set_permissions(&temp_path, permissions).await?;
+                tokio::fs::rename(&temp_path, path)
+                    .await
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+
+                tracing::info!(path = %path.display(), "created signing key");
+
+                pkcs8
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read signing key {}", path.display()));
+            }
+        };
+
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|error| anyhow::anyhow!("invalid signing key {}: {error}", path.display()))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The hex-encoded public key, as listed in `trusted_builder_keys`.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, manifest: BakeManifest) -> anyhow::Result<BakeAttestation> {
+        let message = manifest.signed_message()?;
+        let signature = self.key_pair.sign(&message);
+        Ok(BakeAttestation {
+            manifest,
+            public_key: self.public_key(),
+            signature: hex::encode(signature.as_ref()),
+        })
+    }
+}
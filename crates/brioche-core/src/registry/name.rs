@@ -0,0 +1,61 @@
+/// The name of a project in the registry. Names can optionally be scoped
+/// to an organization, like `@org/name`, so different organizations can
+/// publish projects with the same name without colliding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProjectName {
+    pub scope: Option<String>,
+    pub name: String,
+}
+
+impl ProjectName {
+    pub fn is_valid(name: &str) -> bool {
+        name.parse::<Self>().is_ok()
+    }
+}
+
+impl std::str::FromStr for ProjectName {
+    type Err = anyhow::Error;
+
+    fn from_str(project_name: &str) -> Result<Self, Self::Err> {
+        static SCOPE_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+        static NAME_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+        let scope_regex = SCOPE_REGEX.get_or_init(|| {
+            regex::Regex::new("^[a-zA-Z0-9_][a-zA-Z0-9_-]*$").expect("failed to compile regex")
+        });
+        let name_regex = NAME_REGEX
+            .get_or_init(|| regex::Regex::new("^[a-zA-Z0-9_]+$").expect("failed to compile regex"));
+
+        let (scope, name) = match project_name.strip_prefix('@') {
+            Some(scoped_name) => {
+                let (scope, name) = scoped_name.split_once('/').ok_or_else(|| {
+                    anyhow::anyhow!("invalid project name {project_name:?}, expected `@scope/name`")
+                })?;
+                anyhow::ensure!(
+                    scope_regex.is_match(scope),
+                    "invalid scope in project name {project_name:?}"
+                );
+                (Some(scope), name)
+            }
+            None => (None, project_name),
+        };
+
+        anyhow::ensure!(
+            name_regex.is_match(name),
+            "invalid project name {project_name:?}"
+        );
+
+        Ok(Self {
+            scope: scope.map(|scope| scope.to_string()),
+            name: name.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ProjectName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.scope {
+            Some(scope) => write!(f, "@{scope}/{}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use crate::reporter::Reporter;
+
+/// The longest we'll wait for a single `Retry-After` delay. Longer delays
+/// are capped, since the request will just be throttled again if the
+/// registry is still busy.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The delay used when the registry responds with 429 Too Many Requests
+/// without a `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Paces requests to the registry, and pauses all requests when the
+/// registry asks us to slow down. The pause is shared by every request,
+/// so one throttled response holds back everything else instead of each
+/// request failing on its own.
+#[derive(Debug)]
+pub struct RequestThrottle {
+    min_interval: Option<Duration>,
+    next_request_at: tokio::sync::Mutex<Instant>,
+    paused_until: std::sync::Mutex<Option<Instant>>,
+    reporter: std::sync::OnceLock<Reporter>,
+}
+
+impl RequestThrottle {
+    pub fn new(max_requests_per_second: Option<f64>) -> Self {
+        let min_interval = max_requests_per_second
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(1.0 / rate));
+        Self {
+            min_interval,
+            next_request_at: tokio::sync::Mutex::new(Instant::now()),
+            paused_until: std::sync::Mutex::new(None),
+            reporter: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Report when the registry is throttling requests.
+    pub fn set_reporter(&self, reporter: Reporter) {
+        let _ = self.reporter.set(reporter);
+    }
+
+    /// Returns when requests will resume, if the registry is currently
+    /// throttling requests.
+    pub fn paused_until(&self) -> Option<Instant> {
+        let paused_until = self.paused_until.lock().expect("throttle lock poisoned");
+        paused_until.filter(|until| *until > Instant::now())
+    }
+
+    /// Wait until a request can be sent.
+    pub async fn wait_turn(&self) {
+        while let Some(until) = self.paused_until() {
+            tokio::time::sleep_until(until.into()).await;
+        }
+
+        if let Some(min_interval) = self.min_interval {
+            let mut next_request_at = self.next_request_at.lock().await;
+            let now = Instant::now();
+            let request_at = std::cmp::max(*next_request_at, now);
+            *next_request_at = request_at + min_interval;
+            drop(next_request_at);
+
+            tokio::time::sleep_until(request_at.into()).await;
+        }
+    }
+
+    /// Pause all requests for `delay`, after a throttled response.
+    pub fn pause(&self, delay: Duration) {
+        let delay = std::cmp::min(delay, MAX_RETRY_AFTER);
+        let until = Instant::now() + delay;
+
+        let mut paused_until = self.paused_until.lock().expect("throttle lock poisoned");
+        let was_paused = paused_until.is_some_and(|until| until > Instant::now());
+        if paused_until.is_some_and(|current| current >= until) {
+            return;
+        }
+        *paused_until = Some(until);
+        drop(paused_until);
+
+        if !was_paused {
+            tracing::info!(
+                delay_ms = delay.as_millis(),
+                "registry is throttling requests, waiting before retrying"
+            );
+        }
+        if let Some(reporter) = self.reporter.get() {
+            reporter.set_registry_throttled_until(Some(until));
+        }
+    }
+
+    /// Clear the throttled state once requests are going through again.
+    pub fn resume(&self) {
+        let mut paused_until = self.paused_until.lock().expect("throttle lock poisoned");
+        let Some(until) = *paused_until else {
+            return;
+        };
+        if until > Instant::now() {
+            return;
+        }
+        *paused_until = None;
+        drop(paused_until);
+
+        tracing::debug!("registry is no longer throttling requests");
+        if let Some(reporter) = self.reporter.get() {
+            reporter.set_registry_throttled_until(None);
+        }
+    }
+}
+
+/// If the response asks us to back off, returns how long to wait before
+/// retrying, based on its `Retry-After` header. Only 429 Too Many Requests
+/// and 503 Service Unavailable responses are considered.
+pub fn throttled_retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+
+    match status {
+        reqwest::StatusCode::TOO_MANY_REQUESTS => Some(retry_after.unwrap_or(DEFAULT_RETRY_AFTER)),
+        reqwest::StatusCode::SERVICE_UNAVAILABLE => retry_after,
+        _ => None,
+    }
+}
+
+/// Parse a `Retry-After` header value, which is either a number of seconds
+/// or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    let delay = date
+        .duration_since(std::time::SystemTime::now())
+        .unwrap_or_default();
+    Some(delay)
+}
@@ -0,0 +1,175 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+/// The longest a transfer yields to higher-priority ones before going
+/// ahead anyway. Lower-priority transfers age into the foreground after
+/// this, so a steady stream of downloads can't starve them.
+const MAX_YIELD_TIME: Duration = Duration::from_secs(5);
+
+tokio::task_local! {
+    static TRANSFER_PRIORITY: TransferPriority;
+}
+
+/// How urgently a download is needed. Downloads yield to any in-progress
+/// downloads with a higher priority before starting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TransferPriority {
+    /// Fetched speculatively while nothing else is happening, such as
+    /// updates prefetched by the daemon.
+    Idle,
+
+    /// Fetched in a batch before it's needed, such as the blobs of an
+    /// output that's about to be created.
+    Prefetch,
+
+    /// Needed right now by a bake that can't continue without it.
+    Blocking,
+}
+
+impl TransferPriority {
+    const ALL: [Self; 3] = [Self::Idle, Self::Prefetch, Self::Blocking];
+
+    /// The priority of transfers started from the current task. Transfers
+    /// are blocking unless run within [`with_priority`].
+    pub fn current() -> Self {
+        TRANSFER_PRIORITY
+            .try_with(|priority| *priority)
+            .unwrap_or(Self::Blocking)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Run `future`, lowering the priority of any downloads it starts to at
+/// most `priority`. Nested calls can't raise the priority back up, so
+/// blobs prefetched for an idle update check stay idle.
+pub async fn with_priority<F>(priority: TransferPriority, future: F) -> F::Output
+where
+    F: Future,
+{
+    let priority = priority.min(TransferPriority::current());
+    TRANSFER_PRIORITY.scope(priority, future).await
+}
+
+/// Schedules blob transfers to and from the registry. Downloads are
+/// ordered by [`TransferPriority`], so a bake waiting on a blob isn't held
+/// up by blobs fetched ahead of time. Uploads happen in the background
+/// (e.g. when syncing bakes), and yield to any in-progress downloads so
+/// they don't slow down the build. Each wait is capped at
+/// [`MAX_YIELD_TIME`], so lower-priority transfers still make progress.
+#[derive(Debug)]
+pub struct TransferScheduler {
+    download_limiter: Option<BandwidthLimiter>,
+    upload_limiter: Option<BandwidthLimiter>,
+    active_downloads: tokio::sync::watch::Sender<[usize; 3]>,
+}
+
+impl TransferScheduler {
+    pub fn new(
+        download_bytes_per_second: Option<u64>,
+        upload_bytes_per_second: Option<u64>,
+    ) -> Self {
+        let (active_downloads, _) = tokio::sync::watch::channel([0; 3]);
+        Self {
+            download_limiter: download_bytes_per_second.and_then(BandwidthLimiter::new),
+            upload_limiter: upload_bytes_per_second.and_then(BandwidthLimiter::new),
+            active_downloads,
+        }
+    }
+
+    /// Start a download at the current task's priority. This first waits
+    /// for higher-priority downloads to finish (up to [`MAX_YIELD_TIME`]),
+    /// and the download counts as in progress until the returned guard is
+    /// dropped.
+    pub async fn start_download(self: &Arc<Self>) -> ActiveDownload {
+        let priority = TransferPriority::current();
+        self.yield_to_downloads(|higher| higher > priority).await;
+
+        self.active_downloads
+            .send_modify(|active| active[priority.index()] += 1);
+        ActiveDownload {
+            scheduler: self.clone(),
+            priority,
+        }
+    }
+
+    /// Wait until `bytes` more bytes can be downloaded without exceeding
+    /// the download bandwidth limit.
+    pub async fn consume_download(&self, bytes: u64) {
+        if let Some(limiter) = &self.download_limiter {
+            limiter.consume(bytes).await;
+        }
+    }
+
+    /// Wait until `bytes` more bytes can be uploaded without exceeding the
+    /// upload bandwidth limit. This also yields to in-progress downloads
+    /// first.
+    pub async fn consume_upload(&self, bytes: u64) {
+        self.yield_to_downloads(|_| true).await;
+        if let Some(limiter) = &self.upload_limiter {
+            limiter.consume(bytes).await;
+        }
+    }
+
+    /// Wait until there are no downloads in progress with a priority
+    /// matching `yields_to`, or until [`MAX_YIELD_TIME`] has passed.
+    async fn yield_to_downloads(&self, yields_to: impl Fn(TransferPriority) -> bool) {
+        let mut active_downloads = self.active_downloads.subscribe();
+        let idle = active_downloads.wait_for(|active| {
+            TransferPriority::ALL
+                .into_iter()
+                .all(|priority| !yields_to(priority) || active[priority.index()] == 0)
+        });
+        let _ = tokio::time::timeout(MAX_YIELD_TIME, idle).await;
+    }
+}
+
+pub struct ActiveDownload {
+    scheduler: Arc<TransferScheduler>,
+    priority: TransferPriority,
+}
+
+impl Drop for ActiveDownload {
+    fn drop(&mut self) {
+        let priority = self.priority;
+        self.scheduler
+            .active_downloads
+            .send_modify(|active| active[priority.index()] -= 1);
+    }
+}
+
+/// Limits the average throughput of a transfer direction. Each call to
+/// `consume` reserves the next time slot for the given number of bytes,
+/// so concurrent transfers share the limit between them.
+#[derive(Debug)]
+struct BandwidthLimiter {
+    bytes_per_second: u64,
+    next_slot: std::sync::Mutex<tokio::time::Instant>,
+}
+
+impl BandwidthLimiter {
+    fn new(bytes_per_second: u64) -> Option<Self> {
+        if bytes_per_second == 0 {
+            return None;
+        }
+
+        Some(Self {
+            bytes_per_second,
+            next_slot: std::sync::Mutex::new(tokio::time::Instant::now()),
+        })
+    }
+
+    async fn consume(&self, bytes: u64) {
+        let duration =
+            std::time::Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        let start = {
+            let mut next_slot = self.next_slot.lock().expect("bandwidth limiter poisoned");
+            let start = (*next_slot).max(tokio::time::Instant::now());
+            *next_slot = start + duration;
+            start
+        };
+
+        tokio::time::sleep_until(start).await;
+    }
+}
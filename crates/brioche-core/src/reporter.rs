@@ -8,10 +8,12 @@ use std::{
 
 use bstr::ByteSlice;
 use debug_ignore::DebugIgnore;
-use human_repr::HumanDuration as _;
+use human_repr::{HumanCount as _, HumanDuration as _};
 use joinery::JoinableIterator as _;
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _, Layer as _};
 
+pub mod logging;
+
 const DEFAULT_TRACING_LEVEL: &str = "brioche=info";
 const DEFAULT_DEBUG_TRACING_LEVEL: &str = "brioche=debug";
 
@@ -31,14 +33,17 @@ pub fn start_console_reporter(
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
     let brioche_jaeger_endpoint = std::env::var("BRIOCHE_JAEGER_ENDPOINT").ok();
+    let logging_config = logging::read_logging_config();
 
     let start = std::time::Instant::now();
     let is_evaluating = Arc::new(AtomicBool::new(false));
+    let registry_throttled_until = Arc::new(std::sync::Mutex::new(None));
 
     let reporter = Reporter {
         start,
         num_jobs: Arc::new(AtomicUsize::new(0)),
         is_evaluating: is_evaluating.clone(),
+        registry_throttled_until: registry_throttled_until.clone(),
         tx: tx.clone(),
     };
     let guard = ReporterGuard {
@@ -66,6 +71,7 @@ pub fn start_console_reporter(
                     let root = JobsComponent {
                         start,
                         is_evaluating,
+                        registry_throttled_until,
                         jobs,
                         terminal: tokio::sync::RwLock::new(termwiz::surface::Surface::new(80, 24)),
                     };
@@ -144,19 +150,32 @@ pub fn start_console_reporter(
         _ => None,
     };
 
+    // Keep a debug log for each run, so detailed logs are available after
+    // a failure without needing to run again. Skipped when the log output
+    // is explicitly redirected with `BRIOCHE_LOG_OUTPUT`
+    let rotating_log_layer = if log_file_layer.is_none() && logging_config.log_file.unwrap_or(true)
+    {
+        rotating_log_layer(&logging_config)
+    } else {
+        None
+    };
+
     let tracing_console_layer =
         std::env::var_os("BRIOCHE_CONSOLE").map(|_| console_subscriber::spawn());
 
+    let output_filter = tracing_output_filter_from_config(&logging_config);
+
     // HACK: Add a filter to the subscriber to remove debug logs that we
     // shouldn't see if no other layer needs them. This is a workaround for
     // this issue: https://github.com/tokio-rs/tracing/issues/2448
     let root_filter = match (
         &log_file_layer,
+        &rotating_log_layer,
         &opentelemetry_layer,
         &tracing_console_layer,
     ) {
-        (None, None, None) => Some(tracing_output_filter()),
-        (_, _, Some(_)) => Some(tracing_root_filter()),
+        (None, None, None, None) => Some(tracing_output_filter_from_config(&logging_config)),
+        (_, _, _, Some(_)) => Some(tracing_root_filter()),
         _ => None,
     };
 
@@ -164,12 +183,13 @@ pub fn start_console_reporter(
         .compact()
         .with_writer(reporter.clone())
         .without_time()
-        .with_filter(tracing_output_filter());
+        .with_filter(output_filter);
     tracing_subscriber::registry()
         .with(root_filter)
         .with(tracing_console_layer)
         .with(reporter_layer)
         .with(log_file_layer)
+        .with(rotating_log_layer)
         .with(opentelemetry_layer)
         .init();
 
@@ -182,6 +202,54 @@ fn tracing_output_filter() -> tracing_subscriber::EnvFilter {
         .from_env_lossy()
 }
 
+/// Get the filter for console output. `RUST_LOG` takes precedence over
+/// the config file when it's set.
+fn tracing_output_filter_from_config(
+    config: &logging::LoggingConfig,
+) -> tracing_subscriber::EnvFilter {
+    if std::env::var_os(tracing_subscriber::EnvFilter::DEFAULT_ENV).is_some() {
+        return tracing_output_filter();
+    }
+
+    let base = config.filter.as_deref().unwrap_or(DEFAULT_TRACING_LEVEL);
+    match config.filter_with(base) {
+        Ok(filter) => filter,
+        Err(error) => {
+            eprintln!("invalid logging config: {error:#}");
+            tracing_output_filter()
+        }
+    }
+}
+
+fn rotating_log_layer<S>(
+    config: &logging::LoggingConfig,
+) -> Option<impl tracing_subscriber::Layer<S>>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    let logs_dir = logging::default_logs_dir()?;
+    let filter = match config.log_file_filter() {
+        Ok(filter) => filter,
+        Err(error) => {
+            eprintln!("invalid logging config: {error:#}");
+            return None;
+        }
+    };
+    let log_file = match logging::create_log_file(&logs_dir, config.max_log_files) {
+        Ok((_, log_file)) => log_file,
+        Err(error) => {
+            eprintln!("failed to create log file: {error:#}");
+            return None;
+        }
+    };
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(std::sync::Mutex::new(log_file))
+        .with_filter(filter);
+    Some(layer)
+}
+
 fn tracing_debug_filter() -> tracing_subscriber::EnvFilter {
     tracing_subscriber::EnvFilter::builder()
         .with_default_directive(DEFAULT_DEBUG_TRACING_LEVEL.parse().expect("invalid filter"))
@@ -296,7 +364,9 @@ impl ConsoleReporter {
                 let _ = job.update(update);
             }
             ConsoleReporter::Plain { partial_lines } => match update {
-                UpdateJob::Download { progress_percent } => {
+                UpdateJob::Download {
+                    progress_percent, ..
+                } => {
                     if progress_percent == Some(100) {
                         eprintln!("Finished download");
                     }
@@ -382,6 +452,7 @@ pub fn start_lsp_reporter(client: tower_lsp::Client) -> (Reporter, ReporterGuard
         start: std::time::Instant::now(),
         num_jobs: Arc::new(AtomicUsize::new(0)),
         is_evaluating: Arc::new(AtomicBool::new(false)),
+        registry_throttled_until: Arc::new(std::sync::Mutex::new(None)),
         tx: tx.clone(),
     };
     let guard = ReporterGuard {
@@ -431,6 +502,7 @@ pub fn start_null_reporter() -> (Reporter, ReporterGuard) {
         start: std::time::Instant::now(),
         num_jobs: Arc::new(AtomicUsize::new(0)),
         is_evaluating: Arc::new(AtomicBool::new(false)),
+        registry_throttled_until: Arc::new(std::sync::Mutex::new(None)),
         tx: tx.clone(),
     };
     let guard = ReporterGuard {
@@ -468,6 +540,7 @@ pub fn start_test_reporter() -> (Reporter, ReporterGuard) {
         start: std::time::Instant::now(),
         num_jobs: Arc::new(AtomicUsize::new(0)),
         is_evaluating: Arc::new(AtomicBool::new(false)),
+        registry_throttled_until: Arc::new(std::sync::Mutex::new(None)),
         tx: tx.clone(),
     };
     let guard = ReporterGuard {
@@ -532,6 +605,12 @@ pub enum NewJob {
 pub enum UpdateJob {
     Download {
         progress_percent: Option<u8>,
+        /// The number of bytes downloaded so far, if known. `None` keeps
+        /// the previously-reported count.
+        downloaded_bytes: Option<u64>,
+        /// The total size of the download in bytes, if known. `None` keeps
+        /// the previously-reported size.
+        total_bytes: Option<u64>,
     },
     Unarchive {
         progress_percent: u8,
@@ -553,11 +632,39 @@ pub enum UpdateJob {
     RegistryFetchFinish,
 }
 
+impl UpdateJob {
+    /// Report that `downloaded_bytes` out of `total_bytes` have been
+    /// downloaded. The progress stays below 100% until the download
+    /// is explicitly finished, since the content still needs to be saved.
+    pub fn download_progress(downloaded_bytes: u64, total_bytes: Option<u64>) -> Self {
+        let progress_percent = total_bytes.map(|total_bytes| {
+            let progress_percent = (downloaded_bytes as f64 / total_bytes as f64) * 100.0;
+            progress_percent.round().min(99.0) as u8
+        });
+        Self::Download {
+            progress_percent,
+            downloaded_bytes: Some(downloaded_bytes),
+            total_bytes,
+        }
+    }
+
+    /// Report that a download has finished.
+    pub fn download_finished() -> Self {
+        Self::Download {
+            progress_percent: Some(100),
+            downloaded_bytes: None,
+            total_bytes: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Job {
     Download {
         url: url::Url,
         progress_percent: Option<u8>,
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
     },
     Unarchive {
         progress_percent: u8,
@@ -580,6 +687,8 @@ impl Job {
             NewJob::Download { url } => Self::Download {
                 url,
                 progress_percent: Some(0),
+                downloaded_bytes: 0,
+                total_bytes: None,
             },
             NewJob::Unarchive => Self::Unarchive {
                 progress_percent: 0,
@@ -604,14 +713,25 @@ impl Job {
         match update {
             UpdateJob::Download {
                 progress_percent: new_progress_percent,
+                downloaded_bytes: new_downloaded_bytes,
+                total_bytes: new_total_bytes,
             } => {
                 let Self::Download {
-                    progress_percent, ..
+                    progress_percent,
+                    downloaded_bytes,
+                    total_bytes,
+                    ..
                 } = self
                 else {
                     anyhow::bail!("tried to update a non-download job with a download update");
                 };
                 *progress_percent = new_progress_percent;
+                if let Some(new_downloaded_bytes) = new_downloaded_bytes {
+                    *downloaded_bytes = new_downloaded_bytes;
+                }
+                if let Some(new_total_bytes) = new_total_bytes {
+                    *total_bytes = Some(new_total_bytes);
+                }
             }
             UpdateJob::Unarchive {
                 progress_percent: new_progress_percent,
@@ -754,16 +874,26 @@ impl superconsole::Component for Job {
             Job::Download {
                 url,
                 progress_percent,
+                downloaded_bytes,
+                total_bytes,
             } => {
+                let downloaded = downloaded_bytes.human_count_bytes();
+                let size = match total_bytes {
+                    Some(total_bytes) => {
+                        format!(" ({downloaded} / {})", total_bytes.human_count_bytes())
+                    }
+                    None if *downloaded_bytes > 0 => format!(" ({downloaded})"),
+                    None => String::new(),
+                };
                 let message = match progress_percent {
                     Some(100) => {
                         format!("[100%] Downloaded {url}")
                     }
                     Some(progress_percent) => {
-                        format!("[{progress_percent:>3}%] Downloading {url}")
+                        format!("[{progress_percent:>3}%] Downloading {url}{size}")
                     }
                     None => {
-                        format!("[???%] Downloading {url}")
+                        format!("[???%] Downloading {url}{size}")
                     }
                 };
                 superconsole::Lines::from_iter([superconsole::Line::sanitized(&message)])
@@ -916,6 +1046,7 @@ pub struct Reporter {
     start: std::time::Instant,
     num_jobs: Arc<AtomicUsize>,
     is_evaluating: Arc<AtomicBool>,
+    registry_throttled_until: Arc<std::sync::Mutex<Option<std::time::Instant>>>,
     tx: tokio::sync::mpsc::UnboundedSender<ReportEvent>,
 }
 
@@ -929,6 +1060,16 @@ impl Reporter {
             .store(is_evaluating, std::sync::atomic::Ordering::SeqCst);
     }
 
+    /// Show that requests to the registry are paused until the given time,
+    /// or clear the throttled state with `None`.
+    pub fn set_registry_throttled_until(&self, until: Option<std::time::Instant>) {
+        let mut throttled_until = self
+            .registry_throttled_until
+            .lock()
+            .expect("reporter lock poisoned");
+        *throttled_until = until;
+    }
+
     pub fn add_job(&self, job: NewJob) -> JobId {
         let id = self
             .num_jobs
@@ -984,6 +1125,7 @@ impl std::io::Write for ReporterWriter {
 struct JobsComponent {
     start: std::time::Instant,
     is_evaluating: Arc<AtomicBool>,
+    registry_throttled_until: Arc<std::sync::Mutex<Option<std::time::Instant>>>,
     jobs: Arc<tokio::sync::RwLock<HashMap<JobId, Job>>>,
     terminal: tokio::sync::RwLock<termwiz::surface::Surface>,
 }
@@ -1041,11 +1183,24 @@ impl superconsole::Component for JobsComponent {
         let elapsed = self.start.elapsed().human_duration();
         let summary_line = match mode {
             superconsole::DrawMode::Normal => {
-                let summary_line = format!(
+                let mut summary_line = format!(
                     "[{elapsed}] {num_complete_jobs} / {num_jobs}{or_more} job{s} complete",
                     s = if num_jobs == 1 { "" } else { "s" },
                     or_more = if is_evaluating { "+" } else { "" },
                 );
+                let registry_throttled_until = *self
+                    .registry_throttled_until
+                    .lock()
+                    .expect("reporter lock poisoned");
+                let throttled_remaining = registry_throttled_until
+                    .map(|until| until.saturating_duration_since(std::time::Instant::now()))
+                    .filter(|remaining| !remaining.is_zero());
+                if let Some(remaining) = throttled_remaining {
+                    summary_line.push_str(&format!(
+                        " (registry throttled, retrying in {}s)",
+                        remaining.as_secs() + 1
+                    ));
+                }
                 Some(superconsole::Line::from_iter([summary_line
                     .try_into()
                     .unwrap()]))
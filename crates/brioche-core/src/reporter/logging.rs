@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+/// The default number of log files kept in the logs directory. The oldest
+/// log files are removed when a new one is created.
+const DEFAULT_MAX_LOG_FILES: usize = 20;
+
+const DEFAULT_LOG_FILE_FILTER: &str = "brioche=debug";
+
+/// Logging options, read from the `[logging]` table of the Brioche config
+/// file. `RUST_LOG` still takes precedence over the console filter when
+/// it's set.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LoggingConfig {
+    /// Tracing directives for console output, using the same syntax as
+    /// `RUST_LOG` (e.g. `"brioche=info,brioche_core::registry=debug"`).
+    pub filter: Option<String>,
+
+    /// Levels for individual subsystems, applied on top of `filter` and
+    /// `log_file_filter` (e.g. `sandbox = "trace"`).
+    #[serde(default)]
+    pub levels: BTreeMap<Subsystem, String>,
+
+    /// Write a debug log file for each run to the `logs` directory within
+    /// the Brioche home directory. Enabled by default.
+    pub log_file: Option<bool>,
+
+    /// Tracing directives for the log file [default: `brioche=debug`].
+    pub log_file_filter: Option<String>,
+
+    /// The number of log files to keep.
+    pub max_log_files: Option<usize>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    Blob,
+    #[serde(alias = "resolve")]
+    Bake,
+    Sandbox,
+    Script,
+    Registry,
+}
+
+impl Subsystem {
+    fn target(&self) -> &'static str {
+        match self {
+            Self::Blob => "brioche_core::blob",
+            Self::Bake => "brioche_core::bake",
+            Self::Sandbox => "brioche_core::sandbox",
+            Self::Script => "brioche_core::script",
+            Self::Registry => "brioche_core::registry",
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Build a tracing filter from a base set of directives, with the
+    /// per-subsystem levels added on top.
+    pub fn filter_with(&self, base: &str) -> anyhow::Result<tracing_subscriber::EnvFilter> {
+        let mut filter = tracing_subscriber::EnvFilter::builder()
+            .parse(base)
+            .with_context(|| format!("invalid tracing filter {base:?}"))?;
+        for (subsystem, level) in &self.levels {
+            let directive = format!("{}={level}", subsystem.target());
+            let directive = directive
+                .parse()
+                .with_context(|| format!("invalid level {level:?} for {subsystem:?}"))?;
+            filter = filter.add_directive(directive);
+        }
+
+        Ok(filter)
+    }
+
+    pub fn log_file_filter(&self) -> anyhow::Result<tracing_subscriber::EnvFilter> {
+        let base = self
+            .log_file_filter
+            .as_deref()
+            .unwrap_or(DEFAULT_LOG_FILE_FILTER);
+        self.filter_with(base)
+    }
+}
+
+/// Read the `[logging]` table from the Brioche config file. This is read
+/// separately from the rest of the config, since logging gets set up
+/// before anything else. Any problems are printed and ignored, so a bad
+/// config file doesn't prevent errors from being reported.
+pub fn read_logging_config() -> LoggingConfig {
+    #[derive(Default, serde::Deserialize)]
+    struct Config {
+        #[serde(default)]
+        logging: LoggingConfig,
+    }
+
+    let Some(dirs) = directories::ProjectDirs::from("dev", "brioche", "brioche") else {
+        return LoggingConfig::default();
+    };
+    let config_path = dirs.config_dir().join("config.toml");
+    let config = match std::fs::read_to_string(&config_path) {
+        Ok(config) => config,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return LoggingConfig::default();
+        }
+        Err(error) => {
+            eprintln!("failed to read {}: {error}", config_path.display());
+            return LoggingConfig::default();
+        }
+    };
+
+    match toml::from_str::<Config>(&config) {
+        Ok(config) => config.logging,
+        Err(error) => {
+            eprintln!(
+                "failed to parse logging config from {}: {error}",
+                config_path.display()
+            );
+            LoggingConfig::default()
+        }
+    }
+}
+
+/// The default directory for log files, within the Brioche home directory.
+pub fn default_logs_dir() -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("dev", "brioche", "brioche")?;
+    Some(dirs.data_local_dir().join("logs"))
+}
+
+/// Create a new log file in `logs_dir`, removing the oldest log files so
+/// that at most `max_log_files` remain (including the new one).
+pub fn create_log_file(
+    logs_dir: &Path,
+    max_log_files: Option<usize>,
+) -> anyhow::Result<(PathBuf, std::fs::File)> {
+    std::fs::create_dir_all(logs_dir)
+        .with_context(|| format!("failed to create logs directory {}", logs_dir.display()))?;
+
+    let max_log_files = max_log_files.unwrap_or(DEFAULT_MAX_LOG_FILES).max(1);
+    let mut existing_logs = std::fs::read_dir(logs_dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let is_log = path.extension().is_some_and(|ext| ext == "log");
+            is_log.then_some(path)
+        })
+        .collect::<Vec<_>>();
+
+    // Log file names start with a ULID, so sorting by name sorts them
+    // from oldest to newest
+    existing_logs.sort();
+    let num_to_remove = (existing_logs.len() + 1).saturating_sub(max_log_files);
+    for old_log in &existing_logs[..num_to_remove] {
+        if let Err(error) = std::fs::remove_file(old_log) {
+            eprintln!(
+                "failed to remove old log file {}: {error}",
+                old_log.display()
+            );
+        }
+    }
+
+    let log_path = logs_dir.join(format!("{}.log", ulid::Ulid::new()));
+    let log_file = std::fs::File::create(&log_path)
+        .with_context(|| format!("failed to create log file {}", log_path.display()))?;
+    Ok((log_path, log_file))
+}
@@ -1,8 +1,18 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use crate::encoding::{AsPath, TickEncoded};
 
+pub mod input_trace;
 mod linux;
+pub mod subid;
+pub mod terminal;
+
+/// The exit code used by `brioche run-sandbox` when the sandbox itself
+/// couldn't be set up, as opposed to the sandboxed process failing.
+pub const SANDBOX_ERROR_EXIT_CODE: u8 = 122;
 
 #[serde_with::serde_as]
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -17,9 +27,132 @@ pub struct SandboxExecutionConfig {
     #[serde_as(as = "HashMap<TickEncoded, _>")]
     pub env: HashMap<bstr::BString, SandboxTemplate>,
     pub current_dir: SandboxPath,
+    /// Write the process's stdout to this host path, rather than inheriting
+    /// stdout.
+    #[serde_as(as = "Option<AsPath<TickEncoded>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_path: Option<PathBuf>,
+    /// Write the process's stderr to this host path, rather than inheriting
+    /// stderr.
+    #[serde_as(as = "Option<AsPath<TickEncoded>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_path: Option<PathBuf>,
+    /// Attach stdout and stderr to a pseudo-terminal, and write its output
+    /// (with terminal control sequences stripped) to stdout.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub tty: bool,
     pub networking: bool,
     pub uid_hint: u32,
     pub gid_hint: u32,
+    /// Map the other IDs within the sandbox to the host user's subordinate
+    /// IDs, if it has any.
+    #[serde(default, skip_serializing_if = "crate::utils::is_default")]
+    pub subordinate_ids: bool,
+    /// Trace the files the process accesses, and write them to this host
+    /// path as a list of [`input_trace::FileAccess`]es.
+    #[serde_as(as = "Option<AsPath<TickEncoded>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_path: Option<PathBuf>,
+    /// Run the process in its own network namespace, where the only way
+    /// out is through an HTTP proxy on the host. Only used when
+    /// `networking` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_proxy: Option<SandboxNetworkProxy>,
+}
+
+/// An HTTP proxy the sandbox exposes to the process on the loopback
+/// interface. Connections to `127.0.0.1:{guest_port}` within the sandbox
+/// are forwarded to the proxy's abstract Unix socket on the host.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxNetworkProxy {
+    pub host_socket_name: String,
+    pub guest_port: u16,
+}
+
+impl SandboxExecutionConfig {
+    /// Rewrite every host path in the config that's under `from` to be
+    /// under `to` instead. Host paths outside of `from` are left as-is.
+    pub fn rebase_host_paths(self, from: &Path, to: &Path) -> Self {
+        let rebase = |path: PathBuf| match path.strip_prefix(from) {
+            Ok(relative) => to.join(relative),
+            Err(_) => path,
+        };
+        let rebase_template = |template: SandboxTemplate| SandboxTemplate {
+            components: template
+                .components
+                .into_iter()
+                .map(|component| match component {
+                    SandboxTemplateComponent::Path(path) => {
+                        SandboxTemplateComponent::Path(SandboxPath {
+                            host_path: rebase(path.host_path),
+                            options: path.options,
+                        })
+                    }
+                    literal @ SandboxTemplateComponent::Literal { .. } => literal,
+                })
+                .collect(),
+        };
+
+        Self {
+            sandbox_root: rebase(self.sandbox_root),
+            include_host_paths: self
+                .include_host_paths
+                .into_iter()
+                .map(|(path, options)| (rebase(path), options))
+                .collect(),
+            command: rebase_template(self.command),
+            args: self.args.into_iter().map(rebase_template).collect(),
+            env: self
+                .env
+                .into_iter()
+                .map(|(key, value)| (key, rebase_template(value)))
+                .collect(),
+            current_dir: SandboxPath {
+                host_path: rebase(self.current_dir.host_path),
+                options: self.current_dir.options,
+            },
+            stdout_path: self.stdout_path.map(rebase),
+            stderr_path: self.stderr_path.map(rebase),
+            tty: self.tty,
+            networking: self.networking,
+            uid_hint: self.uid_hint,
+            gid_hint: self.gid_hint,
+            subordinate_ids: self.subordinate_ids,
+            trace_path: self.trace_path.map(rebase),
+            network_proxy: self.network_proxy,
+        }
+    }
+}
+
+/// The user and group that processes run as within the sandbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SandboxUser {
+    /// The user ID within the sandbox, which is mapped to the current user
+    /// on the host.
+    pub uid: u32,
+
+    /// The group ID within the sandbox, which is mapped to the current
+    /// user's group on the host.
+    pub gid: u32,
+
+    /// Map the other IDs within the sandbox to the current user's
+    /// subordinate IDs from `/etc/subuid` and `/etc/subgid` (using
+    /// `newuidmap` and `newgidmap`), so processes can `chown` files to
+    /// other users. Only `uid` and `gid` are mapped if no subordinate IDs
+    /// are available.
+    pub subordinate_ids: bool,
+}
+
+impl Default for SandboxUser {
+    fn default() -> Self {
+        Self {
+            uid: 1099,
+            gid: 1099,
+            subordinate_ids: false,
+        }
+    }
 }
 
 #[serde_with::serde_as]
@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bstr::{BString, ByteSlice as _};
+
+use crate::encoding::TickEncoded;
+
+use super::{HostPathMode, SandboxExecutionConfig, SandboxTemplate, SandboxTemplateComponent};
+
+/// A path a traced process tried to access. Only absolute paths are
+/// recorded, since relative paths can't reliably be resolved from outside
+/// of the sandbox.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileAccess {
+    #[serde_as(as = "TickEncoded")]
+    pub path: BString,
+    /// The errno returned by the syscall, if every access to the path
+    /// failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+}
+
+/// Collects accesses by path, so each path is recorded once. A path counts
+/// as accessed successfully if any of its accesses succeeded.
+#[derive(Debug, Default)]
+pub struct FileAccesses {
+    accesses: BTreeMap<BString, Option<i32>>,
+}
+
+impl FileAccesses {
+    pub fn record(&mut self, path: BString, error: Option<i32>) {
+        let entry = self.accesses.entry(path).or_insert(error);
+        if error.is_none() {
+            *entry = None;
+        }
+    }
+
+    pub fn into_accesses(self) -> Vec<FileAccess> {
+        self.accesses
+            .into_iter()
+            .map(|(path, error)| FileAccess { path, error })
+            .collect()
+    }
+}
+
+/// The guest paths mounted into a sandbox, used to compare a process's
+/// declared inputs against the files it actually accessed.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxGuestPaths {
+    /// Read-only mounts, which includes each input artifact.
+    inputs: BTreeSet<BString>,
+    mounts: BTreeSet<BString>,
+}
+
+impl SandboxGuestPaths {
+    pub fn from_config(config: &SandboxExecutionConfig) -> Self {
+        let mut paths = Self::default();
+        for options in config.include_host_paths.values() {
+            paths.insert(options.mode, &options.guest_path_hint);
+        }
+
+        let templates = [&config.command]
+            .into_iter()
+            .chain(&config.args)
+            .chain(config.env.values());
+        for template in templates {
+            paths.insert_template(template);
+        }
+
+        let current_dir = &config.current_dir.options;
+        paths.insert(current_dir.mode, &current_dir.guest_path_hint);
+
+        paths
+    }
+
+    fn insert_template(&mut self, template: &SandboxTemplate) {
+        for component in &template.components {
+            if let SandboxTemplateComponent::Path(path) = component {
+                self.insert(path.options.mode, &path.options.guest_path_hint);
+            }
+        }
+    }
+
+    fn insert(&mut self, mode: HostPathMode, guest_path: &BString) {
+        if mode == HostPathMode::Read {
+            self.inputs.insert(guest_path.clone());
+        }
+        self.mounts.insert(guest_path.clone());
+    }
+
+    /// Build a report from the file accesses recorded while tracing a
+    /// process. An input is unused if nothing under its guest path was
+    /// accessed. An access is undeclared if it failed and wasn't under
+    /// any mounted path, meaning the file may be missing from the sandbox.
+    pub fn report(&self, accesses: &[FileAccess]) -> InputTraceReport {
+        let unused_inputs = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                !accesses
+                    .iter()
+                    .any(|access| is_path_under(&access.path, input))
+            })
+            .cloned()
+            .collect();
+        let undeclared_accesses = accesses
+            .iter()
+            .filter(|access| access.error.is_some())
+            .filter(|access| {
+                !self
+                    .mounts
+                    .iter()
+                    .any(|mount| is_path_under(&access.path, mount))
+            })
+            .map(|access| access.path.clone())
+            .collect();
+
+        InputTraceReport {
+            unused_inputs,
+            undeclared_accesses,
+        }
+    }
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputTraceReport {
+    /// Guest paths of inputs the process never accessed.
+    #[serde_as(as = "Vec<TickEncoded>")]
+    pub unused_inputs: Vec<BString>,
+    /// Paths the process failed to access outside of its inputs.
+    #[serde_as(as = "Vec<TickEncoded>")]
+    pub undeclared_accesses: Vec<BString>,
+}
+
+impl InputTraceReport {
+    pub fn is_empty(&self) -> bool {
+        self.unused_inputs.is_empty() && self.undeclared_accesses.is_empty()
+    }
+}
+
+fn is_path_under(path: &[u8], parent: &[u8]) -> bool {
+    let parent = parent.trim_end_with(|c| c == '/');
+    match path.strip_prefix(parent) {
+        Some(rest) => rest.is_empty() || rest.starts_with(b"/"),
+        None => false,
+    }
+}
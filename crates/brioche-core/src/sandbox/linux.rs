@@ -1,6 +1,14 @@
 #![cfg(target_os = "linux")]
 
-use std::{collections::HashMap, ffi::OsString, path::PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use bstr::ByteSlice as _;
 
@@ -9,7 +17,15 @@ use super::{
     SandboxTemplateComponent,
 };
 
+mod network_proxy;
+mod ptrace;
+mod pty;
+
 pub fn run_sandbox(exec: super::SandboxExecutionConfig) -> anyhow::Result<super::ExitStatus> {
+    if exec.trace_path.is_some() {
+        ptrace::ensure_supported()?;
+    }
+
     let mut host_paths = exec.include_host_paths;
 
     let sandbox_host_dir = exec.sandbox_root.join("mnt").join("brioche-host");
@@ -44,26 +60,97 @@ pub fn run_sandbox(exec: super::SandboxExecutionConfig) -> anyhow::Result<super:
     )?;
     command.current_dir(current_dir);
 
+    if let Some(stdout_path) = &exec.stdout_path {
+        let stdout_file = std::fs::File::create(stdout_path).map_err(|error| {
+            anyhow::anyhow!(
+                "failed to create stdout file {}: {error}",
+                stdout_path.display()
+            )
+        })?;
+        command.stdout(unshare::Stdio::from_file(stdout_file));
+    }
+
+    if let Some(stderr_path) = &exec.stderr_path {
+        let stderr_file = std::fs::File::create(stderr_path).map_err(|error| {
+            anyhow::anyhow!(
+                "failed to create stderr file {}: {error}",
+                stderr_path.display()
+            )
+        })?;
+        command.stderr(unshare::Stdio::from_file(stderr_file));
+    }
+
+    let pty = if exec.tty {
+        let pty = pty::Pty::open()?;
+        command.stdout(unshare::Stdio::from_file(pty.slave_file()?));
+        command.stderr(unshare::Stdio::from_file(pty.slave_file()?));
+        Some(pty)
+    } else {
+        None
+    };
+
     let host_uid = nix::unistd::Uid::current().as_raw();
     let host_gid = nix::unistd::Gid::current().as_raw();
-    command.set_id_maps(
-        vec![unshare::UidMap {
-            inside_uid: exec.uid_hint,
-            outside_uid: host_uid,
-            count: 1,
-        }],
-        vec![unshare::GidMap {
-            inside_gid: exec.gid_hint,
-            outside_gid: host_gid,
-            count: 1,
-        }],
-    );
+    let subid_ranges = if exec.subordinate_ids {
+        subordinate_id_ranges(host_uid)
+    } else {
+        None
+    };
+    let uid_range = subid_ranges.map(|(uid_range, _)| uid_range);
+    let gid_range = subid_ranges.map(|(_, gid_range)| gid_range);
+    let uid_maps = super::subid::id_mappings(exec.uid_hint, host_uid, uid_range)
+        .into_iter()
+        .map(|mapping| unshare::UidMap {
+            inside_uid: mapping.inside,
+            outside_uid: mapping.outside,
+            count: mapping.count,
+        })
+        .collect();
+    let gid_maps = super::subid::id_mappings(exec.gid_hint, host_gid, gid_range)
+        .into_iter()
+        .map(|mapping| unshare::GidMap {
+            inside_gid: mapping.inside,
+            outside_gid: mapping.outside,
+            count: mapping.count,
+        })
+        .collect();
+    command.set_id_maps(uid_maps, gid_maps);
     command.uid(exec.uid_hint);
     command.gid(exec.gid_hint);
-    command.deny_setgroups(true);
 
-    // Only unshare the network namespace if networking is disabled
-    let unshare_net = Some(&unshare::Namespace::Net).filter(|_| !exec.networking);
+    if subid_ranges.is_some() {
+        // Mapping more than one ID needs the setuid helpers from shadow
+        let newuidmap = find_in_path("newuidmap").ok_or_else(|| {
+            anyhow::anyhow!("subordinate IDs are enabled, but `newuidmap` was not found")
+        })?;
+        let newgidmap = find_in_path("newgidmap").ok_or_else(|| {
+            anyhow::anyhow!("subordinate IDs are enabled, but `newgidmap` was not found")
+        })?;
+        command.set_id_map_commands(newuidmap, newgidmap);
+    } else {
+        command.deny_setgroups(true);
+    }
+
+    // Only unshare the network namespace if networking is disabled, or if
+    // the process should only reach the network through a proxy
+    let network_proxy = exec.network_proxy.as_ref().filter(|_| exec.networking);
+    let unshare_net =
+        Some(&unshare::Namespace::Net).filter(|_| !exec.networking || network_proxy.is_some());
+
+    // The proxy listener gets created within the sandbox, then sent back
+    // to us over this channel so we can forward its connections
+    let network_proxy_channel = network_proxy
+        .map(|network_proxy| {
+            let (parent_channel, child_channel) = std::os::unix::net::UnixStream::pair()?;
+            anyhow::Ok((network_proxy.clone(), parent_channel, child_channel))
+        })
+        .transpose()?;
+    let child_proxy_channel = network_proxy_channel
+        .as_ref()
+        .map(|(network_proxy, _, child_channel)| {
+            anyhow::Ok((child_channel.try_clone()?, network_proxy.guest_port))
+        })
+        .transpose()?;
 
     let unshare_namespaces = [
         Some(&unshare::Namespace::Mount),
@@ -78,7 +165,13 @@ pub fn run_sandbox(exec: super::SandboxExecutionConfig) -> anyhow::Result<super:
     command.pivot_root(&exec.sandbox_root, &sandbox_host_dir, true);
     command.before_chroot({
         let sandbox_root = exec.sandbox_root.clone();
+        let trace = exec.trace_path.is_some();
+        let pty_slave_fd = pty.as_ref().map(|pty| pty.slave_raw_fd());
         move || {
+            if let Some((channel, guest_port)) = &child_proxy_channel {
+                network_proxy::listen_in_sandbox(channel, *guest_port)?;
+            }
+
             for (path, options) in &host_paths {
                 let path_metadata = path.metadata().map_err(|error| {
                     std::io::Error::new(
@@ -144,6 +237,16 @@ pub fn run_sandbox(exec: super::SandboxExecutionConfig) -> anyhow::Result<super:
                     )
                 })?;
 
+            if let Some(pty_slave_fd) = pty_slave_fd {
+                pty::set_controlling_terminal(pty_slave_fd);
+            }
+
+            // Stop once the process execs, so the parent can start
+            // tracing it
+            if trace {
+                nix::sys::ptrace::traceme()?;
+            }
+
             Ok(())
         }
     });
@@ -152,16 +255,90 @@ pub fn run_sandbox(exec: super::SandboxExecutionConfig) -> anyhow::Result<super:
         .spawn()
         .map_err(|error| anyhow::anyhow!("failed to spawn sandbox: {error}"))?;
 
-    let exit_status = child.wait()?;
+    // Drop the command to close its handles to the terminal, so reading
+    // from the terminal ends when the child exits
+    drop(command);
+    let pty_output = pty.map(|pty| pty.forward_output());
+
+    let stop_proxy_forwarder = Arc::new(AtomicBool::new(false));
+    let proxy_forwarder = match network_proxy_channel {
+        Some((network_proxy, parent_channel, child_channel)) => {
+            // Close our copy of the child's end, so receiving fails instead
+            // of hanging if the child never sends the listener
+            drop(child_channel);
+            let listener = network_proxy::receive_listener(&parent_channel)?;
+            let forwarder = network_proxy::forward_connections(
+                listener,
+                &network_proxy.host_socket_name,
+                stop_proxy_forwarder.clone(),
+            )?;
+            Some(forwarder)
+        }
+        None => None,
+    };
+
+    let exit_status = match &exec.trace_path {
+        Some(trace_path) => {
+            let (exit_status, accesses) =
+                ptrace::trace_child(nix::unistd::Pid::from_raw(child.pid()))?;
+
+            let trace_file = std::fs::File::create(trace_path).map_err(|error| {
+                anyhow::anyhow!(
+                    "failed to create trace file {}: {error}",
+                    trace_path.display()
+                )
+            })?;
+            serde_json::to_writer(std::io::BufWriter::new(trace_file), &accesses)?;
 
-    let exit_status = match exit_status {
-        unshare::ExitStatus::Exited(code) => ExitStatus::Code(code),
-        unshare::ExitStatus::Signaled(signal, _) => ExitStatus::Signal(signal as i32),
+            exit_status
+        }
+        None => match child.wait()? {
+            unshare::ExitStatus::Exited(code) => ExitStatus::Code(code),
+            unshare::ExitStatus::Signaled(signal, _) => ExitStatus::Signal(signal as i32),
+        },
     };
 
+    stop_proxy_forwarder.store(true, Ordering::Relaxed);
+    if let Some(proxy_forwarder) = proxy_forwarder {
+        proxy_forwarder
+            .join()
+            .map_err(|_| anyhow::anyhow!("network proxy forwarder thread panicked"))?;
+    }
+
+    if let Some(pty_output) = pty_output {
+        pty_output
+            .join()
+            .map_err(|_| anyhow::anyhow!("pseudo-terminal output thread panicked"))??;
+    }
+
     Ok(exit_status)
 }
 
+/// Get the current user's subordinate user and group ID ranges. Returns
+/// `None` unless both are configured.
+fn subordinate_id_ranges(
+    host_uid: u32,
+) -> Option<(super::subid::SubidRange, super::subid::SubidRange)> {
+    let user_name = nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(host_uid))
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .unwrap_or_default();
+    let find_range = |path: &str| {
+        let contents = std::fs::read_to_string(path).ok()?;
+        super::subid::find_subid_range(&contents, &user_name, host_uid)
+    };
+
+    Some((find_range("/etc/subuid")?, find_range("/etc/subgid")?))
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_else(|| "/usr/bin:/bin".into());
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
 fn build_template(
     template: &SandboxTemplate,
     host_paths: &mut HashMap<PathBuf, SandboxPathOptions>,
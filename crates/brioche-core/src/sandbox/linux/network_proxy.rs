@@ -0,0 +1,157 @@
+use std::{
+    io::{IoSlice, IoSliceMut},
+    net::{Ipv4Addr, Shutdown, TcpListener, TcpStream},
+    os::{
+        fd::{AsRawFd as _, FromRawFd as _, OwnedFd, RawFd},
+        linux::net::SocketAddrExt as _,
+        unix::net::{SocketAddr, UnixStream},
+    },
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags};
+
+/// How often the forwarder checks if it should stop while waiting for
+/// connections.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+nix::ioctl_read_bad!(
+    get_interface_flags,
+    nix::libc::SIOCGIFFLAGS,
+    nix::libc::ifreq
+);
+nix::ioctl_write_ptr_bad!(
+    set_interface_flags,
+    nix::libc::SIOCSIFFLAGS,
+    nix::libc::ifreq
+);
+
+/// Listen for connections to the proxy from within the sandbox. Called in
+/// the sandboxed child before it execs, once it's in its own network
+/// namespace. The listener is sent back to the parent over `channel`,
+/// since the parent can't create sockets in the child's namespace.
+pub fn listen_in_sandbox(channel: &UnixStream, guest_port: u16) -> std::io::Result<()> {
+    set_loopback_up()?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, guest_port))?;
+    let fds = [listener.as_raw_fd()];
+    nix::sys::socket::sendmsg::<()>(
+        channel.as_raw_fd(),
+        &[IoSlice::new(&[0])],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Receive the listener sent by [`listen_in_sandbox`].
+pub fn receive_listener(channel: &UnixStream) -> anyhow::Result<TcpListener> {
+    let mut buffer = [0; 1];
+    let mut iov = [IoSliceMut::new(&mut buffer)];
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; 1]);
+    let message = nix::sys::socket::recvmsg::<()>(
+        channel.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buffer),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )?;
+
+    let fd = message
+        .cmsgs()
+        .find_map(|cmsg| match cmsg {
+            ControlMessageOwned::ScmRights(fds) => fds.first().copied(),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("sandbox did not send network proxy listener"))?;
+
+    // SAFETY: The fd was just received, so nothing else owns it
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    Ok(TcpListener::from(fd))
+}
+
+/// Forward each connection from the sandbox to the proxy's abstract Unix
+/// socket on the host, until `stop` is set.
+pub fn forward_connections(
+    listener: TcpListener,
+    host_socket_name: &str,
+    stop: Arc<AtomicBool>,
+) -> anyhow::Result<std::thread::JoinHandle<()>> {
+    let host_addr = SocketAddr::from_abstract_name(host_socket_name)?;
+    listener.set_nonblocking(true)?;
+
+    let handle = std::thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            let guest_stream = match listener.accept() {
+                Ok((guest_stream, _)) => guest_stream,
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+                Err(error) => {
+                    tracing::warn!("failed to accept sandbox proxy connection: {error}");
+                    continue;
+                }
+            };
+
+            let host_addr = host_addr.clone();
+            std::thread::spawn(move || {
+                if let Err(error) = forward_connection(guest_stream, &host_addr) {
+                    tracing::debug!("failed to forward sandbox proxy connection: {error}");
+                }
+            });
+        }
+    });
+
+    Ok(handle)
+}
+
+fn forward_connection(guest_stream: TcpStream, host_addr: &SocketAddr) -> std::io::Result<()> {
+    guest_stream.set_nonblocking(false)?;
+    let host_stream = UnixStream::connect_addr(host_addr)?;
+
+    let mut guest_reader = guest_stream.try_clone()?;
+    let mut host_writer = host_stream.try_clone()?;
+    let upload = std::thread::spawn(move || {
+        let result = std::io::copy(&mut guest_reader, &mut host_writer);
+        let _ = host_writer.shutdown(Shutdown::Write);
+        result
+    });
+
+    let mut host_reader = host_stream;
+    let mut guest_writer = guest_stream;
+    std::io::copy(&mut host_reader, &mut guest_writer)?;
+    let _ = guest_writer.shutdown(Shutdown::Write);
+
+    upload
+        .join()
+        .map_err(|_| std::io::Error::other("proxy upload thread panicked"))??;
+
+    Ok(())
+}
+
+/// Bring up the loopback interface, which starts down in a new network
+/// namespace.
+fn set_loopback_up() -> std::io::Result<()> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+
+    // SAFETY: `ifreq` is a plain C struct, so it's valid when zeroed
+    let mut request: nix::libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dest, src) in request.ifr_name.iter_mut().zip(b"lo\0") {
+        *dest = *src as nix::libc::c_char;
+    }
+
+    // SAFETY: The request is a valid `ifreq` naming the loopback interface
+    unsafe {
+        get_interface_flags(socket.as_raw_fd(), &mut request)?;
+        request.ifr_ifru.ifru_flags |= nix::libc::IFF_UP as nix::libc::c_short;
+        set_interface_flags(socket.as_raw_fd(), &request)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use std::{collections::HashMap, os::unix::fs::FileExt as _};
+
+use bstr::BString;
+use nix::{
+    errno::Errno,
+    sys::{
+        ptrace,
+        signal::Signal,
+        wait::{WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+
+use crate::sandbox::{
+    input_trace::{FileAccess, FileAccesses},
+    ExitStatus,
+};
+
+const MAX_PATH_LENGTH: usize = 4096;
+
+pub fn ensure_supported() -> anyhow::Result<()> {
+    anyhow::ensure!(
+        cfg!(target_arch = "x86_64"),
+        "tracing processes is only supported on x86-64"
+    );
+    Ok(())
+}
+
+/// A syscall that was entered but hasn't returned yet.
+struct PendingSyscall {
+    path: Option<BString>,
+}
+
+/// Trace a child that called `PTRACE_TRACEME` before exec'ing, along with
+/// all of its descendants, until the child exits. Records each absolute
+/// path passed to a syscall that opens, stats, or executes a file.
+pub fn trace_child(child: Pid) -> anyhow::Result<(ExitStatus, Vec<FileAccess>)> {
+    // The child stops with a SIGTRAP once it execs
+    match nix::sys::wait::waitpid(child, None)? {
+        WaitStatus::Stopped(_, Signal::SIGTRAP) => {}
+        WaitStatus::Exited(_, code) => {
+            return Ok((ExitStatus::Code(code as i8), vec![]));
+        }
+        WaitStatus::Signaled(_, signal, _) => {
+            return Ok((ExitStatus::Signal(signal as i32), vec![]));
+        }
+        status => {
+            anyhow::bail!("unexpected status from traced process: {status:?}");
+        }
+    }
+
+    ptrace::setoptions(
+        child,
+        ptrace::Options::PTRACE_O_TRACESYSGOOD
+            | ptrace::Options::PTRACE_O_TRACECLONE
+            | ptrace::Options::PTRACE_O_TRACEFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORK
+            | ptrace::Options::PTRACE_O_TRACEEXEC
+            | ptrace::Options::PTRACE_O_EXITKILL,
+    )?;
+    ptrace::syscall(child, None)?;
+
+    let mut accesses = FileAccesses::default();
+    let mut pending: HashMap<Pid, Option<PendingSyscall>> = HashMap::from_iter([(child, None)]);
+    let mut exit_status = None;
+
+    loop {
+        let status = match nix::sys::wait::waitpid(None, Some(WaitPidFlag::__WALL)) {
+            Ok(status) => status,
+            Err(Errno::ECHILD) => break,
+            Err(Errno::EINTR) => continue,
+            Err(error) => return Err(error.into()),
+        };
+
+        match status {
+            WaitStatus::Exited(pid, code) => {
+                pending.remove(&pid);
+                if pid == child {
+                    exit_status = Some(ExitStatus::Code(code as i8));
+                }
+            }
+            WaitStatus::Signaled(pid, signal, _) => {
+                pending.remove(&pid);
+                if pid == child {
+                    exit_status = Some(ExitStatus::Signal(signal as i32));
+                }
+            }
+            WaitStatus::PtraceSyscall(pid) => {
+                let syscall = pending.entry(pid).or_default();
+                match syscall.take() {
+                    None => {
+                        *syscall = Some(PendingSyscall {
+                            path: syscall_path(pid).unwrap_or_default(),
+                        });
+                    }
+                    Some(PendingSyscall { path }) => {
+                        // Reading the result fails if the process was killed
+                        // mid-syscall
+                        if let (Some(path), Ok(error)) = (path, syscall_error(pid)) {
+                            accesses.record(path, error);
+                        }
+                    }
+                }
+                resume(pid, None);
+            }
+            WaitStatus::PtraceEvent(pid, _, _) => {
+                // Clone, fork, and exec events are only traced so new
+                // processes get traced too
+                resume(pid, None);
+            }
+            WaitStatus::Stopped(pid, Signal::SIGSTOP) if !pending.contains_key(&pid) => {
+                // New tracees start with a SIGSTOP, which shouldn't be
+                // passed on
+                pending.insert(pid, None);
+                resume(pid, None);
+            }
+            WaitStatus::Stopped(pid, signal) => {
+                resume(pid, Some(signal));
+            }
+            WaitStatus::Continued(_) | WaitStatus::StillAlive => {}
+        }
+    }
+
+    let exit_status = exit_status.ok_or_else(|| anyhow::anyhow!("traced process never exited"))?;
+    Ok((exit_status, accesses.into_accesses()))
+}
+
+fn resume(pid: Pid, signal: Option<Signal>) {
+    // The tracee may have been killed since it stopped, in which case
+    // its exit gets reported by the next wait
+    let _ = ptrace::syscall(pid, signal);
+}
+
+/// Read the path argument of the syscall the process just entered, if
+/// it's one that accesses a file by an absolute path.
+#[cfg(target_arch = "x86_64")]
+fn syscall_path(pid: Pid) -> anyhow::Result<Option<BString>> {
+    let regs = ptrace::getregs(pid)?;
+    let path_arg = match regs.orig_rax as i64 {
+        nix::libc::SYS_open
+        | nix::libc::SYS_stat
+        | nix::libc::SYS_lstat
+        | nix::libc::SYS_access
+        | nix::libc::SYS_readlink
+        | nix::libc::SYS_execve => regs.rdi,
+        nix::libc::SYS_openat
+        | nix::libc::SYS_openat2
+        | nix::libc::SYS_newfstatat
+        | nix::libc::SYS_statx
+        | nix::libc::SYS_faccessat
+        | nix::libc::SYS_faccessat2
+        | nix::libc::SYS_readlinkat
+        | nix::libc::SYS_execveat => regs.rsi,
+        _ => return Ok(None),
+    };
+
+    let path = read_string(pid, path_arg)?;
+    if path.starts_with(b"/") {
+        Ok(Some(path))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn syscall_path(_pid: Pid) -> anyhow::Result<Option<BString>> {
+    anyhow::bail!("tracing processes is only supported on x86-64");
+}
+
+/// Get the errno returned by the syscall the process just exited, if it
+/// failed.
+#[cfg(target_arch = "x86_64")]
+fn syscall_error(pid: Pid) -> anyhow::Result<Option<i32>> {
+    let regs = ptrace::getregs(pid)?;
+    let result = regs.rax as i64;
+    if (-4095..0).contains(&result) {
+        Ok(Some(-result as i32))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn syscall_error(_pid: Pid) -> anyhow::Result<Option<i32>> {
+    anyhow::bail!("tracing processes is only supported on x86-64");
+}
+
+/// Read a NUL-terminated string from the process's memory.
+fn read_string(pid: Pid, address: u64) -> anyhow::Result<BString> {
+    let mem = std::fs::File::open(format!("/proc/{pid}/mem"))?;
+
+    let mut result = vec![];
+    let mut buffer = [0; 256];
+    while result.len() < MAX_PATH_LENGTH {
+        let offset = address + result.len() as u64;
+        let length = mem.read_at(&mut buffer, offset)?;
+        anyhow::ensure!(length > 0, "failed to read memory of process {pid}");
+
+        let chunk = &buffer[..length];
+        match chunk.iter().position(|&byte| byte == 0) {
+            Some(end) => {
+                result.extend_from_slice(&chunk[..end]);
+                return Ok(result.into());
+            }
+            None => {
+                result.extend_from_slice(chunk);
+            }
+        }
+    }
+
+    anyhow::bail!("path read from process {pid} is too long");
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::{Read as _, Write as _},
+    os::fd::{AsRawFd as _, OwnedFd, RawFd},
+};
+
+use crate::sandbox::terminal::TerminalOutputNormalizer;
+
+/// A fixed terminal size, so tools that format their output to fit the
+/// terminal produce the same output on every machine.
+const TERMINAL_ROWS: u16 = 24;
+const TERMINAL_COLUMNS: u16 = 80;
+
+pub struct Pty {
+    master: std::fs::File,
+    slave: OwnedFd,
+}
+
+impl Pty {
+    pub fn open() -> anyhow::Result<Self> {
+        let winsize = nix::pty::Winsize {
+            ws_row: TERMINAL_ROWS,
+            ws_col: TERMINAL_COLUMNS,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = nix::pty::openpty(&winsize, None::<&nix::sys::termios::Termios>)
+            .map_err(|error| anyhow::anyhow!("failed to open pseudo-terminal: {error}"))?;
+        Ok(Self {
+            master: std::fs::File::from(pty.master),
+            slave: pty.slave,
+        })
+    }
+
+    /// Open another handle to the terminal, for the child's stdout or
+    /// stderr.
+    pub fn slave_file(&self) -> anyhow::Result<std::fs::File> {
+        Ok(std::fs::File::from(self.slave.try_clone()?))
+    }
+
+    pub fn slave_raw_fd(&self) -> RawFd {
+        self.slave.as_raw_fd()
+    }
+
+    /// Close this process's handle to the terminal, then write everything
+    /// the child writes to it to stdout (with control sequences stripped)
+    /// from a background thread. The thread finishes once every process
+    /// holding the terminal has exited.
+    pub fn forward_output(self) -> std::thread::JoinHandle<anyhow::Result<()>> {
+        let Self { mut master, slave } = self;
+        drop(slave);
+
+        std::thread::spawn(move || {
+            let mut normalizer = TerminalOutputNormalizer::default();
+            let mut buffer = [0; 4096];
+            let mut normalized = vec![];
+            let mut stdout = std::io::stdout().lock();
+            loop {
+                let length = match master.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(length) => length,
+                    // Reading fails with EIO once the terminal is closed
+                    Err(error) if error.raw_os_error() == Some(nix::libc::EIO) => break,
+                    Err(error) => return Err(error.into()),
+                };
+
+                normalized.clear();
+                normalizer.normalize(&buffer[..length], &mut normalized);
+                stdout.write_all(&normalized)?;
+                stdout.flush()?;
+            }
+
+            normalized.clear();
+            normalizer.finish(&mut normalized);
+            stdout.write_all(&normalized)?;
+            stdout.flush()?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Make the terminal the controlling terminal of the current process, so
+/// the process can also open it as `/dev/tty`. Called in the child before
+/// it execs. This is best effort: tools that only check if stdout is a
+/// terminal work either way.
+pub fn set_controlling_terminal(slave_fd: RawFd) {
+    let _ = nix::unistd::setsid();
+    unsafe {
+        nix::libc::ioctl(slave_fd, nix::libc::TIOCSCTTY, 0);
+    }
+}
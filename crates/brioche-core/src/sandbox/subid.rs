@@ -0,0 +1,67 @@
+/// A range of subordinate IDs from `/etc/subuid` or `/etc/subgid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubidRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+/// A range of IDs within the sandbox, and the host IDs they map to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdMapping {
+    pub inside: u32,
+    pub outside: u32,
+    pub count: u32,
+}
+
+/// Find the first subordinate ID range for a user in the contents of
+/// `/etc/subuid` or `/etc/subgid`. Entries can list the user either by
+/// name or by ID. Malformed lines are skipped.
+pub fn find_subid_range(contents: &str, user_name: &str, user_id: u32) -> Option<SubidRange> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.trim().split(':');
+        let user = fields.next()?;
+        let start = fields.next()?.parse().ok()?;
+        let count = fields.next()?.parse().ok()?;
+
+        let matches_user = user == user_name || user.parse() == Ok(user_id);
+        (matches_user && count > 0).then_some(SubidRange { start, count })
+    })
+}
+
+/// Build the ID mappings for the sandbox. `inside_id` always maps to
+/// `host_id`. With a subordinate ID range, the IDs below and above
+/// `inside_id` are filled in from the range, in that order, so other IDs
+/// can own files within the sandbox.
+pub fn id_mappings(
+    inside_id: u32,
+    host_id: u32,
+    subid_range: Option<SubidRange>,
+) -> Vec<IdMapping> {
+    let mut mappings = vec![IdMapping {
+        inside: inside_id,
+        outside: host_id,
+        count: 1,
+    }];
+
+    if let Some(range) = subid_range {
+        let below = range.count.min(inside_id);
+        if below > 0 {
+            mappings.push(IdMapping {
+                inside: 0,
+                outside: range.start,
+                count: below,
+            });
+        }
+
+        let above = (range.count - below).min(u32::MAX - inside_id);
+        if above > 0 {
+            mappings.push(IdMapping {
+                inside: inside_id + 1,
+                outside: range.start + below,
+                count: above,
+            });
+        }
+    }
+
+    mappings
+}
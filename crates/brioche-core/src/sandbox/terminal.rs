@@ -0,0 +1,85 @@
+/// Strips terminal control sequences from the output of a process run
+/// with a pseudo-terminal, so its logs are plain text. Escape sequences
+/// can be split across reads, so the normalizer keeps state between calls
+/// and the result doesn't depend on how the output was chunked.
+///
+/// Line endings are normalized too: `\r\n` becomes `\n`, and a lone `\r`
+/// (as used to redraw progress bars) starts a new line.
+#[derive(Debug, Default)]
+pub struct TerminalOutputNormalizer {
+    state: State,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Text,
+    CarriageReturn,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+impl TerminalOutputNormalizer {
+    pub fn normalize(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &byte in input {
+            if self.state == State::CarriageReturn {
+                output.push(b'\n');
+                self.state = State::Text;
+                if byte == b'\n' {
+                    continue;
+                }
+            }
+
+            self.state = match self.state {
+                State::Text | State::CarriageReturn => match byte {
+                    0x1b => State::Escape,
+                    b'\r' => State::CarriageReturn,
+                    b'\n' | b'\t' => {
+                        output.push(byte);
+                        State::Text
+                    }
+                    // Other control characters, like bells and backspaces
+                    0x00..=0x1f | 0x7f => State::Text,
+                    _ => {
+                        output.push(byte);
+                        State::Text
+                    }
+                },
+                State::Escape => match byte {
+                    b'[' => State::Csi,
+                    b']' => State::Osc,
+                    0x20..=0x2f => State::EscapeIntermediate,
+                    _ => State::Text,
+                },
+                State::EscapeIntermediate => match byte {
+                    0x20..=0x2f => State::EscapeIntermediate,
+                    _ => State::Text,
+                },
+                State::Csi => match byte {
+                    0x40..=0x7e => State::Text,
+                    _ => State::Csi,
+                },
+                State::Osc => match byte {
+                    0x07 => State::Text,
+                    0x1b => State::OscEscape,
+                    _ => State::Osc,
+                },
+                State::OscEscape => match byte {
+                    b'\\' => State::Text,
+                    _ => State::Osc,
+                },
+            };
+        }
+    }
+
+    /// Write anything still pending once the output has ended.
+    pub fn finish(&mut self, output: &mut Vec<u8>) {
+        if self.state == State::CarriageReturn {
+            output.push(b'\n');
+        }
+        self.state = State::Text;
+    }
+}
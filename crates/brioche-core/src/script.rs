@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     rc::Rc,
     sync::Arc,
 };
@@ -12,7 +12,10 @@ use specifier::BriocheModuleSpecifier;
 
 use crate::{
     bake::BakeScope,
-    project::analyze::{StaticInclude, StaticQuery},
+    project::{
+        analyze::{StaticInclude, StaticQuery},
+        env::EnvValue,
+    },
 };
 
 use super::{
@@ -23,13 +26,16 @@ use super::{
     Brioche,
 };
 
+pub mod api_version;
 pub mod check;
 mod compiler_host;
 pub mod evaluate;
 pub mod format;
 mod js;
 pub mod lsp;
+pub mod read_access;
 pub mod specifier;
+pub mod unused;
 
 #[derive(Clone)]
 struct BriocheModuleLoader {
@@ -150,22 +156,98 @@ struct ModuleSource {
     pub source_map: Vec<u8>,
 }
 
+/// Controls when a script evaluation should be terminated, either because
+/// it ran past its deadline or because it was cancelled. Async ops race
+/// their work against this, so that a terminated evaluation doesn't leave
+/// ops running in the background (e.g. holding onto blob permits).
+#[derive(Debug, Clone, Default)]
+pub struct EvaluationControl {
+    pub deadline: Option<tokio::time::Instant>,
+    pub cancellation: tokio_util::sync::CancellationToken,
+}
+
+impl EvaluationControl {
+    pub fn with_timeout(timeout: std::time::Duration) -> Self {
+        Self {
+            deadline: Some(tokio::time::Instant::now() + timeout),
+            cancellation: tokio_util::sync::CancellationToken::new(),
+        }
+    }
+
+    /// Wait until the evaluation should be terminated. Reaching the
+    /// deadline also cancels the cancellation token, so everything else
+    /// watching the token stops too.
+    pub async fn terminated(&self) -> EvaluationTerminated {
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            _ = self.cancellation.cancelled() => EvaluationTerminated::Cancelled,
+            _ = deadline => {
+                self.cancellation.cancel();
+                EvaluationTerminated::TimedOut
+            }
+        }
+    }
+
+    /// Run a future to completion, unless the evaluation is terminated
+    /// first. If the evaluation is terminated, the future is dropped.
+    /// Termination is checked before polling the future, so nothing
+    /// starts once the evaluation has already been terminated.
+    pub async fn run<T>(
+        &self,
+        future: impl std::future::Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        tokio::select! {
+            biased;
+            terminated = self.terminated() => Err(terminated.into()),
+            result = future => result,
+        }
+    }
+
+    fn child(&self) -> Self {
+        Self {
+            deadline: self.deadline,
+            cancellation: self.cancellation.child_token(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum EvaluationTerminated {
+    #[error("script evaluation was cancelled")]
+    Cancelled,
+    #[error("script evaluation timed out")]
+    TimedOut,
+}
+
 deno_core::extension!(brioche_rt,
     ops = [
         op_brioche_bake_all,
         op_brioche_create_proxy,
         op_brioche_read_blob,
+        op_brioche_read_blob_bytes,
         op_brioche_get_static,
+        op_brioche_update_hashes_enabled,
+        op_brioche_project_env,
+        op_brioche_api_version,
+        op_brioche_supported_api_versions,
     ],
     options = {
         brioche: Brioche,
         projects: Projects,
         bake_scope: BakeScope,
+        control: EvaluationControl,
     },
     state = |state, options| {
         state.put(options.brioche);
         state.put(options.projects);
         state.put(options.bake_scope);
+        state.put(options.control);
     },
 );
 
@@ -188,13 +270,18 @@ pub async fn op_brioche_bake_all(
             .context("failed to get bake scope")?
             .clone()
     };
+    let control = evaluation_control(&state)?;
 
-    let mut results = vec![];
-    for recipe in recipes {
-        let result = super::bake::bake(&brioche, recipe, &bake_scope).await?;
-        results.push(result.value);
-    }
-    Ok(results)
+    control
+        .run(async {
+            let mut results = vec![];
+            for recipe in recipes {
+                let result = super::bake::bake(&brioche, recipe, &bake_scope).await?;
+                results.push(result.value);
+            }
+            Ok(results)
+        })
+        .await
 }
 
 #[deno_core::op]
@@ -210,16 +297,36 @@ pub async fn op_brioche_create_proxy(
             .clone()
     };
 
-    let result = super::bake::create_proxy(&brioche, recipe).await?;
+    let control = evaluation_control(&state)?;
+
+    let result = control
+        .run(super::bake::create_proxy(&brioche, recipe))
+        .await?;
     Ok(result)
 }
 
-// TODO: Return a Uint8Array instead of tick-encoding
+/// Read a blob as a tick-encoded string. This is a shim for projects on
+/// API version 1, newer projects should use `op_brioche_read_blob_bytes`.
 #[deno_core::op]
 pub async fn op_brioche_read_blob(
     state: Rc<RefCell<OpState>>,
     blob_hash: BlobHash,
 ) -> anyhow::Result<crate::encoding::TickEncode<Vec<u8>>> {
+    let bytes = read_blob(&state, blob_hash).await?;
+    Ok(crate::encoding::TickEncode(bytes))
+}
+
+/// Read a blob as a `Uint8Array`. Added in API version 2.
+#[deno_core::op]
+pub async fn op_brioche_read_blob_bytes(
+    state: Rc<RefCell<OpState>>,
+    blob_hash: BlobHash,
+) -> anyhow::Result<deno_core::ToJsBuffer> {
+    let bytes = read_blob(&state, blob_hash).await?;
+    Ok(bytes.into())
+}
+
+async fn read_blob(state: &Rc<RefCell<OpState>>, blob_hash: BlobHash) -> anyhow::Result<Vec<u8>> {
     let brioche = {
         let state = state.try_borrow()?;
         state
@@ -228,13 +335,19 @@ pub async fn op_brioche_read_blob(
             .clone()
     };
 
-    let permit = crate::blob::get_save_blob_permit().await?;
-    let path = crate::blob::blob_path(&brioche, permit, blob_hash).await?;
-    let bytes = tokio::fs::read(path)
-        .await
-        .with_context(|| format!("failed to read blob {blob_hash}"))?;
+    let control = evaluation_control(state)?;
 
-    Ok(crate::encoding::TickEncode(bytes))
+    // Any permit used to fetch the blob is dropped along with the future
+    // if the evaluation is terminated while waiting
+    control
+        .run(async {
+            let path = crate::blob::blob_path(&brioche, blob_hash).await?;
+            let bytes = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("failed to read blob {blob_hash}"))?;
+            anyhow::Ok(bytes)
+        })
+        .await
 }
 
 #[deno_core::op]
@@ -272,6 +385,99 @@ pub async fn op_brioche_get_static(
                 format!("failed to resolve Brioche.glob({patterns}) from {specifier}, were the patterns passed in as string literals?")
             }
         })?;
-    let recipe = crate::recipe::get_recipe(&brioche, recipe_hash).await?;
+    let control = evaluation_control(&state)?;
+    let recipe = control
+        .run(crate::recipe::get_recipe(&brioche, recipe_hash))
+        .await?;
     Ok(recipe)
 }
+
+fn evaluation_control(state: &Rc<RefCell<OpState>>) -> anyhow::Result<EvaluationControl> {
+    let state = state.try_borrow()?;
+    let control = state
+        .try_borrow::<EvaluationControl>()
+        .context("failed to get evaluation control")?
+        .clone();
+    Ok(control)
+}
+
+/// Returns true when Brioche is updating mismatched download hashes (with
+/// `--update-hashes`), so scripts can tell that a hash mismatch will be
+/// fixed up rather than failing the build.
+#[deno_core::op]
+pub fn op_brioche_update_hashes_enabled(state: Rc<RefCell<OpState>>) -> anyhow::Result<bool> {
+    let state = state.try_borrow()?;
+    let brioche = state
+        .try_borrow::<Brioche>()
+        .context("failed to get brioche instance")?;
+    Ok(brioche.hash_updates.is_some())
+}
+
+/// Returns the values of the environment variables declared in the
+/// `brioche.toml` of the project containing the module at `url`.
+#[deno_core::op]
+pub fn op_brioche_project_env(
+    state: Rc<RefCell<OpState>>,
+    url: String,
+) -> anyhow::Result<BTreeMap<String, EnvValue>> {
+    let state = state.try_borrow()?;
+    let brioche = state
+        .try_borrow::<Brioche>()
+        .context("failed to get brioche instance")?;
+    let projects = state
+        .try_borrow::<Projects>()
+        .context("failed to get projects instance")?;
+
+    let specifier: BriocheModuleSpecifier = url.parse()?;
+    let BriocheModuleSpecifier::File { path } = &specifier else {
+        anyhow::bail!("could not get project environment for specifier {specifier}");
+    };
+    let project_hash = projects
+        .find_containing_project(path)?
+        .with_context(|| format!("project not found for specifier {specifier}"))?;
+    let project = projects.project(project_hash)?;
+
+    let values =
+        crate::project::env::resolve_env(&project.env, |name| brioche.project_env_var(name))
+            .with_context(|| format!("failed to get environment for project {project_hash}"))?;
+    Ok(values)
+}
+
+/// Returns the script API version negotiated for the project containing
+/// the module at `url`. See [`api_version::CURRENT_API_VERSION`].
+#[deno_core::op]
+pub fn op_brioche_api_version(state: Rc<RefCell<OpState>>, url: String) -> anyhow::Result<u32> {
+    let state = state.try_borrow()?;
+    let projects = state
+        .try_borrow::<Projects>()
+        .context("failed to get projects instance")?;
+
+    let specifier: BriocheModuleSpecifier = url.parse()?;
+    let BriocheModuleSpecifier::File { path } = &specifier else {
+        // Modules outside of a project (like the runtime itself) always
+        // get the current version
+        return Ok(api_version::CURRENT_API_VERSION);
+    };
+    let project_hash = projects
+        .find_containing_project(path)?
+        .with_context(|| format!("project not found for specifier {specifier}"))?;
+    let project = projects.project(project_hash)?;
+    Ok(project.api_version())
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedApiVersions {
+    pub min: u32,
+    pub current: u32,
+}
+
+/// Returns the range of script API versions this version of Brioche
+/// supports.
+#[deno_core::op]
+pub fn op_brioche_supported_api_versions() -> SupportedApiVersions {
+    SupportedApiVersions {
+        min: api_version::MIN_API_VERSION,
+        current: api_version::CURRENT_API_VERSION,
+    }
+}
@@ -0,0 +1,39 @@
+/// The oldest API version this version of Brioche still has shims for.
+pub const MIN_API_VERSION: u32 = 1;
+
+/// The newest API version this version of Brioche supports. A project
+/// declares the version it was written against with `api_version` in its
+/// `brioche.toml`. Ops that change in an incompatible way get a new name,
+/// and the old op is kept as a shim for projects on older versions.
+/// Scripts can call `op_brioche_api_version` to get the version negotiated
+/// for their project.
+///
+/// - Version 1: the original op surface. `op_brioche_read_blob` returns
+///   the blob's contents as a tick-encoded string.
+/// - Version 2: adds `op_brioche_read_blob_bytes`, which returns the
+///   blob's contents as a `Uint8Array`.
+pub const CURRENT_API_VERSION: u32 = 2;
+
+/// The API version used for projects that don't declare one, which were
+/// written before the API was versioned.
+pub const DEFAULT_API_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum UnsupportedApiVersion {
+    #[error("project requires API version {version}, but this version of Brioche only supports up to {CURRENT_API_VERSION} (try updating Brioche)")]
+    TooNew { version: u32 },
+    #[error("project requires API version {version}, which is no longer supported (the oldest supported version is {MIN_API_VERSION})")]
+    TooOld { version: u32 },
+}
+
+/// Check that a project's declared API version is one this version of
+/// Brioche can run.
+pub fn validate_api_version(version: u32) -> Result<(), UnsupportedApiVersion> {
+    if version > CURRENT_API_VERSION {
+        Err(UnsupportedApiVersion::TooNew { version })
+    } else if version < MIN_API_VERSION {
+        Err(UnsupportedApiVersion::TooOld { version })
+    } else {
+        Ok(())
+    }
+}
@@ -76,6 +76,13 @@ impl BriocheCompilerHost {
             let contents = match contents {
                 Some(contents) => contents,
                 None => {
+                    if let BriocheModuleSpecifier::File { path } = &specifier {
+                        self.brioche
+                            .script_read_access
+                            .ensure_can_read(&self.projects, path)
+                            .await?;
+                    }
+
                     let contents =
                         super::specifier::load_specifier_contents(&self.brioche.vfs, &specifier)
                             .await?;
@@ -1,22 +1,104 @@
-use std::rc::Rc;
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    rc::Rc,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::Context as _;
+use sqlx::Acquire as _;
 
 use crate::{
     bake::BakeScope,
-    project::{ProjectHash, Projects},
-    recipe::{Recipe, WithMeta},
+    project::{env::EnvValue, ProjectHash, Projects},
+    recipe::{Meta, Recipe, WithMeta},
     Brioche,
 };
 
-use super::BriocheModuleLoader;
+use super::{BriocheModuleLoader, EvaluationControl};
 
-#[tracing::instrument(skip(brioche, projects, project_hash), fields(%project_hash), err)]
 pub async fn evaluate(
     brioche: &Brioche,
     projects: &Projects,
     project_hash: ProjectHash,
     export: &str,
+) -> anyhow::Result<WithMeta<Recipe>> {
+    evaluate_with_control(
+        brioche,
+        projects,
+        project_hash,
+        export,
+        &EvaluationControl::default(),
+    )
+    .await
+}
+
+/// Evaluate an export from a project, stopping early if the evaluation
+/// runs past the deadline or is cancelled. Any ops still running when
+/// the evaluation stops are dropped, and any JS code still running gets
+/// terminated.
+///
+/// Successful evaluations are cached, keyed by the project hash (which
+/// covers the module graph), the export, the parameters scripts can read,
+/// and the version of Brioche and its runtime. Evaluating an unchanged
+/// project again skips V8 entirely.
+#[tracing::instrument(skip(brioche, projects, project_hash, control), fields(%project_hash), err)]
+pub async fn evaluate_with_control(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+    export: &str,
+    control: &EvaluationControl,
+) -> anyhow::Result<WithMeta<Recipe>> {
+    let parameters_hash = evaluation_parameters_hash(brioche, projects, project_hash)?;
+    if let Some(parameters_hash) = &parameters_hash {
+        let cached = get_cached_evaluation(brioche, project_hash, export, parameters_hash).await?;
+        if let Some(cached) = cached {
+            tracing::debug!(%project_hash, %export, recipe_hash = %cached.hash(), "using cached evaluation");
+            return Ok(cached);
+        }
+    }
+
+    let recipe = evaluate_uncached(brioche, projects, project_hash, export, control).await?;
+
+    // A read-only store can't cache the evaluation, but it's still usable
+    if let Some(parameters_hash) = &parameters_hash {
+        if !brioche.read_only {
+            save_cached_evaluation(brioche, project_hash, export, parameters_hash, &recipe).await?;
+        }
+    }
+
+    Ok(recipe)
+}
+
+async fn evaluate_uncached(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+    export: &str,
+    control: &EvaluationControl,
+) -> anyhow::Result<WithMeta<Recipe>> {
+    // Use a child token, so reaching the deadline or finishing the
+    // evaluation doesn't cancel the caller's token
+    let control = control.child();
+    let _cancel_on_drop = control.cancellation.clone().drop_guard();
+
+    control
+        .run(evaluate_inner(
+            brioche,
+            projects,
+            project_hash,
+            export,
+            &control,
+        ))
+        .await
+}
+
+async fn evaluate_inner(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+    export: &str,
+    control: &EvaluationControl,
 ) -> anyhow::Result<WithMeta<Recipe>> {
     let module_loader = BriocheModuleLoader::new(brioche, projects);
     let bake_scope = BakeScope::Project {
@@ -27,12 +109,29 @@ pub async fn evaluate(
         module_loader: Some(Rc::new(module_loader.clone())),
         source_map_getter: Some(Box::new(module_loader.clone())),
         extensions: vec![
-            super::brioche_rt::init_ops(brioche.clone(), projects.clone(), bake_scope),
+            super::brioche_rt::init_ops(
+                brioche.clone(),
+                projects.clone(),
+                bake_scope,
+                control.clone(),
+            ),
             super::js::brioche_js::init_ops(),
         ],
         ..Default::default()
     });
 
+    // Terminate any running JS code once the evaluation is terminated.
+    // Ops and the event loop are already stopped by `control.run`, but
+    // JS code that never yields would block forever otherwise
+    let isolate_handle = js_runtime.v8_isolate().thread_safe_handle();
+    let _terminate_task = AbortOnDrop(tokio::spawn({
+        let control = control.clone();
+        async move {
+            control.terminated().await;
+            isolate_handle.terminate_execution();
+        }
+    }));
+
     js_runtime.execute_script_static(
         "[brioche_init]",
         r#"
@@ -141,3 +240,185 @@ pub async fn evaluate(
 
     Ok(recipe)
 }
+
+/// Everything outside of the project's contents that a script can read
+/// during evaluation, which needs to be part of the evaluation cache key.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EvaluationParameters {
+    /// The declared environment variables of each project in the
+    /// dependency graph, keyed by project hash.
+    env: BTreeMap<String, BTreeMap<String, EnvValue>>,
+    update_hashes: bool,
+
+    /// The version of Brioche and a hash of the runtime, since a different
+    /// runtime or set of ops can evaluate the same project differently.
+    brioche_version: &'static str,
+    runtime_hash: &'static str,
+}
+
+/// Hash the parameters for evaluating a project. Returns `None` if the
+/// evaluation shouldn't be cached, e.g. because a required environment
+/// variable isn't set (so evaluating should fail with a proper error), or
+/// because bakes are mocked.
+fn evaluation_parameters_hash(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<Option<String>> {
+    if brioche.bake_mocks.is_some() {
+        return Ok(None);
+    }
+
+    let mut env = BTreeMap::new();
+    let mut visited = HashSet::new();
+    let mut unvisited = VecDeque::from_iter([project_hash]);
+    while let Some(project_hash) = unvisited.pop_front() {
+        if !visited.insert(project_hash) {
+            continue;
+        }
+
+        let project = projects.project(project_hash)?;
+        unvisited.extend(project.dependency_hashes());
+
+        if project.env.is_empty() {
+            continue;
+        }
+
+        let values =
+            crate::project::env::resolve_env(&project.env, |name| brioche.project_env_var(name));
+        let Ok(values) = values else {
+            return Ok(None);
+        };
+        env.insert(project_hash.to_string(), values);
+    }
+
+    let parameters = EvaluationParameters {
+        env,
+        update_hashes: brioche.hash_updates.is_some(),
+        brioche_version: env!("CARGO_PKG_VERSION"),
+        runtime_hash: runtime_hash(),
+    };
+    let parameters_json = serde_json::to_vec(&parameters)?;
+    Ok(Some(blake3::hash(&parameters_json).to_string()))
+}
+
+/// Hash the embedded runtime files. Dev builds can change the runtime
+/// without changing the version, so the version alone isn't enough.
+fn runtime_hash() -> &'static str {
+    static RUNTIME_HASH: OnceLock<String> = OnceLock::new();
+    RUNTIME_HASH.get_or_init(|| {
+        let mut files = super::specifier::runtime_specifiers_with_contents()
+            .map(|(specifier, contents)| (specifier.to_string(), contents))
+            .collect::<Vec<_>>();
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut hasher = blake3::Hasher::new();
+        for (specifier, contents) in files {
+            hasher.update(&(specifier.len() as u64).to_le_bytes());
+            hasher.update(specifier.as_bytes());
+            hasher.update(&(contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+        hasher.finalize().to_string()
+    })
+}
+
+async fn get_cached_evaluation(
+    brioche: &Brioche,
+    project_hash: ProjectHash,
+    export: &str,
+    parameters_hash: &str,
+) -> anyhow::Result<Option<WithMeta<Recipe>>> {
+    let record = {
+        let project_hash_value = project_hash.to_string();
+        let mut db_conn = brioche.db_conn.lock().await;
+        let mut db_transaction = db_conn.begin().await?;
+        let record = sqlx::query!(
+            r#"
+                SELECT recipe_hash, meta_json
+                FROM evaluation_cache
+                WHERE project_hash = ? AND export = ? AND parameters_hash = ?
+            "#,
+            project_hash_value,
+            export,
+            parameters_hash,
+        )
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+        db_transaction.commit().await?;
+        record
+    };
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let recipe_hash = record
+        .recipe_hash
+        .parse()
+        .context("invalid recipe hash from database")?;
+    let recipe = crate::recipe::get_recipe(brioche, recipe_hash).await?;
+
+    // The recipes created while evaluating (like proxies) could have been
+    // removed since, in which case the project needs to be evaluated again
+    let referenced = crate::references::referenced_recipes(&recipe);
+    let local = crate::references::local_recipes(brioche, referenced.iter().copied()).await?;
+    if referenced
+        .iter()
+        .any(|recipe_hash| !local.contains(recipe_hash))
+    {
+        return Ok(None);
+    }
+
+    let meta: Meta =
+        serde_json::from_str(&record.meta_json).context("invalid meta JSON from database")?;
+    Ok(Some(WithMeta::new(recipe, Arc::new(meta))))
+}
+
+async fn save_cached_evaluation(
+    brioche: &Brioche,
+    project_hash: ProjectHash,
+    export: &str,
+    parameters_hash: &str,
+    recipe: &WithMeta<Recipe>,
+) -> anyhow::Result<()> {
+    crate::recipe::save_recipes(brioche, [&recipe.value]).await?;
+
+    let project_hash_value = project_hash.to_string();
+    let recipe_hash_value = recipe.hash().to_string();
+    let meta_json = serde_json::to_string(&*recipe.meta)?;
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO evaluation_cache (
+                project_hash,
+                export,
+                parameters_hash,
+                recipe_hash,
+                meta_json
+            ) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (project_hash, export, parameters_hash) DO UPDATE SET
+                recipe_hash = excluded.recipe_hash,
+                meta_json = excluded.meta_json
+        "#,
+        project_hash_value,
+        export,
+        parameters_hash,
+        recipe_hash_value,
+        meta_json,
+    )
+    .execute(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(())
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -45,16 +45,62 @@ impl BriocheLspServer {
         &self,
         text_document: TextDocumentIdentifier,
     ) -> anyhow::Result<Vec<Diagnostic>> {
-        let diagnostics: Vec<Diagnostic> = self
+        let mut diagnostics: Vec<Diagnostic> = self
             .js_lsp
             .send(JsLspMessage::Diagnostic(DocumentDiagnosticParams {
                 identifier: None,
                 previous_result_id: None,
                 partial_result_params: Default::default(),
-                text_document,
+                text_document: text_document.clone(),
                 work_done_progress_params: Default::default(),
             }))
             .await?;
+
+        let unused_diagnostics = self.unused_diagnostics(&text_document.uri).await;
+        match unused_diagnostics {
+            Ok(unused_diagnostics) => {
+                diagnostics.extend(unused_diagnostics);
+            }
+            Err(error) => {
+                tracing::warn!("failed to find unused items: {error:#}");
+            }
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// Get hints for the exports and helpers in a document that are never
+    /// used anywhere in its project.
+    async fn unused_diagnostics(&self, uri: &url::Url) -> anyhow::Result<Vec<Diagnostic>> {
+        let Ok(BriocheModuleSpecifier::File { path: module_path }) = uri.try_into() else {
+            return Ok(vec![]);
+        };
+        let projects = &self.compiler_host.projects;
+        let Some(project_hash) = projects.find_containing_project(&module_path)? else {
+            return Ok(vec![]);
+        };
+
+        let vfs = &self.compiler_host.brioche.vfs;
+        let unused = super::unused::find_unused(vfs, projects, project_hash).await?;
+        let module_path = crate::fs_utils::logical_path(&module_path);
+        let (_, contents) = vfs.load(&module_path).await?;
+        let contents = String::from_utf8_lossy(&contents);
+
+        let diagnostics = unused
+            .into_iter()
+            .filter(|item| item.module_path == module_path)
+            .map(|item| Diagnostic {
+                range: Range {
+                    start: lsp_position(&contents, item.start),
+                    end: lsp_position(&contents, item.end),
+                },
+                severity: Some(DiagnosticSeverity::HINT),
+                source: Some("brioche".to_string()),
+                message: item.to_string(),
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                ..Default::default()
+            })
+            .collect();
         Ok(diagnostics)
     }
 }
@@ -509,6 +555,19 @@ fn lsp_uri_to_module_specifier(uri: &url::Url) -> anyhow::Result<BriocheModuleSp
     Ok(specifier)
 }
 
+/// Convert a byte offset into an LSP position, which counts columns in
+/// UTF-16 code units.
+fn lsp_position(contents: &str, offset: usize) -> Position {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    Position {
+        line: line as u32,
+        character: character as u32,
+    }
+}
+
 const TIMEOUT_DURATION: std::time::Duration = std::time::Duration::from_secs(10);
 
 struct JsLspTask {
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+use crate::project::Projects;
+
+/// Controls which files can be read while loading and evaluating project
+/// scripts. By default, only files within a loaded project directory (the
+/// project itself plus its resolved dependencies) can be read, so a module
+/// that's symlinked to e.g. `~/.ssh/id_ed25519` gets rejected.
+#[derive(Debug, Clone, Default)]
+pub struct ReadAccess {
+    /// Disable the restriction entirely and allow reading any path.
+    pub unrestricted: bool,
+
+    /// Extra paths that can be read in addition to project directories.
+    /// Each path allows reading anything nested within it.
+    pub allowed_paths: Vec<PathBuf>,
+}
+
+impl ReadAccess {
+    pub fn unrestricted() -> Self {
+        Self {
+            unrestricted: true,
+            allowed_paths: vec![],
+        }
+    }
+
+    /// Ensure that a module within the project at `project_path` can be
+    /// read. The module is allowed if its real path (after resolving
+    /// symlinks) is still within the project, or if it's within one of
+    /// the explicitly allowed paths.
+    pub async fn ensure_can_read_project_file(
+        &self,
+        project_path: &Path,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        if self.unrestricted {
+            return Ok(());
+        }
+
+        let canonical_path = canonicalize(path).await?;
+        let canonical_project_path = canonicalize(project_path).await?;
+        if canonical_path.starts_with(&canonical_project_path) {
+            return Ok(());
+        }
+
+        self.ensure_allowed(path, &canonical_path).await
+    }
+
+    /// Ensure that a module can be read. The module is allowed if its real
+    /// path is within any project that has already been loaded, or if
+    /// it's within one of the explicitly allowed paths.
+    pub async fn ensure_can_read(&self, projects: &Projects, path: &Path) -> anyhow::Result<()> {
+        if self.unrestricted {
+            return Ok(());
+        }
+
+        let canonical_path = canonicalize(path).await?;
+        if projects.find_containing_project(&canonical_path)?.is_some() {
+            return Ok(());
+        }
+
+        self.ensure_allowed(path, &canonical_path).await
+    }
+
+    async fn ensure_allowed(&self, path: &Path, canonical_path: &Path) -> anyhow::Result<()> {
+        for allowed_path in &self.allowed_paths {
+            let canonical_allowed_path = match tokio::fs::canonicalize(allowed_path).await {
+                Ok(allowed_path) => allowed_path,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    continue;
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| {
+                        format!(
+                            "failed to canonicalize allowed read path {}",
+                            allowed_path.display()
+                        )
+                    });
+                }
+            };
+
+            if canonical_path.starts_with(&canonical_allowed_path) {
+                return Ok(());
+            }
+        }
+
+        anyhow::bail!(
+            "{}: read denied, resolved path {} is outside of the project (use --allow-read to allow it)",
+            path.display(),
+            canonical_path.display(),
+        );
+    }
+}
+
+async fn canonicalize(path: &Path) -> anyhow::Result<PathBuf> {
+    tokio::fs::canonicalize(path)
+        .await
+        .with_context(|| format!("failed to canonicalize path {}", path.display()))
+}
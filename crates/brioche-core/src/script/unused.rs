@@ -0,0 +1,368 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use biome_rowan::{AstNode as _, AstNodeList as _, AstSeparatedList as _, TextRange};
+
+use crate::{
+    project::{ProjectHash, Projects},
+    vfs::Vfs,
+};
+
+use super::specifier::{BriocheImportSpecifier, BriocheLocalImportSpecifier};
+
+/// An export or top-level helper that isn't referenced anywhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnusedItem {
+    pub module_path: PathBuf,
+    pub name: String,
+    pub kind: UnusedItemKind,
+    /// The byte range of the item's name within the module.
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnusedItemKind {
+    /// An export from a module other than the root module that's neither
+    /// imported by another module nor used within its own module.
+    Export,
+    /// A top-level function or variable that isn't exported or used.
+    Helper,
+}
+
+impl std::fmt::Display for UnusedItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            UnusedItemKind::Export => write!(f, "export `{}` is never imported", self.name),
+            UnusedItemKind::Helper => write!(f, "`{}` is never used", self.name),
+        }
+    }
+}
+
+/// Find the exports and top-level helpers in a project that are never
+/// referenced. Exports from the root module are never reported, since
+/// they're the project's build targets and are what dependent projects
+/// import. Names are matched syntactically, so a local variable that
+/// shadows a top-level item counts as a use of it.
+pub async fn find_unused(
+    vfs: &Vfs,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<Vec<UnusedItem>> {
+    let project_root = projects.project_root(project_hash)?;
+    let root_module_path = projects.project_root_module_path(project_hash)?;
+    let module_paths = projects.project_module_paths(project_hash)?;
+    let module_paths_set = module_paths.iter().cloned().collect::<HashSet<_>>();
+
+    let mut modules = vec![];
+    for module_path in module_paths {
+        let (_, contents) = vfs.load(&module_path).await?;
+        let contents = std::str::from_utf8(&contents)
+            .with_context(|| format!("{}: invalid UTF-8", module_path.display()))?;
+        let symbols = module_symbols(contents)
+            .with_context(|| format!("{}: failed to analyze module", module_path.display()))?;
+        modules.push((module_path, symbols));
+    }
+
+    let mut imported: HashMap<PathBuf, ImportedNames> = HashMap::new();
+    for (module_path, symbols) in &modules {
+        for (specifier, names) in &symbols.imports {
+            let Some(import_path) =
+                resolve_local_import(&project_root, module_path, specifier, &module_paths_set)
+            else {
+                continue;
+            };
+
+            let entry = imported.entry(import_path).or_default();
+            match names {
+                ImportedNames::All => *entry = ImportedNames::All,
+                ImportedNames::Names(names) => {
+                    if let ImportedNames::Names(entry_names) = entry {
+                        entry_names.extend(names.iter().cloned());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut unused = vec![];
+    for (module_path, symbols) in &modules {
+        for declaration in &symbols.declarations {
+            let used_locally = symbols.references.iter().any(|(name, range)| {
+                *name == declaration.name && !declaration.range.contains_range(*range)
+            });
+            if used_locally {
+                continue;
+            }
+
+            let kind = if declaration.exported {
+                if *module_path == root_module_path {
+                    continue;
+                }
+
+                let is_imported = match imported.get(module_path) {
+                    Some(ImportedNames::All) => true,
+                    Some(ImportedNames::Names(names)) => names.contains(&declaration.name),
+                    None => false,
+                };
+                if is_imported {
+                    continue;
+                }
+
+                UnusedItemKind::Export
+            } else {
+                UnusedItemKind::Helper
+            };
+
+            unused.push(UnusedItem {
+                module_path: module_path.clone(),
+                name: declaration.name.clone(),
+                kind,
+                start: declaration.name_range.start().into(),
+                end: declaration.name_range.end().into(),
+            });
+        }
+    }
+
+    unused.sort_by(|a, b| (&a.module_path, a.start).cmp(&(&b.module_path, b.start)));
+    Ok(unused)
+}
+
+#[derive(Debug, Default)]
+struct ModuleSymbols {
+    declarations: Vec<Declaration>,
+    references: Vec<(String, TextRange)>,
+    imports: Vec<(BriocheImportSpecifier, ImportedNames)>,
+}
+
+#[derive(Debug)]
+struct Declaration {
+    name: String,
+    exported: bool,
+    /// The range of the whole declaration, so references from within the
+    /// declaration itself (like recursive calls) don't count as uses.
+    range: TextRange,
+    name_range: TextRange,
+}
+
+#[derive(Debug)]
+enum ImportedNames {
+    All,
+    Names(HashSet<String>),
+}
+
+impl Default for ImportedNames {
+    fn default() -> Self {
+        Self::Names(HashSet::new())
+    }
+}
+
+fn module_symbols(contents: &str) -> anyhow::Result<ModuleSymbols> {
+    let parsed = biome_js_parser::parse(
+        contents,
+        biome_js_syntax::JsFileSource::ts().with_module_kind(biome_js_syntax::ModuleKind::Module),
+        biome_js_parser::JsParserOptions::default(),
+    )
+    .cast::<biome_js_syntax::JsModule>()
+    .expect("failed to cast module");
+    let module = parsed.try_tree().context("failed to parse module")?;
+
+    let mut symbols = ModuleSymbols::default();
+    for item in module.items().iter() {
+        match &item {
+            biome_js_syntax::AnyJsModuleItem::JsExport(export) => {
+                let Ok(clause) = export.export_clause() else {
+                    continue;
+                };
+                match clause {
+                    biome_js_syntax::AnyJsExportClause::AnyJsDeclarationClause(declaration) => {
+                        let range = item.syntax().text_range();
+                        match declaration {
+                            biome_js_syntax::AnyJsDeclarationClause::JsFunctionDeclaration(
+                                function,
+                            ) => {
+                                if let Ok(id) = function.id() {
+                                    push_binding(&mut symbols, &id, true, range);
+                                }
+                            }
+                            biome_js_syntax::AnyJsDeclarationClause::JsVariableDeclarationClause(
+                                variables,
+                            ) => {
+                                if let Ok(declaration) = variables.declaration() {
+                                    push_variables(&mut symbols, &declaration, true, range);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    biome_js_syntax::AnyJsExportClause::JsExportFromClause(clause) => {
+                        if let Some(specifier) = import_specifier(clause.source()) {
+                            symbols.imports.push((specifier, ImportedNames::All));
+                        }
+                    }
+                    biome_js_syntax::AnyJsExportClause::JsExportNamedFromClause(clause) => {
+                        if let Some(specifier) = import_specifier(clause.source()) {
+                            let names = clause
+                                .syntax()
+                                .descendants()
+                                .filter_map(biome_js_syntax::JsExportNamedFromSpecifier::cast)
+                                .filter_map(|specifier| {
+                                    let name = specifier.source_name().ok()?.value().ok()?;
+                                    Some(name.text_trimmed().to_string())
+                                })
+                                .collect();
+                            symbols
+                                .imports
+                                .push((specifier, ImportedNames::Names(names)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            biome_js_syntax::AnyJsModuleItem::JsImport(import) => {
+                let Ok(clause) = import.import_clause() else {
+                    continue;
+                };
+                let Some(specifier) = import_specifier(clause.source()) else {
+                    continue;
+                };
+
+                let is_namespace = clause
+                    .syntax()
+                    .descendants()
+                    .any(|node| biome_js_syntax::JsNamespaceImportSpecifier::can_cast(node.kind()));
+                let names = if is_namespace {
+                    ImportedNames::All
+                } else {
+                    let named = clause
+                        .syntax()
+                        .descendants()
+                        .filter_map(biome_js_syntax::JsNamedImportSpecifier::cast)
+                        .filter_map(|specifier| {
+                            let name = specifier.name().ok()?.value().ok()?;
+                            Some(name.text_trimmed().to_string())
+                        });
+                    let shorthand = clause
+                        .syntax()
+                        .descendants()
+                        .filter_map(biome_js_syntax::JsShorthandNamedImportSpecifier::cast)
+                        .filter_map(|specifier| {
+                            let local_name = specifier.local_name().ok()?;
+                            binding_name(&local_name).map(|(name, _)| name)
+                        });
+                    ImportedNames::Names(named.chain(shorthand).collect())
+                };
+                symbols.imports.push((specifier, names));
+            }
+            biome_js_syntax::AnyJsModuleItem::AnyJsStatement(statement) => {
+                let range = item.syntax().text_range();
+                match statement {
+                    biome_js_syntax::AnyJsStatement::JsFunctionDeclaration(function) => {
+                        if let Ok(id) = function.id() {
+                            push_binding(&mut symbols, &id, false, range);
+                        }
+                    }
+                    biome_js_syntax::AnyJsStatement::JsVariableStatement(variables) => {
+                        if let Ok(declaration) = variables.declaration() {
+                            push_variables(&mut symbols, &declaration, false, range);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    symbols.references = module
+        .syntax()
+        .descendants()
+        .filter_map(biome_js_syntax::JsReferenceIdentifier::cast)
+        .filter_map(|reference| {
+            let name = reference.value_token().ok()?;
+            Some((
+                name.text_trimmed().to_string(),
+                reference.syntax().text_range(),
+            ))
+        })
+        .collect();
+
+    Ok(symbols)
+}
+
+fn push_variables(
+    symbols: &mut ModuleSymbols,
+    declaration: &biome_js_syntax::JsVariableDeclaration,
+    exported: bool,
+    range: TextRange,
+) {
+    for declarator in declaration.declarators().iter().flatten() {
+        let Ok(id) = declarator.id() else {
+            continue;
+        };
+        if let Some(binding) = id.as_any_js_binding() {
+            push_binding(symbols, binding, exported, range);
+        }
+    }
+}
+
+fn push_binding(
+    symbols: &mut ModuleSymbols,
+    binding: &biome_js_syntax::AnyJsBinding,
+    exported: bool,
+    range: TextRange,
+) {
+    if let Some((name, name_range)) = binding_name(binding) {
+        symbols.declarations.push(Declaration {
+            name,
+            exported,
+            range,
+            name_range,
+        });
+    }
+}
+
+fn binding_name(binding: &biome_js_syntax::AnyJsBinding) -> Option<(String, TextRange)> {
+    let name = binding.as_js_identifier_binding()?.name_token().ok()?;
+    Some((name.text_trimmed().to_string(), name.text_trimmed_range()))
+}
+
+fn import_specifier(
+    source: biome_rowan::SyntaxResult<biome_js_syntax::JsModuleSource>,
+) -> Option<BriocheImportSpecifier> {
+    let source = source.ok()?;
+    let source = source.inner_string_text().ok()?;
+    source.text().parse().ok()
+}
+
+fn resolve_local_import(
+    project_root: &Path,
+    module_path: &Path,
+    specifier: &BriocheImportSpecifier,
+    module_paths: &HashSet<PathBuf>,
+) -> Option<PathBuf> {
+    let import_path = match specifier {
+        BriocheImportSpecifier::Local(BriocheLocalImportSpecifier::Relative(subpath)) => {
+            module_path.parent()?.join(subpath)
+        }
+        BriocheImportSpecifier::Local(BriocheLocalImportSpecifier::ProjectRoot(subpath)) => {
+            project_root.join(subpath)
+        }
+        BriocheImportSpecifier::External(_) => {
+            return None;
+        }
+    };
+    let import_path = crate::fs_utils::logical_path(&import_path);
+
+    if import_path == project_root {
+        return Some(import_path.join("project.bri"));
+    }
+
+    if module_paths.contains(&import_path) {
+        Some(import_path)
+    } else {
+        Some(import_path.join("index.bri"))
+    }
+}
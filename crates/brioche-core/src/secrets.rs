@@ -0,0 +1,128 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Context as _;
+use bstr::{ByteSlice as _, ByteVec as _};
+
+use crate::error_codes::{CodedError, ErrorCode};
+
+/// The text that secret values get replaced with in logged output.
+pub const REDACTED: &[u8] = b"[REDACTED]";
+
+/// Where a secret's value is read from, set per secret under `secrets` in
+/// the config file (e.g. `npm_token = { env = "NPM_TOKEN" }`).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    /// Read the secret from a file on the host.
+    File(PathBuf),
+
+    /// Read the secret from an environment variable of the Brioche process.
+    Env(String),
+}
+
+/// The secrets available to processes, keyed by name. Values are only
+/// read when a process that uses them runs, so they're never stored.
+#[derive(Debug, Default)]
+pub struct Secrets {
+    sources: HashMap<String, SecretSource>,
+}
+
+impl Secrets {
+    pub fn new(sources: HashMap<String, SecretSource>) -> Self {
+        Self { sources }
+    }
+
+    pub async fn read(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        let source = self.sources.get(name).with_context(|| {
+            CodedError::new(
+                ErrorCode::SECRET_NOT_CONFIGURED,
+                format!(
+                    "secret {name:?} is not configured (set it under `secrets` in the config file)"
+                ),
+            )
+        })?;
+
+        match source {
+            SecretSource::File(path) => tokio::fs::read(path)
+                .await
+                .with_context(|| format!("failed to read secret {name:?} from {}", path.display())),
+            SecretSource::Env(var) => {
+                let value = std::env::var_os(var).with_context(|| {
+                    CodedError::new(
+                        ErrorCode::SECRET_NOT_CONFIGURED,
+                        format!("secret {name:?} should be read from ${var}, but it isn't set"),
+                    )
+                })?;
+                let value = Vec::from_os_string(value)
+                    .map_err(|_| anyhow::anyhow!("invalid value for secret {name:?}"))?;
+                Ok(value)
+            }
+        }
+    }
+}
+
+/// Secret names are used as file names in the process's secrets
+/// directory, so they're limited to a safe set of characters.
+pub fn validate_secret_name(name: &str) -> anyhow::Result<()> {
+    let is_valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    anyhow::ensure!(
+        is_valid,
+        "invalid secret name {name:?}: must only contain letters, digits, `-`, `_`, and `.`, and must not start with `.`"
+    );
+
+    Ok(())
+}
+
+/// Replaces secret values in a stream of output, such as a process's
+/// logs. Output that could be the start of a secret is held back until
+/// more is written, so secrets split across writes are still redacted.
+pub struct Redactor {
+    values: Vec<Vec<u8>>,
+    max_len: usize,
+    pending: Vec<u8>,
+}
+
+impl Redactor {
+    pub fn new(values: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        // Secret files usually end with a newline that isn't part of the
+        // secret when it gets printed
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|value| value.trim_end().to_vec())
+            .filter(|value| !value.is_empty())
+            .collect();
+        let max_len = values.iter().map(|value| value.len()).max().unwrap_or(0);
+        Self {
+            values,
+            max_len,
+            pending: vec![],
+        }
+    }
+
+    /// Add more output, returning the part that's ready to be written.
+    pub fn redact(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.values.is_empty() {
+            return bytes.to_vec();
+        }
+
+        self.pending.extend_from_slice(bytes);
+        for value in &self.values {
+            if self.pending.find(value).is_some() {
+                self.pending = self.pending.replace(value, REDACTED);
+            }
+        }
+
+        let held_back = self.max_len.saturating_sub(1).min(self.pending.len());
+        let ready_len = self.pending.len() - held_back;
+        self.pending.drain(..ready_len).collect()
+    }
+
+    /// Return any output that was held back, after the stream ends.
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    ffi::OsString,
+    path::PathBuf,
+    process::ExitStatus,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+
+use crate::{
+    annotations::ServiceRestartPolicy,
+    project::{ProjectHash, Projects},
+};
+
+/// How long to wait before starting a service again after it exits.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a service has to exit after being asked to stop, before it's
+/// killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A process to run as a service.
+#[derive(Debug, Clone)]
+pub struct ServiceCommand {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+    pub env: Vec<(OsString, OsString)>,
+    pub restart: ServiceRestartPolicy,
+}
+
+impl ServiceCommand {
+    fn spawn(&self) -> anyhow::Result<tokio::process::Child> {
+        let child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start service {}", self.program.display()))?;
+        Ok(child)
+    }
+}
+
+/// Run a service until it exits for good, restarting it based on its
+/// restart policy. Returns `None` if the service was stopped by
+/// cancelling `stop`, or the exit status of the last run otherwise.
+pub async fn run_service(
+    command: &ServiceCommand,
+    stop: tokio_util::sync::CancellationToken,
+) -> anyhow::Result<Option<ExitStatus>> {
+    loop {
+        let mut child = command.spawn()?;
+
+        let status = tokio::select! {
+            status = child.wait() => status?,
+            _ = stop.cancelled() => {
+                stop_child(&mut child).await?;
+                return Ok(None);
+            }
+        };
+
+        let restart = match command.restart {
+            ServiceRestartPolicy::Never => false,
+            ServiceRestartPolicy::OnFailure => !status.success(),
+            ServiceRestartPolicy::Always => true,
+        };
+        if !restart {
+            return Ok(Some(status));
+        }
+
+        tracing::warn!(%status, "service exited, restarting");
+        tokio::select! {
+            _ = tokio::time::sleep(RESTART_DELAY) => {}
+            _ = stop.cancelled() => {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Ask a child process to exit, then kill it if it doesn't exit in time.
+async fn stop_child(child: &mut tokio::process::Child) -> anyhow::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            if let Some(pid) = child.id() {
+                let pid = nix::unistd::Pid::from_raw(pid.try_into()?);
+                let _ = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGTERM);
+
+                let result = tokio::time::timeout(STOP_TIMEOUT, child.wait()).await;
+                if result.is_ok() {
+                    return Ok(());
+                }
+
+                tracing::warn!("service did not exit after {STOP_TIMEOUT:?}, killing");
+            }
+        }
+    }
+
+    child.kill().await?;
+    Ok(())
+}
+
+/// A snapshot of the files that make up a project and its local
+/// dependencies. Used to tell when a project changes while watching it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProjectFingerprint(blake3::Hash);
+
+/// Take a fingerprint of the files within a project and all of its
+/// dependencies that were loaded from the local filesystem. Hidden files
+/// and directories (starting with `.`) are skipped.
+pub async fn project_fingerprint(
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<ProjectFingerprint> {
+    let mut local_paths = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![project_hash];
+    while let Some(project_hash) = queue.pop() {
+        if !visited.insert(project_hash) {
+            continue;
+        }
+
+        local_paths.extend(projects.local_paths(project_hash)?);
+
+        let project = projects.project(project_hash)?;
+        queue.extend(project.dependency_hashes());
+    }
+
+    let fingerprint = tokio::task::spawn_blocking(move || {
+        let mut hasher = blake3::Hasher::new();
+        for local_path in &local_paths {
+            let entries = walkdir::WalkDir::new(local_path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|entry| {
+                    entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+                });
+            for entry in entries {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let modified = metadata
+                    .modified()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+
+                let path = entry.path().to_string_lossy();
+                hasher.update(&(path.len() as u64).to_le_bytes());
+                hasher.update(path.as_bytes());
+                hasher.update(&metadata.len().to_le_bytes());
+                hasher.update(&modified.as_nanos().to_le_bytes());
+            }
+        }
+
+        anyhow::Ok(ProjectFingerprint(hasher.finalize()))
+    })
+    .await??;
+
+    Ok(fingerprint)
+}
+
+/// Wait until the fingerprint of a project differs from `fingerprint`,
+/// checking every `poll_interval`.
+pub async fn wait_for_project_change(
+    projects: &Projects,
+    project_hash: ProjectHash,
+    fingerprint: ProjectFingerprint,
+    poll_interval: Duration,
+) -> anyhow::Result<()> {
+    loop {
+        tokio::time::sleep(poll_interval).await;
+
+        let current = project_fingerprint(projects, project_hash).await?;
+        if current != fingerprint {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+use crate::{
+    project::{ProjectHash, Projects},
+    recipe::{pretty::render_recipe, Recipe},
+    Brioche,
+};
+
+/// The directory within a project where snapshots are stored.
+pub const SNAPSHOTS_DIR: &str = "__snapshots__";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotResult {
+    /// The rendered recipe matched the existing snapshot.
+    Matched,
+    /// There was no existing snapshot, so a new one was written.
+    Created,
+    /// The snapshot didn't match, and was replaced with the rendered
+    /// recipe.
+    Updated,
+    /// The snapshot didn't match. Contains a unified diff from the
+    /// snapshot to the rendered recipe.
+    Mismatched { diff: String },
+}
+
+impl SnapshotResult {
+    pub fn is_ok(&self) -> bool {
+        match self {
+            Self::Matched | Self::Created | Self::Updated => true,
+            Self::Mismatched { .. } => false,
+        }
+    }
+}
+
+/// Get the path of the snapshot file for a project export.
+pub fn snapshot_path(
+    projects: &Projects,
+    project_hash: ProjectHash,
+    export: &str,
+) -> anyhow::Result<PathBuf> {
+    let project_root = projects.project_root(project_hash)?;
+    Ok(project_root
+        .join(SNAPSHOTS_DIR)
+        .join(format!("{export}.snap")))
+}
+
+/// Compare the canonical rendering of a recipe against its snapshot file.
+/// The recipe isn't baked, so snapshots can guard against unintended
+/// changes to a recipe without rebuilding it. When `update` is set, the
+/// snapshot is replaced if it doesn't match. Missing snapshots are always
+/// written.
+pub async fn check_snapshot(
+    brioche: &Brioche,
+    recipe: &Recipe,
+    path: &Path,
+    update: bool,
+) -> anyhow::Result<SnapshotResult> {
+    let actual = render_recipe(brioche, recipe).await?;
+
+    let expected = match tokio::fs::read_to_string(path).await {
+        Ok(expected) => Some(expected),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read snapshot {}", path.display()));
+        }
+    };
+
+    let result = match expected {
+        Some(expected) if expected == actual => return Ok(SnapshotResult::Matched),
+        Some(_) if update => SnapshotResult::Updated,
+        Some(expected) => {
+            let diff = similar::TextDiff::from_lines(&expected, &actual)
+                .unified_diff()
+                .header("snapshot", "actual")
+                .to_string();
+            return Ok(SnapshotResult::Mismatched { diff });
+        }
+        None => SnapshotResult::Created,
+    };
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    }
+    tokio::fs::write(path, &actual)
+        .await
+        .with_context(|| format!("failed to write snapshot {}", path.display()))?;
+
+    Ok(result)
+}
@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use sqlx::Connection as _;
+
+use crate::Brioche;
+
+/// Entries in the Brioche home directory that aren't copied to the new
+/// home. The database is copied separately so the copy is consistent,
+/// temporary directories only hold leftovers from interrupted operations,
+/// and logs are always written to the default data directory.
+const SKIPPED_ENTRIES: &[&str] = &[
+    "brioche.db",
+    "brioche.db-wal",
+    "brioche.db-shm",
+    "blobs-temp",
+    "locals-temp",
+    "projects-temp",
+    "process-temp",
+    "logs",
+];
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrateReport {
+    /// The absolute path of the new home directory.
+    pub new_home: PathBuf,
+
+    /// The number of files copied to the new home, not counting the
+    /// database.
+    pub num_files: u64,
+
+    /// The total size of the copied files, in bytes.
+    pub total_size: u64,
+
+    /// The number of blobs whose contents were checked against their hash
+    /// after copying.
+    pub num_blobs_verified: u64,
+}
+
+/// Copy the Brioche store (the database, blobs, locals, projects, and
+/// everything else in the home directory) to a new home directory, then
+/// verify the copy. The old home is left untouched, so the caller decides
+/// when to switch over and remove it.
+///
+/// The new home must not exist yet or must be empty. Other Brioche
+/// processes using the old home shouldn't be running during the copy.
+/// Database migrations for newer store layouts run as usual the next time
+/// the new home is opened.
+pub async fn migrate_store(brioche: &Brioche, new_home: &Path) -> anyhow::Result<MigrateReport> {
+    let old_home = tokio::fs::canonicalize(&brioche.home)
+        .await
+        .with_context(|| format!("failed to resolve {}", brioche.home.display()))?;
+    let new_home = crate::fs_utils::logical_path(&std::env::current_dir()?.join(new_home));
+
+    anyhow::ensure!(
+        !new_home.starts_with(&old_home) && !old_home.starts_with(&new_home),
+        "new store path {} must not overlap with the current store at {}",
+        new_home.display(),
+        old_home.display(),
+    );
+
+    match tokio::fs::read_dir(&new_home).await {
+        Ok(mut entries) => {
+            anyhow::ensure!(
+                entries.next_entry().await?.is_none(),
+                "new store path {} is not empty",
+                new_home.display()
+            );
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            tokio::fs::create_dir_all(&new_home)
+                .await
+                .with_context(|| format!("failed to create {}", new_home.display()))?;
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", new_home.display()));
+        }
+    }
+
+    // Write a consistent copy of the database, including anything that's
+    // still in the write-ahead log
+    let new_database_path = new_home.join("brioche.db");
+    let new_database_path_value = new_database_path.to_string_lossy().into_owned();
+    let mut db_conn = brioche.db_conn.lock().await;
+    sqlx::query!("VACUUM INTO ?", new_database_path_value)
+        .execute(&mut *db_conn)
+        .await
+        .context("failed to copy database")?;
+    drop(db_conn);
+
+    let mut report = {
+        let old_home = old_home.clone();
+        let new_home = new_home.clone();
+        tokio::task::spawn_blocking(move || copy_store_blocking(&old_home, &new_home)).await??
+    };
+
+    // Verify the copied database and blobs
+    let db_conn_options = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(&new_database_path)
+        .read_only(true);
+    let mut new_db_conn = sqlx::SqliteConnection::connect_with(&db_conn_options).await?;
+    // This can't use `query!`, since sqlx can't describe the columns
+    // returned by a pragma
+    let (integrity,) = sqlx::query_as::<_, (String,)>("PRAGMA integrity_check")
+        .fetch_one(&mut new_db_conn)
+        .await?;
+    new_db_conn.close().await?;
+    anyhow::ensure!(
+        integrity == "ok",
+        "copied database failed integrity check: {integrity}"
+    );
+
+    report.new_home = new_home.clone();
+    report.num_blobs_verified = {
+        let new_blobs_dir = new_home.join("blobs");
+        tokio::task::spawn_blocking(move || verify_blobs_blocking(&new_blobs_dir)).await??
+    };
+
+    Ok(report)
+}
+
+/// Remove the old store after migrating. Logs are kept, since they're
+/// always written to the default data directory.
+pub async fn remove_old_store(old_home: &Path) -> anyhow::Result<()> {
+    let mut entries = tokio::fs::read_dir(old_home).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name() == "logs" {
+            continue;
+        }
+
+        let path = entry.path();
+        crate::fs_utils::set_directory_rwx_recursive(&path).await?;
+        crate::fs_utils::try_remove(&path)
+            .await
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+
+    // Only succeeds if nothing else is left in the old home
+    let _ = tokio::fs::remove_dir(old_home).await;
+
+    Ok(())
+}
+
+/// Set the Brioche home in the config file, so later commands use the
+/// migrated store. Other settings in the config file are preserved, but
+/// comments aren't. Returns the path of the config file.
+pub async fn set_configured_home(new_home: &Path) -> anyhow::Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("dev", "brioche", "brioche")
+        .context("failed to get Brioche directories (is $HOME set?)")?;
+    let config_path = dirs.config_dir().join("config.toml");
+
+    let mut config = match tokio::fs::read_to_string(&config_path).await {
+        Ok(config) => toml::from_str::<toml::Table>(&config).with_context(|| {
+            format!(
+                "failed to parse brioche config from {}",
+                config_path.display()
+            )
+        })?,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!(
+                    "failed to read brioche config from {}",
+                    config_path.display()
+                )
+            });
+        }
+    };
+
+    let new_home = new_home
+        .to_str()
+        .with_context(|| format!("invalid UTF-8 in path {}", new_home.display()))?;
+    config.insert(
+        "home".to_string(),
+        toml::Value::String(new_home.to_string()),
+    );
+
+    tokio::fs::create_dir_all(dirs.config_dir()).await?;
+    tokio::fs::write(&config_path, toml::to_string_pretty(&config)?)
+        .await
+        .with_context(|| format!("failed to write {}", config_path.display()))?;
+
+    Ok(config_path)
+}
+
+fn copy_store_blocking(old_home: &Path, new_home: &Path) -> anyhow::Result<MigrateReport> {
+    let mut report = MigrateReport::default();
+
+    // Directory permissions are applied after their contents are copied,
+    // since some directories (like locals) are read-only
+    let mut dir_permissions = vec![];
+
+    let walker = walkdir::WalkDir::new(old_home)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() != 1
+                || !SKIPPED_ENTRIES
+                    .iter()
+                    .any(|skipped| entry.file_name() == *skipped)
+        });
+    for entry in walker {
+        let entry = entry?;
+        let relative_path = entry.path().strip_prefix(old_home)?;
+        let dest = new_home.join(relative_path);
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            std::fs::create_dir(&dest)
+                .with_context(|| format!("failed to create {}", dest.display()))?;
+            dir_permissions.push((dest, metadata.permissions()));
+        } else if metadata.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            crate::fs_utils::symlink_blocking(&target, &dest)
+                .with_context(|| format!("failed to create symlink {}", dest.display()))?;
+        } else if metadata.is_file() {
+            std::fs::copy(entry.path(), &dest).with_context(|| {
+                format!(
+                    "failed to copy {} to {}",
+                    entry.path().display(),
+                    dest.display()
+                )
+            })?;
+
+            // Outputs have a fixed modified time, so keep it when copying
+            let file = std::fs::File::open(&dest)?;
+            file.set_modified(metadata.modified()?)?;
+
+            report.num_files += 1;
+            report.total_size = report.total_size.saturating_add(metadata.len());
+        } else {
+            anyhow::bail!("unsupported file type at {}", entry.path().display());
+        }
+    }
+
+    for (dir, permissions) in dir_permissions.into_iter().rev() {
+        std::fs::set_permissions(&dir, permissions)
+            .with_context(|| format!("failed to set permissions for {}", dir.display()))?;
+    }
+
+    Ok(report)
+}
+
+fn verify_blobs_blocking(blobs_dir: &Path) -> anyhow::Result<u64> {
+    if !blobs_dir.try_exists()? {
+        return Ok(0);
+    }
+
+    let mut num_verified = 0;
+    for entry in std::fs::read_dir(blobs_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let expected_hash = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| blake3::Hash::from_hex(name).ok())
+            .with_context(|| format!("unexpected file in blobs directory: {}", path.display()))?;
+
+        let mut file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut hasher = blake3::Hasher::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        let actual_hash = hasher.finalize();
+
+        anyhow::ensure!(
+            actual_hash == expected_hash,
+            "copied blob {} does not match its hash",
+            path.display()
+        );
+        num_verified += 1;
+    }
+
+    Ok(num_verified)
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use sqlx::Acquire as _;
+
+use crate::{
+    blob::{BlobHash, BlobSource},
+    Brioche,
+};
+
+/// Directories where files are stored by content.
+const STORE_DIRS: &[&str] = &["blobs", "locals"];
+
+/// Directories used for in-progress writes, which only hold leftovers from
+/// interrupted operations when Brioche isn't running.
+const TEMP_DIRS: &[&str] = &["blobs-temp", "locals-temp", "projects-temp"];
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreReport {
+    /// The number of distinct files (inodes) in the store. Hard links to
+    /// the same file are only counted once, on platforms where they can be
+    /// identified.
+    pub num_files: u64,
+
+    /// The total size of the distinct files in the store.
+    pub total_size: u64,
+
+    /// Groups of files with identical contents that are stored separately
+    /// instead of being hard linked together, largest savings first.
+    pub duplicates: Vec<DuplicateGroup>,
+
+    /// The space that could be saved by hard linking each group of
+    /// duplicates together.
+    pub duplicate_size: u64,
+
+    /// The space used by leftover temporary files.
+    pub temp_size: u64,
+}
+
+impl StoreReport {
+    /// The total space that could be reclaimed by deduplicating files and
+    /// removing leftover temporary files.
+    pub fn reclaimable_size(&self) -> u64 {
+        self.duplicate_size.saturating_add(self.temp_size)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub content_hash: BlobHash,
+    pub size: u64,
+    pub executable: bool,
+
+    /// One path for each separately-stored copy.
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    pub fn wasted_size(&self) -> u64 {
+        let extra_copies = self.paths.len().saturating_sub(1) as u64;
+        self.size.saturating_mul(extra_copies)
+    }
+}
+
+/// Scan the blobs and local outputs in the Brioche home directory for
+/// identical contents stored as separate files. Blobs and local outputs
+/// are normally hard linked together, but files get copied when hard
+/// linking isn't possible, such as for executable files (since blobs are
+/// never executable) or when the directories are on different
+/// filesystems. Only files of the same mode can be deduplicated, so
+/// executable and non-executable copies are grouped separately.
+pub async fn store_report(brioche: &Brioche) -> anyhow::Result<StoreReport> {
+    let home = brioche.home.clone();
+    let report = tokio::task::spawn_blocking(move || store_report_blocking(&home)).await??;
+    Ok(report)
+}
+
+fn store_report_blocking(home: &Path) -> anyhow::Result<StoreReport> {
+    let mut report = StoreReport::default();
+
+    // Find each distinct file, then group them by size so we only need
+    // to hash files that could possibly be duplicates
+    let mut seen_files = HashSet::new();
+    let mut files_by_size: HashMap<(u64, bool), Vec<PathBuf>> = HashMap::new();
+    for dir in STORE_DIRS {
+        let dir = home.join(dir);
+        if !dir.try_exists()? {
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(&dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            let file_id = crate::fs_utils::file_id(&metadata);
+            if file_id.is_some_and(|file_id| !seen_files.insert(file_id)) {
+                continue;
+            }
+
+            report.num_files += 1;
+            report.total_size = report.total_size.saturating_add(metadata.len());
+
+            let executable = crate::fs_utils::is_executable(&metadata.permissions());
+            files_by_size
+                .entry((metadata.len(), executable))
+                .or_default()
+                .push(entry.into_path());
+        }
+    }
+
+    for ((size, executable), paths) in files_by_size {
+        if paths.len() < 2 {
+            continue;
+        }
+
+        let mut paths_by_hash: HashMap<BlobHash, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            let content_hash = content_hash(home, &path)?;
+            paths_by_hash.entry(content_hash).or_default().push(path);
+        }
+
+        for (content_hash, mut paths) in paths_by_hash {
+            if paths.len() < 2 {
+                continue;
+            }
+
+            paths.sort();
+            let group = DuplicateGroup {
+                content_hash,
+                size,
+                executable,
+                paths,
+            };
+            report.duplicate_size = report.duplicate_size.saturating_add(group.wasted_size());
+            report.duplicates.push(group);
+        }
+    }
+
+    report.duplicates.sort_by(|a, b| {
+        b.wasted_size()
+            .cmp(&a.wasted_size())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    for dir in TEMP_DIRS {
+        let dir = home.join(dir);
+        if !dir.try_exists()? {
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(&dir) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                report.temp_size = report.temp_size.saturating_add(entry.metadata()?.len());
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Get the hash of a file's contents. Blobs are named after their hash,
+/// so only local outputs need to be read.
+fn content_hash(home: &Path, path: &Path) -> anyhow::Result<BlobHash> {
+    if path.parent() == Some(&home.join("blobs")) {
+        let blob_hash = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse().ok());
+        if let Some(blob_hash) = blob_hash {
+            return Ok(blob_hash);
+        }
+    }
+
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(BlobHash::from_blake3(hasher.finalize()))
+}
+
+/// Totals for the blobs recorded in the database as they were saved.
+/// Blobs saved by older versions of Brioche aren't recorded, so these
+/// can undercount compared to [`store_report`].
+#[derive(Debug, Default, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobTotals {
+    pub num_blobs: u64,
+    pub total_size: u64,
+
+    /// Totals for each source, largest first.
+    pub sources: Vec<BlobSourceTotals>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobSourceTotals {
+    pub source: BlobSource,
+    pub num_blobs: u64,
+    pub total_size: u64,
+}
+
+/// A blob recorded in the database when it was saved.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobRecord {
+    pub blob_hash: BlobHash,
+    pub size: u64,
+    pub source: BlobSource,
+
+    /// The URL the blob was downloaded from, for downloaded blobs.
+    pub source_url: Option<String>,
+
+    /// When the blob was saved, in UTC.
+    pub created_at: String,
+}
+
+/// The blobs saved on a single day.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobGrowth {
+    /// The day, as `YYYY-MM-DD` in UTC.
+    pub date: String,
+    pub num_blobs: u64,
+    pub total_size: u64,
+}
+
+/// Sum up the recorded blobs, without scanning the store.
+pub async fn blob_totals(brioche: &Brioche) -> anyhow::Result<BlobTotals> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT
+                source,
+                COUNT(*) AS "num_blobs!",
+                COALESCE(SUM(size), 0) AS "total_size!"
+            FROM blobs
+            GROUP BY source
+            ORDER BY SUM(size) DESC, source
+        "#,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    let mut totals = BlobTotals::default();
+    for record in records {
+        let source = record
+            .source
+            .parse()
+            .with_context(|| format!("invalid blob source from database: {:?}", record.source))?;
+        let num_blobs = record.num_blobs.try_into()?;
+        let total_size = record.total_size.try_into()?;
+        totals.num_blobs += num_blobs;
+        totals.total_size = totals.total_size.saturating_add(total_size);
+        totals.sources.push(BlobSourceTotals {
+            source,
+            num_blobs,
+            total_size,
+        });
+    }
+
+    Ok(totals)
+}
+
+/// Get the largest recorded blobs, largest first.
+pub async fn largest_blobs(brioche: &Brioche, limit: u32) -> anyhow::Result<Vec<BlobRecord>> {
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT blob_hash, size, source, source_url, created_at
+            FROM blobs
+            ORDER BY size DESC, blob_hash
+            LIMIT ?
+        "#,
+        limit,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    records
+        .into_iter()
+        .map(|record| {
+            let blob_hash = record
+                .blob_hash
+                .parse()
+                .context("invalid blob hash from database")?;
+            let source = record.source.parse().with_context(|| {
+                format!("invalid blob source from database: {:?}", record.source)
+            })?;
+            Ok(BlobRecord {
+                blob_hash,
+                size: record.size.try_into()?,
+                source,
+                source_url: record.source_url,
+                created_at: record.created_at,
+            })
+        })
+        .collect()
+}
+
+/// Get how much was added to the store each day over the last `days`
+/// days, oldest first. Days where no blobs were saved are left out.
+pub async fn blob_growth(brioche: &Brioche, days: u32) -> anyhow::Result<Vec<BlobGrowth>> {
+    let since_modifier = format!("-{days} days");
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let records = sqlx::query!(
+        r#"
+            SELECT
+                date(created_at) AS "day!",
+                COUNT(*) AS "num_blobs!",
+                COALESCE(SUM(size), 0) AS "total_size!"
+            FROM blobs
+            WHERE created_at >= datetime('now', ?)
+            GROUP BY date(created_at)
+            ORDER BY date(created_at)
+        "#,
+        since_modifier,
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+    drop(db_conn);
+
+    records
+        .into_iter()
+        .map(|record| {
+            Ok(BlobGrowth {
+                date: record.day,
+                num_blobs: record.num_blobs.try_into()?,
+                total_size: record.total_size.try_into()?,
+            })
+        })
+        .collect()
+}
@@ -1,9 +1,11 @@
 use anyhow::Context as _;
 use futures::{StreamExt as _, TryStreamExt as _};
 use human_repr::HumanDuration;
+use sqlx::Acquire as _;
 
 use crate::{
     project::ProjectHash,
+    recipe::{Artifact, Recipe},
     references::{ProjectReferences, RecipeReferences},
     Brioche,
 };
@@ -27,11 +29,13 @@ pub async fn sync_project(
 
 pub async fn sync_bakes(
     brioche: &Brioche,
-    bakes: Vec<(crate::recipe::Recipe, crate::recipe::Artifact)>,
+    bakes: Vec<(Recipe, Artifact)>,
     verbose: bool,
 ) -> anyhow::Result<SyncBakesResults> {
     // TODO: Use reporter for logging in this function
 
+    let bakes = filter_uploadable_bakes(brioche, bakes).await?;
+
     // Collect the references from each input recipe/output artifact
 
     let start_refs = std::time::Instant::now();
@@ -76,9 +80,10 @@ pub async fn sync_bakes(
             let brioche = brioche.clone();
             async move {
                 tokio::spawn(async move {
+                    let attestation = brioche.attestations.sign(input_hash, output_hash).await?;
                     brioche
                         .registry_client
-                        .create_bake(input_hash, output_hash)
+                        .create_bake(input_hash, output_hash, Some(attestation))
                         .await
                 })
                 .await??;
@@ -101,6 +106,94 @@ pub async fn sync_bakes(
     })
 }
 
+/// Remove bakes that shouldn't be uploaded because of a cache policy: bakes
+/// of recipes that don't allow uploads, and bakes whose input references
+/// one of those recipes or its output.
+async fn filter_uploadable_bakes(
+    brioche: &Brioche,
+    bakes: Vec<(Recipe, Artifact)>,
+) -> anyhow::Result<Vec<(Recipe, Artifact)>> {
+    let bakes = bakes
+        .into_iter()
+        .filter(|(input, _)| input.cache_policy().allows_upload())
+        .collect::<Vec<_>>();
+
+    // Check all the bakes at once first, since usually nothing references
+    // a recipe with a restrictive cache policy
+    let mut all_references = RecipeReferences::default();
+    crate::references::recipe_references(
+        brioche,
+        &mut all_references,
+        bakes.iter().map(|(input, _)| input.hash()),
+    )
+    .await?;
+    if !references_private_results(brioche, &all_references).await? {
+        return Ok(bakes);
+    }
+
+    let mut uploadable = vec![];
+    for (input, output) in bakes {
+        let mut references = RecipeReferences::default();
+        crate::references::recipe_references(brioche, &mut references, [input.hash()]).await?;
+        if references_private_results(brioche, &references).await? {
+            tracing::debug!(input_hash = %input.hash(), "not syncing bake that uses a local-only result");
+        } else {
+            uploadable.push((input, output));
+        }
+    }
+
+    Ok(uploadable)
+}
+
+/// Check if any of the references are a recipe that doesn't allow uploads,
+/// or the output of one.
+async fn references_private_results(
+    brioche: &Brioche,
+    references: &RecipeReferences,
+) -> anyhow::Result<bool> {
+    let has_private_recipe = references
+        .recipes
+        .values()
+        .any(|recipe| !recipe.cache_policy().allows_upload());
+    if has_private_recipe {
+        return Ok(true);
+    }
+
+    let recipe_hashes = references
+        .recipes
+        .keys()
+        .map(|hash| hash.to_string())
+        .collect::<Vec<_>>();
+    let recipe_hashes = serde_json::to_string(&recipe_hashes)?;
+
+    let mut db_conn = brioche.db_conn.lock().await;
+    let mut db_transaction = db_conn.begin().await?;
+    let row = sqlx::query!(
+        r#"
+            SELECT 1 AS "found!"
+            FROM bakes
+            INNER JOIN recipes AS input_recipes
+                ON bakes.input_hash = input_recipes.recipe_hash
+            WHERE bakes.output_hash IN (SELECT value FROM json_each(?))
+                AND (
+                    json_extract(input_recipes.recipe_json, '$.cachePolicy')
+                        IN ('no-cache', 'local-only')
+                    OR (
+                        json_extract(input_recipes.recipe_json, '$.secrets') IS NOT NULL
+                        AND json_extract(input_recipes.recipe_json, '$.outputHash') IS NULL
+                    )
+                )
+            LIMIT 1
+        "#,
+        recipe_hashes,
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(row.is_some())
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct SyncBakesResults {
     pub num_new_blobs: usize,
@@ -138,10 +231,7 @@ pub async fn sync_recipe_references(
             let brioche = brioche.clone();
             async move {
                 tokio::spawn(async move {
-                    let blob_path = {
-                        let permit = crate::blob::get_save_blob_permit().await?;
-                        crate::blob::blob_path(&brioche, permit, blob_hash).await?
-                    };
+                    let blob_path = crate::blob::blob_path(&brioche, blob_hash).await?;
 
                     // TODO: Figure out if we can stream the blob (this
                     // will error out due to `reqwest-retry`)
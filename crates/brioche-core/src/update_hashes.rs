@@ -0,0 +1,117 @@
+use std::{collections::BTreeSet, path::PathBuf};
+
+use anyhow::Context as _;
+
+use crate::{
+    project::{ProjectHash, Projects},
+    Brioche, Hash,
+};
+
+/// Downloads whose data didn't match the expected hash, recorded while
+/// baking when updating hashes is enabled.
+#[derive(Debug, Default)]
+pub struct HashUpdates {
+    updates: std::sync::Mutex<Vec<HashUpdate>>,
+}
+
+impl HashUpdates {
+    pub fn record(&self, update: HashUpdate) {
+        let mut updates = self.updates.lock().expect("hash updates lock poisoned");
+        if !updates.contains(&update) {
+            updates.push(update);
+        }
+    }
+
+    pub fn take(&self) -> Vec<HashUpdate> {
+        let mut updates = self.updates.lock().expect("hash updates lock poisoned");
+        std::mem::take(&mut *updates)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashUpdate {
+    pub url: url::Url,
+    pub expected_hash: Hash,
+    pub actual_hash: Hash,
+}
+
+/// A project module that needs to be rewritten to use updated hashes.
+#[derive(Debug, Clone)]
+pub struct SourceEdit {
+    pub path: PathBuf,
+    pub updates: Vec<HashUpdate>,
+    new_contents: String,
+}
+
+/// Find where each expected hash appears in the local projects that have
+/// been loaded, and build the edits to replace them with the actual hashes.
+/// Projects from the registry or from git are never edited.
+pub async fn find_source_edits(
+    brioche: &Brioche,
+    projects: &Projects,
+    updates: &[HashUpdate],
+) -> anyhow::Result<Vec<SourceEdit>> {
+    let mut module_paths = BTreeSet::new();
+    for project_hash in projects.project_hashes()? {
+        if !is_local_project(brioche, projects, project_hash)? {
+            continue;
+        }
+
+        for module_path in projects.project_module_paths(project_hash)? {
+            module_paths.insert(module_path);
+        }
+    }
+
+    let mut edits = vec![];
+    for module_path in module_paths {
+        let contents = tokio::fs::read_to_string(&module_path)
+            .await
+            .with_context(|| format!("failed to read module {}", module_path.display()))?;
+
+        let mut new_contents = contents;
+        let mut module_updates = vec![];
+        for update in updates {
+            let expected = hash_source_value(&update.expected_hash);
+            let actual = hash_source_value(&update.actual_hash);
+            if new_contents.contains(&expected) {
+                new_contents = new_contents.replace(&expected, &actual);
+                module_updates.push(update.clone());
+            }
+        }
+
+        if !module_updates.is_empty() {
+            edits.push(SourceEdit {
+                path: module_path,
+                updates: module_updates,
+                new_contents,
+            });
+        }
+    }
+
+    Ok(edits)
+}
+
+pub async fn apply_source_edits(edits: &[SourceEdit]) -> anyhow::Result<()> {
+    for edit in edits {
+        tokio::fs::write(&edit.path, &edit.new_contents)
+            .await
+            .with_context(|| format!("failed to write module {}", edit.path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// The form of a hash as it appears in a project's source code, such as
+/// the hex string passed to `std.sha256Hash()`.
+pub fn hash_source_value(hash: &Hash) -> String {
+    hex::encode(hash.value())
+}
+
+fn is_local_project(
+    brioche: &Brioche,
+    projects: &Projects,
+    project_hash: ProjectHash,
+) -> anyhow::Result<bool> {
+    let project_root = projects.project_root(project_hash)?;
+    Ok(!project_root.starts_with(&brioche.home))
+}
@@ -0,0 +1,121 @@
+use brioche_core::{
+    annotations::{get_annotations, Annotations, ServiceAnnotation, ServiceRestartPolicy},
+    recipe::Recipe,
+};
+use brioche_test::{bake_without_meta, without_meta};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_annotations_saved_for_output() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_dir = brioche_test::lazy_dir([(
+        "bin",
+        brioche_test::lazy_dir([("hello", brioche_test::lazy_file(hello_blob, true))]),
+    )]);
+
+    let annotations = Annotations {
+        name: Some("hello".to_string()),
+        version: Some("1.0.0".to_string()),
+        license: Some("MIT".to_string()),
+        main_executable: Some("bin/hello".to_string()),
+        service: None,
+        extra: [("homepage".to_string(), "https://example.com".to_string())]
+            .into_iter()
+            .collect(),
+    };
+    let annotated = Recipe::Annotate {
+        recipe: Box::new(without_meta(hello_dir.clone())),
+        annotations: annotations.clone(),
+    };
+
+    let expected = bake_without_meta(&brioche, hello_dir).await?;
+    assert_eq!(get_annotations(&brioche, expected.hash()).await?, None);
+
+    // Annotating doesn't change the artifact
+    let artifact = bake_without_meta(&brioche, annotated).await?;
+    assert_eq!(artifact, expected);
+
+    assert_eq!(
+        get_annotations(&brioche, artifact.hash()).await?,
+        Some(annotations),
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_annotations_missing_main_executable() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let annotated = Recipe::Annotate {
+        recipe: Box::new(without_meta(brioche_test::lazy_dir_empty())),
+        annotations: Annotations {
+            main_executable: Some("bin/hello".to_string()),
+            ..Default::default()
+        },
+    };
+
+    assert!(bake_without_meta(&brioche, annotated).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_annotations_service() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_dir = brioche_test::lazy_dir([(
+        "bin",
+        brioche_test::lazy_dir([("serve", brioche_test::lazy_file(hello_blob, true))]),
+    )]);
+
+    let annotations: Annotations = serde_json::from_value(serde_json::json!({
+        "service": {
+            "command": "bin/serve",
+            "args": ["--port", "8080"],
+            "env": { "LOG_LEVEL": "debug" },
+        },
+    }))?;
+    assert_eq!(
+        annotations.service,
+        Some(ServiceAnnotation {
+            command: "bin/serve".to_string(),
+            args: vec!["--port".to_string(), "8080".to_string()],
+            env: [("LOG_LEVEL".to_string(), "debug".to_string())]
+                .into_iter()
+                .collect(),
+            restart: ServiceRestartPolicy::OnFailure,
+        }),
+    );
+
+    let annotated = Recipe::Annotate {
+        recipe: Box::new(without_meta(hello_dir)),
+        annotations: annotations.clone(),
+    };
+    let artifact = bake_without_meta(&brioche, annotated).await?;
+    assert_eq!(
+        get_annotations(&brioche, artifact.hash()).await?,
+        Some(annotations),
+    );
+
+    // The service command must exist
+    let annotated = Recipe::Annotate {
+        recipe: Box::new(without_meta(brioche_test::lazy_dir_empty())),
+        annotations: Annotations {
+            service: Some(ServiceAnnotation {
+                command: "bin/serve".to_string(),
+                args: vec![],
+                env: Default::default(),
+                restart: ServiceRestartPolicy::Always,
+            }),
+            ..Default::default()
+        },
+    };
+    assert!(bake_without_meta(&brioche, annotated).await.is_err());
+
+    Ok(())
+}
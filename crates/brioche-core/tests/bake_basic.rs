@@ -1,4 +1,8 @@
-use brioche_core::{recipe::Recipe, Brioche};
+use brioche_core::{
+    error_codes::{error_code, ErrorCode},
+    recipe::{EnvironmentPackage, EnvironmentRecipe, Meta, Recipe, StackFrame, WithMeta},
+    Brioche,
+};
 
 mod brioche_test;
 
@@ -76,3 +80,335 @@ async fn test_bake_basic() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_bake_remove() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob_hello = brioche_test::blob(&brioche, b"hello").await;
+    let file_hello = brioche_test::lazy_file(blob_hello, false);
+
+    let lazy_dir = brioche_test::lazy_dir([
+        ("hello.txt", file_hello.clone()),
+        (
+            "hello",
+            brioche_test::lazy_dir([
+                ("hello.txt", file_hello.clone()),
+                ("hi.txt", file_hello.clone()),
+            ]),
+        ),
+    ]);
+
+    let remove = Recipe::Remove {
+        directory: Box::new(WithMeta::without_meta(lazy_dir)),
+        paths: vec!["hello.txt".into(), "hello/hi.txt".into(), "missing".into()],
+    };
+
+    let expected = brioche_test::dir(
+        &brioche,
+        [(
+            "hello",
+            brioche_test::dir(
+                &brioche,
+                [("hello.txt", brioche_test::file(blob_hello, false))],
+            )
+            .await,
+        )],
+    )
+    .await;
+
+    assert_eq!(bake_to_recipe(&brioche, &remove).await, expected.into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_rename() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob_hello = brioche_test::blob(&brioche, b"hello").await;
+    let file_hello = brioche_test::lazy_file(blob_hello, false);
+
+    let lazy_dir = brioche_test::lazy_dir([
+        ("hello.txt", file_hello.clone()),
+        ("hi.txt", file_hello.clone()),
+    ]);
+
+    let rename = Recipe::Rename {
+        directory: Box::new(WithMeta::without_meta(lazy_dir.clone())),
+        from: "hello.txt".into(),
+        to: "nested/hello.txt".into(),
+    };
+
+    let expected = brioche_test::dir(
+        &brioche,
+        [
+            ("hi.txt", brioche_test::file(blob_hello, false)),
+            (
+                "nested",
+                brioche_test::dir(
+                    &brioche,
+                    [("hello.txt", brioche_test::file(blob_hello, false))],
+                )
+                .await,
+            ),
+        ],
+    )
+    .await;
+
+    assert_eq!(bake_to_recipe(&brioche, &rename).await, expected.into());
+
+    let rename_to_existing = Recipe::Rename {
+        directory: Box::new(WithMeta::without_meta(lazy_dir.clone())),
+        from: "hello.txt".into(),
+        to: "hi.txt".into(),
+    };
+    assert!(
+        brioche_test::bake_without_meta(&brioche, rename_to_existing)
+            .await
+            .is_err()
+    );
+
+    let rename_missing = Recipe::Rename {
+        directory: Box::new(WithMeta::without_meta(lazy_dir)),
+        from: "missing.txt".into(),
+        to: "other.txt".into(),
+    };
+    assert!(brioche_test::bake_without_meta(&brioche, rename_missing)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_resolve_symlinks() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob_hello = brioche_test::blob(&brioche, b"hello").await;
+    let file_hello = brioche_test::lazy_file(blob_hello, false);
+
+    let resolve_symlinks = |dir| Recipe::ResolveSymlinks {
+        directory: Box::new(WithMeta::without_meta(dir)),
+    };
+
+    let lazy_dir = brioche_test::lazy_dir([
+        (
+            "share",
+            brioche_test::lazy_dir([("hello.txt", file_hello.clone())]),
+        ),
+        ("data", brioche_test::lazy_symlink("share")),
+        (
+            "bin",
+            brioche_test::lazy_dir([("hi.txt", brioche_test::lazy_symlink("../data/hello.txt"))]),
+        ),
+    ]);
+
+    let share_dir = brioche_test::dir(
+        &brioche,
+        [("hello.txt", brioche_test::file(blob_hello, false))],
+    )
+    .await;
+    let expected = brioche_test::dir(
+        &brioche,
+        [
+            ("share", share_dir.clone()),
+            ("data", share_dir),
+            (
+                "bin",
+                brioche_test::dir(
+                    &brioche,
+                    [("hi.txt", brioche_test::file(blob_hello, false))],
+                )
+                .await,
+            ),
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        bake_to_recipe(&brioche, &resolve_symlinks(lazy_dir)).await,
+        expected.into()
+    );
+
+    // Symlinks that escape the directory, are absolute, dangle, or form a
+    // cycle can't be resolved
+    for symlink in ["../outside", "/etc/passwd", "missing", "."] {
+        let lazy_dir = brioche_test::lazy_dir([
+            ("hello.txt", file_hello.clone()),
+            ("link", brioche_test::lazy_symlink(symlink)),
+        ]);
+        let result = brioche_test::bake_without_meta(&brioche, resolve_symlinks(lazy_dir)).await;
+        assert!(result.is_err(), "expected error resolving {symlink:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_environment() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob_hello = brioche_test::blob(&brioche, b"hello").await;
+    let blob_hi = brioche_test::blob(&brioche, b"hi").await;
+
+    let foo = brioche_test::lazy_dir([
+        (
+            "bin",
+            brioche_test::lazy_dir([
+                ("foo", brioche_test::lazy_file(blob_hello, true)),
+                ("tool", brioche_test::lazy_file(blob_hello, true)),
+            ]),
+        ),
+        (
+            "share",
+            brioche_test::lazy_dir([("README", brioche_test::lazy_file(blob_hello, false))]),
+        ),
+    ]);
+    let bar = brioche_test::lazy_dir([
+        (
+            "bin",
+            brioche_test::lazy_dir([
+                ("bar", brioche_test::lazy_file(blob_hi, true)),
+                ("tool", brioche_test::lazy_file(blob_hi, true)),
+            ]),
+        ),
+        (
+            "share",
+            brioche_test::lazy_dir([("README", brioche_test::lazy_file(blob_hello, false))]),
+        ),
+    ]);
+    let environment = |foo_priority, ignore_collisions| {
+        Recipe::Environment(EnvironmentRecipe {
+            packages: vec![
+                EnvironmentPackage {
+                    name: "foo".to_string(),
+                    recipe: WithMeta::without_meta(foo.clone()),
+                    priority: foo_priority,
+                },
+                EnvironmentPackage {
+                    name: "bar".to_string(),
+                    recipe: WithMeta::without_meta(bar.clone()),
+                    priority: 0,
+                },
+            ],
+            ignore_collisions,
+        })
+    };
+
+    // `foo` wins the collision on `bin/tool` in each case
+    let expected = brioche_test::dir(
+        &brioche,
+        [
+            (
+                "bin",
+                brioche_test::dir(
+                    &brioche,
+                    [
+                        ("bar", brioche_test::file(blob_hi, true)),
+                        ("foo", brioche_test::file(blob_hello, true)),
+                        ("tool", brioche_test::file(blob_hello, true)),
+                    ],
+                )
+                .await,
+            ),
+            (
+                "share",
+                brioche_test::dir(
+                    &brioche,
+                    [("README", brioche_test::file(blob_hello, false))],
+                )
+                .await,
+            ),
+        ],
+    )
+    .await;
+
+    // The package with the higher priority wins. Identical files don't
+    // collide
+    assert_eq!(
+        bake_to_recipe(&brioche, &environment(1, false)).await,
+        expected.clone().into(),
+    );
+
+    // Packages with the same priority collide
+    let error = brioche_test::bake_without_meta(&brioche, environment(0, false))
+        .await
+        .expect_err("expected collision");
+    assert_eq!(error_code(&error), Some(ErrorCode::ENVIRONMENT_COLLISION));
+    let message = format!("{error:#}");
+    assert!(message.contains(r#""bin/tool" is provided by foo, bar"#));
+    assert!(!message.contains("README"));
+
+    // Unless collisions are ignored, then the earliest package wins
+    assert_eq!(
+        bake_to_recipe(&brioche, &environment(0, true)).await,
+        expected.clone().into(),
+    );
+
+    let with_file = Recipe::Environment(EnvironmentRecipe {
+        packages: vec![EnvironmentPackage {
+            name: "file".to_string(),
+            recipe: WithMeta::without_meta(brioche_test::lazy_file(blob_hello, false)),
+            priority: 0,
+        }],
+        ignore_collisions: false,
+    });
+    assert!(brioche_test::bake_without_meta(&brioche, with_file)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_error_required_by() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let meta = |file_name: &str, line_number: i64| {
+        std::sync::Arc::new(Meta {
+            source: Some(vec![StackFrame {
+                file_name: Some(file_name.to_string()),
+                line_number: Some(line_number),
+                column_number: None,
+            }]),
+        })
+    };
+
+    let missing = Recipe::Get {
+        directory: Box::new(WithMeta::without_meta(brioche_test::lazy_dir_empty())),
+        path: "missing".into(),
+    };
+    let dependent = Recipe::Get {
+        directory: Box::new(WithMeta::new(missing, meta("missing.bri", 3))),
+        path: "foo".into(),
+    };
+    let root = Recipe::Get {
+        directory: Box::new(WithMeta::new(dependent, meta("dependent.bri", 5))),
+        path: "bar".into(),
+    };
+
+    let error = brioche_core::bake::bake(
+        &brioche,
+        WithMeta::new(root, meta("root.bri", 7)),
+        &brioche_core::bake::BakeScope::Anonymous,
+    )
+    .await
+    .expect_err("expected missing path to fail");
+
+    // The error comes from the innermost recipe, followed by each recipe
+    // that depended on it
+    let message = format!("{error:#}");
+    let expected_lines = [
+        "path not found in directory: \"missing\"",
+        "    at missing.bri:3",
+        "required by Get recipe at dependent.bri:5",
+        "required by Get recipe at root.bri:7",
+    ];
+    assert!(
+        message.ends_with(&expected_lines.join("\n")),
+        "unexpected error message: {message}"
+    );
+
+    Ok(())
+}
@@ -88,3 +88,64 @@ async fn test_bake_cache_unnested() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_bake_cache_fetches_missing_blobs_on_output() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello = "hello";
+    let hello_hash = brioche_test::sha256(hello);
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body(hello)
+        .expect(1)
+        .create();
+
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: format!("{server_url}/file.txt").parse().unwrap(),
+    });
+
+    let hello_blob = brioche_test::blob(&brioche, hello).await;
+    assert_eq!(
+        bake_without_meta(&brioche, hello_download.clone()).await?,
+        brioche_test::file(hello_blob, false),
+    );
+
+    // Remove the blob locally, so the cached bake result references a
+    // blob we don't have
+    let hello_blob_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    tokio::fs::remove_file(&hello_blob_path).await?;
+
+    let hello_blob_endpoint = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!(
+                "/v0/blobs/{hello_blob}.zst?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(zstd::encode_all(hello.as_bytes(), 0)?)
+        .expect(1)
+        .create();
+
+    // The bake should be a cache hit, without fetching the missing blob
+    let artifact = bake_without_meta(&brioche, hello_download).await?;
+    assert_eq!(artifact, brioche_test::file(hello_blob, false));
+    assert!(!tokio::fs::try_exists(&hello_blob_path).await?);
+
+    // The missing blob should be fetched from the registry once the
+    // output is created
+    brioche_core::output::create_local_output(&brioche, &artifact).await?;
+    assert!(tokio::fs::try_exists(&hello_blob_path).await?);
+
+    hello_endpoint.assert();
+    hello_blob_endpoint.assert();
+
+    Ok(())
+}
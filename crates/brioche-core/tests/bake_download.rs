@@ -284,3 +284,117 @@ async fn test_bake_download_does_not_cache_using_only_hash() -> anyhow::Result<(
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_bake_download_invalid_hash_quarantined() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello_hash = brioche_test::sha256("hello");
+    let not_hello_hash = brioche_test::sha256("not hello");
+    let _hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body("not hello")
+        .create();
+
+    let url = format!("{server_url}/file.txt");
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: url.parse().unwrap(),
+    });
+
+    let error = bake_without_meta(&brioche, hello_download)
+        .await
+        .expect_err("expected download to fail");
+    let message = format!("{error:#}");
+
+    // The error should include everything needed to update the hash
+    assert!(message.contains(&hello_hash.to_string()), "{message}");
+    assert!(message.contains(&not_hello_hash.to_string()), "{message}");
+    assert!(message.contains(&url), "{message}");
+    assert!(message.contains("9 bytes"), "{message}");
+
+    // The downloaded data should be kept around for inspection
+    let quarantine_dir = brioche.home.join("blobs-quarantine");
+    let mut quarantined = tokio::fs::read_dir(&quarantine_dir).await?;
+    let entry = quarantined
+        .next_entry()
+        .await?
+        .expect("no quarantined data found");
+    assert_eq!(tokio::fs::read_to_string(entry.path()).await?, "not hello");
+    assert!(quarantined.next_entry().await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_download_update_hashes() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test_with(|b| b.update_hashes(true)).await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello_hash = brioche_test::sha256("hello");
+    let not_hello_hash = brioche_test::sha256("not hello");
+    let _hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body("not hello")
+        .create();
+
+    let url: url::Url = format!("{server_url}/file.txt").parse()?;
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: url.clone(),
+    });
+
+    let result = bake_without_meta(&brioche, hello_download).await;
+    assert!(result.is_err());
+
+    let hash_updates = brioche.hash_updates.as_ref().unwrap();
+    let updates = hash_updates.take();
+    assert_eq!(
+        updates,
+        [brioche_core::update_hashes::HashUpdate {
+            url: url.clone(),
+            expected_hash: hello_hash.clone(),
+            actual_hash: not_hello_hash.clone(),
+        }]
+    );
+
+    let hello_hex = brioche_core::update_hashes::hash_source_value(&hello_hash);
+    let not_hello_hex = brioche_core::update_hashes::hash_source_value(&not_hello_hash);
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            format!(
+                r#"
+                    export default function () {{
+                        return std.download({{
+                            url: "{url}",
+                            hash: std.sha256Hash("{hello_hex}"),
+                        }});
+                    }}
+                "#
+            ),
+        )
+        .await;
+    let (projects, _) = brioche_test::load_project_no_validate(&brioche, &project_dir).await?;
+
+    let edits =
+        brioche_core::update_hashes::find_source_edits(&brioche, &projects, &updates).await?;
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].path, project_dir.join("project.bri"));
+    assert_eq!(edits[0].updates, updates);
+
+    brioche_core::update_hashes::apply_source_edits(&edits).await?;
+
+    let contents = tokio::fs::read_to_string(project_dir.join("project.bri")).await?;
+    assert!(contents.contains(&not_hello_hex), "{contents}");
+    assert!(!contents.contains(&hello_hex), "{contents}");
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::os::unix::fs::PermissionsExt as _;
+
+use assert_matches::assert_matches;
+use brioche_core::recipe::{GitCheckoutRecipe, Recipe};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+fn git(repo_path: &std::path::Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .env("GIT_AUTHOR_NAME", "brioche")
+        .env("GIT_AUTHOR_EMAIL", "brioche@example.com")
+        .env("GIT_COMMITTER_NAME", "brioche")
+        .env("GIT_COMMITTER_EMAIL", "brioche@example.com")
+        .output()
+        .expect("failed to run git");
+    assert!(output.status.success(), "git {args:?} failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Seed the git cache with a clone of a local repo, so checkouts from
+/// `url` can be baked without fetching over the network.
+fn seed_git_cache(brioche: &brioche_core::Brioche, url: &url::Url, repo_path: &std::path::Path) {
+    let url_hash = blake3::hash(url.as_str().as_bytes());
+    let cache_path = brioche
+        .home
+        .join("git-cache")
+        .join(url_hash.to_hex().as_str());
+    git(
+        repo_path,
+        &[
+            "clone",
+            "--quiet",
+            "--bare",
+            ".",
+            cache_path.to_str().unwrap(),
+        ],
+    );
+}
+
+#[tokio::test]
+async fn test_bake_git_checkout() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let repo_path = context.mkdir("repo").await;
+    git(&repo_path, &["init", "--quiet"]);
+    context.write_file("repo/README", "hello").await;
+    let script_path = context.write_file("repo/bin/run.sh", "echo hi").await;
+    tokio::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755)).await?;
+    tokio::fs::symlink("README", repo_path.join("link")).await?;
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "--quiet", "-m", "first"]);
+    let first_rev = git(&repo_path, &["rev-parse", "HEAD"]);
+
+    context.write_file("repo/README", "goodbye").await;
+    git(&repo_path, &["commit", "--quiet", "-am", "second"]);
+
+    let url: url::Url = "https://git.example.com/repo.git".parse()?;
+    seed_git_cache(&brioche, &url, &repo_path);
+    let checkout = Recipe::GitCheckout(GitCheckoutRecipe {
+        url: url.clone(),
+        rev: first_rev,
+    });
+
+    // The checkout should match the first commit, without a `.git` dir
+    assert_eq!(
+        bake_without_meta(&brioche, checkout).await?,
+        brioche_test::dir(
+            &brioche,
+            [
+                (
+                    "README",
+                    brioche_test::file(brioche_test::blob(&brioche, "hello").await, false),
+                ),
+                (
+                    "bin",
+                    brioche_test::dir(
+                        &brioche,
+                        [(
+                            "run.sh",
+                            brioche_test::file(brioche_test::blob(&brioche, "echo hi").await, true),
+                        )]
+                    )
+                    .await,
+                ),
+                ("link", brioche_test::symlink("README")),
+            ]
+        )
+        .await,
+    );
+
+    // Branch names aren't allowed, since they can change
+    let branch_checkout = Recipe::GitCheckout(GitCheckoutRecipe {
+        url,
+        rev: "main".to_string(),
+    });
+    assert_matches!(bake_without_meta(&brioche, branch_checkout).await, Err(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_git_checkout_rejects_local_url() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let repo_path = context.mkdir("repo").await;
+    git(&repo_path, &["init", "--quiet"]);
+    context.write_file("repo/README", "hello").await;
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "--quiet", "-m", "first"]);
+    let rev = git(&repo_path, &["rev-parse", "HEAD"]);
+
+    let url: url::Url = format!("file://{}", repo_path.display()).parse()?;
+    let checkout = Recipe::GitCheckout(GitCheckoutRecipe { url, rev });
+    assert_matches!(bake_without_meta(&brioche, checkout).await, Err(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_git_checkout_rejects_invalid_entry_names() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let repo_path = context.mkdir("repo").await;
+    git(&repo_path, &["init", "--quiet"]);
+    context.write_file("repo/README", "hello").await;
+    let blob = git(&repo_path, &["hash-object", "-w", "README"]);
+
+    for name in ["..", ".git", ".GIT"] {
+        // Write the tree object directly, since `git add` won't accept
+        // these names
+        let mut tree_object = format!("100644 {name}\0").into_bytes();
+        tree_object.extend_from_slice(&hex::decode(&blob)?);
+        let tree_object_path = context.write_file("tree", tree_object).await;
+        let tree = git(
+            &repo_path,
+            &[
+                "hash-object",
+                "-t",
+                "tree",
+                "--literally",
+                "-w",
+                tree_object_path.to_str().unwrap(),
+            ],
+        );
+        let rev = git(&repo_path, &["commit-tree", &tree, "-m", name]);
+        // Keep the commit reachable, so it's included when seeding the cache
+        let branch = format!("refs/heads/invalid-{}", &rev[..8]);
+        git(&repo_path, &["update-ref", &branch, &rev]);
+
+        let url: url::Url = format!("https://git.example.com/{rev}.git").parse()?;
+        seed_git_cache(&brioche, &url, &repo_path);
+        let checkout = Recipe::GitCheckout(GitCheckoutRecipe { url, rev });
+        assert_matches!(bake_without_meta(&brioche, checkout).await, Err(_));
+    }
+
+    Ok(())
+}
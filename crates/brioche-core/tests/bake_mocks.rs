@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+use brioche_core::{
+    bake::mocks::BakeMocks,
+    platform::current_platform,
+    recipe::{
+        Directory, DownloadRecipe, ProcessRecipe, ProcessTemplate, ProcessTemplateComponent,
+        Recipe, WithMeta,
+    },
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_bake_mocks_download() -> anyhow::Result<()> {
+    let hello_url: url::Url = "https://example.com/hello.txt".parse()?;
+
+    let (brioche, _context) = brioche_test::brioche_test_with({
+        let hello_url = hello_url.clone();
+        move |builder| {
+            builder.bake_mocks(BakeMocks::default().download(hello_url, brioche_test::dir_empty()))
+        }
+    })
+    .await;
+
+    let hello_download = Recipe::Download(DownloadRecipe {
+        url: hello_url,
+        hash: brioche_test::sha256("hello"),
+    });
+    assert_eq!(
+        bake_without_meta(&brioche, hello_download).await?,
+        brioche_test::dir_empty(),
+    );
+
+    let other_download = Recipe::Download(DownloadRecipe {
+        url: "https://example.com/other.txt".parse()?,
+        hash: brioche_test::sha256("other"),
+    });
+    let error = bake_without_meta(&brioche, other_download)
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("no mock for download"),
+        "unexpected error: {error:#}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_mocks_process() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.bake_mocks(BakeMocks::default().process(|process| {
+            let is_hello = process.args.iter().any(|arg| {
+                arg.components.iter().any(|component| {
+                    matches!(
+                        component,
+                        brioche_core::recipe::CompleteProcessTemplateComponent::Literal { value }
+                            if value == "hello"
+                    )
+                })
+            });
+            is_hello.then(brioche_test::dir_empty)
+        }))
+    })
+    .await;
+
+    let process = |arg: &str| {
+        Recipe::Process(ProcessRecipe {
+            command: ProcessTemplate {
+                components: vec![ProcessTemplateComponent::Literal {
+                    value: "/usr/bin/env".into(),
+                }],
+            },
+            args: vec![ProcessTemplate {
+                components: vec![ProcessTemplateComponent::Literal { value: arg.into() }],
+            }],
+            env: BTreeMap::new(),
+            dependencies: vec![],
+            work_dir: Box::new(WithMeta::without_meta(Recipe::Directory(
+                Directory::default(),
+            ))),
+            output_scaffold: None,
+            platform: current_platform(),
+            is_unsafe: false,
+            networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
+        })
+    };
+
+    assert_eq!(
+        bake_without_meta(&brioche, process("hello")).await?,
+        brioche_test::dir_empty(),
+    );
+
+    let error = bake_without_meta(&brioche, process("goodbye"))
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("no mock for process"),
+        "unexpected error: {error:#}"
+    );
+
+    // Nothing should have run in a sandbox
+    assert!(!brioche.process_scratch_dir.exists());
+
+    Ok(())
+}
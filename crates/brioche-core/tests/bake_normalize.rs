@@ -0,0 +1,154 @@
+use brioche_core::{
+    bake::normalize_recipe,
+    recipe::{ArtifactDiscriminants, Recipe},
+};
+use brioche_test::{bake_without_meta, without_meta};
+
+mod brioche_test;
+
+fn normalized(recipe: Recipe) -> Recipe {
+    normalize_recipe(without_meta(recipe)).value
+}
+
+#[tokio::test]
+async fn test_bake_normalize_nested_merges() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hi_blob = brioche_test::blob(&brioche, "hi").await;
+
+    let hello_dir = brioche_test::lazy_dir([("hello", brioche_test::lazy_file(hello_blob, false))]);
+    let hi_dir = brioche_test::lazy_dir([("hi", brioche_test::lazy_file(hi_blob, false))]);
+
+    let flat_merge = Recipe::Merge {
+        directories: vec![
+            without_meta(hello_dir.clone()),
+            without_meta(hi_dir.clone()),
+        ],
+    };
+    let nested_merge = Recipe::Merge {
+        directories: vec![
+            without_meta(brioche_test::lazy_dir_empty()),
+            without_meta(Recipe::Merge {
+                directories: vec![without_meta(hello_dir.clone())],
+            }),
+            without_meta(Recipe::Merge {
+                directories: vec![
+                    without_meta(hi_dir.clone()),
+                    without_meta(brioche_test::lazy_dir_empty()),
+                ],
+            }),
+        ],
+    };
+
+    assert_ne!(flat_merge.hash(), nested_merge.hash());
+    assert_eq!(
+        normalized(flat_merge.clone()),
+        normalized(nested_merge.clone())
+    );
+
+    let expected = brioche_test::dir(
+        &brioche,
+        [
+            ("hello", brioche_test::file(hello_blob, false)),
+            ("hi", brioche_test::file(hi_blob, false)),
+        ],
+    )
+    .await;
+    assert_eq!(bake_without_meta(&brioche, flat_merge).await?, expected);
+    assert_eq!(bake_without_meta(&brioche, nested_merge).await?, expected);
+
+    // A merge of a single directory is just the directory
+    let single_merge = Recipe::Merge {
+        directories: vec![without_meta(hello_dir.clone())],
+    };
+    assert_eq!(normalized(single_merge), normalized(hello_dir));
+
+    // Merging nothing is an empty directory
+    let empty_merge = Recipe::Merge {
+        directories: vec![without_meta(brioche_test::lazy_dir_empty())],
+    };
+    assert_eq!(
+        normalized(empty_merge),
+        Recipe::Directory(brioche_test::empty_dir_value()),
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_normalize_nested_create_directory() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_file = brioche_test::lazy_file(hello_blob, false);
+
+    let nested_dir = brioche_test::lazy_dir([
+        (
+            "a",
+            brioche_test::lazy_dir([("b", brioche_test::lazy_dir([("c", hello_file.clone())]))]),
+        ),
+        ("d", hello_file.clone()),
+    ]);
+    let flat_dir =
+        brioche_test::lazy_dir([("a/b/c", hello_file.clone()), ("d", hello_file.clone())]);
+
+    assert_ne!(nested_dir.hash(), flat_dir.hash());
+    assert_eq!(normalized(nested_dir.clone()), normalized(flat_dir.clone()));
+    assert_eq!(
+        bake_without_meta(&brioche, nested_dir).await?,
+        bake_without_meta(&brioche, flat_dir).await?,
+    );
+
+    // Overlapping paths should be left alone, so conflicts still fail
+    let conflicting_dir = brioche_test::lazy_dir([
+        ("a", brioche_test::lazy_dir([("b", hello_file.clone())])),
+        ("a/c", hello_file.clone()),
+    ]);
+    assert_eq!(normalized(conflicting_dir.clone()), conflicting_dir);
+    assert!(bake_without_meta(&brioche, conflicting_dir).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_normalize_file_operations() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+
+    let set_permissions = Recipe::SetPermissions {
+        file: Box::new(without_meta(Recipe::SetPermissions {
+            file: Box::new(without_meta(brioche_test::lazy_file(hello_blob, false))),
+            executable: Some(false),
+        })),
+        executable: Some(true),
+    };
+    let cast = Recipe::Cast {
+        recipe: Box::new(without_meta(set_permissions.clone())),
+        to: ArtifactDiscriminants::File,
+    };
+
+    assert_eq!(
+        normalized(set_permissions.clone()),
+        brioche_test::lazy_file(hello_blob, true),
+    );
+    assert_eq!(
+        normalized(cast.clone()),
+        brioche_test::lazy_file(hello_blob, true),
+    );
+    assert_eq!(
+        bake_without_meta(&brioche, cast).await?,
+        brioche_test::file(hello_blob, true),
+    );
+
+    // Casting to a different type should still fail
+    let bad_cast = Recipe::Cast {
+        recipe: Box::new(without_meta(set_permissions)),
+        to: ArtifactDiscriminants::Directory,
+    };
+    assert!(matches!(normalized(bad_cast.clone()), Recipe::Cast { .. }));
+    assert!(bake_without_meta(&brioche, bad_cast).await.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use brioche_core::recipe::{Recipe, WithMeta};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+async fn patch_recipe(brioche: &brioche_core::Brioche, directory: Recipe, patch: &str) -> Recipe {
+    let patch_blob = brioche_test::blob(brioche, patch).await;
+    Recipe::Patch {
+        directory: Box::new(WithMeta::without_meta(directory)),
+        patches: vec![WithMeta::without_meta(brioche_test::lazy_file(
+            patch_blob, false,
+        ))],
+        strip: 1,
+    }
+}
+
+#[tokio::test]
+async fn test_bake_patch() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let directory = brioche_test::lazy_dir([
+        (
+            "src",
+            brioche_test::lazy_dir([(
+                "hello.sh",
+                brioche_test::lazy_file(
+                    brioche_test::blob(&brioche, "#!/bin/sh\necho one\necho two\necho three\n")
+                        .await,
+                    true,
+                ),
+            )]),
+        ),
+        (
+            "old.txt",
+            brioche_test::lazy_file(brioche_test::blob(&brioche, "old\n").await, false),
+        ),
+    ]);
+
+    // The first hunk's line numbers are off by one, which is allowed
+    let patch = patch_recipe(
+        &brioche,
+        directory,
+        r#"diff --git a/src/hello.sh b/src/hello.sh
+--- a/src/hello.sh
++++ b/src/hello.sh
+@@ -2,3 +2,3 @@
+ echo one
+-echo two
++echo 2
+ echo three
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-old
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++new
+\ No newline at end of file
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        bake_without_meta(&brioche, patch).await?,
+        brioche_test::dir(
+            &brioche,
+            [
+                (
+                    "src",
+                    brioche_test::dir(
+                        &brioche,
+                        [(
+                            "hello.sh",
+                            brioche_test::file(
+                                brioche_test::blob(
+                                    &brioche,
+                                    "#!/bin/sh\necho one\necho 2\necho three\n"
+                                )
+                                .await,
+                                true
+                            ),
+                        )]
+                    )
+                    .await,
+                ),
+                (
+                    "new.txt",
+                    brioche_test::file(brioche_test::blob(&brioche, "new").await, false),
+                ),
+            ]
+        )
+        .await,
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_patch_detects_fuzz() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let directory = brioche_test::lazy_dir([(
+        "hello.txt",
+        brioche_test::lazy_file(brioche_test::blob(&brioche, "a\nb\nc\nd\ne\n").await, false),
+    )]);
+
+    // The leading context line doesn't match, so the hunk would only
+    // apply with fuzz
+    let patch = patch_recipe(
+        &brioche,
+        directory,
+        r#"--- a/hello.txt
++++ b/hello.txt
+@@ -1,5 +1,5 @@
+ x
+ b
+-c
++C
+ d
+ e
+"#,
+    )
+    .await;
+
+    let error = bake_without_meta(&brioche, patch).await.unwrap_err();
+    assert!(
+        format!("{error:#}").contains("fuzz 1"),
+        "unexpected error: {error:#}"
+    );
+
+    Ok(())
+}
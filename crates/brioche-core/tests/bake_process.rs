@@ -7,10 +7,11 @@ use assert_matches::assert_matches;
 use pretty_assertions::assert_eq;
 
 use brioche_core::{
+    error_codes::{error_code, ErrorCode},
     platform::current_platform,
     recipe::{
-        ArchiveFormat, Artifact, CompressionFormat, Directory, DownloadRecipe, File, ProcessRecipe,
-        ProcessTemplate, ProcessTemplateComponent, Recipe, Unarchive, WithMeta,
+        ArchiveFormat, Artifact, CachePolicy, CompressionFormat, Directory, DownloadRecipe, File,
+        ProcessRecipe, ProcessTemplate, ProcessTemplateComponent, Recipe, Unarchive, WithMeta,
     },
     Hash,
 };
@@ -134,6 +135,12 @@ fn default_process() -> ProcessRecipe {
         platform: current_platform(),
         is_unsafe: false,
         networking: false,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: Default::default(),
+        incremental_key: None,
     }
 }
 
@@ -174,6 +181,7 @@ async fn test_bake_process() -> anyhow::Result<()> {
             test_bake_process_cached_equivalent_inputs_parallel
         ),
         run_test!(brioche_test, test_bake_process_cache_busted),
+        run_test!(brioche_test, test_bake_process_no_cache),
         run_test!(brioche_test, test_bake_process_custom_env_vars),
         run_test!(brioche_test, test_bake_process_no_default_env_vars),
         run_test!(brioche_test, test_bake_process_no_default_path),
@@ -192,7 +200,13 @@ async fn test_bake_process() -> anyhow::Result<()> {
         run_test!(brioche_test, test_bake_process_networking_disabled),
         run_test!(brioche_test, test_bake_process_networking_enabled),
         run_test!(brioche_test, test_bake_process_networking_enabled_dns),
+        run_test!(brioche_test, test_bake_process_fixed_output),
+        run_test!(brioche_test, test_bake_process_capture_stdout),
+        run_test!(brioche_test, test_bake_process_tty),
         run_test!(brioche_test, test_bake_process_dependencies),
+        run_test!(brioche_test, test_bake_process_replay),
+        run_test!(brioche_test, test_bake_process_identical_output),
+        run_test!(brioche_test, test_bake_process_incremental),
     ];
 
     let mut failures = 0;
@@ -663,6 +677,40 @@ async fn test_bake_process_cache_busted(
     Ok(())
 }
 
+async fn test_bake_process_no_cache(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let process_random = Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![
+            tpl("sh"),
+            tpl("-c"),
+            tpl("cat /dev/urandom | head -c 1024 > $BRIOCHE_OUTPUT"),
+        ],
+        env: BTreeMap::from_iter([
+            ("BRIOCHE_OUTPUT".into(), output_path()),
+            (
+                "PATH".into(),
+                tpl_join([template_input(utils()), tpl("/bin")]),
+            ),
+            ("WATERMARK".into(), tpl("no-cache")),
+        ]),
+        cache_policy: CachePolicy::NoCache,
+        ..default_process()
+    });
+
+    // The process shouldn't use the cached result, so it should run again
+    // and produce a different output
+
+    let random_1 = bake_without_meta(brioche, process_random.clone()).await?;
+    let random_2 = bake_without_meta(brioche, process_random).await?;
+
+    assert_ne!(random_1, random_2);
+
+    Ok(())
+}
+
 async fn test_bake_process_custom_env_vars(
     brioche: &brioche_core::Brioche,
     _context: &brioche_test::TestContext,
@@ -1149,7 +1197,11 @@ async fn test_bake_process_unsafe_validation(
         ..default_process()
     });
 
-    assert_matches!(bake_without_meta(brioche, needs_unsafe).await, Err(_));
+    let result = bake_without_meta(brioche, needs_unsafe).await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::NETWORKING_REQUIRES_UNSAFE)
+    );
 
     let unnecessary_unsafe = Recipe::Process(ProcessRecipe {
         command: tpl("/usr/bin/env"),
@@ -1160,7 +1212,11 @@ async fn test_bake_process_unsafe_validation(
         ..default_process()
     });
 
-    assert_matches!(bake_without_meta(brioche, unnecessary_unsafe).await, Err(_));
+    let result = bake_without_meta(brioche, unnecessary_unsafe).await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::UNNECESSARY_UNSAFE)
+    );
 
     let necessary_unsafe = Recipe::Process(ProcessRecipe {
         command: tpl("/usr/bin/env"),
@@ -1301,6 +1357,127 @@ async fn test_bake_process_networking_enabled_dns(
     Ok(())
 }
 
+async fn test_bake_process_fixed_output(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let hello = brioche_test::file(brioche_test::blob(brioche, "hello").await, false);
+    let goodbye = brioche_test::file(brioche_test::blob(brioche, "goodbye").await, false);
+
+    let hello_process = |output_hash| {
+        Recipe::Process(ProcessRecipe {
+            command: tpl("/usr/bin/env"),
+            args: vec![tpl("sh"), tpl("-c"), tpl("echo -n hello > $BRIOCHE_OUTPUT")],
+            env: BTreeMap::from_iter([("BRIOCHE_OUTPUT".into(), output_path())]),
+            is_unsafe: false,
+            networking: true,
+            output_hash: Some(output_hash),
+            capture_stdout: false,
+            incremental_key: None,
+            ..default_process()
+        })
+    };
+
+    // Networking doesn't require `unsafe` when the output hash is fixed
+    assert_eq!(
+        bake_without_meta(brioche, hello_process(hello.hash())).await?,
+        hello,
+    );
+
+    // The output must match the declared hash
+    assert_matches!(
+        bake_without_meta(brioche, hello_process(goodbye.hash())).await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
+async fn test_bake_process_capture_stdout(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let hello_blob = brioche_test::blob(brioche, "hello").await;
+
+    let process = Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl("echo -n hello")],
+        capture_stdout: true,
+        ..default_process()
+    });
+
+    assert_eq!(
+        bake_without_meta(brioche, process).await?,
+        brioche_test::file(hello_blob, false),
+    );
+
+    // Capturing stdout can't be combined with an output scaffold
+    let process_with_scaffold = Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl("echo -n hello")],
+        output_scaffold: Some(Box::new(WithMeta::without_meta(
+            brioche_test::lazy_dir_empty(),
+        ))),
+        capture_stdout: true,
+        ..default_process()
+    });
+
+    assert_matches!(
+        bake_without_meta(brioche, process_with_scaffold).await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
+async fn test_bake_process_tty(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let yes_blob = brioche_test::blob(brioche, "yes").await;
+    let no_blob = brioche_test::blob(brioche, "no").await;
+
+    let process = |tty| {
+        Recipe::Process(ProcessRecipe {
+            command: tpl("/usr/bin/env"),
+            args: vec![
+                tpl("sh"),
+                tpl("-c"),
+                tpl(
+                    r#"if [ -t 1 ] && [ -t 2 ]; then echo -n yes > "$BRIOCHE_OUTPUT"; else echo -n no > "$BRIOCHE_OUTPUT"; fi"#,
+                ),
+            ],
+            env: BTreeMap::from_iter([("BRIOCHE_OUTPUT".into(), output_path())]),
+            tty,
+            ..default_process()
+        })
+    };
+
+    assert_eq!(
+        bake_without_meta(brioche, process(true)).await?,
+        brioche_test::file(yes_blob, false),
+    );
+    assert_eq!(
+        bake_without_meta(brioche, process(false)).await?,
+        brioche_test::file(no_blob, false),
+    );
+
+    // A TTY can't be combined with capturing stdout
+    let process_capture_stdout = Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl("echo -n hello")],
+        capture_stdout: true,
+        tty: true,
+        ..default_process()
+    });
+    assert_matches!(
+        bake_without_meta(brioche, process_capture_stdout).await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
 async fn test_bake_process_dependencies(
     brioche: &brioche_core::Brioche,
     _context: &brioche_test::TestContext,
@@ -1461,3 +1638,189 @@ async fn test_bake_process_dependencies(
 
     Ok(())
 }
+
+async fn test_bake_process_replay(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let process = |script: &str| {
+        Recipe::Process(ProcessRecipe {
+            command: tpl("/usr/bin/env"),
+            args: vec![tpl("sh"), tpl("-c"), tpl(script)],
+            env: BTreeMap::from_iter([("BRIOCHE_OUTPUT".into(), output_path())]),
+            ..default_process()
+        })
+    };
+    let complete_process_hash = |recipe: Recipe| async move {
+        let report = brioche_core::bake::process_inputs::process_inputs(
+            brioche,
+            WithMeta::without_meta(recipe),
+            &brioche_core::bake::BakeScope::Anonymous,
+        )
+        .await?;
+        anyhow::Ok(report.cache_key)
+    };
+
+    // A deterministic process produces the same output when replayed
+    let deterministic = process("echo -n replay > $BRIOCHE_OUTPUT");
+    let output = bake_without_meta(brioche, deterministic.clone()).await?;
+    let replay =
+        brioche_core::bake::replay_process(brioche, complete_process_hash(deterministic).await?)
+            .await?;
+    assert_eq!(replay.output, output);
+    assert_eq!(replay.cached_output, Some(output));
+
+    // A nondeterministic process produces a different output
+    let nondeterministic =
+        process("read uuid < /proc/sys/kernel/random/uuid; echo $uuid > $BRIOCHE_OUTPUT");
+    let output = bake_without_meta(brioche, nondeterministic.clone()).await?;
+    let replay =
+        brioche_core::bake::replay_process(brioche, complete_process_hash(nondeterministic).await?)
+            .await?;
+    assert_ne!(replay.output, output);
+    assert_eq!(replay.cached_output, Some(output));
+
+    // Processes that never ran can't be replayed
+    let never_run = process("echo -n never > $BRIOCHE_OUTPUT");
+    assert_matches!(
+        brioche_core::bake::replay_process(brioche, complete_process_hash(never_run).await?).await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
+async fn test_bake_process_identical_output(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let process = |script: &str, work_dir: Recipe| {
+        Recipe::Process(ProcessRecipe {
+            command: tpl("/usr/bin/env"),
+            args: vec![tpl("sh"), tpl("-c"), tpl(script)],
+            env: BTreeMap::from_iter([("BRIOCHE_OUTPUT".into(), output_path())]),
+            work_dir: Box::new(WithMeta::without_meta(work_dir)),
+            ..default_process()
+        })
+    };
+    let work_dir = |contents: brioche_core::blob::BlobHash| {
+        brioche_test::lazy_dir([("input.txt", brioche_test::lazy_file(contents, false))])
+    };
+    let complete_process = |recipe: Recipe| async move {
+        let report = brioche_core::bake::process_inputs::process_inputs(
+            brioche,
+            WithMeta::without_meta(recipe),
+            &brioche_core::bake::BakeScope::Anonymous,
+        )
+        .await?;
+        let recipe = brioche_core::recipe::get_recipe(brioche, report.cache_key).await?;
+        let Recipe::CompleteProcess(process) = recipe else {
+            anyhow::bail!("expected complete process recipe");
+        };
+        anyhow::Ok((report.cache_key, process))
+    };
+
+    let script = "echo -n identical > $BRIOCHE_OUTPUT";
+    let process_a = process(script, work_dir(brioche_test::blob(brioche, "a").await));
+    let process_b = process(script, work_dir(brioche_test::blob(brioche, "b").await));
+
+    bake_without_meta(brioche, process_a.clone()).await?;
+    let output = bake_without_meta(brioche, process_b.clone()).await?;
+
+    // Only the work dir changed, but the output didn't
+    let (process_a_hash, _) = complete_process(process_a).await?;
+    let (_, complete_process_b) = complete_process(process_b).await?;
+    let identical_output = brioche_core::bake::process_inputs::find_identical_output(
+        brioche,
+        &complete_process_b,
+        &output,
+    )
+    .await?
+    .context("expected identical output to be found")?;
+    assert_eq!(identical_output.previous_cache_key, process_a_hash);
+    assert_eq!(identical_output.changed_inputs.len(), 1);
+    assert_eq!(identical_output.changed_inputs[0].location, "work_dir");
+
+    // A process with a different script isn't equivalent, even if it has
+    // the same output
+    let process_c = process(
+        "printf identical > $BRIOCHE_OUTPUT",
+        work_dir(brioche_test::blob(brioche, "a").await),
+    );
+    let output = bake_without_meta(brioche, process_c.clone()).await?;
+    let (_, complete_process_c) = complete_process(process_c).await?;
+    let identical_output = brioche_core::bake::process_inputs::find_identical_output(
+        brioche,
+        &complete_process_c,
+        &output,
+    )
+    .await?;
+    assert!(identical_output.is_none());
+
+    Ok(())
+}
+
+async fn test_bake_process_incremental(
+    brioche: &brioche_core::Brioche,
+    _context: &brioche_test::TestContext,
+) -> anyhow::Result<()> {
+    let process = |input: &str| {
+        Recipe::Process(ProcessRecipe {
+            command: tpl("/usr/bin/env"),
+            args: vec![
+                tpl("sh"),
+                tpl("-c"),
+                tpl(r#"echo -n "$input," >> "$BRIOCHE_OUTPUT""#),
+            ],
+            env: BTreeMap::from_iter([
+                ("BRIOCHE_OUTPUT".into(), output_path()),
+                ("input".into(), tpl(input)),
+            ]),
+            incremental_key: Some("test_bake_process_incremental".to_string()),
+            ..default_process()
+        })
+    };
+
+    let a_blob = brioche_test::blob(brioche, "a,").await;
+    let ab_blob = brioche_test::blob(brioche, "a,b,").await;
+    let abc_blob = brioche_test::blob(brioche, "a,b,c,").await;
+
+    // The first bake has no previous output
+    assert_eq!(
+        bake_without_meta(brioche, process("a")).await?,
+        brioche_test::file(a_blob, false),
+    );
+
+    // Each bake after that starts from the previous output
+    assert_eq!(
+        bake_without_meta(brioche, process("b")).await?,
+        brioche_test::file(ab_blob, false),
+    );
+
+    // Baking with the same inputs again is a cache hit, rather than
+    // running again on top of its own output
+    assert_eq!(
+        bake_without_meta(brioche, process("b")).await?,
+        brioche_test::file(ab_blob, false),
+    );
+
+    assert_eq!(
+        bake_without_meta(brioche, process("c")).await?,
+        brioche_test::file(abc_blob, false),
+    );
+
+    // Incremental processes can't capture stdout
+    let process_capture_stdout = Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl("echo -n hello")],
+        capture_stdout: true,
+        incremental_key: Some("test_bake_process_incremental_stdout".to_string()),
+        ..default_process()
+    });
+    assert_matches!(
+        bake_without_meta(brioche, process_capture_stdout).await,
+        Err(_)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::BTreeMap;
+
+use brioche_core::{
+    error_codes::{error_code, ErrorCode},
+    platform::current_platform,
+    recipe::{
+        Directory, ProcessRecipe, ProcessTemplate, ProcessTemplateComponent, Recipe, WithMeta,
+    },
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+const FAILURE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+fn tpl(s: impl AsRef<[u8]>) -> ProcessTemplate {
+    ProcessTemplate {
+        components: vec![ProcessTemplateComponent::Literal {
+            value: s.as_ref().into(),
+        }],
+    }
+}
+
+fn process(script: &str) -> Recipe {
+    Recipe::Process(ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl(script)],
+        env: BTreeMap::from_iter([(
+            "BRIOCHE_OUTPUT".into(),
+            ProcessTemplate {
+                components: vec![ProcessTemplateComponent::OutputPath],
+            },
+        )]),
+        dependencies: vec![],
+        work_dir: Box::new(WithMeta::without_meta(Recipe::Directory(
+            Directory::default(),
+        ))),
+        output_scaffold: None,
+        platform: current_platform(),
+        is_unsafe: false,
+        networking: false,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: Default::default(),
+        incremental_key: None,
+    })
+}
+
+#[tokio::test]
+async fn test_bake_process_failure_cache() -> anyhow::Result<()> {
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.failed_process_cache_ttl(FAILURE_TTL))
+            .await;
+
+    let failing = process("echo -n hello > $BRIOCHE_OUTPUT && exit 1");
+
+    let error = bake_without_meta(&brioche, failing.clone())
+        .await
+        .expect_err("expected process to fail");
+    assert_eq!(error_code(&error), Some(ErrorCode::PROCESS_FAILED));
+    assert!(!format!("{error:#}").contains("already failed"));
+
+    // The second bake fails from the cached failure
+    let error = bake_without_meta(&brioche, failing.clone())
+        .await
+        .expect_err("expected cached failure");
+    assert_eq!(error_code(&error), Some(ErrorCode::PROCESS_FAILED));
+    assert!(format!("{error:#}").contains("already failed"));
+
+    // Retrying runs the process again
+    let home = brioche.home.clone();
+    let (retrying_brioche, _retrying_context) = brioche_test::brioche_test_with(|builder| {
+        builder
+            .home(home)
+            .failed_process_cache_ttl(FAILURE_TTL)
+            .retry_failed(true)
+    })
+    .await;
+    let error = bake_without_meta(&retrying_brioche, failing)
+        .await
+        .expect_err("expected process to fail");
+    assert!(!format!("{error:#}").contains("already failed"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_process_failure_cache_disabled() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let failing = process("exit 1");
+    for _ in 0..2 {
+        let error = bake_without_meta(&brioche, failing.clone())
+            .await
+            .expect_err("expected process to fail");
+        assert!(!format!("{error:#}").contains("already failed"));
+    }
+
+    Ok(())
+}
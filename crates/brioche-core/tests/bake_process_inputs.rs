@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use assert_matches::assert_matches;
+use brioche_core::{
+    bake::{
+        process_inputs::{process_inputs, ProcessInputValue},
+        BakeScope,
+    },
+    platform::{current_platform, Platform},
+    recipe::{
+        Directory, ProcessRecipe, ProcessTemplate, ProcessTemplateComponent, Recipe, WithMeta,
+    },
+};
+
+mod brioche_test;
+
+fn tpl(s: impl AsRef<[u8]>) -> ProcessTemplate {
+    ProcessTemplate {
+        components: vec![ProcessTemplateComponent::Literal {
+            value: s.as_ref().into(),
+        }],
+    }
+}
+
+fn process_with_input(input: Recipe) -> ProcessRecipe {
+    ProcessRecipe {
+        command: tpl("/bin/sh"),
+        args: vec![
+            tpl("-c"),
+            ProcessTemplate {
+                components: vec![
+                    ProcessTemplateComponent::Input {
+                        recipe: WithMeta::without_meta(input),
+                    },
+                    ProcessTemplateComponent::Literal {
+                        value: "/build.sh".into(),
+                    },
+                ],
+            },
+        ],
+        env: BTreeMap::from_iter([("FOO".into(), tpl("bar"))]),
+        dependencies: vec![],
+        work_dir: Box::new(WithMeta::without_meta(Recipe::Directory(
+            Directory::default(),
+        ))),
+        output_scaffold: None,
+        platform: current_platform(),
+        is_unsafe: false,
+        networking: false,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: Default::default(),
+        incremental_key: None,
+    }
+}
+
+#[tokio::test]
+async fn test_bake_process_inputs() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let source = brioche_test::lazy_dir([(
+        "build.sh",
+        brioche_test::lazy_file(brioche_test::blob(&brioche, "echo hi").await, false),
+    )]);
+    let source_artifact = brioche_test::bake_without_meta(&brioche, source.clone()).await?;
+
+    let process = process_with_input(source.clone());
+    let report = process_inputs(
+        &brioche,
+        WithMeta::without_meta(Recipe::Process(process)),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+
+    let inputs = report
+        .inputs
+        .iter()
+        .map(|input| (input.location.as_str(), &input.value))
+        .collect::<Vec<_>>();
+    assert_matches!(inputs[0], ("command", ProcessInputValue::Literal(value)) if value == "/bin/sh");
+    assert_matches!(inputs[1], ("args[0]", ProcessInputValue::Literal(value)) if value == "-c");
+    assert_matches!(inputs[2], ("args[1][0]", ProcessInputValue::Artifact { hash, .. }) if *hash == source_artifact.hash());
+    assert_matches!(inputs[3], ("args[1][1]", ProcessInputValue::Literal(value)) if value == "/build.sh");
+    assert_matches!(inputs[4], ("env.FOO", ProcessInputValue::Literal(value)) if value == "bar");
+    assert_matches!(inputs[5], ("work_dir", ProcessInputValue::Artifact { .. }));
+    assert_matches!(inputs[6], ("platform", ProcessInputValue::Setting(_)));
+    assert_eq!(inputs.len(), 7);
+
+    // Changing an input changes the cache key
+    let other_source = brioche_test::lazy_dir([(
+        "build.sh",
+        brioche_test::lazy_file(brioche_test::blob(&brioche, "echo bye").await, false),
+    )]);
+    let other_report = process_inputs(
+        &brioche,
+        WithMeta::without_meta(Recipe::Process(process_with_input(other_source))),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+    assert_ne!(report.cache_key, other_report.cache_key);
+
+    // Non-process recipes are rejected
+    assert_matches!(
+        process_inputs(
+            &brioche,
+            WithMeta::without_meta(source),
+            &BakeScope::Anonymous
+        )
+        .await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
+fn foreign_platform() -> Platform {
+    match current_platform() {
+        Platform::X86_64Linux => Platform::Aarch64Linux,
+        Platform::Aarch64Linux => Platform::X86_64Linux,
+    }
+}
+
+#[tokio::test]
+async fn test_bake_process_inputs_with_emulator() -> anyhow::Result<()> {
+    let emulators_dir = tempdir::TempDir::new("brioche-test-emulators")?;
+    let emulator_a = emulators_dir.path().join("qemu-a");
+    let emulator_b = emulators_dir.path().join("qemu-b");
+    tokio::fs::write(&emulator_a, "a").await?;
+    tokio::fs::write(&emulator_b, "b").await?;
+
+    let (brioche_a, _context_a) = brioche_test::brioche_test_with(|builder| {
+        builder.emulator(foreign_platform(), emulator_a.clone())
+    })
+    .await;
+    let (brioche_b, _context_b) = brioche_test::brioche_test_with(|builder| {
+        builder.emulator(foreign_platform(), emulator_b.clone())
+    })
+    .await;
+
+    let source = brioche_test::lazy_dir_empty();
+    let process = ProcessRecipe {
+        platform: foreign_platform(),
+        ..process_with_input(source)
+    };
+
+    let report_a = process_inputs(
+        &brioche_a,
+        WithMeta::without_meta(Recipe::Process(process.clone())),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+    let report_b = process_inputs(
+        &brioche_b,
+        WithMeta::without_meta(Recipe::Process(process.clone())),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+
+    let emulator_a_hash = blake3::hash(b"a").to_hex().to_string();
+    assert!(report_a.inputs.iter().any(|input| {
+        input.location == "emulator"
+            && matches!(
+                &input.value,
+                ProcessInputValue::Setting(value) if *value == format!("qemu-a {emulator_a_hash}")
+            )
+    }));
+
+    // The emulator identity is part of the cache key
+    assert_ne!(report_a.cache_key, report_b.cache_key);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bake_process_inputs_without_emulator() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    // Processes for the current platform never use an emulator
+    let report = process_inputs(
+        &brioche,
+        WithMeta::without_meta(Recipe::Process(process_with_input(
+            brioche_test::lazy_dir_empty(),
+        ))),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+    assert!(report
+        .inputs
+        .iter()
+        .all(|input| input.location != "emulator"));
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use assert_matches::assert_matches;
+use brioche_core::{
+    error_codes::{error_code, ErrorCode},
+    platform::current_platform,
+    recipe::{
+        Artifact, CachePolicy, Directory, ProcessRecipe, ProcessTemplate, ProcessTemplateComponent,
+        Recipe, WithMeta,
+    },
+    secrets::SecretSource,
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+const SECRET_ENV_VAR: &str = "BRIOCHE_TEST_SECRET_TOKEN";
+
+fn tpl(s: impl AsRef<[u8]>) -> ProcessTemplate {
+    ProcessTemplate {
+        components: vec![ProcessTemplateComponent::Literal {
+            value: s.as_ref().into(),
+        }],
+    }
+}
+
+fn output_path() -> ProcessTemplate {
+    ProcessTemplate {
+        components: vec![ProcessTemplateComponent::OutputPath],
+    }
+}
+
+fn process_with_secrets(script: &str, secrets: &[&str]) -> ProcessRecipe {
+    ProcessRecipe {
+        command: tpl("/usr/bin/env"),
+        args: vec![tpl("sh"), tpl("-c"), tpl(script)],
+        env: BTreeMap::from_iter([("BRIOCHE_OUTPUT".into(), output_path())]),
+        dependencies: vec![],
+        work_dir: Box::new(WithMeta::without_meta(Recipe::Directory(
+            Directory::default(),
+        ))),
+        output_scaffold: None,
+        platform: current_platform(),
+        is_unsafe: true,
+        networking: false,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: secrets.iter().map(|secret| secret.to_string()).collect(),
+        incremental_key: None,
+    }
+}
+
+#[tokio::test]
+async fn test_bake_process_secrets() -> anyhow::Result<()> {
+    std::env::set_var(SECRET_ENV_VAR, "hunter2");
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.secret(
+            "token".to_string(),
+            SecretSource::Env(SECRET_ENV_VAR.to_string()),
+        )
+    })
+    .await;
+
+    // The secret is mounted as a file in `$BRIOCHE_SECRETS_DIR`
+    let process = process_with_secrets(
+        r#"read -r token < "$BRIOCHE_SECRETS_DIR/token"; echo -n "$token" > "$BRIOCHE_OUTPUT""#,
+        &["token"],
+    );
+    let recipe = Recipe::Process(process.clone());
+    let output = bake_without_meta(&brioche, recipe.clone()).await?;
+    let Artifact::File(file) = output else {
+        anyhow::bail!("expected file output");
+    };
+    let content = brioche_test::blob(&brioche, "hunter2").await;
+    assert_eq!(file.content_blob, content);
+
+    // Only the secret's name is part of the recipe
+    let recipe_json = serde_json::to_string(&recipe)?;
+    assert!(!recipe_json.contains("hunter2"));
+
+    // Processes that use secrets without an output hash can't be uploaded
+    assert_eq!(recipe.cache_policy(), CachePolicy::LocalOnly);
+
+    // Using secrets requires `unsafe` (or an output hash)
+    let needs_unsafe = Recipe::Process(ProcessRecipe {
+        is_unsafe: false,
+        ..process_with_secrets("echo -n hello > $BRIOCHE_OUTPUT", &["token"])
+    });
+    let result = bake_without_meta(&brioche, needs_unsafe).await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::SECRETS_REQUIRE_UNSAFE)
+    );
+
+    let unknown_secret = Recipe::Process(process_with_secrets(
+        "echo -n hello > $BRIOCHE_OUTPUT",
+        &["unknown"],
+    ));
+    let result = bake_without_meta(&brioche, unknown_secret).await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::SECRET_NOT_CONFIGURED)
+    );
+
+    let invalid_name = Recipe::Process(process_with_secrets(
+        "echo -n hello > $BRIOCHE_OUTPUT",
+        &["../token"],
+    ));
+    assert_matches!(bake_without_meta(&brioche, invalid_name).await, Err(_));
+
+    // Without secrets, the process doesn't get a secrets directory
+    let no_secrets = Recipe::Process(ProcessRecipe {
+        secrets: BTreeSet::new(),
+        is_unsafe: false,
+        ..process_with_secrets(
+            r#"echo -n "${BRIOCHE_SECRETS_DIR:-none}" > "$BRIOCHE_OUTPUT""#,
+            &[],
+        )
+    });
+    let output = bake_without_meta(&brioche, no_secrets).await?;
+    let Artifact::File(file) = output else {
+        anyhow::bail!("expected file output");
+    };
+    let content = brioche_test::blob(&brioche, "none").await;
+    assert_eq!(file.content_blob, content);
+
+    Ok(())
+}
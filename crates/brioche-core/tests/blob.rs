@@ -0,0 +1,501 @@
+use std::os::unix::fs::PermissionsExt as _;
+
+use brioche_core::{
+    blob::{
+        get_save_blob_permit, BackfillAliasesOptions, CorruptBlobAction, MAX_CONCURRENT_BLOB_SAVES,
+    },
+    HashAlgorithm,
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_blob_save_concurrency_limit() -> anyhow::Result<()> {
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.max_concurrent_blob_saves(2)).await;
+    assert_eq!(brioche.save_blob_semaphore.available_permits(), 2);
+
+    let _first = get_save_blob_permit(&brioche).await?;
+    let _second = get_save_blob_permit(&brioche).await?;
+    let third = tokio::time::timeout(
+        std::time::Duration::from_millis(100),
+        get_save_blob_permit(&brioche),
+    )
+    .await;
+    assert!(third.is_err(), "expected third permit to wait");
+
+    let (reporter, _reporter_guard) = brioche_core::reporter::start_test_reporter();
+    let result = brioche_core::BriocheBuilder::new(reporter)
+        .home(brioche.home.clone())
+        .max_concurrent_processes(0)
+        .build()
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_path_existing_without_permit() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob_hash = brioche_test::blob(&brioche, "hello").await;
+
+    // Hold every save permit, so getting the path would block if it
+    // needed a permit
+    let mut permits = vec![];
+    for _ in 0..MAX_CONCURRENT_BLOB_SAVES {
+        permits.push(get_save_blob_permit(&brioche).await?);
+    }
+
+    let local_path = brioche_core::blob::try_local_blob_path(&brioche, blob_hash).await?;
+    assert_eq!(
+        local_path,
+        Some(brioche_core::blob::local_blob_path(&brioche, blob_hash).await?),
+    );
+
+    let path = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        brioche_core::blob::blob_path(&brioche, blob_hash),
+    )
+    .await
+    .expect("getting the path of an existing blob should not wait for a permit")?;
+    assert_eq!(tokio::fs::read(&path).await?, b"hello");
+
+    let missing_hash = brioche_core::blob::BlobHash::for_content(b"missing");
+    let missing_path = brioche_core::blob::try_local_blob_path(&brioche, missing_hash).await?;
+    assert_eq!(missing_path, None);
+
+    drop(permits);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_save_from_file_progress() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    for remove_input in [false, true] {
+        let content = format!("hello world, remove_input={remove_input}");
+        let input_path = context.write_file("input", content.as_bytes()).await;
+
+        let mut progress = vec![];
+        let permit = get_save_blob_permit(&brioche).await?;
+        brioche_core::blob::save_blob_from_file(
+            &brioche,
+            permit,
+            &input_path,
+            brioche_core::blob::SaveBlobOptions::new()
+                .remove_input(remove_input)
+                .on_progress(|bytes_read| {
+                    progress.push(bytes_read);
+                    Ok(())
+                }),
+        )
+        .await?;
+
+        assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(progress.last(), Some(&content.len()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_durable_copy() -> anyhow::Result<()> {
+    let (_brioche, context) = brioche_test::brioche_test().await;
+
+    let source = context.write_file("source", "hello").await;
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(946_684_800);
+    std::fs::File::open(&source)?.set_modified(mtime)?;
+
+    let dest = context.mkdir("dest").await.join("file");
+    brioche_core::fs_utils::durable_copy(&source, &dest).await?;
+
+    assert_eq!(tokio::fs::read(&dest).await?, b"hello");
+    assert_eq!(tokio::fs::metadata(&dest).await?.modified()?, mtime);
+
+    // Only the final file is left in the destination directory
+    let mut entries = tokio::fs::read_dir(context.path("dest")).await?;
+    let mut entry_names = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        entry_names.push(entry.file_name());
+    }
+    assert_eq!(entry_names, ["file"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_reflink_or_copy() -> anyhow::Result<()> {
+    let (_brioche, context) = brioche_test::brioche_test().await;
+
+    let source = context.write_file("source", "hello").await;
+    tokio::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o755)).await?;
+
+    // Reflinks are only used if the test's temp directory supports them,
+    // but the result should be the same either way
+    let dest = context.path("dest");
+    brioche_core::fs_utils::reflink_or_copy(&source, &dest).await?;
+
+    assert_eq!(tokio::fs::read(&dest).await?, b"hello");
+    let dest_mode = tokio::fs::metadata(&dest).await?.permissions().mode();
+    assert_eq!(dest_mode & 0o777, 0o755);
+
+    // Changing the copy doesn't change the source
+    tokio::fs::write(&dest, "goodbye").await?;
+    assert_eq!(tokio::fs::read(&source).await?, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_save_with_cross_device_temp() -> anyhow::Result<()> {
+    // Use a tmpfs for `blobs-temp`, which is usually on a different
+    // filesystem from the test's temp directory
+    let Ok(shm_temp) = tempdir::TempDir::new_in("/dev/shm", "brioche-test-blobs-temp") else {
+        return Ok(());
+    };
+    let home_temp = tempdir::TempDir::new("brioche-test-home")?;
+    let brioche_home = home_temp.path().join("brioche-home");
+    tokio::fs::create_dir_all(brioche_home.join("blobs")).await?;
+    tokio::fs::symlink(shm_temp.path(), brioche_home.join("blobs-temp")).await?;
+
+    let blobs_dev = std::os::unix::fs::MetadataExt::dev(
+        &tokio::fs::metadata(brioche_home.join("blobs")).await?,
+    );
+    let shm_dev = std::os::unix::fs::MetadataExt::dev(&tokio::fs::metadata(shm_temp.path()).await?);
+    if blobs_dev == shm_dev {
+        return Ok(());
+    }
+
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.home(brioche_home.clone())).await;
+    assert!(brioche.blobs_temp_cross_device);
+
+    // Save a blob from bytes and from a reader, which both write to
+    // `blobs-temp` first
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let permit = get_save_blob_permit(&brioche).await?;
+    let world_blob = brioche_core::blob::save_blob_from_reader(
+        &brioche,
+        permit,
+        &b"world"[..],
+        brioche_core::blob::SaveBlobOptions::new(),
+    )
+    .await?;
+
+    let hello_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    let world_path = brioche_core::blob::local_blob_path(&brioche, world_blob).await?;
+    assert_eq!(tokio::fs::read(&hello_path).await?, b"hello");
+    assert_eq!(tokio::fs::read(&world_path).await?, b"world");
+    assert_eq!(
+        tokio::fs::metadata(&hello_path).await?.modified()?,
+        brioche_core::fs_utils::brioche_epoch()
+    );
+
+    // The temp files are cleaned up after copying
+    let mut temp_entries = tokio::fs::read_dir(shm_temp.path()).await?;
+    assert!(temp_entries.next_entry().await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_secondary_dirs() -> anyhow::Result<()> {
+    let secondary_temp = tempdir::TempDir::new("brioche-test-secondary-blobs")?;
+    let secondary_blobs_dir = secondary_temp.path().to_owned();
+
+    let shared_hash = brioche_core::blob::BlobHash::for_content(b"shared");
+    let secondary_path = secondary_blobs_dir.join(hex::encode(shared_hash.to_blake3().as_bytes()));
+    tokio::fs::write(&secondary_path, "shared").await?;
+
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.secondary_blob_dirs([secondary_blobs_dir.clone()])
+    })
+    .await;
+
+    // Blobs in a secondary directory are used in place
+    assert_eq!(
+        brioche_core::blob::local_blob_path(&brioche, shared_hash).await?,
+        secondary_path,
+    );
+    assert_eq!(
+        brioche_core::blob::try_local_blob_path(&brioche, shared_hash).await?,
+        Some(secondary_path.clone()),
+    );
+    assert_eq!(
+        brioche_core::blob::blob_path(&brioche, shared_hash).await?,
+        secondary_path,
+    );
+
+    // Saving a blob that's already in a secondary directory doesn't copy
+    // it into the primary store
+    let saved_hash = brioche_test::blob(&brioche, "shared").await;
+    assert_eq!(saved_hash, shared_hash);
+    let primary_path = brioche_core::blob::primary_blob_path(&brioche, shared_hash);
+    assert!(!tokio::fs::try_exists(&primary_path).await?);
+
+    // New blobs are saved to the primary store
+    let hello_hash = brioche_test::blob(&brioche, "hello").await;
+    let hello_path = brioche_core::blob::local_blob_path(&brioche, hello_hash).await?;
+    assert_eq!(
+        hello_path,
+        brioche_core::blob::primary_blob_path(&brioche, hello_hash)
+    );
+    assert_eq!(tokio::fs::read(&hello_path).await?, b"hello");
+    let mut secondary_entries = tokio::fs::read_dir(&secondary_blobs_dir).await?;
+    let mut num_secondary_entries = 0;
+    while secondary_entries.next_entry().await?.is_some() {
+        num_secondary_entries += 1;
+    }
+    assert_eq!(num_secondary_entries, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_save_with_durability() -> anyhow::Result<()> {
+    for durability in [
+        brioche_core::blob::Durability::None,
+        brioche_core::blob::Durability::Metadata,
+        brioche_core::blob::Durability::Full,
+    ] {
+        let (brioche, context) =
+            brioche_test::brioche_test_with(|builder| builder.durability(durability)).await;
+        assert_eq!(brioche.durability, durability);
+
+        let hello_blob = brioche_test::blob(&brioche, "hello").await;
+        let hello_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+        assert_eq!(tokio::fs::read(&hello_path).await?, b"hello");
+
+        let input_path = context.write_file("input", "world").await;
+        let permit = get_save_blob_permit(&brioche).await?;
+        let world_blob = brioche_core::blob::save_blob_from_file(
+            &brioche,
+            permit,
+            &input_path,
+            brioche_core::blob::SaveBlobOptions::new().remove_input(true),
+        )
+        .await?;
+        let world_path = brioche_core::blob::local_blob_path(&brioche, world_blob).await?;
+        assert_eq!(tokio::fs::read(&world_path).await?, b"world");
+        assert!(!input_path.exists());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_blob_durability_from_str() {
+    assert_eq!(
+        "none".parse::<brioche_core::blob::Durability>().unwrap(),
+        brioche_core::blob::Durability::None
+    );
+    assert_eq!(
+        "metadata"
+            .parse::<brioche_core::blob::Durability>()
+            .unwrap(),
+        brioche_core::blob::Durability::Metadata
+    );
+    assert_eq!(
+        "full".parse::<brioche_core::blob::Durability>().unwrap(),
+        brioche_core::blob::Durability::Full
+    );
+    assert_eq!(
+        brioche_core::blob::Durability::default().to_string(),
+        "metadata"
+    );
+}
+
+#[tokio::test]
+async fn test_blob_verify() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_alias = brioche_test::sha256("hello");
+    let permit = get_save_blob_permit(&brioche).await?;
+    let hello_blob = brioche_core::blob::save_blob(
+        &brioche,
+        permit,
+        b"hello",
+        brioche_core::blob::SaveBlobOptions::new().expected_hash(Some(hello_alias.clone())),
+    )
+    .await?;
+    let world_blob = brioche_test::blob(&brioche, "world").await;
+
+    let report = brioche_core::blob::verify_blobs(&brioche, CorruptBlobAction::Report).await?;
+    assert_eq!(report.num_blobs, 2);
+    assert!(!report.has_problems());
+
+    // Corrupt a blob, and add a file that isn't named after its hash
+    let hello_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    tokio::fs::remove_file(&hello_path).await?;
+    tokio::fs::write(&hello_path, "corrupted").await?;
+    let stray_path = brioche.home.join("blobs").join("stray");
+    tokio::fs::write(&stray_path, "stray").await?;
+
+    // Only reporting leaves everything in place
+    let report = brioche_core::blob::verify_blobs(&brioche, CorruptBlobAction::Report).await?;
+    assert_eq!(report.num_blobs, 3);
+    let corrupt_paths = report
+        .corrupt_blobs
+        .iter()
+        .map(|corrupt_blob| corrupt_blob.path.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(corrupt_paths, [hello_path.clone(), stray_path.clone()]);
+    assert_eq!(report.corrupt_blobs[0].expected_hash, Some(hello_blob));
+    assert_eq!(report.corrupt_blobs[1].expected_hash, None);
+    assert_eq!(report.broken_aliases.len(), 1);
+    assert_eq!(report.broken_aliases[0].hash, hello_alias.to_string());
+    assert!(hello_path.exists());
+    assert!(brioche_core::blob::find_blob(&brioche, &hello_alias)
+        .await?
+        .is_some());
+
+    // Quarantining moves corrupt blobs and removes broken aliases
+    let report = brioche_core::blob::verify_blobs(&brioche, CorruptBlobAction::Quarantine).await?;
+    assert_eq!(report.corrupt_blobs.len(), 2);
+    let quarantine_path = report.corrupt_blobs[0]
+        .quarantine_path
+        .clone()
+        .expect("blob was not quarantined");
+    assert_eq!(tokio::fs::read(&quarantine_path).await?, b"corrupted");
+    assert!(!hello_path.exists());
+    assert!(!stray_path.exists());
+    assert!(brioche_core::blob::find_blob(&brioche, &hello_alias)
+        .await?
+        .is_none());
+
+    let report = brioche_core::blob::verify_blobs(&brioche, CorruptBlobAction::Report).await?;
+    assert_eq!(report.num_blobs, 1);
+    assert!(!report.has_problems());
+
+    let world_path = brioche_core::blob::local_blob_path(&brioche, world_blob).await?;
+    assert_eq!(tokio::fs::read(&world_path).await?, b"world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_verify_remove() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    tokio::fs::remove_file(&hello_path).await?;
+    tokio::fs::write(&hello_path, "corrupted").await?;
+
+    let report = brioche_core::blob::verify_blobs(&brioche, CorruptBlobAction::Remove).await?;
+    assert_eq!(report.corrupt_blobs.len(), 1);
+    assert_eq!(report.corrupt_blobs[0].quarantine_path, None);
+    assert!(!hello_path.exists());
+
+    // The blob is saved again the next time it's needed
+    let hello_blob_again = brioche_test::blob(&brioche, "hello").await;
+    assert_eq!(hello_blob_again, hello_blob);
+    assert_eq!(tokio::fs::read(&hello_path).await?, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_backfill_aliases() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hi_blob = brioche_test::blob(&brioche, "hi").await;
+
+    let hello_sha1 = "sha1-qvTGHdzF6KLavt4PO0gs2a6pQ00=".parse()?;
+    let hello_sha256 = brioche_test::sha256("hello");
+    assert_eq!(
+        brioche_core::blob::find_blob(&brioche, &hello_sha256).await?,
+        None
+    );
+
+    let options = BackfillAliasesOptions {
+        algorithms: vec![HashAlgorithm::Sha256, HashAlgorithm::Sha1],
+        max_bytes_per_second: Some(1_000_000),
+    };
+    let report = brioche_core::blob::backfill_blob_aliases(&brioche, &options).await?;
+    assert_eq!(report.num_blobs, 2);
+    assert_eq!(report.num_blobs_hashed, 2);
+    assert_eq!(report.num_aliases_added, 4);
+    assert_eq!(report.bytes_hashed, 7);
+
+    assert_eq!(
+        brioche_core::blob::find_blob(&brioche, &hello_sha256).await?,
+        Some(hello_blob)
+    );
+    assert_eq!(
+        brioche_core::blob::find_blob(&brioche, &hello_sha1).await?,
+        Some(hello_blob)
+    );
+    assert_eq!(
+        brioche_core::blob::find_blob(&brioche, &brioche_test::sha256("hi")).await?,
+        Some(hi_blob)
+    );
+
+    // Blobs that already have aliases aren't hashed again
+    let report = brioche_core::blob::backfill_blob_aliases(&brioche, &options).await?;
+    assert_eq!(report.num_blobs_hashed, 0);
+    assert_eq!(report.num_aliases_added, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_prefetch_blobs() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let contents = [&b"hello"[..], b"world", b"!"];
+    let blob_hashes = contents
+        .iter()
+        .map(|content| brioche_core::blob::BlobHash::for_content(content))
+        .collect::<Vec<_>>();
+
+    let known_blobs_mock = context
+        .registry_server
+        .mock(
+            "POST",
+            &*format!("/v0/known-blobs?brioche={}", brioche_core::VERSION),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(serde_json::to_string(&blob_hashes)?)
+        .create();
+    let mut blob_mocks = vec![];
+    for (content, blob_hash) in contents.iter().zip(&blob_hashes) {
+        let mock = context
+            .registry_server
+            .mock(
+                "GET",
+                &*format!(
+                    "/v0/blobs/{blob_hash}.zst?brioche={}",
+                    brioche_core::VERSION
+                ),
+            )
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(zstd::encode_all(*content, 0)?)
+            .expect(1)
+            .create();
+        blob_mocks.push(mock);
+    }
+
+    // Each blob is fetched once, even if listed multiple times or
+    // prefetched again
+    let mut requested_blobs = blob_hashes.clone();
+    requested_blobs.push(blob_hashes[0]);
+    brioche_core::blob::prefetch_blobs(&brioche, &requested_blobs).await?;
+    brioche_core::blob::prefetch_blobs(&brioche, &requested_blobs).await?;
+
+    for (content, blob_hash) in contents.iter().zip(&blob_hashes) {
+        let blob_path = brioche_core::blob::local_blob_path(&brioche, *blob_hash).await?;
+        assert_eq!(tokio::fs::read(&blob_path).await?, *content);
+    }
+
+    known_blobs_mock.assert_async().await;
+    for mock in blob_mocks {
+        mock.assert_async().await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use brioche_core::blob::{bundle, get_save_blob_permit, SaveBlobOptions};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_blob_bundle_export_import() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let hello_alias = brioche_test::sha256("hello");
+    let permit = get_save_blob_permit(&brioche).await?;
+    let hello_blob = brioche_core::blob::save_blob(
+        &brioche,
+        permit,
+        b"hello",
+        SaveBlobOptions::new().expected_hash(Some(hello_alias.clone())),
+    )
+    .await?;
+    let world_blob = brioche_test::blob(&brioche, "world").await;
+
+    let bundle_path = context.path("bundle.tar.zst");
+    let report = bundle::export_bundle(
+        &brioche,
+        &[hello_blob, world_blob, hello_blob],
+        &bundle_path,
+    )
+    .await?;
+    assert_eq!(report.num_blobs, 2);
+    assert_eq!(report.num_aliases, 1);
+    assert_eq!(report.total_size, 10);
+
+    // Import into a separate store, as if on another machine
+    let (other_brioche, _other_context) = brioche_test::brioche_test().await;
+    let report = bundle::import_bundle(&other_brioche, &bundle_path).await?;
+    assert_eq!(report.num_blobs, 2);
+    assert_eq!(report.num_new_blobs, 2);
+    assert_eq!(report.num_aliases, 1);
+
+    let hello_path = brioche_core::blob::local_blob_path(&other_brioche, hello_blob).await?;
+    let world_path = brioche_core::blob::local_blob_path(&other_brioche, world_blob).await?;
+    assert_eq!(tokio::fs::read_to_string(&hello_path).await?, "hello");
+    assert_eq!(tokio::fs::read_to_string(&world_path).await?, "world");
+    assert_eq!(
+        brioche_core::blob::find_blob(&other_brioche, &hello_alias).await?,
+        Some(hello_blob)
+    );
+
+    // Importing again doesn't save anything new
+    let report = bundle::import_bundle(&other_brioche, &bundle_path).await?;
+    assert_eq!(report.num_blobs, 2);
+    assert_eq!(report.num_new_blobs, 0);
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use brioche_core::{
+    blob::BlobHash,
+    blob_store::{
+        s3::{S3BlobStore, S3BlobStoreConfig, S3Credentials},
+        FilesystemBlobStore,
+    },
+    network::HttpClientOptions,
+};
+
+mod brioche_test;
+
+fn s3_store(server: &mockito::Server, write: bool) -> S3BlobStore {
+    S3BlobStore::new_with_credentials(
+        S3BlobStoreConfig {
+            bucket: "bucket".to_string(),
+            region: "us-east-1".to_string(),
+            endpoint: Some(server.url().parse().unwrap()),
+            prefix: "brioche/blobs/".to_string(),
+            write,
+        },
+        Some(S3Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        }),
+        &HttpClientOptions::default(),
+    )
+    .unwrap()
+}
+
+fn authorization_matcher() -> mockito::Matcher {
+    mockito::Matcher::Regex(
+        r"^AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/\d{8}/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature=[0-9a-f]{64}$"
+            .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_blob_store_filesystem() -> anyhow::Result<()> {
+    let store_temp = tempdir::TempDir::new("brioche-test-blob-store")?;
+    let store_dir = store_temp.path().to_owned();
+
+    // New blobs are copied to a writable store
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.blob_store(FilesystemBlobStore::new(store_dir.clone(), true))
+    })
+    .await;
+    let hello_hash = brioche_test::blob(&brioche, "hello").await;
+    brioche_core::blob_store::flush_uploads(&brioche).await?;
+    let store_path = store_dir.join(hex::encode(hello_hash.to_blake3().as_bytes()));
+    assert_eq!(tokio::fs::read(&store_path).await?, b"hello");
+
+    // Another store can fetch blobs from it without the registry
+    let (other_brioche, _other_context) = brioche_test::brioche_test_with(|builder| {
+        builder.blob_store(FilesystemBlobStore::new(store_dir.clone(), false))
+    })
+    .await;
+    let fetched_path = brioche_core::blob::blob_path(&other_brioche, hello_hash).await?;
+    assert_eq!(
+        fetched_path,
+        brioche_core::blob::primary_blob_path(&other_brioche, hello_hash)
+    );
+    assert_eq!(tokio::fs::read(&fetched_path).await?, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_store_s3_fetch() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let hello_hash = BlobHash::for_content(b"hello");
+    let hello_key = format!(
+        "/bucket/brioche/blobs/{}",
+        hex::encode(hello_hash.to_blake3().as_bytes())
+    );
+    let get_endpoint = server
+        .mock("GET", &*hello_key)
+        .match_header("authorization", authorization_matcher())
+        .match_header(
+            "x-amz-date",
+            mockito::Matcher::Regex(r"^\d{8}T\d{6}Z$".to_string()),
+        )
+        .with_body("hello")
+        .expect(1)
+        .create();
+
+    let store = s3_store(&server, false);
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.blob_store(store)).await;
+
+    let blob_path = brioche_core::blob::blob_path(&brioche, hello_hash).await?;
+    assert_eq!(tokio::fs::read(&blob_path).await?, b"hello");
+
+    get_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_store_s3_ignores_mismatched_blob() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let hello_hash = BlobHash::for_content(b"hello");
+    let hello_key = format!(
+        "/bucket/brioche/blobs/{}",
+        hex::encode(hello_hash.to_blake3().as_bytes())
+    );
+    let get_endpoint = server
+        .mock("GET", &*hello_key)
+        .with_body("goodbye")
+        .expect(1)
+        .create();
+
+    let store = s3_store(&server, false);
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.blob_store(store)).await;
+
+    assert_eq!(
+        brioche_core::blob_store::fetch_blob(&brioche, hello_hash).await,
+        None
+    );
+
+    get_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blob_store_s3_upload() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let hello_hash = BlobHash::for_content(b"hello");
+    let hello_key = format!(
+        "/bucket/brioche/blobs/{}",
+        hex::encode(hello_hash.to_blake3().as_bytes())
+    );
+    let head_endpoint = server
+        .mock("HEAD", &*hello_key)
+        .match_header("authorization", authorization_matcher())
+        .with_status(404)
+        .expect(1)
+        .create();
+    let put_endpoint = server
+        .mock("PUT", &*hello_key)
+        .match_header("authorization", authorization_matcher())
+        .match_header("x-amz-content-sha256", "UNSIGNED-PAYLOAD")
+        .match_body("hello")
+        .expect(1)
+        .create();
+
+    let store = s3_store(&server, true);
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.blob_store(store)).await;
+
+    assert_eq!(brioche_test::blob(&brioche, "hello").await, hello_hash);
+    brioche_core::blob_store::flush_uploads(&brioche).await?;
+
+    head_endpoint.assert();
+    put_endpoint.assert();
+
+    Ok(())
+}
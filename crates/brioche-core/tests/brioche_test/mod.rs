@@ -86,7 +86,9 @@ pub async fn bake_without_meta(
 }
 
 pub async fn blob(brioche: &Brioche, content: impl AsRef<[u8]> + std::marker::Unpin) -> BlobHash {
-    let permit = brioche_core::blob::get_save_blob_permit().await.unwrap();
+    let permit = brioche_core::blob::get_save_blob_permit(brioche)
+        .await
+        .unwrap();
     brioche_core::blob::save_blob_from_reader(
         brioche,
         permit,
@@ -353,6 +355,8 @@ impl TestContext {
             .with_body(
                 serde_json::to_string(&brioche_core::registry::GetProjectTagResponse {
                     project_hash,
+                    yanked: false,
+                    deprecation: None,
                 })
                 .unwrap(),
             )
@@ -410,7 +414,9 @@ impl TestContext {
             mocks.push(mock);
         }
         for blob_hash in &references.recipes.blobs {
-            let blob_path = brioche_core::blob::local_blob_path(brioche, *blob_hash);
+            let blob_path = brioche_core::blob::local_blob_path(brioche, *blob_hash)
+                .await
+                .unwrap();
             let blob_contents = tokio::fs::read(&blob_path).await.unwrap();
             let blob_contents_zstd = zstd::encode_all(&*blob_contents, 0).unwrap();
             let mock = self
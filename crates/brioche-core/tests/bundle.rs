@@ -0,0 +1,133 @@
+use brioche_core::{
+    bake::BakeScope,
+    recipe::{DownloadRecipe, Recipe},
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_bundle_source_builds_offline() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello = "hello";
+    let hello_blob = brioche_test::blob(&brioche, hello).await;
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body(hello)
+        .expect(1)
+        .create();
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: brioche_test::sha256(hello),
+        url: format!("{server_url}/file.txt").parse().unwrap(),
+    });
+
+    let (foo_hash, _) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {};
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let mock_foo_latest = context
+        .mock_registry_publish_tag("foo", "latest", foo_hash)
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: "*",
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    projects.commit_dirty_lockfiles().await?;
+    mock_foo_latest.assert_async().await;
+
+    let artifact = brioche_core::bake::bake(
+        &brioche,
+        brioche_test::without_meta(hello_download.clone()),
+        &BakeScope::Project {
+            project_hash,
+            export: "default".to_string(),
+        },
+    )
+    .await?;
+    assert_eq!(artifact.value, brioche_test::file(hello_blob, false));
+
+    let bundle_path = context.path("myproject.tar.gz");
+    let manifest = brioche_core::bundle::write_source_bundle(
+        &brioche,
+        &projects,
+        project_hash,
+        "default",
+        "myproject",
+        &bundle_path,
+    )
+    .await?;
+    assert_eq!(manifest.vendored_projects, vec![foo_hash]);
+    assert_eq!(manifest.vendored_bakes.len(), 1);
+
+    // Bundling again should give exactly the same archive
+    let bundle_copy_path = context.path("myproject-copy.tar.gz");
+    brioche_core::bundle::write_source_bundle(
+        &brioche,
+        &projects,
+        project_hash,
+        "default",
+        "myproject",
+        &bundle_copy_path,
+    )
+    .await?;
+    assert_eq!(
+        tokio::fs::read(&bundle_path).await?,
+        tokio::fs::read(&bundle_copy_path).await?,
+    );
+
+    // Load and bake the bundle with a fresh Brioche home, without any
+    // registry mocks. The download should not get requested again
+    let (offline_brioche, offline_context) = brioche_test::brioche_test().await;
+    let extract_dir = offline_context.mkdir("extracted").await;
+    let bundle_file = tokio::fs::File::open(&bundle_path).await?;
+    let mut archive = tokio_tar::Archive::new(async_compression::tokio::bufread::GzipDecoder::new(
+        tokio::io::BufReader::new(bundle_file),
+    ));
+    archive.unpack(&extract_dir).await?;
+
+    let (offline_projects, offline_project_hash) =
+        brioche_test::load_project(&offline_brioche, &extract_dir.join("myproject")).await?;
+    assert_eq!(offline_project_hash, project_hash);
+    assert_eq!(
+        offline_projects
+            .project(offline_project_hash)?
+            .dependency_hash("foo"),
+        Some(foo_hash)
+    );
+
+    let offline_artifact = brioche_core::bake::bake(
+        &offline_brioche,
+        brioche_test::without_meta(hello_download),
+        &BakeScope::Anonymous,
+    )
+    .await?;
+    assert_eq!(offline_artifact.value, artifact.value);
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
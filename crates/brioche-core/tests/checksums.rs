@@ -0,0 +1,121 @@
+use brioche_core::{
+    checksums::{ChecksumEntry, ChecksumSignature},
+    Hash, HashAlgorithm,
+};
+
+mod brioche_test;
+
+const HELLO_SHA256_HEX: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+const HELLO_SHA1_HEX: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+const SIGNED_SUMS: &str =
+    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  hello.tar.gz\n";
+const SIGNED_SUMS_PUBLIC_KEY: &str = "untrusted comment: test public key
+RWQBAgMEBQYHCLa2dD1IxFdUlqG5vDAy0i7TNsDAHIBTjT0WhYrUi4QW
+";
+const SIGNED_SUMS_SIGNATURE: &str = "untrusted comment: verify with test.pub
+RWQBAgMEBQYHCFj6bB3mmlxx/pNVoakazs2zjBjM0m5j/8j1mjeXo3B0rS6gdHqBMnThJG2mxW1MojcVC90dnrwwUFVY17KUrAM=
+";
+
+fn entry(file_name: &str, hash: Hash) -> ChecksumEntry {
+    ChecksumEntry {
+        file_name: file_name.to_string(),
+        hash,
+    }
+}
+
+#[test]
+fn test_checksums_parse() -> anyhow::Result<()> {
+    let contents = format!(
+        "# comment\n\
+        {HELLO_SHA256_HEX}  hello.tar.gz\n\
+        {HELLO_SHA256_HEX} *hello with spaces.zip\n\
+        \n\
+        SHA1 (hello.txt) = {HELLO_SHA1_HEX}\n"
+    );
+    let entries = brioche_core::checksums::parse_checksums(&contents, None)?;
+    assert_eq!(
+        entries,
+        vec![
+            entry("hello.tar.gz", brioche_test::sha256("hello")),
+            entry("hello with spaces.zip", brioche_test::sha256("hello")),
+            entry(
+                "hello.txt",
+                Hash::from_value(HashAlgorithm::Sha1, hex::decode(HELLO_SHA1_HEX)?)?
+            ),
+        ]
+    );
+
+    // An explicit algorithm has to match the length of each hash
+    let result = brioche_core::checksums::parse_checksums(
+        &format!("{HELLO_SHA1_HEX}  hello.txt\n"),
+        Some(HashAlgorithm::Sha256),
+    );
+    assert!(result.is_err());
+
+    let result = brioche_core::checksums::parse_checksums("not a checksum\n", None);
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_checksums_pgp_clearsigned() -> anyhow::Result<()> {
+    let contents = format!(
+        "-----BEGIN PGP SIGNED MESSAGE-----\n\
+        Hash: SHA256\n\
+        \n\
+        {HELLO_SHA256_HEX}  hello.tar.gz\n\
+        -----BEGIN PGP SIGNATURE-----\n\
+        \n\
+        AAAA\n\
+        -----END PGP SIGNATURE-----\n"
+    );
+    let checksum_file = brioche_core::checksums::read_checksum_file(&contents, None, None)?;
+    assert_eq!(checksum_file.signature, ChecksumSignature::UnverifiedPgp);
+    assert_eq!(
+        checksum_file.entries,
+        vec![entry("hello.tar.gz", brioche_test::sha256("hello"))]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_checksums_signify() -> anyhow::Result<()> {
+    let checksum_file = brioche_core::checksums::read_checksum_file(
+        SIGNED_SUMS,
+        None,
+        Some((SIGNED_SUMS_SIGNATURE, SIGNED_SUMS_PUBLIC_KEY)),
+    )?;
+    assert_eq!(checksum_file.signature, ChecksumSignature::Verified);
+    assert_eq!(
+        checksum_file.entries,
+        vec![entry("hello.tar.gz", brioche_test::sha256("hello"))]
+    );
+
+    // Tampering with the checksums should fail verification
+    let tampered = SIGNED_SUMS.replace("hello.tar.gz", "hello.zip");
+    let result = brioche_core::checksums::read_checksum_file(
+        &tampered,
+        None,
+        Some((SIGNED_SUMS_SIGNATURE, SIGNED_SUMS_PUBLIC_KEY)),
+    );
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_checksums_download_source() {
+    let source = brioche_core::checksums::download_source(
+        "https://example.com/hello.tar.gz",
+        &brioche_test::sha256("hello"),
+    );
+    assert_eq!(
+        source,
+        format!(
+            "std.download({{\n  url: \"https://example.com/hello.tar.gz\",\n  hash: std.sha256Hash(\"{HELLO_SHA256_HEX}\"),\n}})"
+        )
+    );
+}
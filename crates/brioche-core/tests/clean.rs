@@ -0,0 +1,124 @@
+use brioche_core::{
+    bake::BakeScope,
+    recipe::{DownloadRecipe, Recipe, WithMeta},
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_clean_project() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello = "hello";
+    let hello_hash = brioche_test::sha256(hello);
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body(hello)
+        .expect(2)
+        .create();
+
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: format!("{server_url}/file.txt").parse().unwrap(),
+    });
+
+    let (_, project_hash, _) = context
+        .temp_project(|path| async move {
+            tokio::fs::write(path.join("project.bri"), "")
+                .await
+                .unwrap();
+        })
+        .await;
+    let scope = BakeScope::Project {
+        project_hash,
+        export: "default".to_string(),
+    };
+
+    brioche_core::bake::bake(
+        &brioche,
+        WithMeta::without_meta(hello_download.clone()),
+        &scope,
+    )
+    .await?;
+
+    let results = brioche_core::clean::clean_project(&brioche, project_hash).await?;
+    assert_eq!(results.num_project_bakes, 1);
+    assert_eq!(results.num_bakes, 1);
+
+    // The cached bake was removed, so the recipe should be downloaded again
+    brioche_core::bake::bake(&brioche, WithMeta::without_meta(hello_download), &scope).await?;
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clean_project_keeps_shared_bakes() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello = "hello";
+    let hello_hash = brioche_test::sha256(hello);
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body(hello)
+        .expect(1)
+        .create();
+
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: format!("{server_url}/file.txt").parse().unwrap(),
+    });
+
+    let (_, foo_hash, _) = context
+        .temp_project(|path| async move {
+            tokio::fs::write(path.join("project.bri"), "// foo")
+                .await
+                .unwrap();
+        })
+        .await;
+    let (_, bar_hash, _) = context
+        .temp_project(|path| async move {
+            tokio::fs::write(path.join("project.bri"), "// bar")
+                .await
+                .unwrap();
+        })
+        .await;
+
+    for project_hash in [foo_hash, bar_hash] {
+        brioche_core::bake::bake(
+            &brioche,
+            WithMeta::without_meta(hello_download.clone()),
+            &BakeScope::Project {
+                project_hash,
+                export: "default".to_string(),
+            },
+        )
+        .await?;
+    }
+
+    let results = brioche_core::clean::clean_project(&brioche, foo_hash).await?;
+    assert_eq!(results.num_project_bakes, 1);
+    assert_eq!(results.num_bakes, 0);
+
+    // The bake is still used by `bar`, so it should still be cached
+    brioche_core::bake::bake(
+        &brioche,
+        WithMeta::without_meta(hello_download),
+        &BakeScope::Project {
+            project_hash: bar_hash,
+            export: "default".to_string(),
+        },
+    )
+    .await?;
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
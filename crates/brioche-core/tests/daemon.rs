@@ -0,0 +1,226 @@
+#![cfg(unix)]
+
+use brioche_core::daemon::{DaemonBuildRequest, DaemonClient, DaemonRequest, DaemonResponse};
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_daemon_build_failure() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let socket_path = context.path("daemon.sock");
+    let missing_project = context.path("missing");
+
+    let client = async {
+        let mut client = loop {
+            match DaemonClient::connect(&socket_path).await {
+                Some(client) => break client,
+                None => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+
+        let request = DaemonRequest::Build(DaemonBuildRequest {
+            project_path: missing_project.clone(),
+            export: "default".to_string(),
+            output: None,
+            replace: false,
+            merge: false,
+            output_permissions: Default::default(),
+            link_strategy: Default::default(),
+            env: None,
+        });
+        client.request(&request).await
+    };
+
+    let response = tokio::select! {
+        result = brioche_core::daemon::serve(&brioche, &socket_path, &Default::default()) => {
+            panic!("daemon stopped: {result:?}");
+        }
+        response = client => response?,
+    };
+
+    assert!(matches!(response, DaemonResponse::Failed { .. }));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_handles_connections_concurrently() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let socket_path = context.path("daemon.sock");
+    let missing_project = context.path("missing");
+
+    let client = async {
+        // An open connection that never sends anything, like an idle
+        // editor, shouldn't hold up other clients
+        let _idle_client = loop {
+            match DaemonClient::connect(&socket_path).await {
+                Some(client) => break client,
+                None => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+
+        let mut client = DaemonClient::connect(&socket_path)
+            .await
+            .expect("failed to connect to daemon");
+        let request = DaemonRequest::Build(DaemonBuildRequest {
+            project_path: missing_project.clone(),
+            export: "default".to_string(),
+            output: None,
+            replace: false,
+            merge: false,
+            output_permissions: Default::default(),
+            link_strategy: Default::default(),
+            env: None,
+        });
+        client.request(&request).await
+    };
+
+    let response = tokio::select! {
+        result = brioche_core::daemon::serve(&brioche, &socket_path, &Default::default()) => {
+            panic!("daemon stopped: {result:?}");
+        }
+        response = tokio::time::timeout(std::time::Duration::from_secs(30), client) => response??,
+    };
+
+    assert!(matches!(response, DaemonResponse::Failed { .. }));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_replaces_stale_socket() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    // A socket file with nothing listening on it, like one left behind by
+    // a daemon that was killed
+    let socket_path = context.path("daemon.sock");
+    drop(std::os::unix::net::UnixListener::bind(&socket_path)?);
+    assert!(socket_path.exists());
+
+    let connected = async {
+        loop {
+            if DaemonClient::connect(&socket_path).await.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    };
+
+    tokio::select! {
+        result = brioche_core::daemon::serve(&brioche, &socket_path, &Default::default()) => {
+            panic!("daemon stopped: {result:?}");
+        }
+        () = connected => {}
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_socket_only_accessible_by_owner() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let socket_path = context.path("daemon.sock");
+
+    let connected = async {
+        loop {
+            if DaemonClient::connect(&socket_path).await.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    };
+
+    tokio::select! {
+        result = brioche_core::daemon::serve(&brioche, &socket_path, &Default::default()) => {
+            panic!("daemon stopped: {result:?}");
+        }
+        () = connected => {}
+    };
+
+    let metadata = tokio::fs::metadata(&socket_path).await?;
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_editor_rpc_errors() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let socket_path = context.path("daemon.sock");
+    let missing_project = context.path("missing");
+
+    let client = async {
+        let stream = loop {
+            match tokio::net::UnixStream::connect(&socket_path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+
+        let messages = [
+            "not json".to_string(),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "brioche/unknown",
+            })
+            .to_string(),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "brioche/exportStatus",
+                "params": { "projectPath": "relative" },
+            })
+            .to_string(),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "brioche/exportStatus",
+                "params": { "projectPath": missing_project },
+            })
+            .to_string(),
+        ];
+
+        let mut responses = vec![];
+        for message in messages {
+            writer.write_all(format!("{message}\n").as_bytes()).await?;
+            let response = lines.next_line().await?.expect("connection closed");
+            let response: serde_json::Value = serde_json::from_str(&response)?;
+            responses.push(response);
+        }
+
+        anyhow::Ok(responses)
+    };
+
+    let responses = tokio::select! {
+        result = brioche_core::daemon::serve(&brioche, &socket_path, &Default::default()) => {
+            panic!("daemon stopped: {result:?}");
+        }
+        responses = client => responses?,
+    };
+
+    let error_codes = responses
+        .iter()
+        .map(|response| (response["id"].clone(), response["error"]["code"].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        error_codes,
+        [
+            (serde_json::Value::Null, serde_json::json!(-32700)),
+            (serde_json::json!(1), serde_json::json!(-32601)),
+            (serde_json::json!(2), serde_json::json!(-32602)),
+            (serde_json::json!(3), serde_json::json!(-32000)),
+        ]
+    );
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 
 use assert_matches::assert_matches;
-use brioche_core::recipe::{Artifact, WithMeta};
+use brioche_core::recipe::{arbitrary, Artifact, DirectoryError, WithMeta};
+use futures::TryStreamExt as _;
 
 mod brioche_test;
 
@@ -273,3 +274,208 @@ async fn test_directory_create_nested_with_common_nondir_error() -> anyhow::Resu
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_directory_walk() -> anyhow::Result<()> {
+    let (brioche, _) = brioche_test::brioche_test().await;
+
+    let blob1 = brioche_test::blob(&brioche, "hello world").await;
+    let file1 = brioche_test::file(blob1, false);
+
+    let blob2 = brioche_test::blob(&brioche, "hi").await;
+    let file2 = brioche_test::file(blob2, false);
+
+    let directory = brioche_core::recipe::Directory::create(
+        &brioche,
+        &BTreeMap::from_iter([
+            ("foo.txt".into(), WithMeta::without_meta(file1.clone())),
+            ("subdir/file1.txt".into(), WithMeta::without_meta(file1)),
+            (
+                "subdir/inner/file2.txt".into(),
+                WithMeta::without_meta(file2),
+            ),
+            (
+                "link".into(),
+                WithMeta::without_meta(brioche_test::symlink("foo.txt")),
+            ),
+        ]),
+    )
+    .await?;
+
+    let paths = directory
+        .walk(&brioche)
+        .map_ok(|(path, _)| path.to_string())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(
+        paths,
+        [
+            "foo.txt",
+            "link",
+            "subdir",
+            "subdir/file1.txt",
+            "subdir/inner",
+            "subdir/inner/file2.txt",
+        ]
+    );
+
+    let (path, artifact) = directory
+        .walk(&brioche)
+        .try_filter(|(path, _)| std::future::ready(path.as_bytes() == b"subdir/inner/file2.txt"))
+        .try_next()
+        .await?
+        .unwrap();
+    assert_eq!(path.to_string(), "subdir/inner/file2.txt");
+    assert_eq!(artifact.value, brioche_test::file(blob2, false));
+
+    let empty = brioche_core::recipe::Directory::default();
+    let entries = empty.walk(&brioche).try_collect::<Vec<_>>().await?;
+    assert!(entries.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_directory_check_invariants_backslash() -> anyhow::Result<()> {
+    let (brioche, _) = brioche_test::brioche_test().await;
+
+    // Backslashes are valid in Unix file names, so they're allowed in
+    // entry names too
+    let blob = brioche_test::blob(&brioche, "hello").await;
+    let directory = brioche_core::recipe::Directory::create(
+        &brioche,
+        &BTreeMap::from_iter([(
+            "back\\slash.txt".into(),
+            WithMeta::without_meta(brioche_test::file(blob, false)),
+        )]),
+    )
+    .await?;
+    directory.check_invariants()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_directory_insert_property() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (brioche, _context) = runtime.block_on(brioche_test::brioche_test());
+
+    proptest::proptest!(
+        proptest::test_runner::Config::with_cases(32),
+        |(
+            directory in arbitrary::arb_test_directory(),
+            path in arbitrary::arb_entry_path(),
+            entry in arbitrary::arb_test_artifact(),
+        )| {
+            runtime.block_on(async {
+                let Artifact::Directory(mut directory) =
+                    arbitrary::create_test_artifact(&brioche, &directory).await.unwrap()
+                else {
+                    panic!("expected directory");
+                };
+                let entry = arbitrary::create_test_artifact(&brioche, &entry).await.unwrap();
+
+                let result = directory
+                    .insert(&brioche, &path, Some(WithMeta::without_meta(entry.clone())))
+                    .await;
+                match result {
+                    Ok(_) => {
+                        let inserted = directory.get(&brioche, &path).await.unwrap();
+                        assert_eq!(inserted.map(|inserted| inserted.value), Some(entry));
+                    }
+                    Err(DirectoryError::PathDescendsIntoNonDirectory { .. }) => {}
+                    Err(error) => panic!("unexpected error: {error}"),
+                }
+
+                directory.check_invariants_recursive(&brioche).await.unwrap();
+            });
+        }
+    );
+}
+
+#[test]
+fn test_directory_insert_remove_property() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (brioche, _context) = runtime.block_on(brioche_test::brioche_test());
+
+    proptest::proptest!(
+        proptest::test_runner::Config::with_cases(32),
+        |(
+            directory in arbitrary::arb_test_directory(),
+            name in arbitrary::arb_entry_name(),
+            entry in arbitrary::arb_test_artifact(),
+        )| {
+            runtime.block_on(async {
+                let Artifact::Directory(mut directory) =
+                    arbitrary::create_test_artifact(&brioche, &directory).await.unwrap()
+                else {
+                    panic!("expected directory");
+                };
+                let entry = arbitrary::create_test_artifact(&brioche, &entry).await.unwrap();
+
+                directory.insert(&brioche, &name, None).await.unwrap();
+                let original = directory.clone();
+
+                directory
+                    .insert(&brioche, &name, Some(WithMeta::without_meta(entry)))
+                    .await
+                    .unwrap();
+                directory.insert(&brioche, &name, None).await.unwrap();
+
+                assert_eq!(directory, original);
+                assert_matches!(directory.get(&brioche, &name).await, Ok(None));
+                directory.check_invariants_recursive(&brioche).await.unwrap();
+            });
+        }
+    );
+}
+
+#[test]
+fn test_directory_merge_property() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (brioche, _context) = runtime.block_on(brioche_test::brioche_test());
+
+    proptest::proptest!(
+        proptest::test_runner::Config::with_cases(32),
+        |(
+            left in arbitrary::arb_test_directory(),
+            right in arbitrary::arb_test_directory(),
+        )| {
+            runtime.block_on(async {
+                let Artifact::Directory(left) =
+                    arbitrary::create_test_artifact(&brioche, &left).await.unwrap()
+                else {
+                    panic!("expected directory");
+                };
+                let Artifact::Directory(right) =
+                    arbitrary::create_test_artifact(&brioche, &right).await.unwrap()
+                else {
+                    panic!("expected directory");
+                };
+
+                let mut merged = left.clone();
+                merged.merge(&right, &brioche).await.unwrap();
+                merged.check_invariants_recursive(&brioche).await.unwrap();
+
+                let left_entries = left.entries(&brioche).await.unwrap();
+                let right_entries = right.entries(&brioche).await.unwrap();
+                let merged_entries = merged.entries(&brioche).await.unwrap();
+
+                // Every entry from either side should be present
+                for name in left_entries.keys().chain(right_entries.keys()) {
+                    assert!(merged_entries.contains_key(name));
+                }
+                assert!(merged_entries
+                    .keys()
+                    .all(|name| left_entries.contains_key(name) || right_entries.contains_key(name)));
+
+                // Non-directory entries on the right side always win
+                for (name, right_entry) in &right_entries {
+                    if !matches!(right_entry.value, Artifact::Directory(_)) {
+                        assert_eq!(merged_entries[name].value, right_entry.value);
+                    }
+                }
+            });
+        }
+    );
+}
@@ -0,0 +1,93 @@
+use assert_matches::assert_matches;
+use brioche_core::{
+    disk_space::{
+        available_space, ensure_available_space, estimated_process_output_size,
+        record_process_output_size, InsufficientSpaceError,
+    },
+    platform::current_platform,
+    recipe::{CompleteProcessRecipe, CompleteProcessTemplate, CompleteProcessTemplateComponent},
+};
+
+mod brioche_test;
+
+fn tpl(s: impl AsRef<[u8]>) -> CompleteProcessTemplate {
+    CompleteProcessTemplate {
+        components: vec![CompleteProcessTemplateComponent::Literal {
+            value: s.as_ref().into(),
+        }],
+    }
+}
+
+fn process(script: &str, work_dir: brioche_core::recipe::Directory) -> CompleteProcessRecipe {
+    CompleteProcessRecipe {
+        command: tpl("/bin/sh"),
+        args: vec![tpl("-c"), tpl(script)],
+        env: Default::default(),
+        work_dir,
+        output_scaffold: None,
+        platform: current_platform(),
+        is_unsafe: false,
+        networking: false,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: Default::default(),
+        emulator: None,
+        incremental_key: None,
+        previous_output: None,
+    }
+}
+
+#[tokio::test]
+async fn test_disk_space_ensure_available() -> anyhow::Result<()> {
+    let (_brioche, context) = brioche_test::brioche_test().await;
+
+    // Paths that don't exist yet use their closest existing ancestor
+    let path = context.path("not/created/yet");
+    let available = available_space(&path)?;
+    assert!(available > 0);
+
+    let result = ensure_available_space(&path, u64::MAX, "test").await;
+    let error = result.unwrap_err();
+    assert_matches!(
+        error.downcast_ref::<InsufficientSpaceError>(),
+        Some(InsufficientSpaceError {
+            required: u64::MAX,
+            ..
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_disk_space_process_output_size_estimate() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let blob = brioche_test::blob(&brioche, "hello").await;
+    let work_dir_a = brioche_test::empty_dir_value();
+    let work_dir_b =
+        brioche_test::dir_value(&brioche, [("hello.txt", brioche_test::file(blob, false))]).await;
+
+    let build = process("build", work_dir_a);
+    assert_eq!(estimated_process_output_size(&brioche, &build).await?, None);
+
+    record_process_output_size(&brioche, &build, 1234).await?;
+    assert_eq!(
+        estimated_process_output_size(&brioche, &build).await?,
+        Some(1234)
+    );
+
+    // Processes that only differ by input artifacts share an estimate
+    let rebuild = process("build", work_dir_b.clone());
+    assert_eq!(
+        estimated_process_output_size(&brioche, &rebuild).await?,
+        Some(1234)
+    );
+
+    let other = process("other", work_dir_b);
+    assert_eq!(estimated_process_output_size(&brioche, &other).await?, None);
+
+    Ok(())
+}
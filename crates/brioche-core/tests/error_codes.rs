@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use anyhow::Context as _;
+use brioche_core::error_codes::{error_code, CodedError, ErrorCode, EXPLANATIONS};
+
+#[test]
+fn test_error_code_display_and_parse() {
+    assert_eq!(ErrorCode::HASH_MISMATCH.to_string(), "BRI0001");
+    assert_eq!(
+        "BRI0001".parse::<ErrorCode>().unwrap(),
+        ErrorCode::HASH_MISMATCH
+    );
+    assert_eq!(
+        "bri0002".parse::<ErrorCode>().unwrap(),
+        ErrorCode::INSUFFICIENT_DISK_SPACE
+    );
+    assert_eq!(
+        "3".parse::<ErrorCode>().unwrap(),
+        ErrorCode::UNSUPPORTED_API_VERSION
+    );
+
+    assert!("BRI".parse::<ErrorCode>().is_err());
+    assert!("E0001".parse::<ErrorCode>().is_err());
+}
+
+#[test]
+fn test_error_code_explanations() {
+    let mut codes = HashSet::new();
+    for explanation in EXPLANATIONS {
+        assert!(
+            codes.insert(explanation.code),
+            "duplicate explanation for {}",
+            explanation.code
+        );
+    }
+
+    let all_codes = [
+        ErrorCode::HASH_MISMATCH,
+        ErrorCode::INSUFFICIENT_DISK_SPACE,
+        ErrorCode::UNSUPPORTED_API_VERSION,
+        ErrorCode::NETWORKING_REQUIRES_UNSAFE,
+        ErrorCode::UNNECESSARY_UNSAFE,
+        ErrorCode::PROCESS_FAILED,
+        ErrorCode::EMULATOR_NOT_FOUND,
+        ErrorCode::LOCKFILE_OUT_OF_DATE,
+        ErrorCode::OUTPUT_HASH_MISMATCH,
+        ErrorCode::SANDBOX_FAILED,
+        ErrorCode::DEPENDENCY_RESOLUTION_FAILED,
+        ErrorCode::REGISTRY_PERMISSION_DENIED,
+    ];
+    for code in all_codes {
+        let explanation = code.explanation();
+        assert!(explanation.is_some(), "no explanation for {code}");
+        assert!(
+            !explanation.unwrap().fixes.is_empty(),
+            "no fixes listed for {code}"
+        );
+    }
+
+    assert!("BRI9999"
+        .parse::<ErrorCode>()
+        .unwrap()
+        .explanation()
+        .is_none());
+}
+
+#[test]
+fn test_error_code_from_error() {
+    let error = anyhow::Error::from(CodedError::new(ErrorCode::PROCESS_FAILED, "process failed"));
+    assert_eq!(error_code(&error), Some(ErrorCode::PROCESS_FAILED));
+    assert_eq!(error.to_string(), "process failed");
+
+    let error = Err::<(), _>(CodedError::new(ErrorCode::UNNECESSARY_UNSAFE, "unsafe"))
+        .context("failed to bake")
+        .unwrap_err();
+    assert_eq!(error_code(&error), Some(ErrorCode::UNNECESSARY_UNSAFE));
+
+    let error = Err::<(), _>(brioche_core::disk_space::InsufficientSpaceError {
+        purpose: "process".to_string(),
+        path: "/".into(),
+        required: 100,
+        available: 10,
+    })
+    .context("failed to bake")
+    .unwrap_err();
+    assert_eq!(error_code(&error), Some(ErrorCode::INSUFFICIENT_DISK_SPACE));
+
+    let error = Err::<(), _>(brioche_core::registry::RegistryPermissionError {
+        project_name: "@scope/hello".to_string(),
+        scope: Some("scope".to_string()),
+        has_scope_token: false,
+    })
+    .context("failed to publish")
+    .unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        Some(ErrorCode::REGISTRY_PERMISSION_DENIED)
+    );
+
+    let error = anyhow::anyhow!("some other error");
+    assert_eq!(error_code(&error), None);
+}
@@ -0,0 +1,81 @@
+use brioche_core::{blob::SaveBlobOptions, Hash, HashAlgorithm};
+
+mod brioche_test;
+
+// Hashes of "hello"
+const HELLO_SHA256_HEX: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+const HELLO_SHA256_SRI: &str = "sha256-LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=";
+const HELLO_SHA512_SRI: &str =
+    "sha512-m3HSJL1i83hdltRq0+o9czGb+8KJDKra4t/3JRlnPKcjI8PZm6XBHXx6zG4UuMXaDEZjR1wuXDre9G9zvN7AQw==";
+const HELLO_SHA1_SRI: &str = "sha1-qvTGHdzF6KLavt4PO0gs2a6pQ00=";
+const HELLO_MD5_SRI: &str = "md5-XUFAKrxLKna5cZ2REBfFkg==";
+
+#[test]
+fn test_hash_parse_display() {
+    let hash: Hash = format!("sha256:{HELLO_SHA256_HEX}").parse().unwrap();
+    assert_eq!(hash, brioche_test::sha256("hello"));
+    assert_eq!(hash.to_string(), format!("sha256:{HELLO_SHA256_HEX}"));
+    assert_eq!(hash.to_sri(), HELLO_SHA256_SRI);
+
+    // The value has to be the right length for the algorithm
+    assert!("sha256:00".parse::<Hash>().is_err());
+    assert!("sha384:00".parse::<Hash>().is_err());
+}
+
+#[test]
+fn test_hash_parse_sri() {
+    let hash: Hash = HELLO_SHA256_SRI.parse().unwrap();
+    assert_eq!(hash, brioche_test::sha256("hello"));
+
+    // The strongest supported hash is used, and unknown algorithms and
+    // options are ignored
+    let hash = Hash::from_sri(&format!(
+        "{HELLO_MD5_SRI} {HELLO_SHA512_SRI}?foo sha384-AAAA"
+    ))
+    .unwrap();
+    assert_eq!(hash.algorithm(), HashAlgorithm::Sha512);
+    assert_eq!(hash.to_sri(), HELLO_SHA512_SRI);
+
+    assert!(Hash::from_sri("sha384-AAAA").is_err());
+    assert!(Hash::from_sri("sha256-not base64").is_err());
+}
+
+#[tokio::test]
+async fn test_hash_validate_blob() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    for sri in [
+        HELLO_SHA256_SRI,
+        HELLO_SHA512_SRI,
+        HELLO_SHA1_SRI,
+        HELLO_MD5_SRI,
+    ] {
+        let expected_hash = Hash::from_sri(sri)?;
+
+        let permit = brioche_core::blob::get_save_blob_permit(&brioche).await?;
+        let blob_hash = brioche_core::blob::save_blob(
+            &brioche,
+            permit,
+            b"hello",
+            SaveBlobOptions::new().expected_hash(Some(expected_hash.clone())),
+        )
+        .await?;
+        assert_eq!(blob_hash, brioche_test::blob(&brioche, "hello").await);
+
+        // The expected hash is recorded as an alias for the blob
+        let alias = brioche_core::blob::find_blob(&brioche, &expected_hash).await?;
+        assert_eq!(alias, Some(blob_hash));
+
+        let permit = brioche_core::blob::get_save_blob_permit(&brioche).await?;
+        let result = brioche_core::blob::save_blob(
+            &brioche,
+            permit,
+            b"goodbye",
+            SaveBlobOptions::new().expected_hash(Some(expected_hash)),
+        )
+        .await;
+        assert!(result.is_err(), "expected {sri} to not match");
+    }
+
+    Ok(())
+}
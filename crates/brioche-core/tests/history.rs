@@ -0,0 +1,65 @@
+use brioche_core::{
+    bake::BakeScope,
+    recipe::{DownloadRecipe, Recipe, WithMeta},
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_history_records_bakes() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let mut server = mockito::Server::new();
+    let server_url = server.url();
+
+    let hello = "hello";
+    let hello_blob = brioche_test::blob(&brioche, hello).await;
+    let hello_hash = brioche_test::sha256(hello);
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body(hello)
+        .expect(1)
+        .create();
+
+    let hello_download = Recipe::Download(DownloadRecipe {
+        hash: hello_hash.clone(),
+        url: format!("{server_url}/file.txt").parse().unwrap(),
+    });
+    let hello_download_hash = hello_download.hash();
+
+    for _ in 0..2 {
+        brioche_core::history::bake_and_record(
+            &brioche,
+            WithMeta::without_meta(hello_download.clone()),
+            &BakeScope::Anonymous,
+        )
+        .await?;
+    }
+
+    hello_endpoint.assert();
+
+    let runs = brioche_core::history::list_bake_runs(&brioche, 10).await?;
+    assert_eq!(runs.len(), 2);
+
+    // Runs are listed newest first, so the second run was a cache hit
+    assert_eq!(runs[0].recipe_hash, hello_download_hash);
+    assert_eq!((runs[0].num_cache_hits, runs[0].num_baked), (1, 0));
+    assert_eq!((runs[1].num_cache_hits, runs[1].num_baked), (0, 1));
+
+    let artifact =
+        brioche_core::history::find_bake_run_artifact(&brioche, hello_download_hash).await?;
+    assert_eq!(artifact, Some(brioche_test::file(hello_blob, false)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_history_find_missing() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let recipe = brioche_test::lazy_dir_empty();
+    let artifact = brioche_core::history::find_bake_run_artifact(&brioche, recipe.hash()).await?;
+    assert_eq!(artifact, None);
+
+    Ok(())
+}
@@ -303,6 +303,48 @@ async fn test_input_dir_use_resource_dir() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_input_dir_malformed_pack() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let dir_path = context.mkdir("test").await;
+    let resource_dir = context.mkdir("resources").await;
+
+    // A pack marker where the length points past the start of the file
+    let marker = b"brioche_pack_v0                 ";
+    let mut malformed_file = b"test".to_vec();
+    malformed_file.extend_from_slice(marker);
+    malformed_file.extend_from_slice(&1000u32.to_le_bytes());
+    malformed_file.extend_from_slice(b"not a pack");
+    malformed_file.extend_from_slice(&1000u32.to_le_bytes());
+    malformed_file.extend_from_slice(marker);
+
+    assert!(matches!(
+        brioche_pack::try_extract_pack(&malformed_file[..]),
+        Err(brioche_pack::ExtractPackError::TruncatedPack { .. })
+    ));
+
+    context.write_file("test/hi", &malformed_file).await;
+    context.write_file("resources/test", b"test").await;
+
+    let artifact =
+        create_input_with_resources(&brioche, &dir_path, Some(&resource_dir), &[], false).await?;
+
+    assert_eq!(
+        artifact,
+        brioche_test::dir(
+            &brioche,
+            [(
+                "hi",
+                brioche_test::file(brioche_test::blob(&brioche, &malformed_file).await, false)
+            ),]
+        )
+        .await
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_input_dir_use_input_resource_dir() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use brioche_core::sandbox::{
+    input_trace::{FileAccess, FileAccesses, SandboxGuestPaths},
+    HostPathMode, SandboxExecutionConfig, SandboxPath, SandboxPathOptions, SandboxTemplate,
+    SandboxTemplateComponent,
+};
+
+fn path_template(guest_path: &str, mode: HostPathMode) -> SandboxTemplate {
+    SandboxTemplate {
+        components: vec![SandboxTemplateComponent::Path(SandboxPath {
+            host_path: PathBuf::from("/host").join(guest_path.trim_start_matches('/')),
+            options: SandboxPathOptions {
+                mode,
+                guest_path_hint: guest_path.into(),
+            },
+        })],
+    }
+}
+
+fn access(path: &str, error: Option<i32>) -> FileAccess {
+    FileAccess {
+        path: path.into(),
+        error,
+    }
+}
+
+#[test]
+fn test_input_trace_report() {
+    let config = SandboxExecutionConfig {
+        sandbox_root: PathBuf::from("/host/root"),
+        include_host_paths: HashMap::from_iter([(
+            PathBuf::from("/tmp"),
+            SandboxPathOptions {
+                mode: HostPathMode::ReadWriteCreate,
+                guest_path_hint: "/tmp".into(),
+            },
+        )]),
+        command: path_template("/home/runner/locals/sh", HostPathMode::Read),
+        args: vec![
+            path_template("/home/runner/locals/used", HostPathMode::Read),
+            path_template("/home/runner/locals/unused", HostPathMode::Read),
+            path_template("/home/runner/outputs", HostPathMode::ReadWriteCreate),
+        ],
+        env: HashMap::new(),
+        current_dir: SandboxPath {
+            host_path: PathBuf::from("/host/work"),
+            options: SandboxPathOptions {
+                mode: HostPathMode::ReadWriteCreate,
+                guest_path_hint: "/home/runner/work".into(),
+            },
+        },
+        stdout_path: None,
+        stderr_path: None,
+        tty: false,
+        networking: false,
+        uid_hint: 0,
+        gid_hint: 0,
+        subordinate_ids: false,
+        trace_path: None,
+        network_proxy: None,
+    };
+    let guest_paths = SandboxGuestPaths::from_config(&config);
+
+    let mut accesses = FileAccesses::default();
+    accesses.record("/home/runner/locals/sh".into(), None);
+    accesses.record("/home/runner/locals/used/lib/libfoo.so".into(), Some(2));
+    accesses.record("/home/runner/locals/unused-sibling".into(), Some(2));
+    accesses.record("/usr/lib/libbar.so".into(), Some(2));
+    accesses.record("/etc/passwd".into(), Some(2));
+    accesses.record("/etc/passwd".into(), None);
+    accesses.record("/tmp/missing".into(), Some(2));
+    let accesses = accesses.into_accesses();
+
+    assert!(accesses.contains(&access("/etc/passwd", None)));
+
+    let report = guest_paths.report(&accesses);
+    assert_eq!(report.unused_inputs, vec!["/home/runner/locals/unused"]);
+    assert_eq!(
+        report.undeclared_accesses,
+        vec!["/home/runner/locals/unused-sibling", "/usr/lib/libbar.so"]
+    );
+}
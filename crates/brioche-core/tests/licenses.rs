@@ -0,0 +1,192 @@
+use brioche_core::{
+    annotations::Annotations,
+    error_codes::{error_code, ErrorCode},
+    licenses::{
+        scan_licenses, LicenseExpression, LicensePolicy, LicenseSource, LicenseStatus, PolicyAction,
+    },
+    recipe::Recipe,
+};
+use brioche_test::{bake_without_meta, without_meta};
+
+mod brioche_test;
+
+fn license(id: &str) -> LicenseExpression {
+    LicenseExpression::License(id.to_string())
+}
+
+#[test]
+fn test_license_expression_parse() -> anyhow::Result<()> {
+    assert_eq!(LicenseExpression::parse("MIT")?, license("MIT"));
+    assert_eq!(
+        LicenseExpression::parse("MIT OR Apache-2.0")?,
+        LicenseExpression::Or(vec![license("MIT"), license("Apache-2.0")]),
+    );
+    assert_eq!(
+        LicenseExpression::parse("(MIT OR Apache-2.0) AND BSD-3-Clause")?,
+        LicenseExpression::And(vec![
+            LicenseExpression::Or(vec![license("MIT"), license("Apache-2.0")]),
+            license("BSD-3-Clause"),
+        ]),
+    );
+    assert_eq!(
+        LicenseExpression::parse("GPL-2.0-only WITH Classpath-exception-2.0 OR MIT")?,
+        LicenseExpression::Or(vec![
+            license("GPL-2.0-only WITH Classpath-exception-2.0"),
+            license("MIT"),
+        ]),
+    );
+
+    assert!(LicenseExpression::parse("").is_err());
+    assert!(LicenseExpression::parse("MIT OR").is_err());
+    assert!(LicenseExpression::parse("(MIT").is_err());
+    assert!(LicenseExpression::parse("MIT Apache-2.0").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_license_policy_check() {
+    let policy: LicensePolicy = toml::from_str(
+        r#"
+            allow = ["MIT", "Apache-2.0", "GPL-2.0-only"]
+            deny = ["GPL-2.0-only WITH Classpath-exception-2.0"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(policy.check(Some("MIT")), LicenseStatus::Allowed);
+    assert_eq!(policy.check(Some("mit")), LicenseStatus::Allowed);
+    assert_eq!(
+        policy.check(Some("MIT OR GPL-3.0-only")),
+        LicenseStatus::Allowed
+    );
+    assert_eq!(
+        policy.check(Some("MIT AND GPL-3.0-only")),
+        LicenseStatus::Disallowed
+    );
+    assert_eq!(
+        policy.check(Some("GPL-2.0-only WITH Classpath-exception-2.0")),
+        LicenseStatus::Disallowed
+    );
+    assert_eq!(policy.check(None), LicenseStatus::Unknown);
+    assert_eq!(policy.check(Some("MIT OR")), LicenseStatus::Unknown);
+
+    assert_eq!(policy.on_violation, PolicyAction::Error);
+    assert_eq!(policy.on_unknown, PolicyAction::Allow);
+}
+
+#[tokio::test]
+async fn test_scan_licenses() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let main_project_dir = context.mkdir("mainproject").await;
+    context
+        .write_file(
+            "mainproject/project.bri",
+            r#"
+                import "depproject";
+                export const project = {
+                    name: "mainproject",
+                    license: "MIT",
+                    dependencies: {
+                        depproject: {
+                            path: "../depproject",
+                        },
+                    },
+                };
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "mainproject/brioche_licenses.toml",
+            r#"
+                deny = ["GPL-3.0-only"]
+                on_unknown = "warn"
+            "#,
+        )
+        .await;
+
+    context.mkdir("depproject").await;
+    context
+        .write_file(
+            "depproject/project.bri",
+            r#"
+                export const project = {
+                    name: "depproject",
+                };
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &main_project_dir).await?;
+    let policy = brioche_core::licenses::project_license_policy(&projects, project_hash)
+        .await?
+        .expect("license policy not found");
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let annotated_dir = Recipe::Annotate {
+        recipe: Box::new(without_meta(brioche_test::lazy_dir([(
+            "hello",
+            brioche_test::lazy_file(hello_blob, false),
+        )]))),
+        annotations: Annotations {
+            name: Some("hello".to_string()),
+            version: Some("1.0.0".to_string()),
+            license: Some("GPL-3.0-only".to_string()),
+            ..Default::default()
+        },
+    };
+    let output = bake_without_meta(
+        &brioche,
+        brioche_test::lazy_dir([("vendor", annotated_dir)]),
+    )
+    .await?;
+
+    let report = scan_licenses(&brioche, &projects, project_hash, &output, Some(&policy)).await?;
+
+    let statuses = report
+        .entries
+        .iter()
+        .map(|entry| {
+            let name = match &entry.source {
+                LicenseSource::Project { name, .. } => name.clone(),
+                LicenseSource::Artifact { name, .. } => name.clone(),
+            };
+            (name.unwrap(), entry.status, entry.action)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            (
+                "mainproject".to_string(),
+                LicenseStatus::Allowed,
+                PolicyAction::Allow
+            ),
+            (
+                "depproject".to_string(),
+                LicenseStatus::Unknown,
+                PolicyAction::Warn
+            ),
+            (
+                "hello".to_string(),
+                LicenseStatus::Disallowed,
+                PolicyAction::Error
+            ),
+        ],
+    );
+
+    let result = report.ensure_allowed();
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::LICENSE_POLICY_VIOLATION)
+    );
+
+    // Without a policy, every license is allowed
+    let report = scan_licenses(&brioche, &projects, project_hash, &output, None).await?;
+    assert_eq!(report.entries.len(), 3);
+    assert!(report.ensure_allowed().is_ok());
+
+    Ok(())
+}
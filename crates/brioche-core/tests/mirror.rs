@@ -0,0 +1,75 @@
+use brioche_core::recipe::Recipe;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_mirror_blob_copied_from_mirror() -> anyhow::Result<()> {
+    let (mirror_brioche, mirror_context) = brioche_test::brioche_test().await;
+    let blob_hash = brioche_test::blob(&mirror_brioche, b"hello from the mirror").await;
+
+    let mirror_home = mirror_context.path("brioche-home");
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.mirror_stores([mirror_home])).await;
+
+    let blob_path = brioche_core::blob::blob_path(&brioche, blob_hash).await?;
+    assert!(blob_path.starts_with(&brioche.home));
+    assert_eq!(tokio::fs::read(&blob_path).await?, b"hello from the mirror");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mirror_missing_blob_falls_back_to_registry() -> anyhow::Result<()> {
+    let (_mirror_brioche, mirror_context) = brioche_test::brioche_test().await;
+
+    let mirror_home = mirror_context.path("brioche-home");
+    let (brioche, mut context) =
+        brioche_test::brioche_test_with(|builder| builder.mirror_stores([mirror_home])).await;
+
+    let blob_hash = brioche_test::blob(&brioche, b"hello from the registry").await;
+    let blob_path = brioche_core::blob::local_blob_path(&brioche, blob_hash).await?;
+    tokio::fs::remove_file(&blob_path).await?;
+
+    let blob_endpoint = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!(
+                "/v0/blobs/{blob_hash}.zst?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(zstd::encode_all(&b"hello from the registry"[..], 0)?)
+        .expect(1)
+        .create();
+
+    let blob_path = brioche_core::blob::blob_path(&brioche, blob_hash).await?;
+    assert_eq!(
+        tokio::fs::read(&blob_path).await?,
+        b"hello from the registry"
+    );
+
+    blob_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mirror_recipes_copied_from_mirror() -> anyhow::Result<()> {
+    let (mirror_brioche, mirror_context) = brioche_test::brioche_test().await;
+    let recipe = Recipe::from(brioche_test::file(
+        brioche_test::blob(&mirror_brioche, b"foo").await,
+        false,
+    ));
+    brioche_core::recipe::save_recipes(&mirror_brioche, [&recipe]).await?;
+
+    let mirror_home = mirror_context.path("brioche-home");
+    let (brioche, _context) =
+        brioche_test::brioche_test_with(|builder| builder.mirror_stores([mirror_home])).await;
+
+    let local_recipe = brioche_core::recipe::get_recipe(&brioche, recipe.hash()).await?;
+    assert_eq!(local_recipe, recipe);
+
+    Ok(())
+}
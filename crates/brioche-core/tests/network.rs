@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use brioche_core::{
+    error_codes::{error_code, ErrorCode},
+    network::{ContactedHost, NetworkAllowlist, ProxyConfig},
+    recipe::{DownloadRecipe, Recipe},
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+#[test]
+fn test_network_allowlist() {
+    let allowlist = NetworkAllowlist::new(["example.com".into(), "*.crates.io".into()]);
+
+    assert!(allowlist.allows("example.com"));
+    assert!(allowlist.allows("cdn.example.com"));
+    assert!(allowlist.allows("EXAMPLE.com."));
+    assert!(allowlist.allows("crates.io"));
+    assert!(allowlist.allows("static.crates.io"));
+    assert!(!allowlist.allows("example.org"));
+    assert!(!allowlist.allows("notexample.com"));
+    assert!(!allowlist.allows("com"));
+
+    let allow_all = NetworkAllowlist::new(["*".into()]);
+    assert!(allow_all.allows("example.org"));
+
+    let allow_none = NetworkAllowlist::new([]);
+    assert!(!allow_none.allows("example.com"));
+}
+
+#[test]
+fn test_network_proxy_config() {
+    let proxy = ProxyConfig {
+        http: Some("http://proxy.corp:3128".parse().unwrap()),
+        https: Some("http://secure-proxy.corp:3128".parse().unwrap()),
+        no_proxy: Some("localhost, .internal.corp,example.com".into()),
+    };
+
+    assert_eq!(
+        proxy.proxy_for("http", "crates.io").map(|url| url.as_str()),
+        Some("http://proxy.corp:3128/"),
+    );
+    assert_eq!(
+        proxy
+            .proxy_for("https", "crates.io")
+            .map(|url| url.as_str()),
+        Some("http://secure-proxy.corp:3128/"),
+    );
+    assert_eq!(proxy.proxy_for("https", "localhost"), None);
+    assert_eq!(proxy.proxy_for("https", "git.internal.corp"), None);
+    assert_eq!(proxy.proxy_for("https", "internal.corp"), None);
+    assert_eq!(proxy.proxy_for("http", "cdn.example.com"), None);
+    assert!(proxy.proxy_for("http", "notexample.com").is_some());
+    assert_eq!(proxy.proxy_for("ftp", "crates.io"), None);
+
+    let env_vars = proxy.env_vars();
+    assert!(env_vars.contains(&("HTTPS_PROXY", "http://secure-proxy.corp:3128/".into())));
+    assert!(env_vars.contains(&("no_proxy", "localhost, .internal.corp,example.com".into())));
+
+    let bypass_all = ProxyConfig {
+        no_proxy: Some("*".into()),
+        ..proxy
+    };
+    assert_eq!(bypass_all.proxy_for("http", "crates.io"), None);
+}
+
+#[tokio::test]
+async fn test_network_allowlist_blocks_download() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.network_allowlist(vec!["example.com".to_string()])
+    })
+    .await;
+
+    let mut server = mockito::Server::new();
+    let server_url: url::Url = server.url().parse()?;
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body("hello")
+        .expect(0)
+        .create();
+
+    let download = Recipe::Download(DownloadRecipe {
+        hash: brioche_test::sha256("hello"),
+        url: server_url.join("file.txt")?,
+    });
+    let error = bake_without_meta(&brioche, download.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        Some(ErrorCode::NETWORK_HOST_NOT_ALLOWED)
+    );
+
+    let contacted_hosts = brioche_core::network::contacted_hosts(&brioche, download.hash()).await?;
+    assert_eq!(
+        contacted_hosts,
+        [ContactedHost {
+            host: server_url.host_str().unwrap().to_string(),
+            port: server_url.port().unwrap(),
+            allowed: false,
+        }],
+    );
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_network_allowlist_records_download_host() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let server_url: url::Url = server.url().parse()?;
+    let server_host = server_url.host_str().unwrap().to_string();
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body("hello")
+        .expect(1)
+        .create();
+
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.network_allowlist(vec![server_host.clone()])
+    })
+    .await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let download = Recipe::Download(DownloadRecipe {
+        hash: brioche_test::sha256("hello"),
+        url: server_url.join("file.txt")?,
+    });
+    assert_eq!(
+        bake_without_meta(&brioche, download.clone()).await?,
+        brioche_test::file(hello_blob, false),
+    );
+
+    let contacted_hosts = brioche_core::network::contacted_hosts(&brioche, download.hash()).await?;
+    assert_eq!(
+        contacted_hosts,
+        [ContactedHost {
+            host: server_host,
+            port: server_url.port().unwrap(),
+            allowed: true,
+        }],
+    );
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_network_allowlist_blocks_download_redirect() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let server_url: url::Url = server.url().parse()?;
+    let server_host = server_url.host_str().unwrap().to_string();
+    let redirect_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_status(302)
+        .with_header("Location", "http://blocked.invalid/file.txt")
+        .expect(1)
+        .create();
+
+    let (brioche, _context) = brioche_test::brioche_test_with(|builder| {
+        builder.network_allowlist(vec![server_host.clone()])
+    })
+    .await;
+
+    let download = Recipe::Download(DownloadRecipe {
+        hash: brioche_test::sha256("hello"),
+        url: server_url.join("file.txt")?,
+    });
+    let error = bake_without_meta(&brioche, download.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        Some(ErrorCode::NETWORK_HOST_NOT_ALLOWED)
+    );
+
+    let contacted_hosts = brioche_core::network::contacted_hosts(&brioche, download.hash()).await?;
+    assert_eq!(
+        contacted_hosts,
+        [
+            ContactedHost {
+                host: server_host,
+                port: server_url.port().unwrap(),
+                allowed: true,
+            },
+            ContactedHost {
+                host: "blocked.invalid".to_string(),
+                port: 80,
+                allowed: false,
+            },
+        ],
+    );
+
+    redirect_endpoint.assert();
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn proxy_request(
+    proxy: &brioche_core::network::NetworkProxy,
+    request: &str,
+) -> anyhow::Result<String> {
+    use std::os::linux::net::SocketAddrExt as _;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(proxy.socket_name())?;
+    let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+    stream.set_nonblocking(true)?;
+    let mut stream = tokio::net::UnixStream::from_std(stream)?;
+
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_network_proxy() -> anyhow::Result<()> {
+    let mut server = mockito::Server::new();
+    let server_url: url::Url = server.url().parse()?;
+    let server_host = server_url.host_str().unwrap().to_string();
+    let server_port = server_url.port().unwrap();
+    let hello_endpoint = server
+        .mock("GET", "/file.txt")
+        .with_body("hello")
+        .expect(1)
+        .create();
+
+    let allowlist = NetworkAllowlist::new([server_host.clone()]);
+    let proxy =
+        brioche_core::network::NetworkProxy::start(Arc::new(allowlist), ProxyConfig::default())?;
+
+    let response = proxy_request(
+        &proxy,
+        &format!(
+            "GET {} HTTP/1.1\r\nHost: {server_host}:{server_port}\r\nProxy-Connection: keep-alive\r\n\r\n",
+            server_url.join("file.txt")?
+        ),
+    )
+    .await?;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("hello"), "{response}");
+
+    let response = proxy_request(
+        &proxy,
+        "CONNECT blocked.invalid:443 HTTP/1.1\r\nHost: blocked.invalid:443\r\n\r\n",
+    )
+    .await?;
+    assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+
+    let contacted_hosts = proxy.stop();
+    assert_eq!(
+        contacted_hosts.into_iter().collect::<Vec<_>>(),
+        [
+            ContactedHost {
+                host: server_host,
+                port: server_port,
+                allowed: true,
+            },
+            ContactedHost {
+                host: "blocked.invalid".to_string(),
+                port: 443,
+                allowed: false,
+            },
+        ],
+    );
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_network_proxy_upstream() -> anyhow::Result<()> {
+    // The mock server acts as the upstream proxy, so it receives the
+    // request for the allowed host
+    let mut upstream = mockito::Server::new();
+    let upstream_url: url::Url = upstream.url().parse()?;
+    let hello_endpoint = upstream
+        .mock("GET", "/file.txt")
+        .with_body("hello")
+        .expect(1)
+        .create();
+
+    let allowlist = NetworkAllowlist::new(["example.com".to_string()]);
+    let upstream_proxy = ProxyConfig {
+        http: Some(upstream_url),
+        https: None,
+        no_proxy: None,
+    };
+    let proxy = brioche_core::network::NetworkProxy::start(Arc::new(allowlist), upstream_proxy)?;
+
+    let response = proxy_request(
+        &proxy,
+        "GET http://example.com/file.txt HTTP/1.1\r\nHost: example.com\r\n\r\n",
+    )
+    .await?;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("hello"), "{response}");
+
+    let contacted_hosts = proxy.stop();
+    assert_eq!(
+        contacted_hosts.into_iter().collect::<Vec<_>>(),
+        [ContactedHost {
+            host: "example.com".to_string(),
+            port: 80,
+            allowed: true,
+        }],
+    );
+
+    hello_endpoint.assert();
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use brioche_core::{
+    oci::{export_oci_image, plan_layers, ExportOciOptions, LayerGroup, LayerStrategy},
+    platform::current_platform,
+};
+
+mod brioche_test;
+
+fn packages(packages: &[(&str, usize)]) -> BTreeMap<bstr::BString, usize> {
+    packages
+        .iter()
+        .map(|(name, depth)| (bstr::BString::from(*name), *depth))
+        .collect()
+}
+
+fn group(name: &str, packages: &[&str]) -> LayerGroup {
+    LayerGroup {
+        name: name.to_string(),
+        packages: packages.iter().map(|name| (*name).into()).collect(),
+    }
+}
+
+#[test]
+fn test_oci_plan_layers_depth() {
+    let packages = packages(&[("app-lib", 1), ("libc", 3), ("libfoo", 2), ("libbar", 2)]);
+
+    assert_eq!(
+        plan_layers(&packages, LayerStrategy::Depth, 8),
+        vec![
+            group("depth-3", &["libc"]),
+            group("depth-2", &["libbar", "libfoo"]),
+            group("depth-1", &["app-lib"]),
+            group("root", &[]),
+        ]
+    );
+
+    // The deepest layers get merged when there are too many
+    assert_eq!(
+        plan_layers(&packages, LayerStrategy::Depth, 3),
+        vec![
+            group("depth-2+", &["libbar", "libc", "libfoo"]),
+            group("depth-1", &["app-lib"]),
+            group("root", &[]),
+        ]
+    );
+
+    assert_eq!(
+        plan_layers(&packages, LayerStrategy::Depth, 1),
+        vec![group("root", &[])]
+    );
+}
+
+#[test]
+fn test_oci_plan_layers_package() {
+    let few = packages(&[("app-lib", 1), ("libc", 2)]);
+    assert_eq!(
+        plan_layers(&few, LayerStrategy::Package, 8),
+        vec![
+            group("package-libc", &["libc"]),
+            group("package-app-lib", &["app-lib"]),
+            group("root", &[]),
+        ]
+    );
+
+    // With more packages than layers, adding a package only changes the
+    // layer it lands in
+    let many = packages(&[("a", 1), ("b", 1), ("c", 1), ("d", 1), ("e", 1)]);
+    let mut more = many.clone();
+    more.insert("f".into(), 1);
+    let layers = plan_layers(&many, LayerStrategy::Package, 3);
+    let more_layers = plan_layers(&more, LayerStrategy::Package, 3);
+    assert!(layers.len() <= 3);
+    let num_changed = layers
+        .iter()
+        .filter(|layer| !more_layers.contains(layer))
+        .count();
+    assert!(num_changed <= 1, "{layers:?} -> {more_layers:?}");
+}
+
+#[tokio::test]
+async fn test_oci_export_is_deterministic() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let lib_blob = brioche_test::blob(&brioche, "lib").await;
+    let program_blob = brioche_test::blob(&brioche, "program").await;
+    let resources = brioche_test::dir_value(
+        &brioche,
+        [("libfoo/libfoo.so", brioche_test::file(lib_blob, false))],
+    )
+    .await;
+    let artifact = brioche_test::dir(
+        &brioche,
+        [(
+            "bin/program",
+            brioche_test::file_with_resources(program_blob, true, resources),
+        )],
+    )
+    .await;
+
+    let mut digests = vec![];
+    for output in ["image-a", "image-b"] {
+        let output_dir = context.mkdir(output).await;
+        let result = export_oci_image(
+            &brioche,
+            &artifact,
+            ExportOciOptions {
+                output_dir: &output_dir,
+                strategy: LayerStrategy::Package,
+                max_layers: 8,
+                platform: current_platform(),
+                tag: Some("test:latest"),
+                entrypoint: Some("bin/program"),
+            },
+        )
+        .await?;
+
+        let names = result
+            .layers
+            .iter()
+            .map(|layer| &*layer.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["package-libfoo", "root"]);
+        assert!(output_dir.join("index.json").is_file());
+        digests.push(result.manifest_digest);
+    }
+
+    assert_eq!(digests[0], digests[1]);
+
+    Ok(())
+}
@@ -1,8 +1,12 @@
-use std::{os::unix::prelude::PermissionsExt, path::Path};
+use std::{
+    os::unix::prelude::{MetadataExt as _, PermissionsExt},
+    path::Path,
+};
 
 use assert_matches::assert_matches;
 use brioche_core::{output::create_local_output, recipe::Artifact, Brioche};
 use pretty_assertions::assert_eq;
+use tokio::io::{AsyncSeekExt as _, AsyncWriteExt as _};
 
 mod brioche_test;
 
@@ -27,6 +31,8 @@ async fn create_output(
             resource_dir: None,
             mtime: None,
             link_locals: false,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
         },
     )
     .await
@@ -48,6 +54,8 @@ async fn create_output_with_resources(
             resource_dir: Some(resource_dir),
             mtime: None,
             link_locals: false,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
         },
     )
     .await
@@ -68,6 +76,8 @@ async fn create_output_with_links(
             resource_dir: None,
             mtime: None,
             link_locals: true,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
         },
     )
     .await
@@ -95,6 +105,163 @@ async fn test_output_file() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_output_file_hardlink_strategy() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let blob_hash = brioche_test::blob(&brioche, b"hello").await;
+    let blob_path = brioche_core::blob::local_blob_path(&brioche, blob_hash).await?;
+
+    for (name, executable) in [("file", false), ("executable", true)] {
+        brioche_core::output::create_output(
+            &brioche,
+            &brioche_test::file(blob_hash, executable),
+            brioche_core::output::OutputOptions {
+                output_path: &context.path(name),
+                merge: false,
+                resource_dir: None,
+                mtime: None,
+                link_locals: false,
+                link_strategy: brioche_core::output::LinkStrategy::Hardlink,
+                permissions: brioche_core::output::OutputPermissions::default(),
+            },
+        )
+        .await?;
+
+        let contents = tokio::fs::read_to_string(&context.path(name)).await?;
+        assert_eq!(contents, "hello");
+    }
+
+    let blob_ino = tokio::fs::metadata(&blob_path).await?.ino();
+
+    // Non-executable files are linked to the blob, but executables are
+    // copied since the blob itself isn't executable
+    let file_metadata = tokio::fs::metadata(&context.path("file")).await?;
+    assert_eq!(file_metadata.ino(), blob_ino);
+
+    let executable_metadata = tokio::fs::metadata(&context.path("executable")).await?;
+    assert_ne!(executable_metadata.ino(), blob_ino);
+    assert_eq!(executable_metadata.permissions().mode() & 0o777, 0o755);
+
+    // Files with a custom modified time or mode are copied, so the blob
+    // itself isn't changed
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    let options = [
+        ("mtime", Some(mtime), Default::default()),
+        (
+            "umask",
+            None,
+            brioche_core::output::OutputPermissions {
+                umask: 0o077,
+                ..Default::default()
+            },
+        ),
+        (
+            "readonly",
+            None,
+            brioche_core::output::OutputPermissions {
+                readonly: true,
+                ..Default::default()
+            },
+        ),
+    ];
+    for (name, mtime, permissions) in options {
+        brioche_core::output::create_output(
+            &brioche,
+            &brioche_test::file(blob_hash, false),
+            brioche_core::output::OutputOptions {
+                output_path: &context.path(name),
+                merge: false,
+                resource_dir: None,
+                mtime,
+                link_locals: false,
+                link_strategy: brioche_core::output::LinkStrategy::Hardlink,
+                permissions,
+            },
+        )
+        .await?;
+
+        let metadata = tokio::fs::metadata(&context.path(name)).await?;
+        assert_ne!(metadata.ino(), blob_ino);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_sparse_file() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    // A 64 MiB file with a few bytes of data in the middle
+    const SIZE: u64 = 64 * 1024 * 1024;
+    let input_path = context.path("disk.img");
+    let mut input_file = tokio::fs::File::create(&input_path).await?;
+    input_file.set_len(SIZE).await?;
+    input_file.seek(std::io::SeekFrom::Start(SIZE / 2)).await?;
+    input_file.write_all(b"hello").await?;
+    input_file.flush().await?;
+    drop(input_file);
+
+    let permit = brioche_core::blob::get_save_blob_permit(&brioche).await?;
+    let blob_hash = brioche_core::blob::save_blob_from_file(
+        &brioche,
+        permit,
+        &input_path,
+        brioche_core::blob::SaveBlobOptions::new(),
+    )
+    .await?;
+    let data_extents = brioche_core::blob::blob_data_extents(&brioche, blob_hash).await?;
+    assert!(
+        data_extents.is_some(),
+        "expected sparse blob to have data extents"
+    );
+
+    let blob_path = brioche_core::blob::local_blob_path(&brioche, blob_hash).await?;
+    let blob_metadata = tokio::fs::metadata(&blob_path).await?;
+    assert_eq!(blob_metadata.len(), SIZE);
+    assert!(blob_metadata.blocks() * 512 < SIZE);
+
+    let artifact = brioche_test::file(blob_hash, false);
+    create_output(&brioche, &context.path("output"), &artifact, false).await?;
+
+    let output_metadata = tokio::fs::metadata(&context.path("output")).await?;
+    assert_eq!(output_metadata.len(), SIZE);
+    assert!(output_metadata.blocks() * 512 < SIZE);
+    assert_eq!(
+        tokio::fs::read(&context.path("output")).await?,
+        tokio::fs::read(&input_path).await?,
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_corrupt_blob() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let blob_hash = brioche_test::blob(&brioche, b"hello").await;
+    let blob_path = brioche_core::blob::local_blob_path(&brioche, blob_hash).await?;
+
+    // Corrupt the blob in the store
+    let mut permissions = tokio::fs::metadata(&blob_path).await?.permissions();
+    permissions.set_mode(0o644);
+    tokio::fs::set_permissions(&blob_path, permissions).await?;
+    tokio::fs::write(&blob_path, b"jello").await?;
+
+    let artifact = brioche_test::file(blob_hash, false);
+    let error = create_output(&brioche, &context.path("output"), &artifact, false)
+        .await
+        .expect_err("expected corrupt blob to fail");
+    assert_eq!(
+        brioche_core::error_codes::error_code(&error),
+        Some(brioche_core::error_codes::ErrorCode::CORRUPT_BLOB)
+    );
+    assert!(format!("{error:#}").contains(&blob_path.display().to_string()));
+    assert!(!context.path("output").exists());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_output_executable_file() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
@@ -127,6 +294,79 @@ async fn test_output_executable_file() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_output_custom_permissions() -> anyhow::Result<()> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let artifact = brioche_test::dir(
+        &brioche,
+        [
+            (
+                "hello.txt",
+                brioche_test::file(brioche_test::blob(&brioche, b"hello").await, false),
+            ),
+            (
+                "bin/hello",
+                brioche_test::file(brioche_test::blob(&brioche, b"hi").await, true),
+            ),
+        ],
+    )
+    .await;
+
+    // Changing the owner to the current user is always allowed
+    let current_metadata = tokio::fs::metadata(context.path("")).await?;
+    let permissions = brioche_core::output::OutputPermissions {
+        umask: 0o077,
+        directory_mode: Some(0o750),
+        readonly: true,
+        owner: Some(brioche_core::output::OutputOwner {
+            uid: current_metadata.uid(),
+            gid: current_metadata.gid(),
+        }),
+    };
+    let output_path = context.path("output");
+    let output_options = brioche_core::output::OutputOptions {
+        output_path: &output_path,
+        merge: false,
+        resource_dir: None,
+        mtime: None,
+        link_locals: false,
+        link_strategy: brioche_core::output::LinkStrategy::Copy,
+        permissions,
+    };
+    brioche_core::output::create_output(&brioche, &artifact, output_options).await?;
+
+    let mode = |path: &str| {
+        let path = context.path(path);
+        async move {
+            let metadata = tokio::fs::metadata(&path).await.unwrap();
+            metadata.permissions().mode() & 0o7777
+        }
+    };
+    assert_eq!(mode("output").await, 0o700);
+    assert_eq!(mode("output/bin").await, 0o700);
+    assert_eq!(mode("output/hello.txt").await, 0o400);
+    assert_eq!(mode("output/bin/hello").await, 0o500);
+
+    // Outputs linked to the store can't use custom permissions
+    let result = brioche_core::output::create_output(
+        &brioche,
+        &artifact,
+        brioche_core::output::OutputOptions {
+            output_path: &context.path("linked"),
+            link_locals: true,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            ..output_options
+        },
+    )
+    .await;
+    assert_matches!(result, Err(_));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_output_symlink() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
@@ -734,12 +974,38 @@ async fn test_output_top_level_file_with_parallel_resources() -> anyhow::Result<
     Ok(())
 }
 
+#[tokio::test]
+async fn test_output_local_output_path() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, b"hello").await;
+    let hello = brioche_test::file(hello_blob, false);
+    let hello_with_resource = brioche_test::file_with_resources(
+        hello_blob,
+        false,
+        brioche_test::dir_value(&brioche, [("resource.txt", hello.clone())]).await,
+    );
+
+    // The path is known before the local output is created
+    let local_path = brioche_core::output::local_output_path(&brioche, hello_with_resource.hash());
+    let local_resource_dir =
+        brioche_core::output::local_output_resource_dir(&brioche, hello_with_resource.hash());
+    assert!(!local_path.exists());
+
+    let local_output = create_local_output(&brioche, &hello_with_resource).await?;
+    assert_eq!(local_output.path, local_path);
+    assert_eq!(local_output.resource_dir, Some(local_resource_dir));
+    assert!(local_path.is_file());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_output_with_links() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
 
     let hello_blob = brioche_test::blob(&brioche, b"hello").await;
-    let hello_blob_path = brioche_core::blob::local_blob_path(&brioche, hello_blob);
+    let hello_blob_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
 
     let hello = brioche_test::file(hello_blob, false);
     let hello_exe = brioche_test::file(hello_blob, true);
@@ -961,6 +1227,138 @@ async fn test_output_with_links() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_output_atomic_replace() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let artifact = brioche_test::dir(
+        &brioche,
+        [(
+            "hi.txt",
+            brioche_test::file(brioche_test::blob(&brioche, b"hello").await, false),
+        )],
+    )
+    .await;
+
+    context.write_file("output/old.txt", "old").await;
+
+    brioche_core::output::create_output_atomic(
+        &brioche,
+        &artifact,
+        brioche_core::output::OutputOptions {
+            output_path: &context.path("output"),
+            merge: false,
+            resource_dir: None,
+            mtime: None,
+            link_locals: false,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
+        },
+        true,
+    )
+    .await?;
+
+    assert_eq!(
+        tokio::fs::read_to_string(context.path("output/hi.txt")).await?,
+        "hello",
+    );
+    assert!(!context.path("output/old.txt").exists());
+
+    // No temporary paths should be left behind
+    let mut entries = tokio::fs::read_dir(context.path("")).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        assert!(!name.to_string_lossy().contains(".brioche-"), "{name:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_atomic_conflict_no_replace() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let artifact = brioche_test::file(brioche_test::blob(&brioche, b"hello").await, false);
+
+    context.write_file("output", "old").await;
+
+    let result = brioche_core::output::create_output_atomic(
+        &brioche,
+        &artifact,
+        brioche_core::output::OutputOptions {
+            output_path: &context.path("output"),
+            merge: false,
+            resource_dir: None,
+            mtime: None,
+            link_locals: false,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
+        },
+        false,
+    )
+    .await;
+
+    assert_matches!(result, Err(_));
+    assert_eq!(
+        tokio::fs::read_to_string(context.path("output")).await?,
+        "old"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_atomic_failure_keeps_old_output() -> anyhow::Result<()> {
+    // Disable the registry so fetching the missing blob fails right away
+    let (brioche, context) = brioche_test::brioche_test_with(|builder| {
+        builder.registry_client(brioche_core::registry::RegistryClient::disabled())
+    })
+    .await;
+
+    let missing_blob = brioche_test::blob(&brioche, b"missing").await;
+    tokio::fs::remove_file(brioche_core::blob::local_blob_path(&brioche, missing_blob).await?)
+        .await?;
+
+    let artifact = brioche_test::dir(
+        &brioche,
+        [
+            (
+                "hi.txt",
+                brioche_test::file(brioche_test::blob(&brioche, b"hello").await, false),
+            ),
+            ("missing.txt", brioche_test::file(missing_blob, false)),
+        ],
+    )
+    .await;
+
+    context.write_file("output/old.txt", "old").await;
+
+    let result = brioche_core::output::create_output_atomic(
+        &brioche,
+        &artifact,
+        brioche_core::output::OutputOptions {
+            output_path: &context.path("output"),
+            merge: false,
+            resource_dir: None,
+            mtime: None,
+            link_locals: false,
+            link_strategy: brioche_core::output::LinkStrategy::Copy,
+            permissions: brioche_core::output::OutputPermissions::default(),
+        },
+        true,
+    )
+    .await;
+
+    assert_matches!(result, Err(_));
+    assert_eq!(
+        tokio::fs::read_to_string(context.path("output/old.txt")).await?,
+        "old",
+    );
+    assert!(!context.path("output/hi.txt").exists());
+
+    Ok(())
+}
+
 async fn assert_mtime_is_brioche_epoch(path: impl AsRef<Path>) {
     let path = path.as_ref();
     let metadata = tokio::fs::metadata(path)
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use brioche_core::{
+    packaging::{
+        package_entries, write_package, PackageEntry, PackageFormat, PackageMetadata,
+        PackageOptions,
+    },
+    platform::current_platform,
+    recipe::Directory,
+};
+
+mod brioche_test;
+
+fn metadata(name: &str) -> PackageMetadata {
+    PackageMetadata {
+        name: name.to_string(),
+        version: "1.0.0".to_string(),
+        license: Some("MIT".to_string()),
+        description: None,
+        maintainer: None,
+    }
+}
+
+async fn test_package(brioche: &brioche_core::Brioche) -> Directory {
+    let lib_blob = brioche_test::blob(brioche, "lib").await;
+    let program_blob = brioche_test::blob(brioche, "program").await;
+    let resources = brioche_test::dir_value(
+        brioche,
+        [("libfoo.so", brioche_test::file(lib_blob, false))],
+    )
+    .await;
+    brioche_test::dir_value(
+        brioche,
+        [
+            (
+                "bin/program",
+                brioche_test::file_with_resources(program_blob, true, resources),
+            ),
+            ("bin/alias", brioche_test::symlink("program")),
+        ],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_packaging_entries_include_resources() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+    let directory = test_package(&brioche).await;
+
+    let entries = package_entries(&brioche, &directory).await?;
+    let entries = entries
+        .into_iter()
+        .map(|(path, entry)| (path.to_string(), entry.mode()))
+        .collect::<BTreeMap<_, _>>();
+
+    assert_eq!(
+        entries,
+        BTreeMap::from_iter([
+            ("bin".to_string(), 0o755),
+            ("bin/alias".to_string(), 0o777),
+            ("bin/program".to_string(), 0o755),
+            ("brioche-resources.d".to_string(), 0o755),
+            ("brioche-resources.d/libfoo.so".to_string(), 0o644),
+        ])
+    );
+
+    let entries = package_entries(&brioche, &directory).await?;
+    assert!(matches!(
+        entries.get(&bstr::BString::from("bin/alias")),
+        Some(PackageEntry::Symlink { target }) if target == "program"
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_packaging_zip_and_deb_are_deterministic() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let directory = test_package(&brioche).await;
+    let metadata = metadata("program");
+
+    for format in [PackageFormat::Zip, PackageFormat::Deb] {
+        let mut contents = vec![];
+        for n in 0..2 {
+            let output_path = context.path(format!("package-{n}.{}", format.extension()));
+            write_package(
+                &brioche,
+                &directory,
+                PackageOptions {
+                    format,
+                    metadata: &metadata,
+                    platform: current_platform(),
+                    prefix: None,
+                },
+                &output_path,
+            )
+            .await?;
+            contents.push(tokio::fs::read(&output_path).await?);
+        }
+
+        assert_eq!(contents[0], contents[1], "{format} package changed");
+    }
+
+    let zip_file = std::fs::File::open(context.path("package-0.zip"))?;
+    let mut zip = zip::ZipArchive::new(zip_file)?;
+    let program = zip.by_name("program-1.0.0/bin/program")?;
+    assert_eq!(program.unix_mode().map(|mode| mode & 0o777), Some(0o755));
+    drop(program);
+    assert!(zip
+        .by_name("program-1.0.0/brioche-resources.d/libfoo.so")
+        .is_ok());
+
+    let deb = tokio::fs::read(context.path("package-0.deb")).await?;
+    assert!(deb.starts_with(b"!<arch>\ndebian-binary   "));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_packaging_invalid_name() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let directory = test_package(&brioche).await;
+
+    let result = write_package(
+        &brioche,
+        &directory,
+        PackageOptions {
+            format: PackageFormat::Deb,
+            metadata: &metadata("Not Valid"),
+            platform: current_platform(),
+            prefix: None,
+        },
+        &context.path("invalid.deb"),
+    )
+    .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use assert_matches::assert_matches;
+use brioche_core::path::{BriochePath, BriochePathError};
+
+fn path(path: &str) -> BriochePath {
+    BriochePath::new(path).unwrap()
+}
+
+#[test]
+fn test_path_normalize() {
+    assert_eq!(path("foo/bar").to_string(), "foo/bar");
+    assert_eq!(path("/foo//./bar/").to_string(), "foo/bar");
+    assert_eq!(path("foo\\bar").to_string(), "foo/bar");
+    assert_eq!(path("foo/../bar/baz/..").to_string(), "bar");
+    assert_eq!(path("foo/.."), BriochePath::root());
+    assert_eq!(path(""), BriochePath::root());
+    assert_eq!(path("."), BriochePath::root());
+
+    assert_matches!(
+        BriochePath::new("foo/../.."),
+        Err(BriochePathError::Escapes { .. })
+    );
+    assert_matches!(
+        BriochePath::new(b"foo/b\0r"),
+        Err(BriochePathError::InvalidComponent { .. })
+    );
+}
+
+#[test]
+fn test_path_components() {
+    let foo_bar = path("foo/bar");
+    assert_eq!(foo_bar.components().collect::<Vec<_>>(), vec!["foo", "bar"]);
+    assert_eq!(foo_bar.file_name(), Some(bstr::BStr::new("bar")));
+    assert_eq!(foo_bar.parent(), Some(path("foo")));
+    assert_eq!(path("foo").parent(), Some(BriochePath::root()));
+    assert_eq!(BriochePath::root().parent(), None);
+    assert_eq!(BriochePath::root().file_name(), None);
+    assert_eq!(BriochePath::root().components().count(), 0);
+}
+
+#[test]
+fn test_path_join() {
+    assert_eq!(path("foo").join(&path("bar/baz")), path("foo/bar/baz"));
+    assert_eq!(BriochePath::root().join(&path("foo")), path("foo"));
+    assert_eq!(path("foo").join(&BriochePath::root()), path("foo"));
+
+    assert_eq!(path("foo").join_component("bar").unwrap(), path("foo/bar"));
+    for name in ["", ".", "..", "bar/baz", "bar\\baz"] {
+        assert_matches!(
+            path("foo").join_component(name),
+            Err(BriochePathError::InvalidComponent { .. }),
+            "{name:?} should be an invalid component"
+        );
+    }
+
+    assert_eq!(path("a/b").resolve("../c").unwrap(), path("a/c"));
+    assert_matches!(
+        path("a/b").resolve("../../.."),
+        Err(BriochePathError::Escapes { .. })
+    );
+}
+
+#[test]
+fn test_path_display_lossy() {
+    let path = BriochePath::new(b"foo/\xffbar").unwrap();
+    assert_eq!(path.to_string(), "foo/\u{FFFD}bar");
+    assert_eq!(path.as_bytes(), b"foo/\xffbar");
+}
@@ -0,0 +1,52 @@
+use brioche_core::pins::{
+    list_pins, pin_blob, pin_hash, pinned_blobs, unpin_artifact, unpin_hash, PinKind,
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_pins() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hi_blob = brioche_test::blob(&brioche, "hi").await;
+    let hi_dir = brioche_test::lazy_dir([("hi.txt", brioche_test::lazy_file(hi_blob, false))]);
+    let artifact = bake_without_meta(&brioche, hi_dir).await?;
+    let artifact_hash = artifact.hash();
+
+    assert!(pinned_blobs(&brioche).await?.is_empty());
+
+    let kind = pin_hash(&brioche, &hello_blob.to_string(), Some("toolchain")).await?;
+    assert_eq!(kind, PinKind::Blob);
+    let kind = pin_hash(&brioche, &artifact_hash.to_string(), None).await?;
+    assert_eq!(kind, PinKind::Artifact);
+
+    let pins = list_pins(&brioche).await?;
+    assert_eq!(pins.len(), 2);
+    let blob_pin = pins
+        .iter()
+        .find(|pin| pin.kind == PinKind::Blob)
+        .expect("blob pin not found");
+    assert_eq!(blob_pin.hash, hello_blob.to_string());
+    assert_eq!(blob_pin.note.as_deref(), Some("toolchain"));
+
+    // Blobs within pinned artifacts are pinned too
+    let pinned = pinned_blobs(&brioche).await?;
+    assert_eq!(pinned, [hello_blob, hi_blob].into_iter().collect());
+
+    assert!(unpin_artifact(&brioche, artifact_hash).await?);
+    assert!(!unpin_artifact(&brioche, artifact_hash).await?);
+    let pinned = pinned_blobs(&brioche).await?;
+    assert_eq!(pinned, [hello_blob].into_iter().collect());
+
+    let unpinned = unpin_hash(&brioche, &hello_blob.to_string()).await?;
+    assert_eq!(unpinned, [PinKind::Blob]);
+    assert!(list_pins(&brioche).await?.is_empty());
+
+    // Only blobs saved locally can be pinned
+    let missing_blob = brioche_core::blob::BlobHash::for_content(b"missing");
+    assert!(pin_blob(&brioche, missing_blob, None).await.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use brioche_core::{
+    profile::{install_to_profile, ConflictPolicy, InstallOptions},
+    recipe::{Artifact, Directory},
+};
+
+mod brioche_test;
+
+async fn package(brioche: &brioche_core::Brioche, name: &str, content: &str) -> Directory {
+    let blob = brioche_test::blob(brioche, content).await;
+    brioche_test::dir_value(
+        brioche,
+        [(format!("bin/{name}"), brioche_test::file(blob, true))],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_profile_install_links_and_wraps() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let profile_dir = context.mkdir("profile").await;
+    let options = InstallOptions {
+        profile_dir: &profile_dir,
+        conflict_policy: ConflictPolicy::Error,
+    };
+
+    let mut packed_program = b"packed".to_vec();
+    brioche_pack::inject_pack(
+        &mut packed_program,
+        &brioche_pack::Pack::Static {
+            library_dirs: vec![b"lib".to_vec()],
+        },
+    )?;
+    let packed_blob = brioche_test::blob(&brioche, packed_program).await;
+    let lib_blob = brioche_test::blob(&brioche, "lib").await;
+    let resources = brioche_test::dir_value(
+        &brioche,
+        [("lib/libfoo.so", brioche_test::file(lib_blob, false))],
+    )
+    .await;
+
+    let mut directory = package(&brioche, "plain", "plain").await;
+    directory
+        .insert(
+            &brioche,
+            b"bin/packed",
+            Some(brioche_test::without_meta(
+                brioche_test::file_with_resources(packed_blob, true, resources),
+            )),
+        )
+        .await?;
+    let package_hash = Artifact::Directory(directory.clone()).hash();
+
+    let result = install_to_profile(&brioche, directory.clone(), options).await?;
+    assert_eq!(
+        result.package_dir,
+        profile_dir.join("packages").join(package_hash.to_string())
+    );
+    assert_eq!(result.installed, ["packed", "plain"]);
+    assert!(result.skipped.is_empty());
+
+    let plain_target = tokio::fs::read_link(profile_dir.join("bin/plain")).await?;
+    assert_eq!(plain_target, result.package_dir.join("bin/plain"));
+
+    let packed_wrapper = tokio::fs::read_to_string(profile_dir.join("bin/packed")).await?;
+    assert!(packed_wrapper.starts_with("#!/bin/sh\n"));
+    assert!(packed_wrapper.contains("BRIOCHE_RESOURCE_DIR="));
+    assert!(packed_wrapper.contains(&*result.package_dir.join("bin/packed").to_string_lossy()));
+
+    // Installing the same package again is a no-op, even though the
+    // executables already exist
+    let result = install_to_profile(&brioche, directory, options).await?;
+    assert_eq!(result.installed, ["packed", "plain"]);
+    assert_eq!(
+        tokio::fs::read_to_string(profile_dir.join("bin/packed")).await?,
+        packed_wrapper
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_profile_install_conflicts() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let profile_dir = context.mkdir("profile").await;
+
+    let first = package(&brioche, "hello", "first").await;
+    let second = package(&brioche, "hello", "second").await;
+
+    let options = |conflict_policy| InstallOptions {
+        profile_dir: &profile_dir,
+        conflict_policy,
+    };
+
+    let first_result = install_to_profile(&brioche, first, options(ConflictPolicy::Error)).await?;
+    let first_target = tokio::fs::read_link(profile_dir.join("bin/hello")).await?;
+    assert_eq!(first_target, first_result.package_dir.join("bin/hello"));
+
+    let result = install_to_profile(&brioche, second.clone(), options(ConflictPolicy::Error)).await;
+    assert!(result.is_err());
+    assert_eq!(
+        tokio::fs::read_link(profile_dir.join("bin/hello")).await?,
+        first_target
+    );
+
+    let result =
+        install_to_profile(&brioche, second.clone(), options(ConflictPolicy::Skip)).await?;
+    assert_eq!(result.skipped, ["hello"]);
+    assert_eq!(
+        tokio::fs::read_link(profile_dir.join("bin/hello")).await?,
+        first_target
+    );
+
+    let result = install_to_profile(&brioche, second, options(ConflictPolicy::Replace)).await?;
+    assert_eq!(result.installed, ["hello"]);
+    assert_eq!(
+        tokio::fs::read_link(profile_dir.join("bin/hello")).await?,
+        result.package_dir.join("bin/hello")
+    );
+
+    Ok(())
+}
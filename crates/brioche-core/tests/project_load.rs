@@ -1069,6 +1069,154 @@ async fn test_project_load_complex_implied() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_project_load_with_follows() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let main_project_dir = context.mkdir("mainproject").await;
+    context
+        .write_file(
+            "mainproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: {
+                            path: "../foo",
+                        },
+                        bar: {
+                            path: "../bar",
+                        },
+                    },
+                    follows: {
+                        foo: {
+                            bar: "bar",
+                        },
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    let foo_project_dir = context.mkdir("foo").await;
+    context
+        .write_file(
+            "foo/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        bar: {
+                            path: "../otherbar",
+                        },
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    let bar_project_dir = context.mkdir("bar").await;
+    context
+        .write_file(
+            "bar/project.bri",
+            r#"
+                export const project = {};
+                export const version = "1";
+            "#,
+        )
+        .await;
+
+    let other_bar_project_dir = context.mkdir("otherbar").await;
+    context
+        .write_file(
+            "otherbar/project.bri",
+            r#"
+                export const project = {};
+                export const version = "2";
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &main_project_dir).await?;
+    let project = projects.project(project_hash).unwrap();
+
+    let foo_project_hash = project.dependency_hash("foo").unwrap();
+    let foo_project = projects.project(foo_project_hash).unwrap();
+    assert!(projects
+        .local_paths(foo_project_hash)
+        .unwrap()
+        .contains(&foo_project_dir));
+
+    // Both projects use the same `bar`, and `foo`'s own `bar` isn't loaded
+    let bar_project_hash = project.dependency_hash("bar").unwrap();
+    assert_eq!(foo_project.dependency_hash("bar"), Some(bar_project_hash));
+    assert!(projects
+        .local_paths(bar_project_hash)
+        .unwrap()
+        .contains(&bar_project_dir));
+    assert_eq!(
+        projects.find_containing_project(&other_bar_project_dir)?,
+        None
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_load_with_follows_missing_dep() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let main_project_dir = context.mkdir("mainproject").await;
+    context
+        .write_file(
+            "mainproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: {
+                            path: "../foo",
+                        },
+                        bar: {
+                            path: "../bar",
+                        },
+                    },
+                    follows: {
+                        foo: {
+                            baz: "bar",
+                        },
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    let _foo_project_dir = context.mkdir("foo").await;
+    context
+        .write_file(
+            "foo/project.bri",
+            r#"
+                export const project = {};
+            "#,
+        )
+        .await;
+
+    let _bar_project_dir = context.mkdir("bar").await;
+    context
+        .write_file(
+            "bar/project.bri",
+            r#"
+                export const project = {};
+            "#,
+        )
+        .await;
+
+    let result = brioche_test::load_project(&brioche, &main_project_dir)
+        .await
+        .map(|_| ());
+
+    assert_matches!(result, Err(_));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_project_load_not_found() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
@@ -1214,6 +1362,119 @@ async fn test_project_load_brioche_include_outside_of_project() -> anyhow::Resul
     Ok(())
 }
 
+#[tokio::test]
+async fn test_project_load_brioche_include_symlink_outside_of_project() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    let secret_path = context.write_file("secret", "secret!!!").await;
+    context.write_symlink(&secret_path, "myproject/foo").await;
+
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+
+                globalThis.Brioche = {
+                    includeFile: (path) => {
+                        return {
+                            briocheSerialize: async () => {
+                                return Deno.core.ops.op_brioche_get_static(
+                                    import.meta.url,
+                                    {
+                                        type: "include",
+                                        include: "file",
+                                        path,
+                                    },
+                                );
+                            },
+                        };
+                    }
+                }
+
+                export default () => {
+                    return Brioche.includeFile("foo");
+                };
+            "#,
+        )
+        .await;
+
+    let result = brioche_test::load_project(&brioche, &project_dir)
+        .await
+        .map(|_| ());
+
+    assert_matches!(result, Err(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_load_symlinked_module_outside_of_project() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    let secret_path = context.write_file("secret.bri", "// secret!!!").await;
+    context
+        .write_symlink(&secret_path, "myproject/foo.bri")
+        .await;
+
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                import "./foo.bri";
+
+                export const project = {};
+            "#,
+        )
+        .await;
+
+    let result = brioche_test::load_project(&brioche, &project_dir)
+        .await
+        .map(|_| ());
+
+    assert_matches!(result, Err(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_load_symlinked_module_outside_of_project_allowed() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test_with(|builder| {
+        builder.script_read_access(brioche_core::script::read_access::ReadAccess::unrestricted())
+    })
+    .await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    let secret_path = context.write_file("secret.bri", "// secret!!!").await;
+    context
+        .write_symlink(&secret_path, "myproject/foo.bri")
+        .await;
+
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                import "./foo.bri";
+
+                export const project = {};
+            "#,
+        )
+        .await;
+
+    let result = brioche_test::load_project(&brioche, &project_dir)
+        .await
+        .map(|_| ());
+
+    assert_matches!(result, Ok(()));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_project_load_brioche_include_directory_as_file_error() -> anyhow::Result<()> {
     let (brioche, context) = brioche_test::brioche_test().await;
@@ -1307,3 +1568,227 @@ async fn test_project_load_brioche_include_file_as_directory_error() -> anyhow::
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_project_resolve_project_path() -> anyhow::Result<()> {
+    let (_brioche, mut context) = brioche_test::brioche_test().await;
+
+    context
+        .write_toml(
+            "myworkspace/brioche_workspace.toml",
+            &brioche_core::project::WorkspaceDefinition {
+                members: vec!["./packages/*".parse()?],
+            },
+        )
+        .await;
+    let foo_dir = context.mkdir("myworkspace/packages/foo").await;
+    context
+        .write_file("myworkspace/packages/foo/project.bri", "")
+        .await;
+    let bar_dir = context.mkdir("myworkspace/bar").await;
+    let workspace_dir = context.path("myworkspace");
+
+    // Paths relative to the current directory
+    assert_eq!(
+        brioche_core::project::resolve_project_path(&workspace_dir, "packages/foo").await?,
+        foo_dir,
+    );
+    assert_eq!(
+        brioche_core::project::resolve_project_path(&workspace_dir, "./bar").await?,
+        workspace_dir.join("./bar"),
+    );
+    assert_eq!(
+        brioche_core::project::resolve_project_path(&bar_dir, "..").await?,
+        bar_dir.join(".."),
+    );
+
+    // Workspace members, from anywhere within the workspace
+    assert_eq!(
+        brioche_core::project::resolve_project_path(&bar_dir, "foo").await?,
+        foo_dir,
+    );
+
+    assert!(
+        brioche_core::project::resolve_project_path(&workspace_dir, "missing")
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_load_with_registry_dep_skips_yanked() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let (foo_old_hash, foo_old_path) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    // foo v1
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let (foo_yanked_hash, _) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    // foo v2
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+
+    let mock_foo_latest = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!(
+                "/v0/project-tags/foo/latest?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            serde_json::to_string(&brioche_core::registry::GetProjectTagResponse {
+                project_hash: foo_yanked_hash,
+                yanked: true,
+                deprecation: None,
+            })
+            .unwrap(),
+        )
+        .create_async()
+        .await;
+    let mock_foo_versions = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!("/v0/project-versions/foo?brioche={}", brioche_core::VERSION),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            serde_json::to_string(&brioche_core::registry::GetProjectVersionsResponse {
+                versions: vec![
+                    brioche_core::registry::ProjectVersion {
+                        version: "2".to_string(),
+                        project_hash: foo_yanked_hash,
+                        yanked: true,
+                        deprecation: None,
+                    },
+                    brioche_core::registry::ProjectVersion {
+                        version: "1".to_string(),
+                        project_hash: foo_old_hash,
+                        yanked: false,
+                        deprecation: Some("use bar instead".to_string()),
+                    },
+                ],
+            })
+            .unwrap(),
+        )
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: "*",
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let project = projects.project(project_hash).unwrap();
+
+    // The newest version that isn't yanked should be used
+    let foo_dep_hash = project.dependency_hash("foo").unwrap();
+    assert_eq!(foo_dep_hash, foo_old_hash);
+    assert!(projects
+        .local_paths(foo_dep_hash)
+        .unwrap()
+        .contains(&foo_old_path));
+
+    mock_foo_latest.assert_async().await;
+    mock_foo_versions.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_load_with_registry_dep_locked_yanked() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let (foo_yanked_hash, foo_yanked_path) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    // foo v2
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let mock_foo_latest = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!(
+                "/v0/project-tags/foo/latest?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .expect(0)
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: "*",
+                    },
+                };
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/brioche.lock",
+            serde_json::to_string_pretty(&brioche_core::project::Lockfile {
+                dependencies: [("foo".to_string(), foo_yanked_hash)].into_iter().collect(),
+            })?,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let project = projects.project(project_hash).unwrap();
+
+    // The locked version should still be used, even though it was yanked
+    let foo_dep_hash = project.dependency_hash("foo").unwrap();
+    assert_eq!(foo_dep_hash, foo_yanked_hash);
+    assert!(projects
+        .local_paths(foo_dep_hash)
+        .unwrap()
+        .contains(&foo_yanked_path));
+
+    mock_foo_latest.assert_async().await;
+
+    Ok(())
+}
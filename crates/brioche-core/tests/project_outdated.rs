@@ -0,0 +1,210 @@
+mod brioche_test;
+
+#[tokio::test]
+async fn test_project_outdated_locked_dependency() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let (foo_v1_hash, _) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {
+                        name: "foo",
+                        version: "1.0.0",
+                    };
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let foo_v2_hash = context
+        .remote_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {
+                        name: "foo",
+                        version: "2.0.0",
+                    };
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let mock_foo_latest = context
+        .mock_registry_publish_tag("foo", "latest", foo_v2_hash)
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: "*",
+                    },
+                };
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/brioche.lock",
+            serde_json::to_string_pretty(&brioche_core::project::Lockfile {
+                dependencies: [("foo".to_string(), foo_v1_hash)].into_iter().collect(),
+            })?,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let outdated =
+        brioche_core::outdated::find_outdated_dependencies(&brioche, &projects, project_hash)
+            .await?;
+
+    assert_eq!(outdated.len(), 1);
+    let foo = &outdated[0];
+    assert_eq!(foo.name, "foo");
+    assert_eq!(foo.locked.hash, foo_v1_hash);
+    assert_eq!(foo.locked.version.as_deref(), Some("1.0.0"));
+    assert_eq!(foo.latest.hash, foo_v2_hash);
+    assert_eq!(foo.latest.version.as_deref(), Some("2.0.0"));
+    assert_eq!(
+        foo.upgradable.as_ref().map(|upgradable| upgradable.hash),
+        Some(foo_v2_hash)
+    );
+    assert!(!foo.is_blocked());
+
+    mock_foo_latest.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_outdated_up_to_date() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let (foo_hash, _) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {
+                        name: "foo",
+                        version: "1.0.0",
+                    };
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let mock_foo_latest = context
+        .mock_registry_publish_tag("foo", "latest", foo_hash)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                import "foo";
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    projects.commit_dirty_lockfiles().await?;
+
+    let outdated =
+        brioche_core::outdated::find_outdated_dependencies(&brioche, &projects, project_hash)
+            .await?;
+    assert!(outdated.is_empty());
+
+    mock_foo_latest.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_outdated_prefetch_updates() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let (foo_v1_hash, _) = context
+        .local_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {
+                        name: "foo",
+                        version: "1.0.0",
+                    };
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    let foo_v2_hash = context
+        .remote_registry_project(|path| async move {
+            tokio::fs::write(
+                path.join("project.bri"),
+                r#"
+                    export const project = {
+                        name: "foo",
+                        version: "2.0.0",
+                    };
+                "#,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+    context
+        .mock_registry_publish_tag("foo", "latest", foo_v2_hash)
+        .create_async()
+        .await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {
+                    dependencies: {
+                        foo: "*",
+                    },
+                };
+            "#,
+        )
+        .await;
+    let lockfile = serde_json::to_string_pretty(&brioche_core::project::Lockfile {
+        dependencies: [("foo".to_string(), foo_v1_hash)].into_iter().collect(),
+    })?;
+    context
+        .write_file("myproject/brioche.lock", &lockfile)
+        .await;
+
+    let foo_v2_path = brioche.home.join("projects").join(foo_v2_hash.to_string());
+    assert!(!tokio::fs::try_exists(&foo_v2_path).await?);
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let num_fetched =
+        brioche_core::outdated::prefetch_updates(&brioche, &projects, project_hash).await?;
+    assert_eq!(num_fetched, 1);
+
+    // The update is ready to use, but the lockfile is left alone
+    assert!(tokio::fs::try_exists(&foo_v2_path).await?);
+    assert_eq!(
+        tokio::fs::read_to_string(project_dir.join("brioche.lock")).await?,
+        lockfile
+    );
+
+    Ok(())
+}
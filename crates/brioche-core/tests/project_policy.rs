@@ -0,0 +1,214 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use brioche_core::{
+    bake::BakeScope,
+    platform::current_platform,
+    project::policy::{Capabilities, CapabilityPolicy},
+    recipe::{Directory, Meta, ProcessRecipe, ProcessTemplate, Recipe, StackFrame, WithMeta},
+    Brioche,
+};
+
+mod brioche_test;
+
+async fn write_networking_projects(context: &brioche_test::TestContext) -> std::path::PathBuf {
+    let main_project_dir = context.mkdir("mainproject").await;
+    context
+        .write_file(
+            "mainproject/project.bri",
+            r#"
+                import "depproject";
+                export const project = {
+                    dependencies: {
+                        depproject: {
+                            path: "../depproject",
+                        },
+                    },
+                };
+            "#,
+        )
+        .await;
+
+    context.mkdir("depproject").await;
+    context
+        .write_file(
+            "depproject/project.bri",
+            r#"
+                export const project = {};
+
+                export function fetch() {
+                    return std.process({
+                        command: "curl",
+                        unsafe: true,
+                        networking: true,
+                    });
+                }
+            "#,
+        )
+        .await;
+
+    main_project_dir
+}
+
+/// Bake the networking process from `depproject`, with a stack frame
+/// pointing into its module, and return the error message if baking failed.
+async fn bake_dep_process(
+    brioche: &Brioche,
+    context: &brioche_test::TestContext,
+) -> Option<String> {
+    let module_path = tokio::fs::canonicalize(context.path("depproject/project.bri"))
+        .await
+        .unwrap();
+    let meta = Meta {
+        source: Some(vec![StackFrame {
+            file_name: Some(url::Url::from_file_path(&module_path).unwrap().to_string()),
+            line_number: Some(6),
+            column_number: Some(28),
+        }]),
+    };
+    let process = ProcessRecipe {
+        command: ProcessTemplate { components: vec![] },
+        args: vec![],
+        env: BTreeMap::new(),
+        dependencies: vec![],
+        work_dir: Box::new(WithMeta::without_meta(Recipe::Directory(
+            Directory::default(),
+        ))),
+        output_scaffold: None,
+        platform: current_platform(),
+        is_unsafe: true,
+        networking: true,
+        output_hash: None,
+        capture_stdout: false,
+        tty: false,
+        cache_policy: Default::default(),
+        secrets: Default::default(),
+        incremental_key: None,
+    };
+
+    let recipe = WithMeta::new(Recipe::Process(process), Arc::new(meta));
+    let result = brioche_core::bake::bake(brioche, recipe, &BakeScope::Anonymous).await;
+    result.err().map(|error| format!("{error:#}"))
+}
+
+fn is_policy_error(error: &Option<String>) -> bool {
+    error
+        .as_deref()
+        .is_some_and(|error| error.contains("not allowed by policy"))
+}
+
+#[tokio::test]
+async fn test_project_policy_no_policy() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let main_project_dir = write_networking_projects(&context).await;
+
+    brioche_test::load_project(&brioche, &main_project_dir).await?;
+
+    let error = bake_dep_process(&brioche, &context).await;
+    assert!(!is_policy_error(&error), "unexpected error: {error:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_policy_denied() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test_with(|builder| {
+        builder.capability_policy(CapabilityPolicy::default())
+    })
+    .await;
+    let main_project_dir = write_networking_projects(&context).await;
+
+    brioche_test::load_project(&brioche, &main_project_dir).await?;
+
+    let error = bake_dep_process(&brioche, &context).await;
+    assert!(is_policy_error(&error), "unexpected error: {error:?}");
+    assert!(error
+        .unwrap()
+        .contains("project 'depproject' uses capability"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_policy_allowed() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test_with(|builder| {
+        builder.capability_policy(CapabilityPolicy {
+            default: Capabilities::default(),
+            projects: [(
+                "depproject".to_string(),
+                Capabilities {
+                    is_unsafe: true,
+                    networking: true,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        })
+    })
+    .await;
+    let main_project_dir = write_networking_projects(&context).await;
+
+    brioche_test::load_project(&brioche, &main_project_dir).await?;
+
+    let error = bake_dep_process(&brioche, &context).await;
+    assert!(!is_policy_error(&error), "unexpected error: {error:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_policy_project_file() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+    let main_project_dir = write_networking_projects(&context).await;
+    context
+        .write_file(
+            "mainproject/brioche_policy.toml",
+            r#"
+                [projects.depproject]
+                networking = true
+            "#,
+        )
+        .await;
+
+    brioche_test::load_project(&brioche, &main_project_dir).await?;
+
+    let error = bake_dep_process(&brioche, &context).await;
+    assert!(is_policy_error(&error), "unexpected error: {error:?}");
+    assert!(error.unwrap().contains("capability 'unsafe'"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_policy_project_file_narrows_user_policy() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test_with(|builder| {
+        builder.capability_policy(CapabilityPolicy {
+            default: Capabilities {
+                is_unsafe: true,
+                networking: false,
+            },
+            projects: Default::default(),
+        })
+    })
+    .await;
+    let main_project_dir = write_networking_projects(&context).await;
+
+    // The project's policy can't grant more than the user's policy allows
+    context
+        .write_file(
+            "mainproject/brioche_policy.toml",
+            r#"
+                [projects.depproject]
+                networking = true
+                unsafe = true
+            "#,
+        )
+        .await;
+
+    brioche_test::load_project(&brioche, &main_project_dir).await?;
+
+    let error = bake_dep_process(&brioche, &context).await;
+    assert!(is_policy_error(&error), "unexpected error: {error:?}");
+    assert!(error.unwrap().contains("capability 'networking'"));
+
+    Ok(())
+}
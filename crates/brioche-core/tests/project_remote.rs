@@ -0,0 +1,119 @@
+use brioche_core::project::remote::{fetch_remote_project, RemoteProjectSource};
+
+mod brioche_test;
+
+fn git(repo_path: &std::path::Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .env("GIT_AUTHOR_NAME", "brioche")
+        .env("GIT_AUTHOR_EMAIL", "brioche@example.com")
+        .env("GIT_COMMITTER_NAME", "brioche")
+        .env("GIT_COMMITTER_EMAIL", "brioche@example.com")
+        .output()
+        .expect("failed to run git");
+    assert!(output.status.success(), "git {args:?} failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+#[test]
+fn test_project_remote_source_parse() -> anyhow::Result<()> {
+    assert_eq!(
+        RemoteProjectSource::parse("github:brioche-dev/brioche-packages")?,
+        Some(RemoteProjectSource {
+            url: "https://github.com/brioche-dev/brioche-packages.git".parse()?,
+            rev: None,
+        }),
+    );
+    assert_eq!(
+        RemoteProjectSource::parse("github:brioche-dev/brioche-packages/v1.0")?,
+        Some(RemoteProjectSource {
+            url: "https://github.com/brioche-dev/brioche-packages.git".parse()?,
+            rev: Some("v1.0".to_string()),
+        }),
+    );
+    assert_eq!(
+        RemoteProjectSource::parse("gitlab:foo/bar")?,
+        Some(RemoteProjectSource {
+            url: "https://gitlab.com/foo/bar.git".parse()?,
+            rev: None,
+        }),
+    );
+    assert_eq!(
+        RemoteProjectSource::parse("git+https://example.com/foo.git?rev=main")?,
+        Some(RemoteProjectSource {
+            url: "https://example.com/foo.git".parse()?,
+            rev: Some("main".to_string()),
+        }),
+    );
+    assert_eq!(
+        RemoteProjectSource::parse("git+https://example.com/foo.git?a=b&rev=main")?,
+        Some(RemoteProjectSource {
+            url: "https://example.com/foo.git?a=b".parse()?,
+            rev: Some("main".to_string()),
+        }),
+    );
+
+    assert_eq!(RemoteProjectSource::parse("./packages/foo")?, None);
+    assert_eq!(RemoteProjectSource::parse("foo")?, None);
+    assert!(RemoteProjectSource::parse("github:foo").is_err());
+    assert!(RemoteProjectSource::parse("github:/bar").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_project_remote_fetch() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let repo_path = context.mkdir("repo").await;
+    git(&repo_path, &["init", "--quiet", "--initial-branch", "main"]);
+    context
+        .write_file(
+            "repo/project.bri",
+            r#"
+                export const project = {};
+            "#,
+        )
+        .await;
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "--quiet", "-m", "first"]);
+    let first_rev = git(&repo_path, &["rev-parse", "HEAD"]);
+
+    context.write_file("repo/README", "hello").await;
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "--quiet", "-m", "second"]);
+    let second_rev = git(&repo_path, &["rev-parse", "HEAD"]);
+
+    // Fetching without a rev should use the latest commit on the default
+    // branch
+    let source = RemoteProjectSource::parse(&format!("git+file://{}", repo_path.display()))?
+        .expect("expected remote source");
+    let fetched = fetch_remote_project(&brioche, &source).await?;
+    assert_eq!(fetched.commit, second_rev);
+    assert!(fetched.path.join("README").is_file());
+    assert!(!fetched.path.join(".git").exists());
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &fetched.path).await?;
+    assert!(projects.project(project_hash).is_ok());
+
+    // Fetching by commit hash should check out that exact commit
+    let source = RemoteProjectSource::parse(&format!(
+        "git+file://{}?rev={first_rev}",
+        repo_path.display()
+    ))?
+    .expect("expected remote source");
+    let fetched = fetch_remote_project(&brioche, &source).await?;
+    assert_eq!(fetched.commit, first_rev);
+    assert!(fetched.path.join("project.bri").is_file());
+    assert!(!fetched.path.join("README").exists());
+
+    // Fetching by branch name should resolve the branch
+    let source =
+        RemoteProjectSource::parse(&format!("git+file://{}?rev=main", repo_path.display()))?
+            .expect("expected remote source");
+    let fetched = fetch_remote_project(&brioche, &source).await?;
+    assert_eq!(fetched.commit, second_rev);
+
+    Ok(())
+}
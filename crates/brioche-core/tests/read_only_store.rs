@@ -0,0 +1,55 @@
+use brioche_core::{
+    blob::{get_save_blob_permit, SaveBlobOptions},
+    error_codes::{error_code, ErrorCode},
+};
+use brioche_test::bake_without_meta;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_read_only_store() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_dir =
+        brioche_test::lazy_dir([("hello.txt", brioche_test::lazy_file(hello_blob, false))]);
+    let baked = bake_without_meta(&brioche, hello_dir.clone()).await?;
+
+    let home = brioche.home.clone();
+    let (read_only_brioche, _read_only_context) =
+        brioche_test::brioche_test_with(|builder| builder.home(home).read_only(true)).await;
+    assert!(read_only_brioche.read_only);
+
+    // Anything already in the store can be used
+    let read_only_baked = bake_without_meta(&read_only_brioche, hello_dir).await?;
+    assert_eq!(read_only_baked, baked);
+
+    let permit = get_save_blob_permit(&read_only_brioche).await?;
+    let saved_blob =
+        brioche_core::blob::save_blob(&read_only_brioche, permit, b"hello", SaveBlobOptions::new())
+            .await?;
+    assert_eq!(saved_blob, hello_blob);
+
+    // Saving anything new fails
+    let permit = get_save_blob_permit(&read_only_brioche).await?;
+    let result = brioche_core::blob::save_blob(
+        &read_only_brioche,
+        permit,
+        b"goodbye",
+        SaveBlobOptions::new(),
+    )
+    .await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::READ_ONLY_STORE)
+    );
+
+    let new_dir = brioche_test::lazy_dir([("hi.txt", brioche_test::lazy_file(hello_blob, false))]);
+    let result = bake_without_meta(&read_only_brioche, new_dir).await;
+    assert_eq!(
+        result.err().as_ref().and_then(error_code),
+        Some(ErrorCode::READ_ONLY_STORE)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use brioche_core::recipe::{
+    diff::{diff_recipe_hashes, diff_recipes, RecipeChange},
+    Recipe,
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_recipe_diff_unchanged() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let recipe = Recipe::from(brioche_test::file(hello_blob, false));
+
+    let changes = diff_recipes(&brioche, &recipe, &recipe).await?;
+    assert_eq!(changes, []);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recipe_diff_nested_directory() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let readme_blob = brioche_test::blob(&brioche, "readme").await;
+
+    let old = brioche_test::dir(
+        &brioche,
+        [
+            (
+                "bin",
+                brioche_test::dir(&brioche, [("hello", brioche_test::file(hello_blob, false))])
+                    .await,
+            ),
+            ("README", brioche_test::file(readme_blob, false)),
+        ],
+    )
+    .await;
+    let new = brioche_test::dir(
+        &brioche,
+        [
+            (
+                "bin",
+                brioche_test::dir(&brioche, [("hello", brioche_test::file(hello_blob, true))])
+                    .await,
+            ),
+            ("README", brioche_test::file(readme_blob, false)),
+            ("link", brioche_test::symlink("README")),
+        ],
+    )
+    .await;
+    let old = Recipe::from(old);
+    let new = Recipe::from(new);
+    brioche_core::recipe::save_recipes(&brioche, [&old, &new]).await?;
+
+    let changes = diff_recipe_hashes(&brioche, old.hash(), new.hash()).await?;
+    assert_eq!(changes.len(), 2, "{changes:#?}");
+    assert_eq!(
+        changes[0],
+        RecipeChange {
+            path: "entries.bin.entries.hello.executable".to_string(),
+            old: Some("false".to_string()),
+            new: Some("true".to_string()),
+        },
+    );
+    assert_eq!(changes[1].path, "entries.link");
+    assert_eq!(changes[1].old, None);
+    assert!(changes[1].new.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recipe_diff_changed_type() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let old = Recipe::from(brioche_test::file(hello_blob, false));
+    let new = brioche_test::lazy_symlink("hello");
+
+    let changes = diff_recipes(&brioche, &old, &new).await?;
+    assert_eq!(changes.len(), 1, "{changes:#?}");
+    assert_eq!(changes[0].path, "");
+    assert!(changes[0]
+        .old
+        .as_deref()
+        .unwrap()
+        .starts_with("file recipe "));
+    assert!(changes[0]
+        .new
+        .as_deref()
+        .unwrap()
+        .starts_with("symlink recipe "));
+
+    Ok(())
+}
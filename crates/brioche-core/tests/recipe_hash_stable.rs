@@ -275,6 +275,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: false,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -296,6 +302,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: false,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -319,6 +331,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: false,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -349,6 +367,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: false,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -384,6 +408,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: false,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -419,6 +449,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: true,
             networking: false,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
@@ -454,6 +490,12 @@ async fn test_recipe_hash_stable_process() -> anyhow::Result<()> {
             platform: Platform::X86_64Linux,
             is_unsafe: true,
             networking: true,
+            output_hash: None,
+            capture_stdout: false,
+            tty: false,
+            cache_policy: Default::default(),
+            secrets: Default::default(),
+            incremental_key: None,
         })
         .hash()
         .to_string(),
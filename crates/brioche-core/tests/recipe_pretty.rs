@@ -0,0 +1,74 @@
+use brioche_core::recipe::{
+    pretty::{describe_artifact, render_artifact, render_recipe, short_hash},
+    Recipe,
+};
+use brioche_test::without_meta;
+use human_repr::HumanCount as _;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_recipe_pretty_render_recipe() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let recipe = Recipe::Merge {
+        directories: vec![
+            without_meta(brioche_test::lazy_dir([
+                ("b", brioche_test::lazy_symlink("a")),
+                ("a", brioche_test::lazy_file(hello_blob, true)),
+            ])),
+            without_meta(Recipe::Get {
+                directory: Box::new(without_meta(brioche_test::lazy_dir_empty())),
+                path: "foo".into(),
+            }),
+        ],
+    };
+
+    let rendered = render_recipe(&brioche, &recipe).await?;
+    let size = 5u64.human_count_bytes();
+    let expected = format!(
+        "merge\n\
+        \x20 0: create_directory\n\
+        \x20   a: file {} ({size}, executable)\n\
+        \x20   b: symlink -> \"a\"\n\
+        \x20 1: get \"foo\"\n\
+        \x20   directory: create_directory\n",
+        short_hash(hello_blob),
+    );
+    assert_eq!(rendered, expected);
+
+    // Rendering is stable
+    assert_eq!(render_recipe(&brioche, &recipe).await?, rendered);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recipe_pretty_render_artifact() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let inner =
+        brioche_test::dir(&brioche, [("hello", brioche_test::file(hello_blob, false))]).await;
+    let artifact = brioche_test::dir(&brioche, [("inner", inner.clone())]).await;
+
+    let rendered = render_artifact(&brioche, &artifact).await?;
+    let size = 5u64.human_count_bytes();
+    let expected = format!(
+        "directory {} (1 entry)\n\
+        \x20 inner: directory {} (1 entry)\n\
+        \x20   hello: file {} ({size})\n",
+        short_hash(artifact.hash()),
+        short_hash(inner.hash()),
+        short_hash(hello_blob),
+    );
+    assert_eq!(rendered, expected);
+
+    assert_eq!(
+        describe_artifact(&artifact),
+        format!("directory {} (1 entry)", short_hash(artifact.hash())),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+
+use brioche_core::{recipe::Recipe, registry::attestation::Attestations};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_registry_attestation_sign_and_verify() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let input = Recipe::from(brioche_test::file(hello_blob, false));
+    let input_hash = input.hash();
+    let output = brioche_test::dir_empty();
+    let output_hash = output.hash();
+
+    let attestation = brioche.attestations.sign(input_hash, output_hash).await?;
+    attestation.verify_signature()?;
+
+    // Any valid attestation is accepted when no keys are trusted
+    brioche
+        .attestations
+        .verify_bake(input_hash, output_hash, &output, Some(&attestation))?;
+    brioche
+        .attestations
+        .verify_bake(input_hash, output_hash, &output, None)?;
+
+    // The attestation must match the bake
+    let other_output = brioche_test::file(hello_blob, false);
+    assert!(brioche
+        .attestations
+        .verify_bake(
+            input_hash,
+            other_output.hash(),
+            &other_output,
+            Some(&attestation)
+        )
+        .is_err());
+    assert!(brioche
+        .attestations
+        .verify_bake(input_hash, output_hash, &other_output, Some(&attestation))
+        .is_err());
+
+    // A tampered manifest invalidates the signature
+    let mut tampered = attestation.clone();
+    tampered.manifest.output_hash = other_output.hash();
+    assert!(tampered.verify_signature().is_err());
+    assert!(brioche
+        .attestations
+        .verify_bake(
+            input_hash,
+            other_output.hash(),
+            &other_output,
+            Some(&tampered)
+        )
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_attestation_trusted_keys() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let input_hash = Recipe::from(brioche_test::file(hello_blob, false)).hash();
+    let output = brioche_test::dir_empty();
+    let output_hash = output.hash();
+
+    let attestation = brioche.attestations.sign(input_hash, output_hash).await?;
+
+    let keys_dir = tempdir::TempDir::new("brioche-test-keys")?;
+    let other_attestations =
+        Attestations::new(keys_dir.path().join("other-key.pk8"), BTreeSet::new());
+    let other_public_key = other_attestations.signing_key().await?.public_key();
+
+    // Bakes signed by other builders are rejected
+    let trusting_other = Attestations::new(
+        keys_dir.path().join("unused-key.pk8"),
+        BTreeSet::from([other_public_key]),
+    );
+    assert!(trusting_other
+        .verify_bake(input_hash, output_hash, &output, Some(&attestation))
+        .is_err());
+
+    // Unsigned bakes are rejected once any key is trusted
+    assert!(trusting_other
+        .verify_bake(input_hash, output_hash, &output, None)
+        .is_err());
+
+    // The signing key is persisted, so the same key is trusted across runs
+    let public_key = brioche.attestations.signing_key().await?.public_key();
+    let reloaded = Attestations::new(
+        brioche
+            .home
+            .join(brioche_core::registry::attestation::SIGNING_KEY_FILENAME),
+        BTreeSet::from([public_key]),
+    );
+    assert_eq!(
+        reloaded.signing_key().await?.public_key(),
+        attestation.public_key
+    );
+    reloaded.verify_bake(input_hash, output_hash, &output, Some(&attestation))?;
+
+    Ok(())
+}
@@ -111,6 +111,119 @@ async fn test_registry_client_get_blob() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_registry_client_download_blob_resume() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let path = context.write_file("test.txt", "hello world!").await;
+    let (file_id, contents) = brioche.vfs.load(&path).await?;
+
+    let contents_zstd = zstd::encode_all(&**contents, 0)?;
+    let (first_half, second_half) = contents_zstd.split_at(contents_zstd.len() / 2);
+    let first_half = first_half.to_vec();
+
+    // The first request fails partway through the body
+    let blob_path = format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION);
+    let first_mock = context
+        .registry_server
+        .mock("GET", &*blob_path)
+        .match_header("range", mockito::Matcher::Missing)
+        .with_header("Content-Type", "application/octet-stream")
+        .with_chunked_body(move |writer| {
+            writer.write_all(&first_half)?;
+            Err(std::io::Error::other("connection reset"))
+        })
+        .create();
+
+    // The retry only requests the rest of the blob
+    let resume_mock = context
+        .registry_server
+        .mock("GET", &*blob_path)
+        .match_header(
+            "range",
+            &*format!("bytes={}-", contents_zstd.len() - second_half.len()),
+        )
+        .with_status(206)
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(second_half)
+        .create();
+
+    let blob_hash = file_id.as_blob_hash()?;
+    let output_path = context.path("blob");
+    let size = brioche
+        .registry_client
+        .download_blob(blob_hash, &output_path, |_, _| {})
+        .await?;
+    assert_eq!(size, contents.len() as u64);
+    assert_eq!(tokio::fs::read(&output_path).await?, *contents);
+
+    first_mock.assert_async().await;
+    resume_mock.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_download_blob_invalid_hash() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let path = context.write_file("test.txt", "hello world!").await;
+    let (file_id, _) = brioche.vfs.load(&path).await?;
+
+    let evil_zstd = zstd::encode_all(&b"evil"[..], 0)?;
+    let mock = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION),
+        )
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(&*evil_zstd)
+        .create();
+
+    let blob_hash = file_id.as_blob_hash()?;
+    let result = brioche
+        .registry_client
+        .download_blob(blob_hash, &context.path("blob"), |_, _| {})
+        .await;
+    assert_matches!(result, Err(_));
+
+    mock.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_download_blob_not_found_not_retried() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let path = context.write_file("test.txt", "hello world!").await;
+    let (file_id, _) = brioche.vfs.load(&path).await?;
+
+    let mock = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION),
+        )
+        .with_status(404)
+        .expect(1)
+        .create();
+
+    let blob_hash = file_id.as_blob_hash()?;
+    let start = std::time::Instant::now();
+    let result = brioche
+        .registry_client
+        .download_blob(blob_hash, &context.path("blob"), |_, _| {})
+        .await;
+    assert_matches!(result, Err(_));
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+    mock.assert_async().await;
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_registry_client_get_blob_invalid_hash() -> anyhow::Result<()> {
     let (brioche, mut context) = brioche_test::brioche_test().await;
@@ -136,3 +249,492 @@ async fn test_registry_client_get_blob_invalid_hash() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_registry_client_concurrent_requests_limited() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions {
+            max_connections: 1,
+            ..Default::default()
+        },
+    );
+
+    let mut blobs = vec![];
+    let mut mocks = vec![];
+    for n in 0..4 {
+        let path = context
+            .write_file(format!("test{n}.txt"), format!("hello {n}"))
+            .await;
+        let (file_id, contents) = brioche.vfs.load(&path).await?;
+        let contents_zstd = zstd::encode_all(&**contents, 0)?;
+
+        let mock = context
+            .registry_server
+            .mock(
+                "GET",
+                &*format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION),
+            )
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(&*contents_zstd)
+            .create();
+
+        blobs.push((file_id.as_blob_hash()?, contents));
+        mocks.push(mock);
+    }
+
+    // All requests should finish even though only one can be in flight
+    // at a time
+    let results = futures::future::try_join_all(
+        blobs
+            .iter()
+            .map(|(blob_hash, _)| registry_client.get_blob(*blob_hash)),
+    )
+    .await?;
+    for ((_, contents), result) in blobs.iter().zip(results) {
+        assert_eq!(result, **contents);
+    }
+
+    for mock in mocks {
+        mock.assert_async().await;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_download_bandwidth_limited() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions {
+            download_bytes_per_second: Some(1000),
+            ..Default::default()
+        },
+    );
+
+    let mut blobs = vec![];
+    let mut mocks = vec![];
+    let mut total_bytes = 0;
+    for n in 0..3 {
+        let path = context
+            .write_file(format!("test{n}.txt"), format!("hello {n}"))
+            .await;
+        let (file_id, contents) = brioche.vfs.load(&path).await?;
+        let contents_zstd = zstd::encode_all(&**contents, 0)?;
+        total_bytes += contents_zstd.len();
+
+        let mock = context
+            .registry_server
+            .mock(
+                "GET",
+                &*format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION),
+            )
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(&*contents_zstd)
+            .create();
+
+        blobs.push((file_id.as_blob_hash()?, contents));
+        mocks.push(mock);
+    }
+
+    let start = std::time::Instant::now();
+    for (blob_hash, contents) in &blobs {
+        let result = registry_client.get_blob(*blob_hash).await?;
+        assert_eq!(result, **contents);
+    }
+
+    // The first blob is downloaded immediately, but every blob after that
+    // has to wait for the previous ones to fit within the limit
+    let last_blob_bytes = zstd::encode_all(&**blobs.last().unwrap().1, 0)?.len();
+    let min_duration = std::time::Duration::from_millis((total_bytes - last_blob_bytes) as u64);
+    assert!(start.elapsed() >= min_duration);
+
+    for mock in mocks {
+        mock.assert_async().await;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_scoped_project_tag_uses_scope_token() -> anyhow::Result<()> {
+    let (_brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions {
+            scope_tokens: [("myorg".to_string(), "secret".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    );
+
+    let project_hash: brioche_core::project::ProjectHash =
+        "0000000000000000000000000000000000000000000000000000000000000000".parse()?;
+    let mock = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!(
+                "/v0/project-tags/%40myorg%2Ffoo/latest?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .match_header("authorization", "Bearer secret")
+        .with_header("Content-Type", "application/json")
+        .with_body(serde_json::json!({ "projectHash": project_hash }).to_string())
+        .create();
+
+    let response = registry_client
+        .get_project_tag("@myorg/foo", "latest")
+        .await?;
+    assert_eq!(response.project_hash, project_hash);
+
+    mock.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_publish_permission_error() -> anyhow::Result<()> {
+    let (_brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions::default(),
+    );
+
+    let mock = context
+        .registry_server
+        .mock(
+            "POST",
+            &*format!("/v0/project-tags?brioche={}", brioche_core::VERSION),
+        )
+        .with_status(403)
+        .create();
+
+    let request = brioche_core::registry::CreateProjectTagsRequest {
+        tags: vec![brioche_core::registry::CreateProjectTagsRequestTag {
+            project_name: "@otherorg/foo".to_string(),
+            tag: "latest".to_string(),
+            project_hash: "0000000000000000000000000000000000000000000000000000000000000000"
+                .parse()?,
+        }],
+    };
+    let error = registry_client
+        .create_project_tags(&request)
+        .await
+        .unwrap_err();
+    let error = error
+        .downcast_ref::<brioche_core::registry::RegistryPermissionError>()
+        .expect("expected a permission error");
+    assert_eq!(error.project_name, "@otherorg/foo");
+    assert_eq!(error.scope.as_deref(), Some("otherorg"));
+    assert!(!error.has_scope_token);
+
+    mock.assert_async().await;
+
+    // Scoped names must be well-formed
+    let request = brioche_core::registry::CreateProjectTagsRequest {
+        tags: vec![brioche_core::registry::CreateProjectTagsRequestTag {
+            project_name: "@otherorg".to_string(),
+            tag: "latest".to_string(),
+            project_hash: "0000000000000000000000000000000000000000000000000000000000000000"
+                .parse()?,
+        }],
+    };
+    assert_matches!(registry_client.create_project_tags(&request).await, Err(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_prune_project() -> anyhow::Result<()> {
+    let (_brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions {
+            scope_tokens: [("myorg".to_string(), "secret".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    );
+
+    let project_hash: brioche_core::project::ProjectHash =
+        "0000000000000000000000000000000000000000000000000000000000000000".parse()?;
+    let prune_path = format!(
+        "/v0/project-versions/%40myorg%2Ffoo/prune?brioche={}",
+        brioche_core::VERSION
+    );
+    let mock = context
+        .registry_server
+        .mock("POST", &*prune_path)
+        .match_header("authorization", "Bearer secret")
+        .match_body(mockito::Matcher::Json(serde_json::json!({
+            "keepLatest": 2,
+            "dryRun": true,
+        })))
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "prunedTags": [{ "tag": "0.1.0", "projectHash": project_hash }],
+                "remainingTags": 2,
+            })
+            .to_string(),
+        )
+        .create();
+
+    let request = brioche_core::registry::PruneProjectRequest {
+        keep_latest: Some(2),
+        dry_run: true,
+        ..Default::default()
+    };
+    let response = registry_client
+        .prune_project("@myorg/foo", &request)
+        .await?;
+    assert_eq!(response.pruned_tags.len(), 1);
+    assert_eq!(response.pruned_tags[0].tag, "0.1.0");
+    assert_eq!(response.pruned_tags[0].project_hash, project_hash);
+    assert_eq!(response.remaining_tags, 2);
+
+    mock.assert_async().await;
+
+    // A retention policy is required, so nothing gets pruned by accident
+    let request = brioche_core::registry::PruneProjectRequest::default();
+    assert_matches!(
+        registry_client.prune_project("@myorg/foo", &request).await,
+        Err(_)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_prune_project_permission_error() -> anyhow::Result<()> {
+    let (_brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions::default(),
+    );
+
+    let mock = context
+        .registry_server
+        .mock(
+            "POST",
+            &*format!(
+                "/v0/project-versions/foo/prune?brioche={}",
+                brioche_core::VERSION
+            ),
+        )
+        .with_status(401)
+        .create();
+
+    let request = brioche_core::registry::PruneProjectRequest {
+        older_than_secs: Some(60 * 60 * 24 * 30),
+        ..Default::default()
+    };
+    let error = registry_client
+        .prune_project("foo", &request)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        brioche_core::error_codes::error_code(&error),
+        Some(brioche_core::error_codes::ErrorCode::REGISTRY_PERMISSION_DENIED)
+    );
+
+    mock.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_retries_after_throttled() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let path = context.write_file("test.txt", "hello").await;
+    let (file_id, contents) = brioche.vfs.load(&path).await?;
+    let contents_zstd = zstd::encode_all(&**contents, 0)?;
+    let blob_path = format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION);
+
+    // Mocks are matched in order, so the first request is throttled and
+    // the retry succeeds
+    let throttled_mock = context
+        .registry_server
+        .mock("GET", &*blob_path)
+        .with_status(429)
+        .with_header("Retry-After", "1")
+        .expect(1)
+        .create();
+    let mock = context
+        .registry_server
+        .mock("GET", &*blob_path)
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(&*contents_zstd)
+        .expect(1)
+        .create();
+
+    let start = std::time::Instant::now();
+    let result = brioche
+        .registry_client
+        .get_blob(file_id.as_blob_hash()?)
+        .await?;
+    assert_eq!(result, **contents);
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+
+    throttled_mock.assert_async().await;
+    mock.assert_async().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_client_requests_paced() -> anyhow::Result<()> {
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let registry_client = brioche_core::registry::RegistryClient::new_with_options(
+        context.registry_server.url().parse()?,
+        brioche_core::registry::RegistryAuthentication::Anonymous,
+        brioche_core::registry::RegistryClientOptions {
+            max_requests_per_second: Some(10.0),
+            ..Default::default()
+        },
+    );
+
+    let path = context.write_file("test.txt", "hello").await;
+    let (file_id, contents) = brioche.vfs.load(&path).await?;
+    let contents_zstd = zstd::encode_all(&**contents, 0)?;
+    let mock = context
+        .registry_server
+        .mock(
+            "GET",
+            &*format!("/v0/blobs/{file_id}.zst?brioche={}", brioche_core::VERSION),
+        )
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(&*contents_zstd)
+        .expect(4)
+        .create();
+
+    let start = std::time::Instant::now();
+    let blob_hash = file_id.as_blob_hash()?;
+    futures::future::try_join_all((0..4).map(|_| registry_client.get_blob(blob_hash))).await?;
+
+    // The first request is sent immediately, then each request after that
+    // waits 100ms
+    assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+
+    mock.assert_async().await;
+
+    Ok(())
+}
+
+#[test]
+fn test_registry_client_parse_retry_after() {
+    use brioche_core::registry::throttle::parse_retry_after;
+
+    assert_eq!(
+        parse_retry_after("5"),
+        Some(std::time::Duration::from_secs(5))
+    );
+    assert_eq!(
+        parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+        Some(std::time::Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon"), None);
+}
+
+#[tokio::test]
+async fn test_registry_fetch_artifact_subpath() -> anyhow::Result<()> {
+    // Create the artifact in a separate instance, so none of its recipes
+    // or blobs are saved locally
+    let (source_brioche, _source_context) = brioche_test::brioche_test().await;
+    let foo_blob = brioche_test::blob(&source_brioche, "foo").await;
+    let bar_blob = brioche_test::blob(&source_brioche, "bar").await;
+    let foo_file = brioche_test::file(foo_blob, true);
+    let bin_dir = brioche_test::dir(&source_brioche, [("foo", foo_file.clone())]).await;
+    let share_dir = brioche_test::dir(
+        &source_brioche,
+        [("bar", brioche_test::file(bar_blob, false))],
+    )
+    .await;
+    let root_dir = brioche_test::dir(
+        &source_brioche,
+        [("bin", bin_dir.clone()), ("share", share_dir.clone())],
+    )
+    .await;
+
+    let (brioche, mut context) = brioche_test::brioche_test().await;
+
+    let mut mock_recipe = |artifact: &brioche_core::recipe::Artifact, expected_hits| {
+        let recipe = brioche_core::recipe::Recipe::from(artifact.clone());
+        context
+            .registry_server
+            .mock(
+                "GET",
+                &*format!(
+                    "/v0/recipes/{}?brioche={}",
+                    artifact.hash(),
+                    brioche_core::VERSION
+                ),
+            )
+            .with_header("Content-Type", "application/json")
+            .with_body(serde_json::to_string(&recipe).unwrap())
+            .expect(expected_hits)
+            .create()
+    };
+    let bin_dir_mock = mock_recipe(&bin_dir, 1);
+    let foo_file_mock = mock_recipe(&foo_file, 1);
+    let share_dir_mock = mock_recipe(&share_dir, 0);
+
+    let mut mock_blob = |blob_hash: brioche_core::blob::BlobHash, contents: &str, expected_hits| {
+        context
+            .registry_server
+            .mock(
+                "GET",
+                &*format!(
+                    "/v0/blobs/{blob_hash}.zst?brioche={}",
+                    brioche_core::VERSION
+                ),
+            )
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(zstd::encode_all(contents.as_bytes(), 0).unwrap())
+            .expect(expected_hits)
+            .create()
+    };
+    let foo_blob_mock = mock_blob(foo_blob, "foo", 1);
+    let bar_blob_mock = mock_blob(bar_blob, "bar", 0);
+
+    // Only the path to `bin/foo` should be fetched
+    let result =
+        brioche_core::registry::fetch_artifact_subpath(&brioche, &root_dir, b"bin/foo").await?;
+    assert_eq!(result, Some(foo_file));
+    assert!(
+        tokio::fs::try_exists(brioche_core::blob::local_blob_path(&brioche, foo_blob).await?)
+            .await?
+    );
+
+    let result =
+        brioche_core::registry::fetch_artifact_subpath(&brioche, &root_dir, b"bin/missing").await?;
+    assert_eq!(result, None);
+
+    bin_dir_mock.assert_async().await;
+    foo_file_mock.assert_async().await;
+    share_dir_mock.assert_async().await;
+    foo_blob_mock.assert_async().await;
+    bar_blob_mock.assert_async().await;
+
+    Ok(())
+}
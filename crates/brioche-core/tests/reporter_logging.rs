@@ -0,0 +1,61 @@
+use brioche_core::reporter::logging::{create_log_file, LoggingConfig, Subsystem};
+
+#[test]
+fn test_reporter_logging_config() -> anyhow::Result<()> {
+    let config: LoggingConfig = toml::from_str(
+        r#"
+            filter = "brioche=warn"
+            log_file_filter = "brioche=trace"
+
+            [levels]
+            resolve = "debug"
+            sandbox = "trace"
+        "#,
+    )?;
+
+    assert_eq!(config.filter.as_deref(), Some("brioche=warn"));
+    assert_eq!(
+        config.levels.keys().copied().collect::<Vec<_>>(),
+        [Subsystem::Bake, Subsystem::Sandbox]
+    );
+
+    let filter = config.filter_with("brioche=info")?.to_string();
+    assert!(filter.contains("brioche_core::bake=debug"));
+    assert!(filter.contains("brioche_core::sandbox=trace"));
+    assert!(config
+        .log_file_filter()?
+        .to_string()
+        .contains("brioche=trace"));
+
+    let invalid: LoggingConfig = toml::from_str(
+        r#"
+            [levels]
+            blob = "loud"
+        "#,
+    )?;
+    assert!(invalid.filter_with("brioche=info").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_reporter_logging_rotate_log_files() -> anyhow::Result<()> {
+    let logs_dir = tempdir::TempDir::new("brioche-test-logs")?;
+
+    let mut log_paths = vec![];
+    for _ in 0..5 {
+        let (log_path, _) = create_log_file(logs_dir.path(), Some(3))?;
+        log_paths.push(log_path);
+
+        // Make sure each log file gets a distinct timestamp
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+
+    let mut remaining = std::fs::read_dir(logs_dir.path())?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    remaining.sort();
+    assert_eq!(remaining, log_paths[2..]);
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use brioche_core::sandbox::subid::{find_subid_range, id_mappings, IdMapping, SubidRange};
+
+#[test]
+fn test_subid_find_range_by_name() {
+    let contents = "alice:100000:65536\nbob:165536:65536\n";
+    assert_eq!(
+        find_subid_range(contents, "bob", 1001),
+        Some(SubidRange {
+            start: 165536,
+            count: 65536
+        })
+    );
+}
+
+#[test]
+fn test_subid_find_range_by_id() {
+    let contents = "1000:100000:65536\n";
+    assert_eq!(
+        find_subid_range(contents, "alice", 1000),
+        Some(SubidRange {
+            start: 100000,
+            count: 65536
+        })
+    );
+}
+
+#[test]
+fn test_subid_find_range_skips_invalid() {
+    let contents = "alice\nalice:foo:65536\nalice:100000:0\nalice:200000:10\n";
+    assert_eq!(
+        find_subid_range(contents, "alice", 1000),
+        Some(SubidRange {
+            start: 200000,
+            count: 10
+        })
+    );
+    assert_eq!(find_subid_range(contents, "bob", 1001), None);
+}
+
+#[test]
+fn test_subid_id_mappings_without_range() {
+    assert_eq!(
+        id_mappings(1099, 1000, None),
+        vec![IdMapping {
+            inside: 1099,
+            outside: 1000,
+            count: 1
+        }]
+    );
+}
+
+#[test]
+fn test_subid_id_mappings_with_range() {
+    let range = SubidRange {
+        start: 100000,
+        count: 65536,
+    };
+    assert_eq!(
+        id_mappings(1099, 1000, Some(range)),
+        vec![
+            IdMapping {
+                inside: 1099,
+                outside: 1000,
+                count: 1
+            },
+            IdMapping {
+                inside: 0,
+                outside: 100000,
+                count: 1099
+            },
+            IdMapping {
+                inside: 1100,
+                outside: 101099,
+                count: 64437
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_subid_id_mappings_small_range() {
+    let range = SubidRange {
+        start: 100000,
+        count: 10,
+    };
+    assert_eq!(
+        id_mappings(1099, 1000, Some(range)),
+        vec![
+            IdMapping {
+                inside: 1099,
+                outside: 1000,
+                count: 1
+            },
+            IdMapping {
+                inside: 0,
+                outside: 100000,
+                count: 10
+            },
+        ]
+    );
+
+    // Running as root within the sandbox only maps IDs above it
+    assert_eq!(
+        id_mappings(0, 1000, Some(range)),
+        vec![
+            IdMapping {
+                inside: 0,
+                outside: 1000,
+                count: 1
+            },
+            IdMapping {
+                inside: 1,
+                outside: 100000,
+                count: 10
+            },
+        ]
+    );
+}
@@ -0,0 +1,53 @@
+use brioche_core::sandbox::terminal::TerminalOutputNormalizer;
+
+fn normalize_chunks(chunks: &[&[u8]]) -> String {
+    let mut normalizer = TerminalOutputNormalizer::default();
+    let mut output = vec![];
+    for chunk in chunks {
+        normalizer.normalize(chunk, &mut output);
+    }
+    normalizer.finish(&mut output);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_terminal_normalize_plain_text() {
+    assert_eq!(normalize_chunks(&[b"hello\tworld\n"]), "hello\tworld\n");
+}
+
+#[test]
+fn test_terminal_normalize_line_endings() {
+    assert_eq!(normalize_chunks(&[b"a\r\nb\r\n"]), "a\nb\n");
+    assert_eq!(normalize_chunks(&[b"10%\r50%\r100%\n"]), "10%\n50%\n100%\n");
+    assert_eq!(normalize_chunks(&[b"done\r"]), "done\n");
+}
+
+#[test]
+fn test_terminal_normalize_escape_sequences() {
+    assert_eq!(
+        normalize_chunks(&[b"\x1b[1;31merror\x1b[0m: failed\n"]),
+        "error: failed\n"
+    );
+    assert_eq!(
+        normalize_chunks(&[b"\x1b]0;window title\x07hello\n"]),
+        "hello\n"
+    );
+    assert_eq!(
+        normalize_chunks(&[b"\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\\n"]),
+        "link\n"
+    );
+    assert_eq!(normalize_chunks(&[b"\x1b(Bplain\x1b=\n"]), "plain\n");
+    assert_eq!(normalize_chunks(&[b"beep\x07\x08\n"]), "beep\n");
+}
+
+#[test]
+fn test_terminal_normalize_split_chunks() {
+    let input = b"\x1b[1;31merror\x1b[0m\r\nnext\r\n";
+    let expected = normalize_chunks(&[input]);
+    assert_eq!(expected, "error\nnext\n");
+
+    for split in 0..input.len() {
+        let (a, b) = input.split_at(split);
+        assert_eq!(normalize_chunks(&[a, b]), expected, "split at {split}");
+    }
+}
@@ -1,4 +1,7 @@
-use brioche_core::script::evaluate::evaluate;
+use brioche_core::script::{
+    evaluate::{evaluate, evaluate_with_control},
+    EvaluationControl, EvaluationTerminated,
+};
 
 mod brioche_test;
 
@@ -536,3 +539,284 @@ async fn test_eval_brioche_glob_submodule() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_eval_project_env() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    context
+        .write_file(
+            "myproject/brioche.toml",
+            r#"
+                [env.BRIOCHE_TEST_EVAL_PROJECT_ENV_NAME]
+                type = "string"
+
+                [env.BRIOCHE_TEST_EVAL_PROJECT_ENV_COUNT]
+                type = "number"
+                default = 3
+
+                [env.BRIOCHE_TEST_EVAL_PROJECT_ENV_ENABLED]
+                type = "boolean"
+                default = false
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+                export default () => {
+                    const env = Deno.core.ops.op_brioche_project_env(import.meta.url);
+                    const expected = {
+                        BRIOCHE_TEST_EVAL_PROJECT_ENV_NAME: "hello",
+                        BRIOCHE_TEST_EVAL_PROJECT_ENV_COUNT: 3,
+                        BRIOCHE_TEST_EVAL_PROJECT_ENV_ENABLED: true,
+                    };
+                    if (JSON.stringify(env) !== JSON.stringify(expected)) {
+                        throw new Error(`unexpected env: ${JSON.stringify(env)}`);
+                    }
+
+                    return {
+                        briocheSerialize: () => {
+                            return {
+                                type: "directory",
+                                entries: {},
+                            }
+                        },
+                    };
+                };
+            "#,
+        )
+        .await;
+
+    std::env::remove_var("BRIOCHE_TEST_EVAL_PROJECT_ENV_NAME");
+    std::env::set_var("BRIOCHE_TEST_EVAL_PROJECT_ENV_ENABLED", "true");
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let project = projects.project(project_hash)?;
+    assert_eq!(project.env.len(), 3);
+
+    // The name has no default, so it's required
+    let result = evaluate(&brioche, &projects, project_hash, "default").await;
+    assert!(result.is_err());
+
+    std::env::set_var("BRIOCHE_TEST_EVAL_PROJECT_ENV_NAME", "hello");
+
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::dir_empty().into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_api_version() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+
+    let script = format!(
+        r#"
+            export const project = {{}};
+            export default async () => {{
+                const version = Deno.core.ops.op_brioche_api_version(import.meta.url);
+                const supported = Deno.core.ops.op_brioche_supported_api_versions();
+                if (version < supported.min || version > supported.current) {{
+                    throw new Error(`unsupported version: ${{version}}`);
+                }}
+
+                const bytes = await Deno.core.opAsync("op_brioche_read_blob_bytes", "{hello_blob}");
+                const shimmed = await Deno.core.opAsync("op_brioche_read_blob", "{hello_blob}");
+                if (!(bytes instanceof Uint8Array) || bytes.length !== 5 || shimmed !== "hello") {{
+                    throw new Error("unexpected blob contents");
+                }}
+
+                return {{
+                    briocheSerialize: () => {{
+                        return {{
+                            type: "symlink",
+                            target: `${{version}}`,
+                        }}
+                    }},
+                }};
+            }};
+        "#
+    );
+
+    let legacy_dir = context.mkdir("legacy").await;
+    context.write_file("legacy/project.bri", &script).await;
+
+    let versioned_dir = context.mkdir("versioned").await;
+    context.write_file("versioned/project.bri", &script).await;
+    context
+        .write_file("versioned/brioche.toml", "api_version = 2\n")
+        .await;
+
+    // Projects without a declared version get the original API
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &legacy_dir).await?;
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::lazy_symlink("1"));
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &versioned_dir).await?;
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::lazy_symlink("2"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_api_version_unsupported() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+            "#,
+        )
+        .await;
+    context
+        .write_file("myproject/brioche.toml", "api_version = 999\n")
+        .await;
+
+    let result = brioche_test::load_project(&brioche, &project_dir).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_terminated() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+                export default async () => {
+                    await Deno.core.opAsync("op_brioche_bake_all", []);
+                    return {
+                        briocheSerialize: () => {
+                            return {
+                                type: "directory",
+                                entries: {},
+                            }
+                        },
+                    };
+                };
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+
+    let control = EvaluationControl::with_timeout(std::time::Duration::ZERO);
+    let result =
+        evaluate_with_control(&brioche, &projects, project_hash, "default", &control).await;
+    let error = result.expect_err("expected evaluation to time out");
+    assert_eq!(
+        error.downcast_ref::<EvaluationTerminated>(),
+        Some(&EvaluationTerminated::TimedOut),
+    );
+
+    // Timing out shouldn't cancel the caller's token
+    assert!(!control.cancellation.is_cancelled());
+
+    let control = EvaluationControl::default();
+    control.cancellation.cancel();
+    let result =
+        evaluate_with_control(&brioche, &projects, project_hash, "default", &control).await;
+    let error = result.expect_err("expected evaluation to be cancelled");
+    assert_eq!(
+        error.downcast_ref::<EvaluationTerminated>(),
+        Some(&EvaluationTerminated::Cancelled),
+    );
+
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::dir_empty().into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_cached() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+
+    context
+        .write_file(
+            "myproject/brioche.toml",
+            r#"
+                [env.BRIOCHE_TEST_EVAL_CACHED_NAME]
+                type = "string"
+                default = "a"
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+                export default () => {
+                    const env = Deno.core.ops.op_brioche_project_env(import.meta.url);
+                    return {
+                        briocheSerialize: () => {
+                            return {
+                                type: "symlink",
+                                target: env.BRIOCHE_TEST_EVAL_CACHED_NAME,
+                            }
+                        },
+                    };
+                };
+            "#,
+        )
+        .await;
+
+    std::env::remove_var("BRIOCHE_TEST_EVAL_CACHED_NAME");
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::lazy_symlink("a"));
+
+    // A cached evaluation doesn't run any JS, so it succeeds even when
+    // the evaluation is already cancelled
+    let cancelled = EvaluationControl::default();
+    cancelled.cancellation.cancel();
+    let resolved = evaluate_with_control(&brioche, &projects, project_hash, "default", &cancelled)
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::lazy_symlink("a"));
+
+    // Changing a declared environment variable invalidates the cache
+    std::env::set_var("BRIOCHE_TEST_EVAL_CACHED_NAME", "b");
+    let result =
+        evaluate_with_control(&brioche, &projects, project_hash, "default", &cancelled).await;
+    assert!(result.is_err());
+
+    let resolved = evaluate(&brioche, &projects, project_hash, "default")
+        .await?
+        .value;
+    assert_eq!(resolved, brioche_test::lazy_symlink("b"));
+
+    Ok(())
+}
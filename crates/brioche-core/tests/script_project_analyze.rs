@@ -3,7 +3,10 @@ use std::collections::{BTreeSet, HashMap};
 use assert_matches::assert_matches;
 use brioche_core::{
     project::{
-        analyze::{analyze_project, ImportAnalysis, ProjectAnalysis, StaticInclude, StaticQuery},
+        analyze::{
+            analyze_project, module_exports, ImportAnalysis, ProjectAnalysis, StaticInclude,
+            StaticQuery,
+        },
         DependencyDefinition, ProjectDefinition, Version,
     },
     script::specifier::{BriocheImportSpecifier, BriocheModuleSpecifier},
@@ -51,7 +54,9 @@ async fn test_analyze_simple_project() -> anyhow::Result<()> {
         ProjectDefinition {
             name: None,
             version: None,
+            license: None,
             dependencies: HashMap::new(),
+            follows: HashMap::new(),
         },
     );
 
@@ -73,6 +78,7 @@ async fn test_analyze_project_metadata() -> anyhow::Result<()> {
                 export const project = {
                     name: "myproject",
                     version: "0.1.0",
+                    license: "MIT",
                 };
             "#,
         )
@@ -85,7 +91,9 @@ async fn test_analyze_project_metadata() -> anyhow::Result<()> {
         ProjectDefinition {
             name: Some("myproject".to_string()),
             version: Some("0.1.0".to_string()),
+            license: Some("MIT".to_string()),
             dependencies: HashMap::new(),
+            follows: HashMap::new(),
         },
     );
 
@@ -266,10 +274,12 @@ async fn test_analyze_external_dep() -> anyhow::Result<()> {
         ProjectDefinition {
             name: None,
             version: None,
+            license: None,
             dependencies: HashMap::from_iter([(
                 "foo".to_string(),
                 DependencyDefinition::Version(Version::Any),
             ),]),
+            follows: HashMap::new(),
         }
     );
 
@@ -374,3 +384,26 @@ async fn test_analyze_static_brioche_glob() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_analyze_module_exports() -> anyhow::Result<()> {
+    let exports = module_exports(
+        r#"
+            import * as std from "std";
+            export const project = {};
+            export function testFoo() {}
+            export async function testBar() {}
+            export const testBaz = () => {}, other = 1;
+            export default () => {};
+            export { std };
+            function notExported() {}
+        "#,
+    )?;
+
+    assert_eq!(
+        exports,
+        ["project", "testFoo", "testBar", "testBaz", "other"]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use brioche_core::script::unused::{find_unused, UnusedItemKind};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_find_unused() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                import { used } from "./lib.bri";
+                import * as everything from "./all";
+                export const project = {};
+                export function rootExport() {
+                    return used() + everything.foo;
+                }
+                function unusedRootHelper() {}
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/lib.bri",
+            r#"
+                export function used() {
+                    return helper();
+                }
+                export function unusedExport() {}
+                export const usedLocally = 1, alsoUnused = 2;
+                function helper() {
+                    return usedLocally;
+                }
+                function recursive(n) {
+                    return recursive(n - 1);
+                }
+            "#,
+        )
+        .await;
+    context
+        .write_file(
+            "myproject/all/index.bri",
+            r#"
+                export const foo = 1;
+                export const bar = 2;
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+
+    let unused = find_unused(&brioche.vfs, &projects, project_hash).await?;
+    let unused = unused
+        .iter()
+        .map(|item| {
+            let module = item.module_path.strip_prefix(&project_dir).unwrap();
+            (module.to_str().unwrap(), item.name.as_str(), item.kind)
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        unused,
+        [
+            ("lib.bri", "unusedExport", UnusedItemKind::Export),
+            ("lib.bri", "alsoUnused", UnusedItemKind::Export),
+            ("lib.bri", "recursive", UnusedItemKind::Helper),
+            ("project.bri", "unusedRootHelper", UnusedItemKind::Helper),
+        ]
+    );
+
+    Ok(())
+}
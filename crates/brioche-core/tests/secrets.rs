@@ -0,0 +1,55 @@
+use brioche_core::secrets::{validate_secret_name, Redactor};
+
+fn redact_all(redactor: &mut Redactor, chunks: &[&str]) -> String {
+    let mut output = vec![];
+    for chunk in chunks {
+        output.extend(redactor.redact(chunk.as_bytes()));
+    }
+    output.extend(redactor.finish());
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_redactor() {
+    let mut redactor = Redactor::new([b"hunter2".to_vec()]);
+    assert_eq!(
+        redact_all(&mut redactor, &["password: hunter2\n", "again: hunter2"]),
+        "password: [REDACTED]\nagain: [REDACTED]",
+    );
+}
+
+#[test]
+fn test_redactor_split_across_writes() {
+    let mut redactor = Redactor::new([b"hunter2".to_vec()]);
+    assert_eq!(
+        redact_all(&mut redactor, &["password: hun", "t", "er2 done"]),
+        "password: [REDACTED] done",
+    );
+}
+
+#[test]
+fn test_redactor_trailing_newline() {
+    let mut redactor = Redactor::new([b"hunter2\n".to_vec(), b"".to_vec()]);
+    assert_eq!(
+        redact_all(&mut redactor, &["hunter2 and hunter2"]),
+        "[REDACTED] and [REDACTED]",
+    );
+}
+
+#[test]
+fn test_redactor_without_secrets() {
+    let mut redactor = Redactor::new([]);
+    assert_eq!(redactor.redact(b"hello"), b"hello");
+    assert_eq!(redactor.finish(), b"");
+}
+
+#[test]
+fn test_validate_secret_name() {
+    assert!(validate_secret_name("npm_token").is_ok());
+    assert!(validate_secret_name("signing-key.pem").is_ok());
+
+    assert!(validate_secret_name("").is_err());
+    assert!(validate_secret_name(".hidden").is_err());
+    assert!(validate_secret_name("..").is_err());
+    assert!(validate_secret_name("dir/token").is_err());
+}
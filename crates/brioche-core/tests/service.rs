@@ -0,0 +1,80 @@
+use brioche_core::{
+    annotations::ServiceRestartPolicy,
+    service::{project_fingerprint, run_service, ServiceCommand},
+};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_service_project_fingerprint() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let project_dir = context.mkdir("myproject").await;
+    context
+        .write_file(
+            "myproject/project.bri",
+            r#"
+                export const project = {};
+            "#,
+        )
+        .await;
+
+    let (projects, project_hash) = brioche_test::load_project(&brioche, &project_dir).await?;
+    let fingerprint = project_fingerprint(&projects, project_hash).await?;
+    assert_eq!(
+        project_fingerprint(&projects, project_hash).await?,
+        fingerprint
+    );
+
+    // Hidden files are ignored
+    context
+        .write_file("myproject/.cache/output.txt", "ignored")
+        .await;
+    assert_eq!(
+        project_fingerprint(&projects, project_hash).await?,
+        fingerprint
+    );
+
+    context.write_file("myproject/src/hello.txt", "hello").await;
+    assert_ne!(
+        project_fingerprint(&projects, project_hash).await?,
+        fingerprint
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_service_restart_on_failure() -> anyhow::Result<()> {
+    let (_brioche, context) = brioche_test::brioche_test().await;
+
+    // Fails until it has run 3 times
+    let counter_path = context.path("counter");
+    let command = ServiceCommand {
+        program: "/bin/sh".into(),
+        args: vec![
+            "-c".into(),
+            r#"echo run >> "$COUNTER"; test "$(wc -l < "$COUNTER")" -ge 3"#.into(),
+        ],
+        env: vec![("COUNTER".into(), counter_path.clone().into())],
+        restart: ServiceRestartPolicy::OnFailure,
+    };
+
+    let stop = tokio_util::sync::CancellationToken::new();
+    let status = run_service(&command, stop).await?;
+    assert!(status.is_some_and(|status| status.success()));
+
+    let runs = tokio::fs::read_to_string(&counter_path).await?;
+    assert_eq!(runs.lines().count(), 3);
+
+    let command = ServiceCommand {
+        restart: ServiceRestartPolicy::Never,
+        args: vec!["-c".into(), "exit 3".into()],
+        ..command
+    };
+    let stop = tokio_util::sync::CancellationToken::new();
+    let status = run_service(&command, stop).await?;
+    assert_eq!(status.and_then(|status| status.code()), Some(3));
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use brioche_core::snapshot::{check_snapshot, SnapshotResult};
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_snapshot_check() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hi_blob = brioche_test::blob(&brioche, "hi").await;
+    let hello_recipe =
+        brioche_test::lazy_dir([("hello", brioche_test::lazy_file(hello_blob, false))]);
+    let hi_recipe = brioche_test::lazy_dir([("hello", brioche_test::lazy_file(hi_blob, false))]);
+
+    let snapshot_path = context.path("__snapshots__/default.snap");
+
+    let result = check_snapshot(&brioche, &hello_recipe, &snapshot_path, false).await?;
+    assert_eq!(result, SnapshotResult::Created);
+    let snapshot = tokio::fs::read_to_string(&snapshot_path).await?;
+    assert!(snapshot.starts_with("create_directory\n"));
+
+    let result = check_snapshot(&brioche, &hello_recipe, &snapshot_path, false).await?;
+    assert_eq!(result, SnapshotResult::Matched);
+
+    // A mismatch is reported without touching the snapshot
+    let result = check_snapshot(&brioche, &hi_recipe, &snapshot_path, false).await?;
+    let SnapshotResult::Mismatched { diff } = result else {
+        panic!("expected snapshot mismatch, got {result:?}");
+    };
+    assert!(diff.contains("-  hello: file"));
+    assert!(diff.contains("+  hello: file"));
+    assert_eq!(tokio::fs::read_to_string(&snapshot_path).await?, snapshot);
+
+    let result = check_snapshot(&brioche, &hi_recipe, &snapshot_path, true).await?;
+    assert_eq!(result, SnapshotResult::Updated);
+    let result = check_snapshot(&brioche, &hi_recipe, &snapshot_path, false).await?;
+    assert_eq!(result, SnapshotResult::Matched);
+
+    Ok(())
+}
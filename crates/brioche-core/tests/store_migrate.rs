@@ -0,0 +1,102 @@
+use brioche_core::recipe::Recipe;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_store_migrate() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let recipe = Recipe::from(brioche_test::file(hello_blob, false));
+    brioche_core::recipe::save_recipes(&brioche, [&recipe]).await?;
+
+    let locals_dir = brioche.home.join("locals");
+    tokio::fs::create_dir_all(locals_dir.join("dir")).await?;
+    tokio::fs::write(locals_dir.join("dir/file"), "world").await?;
+    tokio::fs::symlink("dir/file", locals_dir.join("link")).await?;
+
+    let temp_dir = brioche.home.join("locals-temp");
+    tokio::fs::create_dir_all(&temp_dir).await?;
+    tokio::fs::write(temp_dir.join("leftover"), "leftover").await?;
+
+    let new_home = context.path("new-home");
+    let report = brioche_core::store_migrate::migrate_store(&brioche, &new_home).await?;
+    assert_eq!(report.new_home, new_home);
+    assert_eq!(report.num_files, 2);
+    assert_eq!(report.total_size, 10);
+    assert_eq!(report.num_blobs_verified, 1);
+
+    // The old store is left untouched
+    assert!(brioche_core::blob::local_blob_path(&brioche, hello_blob)
+        .await?
+        .exists());
+
+    assert_eq!(
+        tokio::fs::read_to_string(new_home.join("locals/dir/file")).await?,
+        "world"
+    );
+    assert_eq!(
+        tokio::fs::read_link(new_home.join("locals/link")).await?,
+        std::path::Path::new("dir/file")
+    );
+    assert!(!new_home.join("locals-temp").exists());
+
+    let (new_brioche, _new_context) =
+        brioche_test::brioche_test_with(|builder| builder.home(new_home.clone())).await;
+    assert_eq!(
+        brioche_core::recipe::get_recipe(&new_brioche, recipe.hash()).await?,
+        recipe
+    );
+    assert!(
+        brioche_core::blob::local_blob_path(&new_brioche, hello_blob)
+            .await?
+            .exists()
+    );
+
+    brioche_core::store_migrate::remove_old_store(&brioche.home).await?;
+    assert!(!brioche_core::blob::local_blob_path(&brioche, hello_blob)
+        .await?
+        .exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_migrate_to_non_empty_dir() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let new_home = context.mkdir("new-home").await;
+    context.write_file("new-home/file.txt", "hello").await;
+
+    let result = brioche_core::store_migrate::migrate_store(&brioche, &new_home).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_migrate_into_current_store() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let new_home = brioche.home.join("nested");
+    let result = brioche_core::store_migrate::migrate_store(&brioche, &new_home).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_migrate_corrupted_blob() -> anyhow::Result<()> {
+    let (brioche, context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_blob_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    tokio::fs::remove_file(&hello_blob_path).await?;
+    tokio::fs::write(&hello_blob_path, "goodbye").await?;
+
+    let new_home = context.path("new-home");
+    let result = brioche_core::store_migrate::migrate_store(&brioche, &new_home).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use brioche_core::blob::BlobSource;
+
+mod brioche_test;
+
+#[tokio::test]
+async fn test_store_stats_duplicates() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    let hello_blob_path = brioche_core::blob::local_blob_path(&brioche, hello_blob).await?;
+    brioche_test::blob(&brioche, "world").await;
+
+    let locals_dir = brioche.home.join("locals");
+    tokio::fs::create_dir_all(locals_dir.join("dir")).await?;
+
+    // A hard link to a blob isn't a duplicate
+    tokio::fs::hard_link(&hello_blob_path, locals_dir.join("linked")).await?;
+
+    // A copy of a blob is
+    tokio::fs::write(locals_dir.join("dir/copied"), "hello").await?;
+
+    // Same size, different contents
+    tokio::fs::write(locals_dir.join("other"), "howdy").await?;
+
+    let temp_dir = brioche.home.join("locals-temp");
+    tokio::fs::create_dir_all(&temp_dir).await?;
+    tokio::fs::write(temp_dir.join("leftover"), "leftover").await?;
+
+    let report = brioche_core::store_stats::store_report(&brioche).await?;
+    assert_eq!(report.num_files, 4);
+    assert_eq!(report.total_size, 20);
+    assert_eq!(report.duplicates.len(), 1);
+    assert_eq!(report.duplicates[0].content_hash, hello_blob);
+    assert_eq!(
+        report.duplicates[0].paths,
+        vec![hello_blob_path, locals_dir.join("dir/copied")]
+    );
+    assert_eq!(report.duplicate_size, 5);
+    assert_eq!(report.temp_size, 8);
+    assert_eq!(report.reclaimable_size(), 13);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_stats_recorded_blobs() -> anyhow::Result<()> {
+    let (brioche, _context) = brioche_test::brioche_test().await;
+
+    let hello_blob = brioche_test::blob(&brioche, "hello").await;
+    brioche_test::blob(&brioche, "hi").await;
+
+    // Saving the same blob again doesn't record it twice
+    brioche_test::blob(&brioche, "hello").await;
+
+    let permit = brioche_core::blob::get_save_blob_permit(&brioche).await?;
+    let download_blob = brioche_core::blob::save_blob(
+        &brioche,
+        permit,
+        b"downloaded",
+        brioche_core::blob::SaveBlobOptions::new()
+            .source_url("https://example.com/file.txt".parse()?),
+    )
+    .await?;
+
+    let totals = brioche_core::store_stats::blob_totals(&brioche).await?;
+    assert_eq!(totals.num_blobs, 3);
+    assert_eq!(totals.total_size, 17);
+    let sources = totals
+        .sources
+        .iter()
+        .map(|source| (source.source, source.num_blobs, source.total_size))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        [(BlobSource::Download, 1, 10), (BlobSource::Local, 2, 7)]
+    );
+
+    let largest = brioche_core::store_stats::largest_blobs(&brioche, 2).await?;
+    let largest_hashes = largest
+        .iter()
+        .map(|blob| blob.blob_hash)
+        .collect::<Vec<_>>();
+    assert_eq!(largest_hashes, [download_blob, hello_blob]);
+    assert_eq!(
+        largest[0].source_url.as_deref(),
+        Some("https://example.com/file.txt")
+    );
+    assert_eq!(largest[1].source_url, None);
+
+    let growth = brioche_core::store_stats::blob_growth(&brioche, 1).await?;
+    assert_eq!(growth.len(), 1);
+    assert_eq!(growth[0].num_blobs, 3);
+    assert_eq!(growth[0].total_size, 17);
+
+    Ok(())
+}
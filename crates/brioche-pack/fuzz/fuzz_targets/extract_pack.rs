@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|program: &[u8]| {
+    // Any result is fine, we only care that parsing never panics
+    let _ = brioche_pack::try_extract_pack_from_bytes(program);
+
+    // Also make sure a valid marker and length with a bogus pack in
+    // between doesn't panic
+    let mut packed = program.to_vec();
+    let length = u32::try_from(program.len()).unwrap_or(u32::MAX);
+    packed.extend_from_slice(b"brioche_pack_v0                 ");
+    packed.extend_from_slice(&length.to_le_bytes());
+    packed.extend_from_slice(program);
+    packed.extend_from_slice(&length.to_le_bytes());
+    packed.extend_from_slice(b"brioche_pack_v0                 ");
+    let _ = brioche_pack::try_extract_pack_from_bytes(&packed);
+});
@@ -14,6 +14,11 @@ const SEARCH_DEPTH_LIMIT: u32 = 64;
 const LENGTH_BYTES: usize = 4;
 type LengthInt = u32;
 
+/// The maximum size of an encoded pack. Packs are small (mostly paths), so
+/// this is only here to bound how much we'll allocate when decoding a
+/// malformed pack.
+const MAX_PACK_LENGTH: usize = 16 * 1024 * 1024;
+
 #[serde_with::serde_as]
 #[derive(Debug, bincode::Encode, bincode::Decode, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
@@ -186,16 +191,32 @@ pub fn inject_pack(mut writer: impl std::io::Write, pack: &Pack) -> Result<(), I
     Ok(())
 }
 
-pub fn extract_pack(mut reader: impl std::io::Read) -> Result<Pack, ExtractPackError> {
+pub fn extract_pack(reader: impl std::io::Read) -> Result<Pack, ExtractPackError> {
+    let pack = try_extract_pack(reader)?;
+    pack.ok_or(ExtractPackError::MarkerNotFound)
+}
+
+/// Extract a pack from a packed program, if it has one. Returns `Ok(None)`
+/// if the program doesn't end with a pack marker, or an error if the program
+/// has a marker but the pack itself is malformed or truncated.
+pub fn try_extract_pack(mut reader: impl std::io::Read) -> Result<Option<Pack>, ExtractPackError> {
     let mut program = vec![];
     reader
         .read_to_end(&mut program)
         .map_err(ExtractPackError::ReadPackedProgramError)?;
 
-    let program = program
-        .strip_suffix(MARKER)
-        .ok_or_else(|| ExtractPackError::MarkerNotFound)?;
-    let (program, length_bytes) = program.split_at(program.len().wrapping_sub(LENGTH_BYTES));
+    try_extract_pack_from_bytes(&program)
+}
+
+/// Like [`try_extract_pack`], but operating on the full contents of the
+/// packed program. This never panics, even for arbitrary (or hostile) input.
+pub fn try_extract_pack_from_bytes(program: &[u8]) -> Result<Option<Pack>, ExtractPackError> {
+    let Some(program) = program.strip_suffix(MARKER) else {
+        return Ok(None);
+    };
+
+    let (program, length_bytes) =
+        split_suffix(program, LENGTH_BYTES).ok_or(ExtractPackError::MalformedMarker)?;
     let length_bytes: [u8; LENGTH_BYTES] = length_bytes
         .try_into()
         .map_err(|_| ExtractPackError::MalformedMarker)?;
@@ -203,19 +224,34 @@ pub fn extract_pack(mut reader: impl std::io::Read) -> Result<Pack, ExtractPackE
     let length: usize = length
         .try_into()
         .map_err(|_| ExtractPackError::MalformedMarker)?;
+    if length > MAX_PACK_LENGTH {
+        return Err(ExtractPackError::PackTooLarge { length });
+    }
 
-    let (program, pack) = program.split_at(program.len().wrapping_sub(length));
+    let (program, pack) = split_suffix(program, length).ok_or(ExtractPackError::TruncatedPack {
+        length,
+        available: program.len(),
+    })?;
     let program = program
         .strip_suffix(&length_bytes)
-        .ok_or_else(|| ExtractPackError::MalformedMarker)?;
+        .ok_or(ExtractPackError::MalformedMarker)?;
     let _program = program
         .strip_suffix(MARKER)
-        .ok_or_else(|| ExtractPackError::MalformedMarker)?;
+        .ok_or(ExtractPackError::MalformedMarker)?;
 
-    let (pack, _) = bincode::decode_from_slice(pack, bincode::config::standard())
-        .map_err(ExtractPackError::InvalidPack)?;
+    let config = bincode::config::standard().with_limit::<MAX_PACK_LENGTH>();
+    let (pack, consumed) =
+        bincode::decode_from_slice(pack, config).map_err(ExtractPackError::InvalidPack)?;
+    if consumed != length {
+        return Err(ExtractPackError::TrailingPackData { length, consumed });
+    }
+
+    Ok(Some(pack))
+}
 
-    Ok(pack)
+fn split_suffix(bytes: &[u8], suffix_length: usize) -> Option<(&[u8], &[u8])> {
+    let split_index = bytes.len().checked_sub(suffix_length)?;
+    Some(bytes.split_at(split_index))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -246,6 +282,12 @@ pub enum ExtractPackError {
     MarkerNotFound,
     #[error("marker was malformed at the end of the packed program")]
     MalformedMarker,
+    #[error("pack length {length} exceeds the maximum pack length")]
+    PackTooLarge { length: usize },
+    #[error("pack is truncated: expected {length} bytes but only {available} bytes are available")]
+    TruncatedPack { length: usize, available: usize },
+    #[error("pack has trailing data: parsed {consumed} of {length} bytes")]
+    TrailingPackData { length: usize, consumed: usize },
     #[error("failed to parse pack: {0}")]
     InvalidPack(#[source] bincode::error::DecodeError),
 }
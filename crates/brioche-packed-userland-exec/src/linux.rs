@@ -182,6 +182,11 @@ fn error_summary(error: &PackedError) -> &'static str {
             brioche_pack::ExtractPackError::MalformedMarker => {
                 "malformed marker at the end of the packed program"
             }
+            brioche_pack::ExtractPackError::PackTooLarge { .. } => {
+                "pack length exceeds the maximum pack length"
+            }
+            brioche_pack::ExtractPackError::TruncatedPack { .. } => "pack is truncated",
+            brioche_pack::ExtractPackError::TrailingPackData { .. } => "pack has trailing data",
             brioche_pack::ExtractPackError::InvalidPack(_) => "failed to parse pack: bincode error",
         },
         PackedError::PackResourceDirError(error) => match error {
@@ -1,7 +1,6 @@
 use std::{path::PathBuf, process::ExitCode};
 
-use anyhow::Context as _;
-use brioche_core::{fs_utils, reporter::ConsoleReporterKind};
+use brioche_core::reporter::ConsoleReporterKind;
 use clap::Parser;
 use human_repr::HumanDuration;
 use tracing::Instrument;
@@ -11,9 +10,15 @@ pub struct BuildArgs {
     #[command(flatten)]
     project: super::ProjectArgs,
 
-    /// Which TypeScript export to build
-    #[arg(short, long, default_value = "default")]
-    export: String,
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`). The path can also be the name of a
+    /// workspace member, or a git repository like `github:owner/repo`,
+    /// `github:owner/repo/<rev>`, or `git+https://...?rev=<rev>`
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
 
     /// The path to write the output to. The build result will not be
     /// saved if not specified
@@ -32,29 +37,85 @@ pub struct BuildArgs {
     #[arg(long)]
     merge: bool,
 
+    #[command(flatten)]
+    output_permissions: super::OutputPermissionsArgs,
+
+    /// How to write files to the output: `copy`, or `hardlink` to link
+    /// files to the read-only blob store (much faster for large outputs)
+    #[arg(long, default_value_t)]
+    link_strategy: brioche_core::output::LinkStrategy,
+
+    #[command(flatten)]
+    concurrency: super::ConcurrencyArgs,
+
     /// Keep temporary build files. Useful for debugging build failures
     #[arg(long)]
     keep_temps: bool,
 
+    /// Trace the files each process accesses, and report inputs that
+    /// were never used or files missing from the sandbox
+    #[arg(long)]
+    trace_inputs: bool,
+
     /// Sync / cache baked recipes to the registry during the build
     #[arg(long)]
     sync: bool,
+
+    /// Run processes again even if they failed recently, when failures
+    /// are cached with `failed_process_cache_ttl` in the config file
+    #[arg(long)]
+    retry_failed: bool,
+
+    /// When a download doesn't match its expected hash, offer to update
+    /// the hash in the project's source files
+    #[arg(long)]
+    update_hashes: bool,
+
+    /// Print the recipe that would be baked instead of baking it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Build in this process, even if `brioche daemon` is running
+    #[arg(long)]
+    no_daemon: bool,
+
+    /// Write a JSON report of the licenses used by the project's
+    /// dependencies and within the output to this path
+    #[arg(long)]
+    license_report: Option<PathBuf>,
 }
 
 pub async fn build(args: BuildArgs) -> anyhow::Result<ExitCode> {
+    if let Some(exit_code) = try_build_with_daemon(&args).await? {
+        return Ok(exit_code);
+    }
+
     let (reporter, mut guard) =
         brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
     reporter.set_is_evaluating(true);
 
-    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+    let brioche = args
+        .concurrency
+        .apply(brioche_core::BriocheBuilder::new(reporter.clone()))
         .keep_temps(args.keep_temps)
+        .trace_process_inputs(args.trace_inputs)
         .sync(args.sync)
+        .retry_failed(args.retry_failed)
+        .update_hashes(args.update_hashes)
+        .script_read_access(args.project.script_read_access())
         .build()
         .await?;
     let projects = brioche_core::project::Projects::default();
 
     let build_future = async {
-        let project_hash = super::load_project(&brioche, &projects, &args.project).await?;
+        let (project_hash, export) = super::load_project_target(
+            &brioche,
+            &projects,
+            &args.project,
+            args.target.as_deref(),
+            args.export.as_deref(),
+        )
+        .await?;
 
         let num_lockfiles_updated = projects.commit_dirty_lockfiles().await?;
         if num_lockfiles_updated > 0 {
@@ -84,21 +145,27 @@ pub async fn build(args: BuildArgs) -> anyhow::Result<ExitCode> {
             }
         }
 
-        let recipe = brioche_core::script::evaluate::evaluate(
-            &brioche,
-            &projects,
-            project_hash,
-            &args.export,
-        )
-        .await?;
+        let recipe =
+            brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+                .await?;
 
         reporter.set_is_evaluating(false);
-        let artifact = brioche_core::bake::bake(
+
+        if args.dry_run {
+            let rendered =
+                brioche_core::recipe::pretty::render_recipe(&brioche, &recipe.value).await?;
+            guard.shutdown_console().await;
+
+            print!("{rendered}");
+            return anyhow::Ok(ExitCode::SUCCESS);
+        }
+
+        let artifact = brioche_core::history::bake_and_record(
             &brioche,
             recipe,
             &brioche_core::bake::BakeScope::Project {
                 project_hash,
-                export: args.export.to_string(),
+                export: export.clone(),
             },
         )
         .await?;
@@ -117,26 +184,69 @@ pub async fn build(args: BuildArgs) -> anyhow::Result<ExitCode> {
         let artifact_hash = artifact.value.hash();
         println!("Result: {artifact_hash}");
 
-        if let Some(output) = &args.output {
-            if args.replace {
-                fs_utils::try_remove(output)
-                    .await
-                    .with_context(|| format!("Failed to remove path {}", output.display()))?;
-            }
-
-            println!("Writing output");
-            brioche_core::output::create_output(
+        // Check the licenses in the output before writing it, if the
+        // project has a license policy
+        let license_policy =
+            brioche_core::licenses::project_license_policy(&projects, project_hash).await?;
+        if license_policy.is_some() || args.license_report.is_some() {
+            let report = brioche_core::licenses::scan_licenses(
                 &brioche,
+                &projects,
+                project_hash,
                 &artifact.value,
-                brioche_core::output::OutputOptions {
-                    output_path: output,
-                    merge: args.merge,
-                    resource_dir: None,
-                    mtime: Some(std::time::SystemTime::now()),
-                    link_locals: false,
-                },
+                license_policy.as_ref(),
             )
             .await?;
+
+            if let Some(license_report) = &args.license_report {
+                let report_json = serde_json::to_string_pretty(&report)?;
+                tokio::fs::write(license_report, report_json).await?;
+                println!("Wrote license report to {}", license_report.display());
+            }
+
+            for entry in report.warnings() {
+                match entry.status {
+                    brioche_core::licenses::LicenseStatus::Disallowed => {
+                        println!("Warning: license not allowed by policy: {entry}");
+                    }
+                    _ => println!("Warning: unknown license: {entry}"),
+                }
+            }
+            report.ensure_allowed()?;
+        }
+
+        if let Some(output) = &args.output {
+            println!("Writing output");
+            let output_options = brioche_core::output::OutputOptions {
+                output_path: output,
+                merge: false,
+                resource_dir: None,
+                mtime: Some(std::time::SystemTime::now()),
+                link_locals: false,
+                link_strategy: args.link_strategy,
+                permissions: args.output_permissions.output_permissions(),
+            };
+            if args.merge && !args.replace {
+                brioche_core::output::create_output(
+                    &brioche,
+                    &artifact.value,
+                    brioche_core::output::OutputOptions {
+                        merge: true,
+                        ..output_options
+                    },
+                )
+                .await?;
+            } else {
+                // Write to a temporary path first, so a failure doesn't
+                // leave behind a half-written output
+                brioche_core::output::create_output_atomic(
+                    &brioche,
+                    &artifact.value,
+                    output_options,
+                    args.replace,
+                )
+                .await?;
+            }
             println!("Wrote output to {}", output.display());
         }
 
@@ -167,7 +277,7 @@ pub async fn build(args: BuildArgs) -> anyhow::Result<ExitCode> {
             println!("Syncing project...");
 
             let sync_start = std::time::Instant::now();
-            brioche_core::sync::sync_project(&brioche, project_hash, &args.export).await?;
+            brioche_core::sync::sync_project(&brioche, project_hash, &export).await?;
             let sync_duration = sync_start.elapsed().human_duration();
             println!("Finished sync in {sync_duration}");
         }
@@ -175,9 +285,163 @@ pub async fn build(args: BuildArgs) -> anyhow::Result<ExitCode> {
         anyhow::Ok(ExitCode::SUCCESS)
     };
 
-    let exit_code = build_future
-        .instrument(tracing::info_span!("build"))
-        .await?;
+    let result = build_future.instrument(tracing::info_span!("build")).await;
 
+    if let Some(hash_updates) = &brioche.hash_updates {
+        let updates = hash_updates.take();
+        if !updates.is_empty() {
+            guard.shutdown_console().await;
+            update_hashes(&brioche, &projects, &updates).await?;
+        }
+    }
+
+    // Wait for new blobs to reach the writable blob stores, even if the
+    // build failed
+    brioche_core::blob_store::flush_uploads(&brioche).await?;
+
+    let exit_code = result?;
     Ok(exit_code)
 }
+
+/// Send the build to `brioche daemon` if it's running. Returns `None` if
+/// there's no daemon, or if the build uses options the daemon doesn't
+/// support, so the build should run in this process instead.
+#[cfg(unix)]
+async fn try_build_with_daemon(args: &BuildArgs) -> anyhow::Result<Option<ExitCode>> {
+    let needs_local_build = args.no_daemon
+        || args.check
+        || args.keep_temps
+        || args.trace_inputs
+        || args.sync
+        || args.retry_failed
+        || args.update_hashes
+        || args.dry_run
+        || args.concurrency.is_set()
+        || args.license_report.is_some()
+        || args.project.registry.is_some()
+        || args.project.allow_read_all
+        || !args.project.allow_read.is_empty();
+    if needs_local_build {
+        return Ok(None);
+    }
+
+    let (target_path, export) =
+        super::split_target(args.target.as_deref(), args.export.as_deref())?;
+    let current_dir = std::env::current_dir()?;
+    let project_path = match target_path {
+        Some(target_path) => {
+            anyhow::ensure!(
+                args.project.project.is_none(),
+                "cannot specify both a target path and --project or --registry"
+            );
+
+            if brioche_core::project::remote::RemoteProjectSource::parse(target_path)?.is_some() {
+                return Ok(None);
+            }
+            brioche_core::project::resolve_project_path(&current_dir, target_path).await?
+        }
+        None => current_dir.join(args.project.project.as_deref().unwrap_or(".".as_ref())),
+    };
+
+    let socket_path = brioche_core::daemon::socket_path()?;
+    let Some(mut client) = brioche_core::daemon::DaemonClient::connect(&socket_path).await else {
+        return Ok(None);
+    };
+
+    // The daemon reads the project's declared environment variables from
+    // this process's environment, not its own. Variables that aren't valid
+    // UTF-8 can't be read by projects anyway
+    let env = std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+
+    let output = args.output.as_ref().map(|output| current_dir.join(output));
+    let request =
+        brioche_core::daemon::DaemonRequest::Build(brioche_core::daemon::DaemonBuildRequest {
+            project_path,
+            export,
+            output: output.clone(),
+            replace: args.replace,
+            merge: args.merge,
+            output_permissions: args.output_permissions.output_permissions(),
+            link_strategy: args.link_strategy,
+            env: Some(env),
+        });
+    let response = client.request(&request).await?;
+
+    match response {
+        brioche_core::daemon::DaemonResponse::BuildFinished {
+            artifact_hash,
+            num_jobs,
+            elapsed,
+        } => {
+            let jobs_message = match num_jobs {
+                0 => "(no new jobs)".to_string(),
+                1 => "1 job".to_string(),
+                n => format!("{n} jobs"),
+            };
+            let elapsed = elapsed.human_duration();
+            println!("Build finished by daemon, completed {jobs_message} in {elapsed}");
+            println!("Result: {artifact_hash}");
+
+            if let Some(output) = &output {
+                println!("Wrote output to {}", output.display());
+            }
+
+            Ok(Some(ExitCode::SUCCESS))
+        }
+        brioche_core::daemon::DaemonResponse::Failed { message, code } => match code {
+            Some(code) => Err(brioche_core::error_codes::CodedError::new(code, message).into()),
+            None => Err(anyhow::anyhow!(message)),
+        },
+    }
+}
+
+#[cfg(not(unix))]
+async fn try_build_with_daemon(_args: &BuildArgs) -> anyhow::Result<Option<ExitCode>> {
+    Ok(None)
+}
+
+async fn update_hashes(
+    brioche: &brioche_core::Brioche,
+    projects: &brioche_core::project::Projects,
+    updates: &[brioche_core::update_hashes::HashUpdate],
+) -> anyhow::Result<()> {
+    println!("Downloads with mismatched hashes:");
+    for update in updates {
+        println!("  {}", update.url);
+        println!("    expected: {}", update.expected_hash);
+        println!("    actual:   {}", update.actual_hash);
+    }
+
+    let edits = brioche_core::update_hashes::find_source_edits(brioche, projects, updates).await?;
+
+    let num_missing = updates
+        .iter()
+        .filter(|update| !edits.iter().any(|edit| edit.updates.contains(update)))
+        .count();
+    if num_missing > 0 {
+        println!("{num_missing} hash(es) could not be found in the project's source files");
+    }
+
+    if edits.is_empty() {
+        return Ok(());
+    }
+
+    println!("Files to update:");
+    for edit in &edits {
+        println!("  {}", edit.path.display());
+    }
+
+    let should_update =
+        super::confirm("Update hashes?", "Re-run interactively to update hashes").await?;
+    if !should_update {
+        println!("Hashes not updated");
+        return Ok(());
+    }
+
+    brioche_core::update_hashes::apply_source_edits(&edits).await?;
+    println!("Updated hashes in {} file(s)", edits.len());
+
+    Ok(())
+}
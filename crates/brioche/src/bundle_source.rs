@@ -0,0 +1,91 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct BundleSourceArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to bundle, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to bundle [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// The path to write the `.tar.gz` bundle to [default: a file in the
+    /// current directory named after the project]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub async fn bundle_source(args: BundleSourceArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let project = projects.project(project_hash)?;
+
+    // Build the export first, so every download and registry dependency
+    // it needs is known and available locally
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::history::bake_and_record(
+        &brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export: export.clone(),
+        },
+    )
+    .await?;
+
+    let prefix = match (&project.definition.name, &project.definition.version) {
+        (Some(name), Some(version)) => format!("{name}-{version}"),
+        (Some(name), None) => name.clone(),
+        (None, _) => "source".to_string(),
+    };
+    let output = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("{prefix}.tar.gz")));
+    let manifest = brioche_core::bundle::write_source_bundle(
+        &brioche,
+        &projects,
+        project_hash,
+        &export,
+        &prefix,
+        &output,
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    println!("Result: {}", artifact.value.hash());
+    println!(
+        "Vendored {} project(s) and {} download(s)",
+        manifest.vendored_projects.len(),
+        manifest.vendored_bakes.len()
+    );
+    println!("Wrote source bundle to {}", output.display());
+
+    Ok(ExitCode::SUCCESS)
+}
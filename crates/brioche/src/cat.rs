@@ -0,0 +1,89 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use tokio::io::AsyncWriteExt as _;
+
+#[derive(Debug, Parser)]
+pub struct CatArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: String,
+
+    /// The path within the build output to print (e.g. `bin/foo`).
+    /// Directories are listed, and symlink targets are printed
+    path: String,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+}
+
+pub async fn cat(args: CatArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        Some(&args.target),
+        args.export.as_deref(),
+    )
+    .await?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::bake::bake_subpath(
+        &brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export,
+        },
+        args.path.as_bytes(),
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    let Some(artifact) = artifact else {
+        eprintln!("{}: not found in build output", args.path);
+        return Ok(ExitCode::FAILURE);
+    };
+
+    let mut stdout = tokio::io::stdout();
+    match artifact {
+        brioche_core::recipe::Artifact::File(file) => {
+            let blob_path =
+                brioche_core::blob::local_blob_path(&brioche, file.content_blob).await?;
+            let mut blob = tokio::fs::File::open(&blob_path).await?;
+            tokio::io::copy(&mut blob, &mut stdout).await?;
+        }
+        brioche_core::recipe::Artifact::Symlink { target } => {
+            stdout.write_all(&target).await?;
+            stdout.write_all(b"\n").await?;
+        }
+        brioche_core::recipe::Artifact::Directory(directory) => {
+            for name in directory.entry_hashes().keys() {
+                stdout.write_all(name).await?;
+                stdout.write_all(b"\n").await?;
+            }
+        }
+    }
+    stdout.flush().await?;
+
+    Ok(ExitCode::SUCCESS)
+}
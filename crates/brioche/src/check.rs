@@ -8,13 +8,20 @@ use tracing::Instrument;
 pub struct CheckArgs {
     #[command(flatten)]
     project: super::ProjectArgs,
+
+    /// Also report exports and helper functions that are never used
+    #[arg(long)]
+    unused: bool,
 }
 
 pub async fn check(args: CheckArgs) -> anyhow::Result<ExitCode> {
     let (reporter, mut guard) =
         brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
 
-    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
     let projects = brioche_core::project::Projects::default();
 
     let check_future = async {
@@ -27,8 +34,25 @@ pub async fn check(args: CheckArgs) -> anyhow::Result<ExitCode> {
 
         let checked = brioche_core::script::check::check(&brioche, &projects, project_hash).await?;
 
+        let unused = if args.unused {
+            brioche_core::script::unused::find_unused(&brioche.vfs, &projects, project_hash).await?
+        } else {
+            vec![]
+        };
+
         guard.shutdown_console().await;
 
+        for item in &unused {
+            let line = match brioche.vfs.load(&item.module_path).await {
+                Ok((_, contents)) => {
+                    let before = &contents[..item.start.min(contents.len())];
+                    before.iter().filter(|&&byte| byte == b'\n').count() + 1
+                }
+                Err(_) => 0,
+            };
+            println!("warning: {}:{line}: {item}", item.module_path.display());
+        }
+
         let result = checked.ensure_ok(brioche_core::script::check::DiagnosticLevel::Message);
 
         match result {
@@ -0,0 +1,127 @@
+use std::process::ExitCode;
+
+use anyhow::Context as _;
+use brioche_core::{
+    checksums::{ChecksumEntry, ChecksumSignature},
+    reporter::ConsoleReporterKind,
+    HashAlgorithm,
+};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ChecksumsArgs {
+    /// The URL or path of the checksum file (e.g. `SHA256SUMS`)
+    checksums: String,
+
+    /// The names of the files to print pinned downloads for [default: all
+    /// files in the checksum file]
+    #[arg(short, long = "file")]
+    files: Vec<String>,
+
+    /// The URL the files are downloaded from [default: the URL of the
+    /// checksum file, without the file name]
+    #[arg(long)]
+    base_url: Option<url::Url>,
+
+    /// The hash algorithm used by the checksum file [default: inferred
+    /// from the length of each hash]
+    #[arg(long)]
+    algorithm: Option<HashAlgorithm>,
+
+    /// The URL or path of a signify signature for the checksum file
+    #[arg(long, requires = "public_key")]
+    signature: Option<String>,
+
+    /// The signify public key to verify the signature with, either
+    /// base64-encoded or as a path to a public key file
+    #[arg(long, requires = "signature")]
+    public_key: Option<String>,
+}
+
+pub async fn checksums(args: ChecksumsArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .build()
+        .await?;
+
+    let contents = brioche_core::checksums::read_url_or_path(&brioche, &args.checksums).await?;
+    let signature = match (&args.signature, &args.public_key) {
+        (Some(signature), Some(public_key)) => {
+            let signature = brioche_core::checksums::read_url_or_path(&brioche, signature).await?;
+            let public_key = if std::path::Path::new(public_key).is_file() {
+                tokio::fs::read_to_string(public_key).await?
+            } else {
+                public_key.clone()
+            };
+            Some((signature, public_key))
+        }
+        _ => None,
+    };
+    let checksum_file = brioche_core::checksums::read_checksum_file(
+        &contents,
+        args.algorithm,
+        signature
+            .as_ref()
+            .map(|(signature, public_key)| (&**signature, &**public_key)),
+    )?;
+
+    guard.shutdown_console().await;
+
+    let base_url = match args.base_url {
+        Some(base_url) => Some(base_url),
+        None => url::Url::parse(&args.checksums)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https")),
+    };
+
+    let entries: Vec<&ChecksumEntry> = if args.files.is_empty() {
+        checksum_file.entries.iter().collect()
+    } else {
+        args.files
+            .iter()
+            .map(|file_name| {
+                checksum_file
+                    .entries
+                    .iter()
+                    .find(|entry| entry.file_name == *file_name)
+                    .with_context(|| format!("{file_name:?} not found in checksum file"))
+            })
+            .collect::<anyhow::Result<_>>()?
+    };
+
+    match checksum_file.signature {
+        ChecksumSignature::Unsigned => {}
+        ChecksumSignature::UnverifiedPgp => {
+            eprintln!("Warning: the checksum file's PGP signature was not verified");
+        }
+        ChecksumSignature::Verified => {
+            eprintln!("Verified checksum file signature");
+        }
+    }
+
+    for entry in entries {
+        if entry.hash.algorithm().is_weak() {
+            eprintln!(
+                "Warning: {} uses the weak hash algorithm {}",
+                entry.file_name,
+                entry.hash.algorithm()
+            );
+        }
+
+        let url = match &base_url {
+            Some(base_url) => base_url
+                .join(&entry.file_name)
+                .with_context(|| format!("invalid file name {:?}", entry.file_name))?
+                .to_string(),
+            None => entry.file_name.clone(),
+        };
+        println!(
+            "{}",
+            brioche_core::checksums::download_source(&url, &entry.hash)
+        );
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
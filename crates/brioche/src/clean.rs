@@ -0,0 +1,43 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use tracing::Instrument;
+
+#[derive(Debug, Parser)]
+pub struct CleanArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+}
+
+pub async fn clean(args: CleanArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let clean_future = async {
+        let project_hash = super::load_project(&brioche, &projects, &args.project).await?;
+
+        let results = brioche_core::clean::clean_project(&brioche, project_hash).await?;
+
+        guard.shutdown_console().await;
+
+        println!(
+            "Removed {} cached bakes and {} outputs for project {project_hash}",
+            results.num_bakes, results.num_locals,
+        );
+
+        anyhow::Ok(ExitCode::SUCCESS)
+    };
+
+    let exit_code = clean_future
+        .instrument(tracing::info_span!("clean"))
+        .await?;
+
+    Ok(exit_code)
+}
@@ -0,0 +1,35 @@
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct DaemonArgs {
+    /// Download updates for the registry dependencies of built projects
+    /// in the background while the daemon is idle, so updating them later
+    /// is fast
+    #[arg(long)]
+    prefetch_updates: bool,
+
+    /// How long the daemon must be idle before prefetching updates, in
+    /// minutes
+    #[arg(long, default_value_t = 10, requires = "prefetch_updates")]
+    prefetch_idle_minutes: u64,
+}
+
+pub async fn daemon(args: DaemonArgs) -> anyhow::Result<()> {
+    let (reporter, _guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Plain)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+    let socket_path = brioche_core::daemon::socket_path()?;
+
+    let options = brioche_core::daemon::DaemonOptions {
+        prefetch_updates_after: args
+            .prefetch_updates
+            .then(|| std::time::Duration::from_secs(args.prefetch_idle_minutes.saturating_mul(60))),
+    };
+
+    println!("Listening on {}", socket_path.display());
+    brioche_core::daemon::serve(&brioche, &socket_path, &options).await?;
+
+    Ok(())
+}
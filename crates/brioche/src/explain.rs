@@ -0,0 +1,39 @@
+use std::process::ExitCode;
+
+use brioche_core::error_codes::{ErrorCode, EXPLANATIONS};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ExplainArgs {
+    /// The error code to explain, like `BRI0001`. Lists all error codes
+    /// if omitted
+    code: Option<String>,
+}
+
+pub fn explain(args: ExplainArgs) -> anyhow::Result<ExitCode> {
+    let Some(code) = args.code else {
+        for explanation in EXPLANATIONS {
+            println!("{}: {}", explanation.code, explanation.title);
+        }
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let code: ErrorCode = code.parse()?;
+    let Some(explanation) = code.explanation() else {
+        anyhow::bail!("no explanation found for error code {code}");
+    };
+
+    println!("{}: {}", explanation.code, explanation.title);
+    println!();
+    println!("{}", explanation.description);
+
+    if !explanation.fixes.is_empty() {
+        println!();
+        println!("Common fixes:");
+        for fix in explanation.fixes {
+            println!("- {fix}");
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
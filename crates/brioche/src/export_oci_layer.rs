@@ -0,0 +1,102 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use brioche_core::{oci::LayerStrategy, reporter::ConsoleReporterKind};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ExportOciLayerArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// The directory to write the OCI image layout to
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// How to split the output into layers (`single`, `depth`, or
+    /// `package`)
+    #[arg(long, default_value_t)]
+    split: LayerStrategy,
+
+    /// The maximum number of layers in the image
+    #[arg(long, default_value_t = 16)]
+    max_layers: usize,
+
+    /// The reference name for the image (e.g. `foo:latest`)
+    #[arg(long)]
+    tag: Option<String>,
+}
+
+pub async fn export_oci_layer(args: ExportOciLayerArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::history::bake_and_record(
+        &brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export,
+        },
+    )
+    .await?;
+
+    let annotations =
+        brioche_core::annotations::get_annotations(&brioche, artifact.value.hash()).await?;
+    let entrypoint = annotations.and_then(|annotations| annotations.main_executable);
+
+    tokio::fs::create_dir_all(&args.output).await?;
+    let result = brioche_core::oci::export_oci_image(
+        &brioche,
+        &artifact.value,
+        brioche_core::oci::ExportOciOptions {
+            output_dir: &args.output,
+            strategy: args.split,
+            max_layers: args.max_layers,
+            platform: brioche_core::platform::current_platform(),
+            tag: args.tag.as_deref(),
+            entrypoint: entrypoint.as_deref(),
+        },
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    for layer in &result.layers {
+        println!("{}  {}", layer.digest, layer.name);
+    }
+    println!(
+        "Wrote image {} to {}",
+        result.manifest_digest,
+        args.output.display()
+    );
+
+    Ok(ExitCode::SUCCESS)
+}
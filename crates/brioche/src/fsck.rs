@@ -0,0 +1,95 @@
+use std::process::ExitCode;
+
+use brioche_core::{blob::CorruptBlobAction, reporter::ConsoleReporterKind};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct FsckArgs {
+    /// What to do with corrupt blobs (`report`, `quarantine`, or `remove`).
+    /// Unless only reporting, blob aliases that point to missing or
+    /// corrupt blobs are removed too
+    #[arg(long, default_value_t)]
+    action: CorruptBlobAction,
+
+    /// Print the full report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn fsck(args: FsckArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+    let report = brioche_core::blob::verify_blobs(&brioche, args.action).await?;
+
+    guard.shutdown_console().await;
+
+    // Problems are only left behind when reporting
+    let exit_code = if report.has_problems() && args.action == CorruptBlobAction::Report {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    };
+
+    if args.json {
+        let serialized = serde_json::to_string_pretty(&report)?;
+        println!("{serialized}");
+        return Ok(exit_code);
+    }
+
+    println!("Checked {} blobs", report.num_blobs);
+
+    if !report.corrupt_blobs.is_empty() {
+        println!();
+        println!("Corrupt blobs:");
+        for corrupt_blob in &report.corrupt_blobs {
+            let actual_hash = match corrupt_blob.actual_hash {
+                Some(actual_hash) => actual_hash.to_string(),
+                None => "<unreadable>".to_string(),
+            };
+            println!(
+                "  {} (contents: {actual_hash})",
+                corrupt_blob.path.display()
+            );
+            if let Some(quarantine_path) = &corrupt_blob.quarantine_path {
+                println!("    moved to {}", quarantine_path.display());
+            }
+        }
+    }
+
+    if !report.broken_aliases.is_empty() {
+        println!();
+        println!("Blob aliases pointing to missing or corrupt blobs:");
+        for alias in &report.broken_aliases {
+            println!("  {} -> {}", alias.hash, alias.blob_hash);
+        }
+    }
+
+    if report.has_problems() {
+        println!();
+        match args.action {
+            CorruptBlobAction::Report => {
+                println!("Run `brioche fsck --action quarantine` to repair the store");
+            }
+            CorruptBlobAction::Quarantine => {
+                println!(
+                    "Quarantined {} corrupt blobs and removed {} blob aliases",
+                    report.corrupt_blobs.len(),
+                    report.broken_aliases.len()
+                );
+            }
+            CorruptBlobAction::Remove => {
+                println!(
+                    "Removed {} corrupt blobs and {} blob aliases",
+                    report.corrupt_blobs.len(),
+                    report.broken_aliases.len()
+                );
+            }
+        }
+    } else {
+        println!("No problems found");
+    }
+
+    Ok(exit_code)
+}
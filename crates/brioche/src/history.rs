@@ -0,0 +1,113 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::Context as _;
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use human_repr::HumanDuration as _;
+
+#[derive(Debug, Parser)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    command: HistoryCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum HistoryCommand {
+    /// List past builds, newest first
+    List {
+        /// The maximum number of builds to list
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: u32,
+    },
+
+    /// Write the output of a past build
+    Materialize {
+        /// The recipe hash or result hash of the build
+        hash: brioche_core::recipe::RecipeHash,
+
+        /// The path to write the output to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Replace the output path if it already exists
+        #[arg(long)]
+        replace: bool,
+
+        #[command(flatten)]
+        output_permissions: super::OutputPermissionsArgs,
+    },
+}
+
+pub async fn history(args: HistoryArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+
+    match args.command {
+        HistoryCommand::List { limit } => {
+            let runs = brioche_core::history::list_bake_runs(&brioche, limit).await?;
+
+            guard.shutdown_console().await;
+
+            if runs.is_empty() {
+                println!("No builds recorded");
+            }
+
+            for run in runs {
+                let created_at = run.created_at.as_deref().unwrap_or("unknown");
+                let duration = run.duration.human_duration();
+                let cache_hits = match run.cache_hit_ratio() {
+                    Some(ratio) => format!("{:.0}% cached", ratio * 100.0),
+                    None => "nothing to bake".to_string(),
+                };
+                println!("{created_at}  {}", run.recipe_hash);
+                if let (Some(project_hash), Some(export)) = (&run.project_hash, &run.export) {
+                    println!("  project: {project_hash} (export {export})");
+                }
+                println!("  result:  {}", run.artifact_hash);
+                println!(
+                    "  took {duration}, baked {} recipes ({cache_hits})",
+                    run.num_baked
+                );
+            }
+        }
+        HistoryCommand::Materialize {
+            hash,
+            output,
+            replace,
+            output_permissions,
+        } => {
+            let artifact = brioche_core::history::find_bake_run_artifact(&brioche, hash)
+                .await?
+                .with_context(|| format!("no build found in history with hash {hash}"))?;
+
+            if replace {
+                brioche_core::fs_utils::try_remove(&output)
+                    .await
+                    .with_context(|| format!("Failed to remove path {}", output.display()))?;
+            }
+
+            brioche_core::output::create_output(
+                &brioche,
+                &artifact,
+                brioche_core::output::OutputOptions {
+                    output_path: &output,
+                    merge: false,
+                    resource_dir: None,
+                    mtime: Some(std::time::SystemTime::now()),
+                    link_locals: false,
+                    link_strategy: brioche_core::output::LinkStrategy::Copy,
+                    permissions: output_permissions.output_permissions(),
+                },
+            )
+            .await?;
+
+            guard.shutdown_console().await;
+
+            println!("Wrote output to {}", output.display());
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
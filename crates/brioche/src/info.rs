@@ -0,0 +1,122 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// Print the information as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Also print the full contents of the build output
+    #[arg(long)]
+    tree: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+    artifact_hash: brioche_core::recipe::RecipeHash,
+    kind: brioche_core::recipe::ArtifactDiscriminants,
+    annotations: brioche_core::annotations::Annotations,
+}
+
+pub async fn info(args: InfoArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::bake::bake(
+        &brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export,
+        },
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    let artifact_hash = artifact.value.hash();
+    let annotations = brioche_core::annotations::get_annotations(&brioche, artifact_hash)
+        .await?
+        .unwrap_or_default();
+    let info = Info {
+        artifact_hash,
+        kind: (&artifact.value).into(),
+        annotations,
+    };
+
+    if args.json {
+        let serialized = serde_json::to_string_pretty(&info)?;
+        println!("{serialized}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("Artifact: {}", info.artifact_hash);
+    println!("Kind: {:?}", info.kind);
+
+    let Info { annotations, .. } = info;
+    if let Some(name) = &annotations.name {
+        println!("Name: {name}");
+    }
+    if let Some(version) = &annotations.version {
+        println!("Version: {version}");
+    }
+    if let Some(license) = &annotations.license {
+        println!("License: {license}");
+    }
+    if let Some(main_executable) = &annotations.main_executable {
+        println!("Main executable: {main_executable}");
+    }
+    if let Some(service) = &annotations.service {
+        println!(
+            "Service: {} (restart: {})",
+            service.command, service.restart
+        );
+    }
+    for (key, value) in &annotations.extra {
+        println!("{key}: {value}");
+    }
+
+    if args.tree {
+        let rendered =
+            brioche_core::recipe::pretty::render_artifact(&brioche, &artifact.value).await?;
+        println!();
+        print!("{rendered}");
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
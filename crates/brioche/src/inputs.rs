@@ -0,0 +1,87 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct InputsArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export that returns a process, as
+    /// `[PATH][#EXPORT]` (e.g. `./packages/foo#build`)
+    target: Option<String>,
+
+    /// Which TypeScript export to use [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// Report on a previously baked recipe (e.g. from `brioche history
+    /// list`) instead of evaluating a project
+    #[arg(long, conflicts_with_all = ["target", "export"])]
+    recipe: Option<brioche_core::recipe::RecipeHash>,
+}
+
+pub async fn inputs(args: InputsArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (recipe, scope) = match args.recipe {
+        Some(recipe_hash) => {
+            let recipe = brioche_core::recipe::get_recipe(&brioche, recipe_hash).await?;
+            let recipe = brioche_core::recipe::WithMeta::without_meta(recipe);
+            (recipe, brioche_core::bake::BakeScope::Anonymous)
+        }
+        None => {
+            let (project_hash, export) = super::load_project_target(
+                &brioche,
+                &projects,
+                &args.project,
+                args.target.as_deref(),
+                args.export.as_deref(),
+            )
+            .await?;
+            let recipe = brioche_core::script::evaluate::evaluate(
+                &brioche,
+                &projects,
+                project_hash,
+                &export,
+            )
+            .await?;
+            let scope = brioche_core::bake::BakeScope::Project {
+                project_hash,
+                export,
+            };
+            (recipe, scope)
+        }
+    };
+
+    let report =
+        brioche_core::bake::process_inputs::process_inputs(&brioche, recipe, &scope).await?;
+
+    guard.shutdown_console().await;
+
+    println!("Cache key: {}", report.cache_key);
+    println!();
+    println!("Inputs:");
+    for input in &report.inputs {
+        let value = match &input.value {
+            brioche_core::bake::process_inputs::ProcessInputValue::Literal(value) => {
+                format!("{value:?}")
+            }
+            brioche_core::bake::process_inputs::ProcessInputValue::Artifact { kind, hash } => {
+                format!("{} {hash}", format!("{kind:?}").to_lowercase())
+            }
+            brioche_core::bake::process_inputs::ProcessInputValue::Setting(value) => value.clone(),
+        };
+        println!("  {}: {value}", input.location);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
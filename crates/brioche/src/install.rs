@@ -11,13 +11,28 @@ pub struct InstallArgs {
     #[command(flatten)]
     project: super::ProjectArgs,
 
-    /// Which TypeScript export to build
-    #[arg(short, long, default_value = "default")]
-    export: String,
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`). The path can also be the name of a
+    /// workspace member, or a git repository like `github:owner/repo`,
+    /// `github:owner/repo/<rev>`, or `git+https://...?rev=<rev>`
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
 
     /// Check the project before buiilding
     #[arg(long)]
     check: bool,
+
+    /// What to do when an executable conflicts with one that's already
+    /// installed: `error`, `skip`, or `replace` [default: from config, or
+    /// `error`]
+    #[arg(long)]
+    on_conflict: Option<brioche_core::profile::ConflictPolicy>,
+
+    #[command(flatten)]
+    concurrency: super::ConcurrencyArgs,
 }
 
 pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
@@ -25,13 +40,23 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
         brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
     reporter.set_is_evaluating(true);
 
-    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+    let brioche = args
+        .concurrency
+        .apply(brioche_core::BriocheBuilder::new(reporter.clone()))
+        .script_read_access(args.project.script_read_access())
         .build()
         .await?;
     let projects = brioche_core::project::Projects::default();
 
     let install_future = async {
-        let project_hash = super::load_project(&brioche, &projects, &args.project).await?;
+        let (project_hash, export) = super::load_project_target(
+            &brioche,
+            &projects,
+            &args.project,
+            args.target.as_deref(),
+            args.export.as_deref(),
+        )
+        .await?;
 
         let num_lockfiles_updated = projects.commit_dirty_lockfiles().await?;
         if num_lockfiles_updated > 0 {
@@ -61,21 +86,17 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
             }
         }
 
-        let recipe = brioche_core::script::evaluate::evaluate(
-            &brioche,
-            &projects,
-            project_hash,
-            &args.export,
-        )
-        .await?;
+        let recipe =
+            brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+                .await?;
 
         reporter.set_is_evaluating(false);
-        let artifact = brioche_core::bake::bake(
+        let artifact = brioche_core::history::bake_and_record(
             &brioche,
             recipe,
             &brioche_core::bake::BakeScope::Project {
                 project_hash,
-                export: args.export.to_string(),
+                export: export.clone(),
             },
         )
         .await?;
@@ -91,6 +112,8 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
         };
         eprintln!("Build finished, completed {jobs_message} in {elapsed}");
 
+        let artifact_hash = artifact.value.hash();
+
         // Ensure the artifact is a directory
         let mut directory = match artifact.value {
             brioche_core::recipe::Artifact::File(_) => {
@@ -102,9 +125,41 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
             brioche_core::recipe::Artifact::Directory(dir) => dir,
         };
 
+        let annotations =
+            brioche_core::annotations::get_annotations(&brioche, artifact_hash).await?;
+        let annotations = annotations.unwrap_or_default();
+
         // Remove the top-level `brioche-run` file if it exists
         directory.insert(&brioche, b"brioche-run", None).await?;
 
+        // Link the annotated main executable into `bin/`, unless it's
+        // already there, so it ends up on the `$PATH`
+        if let Some(main_executable) = &annotations.main_executable {
+            let main_executable = main_executable.trim_start_matches('/');
+            let (parent, file_name) = match main_executable.rsplit_once('/') {
+                Some((parent, file_name)) => (Some(parent), file_name),
+                None => (None, main_executable),
+            };
+            if parent != Some("bin") {
+                let link_path = format!("bin/{file_name}");
+                let existing = directory.get(&brioche, link_path.as_bytes()).await?;
+                if existing.is_none() {
+                    let target = format!("../{main_executable}");
+                    directory
+                        .insert(
+                            &brioche,
+                            link_path.as_bytes(),
+                            Some(brioche_core::recipe::WithMeta::without_meta(
+                                brioche_core::recipe::Artifact::Symlink {
+                                    target: target.into(),
+                                },
+                            )),
+                        )
+                        .await?;
+                }
+            }
+        }
+
         // Create the installation directory if it doesn't exist
         let install_dir = brioche.home.join("installed");
         tokio::fs::create_dir_all(&install_dir)
@@ -117,19 +172,27 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
             })?;
 
         println!("Writing output");
-        brioche_core::output::create_output(
+        let conflict_policy = args.on_conflict.unwrap_or(brioche.install_conflict_policy);
+        let result = brioche_core::profile::install_to_profile(
             &brioche,
-            &brioche_core::recipe::Artifact::Directory(directory),
-            brioche_core::output::OutputOptions {
-                output_path: &install_dir,
-                merge: true,
-                resource_dir: None,
-                mtime: Some(std::time::SystemTime::now()),
-                link_locals: false,
+            directory,
+            brioche_core::profile::InstallOptions {
+                profile_dir: &install_dir,
+                conflict_policy,
             },
         )
         .await?;
-        println!("Wrote output to {}", install_dir.display());
+        println!("Wrote output to {}", result.package_dir.display());
+
+        for name in &result.skipped {
+            println!("Skipped {name}: conflicts with an existing executable");
+        }
+
+        match (&annotations.name, &annotations.version) {
+            (Some(name), Some(version)) => println!("Installed {name} {version}"),
+            (Some(name), None) => println!("Installed {name}"),
+            _ => {}
+        }
 
         let install_bin_dir = install_dir.join("bin");
         let install_bin_dir_exists = tokio::fs::try_exists(&install_bin_dir).await?;
@@ -153,5 +216,7 @@ pub async fn install(args: InstallArgs) -> anyhow::Result<ExitCode> {
         .instrument(tracing::info_span!("run_install"))
         .await?;
 
+    brioche_core::blob_store::flush_uploads(&brioche).await?;
+
     Ok(exit_code)
 }
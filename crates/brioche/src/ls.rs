@@ -0,0 +1,119 @@
+use std::process::ExitCode;
+
+use brioche_core::{recipe::Artifact, reporter::ConsoleReporterKind};
+use clap::Parser;
+use futures::TryStreamExt as _;
+use tokio::io::AsyncWriteExt as _;
+
+#[derive(Debug, Parser)]
+pub struct LsArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// The directory within the build output to list (e.g. `share/doc`)
+    #[arg(long)]
+    path: Option<String>,
+
+    /// Only list entries whose path matches one of these glob patterns
+    /// (e.g. `bin/*` or `**/*.so`)
+    #[arg(long)]
+    glob: Vec<String>,
+}
+
+pub async fn ls(args: LsArgs) -> anyhow::Result<ExitCode> {
+    let glob_set = {
+        let mut glob_set = globset::GlobSetBuilder::new();
+        for pattern in &args.glob {
+            let glob = globset::GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()?;
+            glob_set.add(glob);
+        }
+        glob_set.build()?
+    };
+
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+
+    reporter.set_is_evaluating(false);
+    let scope = brioche_core::bake::BakeScope::Project {
+        project_hash,
+        export,
+    };
+    let artifact = match &args.path {
+        Some(path) => {
+            brioche_core::bake::bake_subpath(&brioche, recipe, &scope, path.as_bytes()).await?
+        }
+        None => Some(
+            brioche_core::bake::bake(&brioche, recipe, &scope)
+                .await?
+                .value,
+        ),
+    };
+
+    guard.shutdown_console().await;
+
+    let path = args.path.as_deref().unwrap_or(".");
+    let directory = match artifact {
+        Some(Artifact::Directory(directory)) => directory,
+        Some(_) => {
+            eprintln!("{path}: not a directory");
+            return Ok(ExitCode::FAILURE);
+        }
+        None => {
+            eprintln!("{path}: not found in build output");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    let mut stdout = tokio::io::stdout();
+    let mut entries = std::pin::pin!(directory.walk(&brioche));
+    while let Some((path, entry)) = entries.try_next().await? {
+        if !args.glob.is_empty() && !glob_set.is_match(path.to_string()) {
+            continue;
+        }
+
+        stdout.write_all(path.as_bytes()).await?;
+        match entry.value {
+            Artifact::Directory(_) => {
+                stdout.write_all(b"/").await?;
+            }
+            Artifact::Symlink { target } => {
+                stdout.write_all(b" -> ").await?;
+                stdout.write_all(&target).await?;
+            }
+            Artifact::File(_) => {}
+        }
+        stdout.write_all(b"\n").await?;
+    }
+    stdout.flush().await?;
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -1,17 +1,46 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitCode, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::{IsTerminal as _, Write as _},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
 
 use brioche_core::reporter::ConsoleReporterKind;
 use clap::Parser;
 
 mod build;
+mod bundle_source;
+mod cat;
 mod check;
+mod checksums;
+mod clean;
+#[cfg(unix)]
+mod daemon;
+mod explain;
+mod export_oci_layer;
 mod format;
+mod fsck;
+mod history;
+mod info;
+mod inputs;
 mod install;
+mod ls;
 mod lsp;
+mod outdated;
+mod package;
+mod pin;
 mod publish;
+mod registry;
+mod replay;
 mod run;
 mod run_sandbox;
 mod self_update;
+mod snapshot;
+mod store;
+mod store_stats;
+mod test;
+mod why;
 
 #[derive(Debug, Parser)]
 #[command(version)]
@@ -19,22 +48,106 @@ enum Args {
     /// Build a project
     Build(build::BuildArgs),
 
-    /// Build a project, then run the result
+    /// Build a project, then run the result. Results annotated as a
+    /// service are supervised and restarted instead
     Run(run::RunArgs),
 
     /// Build a project, then install it globally
     Install(install::InstallArgs),
 
+    /// Print a file from a project's build output. Only the needed part of
+    /// the output is fetched from the registry
+    Cat(cat::CatArgs),
+
+    /// Recursively list the contents of a project's build output
+    Ls(ls::LsArgs),
+
+    /// Show the metadata annotations attached to a project's build output
+    Info(info::InfoArgs),
+
     /// Check a project for type errors
     Check(check::CheckArgs),
 
+    /// Run a project's tests, which are exports whose names start with
+    /// `test`. Downloads and processes are mocked instead of run
+    Test(test::TestArgs),
+
+    /// Compare a project's recipe against its checked-in snapshot, without
+    /// building it
+    Snapshot(snapshot::SnapshotArgs),
+
+    /// Report duplicated and leftover files in the Brioche store, and how
+    /// much space could be reclaimed
+    StoreStats(store_stats::StoreStatsArgs),
+
+    /// Check the blobs in the Brioche store for corruption, and quarantine
+    /// or remove corrupt blobs
+    Fsck(fsck::FsckArgs),
+
+    /// Manage the Brioche store, such as moving it to a new location
+    Store(store::StoreArgs),
+
+    /// Pin artifacts or blobs by hash, so they're never removed from the
+    /// Brioche store. Lists every pin if no hashes are given
+    Pin(pin::PinArgs),
+
+    /// Remove pins added with `brioche pin`
+    Unpin(pin::UnpinArgs),
+
+    /// Run a background daemon that keeps caches warm between builds.
+    /// `brioche build` sends builds to the daemon when it's running
+    #[cfg(unix)]
+    Daemon(daemon::DaemonArgs),
+
+    /// Remove cached build results and outputs for a project
+    Clean(clean::CleanArgs),
+
+    /// Build a project, then export the result as an OCI container image,
+    /// split into layers
+    ExportOciLayer(export_oci_layer::ExportOciLayerArgs),
+
+    /// Build a project, then write the result as a `.zip`, `.deb`, or
+    /// `.rpm` package
+    Package(package::PackageArgs),
+
+    /// Build a project, then write an archive of its source, lockfile, and
+    /// vendored dependencies that can be built again without network access
+    BundleSource(bundle_source::BundleSourceArgs),
+
+    /// Print pinned downloads for the files listed in a checksum file,
+    /// like `SHA256SUMS`
+    Checksums(checksums::ChecksumsArgs),
+
     /// Format the Brioche files in a project
     #[command(name = "fmt")]
     Format(format::FormatArgs),
 
+    /// List past builds, or write the output of a past build
+    History(history::HistoryArgs),
+
+    /// List the inputs that make up a process's cache key
+    Inputs(inputs::InputsArgs),
+
+    /// Show what changed between two builds' recipes, to explain why a
+    /// build wasn't cached
+    Why(why::WhyArgs),
+
     /// Publish a project to a registry
     Publish(publish::PublishArgs),
 
+    /// Manage projects published to a registry, such as pruning old tags
+    Registry(registry::RegistryArgs),
+
+    /// Explain an error code, like `BRI0001`, or list all error codes
+    Explain(explain::ExplainArgs),
+
+    /// Run a previously baked process again in the same sandbox, and
+    /// compare the output
+    Replay(replay::ReplayArgs),
+
+    /// List locked dependencies with newer versions in the registry
+    Outdated(outdated::OutdatedArgs),
+
     /// Start the Language Server Protocol server
     Lsp(lsp::LspArgs),
 
@@ -54,9 +167,24 @@ enum Args {
     RunSandbox(run_sandbox::RunSandboxArgs),
 }
 
-fn main() -> anyhow::Result<ExitCode> {
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    match run(args) {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            if let Some(code) = brioche_core::error_codes::error_code(&error) {
+                eprintln!();
+                eprintln!("For more information about this error, run `brioche explain {code}`");
+            }
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> anyhow::Result<ExitCode> {
     match args {
         Args::Build(args) => {
             let rt = tokio::runtime::Builder::new_multi_thread()
@@ -94,6 +222,25 @@ fn main() -> anyhow::Result<ExitCode> {
 
             Ok(exit_code)
         }
+        Args::Clean(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(clean::clean(args))?;
+
+            Ok(exit_code)
+        }
+        #[cfg(unix)]
+        Args::Daemon(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            rt.block_on(daemon::daemon(args))?;
+
+            Ok(ExitCode::SUCCESS)
+        }
         Args::Format(args) => {
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
@@ -103,6 +250,159 @@ fn main() -> anyhow::Result<ExitCode> {
 
             Ok(exit_code)
         }
+        Args::History(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(history::history(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Cat(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(cat::cat(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Ls(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(ls::ls(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Test(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(test::test(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Snapshot(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(snapshot::snapshot(args))?;
+
+            Ok(exit_code)
+        }
+        Args::StoreStats(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(store_stats::store_stats(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Fsck(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(fsck::fsck(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Store(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(store::store(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Pin(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(pin::pin(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Unpin(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(pin::unpin(args))?;
+
+            Ok(exit_code)
+        }
+        Args::ExportOciLayer(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(export_oci_layer::export_oci_layer(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Package(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(package::package(args))?;
+
+            Ok(exit_code)
+        }
+        Args::BundleSource(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(bundle_source::bundle_source(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Checksums(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(checksums::checksums(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Info(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(info::info(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Inputs(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(inputs::inputs(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Why(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(why::why(args))?;
+
+            Ok(exit_code)
+        }
         Args::Publish(args) => {
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
@@ -112,6 +412,38 @@ fn main() -> anyhow::Result<ExitCode> {
 
             Ok(exit_code)
         }
+        Args::Registry(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(registry::registry(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Explain(args) => {
+            let exit_code = explain::explain(args)?;
+
+            Ok(exit_code)
+        }
+        Args::Replay(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(replay::replay(args))?;
+
+            Ok(exit_code)
+        }
+        Args::Outdated(args) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            let exit_code = rt.block_on(outdated::outdated(args))?;
+
+            Ok(exit_code)
+        }
         Args::Lsp(args) => {
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
@@ -168,7 +500,9 @@ struct AnalyzeArgs {
 
 async fn analyze(args: AnalyzeArgs) -> anyhow::Result<()> {
     let vfs = brioche_core::vfs::Vfs::immutable();
-    let project = brioche_core::project::analyze::analyze_project(&vfs, &args.project).await?;
+    let read_access = brioche_core::script::read_access::ReadAccess::default();
+    let project =
+        brioche_core::project::analyze::analyze_project(&vfs, &read_access, &args.project).await?;
     println!("{project:#?}");
     Ok(())
 }
@@ -227,6 +561,104 @@ struct ProjectArgs {
     /// The name of a registry project to build
     #[clap(short, long)]
     registry: Option<String>,
+
+    /// Allow project scripts to read files from this path, even if it's
+    /// outside of the project directories. Can be passed multiple times
+    #[clap(long)]
+    allow_read: Vec<PathBuf>,
+
+    /// Allow project scripts to read any file. Only use this with
+    /// trusted projects
+    #[clap(long)]
+    allow_read_all: bool,
+}
+
+impl ProjectArgs {
+    fn script_read_access(&self) -> brioche_core::script::read_access::ReadAccess {
+        brioche_core::script::read_access::ReadAccess {
+            unrestricted: self.allow_read_all,
+            allowed_paths: self.allow_read.clone(),
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct OutputPermissionsArgs {
+    /// Permission bits to clear from files and directories in the output,
+    /// in octal [default: 022]
+    #[arg(long, value_parser = parse_octal_mode)]
+    umask: Option<u32>,
+
+    /// The mode for directories in the output before applying the umask,
+    /// in octal [default: 777]
+    #[arg(long, value_parser = parse_octal_mode)]
+    dir_mode: Option<u32>,
+
+    /// Make files in the output read-only
+    #[arg(long)]
+    readonly: bool,
+
+    /// Change the owner of the output, as numeric `UID:GID`. Usually
+    /// requires running as root
+    #[arg(long)]
+    owner: Option<brioche_core::output::OutputOwner>,
+}
+
+impl OutputPermissionsArgs {
+    fn output_permissions(&self) -> brioche_core::output::OutputPermissions {
+        let default = brioche_core::output::OutputPermissions::default();
+        brioche_core::output::OutputPermissions {
+            umask: self.umask.unwrap_or(default.umask),
+            directory_mode: self.dir_mode,
+            readonly: self.readonly,
+            owner: self.owner,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct ConcurrencyArgs {
+    /// The maximum number of processes to run at once [default: 20]
+    #[arg(long)]
+    max_processes: Option<std::num::NonZeroUsize>,
+
+    /// The maximum number of downloads to run at once [default: 20]
+    #[arg(long)]
+    max_downloads: Option<std::num::NonZeroUsize>,
+
+    /// The maximum number of blobs to save at once, such as when
+    /// ingesting input files or unpacking archives [default: 10]
+    #[arg(long)]
+    max_blob_saves: Option<std::num::NonZeroUsize>,
+}
+
+impl ConcurrencyArgs {
+    /// Returns true if any limit was set. The daemon's limits are shared
+    /// by every build it runs, so these can't be applied to a single build.
+    fn is_set(&self) -> bool {
+        self.max_processes.is_some()
+            || self.max_downloads.is_some()
+            || self.max_blob_saves.is_some()
+    }
+
+    fn apply(&self, mut builder: brioche_core::BriocheBuilder) -> brioche_core::BriocheBuilder {
+        if let Some(max_processes) = self.max_processes {
+            builder = builder.max_concurrent_processes(max_processes.get());
+        }
+        if let Some(max_downloads) = self.max_downloads {
+            builder = builder.max_concurrent_downloads(max_downloads.get());
+        }
+        if let Some(max_blob_saves) = self.max_blob_saves {
+            builder = builder.max_concurrent_blob_saves(max_blob_saves.get());
+        }
+        builder
+    }
+}
+
+fn parse_octal_mode(value: &str) -> anyhow::Result<u32> {
+    let mode = u32::from_str_radix(value, 8)?;
+    anyhow::ensure!(mode <= 0o7777, "mode {value} is out of range");
+    Ok(mode)
 }
 
 async fn load_project(
@@ -253,3 +685,122 @@ async fn load_project(
 
     Ok(project_hash)
 }
+
+/// Load the project for a command that takes a target like
+/// `./packages/foo#test`. Either part of the target can be omitted; the
+/// project defaults to `--project` / `--registry` (or the current
+/// directory), and the export defaults to `--export` (or `default`).
+async fn load_project_target(
+    brioche: &brioche_core::Brioche,
+    projects: &brioche_core::project::Projects,
+    args: &ProjectArgs,
+    target: Option<&str>,
+    export: Option<&str>,
+) -> anyhow::Result<(brioche_core::project::ProjectHash, String)> {
+    let (target_path, export) = split_target(target, export)?;
+
+    let project_hash = match target_path {
+        Some(target_path) => {
+            anyhow::ensure!(
+                args.project.is_none() && args.registry.is_none(),
+                "cannot specify both a target path and --project or --registry"
+            );
+
+            let remote_source =
+                brioche_core::project::remote::RemoteProjectSource::parse(target_path)?;
+            let project_path = match remote_source {
+                Some(remote_source) => {
+                    let fetched = brioche_core::project::remote::fetch_remote_project(
+                        brioche,
+                        &remote_source,
+                    )
+                    .await?;
+                    tracing::info!(
+                        url = %remote_source.url,
+                        commit = %fetched.commit,
+                        "fetched remote project"
+                    );
+                    fetched.path
+                }
+                None => {
+                    let current_dir = std::env::current_dir()?;
+                    brioche_core::project::resolve_project_path(&current_dir, target_path).await?
+                }
+            };
+            projects.load(brioche, &project_path, true).await?
+        }
+        None => load_project(brioche, projects, args).await?,
+    };
+
+    Ok((project_hash, export))
+}
+
+/// Split a target like `./packages/foo#test` into its path and export,
+/// using `--export` (or `default`) if the target has no export.
+fn split_target<'a>(
+    target: Option<&'a str>,
+    export: Option<&str>,
+) -> anyhow::Result<(Option<&'a str>, String)> {
+    let (target_path, target_export) = match target {
+        Some(target) => match target.split_once('#') {
+            Some((path, export)) => (Some(path).filter(|path| !path.is_empty()), Some(export)),
+            None => (Some(target), None),
+        },
+        None => (None, None),
+    };
+
+    let export = match (target_export, export) {
+        (Some(export), None) | (None, Some(export)) => export.to_string(),
+        (None, None) => "default".to_string(),
+        (Some(_), Some(_)) => {
+            anyhow::bail!("cannot specify an export in both the target and --export");
+        }
+    };
+    anyhow::ensure!(!export.is_empty(), "export name cannot be empty");
+
+    Ok((target_path, export))
+}
+
+/// Prompt the user to answer yes or no on stdin, defaulting to no. If stdin
+/// isn't a terminal, print `non_interactive_hint` and answer no.
+async fn confirm(prompt: &str, non_interactive_hint: &str) -> anyhow::Result<bool> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let prompt = prompt.to_string();
+    let non_interactive_hint = non_interactive_hint.to_string();
+    std::thread::spawn(move || {
+        print!("{prompt} [y/N] ");
+        match std::io::stdout().flush() {
+            Ok(_) => {}
+            Err(error) => {
+                let _ = tx.send(Err(error));
+                return;
+            }
+        }
+
+        let stdin = std::io::stdin();
+
+        if !stdin.is_terminal() {
+            println!();
+            println!("{non_interactive_hint}");
+            let _ = tx.send(Ok(false));
+            return;
+        }
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(_) => {}
+            Err(error) => {
+                let _ = tx.send(Err(error));
+                return;
+            }
+        }
+
+        let input = line.trim().to_lowercase();
+        let confirmed = matches!(input.as_str(), "y" | "yes");
+        let _ = tx.send(Ok(confirmed));
+    });
+
+    let response = rx.await??;
+    Ok(response)
+}
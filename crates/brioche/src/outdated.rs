@@ -0,0 +1,65 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct OutdatedArgs {
+    /// The path to the project directory to check
+    #[arg(short, long, default_value = ".")]
+    project: PathBuf,
+}
+
+pub async fn outdated(args: OutdatedArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+    let projects = brioche_core::project::Projects::default();
+    let project_hash = projects.load(&brioche, &args.project, true).await?;
+
+    let outdated =
+        brioche_core::outdated::find_outdated_dependencies(&brioche, &projects, project_hash)
+            .await?;
+
+    guard.shutdown_console().await;
+
+    if outdated.is_empty() {
+        println!("All dependencies are up to date");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let display_version = |version: &brioche_core::outdated::DependencyVersion| {
+        version
+            .version
+            .clone()
+            .unwrap_or_else(|| version.hash.to_string())
+    };
+
+    println!("Outdated dependencies:");
+    for dependency in &outdated {
+        let locked = display_version(&dependency.locked);
+        let latest = display_version(&dependency.latest);
+        match &dependency.upgradable {
+            Some(upgradable) if !dependency.is_blocked() => {
+                let upgradable = display_version(upgradable);
+                println!("  {} {locked} -> {upgradable}", dependency.name);
+            }
+            Some(upgradable) => {
+                let upgradable = display_version(upgradable);
+                println!(
+                    "  {} {locked} -> {upgradable} (latest {latest} blocked by constraint \"{}\")",
+                    dependency.name, dependency.constraint,
+                );
+            }
+            None => {
+                println!(
+                    "  {} {locked} (latest {latest} blocked by constraint \"{}\")",
+                    dependency.name, dependency.constraint,
+                );
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
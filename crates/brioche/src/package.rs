@@ -0,0 +1,139 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::Context as _;
+use brioche_core::{
+    packaging::{PackageFormat, PackageMetadata},
+    reporter::ConsoleReporterKind,
+};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct PackageArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// The package format to write (`zip`, `deb`, or `rpm`)
+    #[arg(short, long)]
+    format: PackageFormat,
+
+    /// The path to write the package to [default: a file in the current
+    /// directory named after the package]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Where the package gets installed [default: `/opt/<name>`, or a
+    /// top-level `<name>-<version>` directory for zip files]
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// The package name [default: the project's name]
+    #[arg(long)]
+    name: Option<String>,
+
+    /// The package version [default: the project's version]
+    #[arg(long = "package-version")]
+    version: Option<String>,
+
+    /// A short description of the package
+    #[arg(long)]
+    description: Option<String>,
+
+    /// The package maintainer, like `Name <email@example.com>`
+    #[arg(long)]
+    maintainer: Option<String>,
+}
+
+pub async fn package(args: PackageArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let project = projects.project(project_hash)?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::history::bake_and_record(
+        &brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export,
+        },
+    )
+    .await?;
+    let brioche_core::recipe::Artifact::Directory(directory) = &artifact.value else {
+        anyhow::bail!("only directory artifacts can be packaged");
+    };
+
+    // Use the command line options first, then the project definition,
+    // then the output's annotations
+    let annotations = brioche_core::annotations::get_annotations(&brioche, artifact.value.hash())
+        .await?
+        .unwrap_or_default();
+    let name = args
+        .name
+        .or_else(|| project.definition.name.clone())
+        .or(annotations.name)
+        .context("package name is required (set `name` in the project or pass `--name`)")?;
+    let version = args
+        .version
+        .or_else(|| project.definition.version.clone())
+        .or(annotations.version)
+        .context(
+            "package version is required (set `version` in the project or pass `--package-version`)",
+        )?;
+    let metadata = PackageMetadata {
+        name,
+        version,
+        license: annotations.license,
+        description: args.description,
+        maintainer: args.maintainer,
+    };
+
+    let platform = brioche_core::platform::current_platform();
+    let output = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(metadata.file_name(args.format, platform)));
+    brioche_core::packaging::write_package(
+        &brioche,
+        directory,
+        brioche_core::packaging::PackageOptions {
+            format: args.format,
+            metadata: &metadata,
+            platform,
+            prefix: args.prefix.as_deref(),
+        },
+        &output,
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    println!("Wrote {} package to {}", args.format, output.display());
+
+    Ok(ExitCode::SUCCESS)
+}
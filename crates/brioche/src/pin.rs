@@ -0,0 +1,97 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct PinArgs {
+    /// The hashes of the artifacts or blobs to pin. Lists every pin if
+    /// no hashes are given
+    hashes: Vec<String>,
+
+    /// A note to record with the pins, like why they're needed
+    #[arg(long)]
+    note: Option<String>,
+
+    /// Print the list of pins as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct UnpinArgs {
+    /// The hashes of the artifacts or blobs to unpin
+    #[arg(required = true)]
+    hashes: Vec<String>,
+}
+
+pub async fn pin(args: PinArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+
+    if args.hashes.is_empty() {
+        let pins = brioche_core::pins::list_pins(&brioche).await?;
+
+        guard.shutdown_console().await;
+
+        if args.json {
+            let serialized = serde_json::to_string_pretty(&pins)?;
+            println!("{serialized}");
+        } else if pins.is_empty() {
+            println!("No pins");
+        } else {
+            for pin in &pins {
+                match &pin.note {
+                    Some(note) => println!("{} {} ({note})", pin.kind, pin.hash),
+                    None => println!("{} {}", pin.kind, pin.hash),
+                }
+            }
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut pinned = vec![];
+    for hash in &args.hashes {
+        let kind = brioche_core::pins::pin_hash(&brioche, hash, args.note.as_deref()).await?;
+        pinned.push((kind, hash));
+    }
+
+    guard.shutdown_console().await;
+
+    for (kind, hash) in pinned {
+        println!("Pinned {kind} {hash}");
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+pub async fn unpin(args: UnpinArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+
+    let mut results = vec![];
+    for hash in &args.hashes {
+        let unpinned = brioche_core::pins::unpin_hash(&brioche, hash).await?;
+        results.push((hash, unpinned));
+    }
+
+    guard.shutdown_console().await;
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for (hash, unpinned) in results {
+        if unpinned.is_empty() {
+            eprintln!("{hash} is not pinned");
+            exit_code = ExitCode::FAILURE;
+        }
+        for kind in unpinned {
+            println!("Unpinned {kind} {hash}");
+        }
+    }
+
+    Ok(exit_code)
+}
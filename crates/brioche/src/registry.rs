@@ -0,0 +1,92 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct RegistryArgs {
+    #[command(subcommand)]
+    command: RegistryCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum RegistryCommand {
+    /// Delete old tags of a published project, so the registry can remove
+    /// artifacts that are no longer reachable. The `latest` tag is always
+    /// kept
+    Prune {
+        /// The name of the project, like `@scope/name`
+        project_name: String,
+
+        /// Delete tags published more than this many days ago
+        #[arg(long)]
+        older_than_days: Option<u64>,
+
+        /// Keep this many of the most recently published tags
+        #[arg(long)]
+        keep_latest: Option<usize>,
+
+        /// Only print which tags would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print the response as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub async fn registry(args: RegistryArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    match args.command {
+        RegistryCommand::Prune {
+            project_name,
+            older_than_days,
+            keep_latest,
+            dry_run,
+            json,
+        } => {
+            anyhow::ensure!(
+                older_than_days.is_some() || keep_latest.is_some(),
+                "either --older-than-days or --keep-latest is required"
+            );
+
+            let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+            let request = brioche_core::registry::PruneProjectRequest {
+                older_than_secs: older_than_days.map(|days| days.saturating_mul(24 * 60 * 60)),
+                keep_latest,
+                dry_run,
+            };
+            let response = brioche
+                .registry_client
+                .prune_project(&project_name, &request)
+                .await?;
+
+            guard.shutdown_console().await;
+
+            if json {
+                let serialized = serde_json::to_string_pretty(&response)?;
+                println!("{serialized}");
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if response.pruned_tags.is_empty() {
+                println!("No tags of {project_name} to prune");
+            } else {
+                if dry_run {
+                    println!("Would delete tags of {project_name}:");
+                } else {
+                    println!("Deleted tags of {project_name}:");
+                }
+                for pruned in &response.pruned_tags {
+                    println!("  {} ({})", pruned.tag, pruned.project_hash);
+                }
+            }
+            println!("{} tags remaining", response.remaining_tags);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
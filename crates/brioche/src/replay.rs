@@ -0,0 +1,55 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ReplayArgs {
+    /// The hash of a complete process recipe that was run before
+    hash: brioche_core::recipe::RecipeHash,
+
+    /// Keep temporary build files
+    #[arg(long)]
+    keep_temps: bool,
+
+    /// Trace the files the process accesses, and report inputs that
+    /// were never used or files missing from the sandbox
+    #[arg(long)]
+    trace_inputs: bool,
+}
+
+pub async fn replay(args: ReplayArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .keep_temps(args.keep_temps)
+        .trace_process_inputs(args.trace_inputs)
+        .build()
+        .await?;
+
+    let replay = brioche_core::bake::replay_process(&brioche, args.hash).await?;
+
+    guard.shutdown_console().await;
+
+    let output_hash = replay.output.hash();
+    println!("Replay output: {output_hash}");
+
+    match replay.cached_output {
+        Some(cached_output) => {
+            let cached_output_hash = cached_output.hash();
+            println!("Cached output: {cached_output_hash}");
+            if cached_output_hash == output_hash {
+                println!("Outputs match");
+            } else {
+                println!("Outputs differ, the process is not deterministic");
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        None => {
+            println!("No cached output to compare against");
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
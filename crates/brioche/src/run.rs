@@ -11,13 +11,20 @@ pub struct RunArgs {
     #[command(flatten)]
     project: super::ProjectArgs,
 
-    /// Which TypeScript export to build
-    #[arg(short, long, default_value = "default")]
-    export: String,
+    /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`). The path can also be the name of a
+    /// workspace member, or a git repository like `github:owner/repo`,
+    /// `github:owner/repo/<rev>`, or `git+https://...?rev=<rev>`
+    target: Option<String>,
 
-    /// The path within the build artifact to execute
-    #[arg(short, long, default_value = "brioche-run")]
-    command: String,
+    /// Which TypeScript export to build [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// The path within the build artifact to execute [default: the
+    /// annotated main executable, or `brioche-run`]
+    #[arg(short, long)]
+    command: Option<String>,
 
     /// Suppress Brioche's output
     #[arg(short, long)]
@@ -31,6 +38,19 @@ pub struct RunArgs {
     #[arg(long)]
     keep_temps: bool,
 
+    #[command(flatten)]
+    concurrency: super::ConcurrencyArgs,
+
+    /// Rebuild whenever the project changes, then restart the command if
+    /// the build output changed
+    #[arg(long)]
+    watch: bool,
+
+    /// How often to check the project for changes with `--watch`, in
+    /// milliseconds
+    #[arg(long, default_value_t = 500)]
+    watch_interval_ms: u64,
+
     /// Arguments to pass to the command
     #[arg(last = true)]
     args: Vec<std::ffi::OsString>,
@@ -42,142 +62,350 @@ pub async fn run(args: RunArgs) -> anyhow::Result<ExitCode> {
     } else {
         brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?
     };
-    reporter.set_is_evaluating(true);
 
-    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+    let brioche = args
+        .concurrency
+        .apply(brioche_core::BriocheBuilder::new(reporter.clone()))
         .keep_temps(args.keep_temps)
+        .script_read_access(args.project.script_read_access())
         .build()
         .await?;
-    let projects = brioche_core::project::Projects::default();
 
-    let build_future = async {
-        let project_hash = super::load_project(&brioche, &projects, &args.project).await?;
+    let built = build(&brioche, &reporter, &mut guard, &args)
+        .instrument(tracing::info_span!("run_build"))
+        .await?;
 
-        let num_lockfiles_updated = projects.commit_dirty_lockfiles().await?;
-        if num_lockfiles_updated > 0 {
-            tracing::info!(num_lockfiles_updated, "updated lockfiles");
-        }
+    let elapsed = reporter.elapsed().human_duration();
+    let num_jobs = reporter.num_jobs();
+    let jobs_message = match num_jobs {
+        0 => "(no new jobs)".to_string(),
+        1 => "1 job".to_string(),
+        n => format!("{n} jobs"),
+    };
+    if !args.quiet {
+        eprintln!("Build finished, completed {jobs_message} in {elapsed}");
+    }
 
-        if args.check {
-            let checked =
-                brioche_core::script::check::check(&brioche, &projects, project_hash).await?;
-
-            let result = checked.ensure_ok(brioche_core::script::check::DiagnosticLevel::Error);
-
-            match result {
-                Ok(()) => reporter.emit(superconsole::Lines::from_multiline_string(
-                    "No errors found",
-                    superconsole::style::ContentStyle {
-                        foreground_color: Some(superconsole::style::Color::Green),
-                        ..superconsole::style::ContentStyle::default()
-                    },
-                )),
-                Err(diagnostics) => {
-                    guard.shutdown_console().await;
-
-                    diagnostics.write(&brioche.vfs, &mut std::io::stdout())?;
-                    anyhow::bail!("checks failed");
-                }
-            }
+    if args.watch {
+        return watch(&brioche, &reporter, &mut guard, &args, built).await;
+    }
+
+    if built.service.is_some() {
+        return run_service(&args, &built).await;
+    }
+
+    if !args.quiet {
+        eprintln!("Running {}", built.command_name);
+    }
+
+    let mut command = std::process::Command::new(built.command_path());
+    command.args(&args.args);
+
+    if let Some(resource_dir) = &built.output.resource_dir {
+        command.env("BRIOCHE_RESOURCE_DIR", resource_dir);
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::process::CommandExt as _;
+
+            let error = command.exec();
+            Err(error.into())
+        } else {
+            let result = command.status().context("failed to run process")?;
+            Ok(exit_code(result))
         }
+    }
+}
 
-        let recipe = brioche_core::script::evaluate::evaluate(
-            &brioche,
-            &projects,
-            project_hash,
-            &args.export,
-        )
-        .await?;
+/// The build output of the project, along with what to run from it.
+struct Built {
+    projects: brioche_core::project::Projects,
+    project_hash: brioche_core::project::ProjectHash,
+    artifact_hash: brioche_core::recipe::RecipeHash,
+    output: brioche_core::output::LocalOutput,
+    command_name: String,
+    service: Option<brioche_core::annotations::ServiceAnnotation>,
+}
 
-        reporter.set_is_evaluating(false);
-        let artifact = brioche_core::bake::bake(
-            &brioche,
-            recipe,
-            &brioche_core::bake::BakeScope::Project {
-                project_hash,
-                export: args.export.to_string(),
-            },
-        )
-        .await?;
+impl Built {
+    fn command_path(&self) -> std::path::PathBuf {
+        self.output.path.join(&self.command_name)
+    }
 
-        guard.shutdown_console().await;
+    /// Get the command to supervise. Commands that aren't services are
+    /// never restarted when they exit.
+    fn service_command(&self, args: &RunArgs) -> brioche_core::service::ServiceCommand {
+        let mut service_args = vec![];
+        let mut env = vec![];
+        let mut restart = brioche_core::annotations::ServiceRestartPolicy::Never;
+        if let Some(service) = &self.service {
+            service_args.extend(service.args.iter().map(std::ffi::OsString::from));
+            env.extend(
+                service
+                    .env
+                    .iter()
+                    .map(|(key, value)| (key.into(), value.into())),
+            );
+            restart = service.restart;
+        }
+        service_args.extend(args.args.iter().cloned());
 
-        let elapsed = reporter.elapsed().human_duration();
-        let num_jobs = reporter.num_jobs();
-        let jobs_message = match num_jobs {
-            0 => "(no new jobs)".to_string(),
-            1 => "1 job".to_string(),
-            n => format!("{n} jobs"),
-        };
-        if !args.quiet {
-            eprintln!("Build finished, completed {jobs_message} in {elapsed}");
+        if let Some(resource_dir) = &self.output.resource_dir {
+            env.push(("BRIOCHE_RESOURCE_DIR".into(), resource_dir.into()));
         }
 
-        // Validate that the artifact is a directory that contains the
-        // command to run before returning
-        let command_artifact = match &artifact.value {
-            brioche_core::recipe::Artifact::File(_) => {
-                anyhow::bail!("artifact returned a file, expected a directory");
-            }
-            brioche_core::recipe::Artifact::Symlink { .. } => {
-                anyhow::bail!("artifact returned a symlink, expected a directory");
+        brioche_core::service::ServiceCommand {
+            program: self.command_path(),
+            args: service_args,
+            env,
+            restart,
+        }
+    }
+}
+
+async fn build(
+    brioche: &brioche_core::Brioche,
+    reporter: &brioche_core::reporter::Reporter,
+    guard: &mut brioche_core::reporter::ReporterGuard,
+    args: &RunArgs,
+) -> anyhow::Result<Built> {
+    reporter.set_is_evaluating(true);
+
+    let projects = brioche_core::project::Projects::default();
+    let (project_hash, export) = super::load_project_target(
+        brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+
+    let num_lockfiles_updated = projects.commit_dirty_lockfiles().await?;
+    if num_lockfiles_updated > 0 {
+        tracing::info!(num_lockfiles_updated, "updated lockfiles");
+    }
+
+    if args.check {
+        let checked = brioche_core::script::check::check(brioche, &projects, project_hash).await?;
+
+        let result = checked.ensure_ok(brioche_core::script::check::DiagnosticLevel::Error);
+
+        match result {
+            Ok(()) => reporter.emit(superconsole::Lines::from_multiline_string(
+                "No errors found",
+                superconsole::style::ContentStyle {
+                    foreground_color: Some(superconsole::style::Color::Green),
+                    ..superconsole::style::ContentStyle::default()
+                },
+            )),
+            Err(diagnostics) => {
+                guard.shutdown_console().await;
+
+                diagnostics.write(&brioche.vfs, &mut std::io::stdout())?;
+                anyhow::bail!("checks failed");
             }
-            brioche_core::recipe::Artifact::Directory(dir) => dir
-                .get(&brioche, args.command.as_bytes())
-                .await
-                .with_context(|| {
-                    format!(
-                        "failed to retrieve {:?} from returned artifact",
-                        args.command
-                    )
-                })?,
-        };
-        anyhow::ensure!(
-            command_artifact.is_some(),
-            "{:?} not found in returned artifact",
-            args.command
-        );
+        }
+    }
+
+    let recipe =
+        brioche_core::script::evaluate::evaluate(brioche, &projects, project_hash, &export).await?;
+
+    reporter.set_is_evaluating(false);
+    let artifact = brioche_core::history::bake_and_record(
+        brioche,
+        recipe,
+        &brioche_core::bake::BakeScope::Project {
+            project_hash,
+            export: export.clone(),
+        },
+    )
+    .await?;
+    brioche_core::blob_store::flush_uploads(brioche).await?;
 
-        let output = brioche_core::output::create_local_output(&brioche, &artifact.value).await?;
+    guard.shutdown_console().await;
 
-        Ok(output)
+    let annotations =
+        brioche_core::annotations::get_annotations(brioche, artifact.value.hash()).await?;
+    let annotations = annotations.unwrap_or_default();
+    let (command_name, service) = match (&args.command, annotations.service) {
+        (Some(command), _) => (command.clone(), None),
+        (None, Some(service)) => (service.command.clone(), Some(service)),
+        (None, None) => {
+            let command = annotations
+                .main_executable
+                .unwrap_or_else(|| "brioche-run".to_string());
+            (command, None)
+        }
     };
 
-    let output = build_future
-        .instrument(tracing::info_span!("run_build"))
-        .await?;
+    // Validate that the artifact is a directory that contains the
+    // command to run before returning
+    let command_artifact = match &artifact.value {
+        brioche_core::recipe::Artifact::File(_) => {
+            anyhow::bail!("artifact returned a file, expected a directory");
+        }
+        brioche_core::recipe::Artifact::Symlink { .. } => {
+            anyhow::bail!("artifact returned a symlink, expected a directory");
+        }
+        brioche_core::recipe::Artifact::Directory(dir) => dir
+            .get(brioche, command_name.as_bytes())
+            .await
+            .with_context(|| {
+                format!("failed to retrieve {command_name:?} from returned artifact")
+            })?,
+    };
+    anyhow::ensure!(
+        command_artifact.is_some(),
+        "{command_name:?} not found in returned artifact",
+    );
+
+    let output = brioche_core::output::create_local_output(brioche, &artifact.value).await?;
 
-    let command_path = output.path.join(&args.command);
+    Ok(Built {
+        projects,
+        project_hash,
+        artifact_hash: artifact.value.hash(),
+        output,
+        command_name,
+        service,
+    })
+}
 
+/// Run the build output as a service until it exits for good, or until
+/// Ctrl-C is pressed.
+async fn run_service(args: &RunArgs, built: &Built) -> anyhow::Result<ExitCode> {
+    let command = built.service_command(args);
     if !args.quiet {
-        eprintln!("Running {}", args.command);
+        eprintln!(
+            "Running service {} (restart: {})",
+            built.command_name, command.restart
+        );
     }
 
-    let mut command = std::process::Command::new(command_path);
-    command.args(&args.args);
+    let stop = tokio_util::sync::CancellationToken::new();
+    tokio::spawn({
+        let stop = stop.clone();
+        async move {
+            let _ = tokio::signal::ctrl_c().await;
+            stop.cancel();
+        }
+    });
 
-    if let Some(resource_dir) = output.resource_dir {
-        command.env("BRIOCHE_RESOURCE_DIR", resource_dir);
+    let status = brioche_core::service::run_service(&command, stop).await?;
+    match status {
+        Some(status) => Ok(exit_code(status)),
+        None => Ok(ExitCode::SUCCESS),
     }
+}
 
-    cfg_if::cfg_if! {
-        if #[cfg(unix)] {
-            use std::os::unix::process::CommandExt as _;
+/// Run the build output, then rebuild whenever the project changes. The
+/// command is restarted only when the rebuilt output differs.
+async fn watch(
+    brioche: &brioche_core::Brioche,
+    reporter: &brioche_core::reporter::Reporter,
+    guard: &mut brioche_core::reporter::ReporterGuard,
+    args: &RunArgs,
+    mut built: Built,
+) -> anyhow::Result<ExitCode> {
+    let poll_interval = std::time::Duration::from_millis(args.watch_interval_ms);
 
-            let error = command.exec();
-            Err(error.into())
-        } else {
-            let result = command.status().context("failed to run process")?;
-            if result.success() {
-                Ok(ExitCode::SUCCESS)
-            } else {
-                let code = result
-                    .code()
-                    .and_then(|code| u8::try_from(code).ok())
-                    .map(ExitCode::from)
-                    .unwrap_or(ExitCode::FAILURE);
-                Ok(code)
+    loop {
+        if !args.quiet {
+            eprintln!("Running {} (watching for changes)", built.command_name);
+        }
+
+        let command = built.service_command(args);
+        let stop = tokio_util::sync::CancellationToken::new();
+        let mut service_task = tokio::spawn({
+            let stop = stop.clone();
+            async move { brioche_core::service::run_service(&command, stop).await }
+        });
+        let mut service_exited = false;
+
+        // Rebuild until the output changes
+        let mut fingerprint =
+            brioche_core::service::project_fingerprint(&built.projects, built.project_hash).await?;
+        let rebuilt = loop {
+            let changed = brioche_core::service::wait_for_project_change(
+                &built.projects,
+                built.project_hash,
+                fingerprint,
+                poll_interval,
+            );
+            tokio::select! {
+                result = changed => result?,
+                result = &mut service_task, if !service_exited => {
+                    service_exited = true;
+                    let status = result??;
+                    if let (Some(status), false) = (status, args.quiet) {
+                        eprintln!(
+                            "{} exited with {status}, waiting for changes",
+                            built.command_name
+                        );
+                    }
+                    continue;
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    stop.cancel();
+                    if !service_exited {
+                        service_task.await??;
+                    }
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+
+            if !args.quiet {
+                eprintln!("Project changed, rebuilding");
             }
+            match build(brioche, reporter, guard, args).await {
+                Ok(rebuilt) if rebuilt.artifact_hash == built.artifact_hash => {
+                    if !args.quiet {
+                        eprintln!("Build output unchanged");
+                    }
+                    fingerprint = brioche_core::service::project_fingerprint(
+                        &rebuilt.projects,
+                        rebuilt.project_hash,
+                    )
+                    .await?;
+                    built = rebuilt;
+                }
+                Ok(rebuilt) => {
+                    break rebuilt;
+                }
+                Err(error) => {
+                    // Keep the previous output running until the project
+                    // builds again
+                    eprintln!("Build failed: {error:#}");
+                    fingerprint = brioche_core::service::project_fingerprint(
+                        &built.projects,
+                        built.project_hash,
+                    )
+                    .await?;
+                }
+            }
+        };
+
+        if !args.quiet {
+            eprintln!("Build output changed, restarting {}", built.command_name);
         }
+        stop.cancel();
+        if !service_exited {
+            service_task.await??;
+        }
+        built = rebuilt;
+    }
+}
+
+fn exit_code(status: std::process::ExitStatus) -> ExitCode {
+    if status.success() {
+        ExitCode::SUCCESS
+    } else {
+        status
+            .code()
+            .and_then(|code| u8::try_from(code).ok())
+            .map(ExitCode::from)
+            .unwrap_or(ExitCode::FAILURE)
     }
 }
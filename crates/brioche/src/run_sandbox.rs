@@ -1,10 +1,8 @@
 use std::process::ExitCode;
 
-use brioche_core::sandbox::SandboxExecutionConfig;
+use brioche_core::sandbox::{SandboxExecutionConfig, SANDBOX_ERROR_EXIT_CODE};
 use clap::Parser;
 
-const BRIOCHE_SANDBOX_ERROR_CODE: u8 = 122;
-
 #[derive(Debug, Parser)]
 pub struct RunSandboxArgs {
     #[arg(long)]
@@ -16,7 +14,7 @@ pub fn run_sandbox(args: RunSandboxArgs) -> ExitCode {
         Ok(config) => config,
         Err(error) => {
             eprintln!("brioche: failed to parse sandbox config: {error:#}");
-            return ExitCode::from(BRIOCHE_SANDBOX_ERROR_CODE);
+            return ExitCode::from(SANDBOX_ERROR_EXIT_CODE);
         }
     };
 
@@ -24,7 +22,7 @@ pub fn run_sandbox(args: RunSandboxArgs) -> ExitCode {
         Ok(status) => status,
         Err(error) => {
             eprintln!("brioche: failed to run sandbox: {error:#}");
-            return ExitCode::from(BRIOCHE_SANDBOX_ERROR_CODE);
+            return ExitCode::from(SANDBOX_ERROR_EXIT_CODE);
         }
     };
 
@@ -1,7 +1,4 @@
-use std::{
-    collections::HashMap,
-    io::{IsTerminal, Write as _},
-};
+use std::collections::HashMap;
 
 use anyhow::Context as _;
 use clap::Parser;
@@ -43,7 +40,11 @@ pub async fn self_update(args: SelfUpdateArgs) -> anyhow::Result<bool> {
     let should_update = if args.confirm {
         true
     } else {
-        confirm_update().await?
+        super::confirm(
+            "Install update?",
+            "Pass `--confirm` to install update non-interactively",
+        )
+        .await?
     };
 
     if !should_update {
@@ -152,46 +153,6 @@ async fn get_update_platform_manifest(
     Ok(Some(platform_manifest))
 }
 
-async fn confirm_update() -> anyhow::Result<bool> {
-    let (tx, rx) = tokio::sync::oneshot::channel();
-
-    std::thread::spawn(move || {
-        print!("Install update? [y/N] ");
-        match std::io::stdout().flush() {
-            Ok(_) => {}
-            Err(error) => {
-                let _ = tx.send(Err(error));
-                return;
-            }
-        }
-
-        let stdin = std::io::stdin();
-
-        if !stdin.is_terminal() {
-            println!();
-            println!("Pass `--confirm` to install update non-interactively");
-            let _ = tx.send(Ok(false));
-            return;
-        }
-
-        let mut line = String::new();
-        match stdin.read_line(&mut line) {
-            Ok(_) => {}
-            Err(error) => {
-                let _ = tx.send(Err(error));
-                return;
-            }
-        }
-
-        let input = line.trim().to_lowercase();
-        let confirmed = matches!(input.as_str(), "y" | "yes");
-        let _ = tx.send(Ok(confirmed));
-    });
-
-    let response = rx.await??;
-    Ok(response)
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct SelfUpdateManifest {
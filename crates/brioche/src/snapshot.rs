@@ -0,0 +1,82 @@
+use std::process::ExitCode;
+
+use brioche_core::{reporter::ConsoleReporterKind, snapshot::SnapshotResult};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct SnapshotArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// The project and export to check, as `[PATH][#EXPORT]` (e.g.
+    /// `./packages/foo#test`)
+    target: Option<String>,
+
+    /// Which TypeScript export to check [default: default]
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// Replace snapshots that don't match instead of failing
+    #[arg(long)]
+    update_snapshots: bool,
+}
+
+pub async fn snapshot(args: SnapshotArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let (project_hash, export) = super::load_project_target(
+        &brioche,
+        &projects,
+        &args.project,
+        args.target.as_deref(),
+        args.export.as_deref(),
+    )
+    .await?;
+    let recipe =
+        brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, &export)
+            .await?;
+    reporter.set_is_evaluating(false);
+
+    let snapshot_path = brioche_core::snapshot::snapshot_path(&projects, project_hash, &export)?;
+    let result = brioche_core::snapshot::check_snapshot(
+        &brioche,
+        &recipe.value,
+        &snapshot_path,
+        args.update_snapshots,
+    )
+    .await?;
+
+    guard.shutdown_console().await;
+
+    let snapshot_path = snapshot_path.display();
+    match &result {
+        SnapshotResult::Matched => {
+            println!("Snapshot for {export} matches {snapshot_path}");
+        }
+        SnapshotResult::Created => {
+            println!("Wrote new snapshot for {export} to {snapshot_path}");
+        }
+        SnapshotResult::Updated => {
+            println!("Updated snapshot for {export} at {snapshot_path}");
+        }
+        SnapshotResult::Mismatched { diff } => {
+            print!("{diff}");
+            eprintln!("Snapshot for {export} doesn't match {snapshot_path}");
+            eprintln!("Run with --update-snapshots if this change is expected");
+        }
+    }
+
+    if result.is_ok() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
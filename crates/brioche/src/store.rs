@@ -0,0 +1,248 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use human_repr::HumanCount as _;
+
+#[derive(Debug, Parser)]
+pub struct StoreArgs {
+    #[command(subcommand)]
+    command: StoreCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum StoreCommand {
+    /// Move the Brioche store to a new home directory, verify it, and
+    /// update the config file to use it
+    Migrate {
+        /// The new home directory. Must not exist or must be empty
+        #[arg(long)]
+        to: PathBuf,
+
+        /// Keep the old store instead of removing it after migrating
+        #[arg(long)]
+        keep_old: bool,
+    },
+
+    /// Hash every blob with other algorithms and record the hashes as
+    /// aliases, so downloads pinned with those hashes are found locally
+    /// instead of being downloaded again
+    BackfillAliases {
+        /// The hash algorithms to add aliases for. Can be passed multiple
+        /// times
+        #[arg(long = "algorithm", default_value = "sha256")]
+        algorithms: Vec<brioche_core::HashAlgorithm>,
+
+        /// The most blob data to read per second, in megabytes. Useful for
+        /// backfilling in the background without slowing down builds
+        #[arg(long)]
+        max_mb_per_second: Option<u64>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export blobs and their aliases to a bundle, which can be imported
+    /// on another machine without access to a registry
+    Export {
+        /// The hashes of the blobs to export
+        #[arg(required = true)]
+        blob_hashes: Vec<brioche_core::blob::BlobHash>,
+
+        /// The path to write the bundle to (e.g. `bundle.tar.zst`)
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Import the blobs and aliases from a bundle created with
+    /// `brioche store export`
+    Import {
+        /// The path of the bundle to import
+        bundle: PathBuf,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the store path a project's build output will be saved to,
+    /// without creating it. The path only depends on the output's hash,
+    /// so it can be referenced by other tools before it exists
+    Path {
+        #[command(flatten)]
+        project: super::ProjectArgs,
+
+        /// The project and export to build, as `[PATH][#EXPORT]` (e.g.
+        /// `./packages/foo#test`)
+        target: Option<String>,
+
+        /// Which TypeScript export to build [default: default]
+        #[arg(short, long)]
+        export: Option<String>,
+    },
+}
+
+pub async fn store(args: StoreArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    match args.command {
+        StoreCommand::Migrate { to, keep_old } => {
+            let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+                .build()
+                .await?;
+            let report = brioche_core::store_migrate::migrate_store(&brioche, &to).await?;
+
+            // Open the new store, which also runs any database migrations
+            // needed for the current store layout
+            brioche_core::BriocheBuilder::new(reporter)
+                .home(report.new_home.clone())
+                .build()
+                .await?;
+
+            let config_path =
+                brioche_core::store_migrate::set_configured_home(&report.new_home).await?;
+
+            if !keep_old {
+                brioche_core::store_migrate::remove_old_store(&brioche.home).await?;
+            }
+
+            guard.shutdown_console().await;
+
+            println!(
+                "Copied {} files ({}) to {}",
+                report.num_files,
+                report.total_size.human_count_bytes(),
+                report.new_home.display()
+            );
+            println!(
+                "Verified the database and {} blobs",
+                report.num_blobs_verified
+            );
+            println!("Updated {}", config_path.display());
+            if keep_old {
+                println!("Old store kept at {}", brioche.home.display());
+            } else {
+                println!("Removed old store at {}", brioche.home.display());
+            }
+        }
+        StoreCommand::BackfillAliases {
+            algorithms,
+            max_mb_per_second,
+            json,
+        } => {
+            let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+            let options = brioche_core::blob::BackfillAliasesOptions {
+                algorithms,
+                max_bytes_per_second: max_mb_per_second.map(|mb| mb.saturating_mul(1_000_000)),
+            };
+            let report = brioche_core::blob::backfill_blob_aliases(&brioche, &options).await?;
+
+            guard.shutdown_console().await;
+
+            if json {
+                let serialized = serde_json::to_string_pretty(&report)?;
+                println!("{serialized}");
+            } else {
+                println!(
+                    "Hashed {} of {} blobs ({})",
+                    report.num_blobs_hashed,
+                    report.num_blobs,
+                    report.bytes_hashed.human_count_bytes()
+                );
+                println!("Added {} blob aliases", report.num_aliases_added);
+            }
+        }
+        StoreCommand::Export {
+            blob_hashes,
+            output,
+            json,
+        } => {
+            let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+            let report =
+                brioche_core::blob::bundle::export_bundle(&brioche, &blob_hashes, &output).await?;
+
+            guard.shutdown_console().await;
+
+            if json {
+                let serialized = serde_json::to_string_pretty(&report)?;
+                println!("{serialized}");
+            } else {
+                println!(
+                    "Exported {} blobs ({}) and {} blob aliases to {}",
+                    report.num_blobs,
+                    report.total_size.human_count_bytes(),
+                    report.num_aliases,
+                    output.display()
+                );
+            }
+        }
+        StoreCommand::Import { bundle, json } => {
+            let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+            let report = brioche_core::blob::bundle::import_bundle(&brioche, &bundle).await?;
+
+            guard.shutdown_console().await;
+
+            if json {
+                let serialized = serde_json::to_string_pretty(&report)?;
+                println!("{serialized}");
+            } else {
+                println!(
+                    "Imported {} blobs ({}), {} of them new",
+                    report.num_blobs,
+                    report.total_size.human_count_bytes(),
+                    report.num_new_blobs
+                );
+                println!("Added {} blob aliases", report.num_aliases);
+            }
+        }
+        StoreCommand::Path {
+            project,
+            target,
+            export,
+        } => {
+            reporter.set_is_evaluating(true);
+            let brioche = brioche_core::BriocheBuilder::new(reporter.clone())
+                .script_read_access(project.script_read_access())
+                .build()
+                .await?;
+            let projects = brioche_core::project::Projects::default();
+
+            let (project_hash, export) = super::load_project_target(
+                &brioche,
+                &projects,
+                &project,
+                target.as_deref(),
+                export.as_deref(),
+            )
+            .await?;
+            let recipe = brioche_core::script::evaluate::evaluate(
+                &brioche,
+                &projects,
+                project_hash,
+                &export,
+            )
+            .await?;
+
+            reporter.set_is_evaluating(false);
+            let scope = brioche_core::bake::BakeScope::Project {
+                project_hash,
+                export,
+            };
+            let artifact = brioche_core::bake::bake(&brioche, recipe, &scope).await?;
+
+            guard.shutdown_console().await;
+
+            let local_path =
+                brioche_core::output::local_output_path(&brioche, artifact.value.hash());
+            println!("{}", local_path.display());
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,161 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use human_repr::HumanCount as _;
+
+#[derive(Debug, Parser)]
+pub struct StoreStatsArgs {
+    /// The number of duplicated files to list, largest savings first (or
+    /// with `--recorded`, the number of largest blobs to list)
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+
+    /// Print the full report as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Report on the blobs recorded as they were saved (total size,
+    /// largest blobs, and growth over time) instead of scanning the store.
+    /// Blobs saved by older versions of Brioche aren't included
+    #[arg(long)]
+    recorded: bool,
+
+    /// How many days of growth to show with `--recorded`
+    #[arg(long, default_value_t = 30)]
+    days: u32,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedBlobsReport {
+    totals: brioche_core::store_stats::BlobTotals,
+    largest_blobs: Vec<brioche_core::store_stats::BlobRecord>,
+    growth: Vec<brioche_core::store_stats::BlobGrowth>,
+}
+
+pub async fn store_stats(args: StoreStatsArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter).build().await?;
+
+    if args.recorded {
+        let report = RecordedBlobsReport {
+            totals: brioche_core::store_stats::blob_totals(&brioche).await?,
+            largest_blobs: brioche_core::store_stats::largest_blobs(
+                &brioche,
+                args.top.try_into().unwrap_or(u32::MAX),
+            )
+            .await?,
+            growth: brioche_core::store_stats::blob_growth(&brioche, args.days).await?,
+        };
+
+        guard.shutdown_console().await;
+
+        print_recorded_blobs(&report, args.json)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let report = brioche_core::store_stats::store_report(&brioche).await?;
+
+    guard.shutdown_console().await;
+
+    if args.json {
+        let serialized = serde_json::to_string_pretty(&report)?;
+        println!("{serialized}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!(
+        "Store: {} files, {}",
+        report.num_files,
+        report.total_size.human_count_bytes()
+    );
+    println!(
+        "Duplicated contents: {} stored more than once, {} reclaimable by hard linking",
+        report.duplicates.len(),
+        report.duplicate_size.human_count_bytes()
+    );
+    println!(
+        "Leftover temporary files: {}",
+        report.temp_size.human_count_bytes()
+    );
+    println!(
+        "Total reclaimable: {}",
+        report.reclaimable_size().human_count_bytes()
+    );
+
+    if !report.duplicates.is_empty() && args.top > 0 {
+        println!();
+        println!("Largest duplicates:");
+        for group in report.duplicates.iter().take(args.top) {
+            println!(
+                "  {} ({} copies of {}{})",
+                group.content_hash,
+                group.paths.len(),
+                group.size.human_count_bytes(),
+                if group.executable { ", executable" } else { "" },
+            );
+            for path in &group.paths {
+                println!("    {}", path.display());
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_recorded_blobs(report: &RecordedBlobsReport, json: bool) -> anyhow::Result<()> {
+    if json {
+        let serialized = serde_json::to_string_pretty(report)?;
+        println!("{serialized}");
+        return Ok(());
+    }
+
+    println!(
+        "Recorded blobs: {} blobs, {}",
+        report.totals.num_blobs,
+        report.totals.total_size.human_count_bytes()
+    );
+    for source in &report.totals.sources {
+        println!(
+            "  {}: {} blobs, {}",
+            source.source,
+            source.num_blobs,
+            source.total_size.human_count_bytes()
+        );
+    }
+
+    if !report.largest_blobs.is_empty() {
+        println!();
+        println!("Largest blobs:");
+        for blob in &report.largest_blobs {
+            let source = match &blob.source_url {
+                Some(source_url) => source_url.clone(),
+                None => blob.source.to_string(),
+            };
+            println!(
+                "  {} ({}, from {source}, saved {})",
+                blob.blob_hash,
+                blob.size.human_count_bytes(),
+                blob.created_at
+            );
+        }
+    }
+
+    if !report.growth.is_empty() {
+        println!();
+        println!("Growth by day:");
+        for day in &report.growth {
+            println!(
+                "  {}: {} blobs, {}",
+                day.date,
+                day.num_blobs,
+                day.total_size.human_count_bytes()
+            );
+        }
+    }
+
+    Ok(())
+}
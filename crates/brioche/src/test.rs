@@ -0,0 +1,83 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+use tracing::Instrument;
+
+#[derive(Debug, Parser)]
+pub struct TestArgs {
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// Only run tests whose export name contains this string
+    filter: Option<String>,
+}
+
+pub async fn test(args: TestArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+    reporter.set_is_evaluating(true);
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .script_read_access(args.project.script_read_access())
+        // No mocks are registered, so tests fail if they try to download
+        // or run a process during evaluation
+        .bake_mocks(brioche_core::bake::mocks::BakeMocks::default())
+        .build()
+        .await?;
+    let projects = brioche_core::project::Projects::default();
+
+    let test_future = async {
+        let project_hash = super::load_project(&brioche, &projects, &args.project).await?;
+
+        let root_module_path = projects.project_root_module_path(project_hash)?;
+        let root_module = tokio::fs::read_to_string(&root_module_path).await?;
+        let exports = brioche_core::project::analyze::module_exports(&root_module)?;
+        let tests = exports
+            .into_iter()
+            .filter(|export| export.starts_with("test"))
+            .filter(|export| match &args.filter {
+                Some(filter) => export.contains(filter),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        let mut results = vec![];
+        for test in &tests {
+            let result =
+                brioche_core::script::evaluate::evaluate(&brioche, &projects, project_hash, test)
+                    .await;
+            results.push((test, result));
+        }
+
+        guard.shutdown_console().await;
+
+        let mut num_failed = 0;
+        for (test, result) in &results {
+            match result {
+                Ok(_) => {
+                    println!("test {test} ... ok");
+                }
+                Err(error) => {
+                    num_failed += 1;
+                    println!("test {test} ... FAILED");
+                    println!("{error:#}");
+                }
+            }
+        }
+
+        let num_passed = results.len() - num_failed;
+        println!();
+        println!("{num_passed} passed, {num_failed} failed");
+
+        if num_failed > 0 {
+            anyhow::Ok(ExitCode::FAILURE)
+        } else {
+            anyhow::Ok(ExitCode::SUCCESS)
+        }
+    };
+
+    let exit_code = test_future.instrument(tracing::info_span!("test")).await?;
+
+    Ok(exit_code)
+}
@@ -0,0 +1,95 @@
+use std::process::ExitCode;
+
+use brioche_core::reporter::ConsoleReporterKind;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct WhyArgs {
+    /// The recipe hash of the earlier build (e.g. from `brioche history
+    /// list`)
+    old: brioche_core::recipe::RecipeHash,
+
+    /// The recipe hash to compare against. When omitted, the project is
+    /// evaluated and its current recipe is compared instead
+    new: Option<brioche_core::recipe::RecipeHash>,
+
+    #[command(flatten)]
+    project: super::ProjectArgs,
+
+    /// Which TypeScript export to evaluate [default: default]
+    #[arg(short, long, conflicts_with = "new")]
+    export: Option<String>,
+
+    /// Print the changes as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn why(args: WhyArgs) -> anyhow::Result<ExitCode> {
+    let (reporter, mut guard) =
+        brioche_core::reporter::start_console_reporter(ConsoleReporterKind::Auto)?;
+
+    let brioche = brioche_core::BriocheBuilder::new(reporter)
+        .script_read_access(args.project.script_read_access())
+        .build()
+        .await?;
+
+    let old_recipe = brioche_core::recipe::get_recipe(&brioche, args.old).await?;
+    let new_recipe = match args.new {
+        Some(new) => brioche_core::recipe::get_recipe(&brioche, new).await?,
+        None => {
+            let projects = brioche_core::project::Projects::default();
+            let (project_hash, export) = super::load_project_target(
+                &brioche,
+                &projects,
+                &args.project,
+                None,
+                args.export.as_deref(),
+            )
+            .await?;
+            let recipe = brioche_core::script::evaluate::evaluate(
+                &brioche,
+                &projects,
+                project_hash,
+                &export,
+            )
+            .await?;
+            recipe.value
+        }
+    };
+
+    let changes =
+        brioche_core::recipe::diff::diff_recipes(&brioche, &old_recipe, &new_recipe).await?;
+
+    guard.shutdown_console().await;
+
+    if args.json {
+        let serialized = serde_json::to_string_pretty(&changes)?;
+        println!("{serialized}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let old_hash = old_recipe.hash();
+    let new_hash = new_recipe.hash();
+    if changes.is_empty() {
+        println!("No changes, recipe hash is still {new_hash}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("Recipe hash changed from {old_hash} to {new_hash}:");
+    for change in &changes {
+        let path = if change.path.is_empty() {
+            "(root)"
+        } else {
+            &change.path
+        };
+        match (&change.old, &change.new) {
+            (Some(old), Some(new)) => println!("  {path}: {old} -> {new}"),
+            (Some(old), None) => println!("  {path}: removed {old}"),
+            (None, Some(new)) => println!("  {path}: added {new}"),
+            (None, None) => println!("  {path}: changed"),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}